@@ -0,0 +1,559 @@
+//! Acting on the files behind the images: marks and ratings, deleting,
+//! sorting, saving crops and rotations, exporting, and keeping up with
+//! changes on disk.
+
+use super::{App, DELETE_CONFIRM_WINDOW, MIN_CROP_SIZE, NOTICE_DURATION};
+use crate::config::{QualityTier, SortAction};
+use crate::render::window_rect_to_image;
+use crate::slot::ImageSource;
+use crate::watch::{remap_index, FileChange};
+use crate::{export, external, ops, xmp};
+use std::ffi::OsString;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Instant;
+use winit::event_loop::ActiveEventLoop;
+
+impl App {
+    /// Mark or unmark the displayed image
+    pub(super) fn toggle_mark(&mut self) {
+        let Some(ws) = self.window_state.as_mut() else {
+            return;
+        };
+        if self.store.is_empty() {
+            return;
+        }
+        let id = self.store.slot_id(ws.view_state.current_index);
+        let marked = ws.view_state.toggle_mark(id);
+        let count = ws.view_state.marked.len();
+        let verb = if marked { "marked" } else { "unmarked" };
+        self.notify(format!("{} ({} selected)", verb, count), NOTICE_DURATION);
+    }
+
+    /// Rate the displayed image, 0 clearing its rating, in its XMP sidecar
+    pub(super) fn set_rating(&mut self, rating: u8) {
+        let Some(ws) = self.window_state.as_ref() else {
+            return;
+        };
+        let Some(slot) = self.store.get(ws.view_state.current_index) else {
+            return;
+        };
+        let ImageSource::File(path) = &slot.meta.source else {
+            self.notify("images inside archives can't be rated", NOTICE_DURATION);
+            return;
+        };
+        let text = match xmp::write_rating(path, rating) {
+            Ok(()) => {
+                slot.meta.set_rating(rating);
+                match rating {
+                    0 => "rating cleared".to_string(),
+                    n => format!("rated {}", xmp::stars(n)),
+                }
+            }
+            Err(err) => format!("rating failed: {}", err),
+        };
+        self.notify(text, NOTICE_DURATION);
+    }
+
+    /// Sources of the marked images in browsing order. Pages of one file
+    /// count once.
+    pub(super) fn marked_sources(&self) -> Vec<OsString> {
+        let Some(ws) = self.window_state.as_ref() else {
+            return Vec::new();
+        };
+        let ids = ws.view_state.marked.iter().copied();
+        self.sources_of(ids.filter(|&id| !self.store.is_removed(id)).collect())
+    }
+
+    /// Sources of the images with slot ids `ids`, in browsing order and
+    /// without repeats
+    fn sources_of(&self, mut ids: Vec<usize>) -> Vec<OsString> {
+        ids.sort_by_key(|&id| self.store.position_of(id));
+        let mut sources: Vec<OsString> = Vec::with_capacity(ids.len());
+        for id in ids {
+            let source = self.store.slot_by_id(id).meta.source.to_os_string();
+            if !sources.contains(&source) {
+                sources.push(source);
+            }
+        }
+        sources
+    }
+
+    /// Write the marked images' paths to the marks file, or stdout
+    pub(super) fn write_marks(&mut self) {
+        let marks = self.marked_sources();
+        if marks.is_empty() {
+            self.notify("no marked images", NOTICE_DURATION);
+            return;
+        }
+        let text = match write_paths(&marks, self.marks_file.as_deref()) {
+            Ok(()) => match &self.marks_file {
+                Some(path) => format!("wrote {} marked paths to {}", marks.len(), path.display()),
+                None => format!("wrote {} marked paths to stdout", marks.len()),
+            },
+            Err(err) => format!("can't write marks: {}", err),
+        };
+        self.notify(text, NOTICE_DURATION);
+    }
+
+    /// Finish picking: the marked images in browsing order, or the
+    /// displayed one if none are marked. Pages of one file are one pick.
+    pub(super) fn pick(&mut self, event_loop: &ActiveEventLoop) {
+        let Some(ws) = self.window_state.as_ref() else {
+            return;
+        };
+        if self.store.is_empty() {
+            return;
+        }
+        let mut picked = self.marked_sources();
+        if picked.is_empty() {
+            picked = self.sources_of(vec![self.store.slot_id(ws.view_state.current_index)]);
+        }
+        self.picked = Some(picked);
+        self.quit(event_loop);
+    }
+
+    /// Move the displayed image's file to the trash (or delete it for good,
+    /// if `permanent` and allowed) and show the next image in its place.
+    /// Quits once the last image is gone.
+    pub(super) fn delete_current(&mut self, permanent: bool, event_loop: &ActiveEventLoop) {
+        let Some(ws) = self.window_state.as_ref() else {
+            return;
+        };
+        let current = ws.view_state.current_index;
+        let Some(slot) = self.store.get(current) else {
+            return;
+        };
+        let ImageSource::File(path) = &slot.meta.source else {
+            self.notify("images inside archives can't be deleted", NOTICE_DURATION);
+            return;
+        };
+        let permanent = permanent && self.config.files.permanent_delete;
+
+        if self.config.files.confirm_delete {
+            let id = self.store.slot_id(current);
+            let now = Instant::now();
+            let confirmed = self
+                .pending_delete
+                .take()
+                .is_some_and(|(pending, at)| pending == id && now - at <= DELETE_CONFIRM_WINDOW);
+            if !confirmed {
+                self.pending_delete = Some((id, now));
+                let prompt = if permanent {
+                    "press Delete again to delete permanently"
+                } else {
+                    "press Delete again to move to trash"
+                };
+                self.notify(prompt, DELETE_CONFIRM_WINDOW);
+                return;
+            }
+        }
+
+        if let Err(err) = ops::delete(path, permanent) {
+            eprintln!("Warning: cannot delete {}: {}", path.display(), err);
+            self.notify(format!("delete failed: {}", err), NOTICE_DURATION);
+            return;
+        }
+        self.remove_current_file(path, event_loop);
+        let done = if permanent {
+            "deleted"
+        } else {
+            "moved to trash"
+        };
+        self.notify(done, NOTICE_DURATION);
+    }
+
+    /// Copy or move the displayed image's file into sort directory `n`
+    /// (counting from 1). A moved image leaves the collection like a
+    /// deleted one.
+    pub(super) fn sort_current(&mut self, n: u8, event_loop: &ActiveEventLoop) {
+        let Some(dir) = self.config.files.sort_into.get(n as usize - 1).cloned() else {
+            return;
+        };
+        let Some(ws) = self.window_state.as_ref() else {
+            return;
+        };
+        let Some(slot) = self.store.get(ws.view_state.current_index) else {
+            return;
+        };
+        let ImageSource::File(path) = &slot.meta.source else {
+            self.notify("images inside archives can't be sorted", NOTICE_DURATION);
+            return;
+        };
+
+        let action = self.config.files.sort_action;
+        match ops::sort_into(path, &dir, action) {
+            Ok(target) => {
+                let verb = match action {
+                    SortAction::Copy => "copied",
+                    SortAction::Move => {
+                        self.remove_current_file(path, event_loop);
+                        "moved"
+                    }
+                };
+                self.notify(format!("{} to {}", verb, target.display()), NOTICE_DURATION);
+            }
+            Err(err) => {
+                eprintln!(
+                    "Warning: cannot sort {} into {}: {}",
+                    path.display(),
+                    dir.display(),
+                    err
+                );
+                self.notify(format!("sorting failed: {}", err), NOTICE_DURATION);
+            }
+        }
+    }
+
+    /// Take the displayed image's file out of the collection once it has
+    /// been deleted or moved away. The image after it takes its place,
+    /// wrapping past the end; fiv quits when none are left.
+    fn remove_current_file(&mut self, path: &Path, event_loop: &ActiveEventLoop) {
+        let Some(ws) = self.window_state.as_ref() else {
+            return;
+        };
+        let current = ws.view_state.current_index;
+        let removed = self.store.remove_path(path);
+        if self.store.unfiltered_len() == 0 {
+            self.quit(event_loop);
+            return;
+        }
+        let remaining = self.store.len();
+        let next = current - removed.iter().filter(|&&p| p < current).count();
+        self.apply_list_change(if next < remaining { next } else { 0 }, true);
+    }
+
+    /// Catch the view and the preloader up after images were added or
+    /// removed, showing `new_current`. `current_removed` means the image
+    /// shown until now went away.
+    pub(super) fn apply_list_change(&mut self, mut new_current: usize, mut current_removed: bool) {
+        // The last match went away: show everything again
+        if self.store.is_empty() && self.store.filter().is_some() {
+            self.store.set_filter(None);
+            new_current = 0;
+            current_removed = true;
+        }
+        let total = self.store.len();
+        self.shared_state.set_total(total);
+        self.store.pin(new_current);
+        // The pinned image went away or was filtered out: nothing to compare
+        let pinned = self
+            .window_state
+            .as_ref()
+            .and_then(|ws| ws.view_state.pinned());
+        if pinned.is_some_and(|id| !self.store.is_shown(id)) {
+            if let Some(ws) = self.window_state.as_mut() {
+                ws.view_state.exit_compare();
+            }
+            self.store.hold(None);
+        }
+        // Positions shifted under the preloader's plan: replan from here
+        self.shared_state.set_start(new_current);
+
+        if let Some(ws) = self.window_state.as_mut() {
+            ws.view_state.total_images = total;
+            ws.view_state.unfiltered_total =
+                self.store.filter().map(|_| self.store.unfiltered_len());
+            ws.view_state.current_index = new_current;
+            if current_removed {
+                ws.view_state.navigate(0);
+            }
+            ws.view_state.needs_render = true;
+            ws.update_title(&self.store);
+            ws.window.request_redraw();
+        }
+    }
+
+    /// Apply a change reported by the directory watcher, keeping the
+    /// displayed image where it is (or its successor, if it went away)
+    pub(super) fn file_changed(&mut self, change: FileChange) {
+        // The watched directories aren't the ones browsed any more
+        if self.replaced {
+            return;
+        }
+        let current = self.shared_state.current();
+        let (new_current, current_removed) = match change {
+            FileChange::Created(path) => {
+                if self.store.contains_path(&path) {
+                    return;
+                }
+                self.store.add(self.decoder.expand_pages(vec![path]));
+                (current, false)
+            }
+            FileChange::Modified(path) if self.store.contains_path(&path) => {
+                // A decode of the file while it was partly written failed,
+                // or shows what it held then
+                let reloaded = self.store.reload_path(&path);
+                if reloaded.contains(&current) {
+                    self.reload_current();
+                } else {
+                    self.shared_state.set_current(current);
+                }
+                return;
+            }
+            // Written without its creation being seen as an image's
+            FileChange::Modified(path) => {
+                self.store.add(self.decoder.expand_pages(vec![path]));
+                (current, false)
+            }
+            FileChange::Removed(path) => {
+                let removed = self.store.remove_path(&path);
+                if removed.is_empty() {
+                    return;
+                }
+                let remaining = self.store.len();
+                (
+                    remap_index(current, &removed, remaining),
+                    removed.contains(&current),
+                )
+            }
+        };
+        self.apply_list_change(new_current, current_removed);
+    }
+
+    /// Drop the displayed image and decode it again from its file
+    pub(super) fn reload_current(&mut self) {
+        let Some(ws) = self.window_state.as_mut() else {
+            return;
+        };
+        let current = ws.view_state.current_index;
+        self.store.reload(current);
+        self.store.pin(current);
+        // Wakes the preloader, which always plans the current image first
+        self.shared_state.set_current(current);
+        ws.view_state.reload();
+        ws.window.request_redraw();
+    }
+
+    /// Save the crop selection of the displayed image as a PNG next to its
+    /// file, cut from a full decode in the background, and leave crop mode
+    pub(super) fn save_crop(&mut self) {
+        let Some(ws) = self.window_state.as_mut() else {
+            return;
+        };
+        let index = ws.view_state.current_index;
+        let (Some(selection), Some(slot), Some(shown)) = (
+            ws.view_state.crop_selection(),
+            self.store.get(index),
+            self.store.read(index),
+        ) else {
+            return;
+        };
+        let (start, end) = selection.corners();
+        let rect = window_rect_to_image(
+            &shown,
+            ws.view_state.window_width as usize,
+            ws.view_state.window_height as usize,
+            &ws.view_state.transform,
+            (start.0 as f64, start.1 as f64),
+            (end.0 as f64, end.1 as f64),
+        )
+        .filter(|r| r.width >= MIN_CROP_SIZE && r.height >= MIN_CROP_SIZE);
+        let Some(rect) = rect else {
+            self.notify("selection too small", NOTICE_DURATION);
+            return;
+        };
+        let ImageSource::File(path) = slot.meta.source.clone() else {
+            ws.view_state.cancel_mode();
+            self.notify("images inside archives can't be cropped", NOTICE_DURATION);
+            return;
+        };
+
+        let full = Some(shown).filter(|d| d.quality == QualityTier::Full);
+        let decoder = Arc::clone(&self.decoder);
+        let frame_index = ws.view_state.frame_index;
+        ws.spawn_job(None, move || {
+            let data = match full {
+                Some(data) => data,
+                None => decoder
+                    .decode(&slot.meta, QualityTier::Full)
+                    .map_err(|err| format!("crop failed: {}", err))?,
+            };
+            match ops::save_crop(&data, frame_index, rect, &path) {
+                Ok(target) => Ok(format!("saved {}", target.display())),
+                Err(err) => {
+                    eprintln!("Warning: cannot save a crop of {}: {}", path.display(), err);
+                    Err(format!("crop failed: {}", err))
+                }
+            }
+        });
+        ws.view_state.cancel_mode();
+        self.notify("saving crop...", NOTICE_DURATION);
+    }
+
+    /// Turn the current image's file by the view's rotation and flips, in
+    /// the background. The view goes back to upright once the file is
+    /// rewritten and decoded again.
+    pub(super) fn save_rotation(&mut self) {
+        let Some(ws) = self.window_state.as_mut() else {
+            return;
+        };
+        let turn = ws.view_state.transform.orientation;
+        let index = ws.view_state.current_index;
+        let Some(slot) = self.store.get(index) else {
+            return;
+        };
+        if turn.is_identity() {
+            self.notify("not rotated", NOTICE_DURATION);
+            return;
+        }
+        let ImageSource::File(path) = slot.meta.source.clone() else {
+            self.notify("images inside archives can't be rotated", NOTICE_DURATION);
+            return;
+        };
+        if slot.meta.page.is_some() {
+            self.notify("pages of a document can't be rotated", NOTICE_DURATION);
+            return;
+        }
+
+        let full = self
+            .store
+            .read(index)
+            .filter(|d| d.quality == QualityTier::Full);
+        let id = self.store.slot_id(index);
+        // A second turn before the first is saved would apply it twice
+        if ws.jobs.iter().any(|job| job.rewrites == Some(id)) {
+            self.notify("still rotating", NOTICE_DURATION);
+            return;
+        }
+        let decoder = Arc::clone(&self.decoder);
+        ws.spawn_job(Some(id), move || {
+            let decode = || match full {
+                Some(data) => Ok(data),
+                None => decoder
+                    .decode(&slot.meta, QualityTier::Full)
+                    .map_err(|err| err.to_string()),
+            };
+            match ops::save_orientation(&path, turn, decode) {
+                Ok(()) => Ok(format!("rotated {}", path.display())),
+                Err(err) => {
+                    eprintln!("Warning: cannot rotate {}: {}", path.display(), err);
+                    Err(format!("rotate failed: {}", err))
+                }
+            }
+        });
+        self.notify("rotating file...", NOTICE_DURATION);
+    }
+
+    /// Decode an image again after a job rewrote its file. The new file
+    /// carries the view's rotation, so the view drops it; the thumbnail
+    /// cache sees the new modification time and ignores its old entry.
+    pub(super) fn file_rewritten(&mut self, id: usize) {
+        if self.store.is_removed(id) {
+            return;
+        }
+        let index = self.store.position_of(id);
+        match self.window_state.as_mut() {
+            Some(ws) if ws.view_state.current_index == index => {
+                ws.view_state.reset_orientation();
+                self.reload_current();
+            }
+            _ => {
+                self.store.reload_by_id(id);
+            }
+        }
+    }
+
+    /// Set the current image as the desktop wallpaper, running the command
+    /// in the background
+    pub(super) fn set_wallpaper(&mut self) {
+        let Some(ws) = self.window_state.as_mut() else {
+            return;
+        };
+        let Some(slot) = self.store.get(ws.view_state.current_index) else {
+            return;
+        };
+        let ImageSource::File(path) = &slot.meta.source else {
+            self.notify(
+                "images inside archives can't be the wallpaper",
+                NOTICE_DURATION,
+            );
+            return;
+        };
+        let configured = self.config.external.wallpaper_command.as_deref();
+        let Some(command) = external::session_wallpaper_command(configured) else {
+            self.notify(
+                "no wallpaper command for this desktop; set external.wallpaper_command",
+                NOTICE_DURATION,
+            );
+            return;
+        };
+        let path = std::fs::canonicalize(path).unwrap_or_else(|_| path.clone());
+        ws.spawn_job(None, move || {
+            match external::set_wallpaper(&path, &command) {
+                Ok(()) => Ok("wallpaper set".to_string()),
+                Err(err) => {
+                    eprintln!(
+                        "Warning: cannot set {} as the wallpaper: {}",
+                        path.display(),
+                        err
+                    );
+                    Err(format!("wallpaper failed: {}", err))
+                }
+            }
+        });
+    }
+
+    /// Export a resized copy of the current image as `[export]` says, from
+    /// a full decode in the background
+    pub(super) fn export_current(&mut self) {
+        let Some(ws) = self.window_state.as_mut() else {
+            return;
+        };
+        let index = ws.view_state.current_index;
+        let Some(slot) = self.store.get(index) else {
+            return;
+        };
+        // Archive members export as if they sat next to their archive
+        let original = match &slot.meta.source {
+            ImageSource::File(path) => path.clone(),
+            ImageSource::ArchiveEntry { archive, .. } => {
+                archive.with_file_name(slot.meta.name().file_name().unwrap_or_default())
+            }
+        };
+
+        let full = self
+            .store
+            .read(index)
+            .filter(|d| d.quality == QualityTier::Full);
+        let decoder = Arc::clone(&self.decoder);
+        let config = self.config.export.clone();
+        let frame_index = ws.view_state.frame_index;
+        ws.spawn_job(None, move || {
+            let data = match full {
+                Some(data) => data,
+                None => decoder
+                    .decode(&slot.meta, QualityTier::Full)
+                    .map_err(|err| format!("export failed: {}", err))?,
+            };
+            match export::export(&data, frame_index, &original, &config) {
+                Ok(target) => Ok(format!("exported {}", target.display())),
+                Err(err) => {
+                    eprintln!("Warning: cannot export {}: {}", original.display(), err);
+                    Err(format!("export failed: {}", err))
+                }
+            }
+        });
+        self.notify("exporting...", NOTICE_DURATION);
+    }
+}
+
+/// Write paths one per line to `file`, replacing it, or to stdout. Their
+/// bytes go out as they are, so paths that aren't UTF-8 reach scripts
+/// intact.
+pub fn write_paths(paths: &[OsString], file: Option<&Path>) -> Result<(), String> {
+    let mut text = Vec::new();
+    for path in paths {
+        text.extend_from_slice(path.as_encoded_bytes());
+        text.push(b'\n');
+    }
+    match file {
+        Some(path) => std::fs::write(path, text).map_err(|e| format!("{}: {}", path.display(), e)),
+        None => {
+            use std::io::Write;
+            std::io::stdout()
+                .write_all(&text)
+                .map_err(|e| e.to_string())
+        }
+    }
+}
@@ -0,0 +1,592 @@
+//! Keyboard dispatch: what each bound action does, and the keys read by
+//! prompts, the gallery, crop selection and goto.
+
+use super::{App, BLINK_INTERVAL, NOTICE_DURATION};
+use crate::config::PanKeys;
+use crate::filter::NameFilter;
+use crate::keys::{self, KeyAction};
+use crate::state::{Compare, InputMode, NavKey, Orientation};
+use std::time::Instant;
+use winit::event_loop::ActiveEventLoop;
+use winit::keyboard::KeyCode;
+
+impl App {
+    /// Show the gallery, or the image under its cursor
+    pub(super) fn toggle_gallery(&mut self) {
+        let Some(ws) = self.window_state.as_mut() else {
+            return;
+        };
+        ws.view_state
+            .toggle_gallery(self.config.gallery.cell_size as usize);
+        // Thumbnails on screen first, or back to loading around the image
+        self.shared_state.set_grid(ws.view_state.gallery_cells());
+        ws.update_title(&self.store);
+    }
+
+    /// A key press in the gallery that moves its cursor by rows or leaves
+    /// the gallery. False for other keys, which do what they always do.
+    pub(super) fn gallery_key(&mut self, key: KeyCode) -> bool {
+        let Some(view) = self
+            .window_state
+            .as_ref()
+            .map(|ws| &ws.view_state)
+            .filter(|view| view.gallery.is_some())
+        else {
+            return false;
+        };
+        let delta = match key {
+            KeyCode::ArrowUp => view.gallery_rows_delta(-1),
+            KeyCode::ArrowDown => view.gallery_rows_delta(1),
+            KeyCode::PageUp => view.gallery_rows_delta(-view.gallery_page()),
+            KeyCode::PageDown => view.gallery_rows_delta(view.gallery_page()),
+            // Escape closes the help first
+            KeyCode::Escape if view.show_help => return false,
+            KeyCode::Enter | KeyCode::NumpadEnter | KeyCode::Escape => {
+                self.toggle_gallery();
+                return true;
+            }
+            _ => return false,
+        };
+        if delta != 0 {
+            self.input_state.jump(delta);
+        }
+        true
+    }
+
+    /// Pin the displayed image for comparing, or show the pinned image
+    /// beside it, or go back to single view
+    fn toggle_compare(&mut self) {
+        let Some(ws) = self.window_state.as_mut() else {
+            return;
+        };
+        if self.store.is_empty() {
+            return;
+        }
+        let id = self.store.slot_id(ws.view_state.current_index);
+        ws.view_state.toggle_compare(id);
+        let notice = match ws.view_state.compare {
+            Some(Compare::Pinned(_)) => "pinned; go to another image to compare",
+            Some(Compare::Split(_)) => "comparing",
+            None => "compare off",
+        };
+        self.hold_compared();
+        self.notify(notice, NOTICE_DURATION);
+    }
+
+    /// Leave compare mode, keeping the current image on screen
+    fn exit_compare(&mut self) {
+        if let Some(ws) = self.window_state.as_mut() {
+            ws.view_state.exit_compare();
+        }
+        self.hold_compared();
+        self.notify("compare off", NOTICE_DURATION);
+    }
+
+    /// Keep the pinned image loaded at full quality, ready to show beside
+    /// or in place of the current one, and let the preloader know
+    fn hold_compared(&self) {
+        let pinned = self
+            .window_state
+            .as_ref()
+            .and_then(|ws| ws.view_state.pinned());
+        self.store.hold(pinned);
+        self.shared_state.replan();
+    }
+
+    /// Swap the pinned image and the current one in place, or while the
+    /// key is held keep swapping them
+    fn blink(&mut self, pressed: bool) {
+        let Some(ws) = self.window_state.as_mut() else {
+            return;
+        };
+        if !pressed {
+            ws.view_state.stop_blink();
+        } else if ws.view_state.start_blink(BLINK_INTERVAL, Instant::now()) {
+            ws.update_title(&self.store);
+        } else if ws.view_state.compare.is_none() {
+            self.notify("nothing pinned; pin an image with V first", NOTICE_DURATION);
+        }
+    }
+
+    /// Show the next image after the displayed one that looks like it
+    fn jump_to_similar(&mut self) {
+        let Some(ws) = self.window_state.as_ref() else {
+            return;
+        };
+        if self.store.is_empty() {
+            return;
+        }
+        let id = self.store.slot_id(ws.view_state.current_index);
+        if self.store.slot_by_id(id).meta.phash().is_none() {
+            self.notify("not compared yet", NOTICE_DURATION);
+            return;
+        }
+        let positions = self
+            .store
+            .similar_positions(id, self.config.duplicates.max_distance);
+        // Round the ends whatever the wrap setting, so look-alikes earlier
+        // in the collection are found too
+        match ws.view_state.delta_to_nearest(positions, true, true) {
+            Some(delta) => self.input_state.jump(delta),
+            None => self.notify("no similar images", NOTICE_DURATION),
+        }
+    }
+
+    /// Show the next marked image after the displayed one, or the previous
+    /// one before it
+    fn jump_to_marked(&mut self, forward: bool) {
+        let Some(ws) = self.window_state.as_ref() else {
+            return;
+        };
+        let marked = &ws.view_state.marked;
+        let positions = marked
+            .iter()
+            .filter(|&&id| !self.store.is_removed(id))
+            .map(|&id| self.store.position_of(id));
+        match ws
+            .view_state
+            .delta_to_nearest(positions, forward, self.store.wraps())
+        {
+            Some(delta) => self.input_state.jump(delta),
+            None if marked.is_empty() => self.notify("no marked images", NOTICE_DURATION),
+            None => self.notify("no more marked images", NOTICE_DURATION),
+        }
+    }
+
+    /// Pan direction for an arrow key, if arrows currently pan instead of
+    /// navigating (zoomed in, and Shift held when so configured)
+    pub(super) fn arrow_pan(&self, key: KeyCode) -> Option<(f64, f64)> {
+        let ws = self.window_state.as_ref()?;
+        if !ws.view_state.is_zoomed() {
+            return None;
+        }
+        if self.config.view.pan_keys == PanKeys::ShiftArrows && !self.modifiers.shift_key() {
+            return None;
+        }
+
+        // Keys move the view, so the image moves the opposite way
+        let step = self.config.view.pan_step;
+        match key {
+            KeyCode::ArrowLeft => Some((step, 0.0)),
+            KeyCode::ArrowRight => Some((-step, 0.0)),
+            KeyCode::ArrowUp => Some((0.0, step)),
+            KeyCode::ArrowDown => Some((0.0, -step)),
+            _ => None,
+        }
+    }
+
+    /// A key press in goto, filter or crop mode, with the text it types
+    pub(super) fn prompt_key(&mut self, key: KeyCode, text: Option<&str>) {
+        let Some(ws) = self.window_state.as_ref() else {
+            return;
+        };
+        if ws.view_state.is_cropping() {
+            self.crop_key(key);
+        } else if matches!(ws.view_state.mode, InputMode::Filter(_)) {
+            self.filter_key(key, text);
+        } else {
+            self.goto_key(key);
+        }
+    }
+
+    /// A key press in filter mode: Enter applies the typed filter (none if
+    /// empty) and Escape cancels
+    fn filter_key(&mut self, key: KeyCode, text: Option<&str>) {
+        let Some(ws) = self.window_state.as_mut() else {
+            return;
+        };
+        match key {
+            KeyCode::Enter | KeyCode::NumpadEnter => {
+                let pattern = ws.view_state.finish_filter().unwrap_or_default();
+                ws.update_title(&self.store);
+                self.apply_filter(NameFilter::new(&pattern));
+                return;
+            }
+            KeyCode::Escape => ws.view_state.cancel_mode(),
+            KeyCode::Backspace => ws.view_state.pop_filter_char(),
+            _ => {
+                if let Some(text) = text {
+                    ws.view_state.push_filter_text(text);
+                }
+            }
+        }
+        ws.update_title(&self.store);
+    }
+
+    /// Browse only the images whose names match `filter`, or all of them
+    /// for None. The displayed image stays if it matches; otherwise the
+    /// nearest match in the unfiltered order takes its place.
+    fn apply_filter(&mut self, filter: Option<NameFilter>) {
+        let Some(ws) = self.window_state.as_ref() else {
+            return;
+        };
+        if self.store.is_empty() || filter == self.store.filter() {
+            return;
+        }
+        let current_id = self.store.slot_id(ws.view_state.current_index);
+        let pattern = filter.as_ref().map(|f| f.pattern().to_string());
+        if !self.store.set_filter(filter) {
+            let pattern = pattern.unwrap_or_default();
+            self.notify(format!("nothing matches '{}'", pattern), NOTICE_DURATION);
+            return;
+        }
+        let Some(new_current) = self.store.nearest_position(current_id) else {
+            return;
+        };
+        let moved = !self.store.is_shown(current_id);
+        self.apply_list_change(new_current, moved);
+        let text = match pattern {
+            Some(pattern) => format!("{} matching '{}'", self.store.len(), pattern),
+            None => "filter cleared".to_string(),
+        };
+        self.notify(text, NOTICE_DURATION);
+    }
+
+    /// A key press in crop mode: Enter saves the selection and Escape
+    /// cancels
+    fn crop_key(&mut self, key: KeyCode) {
+        match key {
+            KeyCode::Enter | KeyCode::NumpadEnter => self.save_crop(),
+            KeyCode::Escape => {
+                if let Some(ws) = self.window_state.as_mut() {
+                    ws.view_state.cancel_mode();
+                    ws.update_title(&self.store);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// A key press in goto mode: digits build the number, Backspace
+    /// corrects it, Enter goes there and Escape cancels
+    fn goto_key(&mut self, key: KeyCode) {
+        let Some(ws) = self.window_state.as_mut() else {
+            return;
+        };
+        match key {
+            KeyCode::Enter | KeyCode::NumpadEnter => {
+                if let Some(delta) = ws.view_state.finish_goto() {
+                    self.input_state.jump(delta);
+                }
+            }
+            KeyCode::Escape => ws.view_state.cancel_mode(),
+            KeyCode::Backspace => ws.view_state.pop_goto_digit(),
+            key => {
+                if let Some(digit) = keys::digit(key) {
+                    ws.view_state.push_goto_digit(digit);
+                }
+            }
+        }
+        ws.update_title(&self.store);
+    }
+
+    /// Action for a key press with the current modifiers
+    pub(super) fn resolve_key(&self, key: KeyCode) -> Option<KeyAction> {
+        let action = self.bindings.lookup(key, self.modifiers)?;
+        // Space pauses a running slideshow instead of stepping
+        let slideshow = self
+            .window_state
+            .as_ref()
+            .is_some_and(|ws| ws.view_state.slideshow.is_some());
+        // Escape leaves compare mode, then a filter, before it quits (once
+        // the help is closed)
+        let view = self
+            .window_state
+            .as_ref()
+            .map(|ws| &ws.view_state)
+            .filter(|view| !view.show_help);
+        let comparing = view.is_some_and(|view| view.compare.is_some());
+        let filtered = view.is_some() && self.store.filter().is_some();
+        match action {
+            KeyAction::NavigateRight if key == KeyCode::Space && slideshow => {
+                Some(KeyAction::PauseSlideshow)
+            }
+            KeyAction::Quit if key == KeyCode::Escape && comparing => Some(KeyAction::ExitCompare),
+            KeyAction::Quit if key == KeyCode::Escape && filtered => Some(KeyAction::ClearFilter),
+            action => Some(action),
+        }
+    }
+
+    pub(super) fn handle_key_action(
+        &mut self,
+        action: KeyAction,
+        pressed: bool,
+        event_loop: &ActiveEventLoop,
+    ) {
+        // While the help overlay is open, Escape (or any quit key) closes it
+        let help_open = self
+            .window_state
+            .as_ref()
+            .is_some_and(|ws| ws.view_state.show_help);
+        let action = match action {
+            KeyAction::Quit if help_open => KeyAction::ToggleHelp,
+            action => action,
+        };
+        match action {
+            KeyAction::NavigateRight => self.input_state.set_right(pressed),
+            KeyAction::NavigateLeft => self.input_state.set_left(pressed),
+            KeyAction::Navigate(step) => {
+                self.input_state.set_key(NavKey::Step(step), step, pressed)
+            }
+            KeyAction::JumpHome if pressed => self.input_state.home_pressed = true,
+            KeyAction::JumpEnd if pressed => self.input_state.end_pressed = true,
+            KeyAction::JumpForward | KeyAction::JumpBackward => {
+                let step = self
+                    .window_state
+                    .as_ref()
+                    .map_or(0, |ws| ws.view_state.jump_step(self.config.input.jump_step));
+                let (key, step) = if action == KeyAction::JumpForward {
+                    (NavKey::JumpForward, step)
+                } else {
+                    (NavKey::JumpBackward, -step)
+                };
+                self.input_state.set_key(key, step, pressed);
+            }
+            KeyAction::JumpToTenth(tenth) if pressed => {
+                if let Some(ws) = self.window_state.as_ref() {
+                    self.input_state.jump(ws.view_state.delta_to_tenth(tenth));
+                }
+            }
+            KeyAction::Reshuffle if pressed => {
+                if let Some(ws) = self.window_state.as_ref() {
+                    let current = ws.view_state.current_index;
+                    self.store.reshuffle(current, &mut self.rng);
+                    // Same image, new neighbours: restart preloading around it
+                    self.store.pin(current);
+                    self.shared_state.set_start(current);
+                }
+            }
+            KeyAction::ToggleFullscreen if pressed => {
+                if let Some(ws) = self.window_state.as_mut() {
+                    ws.toggle_fullscreen();
+                }
+            }
+            KeyAction::FitWindow if pressed => {
+                let fullscreen = self
+                    .window_state
+                    .as_ref()
+                    .is_some_and(|ws| ws.window.fullscreen().is_some());
+                if fullscreen {
+                    self.notify("the window can't be fitted in fullscreen", NOTICE_DURATION);
+                } else if let Some(ws) = self.window_state.as_mut() {
+                    ws.fit_at = Some(Instant::now());
+                }
+            }
+            KeyAction::ZoomIn if pressed => {
+                if let Some(ws) = self.window_state.as_mut() {
+                    ws.zoom(self.config.view.zoom_step, None, &self.store, &self.config);
+                }
+            }
+            KeyAction::ZoomOut if pressed => {
+                if let Some(ws) = self.window_state.as_mut() {
+                    ws.zoom(
+                        1.0 / self.config.view.zoom_step,
+                        None,
+                        &self.store,
+                        &self.config,
+                    );
+                }
+            }
+            KeyAction::ToggleActualSize if pressed => {
+                if let Some(ws) = self.window_state.as_mut() {
+                    ws.view_state.toggle_actual_size();
+                }
+            }
+            KeyAction::ZoomReset if pressed => {
+                if let Some(ws) = self.window_state.as_mut() {
+                    ws.view_state.reset_zoom();
+                }
+            }
+            KeyAction::RotateCw | KeyAction::RotateCcw if pressed => {
+                if let Some(ws) = self.window_state.as_mut() {
+                    ws.view_state.reorient(if action == KeyAction::RotateCw {
+                        Orientation::rotate_cw
+                    } else {
+                        Orientation::rotate_ccw
+                    });
+                }
+            }
+            KeyAction::FlipHorizontal if pressed => {
+                if let Some(ws) = self.window_state.as_mut() {
+                    ws.view_state.reorient(Orientation::flip_horizontal);
+                }
+            }
+            KeyAction::FlipVertical if pressed => {
+                if let Some(ws) = self.window_state.as_mut() {
+                    ws.view_state.reorient(Orientation::flip_vertical);
+                }
+            }
+            KeyAction::ToggleInfo if pressed => {
+                if let Some(ws) = self.window_state.as_mut() {
+                    ws.view_state.toggle_info();
+                }
+            }
+            KeyAction::ToggleBackground if pressed => {
+                if let Some(ws) = self.window_state.as_mut() {
+                    ws.view_state.toggle_background();
+                }
+            }
+            KeyAction::CycleScalingFilter if pressed => {
+                if let Some(ws) = self.window_state.as_mut() {
+                    let filter = ws.view_state.cycle_scaling_filter();
+                    ws.view_state
+                        .show_notice(format!("Scaling: {}", filter.name()), NOTICE_DURATION);
+                }
+            }
+            KeyAction::ToggleStatusBar if pressed => {
+                if let Some(ws) = self.window_state.as_mut() {
+                    ws.view_state.toggle_status_bar();
+                }
+            }
+            KeyAction::ToggleFilmstrip if pressed => {
+                if let Some(ws) = self.window_state.as_mut() {
+                    ws.view_state.toggle_filmstrip();
+                }
+            }
+            KeyAction::CycleChannelMode if pressed => {
+                if let Some(ws) = self.window_state.as_mut() {
+                    let mode = ws.view_state.cycle_channel_mode();
+                    ws.view_state
+                        .show_notice(format!("Channels: {}", mode.name()), NOTICE_DURATION);
+                }
+            }
+            KeyAction::ExposureUp | KeyAction::ExposureDown if pressed => {
+                if let Some(ws) = self.window_state.as_mut() {
+                    let step = if action == KeyAction::ExposureUp {
+                        0.5
+                    } else {
+                        -0.5
+                    };
+                    ws.view_state.adjust_exposure(step);
+                }
+            }
+            KeyAction::GammaUp | KeyAction::GammaDown if pressed => {
+                if let Some(ws) = self.window_state.as_mut() {
+                    let factor = if action == KeyAction::GammaUp {
+                        1.1
+                    } else {
+                        1.0 / 1.1
+                    };
+                    ws.view_state.adjust_gamma(factor);
+                }
+            }
+            KeyAction::ContrastUp | KeyAction::ContrastDown if pressed => {
+                if let Some(ws) = self.window_state.as_mut() {
+                    let step = if action == KeyAction::ContrastUp {
+                        0.1
+                    } else {
+                        -0.1
+                    };
+                    ws.view_state.adjust_contrast(step);
+                }
+            }
+            KeyAction::ResetAdjustments if pressed => {
+                if let Some(ws) = self.window_state.as_mut() {
+                    ws.view_state.reset_adjustments();
+                }
+            }
+            KeyAction::ToggleAdjustmentLock if pressed => {
+                if let Some(ws) = self.window_state.as_mut() {
+                    let text = if ws.view_state.toggle_adjustment_lock() {
+                        "Adjustments locked"
+                    } else {
+                        "Adjustments unlocked"
+                    };
+                    ws.view_state.show_notice(text, NOTICE_DURATION);
+                }
+            }
+            KeyAction::Export if pressed => self.export_current(),
+            KeyAction::SetWallpaper if pressed => self.set_wallpaper(),
+            KeyAction::SaveRotation if pressed => self.save_rotation(),
+            KeyAction::Crop if pressed => {
+                if let Some(ws) = self.window_state.as_mut() {
+                    ws.view_state.start_crop();
+                    ws.update_title(&self.store);
+                }
+            }
+            KeyAction::ToggleHistogram if pressed => {
+                if let Some(ws) = self.window_state.as_mut() {
+                    ws.view_state.toggle_histogram();
+                }
+            }
+            KeyAction::ToggleInspector if pressed => {
+                if let Some(ws) = self.window_state.as_mut() {
+                    ws.view_state.toggle_inspector();
+                }
+            }
+            KeyAction::ToggleDebug if pressed => {
+                if let Some(ws) = self.window_state.as_mut() {
+                    ws.view_state.toggle_debug();
+                }
+            }
+            KeyAction::ToggleHelp if pressed => {
+                if let Some(ws) = self.window_state.as_mut() {
+                    ws.view_state.toggle_help();
+                }
+            }
+            KeyAction::TogglePlayback if pressed => {
+                if let Some(ws) = self.window_state.as_mut() {
+                    ws.view_state.toggle_animation_pause();
+                }
+            }
+            KeyAction::Reload if pressed => self.reload_current(),
+            KeyAction::NextDirectory if pressed => self.step_directory(1),
+            KeyAction::PreviousDirectory if pressed => self.step_directory(-1),
+            // Shift asks for permanent deletion
+            KeyAction::Delete if pressed => {
+                self.delete_current(self.modifiers.shift_key(), event_loop)
+            }
+            KeyAction::SortInto(n) if pressed => self.sort_current(n, event_loop),
+            KeyAction::ToggleSlideshow if pressed => {
+                if let Some(ws) = self.window_state.as_mut() {
+                    ws.view_state
+                        .toggle_slideshow(self.config.view.slideshow_interval, Instant::now());
+                    ws.update_title(&self.store);
+                }
+            }
+            KeyAction::PauseSlideshow if pressed => {
+                if let Some(ws) = self.window_state.as_mut() {
+                    ws.view_state.toggle_slideshow_pause(Instant::now());
+                    ws.update_title(&self.store);
+                }
+            }
+            KeyAction::Pick if pressed && self.pick_mode => self.pick(event_loop),
+            KeyAction::SetRating(rating) if pressed => self.set_rating(rating),
+            KeyAction::ToggleMark if pressed => self.toggle_mark(),
+            KeyAction::NextMarked if pressed => self.jump_to_marked(true),
+            KeyAction::PreviousMarked if pressed => self.jump_to_marked(false),
+            KeyAction::WriteMarks if pressed => self.write_marks(),
+            KeyAction::NextSimilar if pressed => self.jump_to_similar(),
+            KeyAction::Compare if pressed => self.toggle_compare(),
+            KeyAction::ExitCompare if pressed => self.exit_compare(),
+            KeyAction::ToggleGallery if pressed => self.toggle_gallery(),
+            KeyAction::Blink => self.blink(pressed),
+            KeyAction::RandomImage if pressed => {
+                let delta = self
+                    .window_state
+                    .as_ref()
+                    .and_then(|ws| ws.view_state.random_delta(&mut self.rng));
+                if let Some(delta) = delta {
+                    self.input_state.jump(delta);
+                }
+            }
+            KeyAction::Goto if pressed => {
+                if let Some(ws) = self.window_state.as_mut() {
+                    ws.view_state.start_goto();
+                    ws.update_title(&self.store);
+                }
+            }
+            KeyAction::Filter if pressed => {
+                let current = self.store.filter();
+                if let Some(ws) = self.window_state.as_mut() {
+                    ws.view_state
+                        .start_filter(current.as_ref().map_or("", |f| f.pattern()));
+                    ws.update_title(&self.store);
+                }
+            }
+            KeyAction::ClearFilter if pressed => self.apply_filter(None),
+            KeyAction::Quit if pressed => self.quit(event_loop),
+            _ => {}
+        }
+    }
+}
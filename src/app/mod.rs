@@ -0,0 +1,911 @@
+//! The viewer: the window, and what input does to it.
+//!
+//! `App` is the winit event handler. It owns the store and the preloader
+//! state, and creates its `WindowState` once the event loop is running.
+//! Its methods are spread over this module's children by what they deal
+//! with:
+//! - `input`: key dispatch and the keys prompts read
+//! - `files`: file operations, marks and changes on disk
+//! - `remote`: commands from `fiv --remote` and handed-over launches
+//! - `scan`: finding the images to browse, and opening another collection
+//! - `window`: composing frames and overlays, the title and the window's
+//!   size
+
+mod files;
+mod input;
+#[cfg(unix)]
+mod remote;
+mod scan;
+mod window;
+
+pub use files::write_paths;
+#[cfg(unix)]
+pub use remote::absolute_paths;
+pub use scan::{collect_images, expand_collection, start_preloading, ImageList, ScanArgs};
+
+use crate::backend::Renderer;
+use crate::config::{BackgroundMode, Config, QualityTier, ScalingFilter, WheelAction};
+use crate::decode::Decoder;
+use crate::external::{Hooks, ImageHookTimer};
+use crate::gesture::{Gesture, TouchTracker};
+use crate::histogram::Histogram;
+use crate::keys::{HeldKeys, KeyBindings};
+use crate::metrics::Sample;
+#[cfg(unix)]
+use crate::remote::RemoteRequest;
+use crate::render::FILMSTRIP_CELLS;
+use crate::session::{LastViewed, Screen, Session, WindowGeometry};
+use crate::slot::ImageData;
+use crate::state::{
+    Adjustments, ChannelMode, CursorIdle, InputMode, InputState, SharedState, ViewState,
+    ViewTransform,
+};
+use crate::store::ImageStore;
+use crate::watch::FileChange;
+use pixels::Pixels;
+use rand::rngs::StdRng;
+use std::ffi::OsString;
+use std::path::PathBuf;
+use std::sync::{Arc, OnceLock, Weak};
+use std::time::{Duration, Instant, SystemTime};
+use winit::application::ApplicationHandler;
+use winit::dpi::{PhysicalPosition, Size};
+use winit::event::{ElementState, MouseButton, MouseScrollDelta, TouchPhase, WindowEvent};
+use winit::event_loop::ActiveEventLoop;
+use winit::keyboard::{ModifiersState, PhysicalKey};
+use winit::window::{Window, WindowId};
+
+/// How long title notices stay up
+const NOTICE_DURATION: Duration = Duration::from_secs(2);
+/// How often a held blink key swaps the pinned and current images
+const BLINK_INTERVAL: Duration = Duration::from_millis(500);
+/// Time to press Delete again when deletes must be confirmed
+const DELETE_CONFIRM_WINDOW: Duration = Duration::from_secs(1);
+/// Longest gap between the clicks of a double-click
+const DOUBLE_CLICK_TIME: Duration = Duration::from_millis(400);
+/// How far the cursor may move during a click before it counts as a drag
+const CLICK_SLOP: f64 = 5.0;
+/// Smallest crop, in image pixels, on either side
+const MIN_CROP_SIZE: u32 = 8;
+/// How often to look for decoded data while the shown image is still
+/// loading or waiting for a better tier
+const DATA_POLL_INTERVAL: Duration = Duration::from_millis(15);
+/// Pause in navigation before the window is fitted to the new image, so
+/// holding a key doesn't resize it for every image passed
+const REFIT_DELAY: Duration = Duration::from_millis(200);
+/// How often the statistics overlay is brought up to date
+const STATS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// What other threads wake the event loop with
+#[derive(Debug)]
+pub enum UserEvent {
+    /// A change in a watched directory
+    File(FileChange),
+    /// A command from `fiv --remote`
+    #[cfg(unix)]
+    Remote(RemoteRequest),
+    /// The scan for an accepted `open`, numbered as `App::opening` counts
+    #[cfg(unix)]
+    Scanned(usize, Result<ImageList, String>),
+}
+
+/// Everything a composed frame depends on. While it stays the same the
+/// frame in the pixel buffer is still right and only needs presenting.
+#[derive(Debug, Clone, PartialEq)]
+struct FrameKey {
+    /// Address of the displayed image data; every decode, upgrade or
+    /// reload stores new data
+    data: Option<usize>,
+    index: usize,
+    size: (u32, u32),
+    transform: ViewTransform,
+    frame_index: usize,
+    filter: ScalingFilter,
+    channel: ChannelMode,
+    adjustments: Adjustments,
+    background: BackgroundMode,
+    info: Option<Vec<String>>,
+    status: Option<(String, String)>,
+    /// Pixel inspector box: where it goes and what it says
+    inspector: Option<((usize, usize), Vec<String>)>,
+    /// Whether the histogram is shown, and if so whether it's ready
+    histogram: Option<bool>,
+    /// Crop selection: its window rectangle and size label
+    crop: Option<CropOverlay>,
+    /// Whether the image is marked
+    marked: bool,
+    /// In split view, the address of the pinned image's data, if loaded
+    pinned: Option<Option<usize>>,
+    /// In the gallery, the addresses of the cells' data, if loaded
+    gallery: Option<Vec<Option<usize>>>,
+    /// The filmstrip's thumbnail addresses, if loaded, while it's shown
+    filmstrip: Option<Vec<Option<usize>>>,
+    /// Whether files are dragged over the window
+    drop_hover: bool,
+    help: bool,
+    /// The statistics overlay's text, while it's shown
+    debug: Option<Vec<String>>,
+}
+
+/// Work running off the main thread, which reports a notice when done
+struct Job {
+    handle: std::thread::JoinHandle<Result<String, String>>,
+    /// Slot id of an image whose file the job rewrites, decoded again once
+    /// it succeeds
+    rewrites: Option<usize>,
+}
+
+/// Window rectangle (x, y, width, height) of a crop selection, and its
+/// label
+type CropOverlay = ((usize, usize, usize, usize), String);
+
+/// Logical window size, and frame position where known
+type WindowBounds = ((u32, u32), Option<(i32, i32)>);
+
+/// Image data and window size a blurred backdrop was made for
+type BackdropKey = (Weak<ImageData>, u32, u32);
+
+/// The last composed frame: its key, the data it showed (held weakly so
+/// the address isn't reused) and the quality it rendered at
+struct ComposedFrame {
+    key: FrameKey,
+    _data: Weak<ImageData>,
+    quality: Option<QualityTier>,
+    /// Likewise for the pinned image, in split view
+    _pinned: Weak<ImageData>,
+    pinned_quality: Option<QualityTier>,
+    /// And for the gallery's cells
+    _cells: Vec<Weak<ImageData>>,
+}
+
+/// Initialized window state - created once window is ready
+struct WindowState {
+    window: Arc<Window>,
+    pixels: Pixels<'static>,
+    /// Draws the image into `pixels` and presents it
+    renderer: Box<dyn Renderer>,
+    view_state: ViewState,
+    /// Titles show paths relative to this root (recursive mode)
+    title_root: Option<PathBuf>,
+    /// Window size to restore when leaving fullscreen
+    windowed_size: Option<Size>,
+    /// Logical size and frame position while last neither maximized nor
+    /// fullscreen, to remember on quitting
+    normal_bounds: Option<WindowBounds>,
+    /// When to resize the window to the current image
+    fit_at: Option<Instant>,
+    /// When `hooks.on_image_change` next runs
+    image_hook: ImageHookTimer,
+    /// Last known cursor position (window pixels)
+    cursor: Option<PhysicalPosition<f64>>,
+    /// When to hide the pointer while the mouse is unused
+    cursor_idle: CursorIdle,
+    /// Cursor position at the last drag step while the left button is held
+    drag_last: Option<PhysicalPosition<f64>>,
+    /// Where the left button went down, for telling clicks from drags
+    click_start: Option<PhysicalPosition<f64>>,
+    /// When the left button last went down, for double-clicks
+    last_click: Option<Instant>,
+    /// Size of the preloader's decode pool, shown in the info overlay
+    decode_threads: usize,
+    /// Slot id and modification time of the displayed file when last checked
+    watched_file: Option<(usize, Option<SystemTime>)>,
+    /// When to next check the displayed file for changes
+    next_reload_check: Instant,
+    /// Rows of the help overlay: keys and the action they're bound to
+    help: Vec<(String, String)>,
+    /// Blurred background, and the image data and window size it was made
+    /// for. The weak reference keeps the address from being reused.
+    backdrop: Option<(BackdropKey, Vec<u8>)>,
+    /// Background work (saving crops, exports and rotations)
+    jobs: Vec<Job>,
+    /// Histogram of the displayed image data, filled in by a background
+    /// thread. The weak reference keeps the address from being reused.
+    histogram: Option<(Weak<ImageData>, Arc<OnceLock<Histogram>>)>,
+    /// What the pixel buffer currently holds, if it can be reused
+    composed: Option<ComposedFrame>,
+    /// Frames composed so far, shown in the debug overlay
+    frames_composed: u64,
+    /// How long composing the last frame took
+    compose_time: Option<Duration>,
+    /// Statistics shown in the debug overlay, and when they're next
+    /// brought up to date
+    stats: Vec<String>,
+    next_stats_at: Option<Instant>,
+    /// Decode counters when the statistics were last brought up to date
+    stats_sample: Option<Sample>,
+    /// System clipboard, opened on first use; some platforms drop copied
+    /// text once it closes
+    clipboard: Option<arboard::Clipboard>,
+    _preloader_handle: std::thread::JoinHandle<()>,
+}
+
+/// Application with two-phase initialization
+pub struct App {
+    config: Config,
+    decoder: Arc<Decoder>,
+    store: Arc<ImageStore>,
+    shared_state: Arc<SharedState>,
+    input_state: InputState,
+    /// Touchscreen gesture recognizer
+    gestures: TouchTracker,
+    modifiers: ModifiersState,
+    /// Action each held key's press went to, so its repeats and release
+    /// go there too
+    held_keys: HeldKeys,
+    bindings: KeyBindings,
+    title_root: Option<PathBuf>,
+    rng: StdRng,
+    /// Slot id and time of a Delete press awaiting confirmation
+    pending_delete: Option<(usize, Instant)>,
+    /// Whether Enter picks images (--pick)
+    pick_mode: bool,
+    /// Start the slideshow once the window is up (--slideshow)
+    slideshow_on_start: bool,
+    /// What was picked, printed once the event loop has exited
+    picked: Option<Vec<OsString>>,
+    /// Where Shift+M writes the marked paths (--marks-file), else stdout
+    marks_file: Option<PathBuf>,
+    /// Paths marked when quitting, written once the event loop has exited
+    marks: Vec<OsString>,
+    /// Directory whose last shown image is remembered on quitting
+    session_dir: Option<PathBuf>,
+    /// Remembered or requested window geometry, for the window once it's
+    /// created
+    geometry: Option<WindowGeometry>,
+    /// Command line options, for scanning what's dropped onto the window
+    /// the same way
+    scan_args: Option<ScanArgs>,
+    /// Wakes the event loop with the scans for remote `open`s
+    #[cfg(unix)]
+    proxy: Option<winit::event_loop::EventLoopProxy<UserEvent>>,
+    /// Remote `open`s accepted so far; only the latest one's scan is opened
+    #[cfg(unix)]
+    opening: usize,
+    /// Paths dropped onto the window, opened once the whole drop is in
+    dropped: Vec<PathBuf>,
+    /// Whether a drop replaced the collection --watch follows
+    replaced: bool,
+    /// Commands run as images are shown
+    hooks: Hooks,
+    /// Directory whose siblings `[`/`]` with Ctrl and cross_directory step
+    /// into
+    browse_dir: Option<PathBuf>,
+    /// The sibling directory offered on stepping past the end (or start),
+    /// with the step that reached it, entered on stepping that way again
+    crossing: Option<(i32, ImageList)>,
+    window_state: Option<WindowState>,
+}
+
+impl App {
+    pub fn new(
+        config: Config,
+        decoder: Arc<Decoder>,
+        store: Arc<ImageStore>,
+        shared_state: Arc<SharedState>,
+        title_root: Option<PathBuf>,
+        rng: StdRng,
+    ) -> Self {
+        let bindings = KeyBindings::with_overrides(&config.keys, config.files.sort_into.len());
+        let hooks = Hooks::from_config(&config.hooks);
+        Self {
+            config,
+            decoder,
+            store,
+            shared_state,
+            input_state: InputState::new(),
+            gestures: TouchTracker::new(),
+            modifiers: ModifiersState::empty(),
+            held_keys: HeldKeys::default(),
+            bindings,
+            title_root,
+            rng,
+            pending_delete: None,
+            pick_mode: false,
+            slideshow_on_start: false,
+            picked: None,
+            marks_file: None,
+            marks: Vec::new(),
+            session_dir: None,
+            geometry: None,
+            scan_args: None,
+            #[cfg(unix)]
+            proxy: None,
+            #[cfg(unix)]
+            opening: 0,
+            dropped: Vec::new(),
+            replaced: false,
+            hooks,
+            browse_dir: None,
+            crossing: None,
+            window_state: None,
+        }
+    }
+
+    /// Write marked paths to a file rather than stdout
+    pub fn with_marks_file(mut self, path: Option<PathBuf>) -> Self {
+        self.marks_file = path;
+        self
+    }
+
+    /// Use Enter and M to choose images, for --pick
+    pub fn with_pick_mode(mut self, pick_mode: bool) -> Self {
+        self.pick_mode = pick_mode;
+        self
+    }
+
+    /// Start in slideshow mode
+    pub fn with_slideshow(mut self, slideshow: bool) -> Self {
+        self.slideshow_on_start = slideshow;
+        self
+    }
+
+    /// Remember where browsing `dir` was left off, on quitting
+    pub fn with_session_dir(mut self, dir: Option<PathBuf>) -> Self {
+        self.session_dir = dir;
+        self
+    }
+
+    /// Step into the directories beside `dir`
+    pub fn with_browse_dir(mut self, dir: Option<PathBuf>) -> Self {
+        self.browse_dir = dir;
+        self
+    }
+
+    /// Open the window with this geometry rather than the configured size
+    pub fn with_geometry(mut self, geometry: Option<WindowGeometry>) -> Self {
+        self.geometry = geometry;
+        self
+    }
+
+    /// Scan dropped files and directories with these options
+    pub fn with_scan_args(mut self, args: ScanArgs) -> Self {
+        self.scan_args = Some(args);
+        self
+    }
+
+    /// Scan the paths of remote `open`s in the background, posting the
+    /// result back through `proxy`
+    #[cfg(unix)]
+    pub fn with_proxy(mut self, proxy: winit::event_loop::EventLoopProxy<UserEvent>) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// What was picked with Enter (--pick), if anything
+    pub fn picked(&self) -> Option<&[OsString]> {
+        self.picked.as_deref()
+    }
+
+    /// Paths marked when quitting
+    pub fn marks(&self) -> &[OsString] {
+        &self.marks
+    }
+
+    /// Stop the preloader and leave the event loop, remembering where the
+    /// session's directory was left off and where the window was
+    fn quit(&mut self, event_loop: &ActiveEventLoop) {
+        if let (Some(hook), Some((path, index, total))) = (&self.hooks.on_quit, self.shown()) {
+            hook.start(&path, index, total);
+        }
+        self.save_session();
+        self.keep_marks();
+        self.shared_state.shutdown();
+        event_loop.exit();
+    }
+
+    /// The shown image's path and number (from 1), and the number of
+    /// images, for hooks
+    fn shown(&self) -> Option<(PathBuf, usize, usize)> {
+        let ws = self.window_state.as_ref()?;
+        let index = ws.view_state.current_index;
+        let slot = self.store.get(index)?;
+        Some((
+            slot.meta.path().to_path_buf(),
+            index + 1,
+            ws.view_state.total_images,
+        ))
+    }
+
+    /// Run `hooks.on_image_change` for the shown image once it has stayed
+    /// for `hooks.debounce`
+    fn run_image_hook(&mut self, now: Instant) {
+        let (Some(ws), Some(hook)) = (self.window_state.as_mut(), &self.hooks.on_image_change)
+        else {
+            return;
+        };
+        let index = ws.view_state.current_index;
+        if index >= self.store.len() {
+            return;
+        }
+        let id = self.store.slot_id(index);
+        if !ws.image_hook.due(id, self.config.hooks.debounce, now) {
+            return;
+        }
+        if let Some((path, index, total)) = self.shown() {
+            hook.run(&path, index, total);
+        }
+    }
+
+    /// Add the marked paths to those written out once the event loop has
+    /// exited, after any from collections browsed before this one
+    fn keep_marks(&mut self) {
+        if self.pick_mode {
+            return;
+        }
+        for source in self.marked_sources() {
+            if !self.marks.contains(&source) {
+                self.marks.push(source);
+            }
+        }
+    }
+
+    /// Record the shown image for the session's directory and the window's
+    /// geometry, as far as the config asks to remember them
+    fn save_session(&self) {
+        let (Some(ws), Some(path)) = (&self.window_state, Session::default_path()) else {
+            return;
+        };
+        let last = self
+            .session_dir
+            .as_ref()
+            .filter(|_| self.config.view.remember_position)
+            .and_then(|dir| {
+                let index = ws.view_state.current_index;
+                let slot = self.store.get(index)?;
+                let file = slot.meta.path().strip_prefix(dir).ok()?.to_path_buf();
+                Some(LastViewed {
+                    dir: dir.clone(),
+                    file,
+                    index,
+                })
+            });
+        let window = self.config.view.remember_window.then(|| ws.geometry());
+        if last.is_none() && window.is_none() {
+            return;
+        }
+
+        let mut session = Session::load(&path);
+        if let Some(last) = last {
+            session.record(last);
+        }
+        if let Some(window) = window {
+            session.set_window(window);
+        }
+        if let Err(err) = session.save(&path) {
+            eprintln!("Warning: {}", err);
+        }
+    }
+
+    /// Show a message in the title for `duration`
+    fn notify(&mut self, text: impl Into<String>, duration: Duration) {
+        if let Some(ws) = self.window_state.as_mut() {
+            ws.view_state.show_notice(text, duration);
+            ws.update_title(&self.store);
+        }
+    }
+}
+
+impl ApplicationHandler<UserEvent> for App {
+    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
+        if self.window_state.is_some() {
+            return;
+        }
+
+        let mut ws = WindowState::create(
+            event_loop,
+            &self.config,
+            &self.store,
+            &self.shared_state,
+            &self.decoder,
+            self.title_root.clone(),
+            self.geometry.map(|g| g.clamped(&screens(event_loop))),
+        );
+
+        ws.help = self.bindings.help();
+        if self.config.view.fit_window {
+            ws.fit_at = Some(Instant::now());
+        }
+        if self.slideshow_on_start {
+            ws.view_state
+                .toggle_slideshow(self.config.view.slideshow_interval, Instant::now());
+        }
+
+        ws.render(&self.store, &self.config);
+        ws.update_title(&self.store);
+        self.window_state = Some(ws);
+        if let (Some(hook), Some((path, index, total))) = (&self.hooks.on_start, self.shown()) {
+            hook.run(&path, index, total);
+        }
+    }
+
+    fn window_event(
+        &mut self,
+        event_loop: &ActiveEventLoop,
+        _window_id: WindowId,
+        event: WindowEvent,
+    ) {
+        let ws = match self.window_state.as_mut() {
+            Some(ws) => ws,
+            None => return,
+        };
+
+        // Any use of the mouse shows the pointer again
+        match &event {
+            WindowEvent::CursorMoved { position, .. } => ws.mouse_used(Some(*position)),
+            WindowEvent::MouseInput { .. } | WindowEvent::MouseWheel { .. } => ws.mouse_used(None),
+            _ => {}
+        }
+
+        match event {
+            WindowEvent::CloseRequested => self.quit(event_loop),
+
+            WindowEvent::KeyboardInput { event, .. } => {
+                if let PhysicalKey::Code(key) = event.physical_key {
+                    let pressed = event.state == ElementState::Pressed;
+                    // Only presses are diverted to panning, so a release always
+                    // reaches the action that saw the press
+                    let pan = if pressed { self.arrow_pan(key) } else { None };
+                    // While typing an image number or filter, or cropping,
+                    // presses go to the prompt
+                    let prompt = pressed
+                        && self
+                            .window_state
+                            .as_ref()
+                            .is_some_and(|ws| ws.view_state.mode != InputMode::Normal);
+
+                    if prompt {
+                        self.prompt_key(key, event.text.as_deref());
+                    } else if pressed && self.gallery_key(key) {
+                        // Moved the gallery cursor, or left the gallery
+                    } else if let Some((dx, dy)) = pan {
+                        if let Some(ws) = self.window_state.as_mut() {
+                            ws.view_state.pan_by(dx, dy);
+                        }
+                    } else if pressed {
+                        let resolved = self.resolve_key(key);
+                        if let Some(action) = self.held_keys.press(key, event.repeat, resolved) {
+                            self.handle_key_action(action, true, event_loop);
+                        }
+                    } else if let Some(action) = self.held_keys.release(key) {
+                        self.handle_key_action(action, false, event_loop);
+                    }
+                }
+            }
+
+            WindowEvent::ModifiersChanged(modifiers) => {
+                self.modifiers = modifiers.state();
+            }
+
+            WindowEvent::MouseInput { state, button, .. } => match (button, state) {
+                (MouseButton::Left, ElementState::Pressed) => {
+                    ws.drag_last = ws.cursor;
+                    let now = Instant::now();
+                    if let Some(at) = ws.cursor.filter(|_| ws.view_state.is_cropping()) {
+                        // Dragging selects instead of panning
+                        ws.view_state
+                            .begin_crop_selection((at.x as i32, at.y as i32));
+                    } else if ws.view_state.gallery.is_none()
+                        && ws
+                            .last_click
+                            .is_some_and(|at| now.duration_since(at) < DOUBLE_CLICK_TIME)
+                    {
+                        ws.last_click = None;
+                        ws.click_start = None;
+                        ws.toggle_fullscreen();
+                    } else {
+                        ws.last_click = Some(now);
+                        ws.click_start = ws.cursor;
+                    }
+                }
+                (MouseButton::Left, ElementState::Released) => {
+                    ws.drag_last = None;
+                    // A click that didn't drag steps toward the half of the
+                    // window it landed in
+                    let click = ws.click_start.take().zip(ws.cursor).filter(|(start, end)| {
+                        (end.x - start.x).abs() <= CLICK_SLOP
+                            && (end.y - start.y).abs() <= CLICK_SLOP
+                    });
+                    if let Some((_, at)) = click.filter(|_| !ws.view_state.is_cropping()) {
+                        if ws.view_state.gallery.is_some() {
+                            // A click picks a cell; another on it opens it
+                            match ws.view_state.gallery_delta_at((at.x, at.y)) {
+                                Some(0) => self.toggle_gallery(),
+                                Some(delta) => self.input_state.jump(delta),
+                                None => {}
+                            }
+                        } else if let Some(delta) = ws.view_state.filmstrip_delta_at((at.x, at.y)) {
+                            if delta != 0 {
+                                self.input_state.jump(delta);
+                            }
+                        } else if ws.view_state.show_inspector {
+                            ws.copy_inspected_pixel(&self.store);
+                        } else if self.config.input.click_navigation && !ws.view_state.is_zoomed() {
+                            let right_half = at.x >= ws.view_state.window_width as f64 / 2.0;
+                            self.input_state.step(if right_half { 1 } else { -1 });
+                        }
+                    }
+                }
+                (MouseButton::Back, ElementState::Pressed) => self.input_state.step(-1),
+                (MouseButton::Forward, ElementState::Pressed) => self.input_state.step(1),
+                _ => {}
+            },
+
+            WindowEvent::CursorMoved { position, .. } => {
+                // Drag steps only mark the view dirty; the render happens once
+                // per event batch in about_to_wait, so motion coalesces
+                if let Some(last) = ws.drag_last {
+                    if ws.view_state.is_cropping() {
+                        ws.view_state
+                            .drag_crop_selection((position.x as i32, position.y as i32));
+                    } else {
+                        ws.view_state
+                            .pan_by(position.x - last.x, position.y - last.y);
+                    }
+                    ws.drag_last = Some(position);
+                }
+                ws.cursor = Some(position);
+                if ws.view_state.show_inspector {
+                    ws.view_state.needs_render = true;
+                }
+            }
+
+            WindowEvent::CursorLeft { .. } => {
+                ws.cursor = None;
+                ws.drag_last = None;
+                if ws.view_state.show_inspector {
+                    ws.view_state.needs_render = true;
+                }
+            }
+
+            WindowEvent::MouseWheel { delta, phase, .. } => {
+                // Pixel deltas (touchpads) count ~50px as one wheel notch
+                let (notches, touchpad) = match delta {
+                    MouseScrollDelta::LineDelta(_, y) => (y as f64, false),
+                    MouseScrollDelta::PixelDelta(pos) => (pos.y / 50.0, true),
+                };
+                let zoom = self.config.input.wheel == WheelAction::Zoom
+                    || ws.view_state.is_zoomed()
+                    || self.modifiers.control_key();
+                if ws.view_state.gallery.is_some() {
+                    // A row per notch, scrolling up when the wheel goes up
+                    let delta = ws.view_state.gallery_rows_delta(-notches.round() as i32);
+                    if delta != 0 {
+                        self.input_state.jump(delta);
+                    }
+                } else if zoom {
+                    if notches != 0.0 {
+                        let factor = self.config.view.zoom_step.powf(notches);
+                        ws.zoom(factor, ws.cursor, &self.store, &self.config);
+                    }
+                } else if touchpad {
+                    if phase == TouchPhase::Started {
+                        self.input_state.start_swipe();
+                    }
+                    self.input_state.swipe(notches);
+                } else {
+                    self.input_state.scroll(notches);
+                }
+            }
+
+            WindowEvent::Touch(touch) => {
+                let position = (touch.location.x, touch.location.y);
+                let zoomed = ws.view_state.is_zoomed();
+                let gesture =
+                    self.gestures
+                        .touch(touch.id, touch.phase, position, Instant::now(), zoomed);
+                match gesture {
+                    Some(Gesture::Swipe(step)) => self.input_state.step(step),
+                    Some(Gesture::Pan { dx, dy }) => ws.view_state.pan_by(dx, dy),
+                    Some(Gesture::Pinch { factor, center }) => {
+                        let center = PhysicalPosition::new(center.0, center.1);
+                        ws.zoom(factor, Some(center), &self.store, &self.config);
+                    }
+                    None => {}
+                }
+            }
+
+            // Touchpad gestures, where the platform recognizes them itself
+            WindowEvent::PinchGesture { delta, .. } => {
+                ws.zoom(1.0 + delta, ws.cursor, &self.store, &self.config);
+            }
+
+            WindowEvent::PanGesture { delta, phase, .. } => {
+                if ws.view_state.is_zoomed() {
+                    ws.view_state.pan_by(delta.x as f64, delta.y as f64);
+                } else {
+                    if phase == TouchPhase::Started {
+                        self.input_state.start_swipe();
+                    }
+                    self.input_state.swipe(delta.x as f64 / 50.0);
+                }
+            }
+
+            WindowEvent::HoveredFile(_) => ws.view_state.hover_drop(true),
+            WindowEvent::HoveredFileCancelled => ws.view_state.hover_drop(false),
+            WindowEvent::DroppedFile(path) => {
+                // One event per path; the drop is opened once it's all in
+                ws.view_state.hover_drop(false);
+                self.dropped.push(path);
+            }
+
+            WindowEvent::Resized(size) if size.width > 0 && size.height > 0 => {
+                ws.handle_resize(size.width, size.height);
+                ws.note_bounds();
+            }
+
+            WindowEvent::ScaleFactorChanged { .. } => {
+                // The Resized event that follows brings the new physical
+                // size; until then keep the buffer matched to the window, and
+                // compose again for the debug overlay's sake
+                let size = ws.window.inner_size();
+                ws.handle_resize(size.width, size.height);
+                ws.composed = None;
+                ws.view_state.needs_render = true;
+            }
+
+            WindowEvent::Moved(_) => ws.note_bounds(),
+
+            WindowEvent::RedrawRequested => {
+                // Unchanged frames still need presenting, e.g. after the
+                // window was uncovered
+                let composed = ws.render(&self.store, &self.config);
+                if !composed {
+                    ws.present();
+                }
+            }
+
+            _ => {}
+        }
+    }
+
+    #[cfg_attr(not(unix), allow(unused_variables))]
+    fn user_event(&mut self, event_loop: &ActiveEventLoop, event: UserEvent) {
+        match event {
+            UserEvent::File(change) => self.file_changed(change),
+            #[cfg(unix)]
+            UserEvent::Remote(request) => self.remote_command(request, event_loop),
+            #[cfg(unix)]
+            UserEvent::Scanned(opening, scanned) => self.remote_scanned(opening, scanned),
+        }
+    }
+
+    fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+        if !self.dropped.is_empty() {
+            self.open_dropped();
+        }
+        let ws = match self.window_state.as_mut() {
+            Some(ws) => ws,
+            None => return,
+        };
+
+        ws.tick_animation(&self.store);
+        let rewritten = ws.finish_jobs();
+        let now = Instant::now();
+        if rewritten.is_some()
+            | ws.view_state.expire_notice(now)
+            | ws.view_state.blink_due(BLINK_INTERVAL, now)
+        {
+            ws.update_title(&self.store);
+        }
+        let auto_reload = self.config.view.auto_reload;
+        if auto_reload
+            && ws.current_file_changed(&self.store, self.config.view.reload_poll_interval)
+        {
+            self.reload_current();
+        }
+        for id in rewritten.into_iter().flatten() {
+            self.file_rewritten(id);
+        }
+        let Some(ws) = self.window_state.as_mut() else {
+            return;
+        };
+        ws.refresh_stats(&self.store, &self.shared_state, &self.decoder, now);
+        event_loop.set_control_flow(ws.control_flow(&self.input_state, auto_reload));
+
+        // Process navigation; a manual step gives the slideshow a full
+        // interval on the new image
+        let now = Instant::now();
+        let mut slideshow = false;
+        let delta = match self.input_state.process(&self.config.input) {
+            Some(delta) => {
+                ws.view_state.restart_slideshow_timer(now);
+                Some(delta)
+            }
+            None if ws.view_state.slideshow_due(now) => {
+                slideshow = true;
+                Some(1)
+            }
+            None => None,
+        };
+        let mut past_edge = None;
+        if let Some(delta) = delta {
+            if ws.view_state.navigate(delta) {
+                self.crossing = None;
+                if !self.config.view.keep_zoom {
+                    ws.view_state.reset_zoom();
+                }
+                if !self.config.view.sticky_rotation {
+                    ws.view_state.reset_orientation();
+                }
+                if !ws.view_state.adjustments_locked {
+                    ws.view_state.reset_adjustments();
+                }
+                ws.view_state.reset_channel_mode();
+                if self.config.view.fit_window && self.config.view.refit_window {
+                    ws.fit_at = Some(now + REFIT_DELAY);
+                }
+                self.store.pin(ws.view_state.current_index);
+                if matches!(delta, -1 | 1) {
+                    self.shared_state.set_current(ws.view_state.current_index);
+                } else {
+                    // After a jump neither way is more likely next, so
+                    // preload around the new image symmetrically
+                    self.shared_state.set_start(ws.view_state.current_index);
+                }
+            } else if self.config.input.cross_directory && matches!(delta, -1 | 1) {
+                past_edge = Some(delta);
+            } else {
+                // Not wrapping, and already at the end: say so, and let a
+                // slideshow finish there
+                let edge = if delta > 0 {
+                    ws.view_state.slideshow = None;
+                    "\u{2014} end \u{2014}"
+                } else {
+                    "\u{2014} start \u{2014}"
+                };
+                ws.view_state.show_notice(edge, NOTICE_DURATION);
+            }
+            ws.update_title(&self.store);
+        }
+        if let Some(step) = past_edge {
+            self.cross_edge(step, slideshow);
+        }
+        // The image-change hook hears of images that stay a moment
+        self.run_image_hook(now);
+        let Some(ws) = self.window_state.as_mut() else {
+            return;
+        };
+
+        // Once held keys let go, so the window manager gets one request
+        if !self.input_state.is_navigating() {
+            ws.fit_if_due(&self.store, Instant::now());
+        }
+        if ws.cursor_idle.hide_due(Instant::now()) {
+            ws.window.set_cursor_visible(false);
+        }
+
+        // The gallery's cells follow its cursor and the window size, and
+        // the display-fit tier the window size and zoom
+        self.shared_state.set_grid(ws.view_state.gallery_cells());
+        self.shared_state
+            .set_window_size(ws.view_state.window_width, ws.view_state.window_height);
+        self.shared_state.set_zoomed_in(ws.view_state.zoomed_in());
+        self.shared_state.set_filmstrip(
+            ws.view_state
+                .filmstrip_layout()
+                .map_or(0, |_| FILMSTRIP_CELLS / 2),
+        );
+        ws.check_quality_upgrade(&self.store);
+
+        if ws.view_state.needs_render && ws.render(&self.store, &self.config) {
+            ws.update_title(&self.store);
+            ws.window.request_redraw();
+        }
+    }
+}
+
+/// The connected monitors, the primary one first
+fn screens(event_loop: &ActiveEventLoop) -> Vec<Screen> {
+    let primary = event_loop.primary_monitor();
+    let mut monitors: Vec<_> = event_loop.available_monitors().collect();
+    monitors.sort_by_key(|monitor| Some(monitor) != primary.as_ref());
+    monitors
+        .iter()
+        .map(|monitor| Screen {
+            position: (monitor.position().x, monitor.position().y),
+            size: (monitor.size().width, monitor.size().height),
+            scale: monitor.scale_factor(),
+        })
+        .collect()
+}
@@ -0,0 +1,131 @@
+//! Carrying out commands from `fiv --remote` and from launches handing
+//! their paths over to this viewer.
+
+use super::scan::{collect_images, ImageList};
+use super::{App, UserEvent, NOTICE_DURATION};
+use crate::remote::{RemoteCommand, RemoteRequest, Reply};
+use std::path::PathBuf;
+use std::sync::Arc;
+use winit::event_loop::ActiveEventLoop;
+
+impl App {
+    /// Carry out a command from `fiv --remote` as its keys would, and
+    /// answer it
+    pub(super) fn remote_command(&mut self, request: RemoteRequest, event_loop: &ActiveEventLoop) {
+        if let RemoteCommand::Open { paths } = &request.command {
+            let paths = paths.clone();
+            self.open_remote(request, paths);
+            return;
+        }
+        let Some(ws) = self.window_state.as_ref() else {
+            request.reply(Reply::error("the window isn't open yet"));
+            return;
+        };
+        // Steps past the collection come to the same as fewer, so a large
+        // count can't pass for the jump to the last image
+        let total = ws.view_state.total_images.max(1);
+        let wrap = ws.view_state.wrap;
+        let steps = |count: u32| {
+            let count = count as usize;
+            let steps = if wrap {
+                count % total
+            } else {
+                count.min(total)
+            };
+            i32::try_from(steps).unwrap_or(i32::MAX - 1)
+        };
+        let reply = match request.command {
+            RemoteCommand::Next { count } => {
+                self.input_state.jump(steps(count));
+                Reply::ok()
+            }
+            RemoteCommand::Prev { count } => {
+                self.input_state.jump(-steps(count));
+                Reply::ok()
+            }
+            RemoteCommand::Goto { index } => {
+                self.input_state.jump(ws.view_state.delta_to_number(index));
+                Reply::ok()
+            }
+            RemoteCommand::First => {
+                self.input_state.home_pressed = true;
+                Reply::ok()
+            }
+            RemoteCommand::Last => {
+                self.input_state.end_pressed = true;
+                Reply::ok()
+            }
+            RemoteCommand::Open { .. } | RemoteCommand::Quit => Reply::ok(),
+            RemoteCommand::QueryStatus => {
+                let index = ws.view_state.current_index;
+                Reply {
+                    path: self
+                        .store
+                        .get(index)
+                        .map(|slot| slot.meta.path().display().to_string()),
+                    index: Some(index + 1),
+                    total: Some(ws.view_state.total_images),
+                    ..Reply::ok()
+                }
+            }
+        };
+        let quit = request.command == RemoteCommand::Quit;
+        request.reply(reply);
+        if quit {
+            self.quit(event_loop);
+        } else if let Some(ws) = self.window_state.as_ref() {
+            ws.window.request_redraw();
+        }
+    }
+
+    /// Accept browsing `paths` in place of the collection, for
+    /// `fiv --remote open` and launches handing over to this viewer, and
+    /// scan them with this viewer's options on a thread of their own. A
+    /// large scan would outlast the client's wait, so it's answered first:
+    /// a launch told the open went ahead never opens a window itself.
+    fn open_remote(&mut self, request: RemoteRequest, paths: Vec<PathBuf>) {
+        let (Some(mut args), Some(proxy)) = (self.scan_args.clone(), self.proxy.clone()) else {
+            request.reply(Reply::error("the viewer isn't browsing directories"));
+            return;
+        };
+        // The client gave up waiting, and may be opening the paths itself
+        if !request.reply(Reply::ok()) {
+            return;
+        }
+        self.opening += 1;
+        let opening = self.opening;
+        args.paths = paths;
+        let decoder = Arc::clone(&self.decoder);
+        std::thread::spawn(move || {
+            let scanned = collect_images(&args, &decoder);
+            let _ = proxy.send_event(UserEvent::Scanned(opening, scanned));
+        });
+    }
+
+    /// Browse what a remote `open` scanned, unless a later one was
+    /// accepted meanwhile, and bring the window forward. Finding nothing
+    /// is shown in the title, the client having long gone.
+    pub(super) fn remote_scanned(&mut self, opening: usize, scanned: Result<ImageList, String>) {
+        if opening != self.opening {
+            return;
+        }
+        match scanned {
+            Ok(list) if !list.paths.is_empty() => self.open_collection(list),
+            Ok(_) => self.notify("no images found", NOTICE_DURATION),
+            Err(err) => self.notify(err, NOTICE_DURATION),
+        }
+        if let Some(ws) = self.window_state.as_ref() {
+            ws.window.set_minimized(false);
+            ws.window.focus_window();
+        }
+    }
+}
+
+/// `paths` made absolute, for another process; any that can't be are
+/// left as they are
+pub fn absolute_paths(paths: &[PathBuf]) -> Vec<PathBuf> {
+    paths
+        .iter()
+        .map(|path| path.canonicalize().unwrap_or_else(|_| path.clone()))
+        .collect()
+}
@@ -0,0 +1,413 @@
+//! Finding the images to browse, and swapping the collection for
+//! another: a drop, a sibling directory, or a remote `open`.
+
+use super::{App, NOTICE_DURATION, REFIT_DELAY};
+use crate::decode::{
+    read_path_list, scan_directory, sibling_directories, sort_paths, Decoder, SortOrder,
+};
+use crate::slot::ImageMeta;
+use crate::state::{InputState, SharedState};
+use crate::store::ImageStore;
+use crate::{archive, xmp};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Instant;
+
+/// What to browse and how to order it, from the command line; kept to
+/// scan what's dropped or opened later the same way
+#[derive(clap::Args, Debug, Clone)]
+pub struct ScanArgs {
+    /// Images and directories to browse. A single file opens its whole
+    /// directory focused on that file; `-` reads a path list from stdin.
+    #[arg(default_value = ".")]
+    pub paths: Vec<PathBuf>,
+
+    /// Include images in subdirectories
+    #[arg(short, long, global = true)]
+    pub recursive: bool,
+
+    /// Maximum subdirectory depth to descend (implies --recursive)
+    #[arg(long, value_name = "N", global = true)]
+    pub max_depth: Option<usize>,
+
+    /// Browsing order within each directory [default: name]
+    #[arg(long, value_enum)]
+    pub sort: Option<SortOrder>,
+
+    /// Reverse the sort order
+    #[arg(long)]
+    pub reverse: bool,
+
+    /// Browse images in random order
+    #[arg(long)]
+    pub shuffle: bool,
+
+    /// Only show images rated at least N stars (1-5) in their XMP sidecars
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u8).range(1..=5))]
+    pub min_rating: Option<u8>,
+}
+
+impl ScanArgs {
+    /// Directory depth to scan (1 = only the given directory)
+    pub fn scan_depth(&self) -> usize {
+        match self.max_depth {
+            Some(depth) => depth.saturating_add(1),
+            None if self.recursive => usize::MAX,
+            None => 1,
+        }
+    }
+
+    /// Scan a directory and order its images as requested
+    pub fn scan(&self, dir: &Path, decoder: &Decoder) -> Vec<PathBuf> {
+        let mut paths = scan_directory(dir, decoder, self.scan_depth());
+        if let Some(min) = self.min_rating {
+            paths.retain(|path| xmp::read_rating(path) >= min);
+        }
+        sort_paths(&mut paths, self.sort.unwrap_or_default(), self.reverse);
+        paths
+    }
+}
+
+/// The resolved list of images to browse
+#[derive(Debug)]
+pub struct ImageList {
+    pub paths: Vec<PathBuf>,
+    /// Directories the images were scanned from, for --watch
+    pub watch_roots: Vec<PathBuf>,
+    /// Index to open at
+    pub start_index: usize,
+    /// Titles show paths relative to this root (recursive mode)
+    pub title_root: Option<PathBuf>,
+    /// The directory opened, when it was the only argument
+    pub session_dir: Option<PathBuf>,
+    /// The directory browsed, opened or a lone file's, whose siblings
+    /// cross_directory steps into
+    pub browse_dir: Option<PathBuf>,
+}
+
+impl ImageList {
+    /// Name of the directory browsed, if there's one
+    fn dir_name(&self) -> String {
+        self.browse_dir
+            .as_deref()
+            .and_then(Path::file_name)
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
+}
+
+/// Resolve command line paths into the ordered, deduplicated image list.
+///
+/// Files are taken as-is, directories are expanded with `scan_directory`,
+/// and order follows the arguments. A lone file argument expands to its
+/// parent directory so neighbouring images can be browsed.
+pub fn collect_images(args: &ScanArgs, decoder: &Decoder) -> Result<ImageList, String> {
+    let depth = args.scan_depth();
+
+    if args.paths.len() == 1 && args.paths[0].as_os_str() == "-" {
+        let (mut paths, skipped) = read_path_list(std::io::stdin().lock(), decoder);
+        // Piped lists keep their order unless a sort is asked for explicitly
+        sort_paths(
+            &mut paths,
+            args.sort.unwrap_or(SortOrder::None),
+            args.reverse,
+        );
+        if skipped > 0 {
+            eprintln!(
+                "Warning: skipped {} empty or missing path(s) from stdin",
+                skipped
+            );
+        }
+        if paths.is_empty() {
+            return Err("No supported images read from stdin".to_string());
+        }
+        return Ok(ImageList {
+            paths,
+            watch_roots: Vec::new(),
+            start_index: 0,
+            title_root: None,
+            session_dir: None,
+            browse_dir: None,
+        });
+    }
+
+    if let [single] = args.paths.as_slice() {
+        let target = single
+            .canonicalize()
+            .map_err(|_| format!("Cannot access '{}'", single.display()))?;
+
+        if target.is_file() && archive::is_archive(&target) {
+            return Ok(ImageList {
+                paths: vec![target],
+                watch_roots: Vec::new(),
+                start_index: 0,
+                title_root: None,
+                session_dir: None,
+                browse_dir: None,
+            });
+        }
+
+        if target.is_file() {
+            if !decoder.is_supported(&target) {
+                return Err(format!(
+                    "'{}' is not a supported image\nSupported formats: {:?}",
+                    target.display(),
+                    decoder.extensions()
+                ));
+            }
+            let dir = target
+                .parent()
+                .map(|p| p.to_path_buf())
+                .unwrap_or_else(|| PathBuf::from("/"));
+            let paths = args.scan(&dir, decoder);
+            // Falls back to the first image if the file vanished before the scan
+            let start_index = paths.iter().position(|p| *p == target).unwrap_or(0);
+            return Ok(ImageList {
+                paths,
+                watch_roots: vec![dir.clone()],
+                start_index,
+                title_root: (depth > 1).then_some(dir.clone()),
+                session_dir: None,
+                browse_dir: Some(dir),
+            });
+        }
+
+        if target.is_dir() {
+            let paths = args.scan(&target, decoder);
+            return Ok(ImageList {
+                paths,
+                watch_roots: vec![target.clone()],
+                start_index: 0,
+                title_root: (depth > 1).then_some(target.clone()),
+                session_dir: Some(target.clone()),
+                browse_dir: Some(target),
+            });
+        }
+    }
+
+    let mut seen = HashSet::new();
+    let mut paths = Vec::new();
+    let mut unused = Vec::new();
+    let mut watch_roots = Vec::new();
+
+    for arg in &args.paths {
+        let found = match arg.canonicalize() {
+            Ok(target) if target.is_dir() => {
+                let found = args.scan(&target, decoder);
+                watch_roots.push(target);
+                found
+            }
+            Ok(target)
+                if target.is_file()
+                    && (decoder.is_supported(&target) || archive::is_archive(&target)) =>
+            {
+                vec![target]
+            }
+            _ => Vec::new(),
+        };
+
+        let before = paths.len();
+        paths.extend(found.into_iter().filter(|p| seen.insert(p.clone())));
+        if paths.len() == before {
+            unused.push(arg.display().to_string());
+        }
+    }
+
+    if paths.is_empty() {
+        return Err(format!(
+            "No supported images found in: {}\nSupported formats: {:?}",
+            unused.join(", "),
+            decoder.extensions()
+        ));
+    }
+
+    if !unused.is_empty() {
+        eprintln!("Warning: no images found in: {}", unused.join(", "));
+    }
+
+    Ok(ImageList {
+        paths,
+        watch_roots,
+        start_index: 0,
+        title_root: None,
+        session_dir: None,
+        browse_dir: None,
+    })
+}
+
+/// Multi-page files as one entry per page, the files shuffled first when
+/// there's an `rng`, and where the first page of the file at `start_index`
+/// ended up
+pub fn expand_collection(
+    decoder: &Decoder,
+    mut paths: Vec<PathBuf>,
+    start_index: usize,
+    rng: Option<&mut StdRng>,
+) -> (Vec<ImageMeta>, usize) {
+    let start_path = paths[start_index].clone();
+    if let Some(rng) = rng {
+        paths.shuffle(rng);
+    }
+    let metas = decoder.expand_pages(paths);
+    let start_index = metas
+        .iter()
+        .position(|m| m.path() == start_path)
+        .unwrap_or(0);
+    (metas, start_index)
+}
+
+/// Preloader state for browsing `store` from `start_index`, with that
+/// image pinned
+pub fn start_preloading(store: &ImageStore, start_index: usize) -> Arc<SharedState> {
+    let shared_state = Arc::new(SharedState::new());
+    shared_state.set_total(store.len());
+    store.pin(start_index);
+    shared_state.set_start(start_index);
+    shared_state
+}
+
+impl App {
+    /// Browse what was dropped onto the window in place of the collection:
+    /// a directory's images, a file's directory from that file, or several
+    /// files and directories together in the order they were dropped
+    pub(super) fn open_dropped(&mut self) {
+        let mut args = match &self.scan_args {
+            Some(args) => args.clone(),
+            None => return,
+        };
+        args.paths = std::mem::take(&mut self.dropped);
+        match collect_images(&args, &self.decoder) {
+            Ok(list) if !list.paths.is_empty() => self.open_collection(list),
+            _ => self.notify("no images in what was dropped", NOTICE_DURATION),
+        }
+    }
+
+    /// Browse `list` in place of the collection, keeping the window
+    pub(super) fn open_collection(&mut self, list: ImageList) {
+        // Left as if quitting: the old directory's place is remembered and
+        // its marks are kept
+        self.save_session();
+        self.keep_marks();
+        let shuffle = self.scan_args.as_ref().is_some_and(|args| args.shuffle);
+        let rng = shuffle.then_some(&mut self.rng);
+        let (metas, start_index) =
+            expand_collection(&self.decoder, list.paths, list.start_index, rng);
+        let store = Arc::new(self.store.sibling(metas));
+        let shared_state = start_preloading(&store, start_index);
+
+        // The old preloader stops on its own; its store is freed, budget
+        // and all, once it lets go
+        self.shared_state.shutdown();
+        self.store = store;
+        self.shared_state = shared_state;
+        self.title_root = list.title_root;
+        self.session_dir = list.session_dir;
+        self.browse_dir = list.browse_dir;
+        self.crossing = None;
+        self.replaced = true;
+        self.input_state = InputState::new();
+        self.pending_delete = None;
+        if let Some(ws) = self.window_state.as_mut() {
+            ws.title_root = self.title_root.clone();
+            ws.replace_store(&self.store, &self.shared_state, &self.decoder, &self.config);
+            if self.config.view.fit_window && self.config.view.refit_window {
+                ws.fit_at = Some(Instant::now() + REFIT_DELAY);
+            }
+            ws.update_title(&self.store);
+            ws.window.request_redraw();
+        }
+    }
+
+    /// The nearest directory with images beside the one browsed, `step`
+    /// of them on (back if negative), opened at its first image going
+    /// forward and its last going back
+    fn sibling_collection(&self, step: i32) -> Option<ImageList> {
+        let mut args = self.scan_args.clone()?;
+        let dir = self.browse_dir.as_ref()?;
+        let siblings = sibling_directories(dir);
+        let at = siblings.iter().position(|d| d == dir)?;
+        let candidates: Vec<&PathBuf> = if step > 0 {
+            siblings[at + 1..].iter().collect()
+        } else {
+            siblings[..at].iter().rev().collect()
+        };
+        for sibling in candidates {
+            args.paths = vec![sibling.clone()];
+            match collect_images(&args, &self.decoder) {
+                Ok(mut list) if !list.paths.is_empty() => {
+                    if step < 0 {
+                        list.start_index = list.paths.len() - 1;
+                    }
+                    return Some(list);
+                }
+                _ => continue,
+            }
+        }
+        None
+    }
+
+    /// Browse the next directory beside this one with images, or the
+    /// previous if `step` is negative
+    pub(super) fn step_directory(&mut self, step: i32) {
+        match self.sibling_collection(step) {
+            Some(list) => self.enter_directory(list),
+            None if step > 0 => self.notify("no next directory", NOTICE_DURATION),
+            None => self.notify("no previous directory", NOTICE_DURATION),
+        }
+    }
+
+    /// Open a sibling directory's images, naming it in the title for a
+    /// moment
+    fn enter_directory(&mut self, list: ImageList) {
+        let name = list.dir_name();
+        self.open_collection(list);
+        self.notify(format!("\u{2192} {}/", name), NOTICE_DURATION);
+    }
+
+    /// Stepped past the end (or start) with cross_directory on: offer the
+    /// next (or previous) directory with images, and enter it on stepping
+    /// that way again. A slideshow goes straight on; a held key stops at
+    /// the edge until it's pressed afresh.
+    pub(super) fn cross_edge(&mut self, step: i32, slideshow: bool) {
+        let go_on = slideshow || !self.input_state.is_repeating();
+        let list = match self.crossing.take() {
+            Some((offered, list)) if offered == step => {
+                if go_on {
+                    self.enter_directory(list);
+                    return;
+                }
+                list
+            }
+            _ => match self.sibling_collection(step) {
+                Some(list) if slideshow => {
+                    self.enter_directory(list);
+                    return;
+                }
+                Some(list) => list,
+                None => {
+                    let edge = if step > 0 {
+                        if let Some(ws) = self.window_state.as_mut() {
+                            ws.view_state.slideshow = None;
+                        }
+                        "\u{2014} end \u{2014}"
+                    } else {
+                        "\u{2014} start \u{2014}"
+                    };
+                    self.notify(edge, NOTICE_DURATION);
+                    return;
+                }
+            },
+        };
+        let (edge, way) = if step > 0 {
+            ("end", "next")
+        } else {
+            ("start", "previous")
+        };
+        let offer = format!("\u{2014} {} \u{2014} {}: {}/", edge, way, list.dir_name());
+        self.crossing = Some((step, list));
+        self.notify(offer, NOTICE_DURATION);
+    }
+}
@@ -0,0 +1,1217 @@
+//! The window: composing frames, overlays and the title, fitting the
+//! window to the image, and the work it keeps running in the background.
+
+use super::{
+    ComposedFrame, CropOverlay, FrameKey, Job, WindowState, DATA_POLL_INTERVAL, NOTICE_DURATION,
+    STATS_INTERVAL,
+};
+use crate::backend::Style;
+use crate::config::{BackgroundMode, Config, QualityTier};
+use crate::decode::{format_file_size, Decoder};
+use crate::external::ImageHookTimer;
+use crate::histogram::Histogram;
+use crate::preload::spawn_preloader;
+use crate::render::{
+    blurred_backdrop, draw_crop_overlay, draw_drop_border, draw_filmstrip, draw_help,
+    draw_histogram, draw_mark_badge, draw_panel, draw_status_bar, fitted_window_size, panel_size,
+    render_placeholder, sample_pixel, tooltip_origin, window_rect_to_image, PixelSample,
+    HISTOGRAM_SIZE, MARK_BADGE_SIZE, STATUS_BAR_HEIGHT,
+};
+use crate::session::WindowGeometry;
+use crate::slot::{ImageData, ImageSlot, ImageSource};
+use crate::state::{CursorIdle, InputState, SharedState, TitleContext, ViewState};
+use crate::store::ImageStore;
+use crate::title::{Field, TitleFormat};
+use crate::{backend, histogram};
+use pixels::{Pixels, SurfaceTexture};
+use std::path::PathBuf;
+use std::sync::{Arc, OnceLock, Weak};
+use std::time::{Duration, Instant};
+use winit::dpi::{LogicalSize, PhysicalPosition, PhysicalSize, Size};
+use winit::event_loop::{ActiveEventLoop, ControlFlow};
+use winit::window::{Fullscreen, Window};
+
+/// Thumbnails of the images at `positions`, or their full data where
+/// that's all there is, and whether any that can load are still missing
+pub(super) fn thumbnails(
+    store: &ImageStore,
+    positions: impl IntoIterator<Item = usize>,
+) -> (Vec<Option<Arc<ImageData>>>, bool) {
+    let mut loading = false;
+    let cells = positions
+        .into_iter()
+        .map(|index| {
+            let slot = store.get(index)?;
+            let data = slot.thumbnail().or_else(|| slot.peek());
+            loading |= data.is_none() && slot.error().is_none();
+            data
+        })
+        .collect();
+    (cells, loading)
+}
+
+/// Addresses of the cells' data, for a frame key
+pub(super) fn addresses(cells: &[Option<Arc<ImageData>>]) -> Vec<Option<usize>> {
+    cells
+        .iter()
+        .map(|d| d.as_ref().map(|d| Arc::as_ptr(d) as usize))
+        .collect()
+}
+
+impl WindowState {
+    pub(super) fn create(
+        event_loop: &ActiveEventLoop,
+        config: &Config,
+        store: &Arc<ImageStore>,
+        shared_state: &Arc<SharedState>,
+        decoder: &Arc<Decoder>,
+        title_root: Option<PathBuf>,
+        geometry: Option<WindowGeometry>,
+    ) -> Self {
+        // A remembered or requested geometry takes the configured size's place
+        let (width, height) = geometry.map_or(
+            (config.render.default_width, config.render.default_height),
+            |g| g.size,
+        );
+        let default_size: Size = LogicalSize::new(width, height).into();
+        let fullscreen = config.render.fullscreen || geometry.is_some_and(|g| g.fullscreen);
+        let mut window_attributes = Window::default_attributes()
+            .with_title("Fiv - Loading...")
+            .with_inner_size(default_size)
+            .with_maximized(geometry.is_some_and(|g| g.maximized))
+            .with_fullscreen(fullscreen.then_some(Fullscreen::Borderless(None)));
+        if let Some((x, y)) = geometry.and_then(|g| g.position) {
+            window_attributes = window_attributes.with_position(PhysicalPosition::new(x, y));
+        }
+
+        let window = Arc::new(
+            event_loop
+                .create_window(window_attributes)
+                .expect("Failed to create window"),
+        );
+
+        let size = window.inner_size();
+        let surface_texture = SurfaceTexture::new(size.width, size.height, Arc::clone(&window));
+        let pixels = Pixels::new(size.width, size.height, surface_texture)
+            .expect("Failed to create pixel buffer");
+
+        let mut view_state = ViewState::new(store.len(), size.width, size.height);
+        view_state.wrap = config.input.wrap;
+        view_state.tiers = config.preload.tier_sizes();
+        view_state.show_status = config.render.status_bar;
+        view_state.show_filmstrip = config.render.filmstrip;
+        view_state.title_format = TitleFormat::new(&config.render.title_format);
+        view_state.background_mode = config.render.background_mode;
+        view_state.scaling_filter = config.render.scaling_filter;
+        view_state.current_index = shared_state.current();
+
+        // Load a preview of the first image synchronously for immediate
+        // display; the preloader schedules the full decode straight after
+        let start = view_state.current_index;
+        if let Some(slot) = store.get(start) {
+            match decoder.decode(&slot.meta, QualityTier::Preview) {
+                Ok(data) => {
+                    store.insert(start, data);
+                }
+                Err(err) => {
+                    eprintln!("Warning: {}: {}", slot.meta.source, err);
+                    slot.fail(err);
+                }
+            }
+        }
+
+        // Spawn preloader after first image, sizing its display-fit tier
+        shared_state.set_window_size(size.width, size.height);
+        let preloader_handle = spawn_preloader(
+            Arc::clone(store),
+            Arc::clone(shared_state),
+            Arc::clone(decoder),
+            config.clone(),
+        );
+
+        Self {
+            window,
+            renderer: backend::create(&pixels, config.render.gpu),
+            pixels,
+            view_state,
+            title_root,
+            windowed_size: fullscreen.then_some(default_size),
+            normal_bounds: None,
+            fit_at: None,
+            image_hook: ImageHookTimer::default(),
+            cursor: None,
+            cursor_idle: CursorIdle::new(
+                config
+                    .view
+                    .hide_cursor
+                    .then_some(config.view.hide_cursor_after),
+                Instant::now(),
+            ),
+            drag_last: None,
+            click_start: None,
+            last_click: None,
+            decode_threads: shared_state.decode_threads(),
+            watched_file: None,
+            next_reload_check: Instant::now(),
+            help: Vec::new(),
+            backdrop: None,
+            histogram: None,
+            jobs: Vec::new(),
+            composed: None,
+            frames_composed: 0,
+            compose_time: None,
+            stats: Vec::new(),
+            next_stats_at: None,
+            stats_sample: None,
+            clipboard: None,
+            _preloader_handle: preloader_handle,
+        }
+    }
+
+    /// Compose and present the frame. Returns false, without touching the
+    /// pixel buffer or presenting, if the last frame is still right.
+    pub(super) fn render(&mut self, store: &ImageStore, config: &Config) -> bool {
+        if self.view_state.gallery.is_some() {
+            return self.render_gallery(store, config);
+        }
+        let shown = self.shown_slot(store);
+        let generation = shown.as_ref().map_or(0, |slot| slot.generation());
+        let image_data = shown.as_ref().and_then(|slot| slot.read());
+
+        if image_data.is_none() {
+            if let Some(err) = shown
+                .as_ref()
+                .and_then(|slot| slot.error().map(|e| e.to_string()))
+            {
+                self.composed = None;
+                self.frames_composed += 1;
+                let lines = vec![self.display_name(store), err];
+                render_placeholder(
+                    self.pixels.frame_mut(),
+                    self.view_state.window_width as usize,
+                    self.view_state.window_height as usize,
+                    config.render.background_color,
+                    &lines,
+                );
+                self.view_state.render_failed();
+                if let Some((name, details)) = self.status_bar(store, config, None) {
+                    draw_status_bar(
+                        self.pixels.frame_mut(),
+                        self.view_state.window_width as usize,
+                        self.view_state.window_height as usize,
+                        &name,
+                        &details,
+                    );
+                }
+                self.present();
+                return true;
+            }
+        }
+
+        let info_lines = self.info_lines(store, image_data.as_deref());
+        let status = self.status_bar(store, config, image_data.as_deref());
+        let inspector = self.inspector_box(image_data.as_deref());
+        let histogram = self
+            .view_state
+            .show_histogram
+            .then(|| self.refresh_histogram(image_data.as_ref()))
+            .flatten();
+        // The histogram is worked out in the background; keep looking
+        // until it's there
+        let histogram_pending = self.view_state.show_histogram && histogram.is_none();
+        let crop = self.crop_overlay(image_data.as_deref());
+        let marked = self.is_marked(store);
+        // In split view, the pinned image drawn beside this one
+        let pinned = self
+            .view_state
+            .compared()
+            .map(|id| store.slot_by_id(id).read());
+        let filmstrip = self.view_state.filmstrip_layout().map(|layout| {
+            let (first, positions) = self.view_state.filmstrip_positions();
+            let (cells, loading) = thumbnails(store, positions);
+            (layout, first, cells, loading)
+        });
+        let filmstrip_loading = filmstrip.as_ref().is_some_and(|(.., loading)| *loading);
+        let key = FrameKey {
+            filmstrip: filmstrip.as_ref().map(|(_, _, cells, _)| addresses(cells)),
+            ..self.frame_key(
+                image_data.as_ref(),
+                info_lines.clone(),
+                status.clone(),
+                inspector.clone(),
+                self.view_state.show_histogram.then_some(!histogram_pending),
+                crop.clone(),
+                marked,
+                pinned.as_ref().map(Option::as_ref),
+            )
+        };
+        if let Some(composed) = self.composed.as_ref().filter(|c| c.key == key) {
+            match composed.quality {
+                Some(quality) => self.view_state.render_complete(quality),
+                None => self.view_state.needs_render = true,
+            }
+            self.view_state.pinned_render_quality = composed.pinned_quality;
+            self.view_state.needs_render |= pinned.is_some() && composed.pinned_quality.is_none();
+            self.view_state.needs_render |= histogram_pending || filmstrip_loading;
+            return false;
+        }
+        self.frames_composed += 1;
+
+        let blur = self.view_state.background_mode == BackgroundMode::Blur && pinned.is_none();
+        if blur {
+            self.refresh_backdrop(image_data.as_ref());
+        }
+        let backdrop = self.backdrop.as_ref().filter(|_| blur);
+        let style = Style {
+            background: config.render.background_color,
+            backdrop: backdrop.map(|(_, pixels)| pixels.as_slice()),
+            filter: self.view_state.scaling_filter,
+        };
+        let started = Instant::now();
+        let (result, pinned_quality) = match &pinned {
+            Some(pinned_data) => {
+                let (left, right) = self.renderer.render_pair(
+                    &mut self.pixels,
+                    &self.view_state,
+                    pinned_data.as_ref(),
+                    image_data.as_ref(),
+                    &style,
+                );
+                (right, left.quality)
+            }
+            None => {
+                let result = self.renderer.render(
+                    &mut self.pixels,
+                    &self.view_state,
+                    image_data.as_ref(),
+                    &style,
+                );
+                // Split view fits both images, leaving the pan as it was
+                self.view_state.transform.pan_x = result.pan.0;
+                self.view_state.transform.pan_y = result.pan.1;
+                (result, None)
+            }
+        };
+        self.compose_time = Some(started.elapsed());
+        self.view_state.display_scale = result.scale;
+        self.view_state.embedded_preview = image_data.as_ref().is_some_and(|d| d.embedded_preview);
+        self.view_state.over_budget = image_data.as_ref().is_some_and(|d| d.over_budget);
+        self.view_state.partial = image_data
+            .as_ref()
+            .filter(|d| d.partial)
+            .map(|_| generation);
+        match result.quality {
+            Some(quality) => self.view_state.render_complete(quality),
+            None => self.view_state.needs_render = true,
+        }
+        self.view_state.pinned_render_quality = pinned_quality;
+        self.view_state.needs_render |= pinned.is_some() && pinned_quality.is_none();
+        self.view_state.needs_render |= histogram_pending || filmstrip_loading;
+
+        // Overlays go on top of the image, the help panel over everything
+        let debug_lines = self.view_state.show_debug.then(|| {
+            let mut lines = vec![
+                format!("Frames composed: {}", self.frames_composed),
+                format!("Scale factor: {}", self.window.scale_factor()),
+            ];
+            lines.extend(self.stats.iter().cloned());
+            lines
+        });
+        let frame = self.pixels.frame_mut();
+        if let Some(lines) = info_lines {
+            draw_panel(
+                frame,
+                self.view_state.window_width as usize,
+                self.view_state.window_height as usize,
+                0,
+                0,
+                &lines,
+            );
+        }
+        if let Some((name, details)) = status {
+            draw_status_bar(
+                frame,
+                self.view_state.window_width as usize,
+                self.view_state.window_height as usize,
+                &name,
+                &details,
+            );
+        }
+        if let Some((layout, first, cells, _)) = &filmstrip {
+            draw_filmstrip(
+                frame,
+                self.view_state.window_width as usize,
+                self.view_state.window_height as usize,
+                layout,
+                *first,
+                cells,
+                self.view_state.scaling_filter,
+            );
+        }
+        // Top-right, clear of the info panel
+        let mut top_right = 0;
+        if let Some(lines) = debug_lines {
+            let window_w = self.view_state.window_width as usize;
+            let (panel_w, panel_h) = panel_size(&lines);
+            draw_panel(
+                frame,
+                window_w,
+                self.view_state.window_height as usize,
+                window_w.saturating_sub(panel_w),
+                0,
+                &lines,
+            );
+            top_right = panel_h;
+        }
+        if marked {
+            let window_w = self.view_state.window_width as usize;
+            draw_mark_badge(
+                frame,
+                window_w,
+                self.view_state.window_height as usize,
+                window_w.saturating_sub(MARK_BADGE_SIZE),
+                top_right,
+            );
+        }
+        if let Some(histogram) = histogram {
+            // Bottom-right, above the status bar
+            let (window_w, window_h) = (
+                self.view_state.window_width as usize,
+                self.view_state.window_height as usize,
+            );
+            let status_h = if self.view_state.show_status {
+                STATUS_BAR_HEIGHT
+            } else {
+                0
+            };
+            draw_histogram(
+                frame,
+                window_w,
+                window_h,
+                window_w.saturating_sub(HISTOGRAM_SIZE.0),
+                window_h.saturating_sub(HISTOGRAM_SIZE.1 + status_h),
+                &histogram.series(self.view_state.channel_mode),
+            );
+        }
+        if let Some((rect, label)) = crop {
+            draw_crop_overlay(
+                frame,
+                self.view_state.window_width as usize,
+                self.view_state.window_height as usize,
+                rect,
+                &label,
+            );
+        }
+        if let Some(((x, y), lines)) = inspector {
+            draw_panel(
+                frame,
+                self.view_state.window_width as usize,
+                self.view_state.window_height as usize,
+                x,
+                y,
+                &lines,
+            );
+        }
+        if self.view_state.drop_hover {
+            draw_drop_border(
+                frame,
+                self.view_state.window_width as usize,
+                self.view_state.window_height as usize,
+            );
+        }
+        if self.view_state.show_help {
+            draw_help(
+                frame,
+                self.view_state.window_width as usize,
+                self.view_state.window_height as usize,
+                &self.help,
+            );
+        }
+
+        // Keyed on the state after rendering, with the pan clamped
+        self.composed = Some(ComposedFrame {
+            key: FrameKey {
+                transform: self.view_state.transform,
+                ..key
+            },
+            _data: image_data.as_ref().map(Arc::downgrade).unwrap_or_default(),
+            quality: result.quality,
+            _pinned: pinned
+                .flatten()
+                .as_ref()
+                .map(Arc::downgrade)
+                .unwrap_or_default(),
+            pinned_quality,
+            _cells: filmstrip
+                .iter()
+                .flat_map(|(_, _, cells, _)| cells)
+                .map(|d| d.as_ref().map(Arc::downgrade).unwrap_or_default())
+                .collect(),
+        });
+        self.present();
+        true
+    }
+
+    /// Compose the gallery in place of the image, with the status bar and
+    /// help over it. Returns false, like `render`, if the last frame is
+    /// still right.
+    fn render_gallery(&mut self, store: &ImageStore, config: &Config) -> bool {
+        // The collection may have changed under the grid
+        self.view_state.scroll_gallery();
+        let (Some(layout), Some(cells)) = (
+            self.view_state.gallery_layout(),
+            self.view_state.gallery_cells(),
+        ) else {
+            return false;
+        };
+        // Keep looking until the thumbnails that can load are there
+        let (data, loading) = thumbnails(store, cells.clone());
+        self.view_state.needs_render = loading;
+        self.view_state.last_render_quality = None;
+        self.view_state.display_scale = None;
+        self.view_state.decode_failed = false;
+        self.view_state.embedded_preview = false;
+        self.view_state.over_budget = false;
+        self.view_state.partial = None;
+
+        let status = self.status_bar(store, config, None);
+        let key = FrameKey {
+            gallery: Some(addresses(&data)),
+            ..self.frame_key(None, None, status.clone(), None, None, None, false, None)
+        };
+        if self.composed.as_ref().is_some_and(|c| c.key == key) {
+            return false;
+        }
+        self.frames_composed += 1;
+
+        let style = Style {
+            background: config.render.background_color,
+            backdrop: None,
+            filter: self.view_state.scaling_filter,
+        };
+        let cursor = self.view_state.current_index.wrapping_sub(cells.start);
+        let started = Instant::now();
+        self.renderer.render_grid(
+            &mut self.pixels,
+            &self.view_state,
+            &layout,
+            &data,
+            cursor,
+            &style,
+        );
+        self.compose_time = Some(started.elapsed());
+        self.draw_gallery_overlays(status);
+
+        self.composed = Some(ComposedFrame {
+            key,
+            _data: Weak::new(),
+            quality: None,
+            _pinned: Weak::new(),
+            pinned_quality: None,
+            _cells: data
+                .iter()
+                .map(|d| d.as_ref().map(Arc::downgrade).unwrap_or_default())
+                .collect(),
+        });
+        self.present();
+        true
+    }
+
+    /// The status bar, drop border and help panel over the gallery
+    fn draw_gallery_overlays(&mut self, status: Option<(String, String)>) {
+        let (window_w, window_h) = (
+            self.view_state.window_width as usize,
+            self.view_state.window_height as usize,
+        );
+        let frame = self.pixels.frame_mut();
+        if let Some((name, details)) = status {
+            draw_status_bar(frame, window_w, window_h, &name, &details);
+        }
+        if self.view_state.drop_hover {
+            draw_drop_border(frame, window_w, window_h);
+        }
+        if self.view_state.show_help {
+            draw_help(frame, window_w, window_h, &self.help);
+        }
+    }
+
+    /// Slot of the image on screen: the current one, or the pinned one
+    /// while it's blinked in its place
+    fn shown_slot(&self, store: &ImageStore) -> Option<Arc<ImageSlot>> {
+        match self.view_state.blinked() {
+            Some(id) => Some(store.slot_by_id(id)),
+            None => store.get(self.view_state.current_index),
+        }
+    }
+
+    /// Show the frame buffer, with the image underneath on the GPU backend
+    pub(super) fn present(&mut self) {
+        let _ = self.renderer.present(&self.pixels);
+    }
+
+    /// Key for the frame the current state would compose
+    #[allow(clippy::too_many_arguments)]
+    fn frame_key(
+        &self,
+        data: Option<&Arc<ImageData>>,
+        info: Option<Vec<String>>,
+        status: Option<(String, String)>,
+        inspector: Option<((usize, usize), Vec<String>)>,
+        histogram: Option<bool>,
+        crop: Option<CropOverlay>,
+        marked: bool,
+        pinned: Option<Option<&Arc<ImageData>>>,
+    ) -> FrameKey {
+        let view = &self.view_state;
+        FrameKey {
+            data: data.map(|d| Arc::as_ptr(d) as usize),
+            index: view.current_index,
+            size: (view.window_width, view.window_height),
+            transform: view.transform,
+            frame_index: view.frame_index,
+            filter: view.scaling_filter,
+            channel: view.channel_mode,
+            adjustments: view.adjustments,
+            background: view.background_mode,
+            info,
+            status,
+            inspector,
+            histogram,
+            crop,
+            marked,
+            pinned: pinned.map(|data| data.map(|d| Arc::as_ptr(d) as usize)),
+            gallery: None,
+            filmstrip: None,
+            drop_hover: view.drop_hover,
+            help: view.show_help,
+            debug: view.show_debug.then(|| self.stats.clone()),
+        }
+    }
+
+    /// Rebuild the blurred background if the image data or window size
+    /// changed since it was made
+    fn refresh_backdrop(&mut self, data: Option<&Arc<ImageData>>) {
+        let Some(data) = data else {
+            self.backdrop = None;
+            return;
+        };
+        let (width, height) = (self.view_state.window_width, self.view_state.window_height);
+        let current = self.backdrop.as_ref().is_some_and(|((made_for, w, h), _)| {
+            std::ptr::eq(made_for.as_ptr(), Arc::as_ptr(data)) && (*w, *h) == (width, height)
+        });
+        if !current {
+            let pixels = blurred_backdrop(
+                data.frame(0),
+                data.width as usize,
+                data.height as usize,
+                width as usize,
+                height as usize,
+            );
+            self.backdrop = Some(((Arc::downgrade(data), width, height), pixels));
+        }
+    }
+
+    /// The histogram of the displayed image data, if it's ready. Starts
+    /// working it out in the background when the data changed since the
+    /// last one.
+    fn refresh_histogram(&mut self, data: Option<&Arc<ImageData>>) -> Option<Histogram> {
+        let data = data?;
+        let current = self
+            .histogram
+            .as_ref()
+            .filter(|(made_for, _)| std::ptr::eq(made_for.as_ptr(), Arc::as_ptr(data)));
+        if let Some((_, result)) = current {
+            return result.get().cloned();
+        }
+
+        let result = Arc::new(OnceLock::new());
+        let (target, source) = (Arc::clone(&result), Arc::clone(data));
+        std::thread::spawn(move || {
+            let _ = target.set(histogram::compute(source.frame(0)));
+        });
+        self.histogram = Some((Arc::downgrade(data), result));
+        None
+    }
+
+    /// Info overlay text for the current image, if the overlay is visible
+    fn info_lines(&self, store: &ImageStore, data: Option<&ImageData>) -> Option<Vec<String>> {
+        if !self.view_state.show_info {
+            return None;
+        }
+        let slot = store.get(self.view_state.current_index)?;
+        let dims = data
+            .map(|d| (d.full_width, d.full_height))
+            .or_else(|| slot.meta.dimensions());
+        let mut lines = slot.meta.info().lines(&self.display_name(store), dims);
+        if let Some(profile) = slot.meta.color_profile() {
+            lines.push(format!("Color profile: {}", profile));
+        }
+        lines.push(format!("Decode threads: {}", self.decode_threads));
+        Some(lines)
+    }
+
+    /// Status bar text for the current image, name and details, if the bar
+    /// is visible
+    fn status_bar(
+        &self,
+        store: &ImageStore,
+        config: &Config,
+        data: Option<&ImageData>,
+    ) -> Option<(String, String)> {
+        if !self.view_state.show_status {
+            return None;
+        }
+        let slot = store.get(self.view_state.current_index)?;
+        let dims = data
+            .map(|d| (d.full_width, d.full_height))
+            .or_else(|| slot.meta.dimensions());
+        let similar = store
+            .similar_positions(
+                store.slot_id(self.view_state.current_index),
+                config.duplicates.max_distance,
+            )
+            .len();
+        let details = self.view_state.status_text(
+            slot.meta.rating(),
+            similar,
+            dims,
+            slot.meta.info().file_size,
+        );
+        Some((self.display_name(store), details))
+    }
+
+    /// The pixel under the cursor, while the inspector is on
+    fn inspected_pixel(&self, data: Option<&ImageData>) -> Option<PixelSample> {
+        // Split view doesn't place the image where the inspector expects
+        if !self.view_state.show_inspector || self.view_state.compared().is_some() {
+            return None;
+        }
+        let cursor = self.cursor?;
+        sample_pixel(
+            data?,
+            self.view_state.frame_index,
+            self.view_state.window_width as usize,
+            self.view_state.window_height as usize,
+            &self.view_state.transform,
+            (cursor.x, cursor.y),
+        )
+    }
+
+    /// Inspector box beside the cursor: its top-left corner and lines
+    fn inspector_box(&self, data: Option<&ImageData>) -> Option<((usize, usize), Vec<String>)> {
+        let lines = self.inspected_pixel(data)?.lines();
+        let cursor = self.cursor?;
+        let window = (
+            self.view_state.window_width as usize,
+            self.view_state.window_height as usize,
+        );
+        let origin = tooltip_origin((cursor.x, cursor.y), panel_size(&lines), window);
+        Some((origin, lines))
+    }
+
+    /// Crop selection overlay: the selected window rectangle, and the size
+    /// in image pixels it selects
+    fn crop_overlay(&self, data: Option<&ImageData>) -> Option<CropOverlay> {
+        let (start, end) = self.view_state.crop_selection()?.corners();
+        let clamp = |(x, y): (i32, i32)| {
+            (
+                x.clamp(0, self.view_state.window_width as i32) as usize,
+                y.clamp(0, self.view_state.window_height as i32) as usize,
+            )
+        };
+        let ((left, top), (right, bottom)) = (clamp(start), clamp(end));
+        let selected = data.and_then(|data| {
+            window_rect_to_image(
+                data,
+                self.view_state.window_width as usize,
+                self.view_state.window_height as usize,
+                &self.view_state.transform,
+                (start.0 as f64, start.1 as f64),
+                (end.0 as f64, end.1 as f64),
+            )
+        });
+        let label = match selected {
+            Some(rect) => format!("{}x{}", rect.width, rect.height),
+            None => "0x0".to_string(),
+        };
+        Some(((left, top, right - left, bottom - top), label))
+    }
+
+    /// Copy the hex value of the inspected pixel to the clipboard
+    pub(super) fn copy_inspected_pixel(&mut self, store: &ImageStore) {
+        let data = store.read(self.view_state.current_index);
+        let Some(sample) = self.inspected_pixel(data.as_deref()) else {
+            return;
+        };
+        let hex = sample.hex();
+        let copied = match self.clipboard.take() {
+            Some(clipboard) => Ok(clipboard),
+            None => arboard::Clipboard::new(),
+        }
+        .and_then(|mut clipboard| {
+            clipboard.set_text(hex.as_str())?;
+            self.clipboard = Some(clipboard);
+            Ok(())
+        });
+        let notice = match copied {
+            Ok(()) => format!("copied {}", hex),
+            Err(err) => {
+                eprintln!("Warning: cannot copy to the clipboard: {}", err);
+                format!("copy failed: {}", err)
+            }
+        };
+        self.view_state.show_notice(notice, NOTICE_DURATION);
+        self.update_title(store);
+    }
+
+    /// Name of the current image as shown to the user
+    fn display_name(&self, store: &ImageStore) -> String {
+        let Some(slot) = store.get(self.view_state.current_index) else {
+            return String::new();
+        };
+        let path = slot.meta.path();
+        let mut name = match &self.title_root {
+            Some(root) => path.strip_prefix(root).ok().map(|p| p.as_os_str()),
+            None => path.file_name(),
+        }
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+        if let ImageSource::ArchiveEntry { name: entry, .. } = &slot.meta.source {
+            name = format!("{}:{}", name, entry);
+        }
+
+        match slot.meta.page {
+            Some(page) => format!("{} (page {}/{})", name, page.index + 1, page.count),
+            None => name,
+        }
+    }
+
+    pub(super) fn update_title(&self, store: &ImageStore) {
+        let mut filename = self.display_name(store);
+        if self.is_marked(store) {
+            filename.insert_str(0, "* ");
+        }
+        let slot = store.get(self.view_state.current_index);
+        let path = slot
+            .as_ref()
+            .map(|slot| slot.meta.source.to_string())
+            .unwrap_or_default();
+        let context = TitleContext {
+            filename: &filename,
+            path: &path,
+            dimensions: slot.as_ref().and_then(|slot| {
+                slot.peek()
+                    .map(|d| (d.full_width, d.full_height))
+                    .or_else(|| slot.meta.dimensions())
+            }),
+            // Read from the file header, so only when it's shown
+            file_size: slot
+                .as_ref()
+                .filter(|_| self.view_state.title_format.uses(Field::Size))
+                .and_then(|slot| slot.meta.info().file_size),
+        };
+        self.window.set_title(&self.view_state.title(&context));
+    }
+
+    /// Whether the displayed image is marked
+    fn is_marked(&self, store: &ImageStore) -> bool {
+        !store.is_empty()
+            && self
+                .view_state
+                .marked
+                .contains(&store.slot_id(self.view_state.current_index))
+    }
+
+    /// Match the view and pixel buffer to the window's size in physical
+    /// pixels, so the image is drawn one buffer pixel per device pixel
+    pub(super) fn handle_resize(&mut self, width: u32, height: u32) {
+        if width == 0 || height == 0 {
+            return;
+        }
+        self.view_state.resize(width, height);
+        let _ = self.pixels.resize_surface(width, height);
+        let _ = self.pixels.resize_buffer(width, height);
+    }
+
+    /// Zoom by `factor` around `at` (or the window center), leaving
+    /// actual-size mode first so the step continues from 100%
+    pub(super) fn zoom(
+        &mut self,
+        factor: f64,
+        at: Option<PhysicalPosition<f64>>,
+        store: &ImageStore,
+        config: &Config,
+    ) {
+        if self.view_state.transform.actual_size {
+            if let Some(data) = store.read(self.view_state.current_index) {
+                let (full_w, full_h) = self
+                    .view_state
+                    .transform
+                    .orientation
+                    .display_dims(data.full_width as usize, data.full_height as usize);
+                let fit = (self.view_state.window_width as f64 / full_w.max(1) as f64)
+                    .min(self.view_state.window_height as f64 / full_h.max(1) as f64);
+                self.view_state.leave_actual_size(fit);
+            }
+        }
+
+        let anchor = at
+            .map(|pos| {
+                (
+                    pos.x - self.view_state.window_width as f64 / 2.0,
+                    pos.y - self.view_state.window_height as f64 / 2.0,
+                )
+            })
+            .unwrap_or((0.0, 0.0));
+        self.view_state.zoom_by(factor, anchor, &config.view);
+    }
+
+    /// Note the window's size and position while it's neither maximized
+    /// nor fullscreen, after it was resized or moved
+    pub(super) fn note_bounds(&mut self) {
+        if self.window.fullscreen().is_some() || self.window.is_maximized() {
+            return;
+        }
+        let size = self
+            .window
+            .inner_size()
+            .to_logical::<u32>(self.window.scale_factor());
+        let position = self.window.outer_position().ok().map(|p| (p.x, p.y));
+        self.normal_bounds = Some(((size.width, size.height), position));
+    }
+
+    /// Where the window is, to open the next one the same way
+    pub(super) fn geometry(&self) -> WindowGeometry {
+        let fullscreen = self.window.fullscreen().is_some();
+        let maximized = self.window.is_maximized();
+        let (size, position) = match self.normal_bounds {
+            Some(bounds) if fullscreen || maximized => bounds,
+            _ => {
+                let scale = self.window.scale_factor();
+                let size = match (fullscreen, self.windowed_size) {
+                    (true, Some(size)) => size.to_logical::<u32>(scale),
+                    _ => self.window.inner_size().to_logical::<u32>(scale),
+                };
+                let position = self.window.outer_position().ok().map(|p| (p.x, p.y));
+                ((size.width, size.height), position)
+            }
+        };
+        WindowGeometry {
+            size,
+            position,
+            maximized,
+            fullscreen,
+        }
+    }
+
+    /// Resize the window to show the current image 1:1, within most of the
+    /// monitor. Nothing happens in fullscreen or the gallery. False if the
+    /// image's size isn't known yet.
+    fn fit_to_image(&mut self, store: &ImageStore) -> bool {
+        if self.window.fullscreen().is_some() || self.view_state.gallery.is_some() {
+            return true;
+        }
+        let Some(slot) = self.shown_slot(store) else {
+            return true;
+        };
+        let Some((width, height)) = slot
+            .peek()
+            .map(|data| (data.full_width, data.full_height))
+            .or_else(|| slot.meta.dimensions())
+        else {
+            return false;
+        };
+        let image = self
+            .view_state
+            .transform
+            .orientation
+            .display_dims(width as usize, height as usize);
+        let monitor = self
+            .window
+            .current_monitor()
+            .map(|monitor| (monitor.size().width, monitor.size().height));
+        let (width, height) = fitted_window_size(image, monitor);
+
+        self.window.set_maximized(false);
+        self.view_state.reset_zoom();
+        // Applied now on some platforms; elsewhere a resize event follows
+        if let Some(size) = self
+            .window
+            .request_inner_size(PhysicalSize::new(width, height))
+        {
+            self.handle_resize(size.width, size.height);
+        }
+        true
+    }
+
+    /// Fit the window to the image if it's time to. Retried shortly while
+    /// the image's size isn't known.
+    pub(super) fn fit_if_due(&mut self, store: &ImageStore, now: Instant) {
+        if self.fit_at.is_some_and(|at| at <= now) {
+            self.fit_at = (!self.fit_to_image(store)).then(|| now + DATA_POLL_INTERVAL);
+        }
+    }
+
+    /// Switch between borderless fullscreen and the previous windowed size
+    pub(super) fn toggle_fullscreen(&mut self) {
+        if self.window.fullscreen().is_some() {
+            self.window.set_fullscreen(None);
+            if let Some(size) = self.windowed_size.take() {
+                let _ = self.window.request_inner_size(size);
+            }
+        } else {
+            // Logical, in case fullscreen ends on a monitor of another scale
+            let scale = self.window.scale_factor();
+            self.windowed_size = Some(self.window.inner_size().to_logical::<f64>(scale).into());
+            self.window
+                .set_fullscreen(Some(Fullscreen::Borderless(None)));
+        }
+
+        // Some compositors send zero-sized or late resize events on
+        // transitions, so pick up whatever size we have now and redraw
+        let size = self.window.inner_size();
+        self.handle_resize(size.width, size.height);
+        self.view_state.needs_render = true;
+        self.window.request_redraw();
+    }
+
+    pub(super) fn check_quality_upgrade(&mut self, store: &ImageStore) {
+        if self.view_state.needs_render || !self.view_state.needs_quality_upgrade() {
+            return;
+        }
+
+        let shown = self.shown_slot(store);
+        let dominated_by_preloader = shown
+            .as_ref()
+            .and_then(|slot| slot.current_quality())
+            .map(|q| Some(q) > self.view_state.last_render_quality)
+            .unwrap_or(false);
+        // Finer passes over a progressive file stay at the same tier
+        let refined = self.view_state.partial.is_some_and(|rendered| {
+            shown
+                .as_ref()
+                .is_some_and(|slot| slot.generation() != rendered)
+        });
+
+        // The pinned image in split view upgrades on its own
+        let pinned_upgraded = self.view_state.compared().is_some_and(|id| {
+            store
+                .slot_by_id(id)
+                .current_quality()
+                .is_some_and(|q| Some(q) > self.view_state.pinned_render_quality)
+        });
+
+        if dominated_by_preloader || refined || pinned_upgraded {
+            self.view_state.signal_quality_upgrade();
+        }
+    }
+
+    pub(super) fn control_flow(&self, input_state: &InputState, auto_reload: bool) -> ControlFlow {
+        // A render clears `needs_render`; one still set after it means the
+        // image is loading, and the preloader can't wake us when it's done,
+        // so look again shortly rather than spinning. Background jobs are
+        // waited for the same way.
+        let waiting = self.view_state.needs_render
+            || self.view_state.needs_quality_upgrade()
+            || !self.jobs.is_empty();
+        let data_check = waiting.then(|| Instant::now() + DATA_POLL_INTERVAL);
+
+        let reload_check = auto_reload.then_some(self.next_reload_check);
+        let notice_end = self.view_state.notice.as_ref().map(|(_, until)| *until);
+        let slideshow = self.view_state.slideshow.and_then(|s| s.next_advance);
+        let deadline = [
+            self.view_state.next_frame_at,
+            reload_check,
+            notice_end,
+            slideshow,
+            self.view_state.next_blink_at,
+            self.fit_at,
+            self.image_hook.deadline(),
+            self.cursor_idle.deadline(),
+            self.next_stats_at,
+            data_check,
+        ]
+        .into_iter()
+        .flatten()
+        .min();
+
+        if input_state.is_navigating() {
+            ControlFlow::Poll
+        } else if let Some(deadline) = deadline {
+            ControlFlow::WaitUntil(deadline)
+        } else {
+            ControlFlow::Wait
+        }
+    }
+
+    /// Bring the debug overlay's statistics up to date, once per
+    /// `STATS_INTERVAL` while it's shown
+    pub(super) fn refresh_stats(
+        &mut self,
+        store: &ImageStore,
+        shared_state: &SharedState,
+        decoder: &Decoder,
+        now: Instant,
+    ) {
+        if !self.view_state.show_debug {
+            self.next_stats_at = None;
+            self.stats_sample = None;
+            return;
+        }
+        if self.next_stats_at.is_some_and(|at| now < at) {
+            return;
+        }
+        self.next_stats_at = Some(now + STATS_INTERVAL);
+
+        let sample = decoder.metrics().sample(now);
+        let earlier = self.stats_sample.replace(sample).unwrap_or(sample);
+        let (images, megabytes) = sample.rates_since(&earlier);
+        let mean = sample
+            .mean_decode_since(&earlier)
+            .map_or("-".to_string(), |d| format!("{} ms", d.as_millis()));
+        let compose = self.compose_time.map_or("-".to_string(), |d| {
+            format!("{:.1} ms", d.as_secs_f64() * 1000.0)
+        });
+        let first_pixel = self
+            .view_state
+            .first_pixel
+            .map_or("-".to_string(), |d| format!("{} ms", d.as_millis()));
+        let direction = format!("{:?}", shared_state.direction()).to_lowercase();
+        let [thumbnails, previews, fulls] = store.resident_tiers();
+        let budget = store.budget();
+        let stats = vec![
+            format!(
+                "Memory: {} of {}",
+                format_file_size(budget.used() as u64),
+                format_file_size(budget.total() as u64)
+            ),
+            format!(
+                "Loaded: {} thumbnails, {} previews, {} full",
+                thumbnails, previews, fulls
+            ),
+            format!("Decoding: {:.1} images/s, {:.1} MB/s", images, megabytes),
+            format!("Mean decode: {}", mean),
+            format!("Decode failures: {}", sample.failed),
+            format!("Last frame: {}", compose),
+            format!("First pixel after navigating: {}", first_pixel),
+            format!(
+                "Preload queue: {}, going {}",
+                shared_state.queued(),
+                direction
+            ),
+        ];
+        if stats != self.stats {
+            self.stats = stats;
+            self.view_state.needs_render = true;
+        }
+    }
+
+    /// Show the pointer again after it was hidden, for a button, the wheel
+    /// or a move to `moved_to`, and start waiting to hide it again
+    pub(super) fn mouse_used(&mut self, moved_to: Option<PhysicalPosition<f64>>) {
+        let now = Instant::now();
+        let show = match moved_to {
+            Some(at) => self.cursor_idle.moved((at.x, at.y), now),
+            None => self.cursor_idle.used(now),
+        };
+        if show {
+            self.window.set_cursor_visible(true);
+        }
+    }
+
+    /// Run `work` on a background thread. `rewrites` is the slot id of
+    /// an image whose file it changes.
+    pub(super) fn spawn_job(
+        &mut self,
+        rewrites: Option<usize>,
+        work: impl FnOnce() -> Result<String, String> + Send + 'static,
+    ) {
+        self.jobs.push(Job {
+            handle: std::thread::spawn(work),
+            rewrites,
+        });
+    }
+
+    /// Collect finished background jobs, showing what they report.
+    /// Returns None if none finished, else the slot ids of images whose
+    /// files they rewrote.
+    pub(super) fn finish_jobs(&mut self) -> Option<Vec<usize>> {
+        let (done, running): (Vec<_>, Vec<_>) = std::mem::take(&mut self.jobs)
+            .into_iter()
+            .partition(|job| job.handle.is_finished());
+        self.jobs = running;
+        if done.is_empty() {
+            return None;
+        }
+        let mut rewritten = Vec::new();
+        for job in done {
+            let text = match job.handle.join() {
+                Ok(Ok(text)) => {
+                    rewritten.extend(job.rewrites);
+                    text
+                }
+                Ok(Err(text)) => text,
+                Err(_) => "background job failed".to_string(),
+            };
+            self.view_state.show_notice(text, NOTICE_DURATION);
+        }
+        Some(rewritten)
+    }
+
+    /// Browse `store` in place of the collection shown until now, from the
+    /// preloader's start, with a preloader of its own
+    pub(super) fn replace_store(
+        &mut self,
+        store: &Arc<ImageStore>,
+        shared_state: &Arc<SharedState>,
+        decoder: &Arc<Decoder>,
+        config: &Config,
+    ) {
+        self.view_state
+            .replace_collection(store.len(), shared_state.current());
+        // Slot ids now refer to the new store's images
+        for job in &mut self.jobs {
+            job.rewrites = None;
+        }
+        self.watched_file = None;
+        self.composed = None;
+        self.image_hook = ImageHookTimer::default();
+        self._preloader_handle = spawn_preloader(
+            Arc::clone(store),
+            Arc::clone(shared_state),
+            Arc::clone(decoder),
+            config.clone(),
+        );
+        self.decode_threads = shared_state.decode_threads();
+    }
+
+    /// Check the displayed file's modification time, at most once per
+    /// `interval`. Returns true if it changed (or the file went away)
+    /// since the last check of the same image.
+    pub(super) fn current_file_changed(&mut self, store: &ImageStore, interval: Duration) -> bool {
+        let now = Instant::now();
+        if now < self.next_reload_check {
+            return false;
+        }
+        self.next_reload_check = now + interval;
+
+        let Some(slot) = store.get(self.view_state.current_index) else {
+            return false;
+        };
+        let id = store.slot_id(self.view_state.current_index);
+        let mtime = std::fs::metadata(slot.meta.path())
+            .and_then(|m| m.modified())
+            .ok();
+        match self.watched_file.replace((id, mtime)) {
+            Some((last_id, last_mtime)) => last_id == id && last_mtime != mtime,
+            None => false,
+        }
+    }
+
+    /// Advance animated images to the frame due now
+    pub(super) fn tick_animation(&mut self, store: &ImageStore) {
+        let image_data = store.read(self.view_state.current_index);
+        let delays = image_data.as_ref().map_or(&[][..], |img| &img.delays);
+        self.view_state.tick_animation(delays, Instant::now());
+    }
+}
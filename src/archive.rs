@@ -0,0 +1,134 @@
+//! Zip archives - browse the images in .zip/.cbz files without extracting.
+//!
+//! Entries are read straight from the archive. `ZipArchive` isn't `Sync`,
+//! so each reading thread keeps its own handle to the archive it read last;
+//! consecutive entries of one archive reuse it instead of re-parsing the
+//! central directory.
+
+use crate::decode::{natural_cmp, Decoder};
+use std::cell::RefCell;
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::{Path, PathBuf};
+use zip::result::ZipError;
+use zip::ZipArchive;
+
+/// Archive file extensions (lowercase)
+const EXTENSIONS: &[&str] = &["zip", "cbz"];
+
+/// Most bytes set aside up front for an entry. The size its header claims
+/// is the file's to make up, so larger entries grow the buffer as they are
+/// actually read.
+const PREALLOCATE_LIMIT: u64 = 64 << 20;
+
+type Archive = ZipArchive<BufReader<File>>;
+
+thread_local! {
+    /// The archive this thread read from last, kept open for the next entry
+    static OPEN: RefCell<Option<(PathBuf, Archive)>> = const { RefCell::new(None) };
+}
+
+/// Whether a path names an archive fiv can browse
+pub fn is_archive(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+}
+
+/// Names of the supported images in an archive, in natural order
+pub fn list_images(archive: &Path, decoder: &Decoder) -> Result<Vec<String>, String> {
+    let zip = open(archive).map_err(|e| format!("{}: {}", archive.display(), e))?;
+    let mut names: Vec<String> = zip
+        .file_names()
+        // Folders, and the resource forks macOS adds to zips it creates
+        .filter(|name| !name.ends_with('/') && !name.starts_with("__MACOSX/"))
+        .filter(|name| decoder.is_supported(Path::new(name)))
+        .map(String::from)
+        .collect();
+    names.sort_by(|a, b| natural_cmp(OsStr::new(a), OsStr::new(b)));
+    Ok(names)
+}
+
+/// Read up to `limit` bytes of an entry
+pub fn read_entry(archive: &Path, name: &str, limit: u64) -> io::Result<Vec<u8>> {
+    OPEN.with(|cached| {
+        let mut cached = cached.borrow_mut();
+        if !matches!(&*cached, Some((path, _)) if path == archive) {
+            *cached = Some((archive.to_path_buf(), open(archive)?));
+        }
+        let Some((_, zip)) = cached.as_mut() else {
+            unreachable!("archive was just opened");
+        };
+
+        let entry = zip.by_name(name).map_err(zip_error)?;
+        let mut data = Vec::with_capacity(entry.size().min(limit).min(PREALLOCATE_LIMIT) as usize);
+        entry.take(limit).read_to_end(&mut data)?;
+        Ok(data)
+    })
+}
+
+fn open(archive: &Path) -> io::Result<Archive> {
+    let file = File::open(archive)?;
+    ZipArchive::new(BufReader::new(file)).map_err(zip_error)
+}
+
+/// Report zip failures (corrupt data, passwords, unsupported compression)
+/// as I/O errors, like any other unreadable file
+fn zip_error(err: ZipError) -> io::Error {
+    match err {
+        ZipError::Io(err) => err,
+        err => io::Error::new(io::ErrorKind::InvalidData, err.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn write_zip(path: &Path, entries: &[(&str, &[u8])]) {
+        let options = zip::write::SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Stored);
+        let mut writer = zip::ZipWriter::new(File::create(path).unwrap());
+        for (name, data) in entries {
+            writer.start_file(*name, options).unwrap();
+            writer.write_all(data).unwrap();
+        }
+        writer.finish().unwrap();
+    }
+
+    #[test]
+    fn test_is_archive() {
+        assert!(is_archive(Path::new("comic.cbz")));
+        assert!(is_archive(Path::new("album.ZIP")));
+        assert!(!is_archive(Path::new("photo.jpg")));
+    }
+
+    #[test]
+    fn test_list_and_read() {
+        let root = std::env::temp_dir().join(format!("fiv_archive_{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let path = root.join("comic.cbz");
+        write_zip(
+            &path,
+            &[
+                ("page_10.png", &b"ten"[..]),
+                ("page_2.png", &b"two"[..]),
+                ("notes.txt", &b"not an image"[..]),
+                ("__MACOSX/._page_2.png", &b"fork"[..]),
+            ],
+        );
+
+        let names = list_images(&path, &Decoder::new()).unwrap();
+        assert_eq!(names, ["page_2.png", "page_10.png"]);
+        assert_eq!(
+            read_entry(&path, "page_10.png", u64::MAX).unwrap(),
+            &b"ten"[..]
+        );
+        assert_eq!(read_entry(&path, "page_2.png", 2).unwrap(), b"tw");
+        assert!(read_entry(&path, "missing.png", u64::MAX).is_err());
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,157 @@
+//! Render backends - how the current image gets onto the screen.
+//!
+//! Both backends draw into the `pixels` frame buffer that the overlays
+//! (info panel, status bar, help) are then drawn on, and both present it.
+//! The CPU backend blits the image into that buffer with `render_image`;
+//! the GPU backend (feature `gpu`) leaves the buffer transparent for the
+//! overlays and draws the image as a texture underneath when presenting.
+//! Channel modes, tone adjustments, compare mode and the gallery are only
+//! done on the CPU, so the GPU backend hands such views to the CPU one.
+
+use crate::config::ScalingFilter;
+use crate::render::{render_grid, render_image, render_pair, GridLayout, RenderResult};
+use crate::slot::ImageData;
+use crate::state::ViewState;
+use pixels::Pixels;
+use std::sync::Arc;
+
+/// How the area around the image is filled
+pub struct Style<'a> {
+    /// Background color (RGBA)
+    pub background: [u8; 4],
+    /// Full-frame pixels to show instead of the background color
+    pub backdrop: Option<&'a [u8]>,
+    /// Resampling used to scale the image
+    pub filter: ScalingFilter,
+}
+
+/// A way of drawing the image
+pub trait Renderer {
+    /// Draw `image_data` as `view` describes into the frame, ready for
+    /// overlays
+    fn render(
+        &mut self,
+        pixels: &mut Pixels<'static>,
+        view: &ViewState,
+        image_data: Option<&Arc<ImageData>>,
+        style: &Style,
+    ) -> RenderResult;
+
+    /// Draw `pinned` and `image_data` side by side for compare mode, each
+    /// fit to its half of the frame. Returns the results for both, pinned
+    /// first.
+    fn render_pair(
+        &mut self,
+        pixels: &mut Pixels<'static>,
+        view: &ViewState,
+        pinned: Option<&Arc<ImageData>>,
+        image_data: Option<&Arc<ImageData>>,
+        style: &Style,
+    ) -> (RenderResult, RenderResult);
+
+    /// Draw the gallery grid laid out as `layout`, with `cells` the images
+    /// from its top-left cell on and the cell `cursor` outlined
+    fn render_grid(
+        &mut self,
+        pixels: &mut Pixels<'static>,
+        view: &ViewState,
+        layout: &GridLayout,
+        cells: &[Option<Arc<ImageData>>],
+        cursor: usize,
+        style: &Style,
+    );
+
+    /// Show the frame, overlays included
+    fn present(&mut self, pixels: &Pixels<'static>) -> Result<(), String>;
+}
+
+/// Blit on the CPU with `render_image`
+pub struct CpuRenderer;
+
+impl Renderer for CpuRenderer {
+    fn render(
+        &mut self,
+        pixels: &mut Pixels<'static>,
+        view: &ViewState,
+        image_data: Option<&Arc<ImageData>>,
+        style: &Style,
+    ) -> RenderResult {
+        render_image(
+            image_data,
+            pixels.frame_mut(),
+            view.window_width,
+            view.window_height,
+            style.background,
+            style.backdrop,
+            &view.transform,
+            style.filter,
+            view.frame_index,
+            &view.look(),
+        )
+    }
+
+    fn render_pair(
+        &mut self,
+        pixels: &mut Pixels<'static>,
+        view: &ViewState,
+        pinned: Option<&Arc<ImageData>>,
+        image_data: Option<&Arc<ImageData>>,
+        style: &Style,
+    ) -> (RenderResult, RenderResult) {
+        render_pair(
+            pinned,
+            image_data,
+            pixels.frame_mut(),
+            view.window_width,
+            view.window_height,
+            style.background,
+            &view.transform,
+            style.filter,
+            view.frame_index,
+            &view.look(),
+        )
+    }
+
+    fn render_grid(
+        &mut self,
+        pixels: &mut Pixels<'static>,
+        view: &ViewState,
+        layout: &GridLayout,
+        cells: &[Option<Arc<ImageData>>],
+        cursor: usize,
+        style: &Style,
+    ) {
+        render_grid(
+            cells,
+            pixels.frame_mut(),
+            view.window_width,
+            view.window_height,
+            style.background,
+            layout,
+            cursor,
+            style.filter,
+        );
+    }
+
+    fn present(&mut self, pixels: &Pixels<'static>) -> Result<(), String> {
+        pixels.render().map_err(|e| e.to_string())
+    }
+}
+
+/// The GPU renderer if `gpu` is set and fiv was built with it, else the
+/// CPU one
+pub fn create(pixels: &Pixels<'static>, gpu: bool) -> Box<dyn Renderer> {
+    if !gpu {
+        return Box::new(CpuRenderer);
+    }
+    #[cfg(feature = "gpu")]
+    {
+        Box::new(crate::gpu::GpuRenderer::new(pixels))
+    }
+    #[cfg(not(feature = "gpu"))]
+    {
+        let _ = pixels;
+        eprintln!("Warning: built without the gpu feature; using the CPU renderer");
+        Box::new(CpuRenderer)
+    }
+}
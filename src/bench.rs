@@ -0,0 +1,221 @@
+//! Bench mode - decode a directory's images and report how fast that went,
+//! without opening a window.
+//!
+//! `fiv bench DIR` runs every image through the same `Decoder` the viewer
+//! uses, on a pool set up like the preloader's, to compare decoder
+//! settings and hardware. Images are handed out in browsing order, so runs
+//! over the same files are comparable.
+
+use fiv::config::QualityTier;
+use fiv::decode::Decoder;
+use fiv::preload::decode_pool;
+use fiv::slot::ImageMeta;
+use rayon::prelude::*;
+use serde::Serialize;
+use std::time::{Duration, Instant};
+
+/// What decoding one image took
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timing {
+    pub took: Duration,
+    /// Bytes of decoded pixels, None if the decode failed
+    pub bytes: Option<usize>,
+}
+
+/// How a run went. Times per image are in milliseconds.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Report {
+    pub tier: &'static str,
+    pub threads: usize,
+    pub images: usize,
+    pub failures: usize,
+    pub wall_seconds: f64,
+    pub mean_ms: f64,
+    pub median_ms: f64,
+    pub p95_ms: f64,
+    pub images_per_second: f64,
+    /// Megabytes of decoded pixels per second of wall time
+    pub megabytes_per_second: f64,
+}
+
+/// Decode each image at `quality` on `threads` threads
+pub fn run(metas: &[ImageMeta], decoder: &Decoder, quality: QualityTier, threads: usize) -> Report {
+    let pool = decode_pool(threads);
+    let started = Instant::now();
+    let timings: Vec<Timing> = pool.install(|| {
+        metas
+            .par_iter()
+            .map(|meta| {
+                let start = Instant::now();
+                let bytes = match decoder.decode(meta, quality) {
+                    Ok(data) => Some(data.memory_size()),
+                    Err(err) => {
+                        eprintln!("Warning: {}: {}", meta.source, err);
+                        None
+                    }
+                };
+                Timing {
+                    took: start.elapsed(),
+                    bytes,
+                }
+            })
+            .collect()
+    });
+    summarize(
+        &timings,
+        started.elapsed(),
+        quality,
+        pool.current_num_threads(),
+    )
+}
+
+/// Statistics over the images' timings, failures included, and the
+/// throughput over `wall` time
+pub fn summarize(
+    timings: &[Timing],
+    wall: Duration,
+    quality: QualityTier,
+    threads: usize,
+) -> Report {
+    let mut millis: Vec<f64> = timings
+        .iter()
+        .map(|t| t.took.as_secs_f64() * 1000.0)
+        .collect();
+    millis.sort_by(f64::total_cmp);
+    let count = millis.len();
+    let mean = if count == 0 {
+        0.0
+    } else {
+        millis.iter().sum::<f64>() / count as f64
+    };
+    let median = match count {
+        0 => 0.0,
+        n if n % 2 == 0 => (millis[n / 2 - 1] + millis[n / 2]) / 2.0,
+        n => millis[n / 2],
+    };
+    // Nearest rank: the smallest time at least 95% of images beat or match
+    let p95 = match count {
+        0 => 0.0,
+        n => millis[(n * 95).div_ceil(100) - 1],
+    };
+    let bytes: usize = timings.iter().filter_map(|t| t.bytes).sum();
+    let seconds = wall.as_secs_f64();
+    let per_second = |amount: f64| if seconds > 0.0 { amount / seconds } else { 0.0 };
+    Report {
+        tier: quality.name(),
+        threads,
+        images: count,
+        failures: timings.iter().filter(|t| t.bytes.is_none()).count(),
+        wall_seconds: seconds,
+        mean_ms: mean,
+        median_ms: median,
+        p95_ms: p95,
+        images_per_second: per_second(count as f64),
+        megabytes_per_second: per_second(bytes as f64 / 1_000_000.0),
+    }
+}
+
+impl Report {
+    fn to_text(&self) -> String {
+        let failed = match self.failures {
+            0 => String::new(),
+            n => format!(" ({} failed)", n),
+        };
+        format!(
+            "Decoded {} images at {} quality on {} threads in {:.2} s{}\n\
+             Per image: mean {:.1} ms, median {:.1} ms, p95 {:.1} ms\n\
+             Throughput: {:.1} images/s, {:.1} MB/s",
+            self.images,
+            self.tier,
+            self.threads,
+            self.wall_seconds,
+            failed,
+            self.mean_ms,
+            self.median_ms,
+            self.p95_ms,
+            self.images_per_second,
+            self.megabytes_per_second,
+        )
+    }
+}
+
+/// Print a report to stdout as text or JSON
+pub fn print(report: &Report, json: bool) {
+    if json {
+        let text = serde_json::to_string_pretty(report).expect("reports are always serializable");
+        println!("{}", text);
+    } else {
+        println!("{}", report.to_text());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timing(ms: u64, bytes: Option<usize>) -> Timing {
+        Timing {
+            took: Duration::from_millis(ms),
+            bytes,
+        }
+    }
+
+    #[test]
+    fn test_summarize() {
+        let timings: Vec<Timing> = (1..=20)
+            .map(|ms| timing(ms, Some(1_000_000)))
+            .chain([timing(100, None)])
+            .collect();
+        let report = summarize(&timings, Duration::from_secs(2), QualityTier::Full, 4);
+        assert_eq!((report.images, report.failures), (21, 1));
+        assert_eq!(report.tier, "full");
+        assert_eq!(report.median_ms, 11.0);
+        assert_eq!(report.p95_ms, 20.0);
+        assert!((report.mean_ms - 310.0 / 21.0).abs() < 1e-9);
+        assert_eq!(report.images_per_second, 10.5);
+        // Failed decodes produce no pixels
+        assert_eq!(report.megabytes_per_second, 10.0);
+
+        let even = summarize(
+            &[timing(1, Some(0)), timing(3, Some(0))],
+            Duration::ZERO,
+            QualityTier::Preview,
+            1,
+        );
+        assert_eq!(even.median_ms, 2.0);
+        assert_eq!(even.images_per_second, 0.0);
+        assert!(even.to_text().contains("at preview quality"));
+        assert!(!even.to_text().contains("failed"));
+    }
+
+    #[test]
+    fn test_summarize_nothing() {
+        let report = summarize(&[], Duration::from_secs(1), QualityTier::Thumbnail, 2);
+        assert_eq!(report.images, 0);
+        assert_eq!(
+            (report.mean_ms, report.median_ms, report.p95_ms),
+            (0.0, 0.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn test_run() {
+        let root = std::env::temp_dir().join(format!("fiv_bench_{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let png = root.join("a.png");
+        image::RgbImage::from_pixel(5, 3, image::Rgb([0, 0, 0]))
+            .save_with_format(&png, image::ImageFormat::Png)
+            .unwrap();
+        let metas = vec![
+            ImageMeta::new(png),
+            ImageMeta::new(root.join("missing.png")),
+        ];
+
+        let report = run(&metas, &Decoder::new(), QualityTier::Full, 2);
+        assert_eq!((report.images, report.failures, report.threads), (2, 1, 2));
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["tier"], "full");
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,414 @@
+//! Thumbnail cache - thumbnails kept on disk between runs.
+//!
+//! Each entry is a PNG under `$XDG_CACHE_HOME/fiv/thumbnails/`, named by a
+//! hash of the image's canonical path and page. Text chunks record the
+//! source's path, size and modification time, and the thumbnail tier's
+//! size it was made at; an entry whose source has changed since, or made
+//! for another `preload.tiers`, is ignored and overwritten by the next
+//! decode. The thumbnail's perceptual hash is kept alongside once known.
+//!
+//! The directory is kept under a size cap by deleting the least recently
+//! used entries first. A hit bumps its entry's modification time.
+
+use crate::config::{QualityTier, TierSizes};
+use crate::slot::{ImageData, ImageMeta, ImageSource};
+use std::fs;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Thumbnails stored on disk, shared by all decode threads
+pub struct ThumbnailCache {
+    dir: PathBuf,
+    /// Longest side of the thumbnail tier, which entries must match
+    side: u32,
+    /// Size cap for the directory in bytes
+    max_bytes: u64,
+    /// Bytes on disk (approximate between prunes)
+    used: AtomicU64,
+    /// Held while pruning, so only one thread prunes at a time
+    pruning: Mutex<()>,
+    /// Numbers temporary files, so concurrent writes never share one
+    next_temp: AtomicUsize,
+}
+
+/// What a cache entry was made from (an archive entry's path is
+/// `archive:name`), and at what thumbnail size
+#[derive(Debug, PartialEq)]
+struct SourceKey {
+    path: String,
+    page: usize,
+    size: u64,
+    mtime: u128,
+    side: u32,
+}
+
+impl SourceKey {
+    /// Identify the file behind `meta` as it is on disk now, thumbnailed
+    /// to `side`
+    fn of(meta: &ImageMeta, side: u32) -> Option<Self> {
+        let path = fs::canonicalize(meta.path()).ok()?;
+        let stat = fs::metadata(&path).ok()?;
+        let mtime = stat.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+        let mut path = path.to_string_lossy().into_owned();
+        // An archive holds many images; each is its own entry
+        if let ImageSource::ArchiveEntry { name, .. } = &meta.source {
+            path = format!("{}:{}", path, name);
+        }
+        Some(Self {
+            path,
+            page: meta.page.map_or(0, |p| p.index),
+            size: stat.len(),
+            mtime: mtime.as_nanos(),
+            side,
+        })
+    }
+
+    /// Entry file name: FNV-1a hash of path and page
+    fn file_name(&self) -> String {
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        let page = self.page.to_le_bytes();
+        for &byte in self.path.as_bytes().iter().chain(&[0]).chain(&page) {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+        format!("{:016x}.png", hash)
+    }
+}
+
+impl ThumbnailCache {
+    /// Default cache location: `$XDG_CACHE_HOME/fiv/thumbnails`, falling
+    /// back to `~/.cache/fiv/thumbnails`
+    pub fn default_dir() -> Option<PathBuf> {
+        let base = std::env::var_os("XDG_CACHE_HOME")
+            .filter(|v| !v.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))?;
+        Some(base.join("fiv").join("thumbnails"))
+    }
+
+    /// Open (creating if needed) a cache directory for thumbnails of the
+    /// size in `sizes`, pruning it to `max_bytes`
+    pub fn open(dir: PathBuf, max_bytes: u64, sizes: TierSizes) -> Result<Self, String> {
+        fs::create_dir_all(&dir)
+            .map_err(|e| format!("cannot create cache directory {}: {}", dir.display(), e))?;
+        let cache = Self {
+            dir,
+            side: QualityTier::Thumbnail.max_dimension(sizes).unwrap_or(0),
+            max_bytes,
+            used: AtomicU64::new(0),
+            pruning: Mutex::new(()),
+            next_temp: AtomicUsize::new(0),
+        };
+        cache.prune();
+        Ok(cache)
+    }
+
+    /// The cached thumbnail for `meta`, if there is one and its source
+    /// hasn't changed since it was stored. A stored hash is set on `meta`.
+    pub fn load(&self, meta: &ImageMeta) -> Option<ImageData> {
+        let key = SourceKey::of(meta, self.side)?;
+        let path = self.dir.join(key.file_name());
+        let (stored, data, hash) = read_entry(&path)?;
+        if stored != key {
+            return None;
+        }
+        if let Some(hash) = hash {
+            meta.set_phash(hash);
+        }
+
+        // Count as recently used for pruning
+        if let Ok(file) = fs::File::options().write(true).open(&path) {
+            let _ = file.set_modified(SystemTime::now());
+        }
+        Some(data)
+    }
+
+    /// Whether there is an entry for `meta` made from its source as it is
+    /// now. Only the entry's header is read.
+    pub fn contains(&self, meta: &ImageMeta) -> bool {
+        let Some(key) = SourceKey::of(meta, self.side) else {
+            return false;
+        };
+        let path = self.dir.join(key.file_name());
+        read_key(&path).is_some_and(|stored| stored == key)
+    }
+
+    /// Save a decoded thumbnail for `meta`, with its hash if known. Other
+    /// tiers and animations are not cached; failures are ignored, the cache
+    /// being best effort.
+    pub fn store(&self, meta: &ImageMeta, data: &ImageData) {
+        if data.quality != QualityTier::Thumbnail || !data.extra_frames.is_empty() {
+            return;
+        }
+        let Some(key) = SourceKey::of(meta, self.side) else {
+            return;
+        };
+
+        // Write aside and rename, so readers never see half an entry
+        let path = self.dir.join(key.file_name());
+        let temp = self.dir.join(format!(
+            "{}.{}.tmp",
+            std::process::id(),
+            self.next_temp.fetch_add(1, Ordering::Relaxed)
+        ));
+        if write_entry(&temp, &key, data, meta.phash()).is_none()
+            || fs::rename(&temp, &path).is_err()
+        {
+            let _ = fs::remove_file(&temp);
+            return;
+        }
+
+        let size = fs::metadata(&path).map_or(0, |m| m.len());
+        if self.used.fetch_add(size, Ordering::Relaxed) + size > self.max_bytes {
+            self.prune();
+        }
+    }
+
+    /// Delete least recently used entries until the cache is a tenth under
+    /// its cap, leaving room for new entries before the next prune
+    fn prune(&self) {
+        let Ok(_guard) = self.pruning.try_lock() else {
+            return;
+        };
+        let Ok(dir) = fs::read_dir(&self.dir) else {
+            return;
+        };
+
+        let mut entries: Vec<(SystemTime, u64, PathBuf)> = Vec::new();
+        for entry in dir.flatten() {
+            let path = entry.path();
+            let Ok(stat) = entry.metadata() else {
+                continue;
+            };
+            match path.extension().and_then(|e| e.to_str()) {
+                Some("png") => {
+                    let mtime = stat.modified().unwrap_or(UNIX_EPOCH);
+                    entries.push((mtime, stat.len(), path));
+                }
+                // Left behind by a run that died mid-write
+                Some("tmp") if is_stale(&stat) => {
+                    let _ = fs::remove_file(&path);
+                }
+                _ => {}
+            }
+        }
+
+        let mut used: u64 = entries.iter().map(|e| e.1).sum();
+        if used > self.max_bytes {
+            let target = self.max_bytes / 10 * 9;
+            entries.sort_by_key(|e| e.0);
+            for (_, size, path) in &entries {
+                if used <= target {
+                    break;
+                }
+                if fs::remove_file(path).is_ok() {
+                    used -= size;
+                }
+            }
+        }
+        self.used.store(used, Ordering::Relaxed);
+    }
+}
+
+/// Temporary files older than a minute are not being written anymore
+fn is_stale(stat: &fs::Metadata) -> bool {
+    stat.modified()
+        .ok()
+        .and_then(|t| t.elapsed().ok())
+        .is_some_and(|age| age.as_secs() > 60)
+}
+
+/// Encode a thumbnail, its source key and hash as PNG
+fn write_entry(path: &Path, key: &SourceKey, data: &ImageData, hash: Option<u64>) -> Option<()> {
+    let file = fs::File::create(path).ok()?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), data.width, data.height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let fields = [
+        ("fiv:path", key.path.clone()),
+        ("fiv:page", key.page.to_string()),
+        ("fiv:size", key.size.to_string()),
+        ("fiv:mtime", key.mtime.to_string()),
+        ("fiv:side", key.side.to_string()),
+        (
+            "fiv:full",
+            format!("{}x{}", data.full_width, data.full_height),
+        ),
+        ("fiv:preview", data.embedded_preview.to_string()),
+    ];
+    let hash = hash.map(|hash| ("fiv:dhash", format!("{:016x}", hash)));
+    for (keyword, text) in fields.into_iter().chain(hash) {
+        encoder.add_itxt_chunk(keyword.into(), text).ok()?;
+    }
+
+    let mut writer = encoder.write_header().ok()?;
+    writer.write_image_data(&data.pixels).ok()?;
+    writer.finish().ok()
+}
+
+/// The text chunks of an entry naming its source
+fn source_key(info: &png::Info) -> Option<SourceKey> {
+    Some(SourceKey {
+        path: entry_text(info, "fiv:path")?,
+        page: entry_text(info, "fiv:page")?.parse().ok()?,
+        size: entry_text(info, "fiv:size")?.parse().ok()?,
+        mtime: entry_text(info, "fiv:mtime")?.parse().ok()?,
+        side: entry_text(info, "fiv:side")?.parse().ok()?,
+    })
+}
+
+fn entry_text(info: &png::Info, keyword: &str) -> Option<String> {
+    info.utf8_text
+        .iter()
+        .find(|chunk| chunk.keyword == keyword)?
+        .get_text()
+        .ok()
+}
+
+/// Read just the source key of a cache entry
+fn read_key(path: &Path) -> Option<SourceKey> {
+    let file = fs::File::open(path).ok()?;
+    let reader = png::Decoder::new(BufReader::new(file)).read_info().ok()?;
+    source_key(reader.info())
+}
+
+/// Decode a cache entry into its source key, thumbnail and hash
+fn read_entry(path: &Path) -> Option<(SourceKey, ImageData, Option<u64>)> {
+    let file = fs::File::open(path).ok()?;
+    let mut reader = png::Decoder::new(BufReader::new(file)).read_info().ok()?;
+    if reader.output_color_type() != (png::ColorType::Rgba, png::BitDepth::Eight) {
+        return None;
+    }
+
+    let info = reader.info();
+    let key = source_key(info)?;
+    let full = entry_text(info, "fiv:full")?;
+    let (full_width, full_height) = full.split_once('x')?;
+    let (full_width, full_height) = (full_width.parse().ok()?, full_height.parse().ok()?);
+    let embedded_preview = entry_text(info, "fiv:preview")? == "true";
+    // Entries written before hashing have none
+    let hash = entry_text(info, "fiv:dhash").and_then(|hex| u64::from_str_radix(&hex, 16).ok());
+
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    let frame = reader.next_frame(&mut pixels).ok()?;
+    pixels.truncate(frame.buffer_size());
+
+    let mut data = ImageData::new(pixels, frame.width, frame.height, QualityTier::Thumbnail);
+    data.full_width = full_width;
+    data.full_height = full_height;
+    data.embedded_preview = embedded_preview;
+    Some((key, data, hash))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn thumbnail(width: u32, height: u32) -> ImageData {
+        let pixels = (0..width * height * 4).map(|i| i as u8).collect();
+        let mut data = ImageData::new(pixels, width, height, QualityTier::Thumbnail);
+        data.full_width = width * 10;
+        data.full_height = height * 10;
+        data
+    }
+
+    #[test]
+    fn test_round_trip_and_staleness() {
+        let root = std::env::temp_dir().join(format!("fiv_thumb_cache_{}", std::process::id()));
+        let source = root.join("photo.png");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(&source, b"original").unwrap();
+        let cache =
+            ThumbnailCache::open(root.join("cache"), 1 << 20, TierSizes::default()).unwrap();
+        let meta = ImageMeta::new(source.clone());
+
+        assert!(cache.load(&meta).is_none());
+        assert!(!cache.contains(&meta));
+        cache.store(&meta, &thumbnail(8, 6));
+        assert!(cache.contains(&meta));
+        let hit = cache.load(&meta).unwrap();
+        assert_eq!((hit.width, hit.height), (8, 6));
+        assert_eq!((hit.full_width, hit.full_height), (80, 60));
+        assert_eq!(hit.pixels, thumbnail(8, 6).pixels);
+        assert_eq!(meta.phash(), None);
+
+        // The hash is kept with the thumbnail
+        meta.set_phash(0xdead_beef);
+        cache.store(&meta, &thumbnail(8, 6));
+        let fresh = ImageMeta::new(source.clone());
+        assert!(cache.load(&fresh).is_some());
+        assert_eq!(fresh.phash(), Some(0xdead_beef));
+
+        // Pages of one file are separate entries
+        let page = ImageMeta::new(source.clone()).with_page(1, 2);
+        assert!(cache.load(&page).is_none());
+
+        // A cache for another thumbnail size doesn't use the entry
+        let resized =
+            ThumbnailCache::open(root.join("cache"), 1 << 20, TierSizes::new([320, 1024, 0]))
+                .unwrap();
+        assert!(!resized.contains(&meta));
+        assert!(resized.load(&meta).is_none());
+
+        // Changing the file invalidates the entry
+        fs::write(&source, b"rewritten, longer").unwrap();
+        assert!(!cache.contains(&meta));
+        assert!(cache.load(&meta).is_none());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_prune_keeps_recent_entries() {
+        let root = std::env::temp_dir().join(format!("fiv_thumb_prune_{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        let metas: Vec<ImageMeta> = (0..4)
+            .map(|i| {
+                let path = root.join(format!("{}.png", i));
+                fs::write(&path, [i as u8]).unwrap();
+                ImageMeta::new(path)
+            })
+            .collect();
+
+        // Room for about two entries
+        let probe =
+            ThumbnailCache::open(root.join("probe"), u64::MAX, TierSizes::default()).unwrap();
+        probe.store(&metas[0], &thumbnail(32, 32));
+        let entry_size = probe.used.load(Ordering::Relaxed);
+        let cache =
+            ThumbnailCache::open(root.join("cache"), entry_size * 5 / 2, TierSizes::default())
+                .unwrap();
+
+        for meta in &metas {
+            cache.store(meta, &thumbnail(32, 32));
+            // Distinct modification times
+            std::thread::sleep(std::time::Duration::from_millis(20));
+        }
+        assert!(cache.used.load(Ordering::Relaxed) <= entry_size * 5 / 2);
+        assert!(cache.load(&metas[0]).is_none());
+        assert!(cache.load(&metas[3]).is_some());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_skips_other_tiers() {
+        let root = std::env::temp_dir().join(format!("fiv_thumb_tiers_{}", std::process::id()));
+        let source = root.join("photo.png");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(&source, b"x").unwrap();
+        let cache =
+            ThumbnailCache::open(root.join("cache"), 1 << 20, TierSizes::default()).unwrap();
+        let meta = ImageMeta::new(source);
+
+        let mut full = thumbnail(4, 4);
+        full.quality = QualityTier::Full;
+        cache.store(&meta, &full);
+        assert!(cache.load(&meta).is_none());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,482 @@
+//! Color management - converting tagged images to sRGB.
+//!
+//! Handles the matrix/TRC RGB profiles that cover nearly all photos
+//! (Display P3, Adobe RGB, ProPhoto, sRGB itself): each channel goes
+//! through its tone curve to linear light, the primaries matrix takes it to
+//! the XYZ connection space, and the sRGB matrix and curve bring it back.
+//! Anything else (LUT-based, CMYK, gray profiles) is left untouched.
+
+use rayon::prelude::*;
+
+/// XYZ (D50, the ICC connection space) to linear sRGB, Bradford-adapted
+const XYZ_TO_SRGB: [[f32; 3]; 3] = [
+    [3.133_856, -1.616_867, -0.490_615],
+    [-0.978_768, 1.916_142, 0.033_454],
+    [0.071_945, -0.228_991, 1.405_243],
+];
+
+/// Entries in the linear-to-sRGB lookup table
+const ENCODE_STEPS: usize = 4096;
+
+/// The ICC profile embedded in an image file, if any
+pub fn embedded_profile(data: &[u8]) -> Option<Vec<u8>> {
+    use image::ImageDecoder;
+
+    let mut decoder = image::ImageReader::new(std::io::Cursor::new(data))
+        .with_guessed_format()
+        .ok()?
+        .into_decoder()
+        .ok()?;
+    decoder.icc_profile().ok().flatten()
+}
+
+/// A conversion from a source profile to sRGB
+pub struct Transform {
+    /// Profile description, e.g. "Display P3"
+    pub name: String,
+    /// Source value to linear light, per channel
+    to_linear: [[f32; 256]; 3],
+    /// Linear source RGB to linear sRGB
+    matrix: [[f32; 3]; 3],
+    /// Linear sRGB (0..1 in `ENCODE_STEPS`) to an 8-bit value
+    encode: Vec<u8>,
+}
+
+impl Transform {
+    /// Build the conversion for an ICC profile. None if the profile isn't a
+    /// matrix/TRC RGB profile, or is sRGB already so there's nothing to do.
+    pub fn from_icc(icc: &[u8]) -> Option<Self> {
+        let profile = Profile::parse(icc)?;
+        let matrix = multiply(&XYZ_TO_SRGB, &profile.primaries);
+        let to_linear = profile.curves.map(|curve| {
+            let mut table = [0.0f32; 256];
+            for (i, value) in table.iter_mut().enumerate() {
+                *value = curve.eval(i as f32 / 255.0);
+            }
+            table
+        });
+
+        let transform = Self {
+            name: profile.name,
+            to_linear,
+            matrix,
+            encode: (0..ENCODE_STEPS)
+                .map(|i| {
+                    let linear = i as f32 / (ENCODE_STEPS - 1) as f32;
+                    (srgb_encode(linear) * 255.0).round() as u8
+                })
+                .collect(),
+        };
+        (!transform.is_srgb()).then_some(transform)
+    }
+
+    /// Whether this maps every value to itself, give or take rounding
+    fn is_srgb(&self) -> bool {
+        let identity = (0..3).all(|i| {
+            (0..3).all(|j| {
+                let expected = if i == j { 1.0 } else { 0.0 };
+                (self.matrix[i][j] - expected).abs() < 0.01
+            })
+        });
+        identity
+            && self.to_linear.iter().all(|table| {
+                table
+                    .iter()
+                    .enumerate()
+                    .all(|(i, &v)| (v - srgb_decode(i as f32 / 255.0)).abs() < 0.002)
+            })
+    }
+
+    /// Convert RGBA pixels in place; alpha is kept
+    pub fn apply(&self, rgba: &mut [u8]) {
+        rgba.par_chunks_mut(4 * 4096).for_each(|chunk| {
+            for pixel in chunk.chunks_exact_mut(4) {
+                let linear = [
+                    self.to_linear[0][pixel[0] as usize],
+                    self.to_linear[1][pixel[1] as usize],
+                    self.to_linear[2][pixel[2] as usize],
+                ];
+                for (channel, row) in pixel.iter_mut().zip(&self.matrix) {
+                    let value = row[0] * linear[0] + row[1] * linear[1] + row[2] * linear[2];
+                    let step = (value.clamp(0.0, 1.0) * (ENCODE_STEPS - 1) as f32).round();
+                    *channel = self.encode[step as usize];
+                }
+            }
+        });
+    }
+}
+
+/// A tone curve from an ICC `curv` or `para` tag
+#[derive(Debug, Clone, PartialEq)]
+enum Curve {
+    /// Linear, or a pure power law
+    Gamma(f32),
+    /// Samples spread evenly over 0..1
+    Table(Vec<f32>),
+    /// ICC parametric function type 4: (a*x + b)^g + e above d, c*x + f
+    /// below; the other types are special cases
+    Parametric {
+        g: f32,
+        a: f32,
+        b: f32,
+        c: f32,
+        d: f32,
+        e: f32,
+        f: f32,
+    },
+}
+
+impl Curve {
+    fn eval(&self, x: f32) -> f32 {
+        match self {
+            Curve::Gamma(g) => x.powf(*g),
+            Curve::Table(samples) => {
+                let pos = x * (samples.len() - 1) as f32;
+                let i = (pos.floor() as usize).min(samples.len() - 2);
+                let frac = pos - i as f32;
+                samples[i] * (1.0 - frac) + samples[i + 1] * frac
+            }
+            &Curve::Parametric {
+                g,
+                a,
+                b,
+                c,
+                d,
+                e,
+                f,
+            } => {
+                if x >= d {
+                    (a * x + b).max(0.0).powf(g) + e
+                } else {
+                    c * x + f
+                }
+            }
+        }
+    }
+}
+
+/// The parts of a matrix/TRC profile the conversion needs
+struct Profile {
+    name: String,
+    /// Columns are the red, green and blue primaries in XYZ
+    primaries: [[f32; 3]; 3],
+    curves: [Curve; 3],
+}
+
+impl Profile {
+    fn parse(icc: &[u8]) -> Option<Self> {
+        if icc.len() < 132 || &icc[16..20] != b"RGB " || &icc[20..24] != b"XYZ " {
+            return None;
+        }
+        let tag = |signature: &[u8; 4]| find_tag(icc, signature);
+
+        let mut primaries = [[0.0; 3]; 3];
+        for (column, signature) in [b"rXYZ", b"gXYZ", b"bXYZ"].into_iter().enumerate() {
+            let xyz = parse_xyz(tag(signature)?)?;
+            for (row, value) in primaries.iter_mut().zip(xyz) {
+                row[column] = value;
+            }
+        }
+        let curves = [
+            parse_curve(tag(b"rTRC")?)?,
+            parse_curve(tag(b"gTRC")?)?,
+            parse_curve(tag(b"bTRC")?)?,
+        ];
+        let name = tag(b"desc")
+            .and_then(parse_description)
+            .unwrap_or_else(|| "Unnamed profile".to_string());
+        Some(Self {
+            name,
+            primaries,
+            curves,
+        })
+    }
+}
+
+/// A tag's data, found through the tag table
+fn find_tag<'a>(icc: &'a [u8], signature: &[u8; 4]) -> Option<&'a [u8]> {
+    let count = read_u32(icc, 128)? as usize;
+    (0..count.min(1024)).find_map(|i| {
+        let entry = 132 + i * 12;
+        if icc.get(entry..entry + 4)? != signature {
+            return None;
+        }
+        let offset = read_u32(icc, entry + 4)? as usize;
+        let size = read_u32(icc, entry + 8)? as usize;
+        icc.get(offset..offset.checked_add(size)?)
+    })
+}
+
+fn read_u16(data: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(data.get(at..at + 2)?.try_into().ok()?))
+}
+
+fn read_u32(data: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(at..at + 4)?.try_into().ok()?))
+}
+
+/// An ICC s15Fixed16Number
+fn read_fixed(data: &[u8], at: usize) -> Option<f32> {
+    Some(read_u32(data, at)? as i32 as f32 / 65536.0)
+}
+
+/// An `XYZ ` tag's first value
+fn parse_xyz(tag: &[u8]) -> Option<[f32; 3]> {
+    if tag.get(0..4)? != b"XYZ " {
+        return None;
+    }
+    Some([
+        read_fixed(tag, 8)?,
+        read_fixed(tag, 12)?,
+        read_fixed(tag, 16)?,
+    ])
+}
+
+/// A `curv` or `para` tag
+fn parse_curve(tag: &[u8]) -> Option<Curve> {
+    match tag.get(0..4)? {
+        b"curv" => match read_u32(tag, 8)? {
+            0 => Some(Curve::Gamma(1.0)),
+            1 => Some(Curve::Gamma(read_u16(tag, 12)? as f32 / 256.0)),
+            count => (0..count as usize)
+                .map(|i| Some(read_u16(tag, 12 + i * 2)? as f32 / 65535.0))
+                .collect::<Option<Vec<_>>>()
+                .map(Curve::Table),
+        },
+        b"para" => {
+            let kind = read_u16(tag, 8)?;
+            let count = [1, 3, 4, 5, 7].get(kind as usize)?;
+            let p = (0..*count)
+                .map(|i| read_fixed(tag, 12 + i * 4))
+                .collect::<Option<Vec<_>>>()?;
+            let g = p[0];
+            Some(match kind {
+                0 => Curve::Gamma(g),
+                // Zero below -b/a; with c, offset by c everywhere
+                1 | 2 => {
+                    let (a, b) = (p[1], p[2]);
+                    let c = p.get(3).copied().unwrap_or(0.0);
+                    Curve::Parametric {
+                        g,
+                        a,
+                        b,
+                        c: 0.0,
+                        d: if a != 0.0 { -b / a } else { 0.0 },
+                        e: c,
+                        f: c,
+                    }
+                }
+                3 => Curve::Parametric {
+                    g,
+                    a: p[1],
+                    b: p[2],
+                    c: p[3],
+                    d: p[4],
+                    e: 0.0,
+                    f: 0.0,
+                },
+                _ => Curve::Parametric {
+                    g,
+                    a: p[1],
+                    b: p[2],
+                    c: p[3],
+                    d: p[4],
+                    e: p[5],
+                    f: p[6],
+                },
+            })
+        }
+        _ => None,
+    }
+}
+
+/// A `desc` (ICC v2) or `mluc` (v4) tag's text
+fn parse_description(tag: &[u8]) -> Option<String> {
+    let text = match tag.get(0..4)? {
+        b"desc" => {
+            let len = read_u32(tag, 8)? as usize;
+            let ascii = tag.get(12..12 + len)?;
+            String::from_utf8_lossy(ascii).into_owned()
+        }
+        b"mluc" => {
+            if read_u32(tag, 8)? == 0 {
+                return None;
+            }
+            let len = read_u32(tag, 20)? as usize;
+            let offset = read_u32(tag, 24)? as usize;
+            let utf16: Vec<u16> = tag
+                .get(offset..offset + len)?
+                .chunks_exact(2)
+                .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+                .collect();
+            String::from_utf16_lossy(&utf16)
+        }
+        _ => return None,
+    };
+    let text = text.trim_end_matches('\0').trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+fn multiply(a: &[[f32; 3]; 3], b: &[[f32; 3]; 3]) -> [[f32; 3]; 3] {
+    let mut out = [[0.0; 3]; 3];
+    for (i, row) in out.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = (0..3).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    out
+}
+
+fn srgb_decode(x: f32) -> f32 {
+    if x <= 0.04045 {
+        x / 12.92
+    } else {
+        ((x + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn srgb_encode(x: f32) -> f32 {
+    if x <= 0.003_130_8 {
+        x * 12.92
+    } else {
+        1.055 * x.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::QualityTier;
+    use crate::decode::Decoder;
+    use crate::slot::ImageMeta;
+    use image::ImageEncoder;
+
+    /// sRGB's tone curve as an ICC parametric (type 3) curve
+    const SRGB_CURVE: [f32; 5] = [2.4, 1.0 / 1.055, 0.055 / 1.055, 1.0 / 12.92, 0.04045];
+
+    /// A minimal matrix/TRC ICC profile with the given primaries (XYZ, D50)
+    /// and the sRGB tone curve
+    fn matrix_profile(name: &str, primaries: [[f32; 3]; 3]) -> Vec<u8> {
+        let fixed = |v: f32| ((v * 65536.0).round() as i32).to_be_bytes();
+        let mut tags: Vec<([u8; 4], Vec<u8>)> = Vec::new();
+        for (signature, xyz) in [*b"rXYZ", *b"gXYZ", *b"bXYZ"].into_iter().zip(primaries) {
+            let mut data = b"XYZ \0\0\0\0".to_vec();
+            for v in xyz {
+                data.extend_from_slice(&fixed(v));
+            }
+            tags.push((signature, data));
+        }
+        let mut curve = b"para\0\0\0\0".to_vec();
+        curve.extend_from_slice(&3u16.to_be_bytes());
+        curve.extend_from_slice(&[0, 0]);
+        for v in SRGB_CURVE {
+            curve.extend_from_slice(&fixed(v));
+        }
+        for signature in [*b"rTRC", *b"gTRC", *b"bTRC"] {
+            tags.push((signature, curve.clone()));
+        }
+        let mut desc = b"desc\0\0\0\0".to_vec();
+        desc.extend_from_slice(&(name.len() as u32 + 1).to_be_bytes());
+        desc.extend_from_slice(name.as_bytes());
+        desc.push(0);
+        tags.push((*b"desc", desc));
+
+        let mut icc = vec![0u8; 128];
+        icc[16..20].copy_from_slice(b"RGB ");
+        icc[20..24].copy_from_slice(b"XYZ ");
+        icc.extend_from_slice(&(tags.len() as u32).to_be_bytes());
+        let mut offset = 132 + tags.len() * 12;
+        for (signature, data) in &tags {
+            icc.extend_from_slice(signature);
+            icc.extend_from_slice(&(offset as u32).to_be_bytes());
+            icc.extend_from_slice(&(data.len() as u32).to_be_bytes());
+            offset += data.len().next_multiple_of(4);
+        }
+        for (_, data) in &tags {
+            icc.extend_from_slice(data);
+            icc.resize(icc.len().next_multiple_of(4), 0);
+        }
+        let len = icc.len() as u32;
+        icc[0..4].copy_from_slice(&len.to_be_bytes());
+        icc
+    }
+
+    /// Display P3's primaries adapted to D50, as in Apple's profile
+    const DISPLAY_P3: [[f32; 3]; 3] = [
+        [0.515_121, 0.241_196, -0.001_053],
+        [0.291_977, 0.692_245, 0.041_885],
+        [0.157_104, 0.066_574, 0.784_073],
+    ];
+
+    /// sRGB's primaries adapted to D50
+    const SRGB: [[f32; 3]; 3] = [
+        [0.436_066, 0.222_488, 0.013_916],
+        [0.385_147, 0.716_873, 0.097_076],
+        [0.143_066, 0.060_608, 0.714_096],
+    ];
+
+    #[test]
+    fn test_display_p3_to_srgb() {
+        let transform = Transform::from_icc(&matrix_profile("Display P3", DISPLAY_P3)).unwrap();
+        assert_eq!(transform.name, "Display P3");
+
+        // Fully saturated P3 red is outside sRGB and clips to sRGB red;
+        // a darker red lands inside, more saturated than the raw values
+        let mut pixels = [255, 0, 0, 255, 200, 60, 60, 128];
+        transform.apply(&mut pixels);
+        assert_eq!(&pixels[..4], &[255, 0, 0, 255]);
+        let expected = [217, 42, 52];
+        for (got, want) in pixels[4..7].iter().zip(expected) {
+            assert!(got.abs_diff(want) <= 1, "{:?}", &pixels[4..7]);
+        }
+        assert_eq!(pixels[7], 128);
+    }
+
+    #[test]
+    fn test_decode_tagged_png() {
+        let root = std::env::temp_dir().join(format!("fiv_color_{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let path = root.join("p3.png");
+        let mut png = Vec::new();
+        let mut encoder = image::codecs::png::PngEncoder::new(&mut png);
+        encoder
+            .set_icc_profile(matrix_profile("Display P3", DISPLAY_P3))
+            .unwrap();
+        encoder
+            .write_image(&[200, 60, 60], 1, 1, image::ExtendedColorType::Rgb8)
+            .unwrap();
+        std::fs::write(&path, &png).unwrap();
+        let meta = ImageMeta::new(path);
+
+        let data = Decoder::new().decode(&meta, QualityTier::Full).unwrap();
+        assert!(data.pixels[0].abs_diff(217) <= 1, "{:?}", &data.pixels[..3]);
+        assert_eq!(
+            meta.color_profile().as_deref().map(String::as_str),
+            Some("Display P3")
+        );
+
+        // Turned off, the raw values come through
+        let data = Decoder::new()
+            .with_color_management(false)
+            .decode(&meta, QualityTier::Full)
+            .unwrap();
+        assert_eq!(&data.pixels[..3], &[200, 60, 60]);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_srgb_profile_is_skipped() {
+        assert!(Transform::from_icc(&matrix_profile("sRGB", SRGB)).is_none());
+        assert!(Transform::from_icc(b"not a profile").is_none());
+    }
+
+    #[test]
+    fn test_curves() {
+        let gamma = parse_curve(b"curv\0\0\0\0\0\0\0\x01\x02\x33").unwrap();
+        assert_eq!(gamma, Curve::Gamma(2.0 + 51.0 / 256.0));
+        let table = parse_curve(b"curv\0\0\0\0\0\0\0\x03\0\0\x40\0\xff\xff").unwrap();
+        assert!((table.eval(0.25) - 0.125).abs() < 0.001);
+        assert_eq!(table.eval(1.0), 1.0);
+    }
+}
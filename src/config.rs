@@ -3,13 +3,25 @@
 //! This module provides a data-driven configuration system where all magic numbers
 //! and behavioral parameters are centralized. This makes tuning easy and prevents
 //! scattered constants throughout the codebase.
+//!
+//! Values can be overridden from a TOML file (see [`Config::load`]). Every
+//! section and field is optional; anything missing keeps its default.
+//! Unknown keys are an error, so a misspelt one can't silently do nothing.
 
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 use sysinfo::System;
 
+use crate::title::DEFAULT_TITLE_FORMAT;
+
 /// Master configuration for the viewer.
 /// All behavioral parameters are here - no magic numbers elsewhere.
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct Config {
     /// Memory management
     pub memory: MemoryConfig,
@@ -19,10 +31,149 @@ pub struct Config {
     pub preload: PreloadConfig,
     /// Rendering
     pub render: RenderConfig,
+    /// Zoom and view behaviour
+    pub view: ViewConfig,
+    /// Deleting and sorting files
+    pub files: FilesConfig,
+    /// Exporting resized copies
+    pub export: ExportConfig,
+    /// Other programs images are handed to
+    pub external: ExternalConfig,
+    /// Finding near-duplicate images
+    pub duplicates: DuplicatesConfig,
+    /// The thumbnail grid
+    pub gallery: GalleryConfig,
+    /// Commands run as images are shown
+    pub hooks: HooksConfig,
+    /// Extra key bindings: winit key name -> action name
+    pub keys: BTreeMap<String, String>,
+}
+
+impl Config {
+    /// Default config file location: `$XDG_CONFIG_HOME/fiv/config.toml`,
+    /// falling back to `~/.config/fiv/config.toml`
+    pub fn default_path() -> Option<PathBuf> {
+        let base = std::env::var_os("XDG_CONFIG_HOME")
+            .filter(|v| !v.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+        Some(base.join("fiv").join("config.toml"))
+    }
+
+    /// Load configuration from `path`, or from the default location if None.
+    ///
+    /// A missing file at the default location is not an error (defaults are
+    /// used); an explicitly given path must exist.
+    pub fn load(path: Option<&Path>) -> Result<Self, String> {
+        let (path, required) = match path {
+            Some(p) => (p.to_path_buf(), true),
+            None => match Self::default_path() {
+                Some(p) => (p, false),
+                None => return Ok(Self::default()),
+            },
+        };
+
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if !required && e.kind() == ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(format!("Cannot read config '{}': {}", path.display(), e)),
+        };
+
+        Self::from_toml(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// Parse configuration from TOML text, keeping defaults for missing fields
+    pub fn from_toml(text: &str) -> Result<Self, String> {
+        let deserializer = toml::Deserializer::new(text);
+        let config: Self = serde_path_to_error::deserialize(deserializer).map_err(|e| {
+            let path = e.path().to_string();
+            let message = e.inner().message().trim().to_string();
+            if path == "." {
+                message
+            } else if message.starts_with("unknown field") {
+                format!("in `{}`: {}", path, message)
+            } else {
+                format!("invalid value for `{}`: {}", path, message)
+            }
+        })?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Render the effective configuration as TOML
+    pub fn to_toml(&self) -> String {
+        toml::to_string_pretty(self).expect("Config is always serializable")
+    }
+
+    /// Reject values that parse but make no sense
+    fn validate(&self) -> Result<(), String> {
+        if !(0.0..=1.0).contains(&self.memory.budget_ratio) {
+            return Err(
+                "invalid value for `memory.budget_ratio`: must be between 0.0 and 1.0".into(),
+            );
+        }
+        if self.memory.min_budget > self.memory.max_budget {
+            return Err(
+                "invalid value for `memory.min_budget`: larger than `memory.max_budget`".into(),
+            );
+        }
+        if self.memory.max_decode_pixels == Some(0) {
+            return Err("invalid value for `memory.max_decode_pixels`: must be non-zero".into());
+        }
+        if !(0.0..=1.0).contains(&self.preload.eviction_recency_weight) {
+            return Err(
+                "invalid value for `preload.eviction_recency_weight`: must be between 0.0 and 1.0"
+                    .into(),
+            );
+        }
+        let [thumbnail, preview, full] = match self.preload.tiers[..] {
+            [thumbnail, preview, full] => [thumbnail, preview, full],
+            _ => return Err("invalid value for `preload.tiers`: must list exactly three sizes, for the thumbnail, preview and full tiers".into()),
+        };
+        if thumbnail < 16 || preview <= thumbnail || (full != 0 && full <= preview) {
+            return Err("invalid value for `preload.tiers`: sizes must increase from at least 16, with 0 for an unlimited full tier".into());
+        }
+        if self.preload.read_threads == 0 {
+            return Err("invalid value for `preload.read_threads`: must be at least 1".into());
+        }
+        if self.view.zoom_step <= 1.0 {
+            return Err("invalid value for `view.zoom_step`: must be greater than 1.0".into());
+        }
+        if !(self.view.min_zoom > 0.0 && self.view.min_zoom <= 1.0 && self.view.max_zoom >= 1.0) {
+            return Err(
+                "invalid value for `view.min_zoom`/`view.max_zoom`: range must include 1.0".into(),
+            );
+        }
+        if self.view.slideshow_interval.is_zero() {
+            return Err("invalid value for `view.slideshow_interval`: must be non-zero".into());
+        }
+        if self.input.jump_step == 0 {
+            return Err("invalid value for `input.jump_step`: must be at least 1".into());
+        }
+        if self.files.sort_into.len() > 9 {
+            return Err("invalid value for `files.sort_into`: at most 9 directories".into());
+        }
+        if !(1..=100).contains(&self.export.quality) {
+            return Err("invalid value for `export.quality`: must be between 1 and 100".into());
+        }
+        if self.duplicates.max_distance > 64 {
+            return Err("invalid value for `duplicates.max_distance`: at most 64".into());
+        }
+        if !(32..=1024).contains(&self.gallery.cell_size) {
+            return Err(
+                "invalid value for `gallery.cell_size`: must be between 32 and 1024".into(),
+            );
+        }
+        if self.render.default_width == 0 || self.render.default_height == 0 {
+            return Err("invalid value for `render.default_width`/`render.default_height`: must be non-zero".into());
+        }
+        Ok(())
+    }
 }
 
 /// Memory budget configuration
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct MemoryConfig {
     /// Percentage of system RAM to use (0.0 - 1.0)
     pub budget_ratio: f64,
@@ -30,18 +181,48 @@ pub struct MemoryConfig {
     pub min_budget: usize,
     /// Maximum budget in bytes
     pub max_budget: usize,
+    /// Largest image (in pixels) decoded at full size; bigger images are
+    /// decoded at reduced scale. Defaults to what fits the budget as RGBA.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_decode_pixels: Option<u64>,
+    /// Memory left free for the rest of the system, in bytes
+    pub safety_margin: usize,
+    /// How often to re-check free memory and shrink the budget if the
+    /// system is running low (0 = only at startup)
+    #[serde(with = "duration_format")]
+    pub recheck_interval: Duration,
 }
 
 impl MemoryConfig {
     /// Calculate the actual memory budget in bytes
     pub fn calculate_budget(&self) -> usize {
-        let mut sys = System::new_all();
-        sys.refresh_memory();
+        self.current_budget(0)
+    }
+
+    /// Budget for the memory free right now, counting `own_usage` (what
+    /// the image store already holds) as ours to keep
+    pub fn current_budget(&self, own_usage: usize) -> usize {
+        let (total, available) = system_memory();
+        self.budget_for(total, available, own_usage)
+    }
 
-        let total_ram = sys.total_memory() as usize;
-        let budget = (total_ram as f64 * self.budget_ratio) as usize;
+    /// The budget is a share of total RAM, but never more than what is
+    /// actually free minus the safety margin
+    pub fn budget_for(&self, total_ram: usize, available: usize, own_usage: usize) -> usize {
+        let by_ratio = (total_ram as f64 * self.budget_ratio) as usize;
+        let headroom = available
+            .saturating_add(own_usage)
+            .saturating_sub(self.safety_margin);
 
-        budget.clamp(self.min_budget, self.max_budget)
+        by_ratio
+            .min(headroom)
+            .clamp(self.min_budget, self.max_budget)
+    }
+
+    /// Pixel cap for decoding, given the budget in bytes. The default lets
+    /// one RGBA image fill the whole budget.
+    pub fn decode_pixel_cap(&self, budget: usize) -> u64 {
+        self.max_decode_pixels.unwrap_or(budget as u64 / 4)
     }
 }
 
@@ -51,18 +232,80 @@ impl Default for MemoryConfig {
             budget_ratio: 0.10,                 // 10% of RAM
             min_budget: 100 * 1024 * 1024,      // 100 MB
             max_budget: 4 * 1024 * 1024 * 1024, // 4 GB
+            max_decode_pixels: None,            // derived from the budget
+            safety_margin: 512 * 1024 * 1024,   // 512 MB
+            recheck_interval: Duration::from_secs(5),
         }
     }
 }
 
+/// Total and available memory in bytes, limited to the cgroup (v2) the
+/// process runs in when it has a memory limit
+fn system_memory() -> (usize, usize) {
+    let mut sys = System::new();
+    sys.refresh_memory();
+    let total = sys.total_memory() as usize;
+    let available = sys.available_memory() as usize;
+
+    let cgroup = fs::read_to_string("/sys/fs/cgroup/memory.max")
+        .ok()
+        .zip(fs::read_to_string("/sys/fs/cgroup/memory.current").ok())
+        .and_then(|(max, current)| parse_cgroup_memory(&max, &current));
+    match cgroup {
+        Some((limit, used)) => (total.min(limit), available.min(limit.saturating_sub(used))),
+        None => (total, available),
+    }
+}
+
+/// Parse cgroup v2 `memory.max` and `memory.current` into (limit, used).
+/// None when the cgroup has no limit ("max").
+fn parse_cgroup_memory(max: &str, current: &str) -> Option<(usize, usize)> {
+    let limit = max.trim().parse().ok()?;
+    let used = current.trim().parse().unwrap_or(0);
+    Some((limit, used))
+}
+
 /// Input handling configuration
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct InputConfig {
     /// How long to hold before entering repeat mode
     /// Below this threshold, release triggers a single click
+    #[serde(with = "duration_format")]
     pub hold_threshold: Duration,
     /// Interval between repeats while key is held (after hold_threshold)
+    #[serde(with = "duration_format")]
     pub repeat_interval: Duration,
+    /// Step from the last image to the first (and back); when false,
+    /// navigation stops at either end
+    pub wrap: bool,
+    /// With `wrap` off, stepping on past the end of a directory moves into
+    /// the next sibling directory, and back past the start into the previous
+    pub cross_directory: bool,
+    /// Images skipped by PageUp/PageDown; at least 5% of the collection
+    pub jump_step: usize,
+    /// What the mouse wheel does at fit-to-window
+    pub wheel: WheelAction,
+    /// Left-click the right half of the window for the next image, the
+    /// left half for the previous one
+    pub click_navigation: bool,
+    /// Take commands from `fiv --remote` over a socket in
+    /// `$XDG_RUNTIME_DIR` (Unix only)
+    pub remote_control: bool,
+    /// Hand the paths of later launches to the viewer already running
+    /// rather than opening another window (as --single-instance)
+    pub single_instance: bool,
+}
+
+/// What the mouse wheel does when the image isn't zoomed in. Once zoomed
+/// in, and with Ctrl held, the wheel always zooms.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum WheelAction {
+    /// Next/previous image, one per notch
+    Navigate,
+    /// Zoom in and out
+    Zoom,
 }
 
 impl Default for InputConfig {
@@ -72,12 +315,20 @@ impl Default for InputConfig {
             hold_threshold: Duration::from_millis(150),
             // ~16 images per second when holding
             repeat_interval: Duration::from_millis(60),
+            wrap: true,
+            cross_directory: false,
+            jump_step: 10,
+            wheel: WheelAction::Navigate,
+            click_navigation: false,
+            remote_control: true,
+            single_instance: false,
         }
     }
 }
 
 /// Preloading strategy configuration
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct PreloadConfig {
     /// Number of images to preload ahead when moving forward
     pub ahead_forward: usize,
@@ -93,10 +344,40 @@ pub struct PreloadConfig {
     pub full_quality_count: usize,
     /// How many images to load at preview quality (after full)
     pub preview_quality_count: usize,
-    /// How long to wait when idle before checking for work
+    /// Longest the idle preloader waits before re-checking for work; it is
+    /// woken early by navigation and shutdown
+    #[serde(with = "duration_format")]
     pub idle_poll_interval: Duration,
-    /// Maximum parallel decode tasks (0 = use all cores)
+    /// Maximum parallel decode tasks (0 = all cores but one, which is left
+    /// for the UI thread)
     pub max_parallel_tasks: usize,
+    /// Use thumbnails embedded in EXIF data for the thumbnail tier
+    pub use_embedded_thumbnails: bool,
+    /// How much recently viewed images are favoured over nearer ones when
+    /// evicting, from 0 (distance only) to 1 (recency only)
+    pub eviction_recency_weight: f64,
+    /// Files read at once ahead of decoding. Keep low for spinning disks
+    /// and network shares; raise for SSDs.
+    pub read_threads: usize,
+    /// Keep decoded thumbnails on disk so later runs can skip decoding
+    pub thumbnail_cache: bool,
+    /// Size cap for the thumbnail cache in bytes
+    pub thumbnail_cache_size: u64,
+    /// Longest a decode may take before its image is given up on as
+    /// failed, so pathological files can't hold up the rest (0 = no limit)
+    #[serde(with = "duration_format")]
+    pub decode_timeout: Duration,
+    /// Show a progressive JPEG coarse to fine while it's slow to read
+    /// (e.g. from a network share), instead of waiting for all of it
+    pub progressive_display: bool,
+    /// Decode nearby images to fit the window rather than at full
+    /// resolution, until the current one is zoomed in past fit
+    pub display_fit: bool,
+    /// Longest side of the thumbnail, preview and full tiers, in pixels;
+    /// 0 for full leaves it unlimited. Always three: the planner, the
+    /// thumbnail cache and the display-fit tier rely on each step's role,
+    /// so only the sizes are configurable.
+    pub tiers: Vec<u32>,
 }
 
 impl Default for PreloadConfig {
@@ -114,8 +395,17 @@ impl Default for PreloadConfig {
             full_quality_count: 5,     // ±5 images at full quality
             preview_quality_count: 10, // Next ±10 at preview
             // Rest at thumbnail
-            idle_poll_interval: Duration::from_millis(1),
-            max_parallel_tasks: 0, // Use all cores
+            idle_poll_interval: Duration::from_millis(500),
+            max_parallel_tasks: 0, // All cores but one
+            use_embedded_thumbnails: true,
+            eviction_recency_weight: 0.5,
+            read_threads: 1,
+            thumbnail_cache: true,
+            thumbnail_cache_size: 512 * 1024 * 1024, // 512 MB
+            decode_timeout: Duration::from_secs(10),
+            progressive_display: true,
+            display_fit: true,
+            tiers: TierSizes::default().sides.to_vec(),
         }
     }
 }
@@ -142,21 +432,105 @@ impl PreloadConfig {
         }
     }
 
+    /// Size of the preloader's decode thread pool
+    pub fn decode_threads(&self) -> usize {
+        match self.max_parallel_tasks {
+            0 => std::thread::available_parallelism()
+                .map_or(1, |n| n.get().saturating_sub(1))
+                .max(1),
+            n => n,
+        }
+    }
+
     /// Total range (for eviction)
     pub fn total_range(&self) -> usize {
         self.ahead_forward.max(self.behind_backward) + 5
     }
+
+    /// The tier sizes from `tiers`, or the defaults if it isn't the three
+    /// sizes `Config::validate` insists on
+    pub fn tier_sizes(&self) -> TierSizes {
+        match self.tiers[..] {
+            [thumbnail, preview, full] => TierSizes::new([thumbnail, preview, full]),
+            _ => TierSizes::default(),
+        }
+    }
 }
 
 /// Rendering configuration
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct RenderConfig {
     /// Default window width
     pub default_width: u32,
     /// Default window height
     pub default_height: u32,
     /// Background color (RGBA)
+    #[serde(with = "color_format")]
     pub background_color: [u8; 4],
+    /// Start in borderless fullscreen
+    pub fullscreen: bool,
+    /// Start with the status bar shown along the bottom edge
+    pub status_bar: bool,
+    /// Start with a strip of the neighbouring images' thumbnails shown
+    /// along the bottom edge
+    pub filmstrip: bool,
+    /// Window title template, with placeholders like `{filename}`
+    pub title_format: String,
+    /// What fills the window around the image
+    pub background_mode: BackgroundMode,
+    /// How the image is resampled to the window
+    pub scaling_filter: ScalingFilter,
+    /// Draw the image as a GPU texture (needs the `gpu` build feature)
+    pub gpu: bool,
+    /// Convert images with an embedded color profile (Display P3, Adobe
+    /// RGB, ...) to sRGB
+    pub color_management: bool,
+}
+
+/// What fills the letterbox area around the image
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BackgroundMode {
+    /// `background_color`
+    #[default]
+    Solid,
+    /// A blurred, dimmed copy of the image itself
+    Blur,
+}
+
+/// Resampling used when scaling the image to the window. Both smooth
+/// filters average over the covered pixels when shrinking.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ScalingFilter {
+    /// Sharp pixels, fastest
+    Nearest,
+    /// Smooth, blending the 2x2 nearest pixels when enlarging
+    #[default]
+    Bilinear,
+    /// Sharper than bilinear when enlarging, over 4x4 pixels
+    CatmullRom,
+}
+
+impl ScalingFilter {
+    /// The next filter, for cycling through them
+    pub fn next(self) -> Self {
+        match self {
+            ScalingFilter::Nearest => ScalingFilter::Bilinear,
+            ScalingFilter::Bilinear => ScalingFilter::CatmullRom,
+            ScalingFilter::CatmullRom => ScalingFilter::Nearest,
+        }
+    }
+
+    /// Name as written in the config file
+    pub fn name(self) -> &'static str {
+        match self {
+            ScalingFilter::Nearest => "nearest",
+            ScalingFilter::Bilinear => "bilinear",
+            ScalingFilter::CatmullRom => "catmull-rom",
+        }
+    }
 }
 
 impl Default for RenderConfig {
@@ -165,35 +539,324 @@ impl Default for RenderConfig {
             default_width: 1280,
             default_height: 720,
             background_color: [0, 0, 0, 255], // Black
+            fullscreen: false,
+            status_bar: false,
+            filmstrip: false,
+            title_format: DEFAULT_TITLE_FORMAT.to_string(),
+            background_mode: BackgroundMode::Solid,
+            scaling_filter: ScalingFilter::Bilinear,
+            gpu: false,
+            color_management: true,
         }
     }
 }
 
+/// Zoom and view behaviour configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ViewConfig {
+    /// Zoom factor applied per key press or wheel notch
+    pub zoom_step: f64,
+    /// Smallest zoom relative to fit-to-window
+    pub min_zoom: f64,
+    /// Largest zoom relative to fit-to-window
+    pub max_zoom: f64,
+    /// Keep zoom and pan when moving to another image
+    pub keep_zoom: bool,
+    /// Keep rotation and flips when moving to another image
+    pub sticky_rotation: bool,
+    /// Which arrow keys pan a zoomed image
+    pub pan_keys: PanKeys,
+    /// Distance in window pixels per pan key press
+    pub pan_step: f64,
+    /// Reload the displayed image when its file changes on disk
+    pub auto_reload: bool,
+    /// How often to check the displayed file for changes
+    #[serde(with = "duration_format")]
+    pub reload_poll_interval: Duration,
+    /// How long the slideshow shows each image
+    #[serde(with = "duration_format")]
+    pub slideshow_interval: Duration,
+    /// Reopen a directory at the image last shown in it
+    pub remember_position: bool,
+    /// Open the window where the last one was closed, at the same size,
+    /// maximized or fullscreen if it was
+    pub remember_window: bool,
+    /// Resize the window to the first image, as Shift+W does
+    pub fit_window: bool,
+    /// With `fit_window`, resize the window to each image moved to as well
+    pub refit_window: bool,
+    /// Hide the mouse pointer over the window while the mouse is unused
+    pub hide_cursor: bool,
+    /// How long the mouse is left alone before the pointer hides
+    #[serde(with = "duration_format")]
+    pub hide_cursor_after: Duration,
+}
+
+/// How arrow keys behave while zoomed in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PanKeys {
+    /// Plain arrows pan; navigation waits until zoom is reset
+    Arrows,
+    /// Shift+arrows pan; plain arrows keep navigating
+    ShiftArrows,
+}
+
+impl Default for ViewConfig {
+    fn default() -> Self {
+        Self {
+            zoom_step: 1.25,
+            min_zoom: 1.0 / 16.0,
+            max_zoom: 32.0,
+            keep_zoom: false,
+            sticky_rotation: false,
+            pan_keys: PanKeys::Arrows,
+            pan_step: 64.0,
+            auto_reload: true,
+            reload_poll_interval: Duration::from_secs(1),
+            slideshow_interval: Duration::from_secs(5),
+            remember_position: true,
+            remember_window: true,
+            fit_window: false,
+            refit_window: true,
+            hide_cursor: true,
+            hide_cursor_after: Duration::from_secs(2),
+        }
+    }
+}
+
+/// File operation configuration
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FilesConfig {
+    /// Delete only on a second press of the key within a second
+    pub confirm_delete: bool,
+    /// Let Shift+Delete remove files for good instead of moving them to
+    /// the trash
+    pub permanent_delete: bool,
+    /// Directories the number keys 1-9 sort the current image into
+    pub sort_into: Vec<PathBuf>,
+    /// Whether sorting copies or moves the file
+    pub sort_action: SortAction,
+}
+
+/// What sorting an image into a directory does with its file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum SortAction {
+    /// Leave the original in place
+    #[default]
+    Copy,
+    /// Take the image out of the browsed collection
+    Move,
+}
+
+/// Export configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExportConfig {
+    /// Directory exports are written to (created if missing); next to the
+    /// image if unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dir: Option<PathBuf>,
+    /// Longest side of the export; larger images are scaled down, never
+    /// up. 0 keeps the size.
+    pub max_dimension: u32,
+    /// File format to encode
+    pub format: ExportFormat,
+    /// JPEG quality, 1-100
+    pub quality: u8,
+}
+
+impl Default for ExportConfig {
+    fn default() -> Self {
+        Self {
+            dir: None,
+            max_dimension: 2048,
+            format: ExportFormat::Jpeg,
+            quality: 85,
+        }
+    }
+}
+
+/// File format of exported images
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ExportFormat {
+    /// Lossy, at `export.quality`; transparency is flattened onto white
+    #[default]
+    Jpeg,
+    /// Lossless
+    Png,
+    /// Lossless
+    Webp,
+}
+
+impl ExportFormat {
+    /// File name extension
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Jpeg => "jpg",
+            Self::Png => "png",
+            Self::Webp => "webp",
+        }
+    }
+}
+
+/// Configuration for other programs
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExternalConfig {
+    /// Shell command setting the wallpaper, with `{path}` or `{uri}` for
+    /// the image; picked for the desktop if unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub wallpaper_command: Option<String>,
+}
+
+/// Commands run as images are shown. Like the wallpaper command they
+/// are shell templates, with `{path}`, `{uri}`, `{index}` (counting from
+/// 1) and `{total}` for the image.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HooksConfig {
+    /// Run for each image once it has been shown for `debounce`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub on_image_change: Option<String>,
+    /// Run for the first image as the window opens
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub on_start: Option<String>,
+    /// Run for the image shown last on quitting
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub on_quit: Option<String>,
+    /// How long an image must stay before `on_image_change` runs, so
+    /// holding a key down doesn't run it for every image passed
+    #[serde(with = "duration_format")]
+    pub debounce: Duration,
+}
+
+impl Default for HooksConfig {
+    fn default() -> Self {
+        Self {
+            on_image_change: None,
+            on_start: None,
+            on_quit: None,
+            debounce: Duration::from_millis(300),
+        }
+    }
+}
+
+/// Near-duplicate detection configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DuplicatesConfig {
+    /// Hash every image's thumbnail in idle time, decoding those not yet
+    /// seen; otherwise only thumbnails loaded anyway are hashed
+    pub enabled: bool,
+    /// Most bits two hashes may differ in for the images to count as
+    /// similar, 0-64
+    pub max_distance: u32,
+}
+
+impl Default for DuplicatesConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_distance: 10,
+        }
+    }
+}
+
+/// Gallery view configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GalleryConfig {
+    /// Side of a grid cell in pixels, thumbnail and gap around it
+    pub cell_size: u32,
+}
+
+impl Default for GalleryConfig {
+    fn default() -> Self {
+        Self { cell_size: 192 }
+    }
+}
+
+/// Longest side of the thumbnail, preview and full tiers (0 = unlimited),
+/// from `preload.tiers`. Passed to whatever sizes an image for a tier;
+/// the decoder, the store and the view state each keep a copy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TierSizes {
+    sides: [u32; 3],
+}
+
+impl Default for TierSizes {
+    fn default() -> Self {
+        Self::new([256, 1024, 0])
+    }
+}
+
+impl TierSizes {
+    pub fn new(sides: [u32; 3]) -> Self {
+        Self { sides }
+    }
+}
+
 /// Quality tier for image loading.
 /// Ordered from lowest to highest quality.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, clap::ValueEnum)]
 pub enum QualityTier {
-    /// Thumbnail - 256px max dimension
+    /// Thumbnail - 256px max dimension by default
     Thumbnail,
-    /// Preview - 1024px max dimension
+    /// Preview - 1024px max dimension by default
     Preview,
-    /// Full - original resolution
+    /// Display fit - the window's longest side as max dimension, so a
+    /// larger window ranks higher
+    #[value(skip)]
+    DisplayFit(u32),
+    /// Full - original resolution, unless capped in the config
     Full,
 }
 
 impl QualityTier {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Thumbnail => "thumbnail",
+            Self::Preview => "preview",
+            Self::DisplayFit(_) => "display-fit",
+            Self::Full => "full",
+        }
+    }
+
+    /// The display-fit tier for a window of this size. It never falls
+    /// below a preview, which it ranks above, nor past a capped full tier.
+    pub fn display_fit(width: u32, height: u32, sizes: TierSizes) -> Self {
+        let side = width
+            .max(height)
+            .max(Self::Preview.max_dimension(sizes).unwrap_or(0));
+        Self::DisplayFit(
+            Self::Full
+                .max_dimension(sizes)
+                .map_or(side, |full| side.min(full)),
+        )
+    }
+
     /// Maximum dimension for this tier (None = unlimited)
-    pub const fn max_dimension(self) -> Option<u32> {
+    pub fn max_dimension(self, sizes: TierSizes) -> Option<u32> {
+        let configured = |index: usize| match sizes.sides[index] {
+            0 => None,
+            side => Some(side),
+        };
         match self {
-            Self::Thumbnail => Some(256),
-            Self::Preview => Some(1024),
-            Self::Full => None,
+            Self::Thumbnail => configured(0),
+            Self::Preview => configured(1),
+            Self::DisplayFit(side) => Some(side),
+            Self::Full => configured(2),
         }
     }
 
     /// Calculate target dimensions maintaining aspect ratio
-    pub fn target_dimensions(self, width: u32, height: u32) -> (u32, u32) {
-        match self.max_dimension() {
+    pub fn target_dimensions(self, width: u32, height: u32, sizes: TierSizes) -> (u32, u32) {
+        match self.max_dimension(sizes) {
             None => (width, height),
             Some(max_dim) => {
                 let max_original = width.max(height);
@@ -210,6 +873,104 @@ impl QualityTier {
     }
 }
 
+/// Parse a human-friendly duration such as "150ms", "2s", "500us" or "1m"
+pub fn parse_duration(text: &str) -> Result<Duration, String> {
+    let text = text.trim();
+    let split = text
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+
+    let value: f64 = number
+        .parse()
+        .map_err(|_| format!("invalid duration '{}'", text))?;
+    let unit_nanos = match unit.trim() {
+        "us" => 1e3,
+        "ms" => 1e6,
+        "s" => 1e9,
+        "m" => 60e9,
+        _ => {
+            return Err(format!(
+                "invalid duration '{}' (expected a unit of us, ms, s or m)",
+                text
+            ))
+        }
+    };
+
+    let nanos = (value * unit_nanos).round();
+    if nanos > u64::MAX as f64 {
+        return Err(format!("duration '{}' is too large", text));
+    }
+    Ok(Duration::from_nanos(nanos as u64))
+}
+
+/// Format a duration in the largest unit that represents it exactly
+pub fn format_duration(duration: Duration) -> String {
+    let micros = duration.as_micros();
+    if micros.is_multiple_of(1_000_000) && micros > 0 {
+        format!("{}s", micros / 1_000_000)
+    } else if micros.is_multiple_of(1000) {
+        format!("{}ms", micros / 1000)
+    } else {
+        format!("{}us", micros)
+    }
+}
+
+/// Parse a "#RRGGBB" or "#RRGGBBAA" color into RGBA
+pub fn parse_color(text: &str) -> Result<[u8; 4], String> {
+    let hex = text.trim().trim_start_matches('#');
+    let invalid = || format!("invalid color '{}' (expected #RRGGBB or #RRGGBBAA)", text);
+
+    if !(hex.len() == 6 || hex.len() == 8) || !hex.is_ascii() {
+        return Err(invalid());
+    }
+
+    let mut rgba = [0, 0, 0, 255];
+    for (i, channel) in rgba.iter_mut().enumerate().take(hex.len() / 2) {
+        *channel = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).map_err(|_| invalid())?;
+    }
+    Ok(rgba)
+}
+
+/// Format RGBA as "#RRGGBB", adding alpha only when not opaque
+pub fn format_color(rgba: [u8; 4]) -> String {
+    let [r, g, b, a] = rgba;
+    if a == 255 {
+        format!("#{:02x}{:02x}{:02x}", r, g, b)
+    } else {
+        format!("#{:02x}{:02x}{:02x}{:02x}", r, g, b, a)
+    }
+}
+
+/// Serde adapter for durations as human-friendly strings
+mod duration_format {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::time::Duration;
+
+    pub fn serialize<S: Serializer>(value: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&super::format_duration(*value))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        super::parse_duration(&text).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Serde adapter for colors as hex strings
+mod color_format {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &[u8; 4], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&super::format_color(*value))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<[u8; 4], D::Error> {
+        let text = String::deserialize(deserializer)?;
+        super::parse_color(&text).map_err(serde::de::Error::custom)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -242,18 +1003,189 @@ mod tests {
         assert!(behind > ahead);
     }
 
+    #[test]
+    fn test_decode_threads() {
+        let mut config = PreloadConfig::default();
+        assert!(config.decode_threads() >= 1);
+
+        config.max_parallel_tasks = 3;
+        assert_eq!(config.decode_threads(), 3);
+    }
+
     #[test]
     fn test_tier_dimensions() {
+        let sizes = TierSizes::default();
+
         // Thumbnail should scale down large images
-        let (w, h) = QualityTier::Thumbnail.target_dimensions(1920, 1080);
+        let (w, h) = QualityTier::Thumbnail.target_dimensions(1920, 1080, sizes);
         assert!(w <= 256 && h <= 256);
 
         // Full should preserve dimensions
-        let (w, h) = QualityTier::Full.target_dimensions(1920, 1080);
+        let (w, h) = QualityTier::Full.target_dimensions(1920, 1080, sizes);
         assert_eq!((w, h), (1920, 1080));
 
         // Small images should not be upscaled
-        let (w, h) = QualityTier::Full.target_dimensions(100, 100);
+        let (w, h) = QualityTier::Full.target_dimensions(100, 100, sizes);
         assert_eq!((w, h), (100, 100));
+
+        // Display fit follows the window's longest side
+        let fit = QualityTier::display_fit(1600, 900, sizes);
+        assert_eq!(fit.target_dimensions(8000, 6000, sizes), (1600, 1200));
+        assert_eq!(fit.target_dimensions(1000, 800, sizes), (1000, 800));
+        assert_eq!(
+            QualityTier::display_fit(640, 480, sizes),
+            QualityTier::DisplayFit(1024)
+        );
+    }
+
+    #[test]
+    fn test_tier_order() {
+        let sizes = TierSizes::default();
+        let small = QualityTier::display_fit(1600, 900, sizes);
+        let large = QualityTier::display_fit(2560, 1440, sizes);
+        assert!(QualityTier::Preview < small);
+        assert!(small < large);
+        assert!(large < QualityTier::Full);
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("150ms"), Ok(Duration::from_millis(150)));
+        assert_eq!(parse_duration("2s"), Ok(Duration::from_secs(2)));
+        assert_eq!(parse_duration("1.5s"), Ok(Duration::from_millis(1500)));
+        assert_eq!(parse_duration("500us"), Ok(Duration::from_micros(500)));
+        assert!(parse_duration("150").is_err());
+        assert!(parse_duration("fast").is_err());
+
+        // Round-trips through the formatter
+        for d in [Duration::from_millis(60), Duration::from_secs(3)] {
+            assert_eq!(parse_duration(&format_duration(d)), Ok(d));
+        }
+    }
+
+    #[test]
+    fn test_parse_color() {
+        assert_eq!(parse_color("#ff8000"), Ok([255, 128, 0, 255]));
+        assert_eq!(parse_color("#00000080"), Ok([0, 0, 0, 128]));
+        assert!(parse_color("#fff").is_err());
+        assert!(parse_color("#gg0000").is_err());
+        assert_eq!(format_color([255, 128, 0, 255]), "#ff8000");
+    }
+
+    #[test]
+    fn test_partial_config_keeps_defaults() {
+        let config = Config::from_toml(
+            r##"
+            [input]
+            hold_threshold = "200ms"
+
+            [render]
+            background_color = "#202020"
+            scaling_filter = "catmull-rom"
+            "##,
+        )
+        .unwrap();
+
+        assert_eq!(config.input.hold_threshold, Duration::from_millis(200));
+        assert_eq!(
+            config.input.repeat_interval,
+            InputConfig::default().repeat_interval
+        );
+        assert_eq!(config.render.background_color, [32, 32, 32, 255]);
+        assert_eq!(config.render.scaling_filter, ScalingFilter::CatmullRom);
+        assert_eq!(config.export.max_dimension, 2048);
+        assert_eq!(
+            config.preload.ahead_forward,
+            PreloadConfig::default().ahead_forward
+        );
+    }
+
+    #[test]
+    fn test_config_error_names_key() {
+        let err = Config::from_toml("[input]\nrepeat_interval = \"soon\"\n").unwrap_err();
+        assert!(err.contains("input.repeat_interval"), "{}", err);
+
+        let err = Config::from_toml("[memory]\nbudget_ratio = 2.0\n").unwrap_err();
+        assert!(err.contains("memory.budget_ratio"), "{}", err);
+
+        let err = Config::from_toml("[export]\nquality = 0\n").unwrap_err();
+        assert!(err.contains("export.quality"), "{}", err);
+
+        let err = Config::from_toml("[duplicates]\nmax_distance = 65\n").unwrap_err();
+        assert!(err.contains("duplicates.max_distance"), "{}", err);
+
+        let err = Config::from_toml("[gallery]\ncell_size = 8\n").unwrap_err();
+        assert!(err.contains("gallery.cell_size"), "{}", err);
+    }
+
+    #[test]
+    fn test_unknown_key_rejected() {
+        let err = Config::from_toml("[preload]\nahead_foward = 50\n").unwrap_err();
+        assert!(err.contains("ahead_foward"), "{}", err);
+        assert!(err.contains("preload"), "{}", err);
+
+        let err = Config::from_toml("[prelaod]\nahead_forward = 50\n").unwrap_err();
+        assert!(err.contains("prelaod"), "{}", err);
+    }
+
+    #[test]
+    fn test_tier_sizes() {
+        let config = Config::from_toml("[preload]\ntiers = [320, 1440, 0]\n").unwrap();
+        let sizes = config.preload.tier_sizes();
+        assert_eq!(QualityTier::Thumbnail.max_dimension(sizes), Some(320));
+        assert_eq!(QualityTier::Preview.max_dimension(sizes), Some(1440));
+        assert_eq!(QualityTier::Full.max_dimension(sizes), None);
+        let config = Config::from_toml("[preload]\ntiers = [320, 1440, 4096]\n").unwrap();
+        assert_eq!(
+            QualityTier::Full.max_dimension(config.preload.tier_sizes()),
+            Some(4096)
+        );
+
+        for tiers in [
+            "[1024, 256, 0]",
+            "[256, 1024, 512]",
+            "[8, 1024, 0]",
+            "[256, 1024]",
+            "[128, 256, 1024, 0]",
+        ] {
+            let err = Config::from_toml(&format!("[preload]\ntiers = {}\n", tiers)).unwrap_err();
+            assert!(err.contains("preload.tiers"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_budget_for() {
+        const GB: usize = 1024 * 1024 * 1024;
+        let config = MemoryConfig::default();
+
+        // Plenty free: 10% of RAM
+        assert_eq!(config.budget_for(16 * GB, 12 * GB, 0), 16 * GB / 10);
+
+        // Nearly full: what's free minus the margin, down to the minimum
+        assert_eq!(config.budget_for(16 * GB, GB, 0), GB / 2);
+        assert_eq!(config.budget_for(16 * GB, GB / 4, 0), config.min_budget);
+
+        // Memory the store already holds stays ours
+        assert_eq!(config.budget_for(16 * GB, GB / 2, GB / 2), GB / 2);
+    }
+
+    #[test]
+    fn test_parse_cgroup_memory() {
+        assert_eq!(parse_cgroup_memory("max\n", "1234\n"), None);
+        assert_eq!(
+            parse_cgroup_memory("2147483648\n", "1073741824\n"),
+            Some((2147483648, 1073741824))
+        );
+    }
+
+    #[test]
+    fn test_dump_round_trip() {
+        let config = Config::default();
+        let parsed = Config::from_toml(&config.to_toml()).unwrap();
+        assert_eq!(parsed.input.hold_threshold, config.input.hold_threshold);
+        assert_eq!(
+            parsed.render.background_color,
+            config.render.background_color
+        );
     }
 }
@@ -3,175 +3,537 @@
 //! This module handles all image decoding, separated from the preloading logic.
 //! It provides a clean interface for decoding images at various quality tiers.
 
-use crate::config::QualityTier;
-use crate::slot::ImageData;
+use crate::archive;
+use crate::color::{self, Transform};
+use crate::config::{QualityTier, TierSizes};
+use crate::formats::{self, FormatDecoder};
+use crate::metrics::Metrics;
+use crate::slot::{ImageData, ImageMeta, ImageSource};
+use std::cell::RefCell;
+use std::cmp::Ordering;
 use std::fs;
-use std::path::Path;
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Bytes read from unknown files when sniffing at scan time
+const SNIFF_LEN: usize = 16;
+
+/// Bytes read when looking for an embedded thumbnail or header dimensions:
+/// a maximal (64 KiB) EXIF segment and what follows it
+const HEAD_LEN: usize = 128 * 1024;
+
+/// Bytes read at a time by `Decoder::read_progressively`
+const PARTIAL_CHUNK: u64 = 256 * 1024;
+
+/// How long a progressive read goes on before the scans read so far are
+/// shown, and between showing more of them
+const PARTIAL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// What `Decoder::read` produced for the decode stage
+#[derive(Debug)]
+pub enum Source {
+    /// Already decoded (an embedded thumbnail)
+    Decoded(Arc<ImageData>),
+    /// The whole file, for `Decoder::decode_bytes`
+    Bytes(Vec<u8>),
+}
+
+/// Why an image could not be decoded
+#[derive(Debug)]
+pub enum DecodeError {
+    /// The file could not be read
+    Io(std::io::Error),
+    /// No registered format handles this file
+    UnsupportedFormat,
+    /// The data is damaged or uses an unsupported variant of the format
+    Corrupt(String),
+    /// The image exceeds the decoder's size limits
+    TooLarge,
+    /// Decoding took longer than `preload.decode_timeout`
+    TimedOut,
+}
+
+impl std::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeError::Io(err) => write!(f, "cannot read file: {}", err),
+            DecodeError::UnsupportedFormat => write!(f, "unsupported format"),
+            DecodeError::Corrupt(msg) => write!(f, "corrupt image: {}", msg),
+            DecodeError::TooLarge => write!(f, "image too large"),
+            DecodeError::TimedOut => write!(f, "decoding took too long"),
+        }
+    }
+}
+
+impl From<image::ImageError> for DecodeError {
+    fn from(err: image::ImageError) -> Self {
+        match err {
+            image::ImageError::IoError(err) => DecodeError::Io(err),
+            image::ImageError::Limits(_) => DecodeError::TooLarge,
+            image::ImageError::Unsupported(_) => DecodeError::UnsupportedFormat,
+            err => DecodeError::Corrupt(err.to_string()),
+        }
+    }
+}
+
+/// Run one decode step, turning a panic inside it (a decoder library
+/// tripping over a malformed file) into a `Corrupt` error, so one bad file
+/// can't take down the thread decoding it. Decoders keep no state a panic
+/// could leave half-updated, which makes asserting unwind safety sound.
+pub fn catch_panics<T>(step: impl FnOnce() -> Result<T, DecodeError>) -> Result<T, DecodeError> {
+    std::panic::catch_unwind(std::panic::AssertUnwindSafe(step)).unwrap_or_else(|payload| {
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown cause".to_string());
+        Err(DecodeError::Corrupt(format!(
+            "decoder panicked: {}",
+            message
+        )))
+    })
+}
+
+thread_local! {
+    /// Cancellation flag of the decode running on this thread, if it can
+    /// be abandoned
+    static CANCEL: RefCell<Option<Arc<AtomicBool>>> = const { RefCell::new(None) };
+}
+
+/// Run a decode step that may be abandoned by setting `flag`. Decoders
+/// can't be stopped from outside, so long loops poll `cancelled()` and
+/// give up early.
+pub fn with_cancel<T>(flag: &Arc<AtomicBool>, step: impl FnOnce() -> T) -> T {
+    let outer = CANCEL.with(|cancel| cancel.replace(Some(Arc::clone(flag))));
+    let result = step();
+    CANCEL.with(|cancel| *cancel.borrow_mut() = outer);
+    result
+}
+
+/// Whether the decode running on this thread has been abandoned, so its
+/// result will be thrown away
+pub fn cancelled() -> bool {
+    CANCEL.with(|cancel| {
+        cancel
+            .borrow()
+            .as_ref()
+            .is_some_and(|flag| flag.load(AtomicOrdering::Relaxed))
+    })
+}
 
 /// Decoder for images - handles format detection and quality tiers.
+///
+/// Formats live in a registry: built-ins are registered by `new()`, and
+/// anything else can be appended with `register()`.
 pub struct Decoder {
-    /// Supported extensions (lowercase, no dot)
-    supported_extensions: Vec<&'static str>,
+    /// Registered formats, in detection order
+    formats: Vec<Box<dyn FormatDecoder>>,
+    /// Sniff the content of files with unknown or missing extensions
+    sniff: bool,
+    /// Images with more pixels than this are decoded at reduced scale
+    max_pixels: u64,
+    /// Serve the thumbnail tier from thumbnails embedded in the file
+    embedded_thumbnails: bool,
+    /// Convert images with an embedded color profile to sRGB
+    color_management: bool,
+    /// Longest side of each reduced quality tier
+    tiers: TierSizes,
+    /// Counts and times the decodes, for the statistics overlay
+    metrics: Arc<Metrics>,
 }
 
 impl Decoder {
     pub fn new() -> Self {
         Self {
-            supported_extensions: vec!["jpg", "jpeg", "png", "gif", "bmp", "webp"],
+            formats: formats::builtin(),
+            sniff: false,
+            max_pixels: u64::MAX,
+            embedded_thumbnails: false,
+            color_management: true,
+            tiers: TierSizes::default(),
+            metrics: Arc::new(Metrics::new()),
         }
     }
 
+    /// Add a format after the existing ones
+    pub fn register(&mut self, format: Box<dyn FormatDecoder>) {
+        self.formats.push(format);
+    }
+
+    /// Also accept files whose extension is unknown but whose content is
+    pub fn with_sniffing(mut self, sniff: bool) -> Self {
+        self.sniff = sniff;
+        self
+    }
+
+    /// Cap the pixel count of decoded images
+    pub fn with_max_pixels(mut self, max_pixels: u64) -> Self {
+        self.max_pixels = max_pixels;
+        self
+    }
+
+    /// Use embedded thumbnails instead of decoding the whole file for the
+    /// thumbnail tier, where the format has them
+    pub fn with_embedded_thumbnails(mut self, enabled: bool) -> Self {
+        self.embedded_thumbnails = enabled;
+        self
+    }
+
+    /// Convert images tagged with a color profile to sRGB (on by default)
+    pub fn with_color_management(mut self, enabled: bool) -> Self {
+        self.color_management = enabled;
+        self
+    }
+
+    /// Set the longest side of the thumbnail, preview and full tiers
+    pub fn with_tiers(mut self, tiers: TierSizes) -> Self {
+        self.tiers = tiers;
+        self
+    }
+
+    /// The tier sizes images are decoded at
+    pub fn tiers(&self) -> TierSizes {
+        self.tiers
+    }
+
+    /// Counts and times of the decodes so far
+    pub fn metrics(&self) -> &Arc<Metrics> {
+        &self.metrics
+    }
+
     /// Check if a file is supported
     pub fn is_supported(&self, path: &Path) -> bool {
-        path.extension()
-            .and_then(|ext| ext.to_str())
-            .map(|ext| {
-                let ext_lower = ext.to_lowercase();
-                self.supported_extensions.iter().any(|&e| e == ext_lower)
-            })
-            .unwrap_or(false)
+        self.by_extension(path).is_some() || (self.sniff && self.sniff_file(path).is_some())
     }
 
     /// Get supported extensions
-    pub fn extensions(&self) -> &[&'static str] {
-        &self.supported_extensions
+    pub fn extensions(&self) -> Vec<&str> {
+        self.formats
+            .iter()
+            .flat_map(|f| f.extensions().iter().copied())
+            .collect()
     }
 
-    /// Decode image at specified quality tier
-    pub fn decode(&self, path: &Path, quality: QualityTier) -> Option<Arc<ImageData>> {
-        let data = fs::read(path).ok()?;
+    /// The format claiming a path's extension
+    fn by_extension(&self, path: &Path) -> Option<&dyn FormatDecoder> {
+        let ext = path.extension()?.to_str()?.to_lowercase();
+        self.formats
+            .iter()
+            .find(|f| f.extensions().contains(&ext.as_str()))
+            .map(|f| f.as_ref())
+    }
 
-        // Decode to RGBA
-        let (rgba, width, height) = if Self::is_jpeg(path) {
-            Self::decode_jpeg(&data)?
-        } else {
-            Self::decode_generic(&data)?
-        };
+    /// The first format recognising a file's leading bytes
+    fn by_content(&self, header: &[u8]) -> Option<&dyn FormatDecoder> {
+        self.formats
+            .iter()
+            .find(|f| f.sniff(header))
+            .map(|f| f.as_ref())
+    }
 
-        // Resize for quality tier if needed
-        let (target_w, target_h) = quality.target_dimensions(width, height);
+    /// Sniff a file on disk by reading its first few bytes
+    fn sniff_file(&self, path: &Path) -> Option<&dyn FormatDecoder> {
+        let header = read_head(path, SNIFF_LEN).ok()?;
+        self.by_content(&header)
+    }
 
-        let final_rgba = if target_w == width && target_h == height {
-            rgba
-        } else {
-            Self::resize_bilinear(&rgba, width, height, target_w, target_h)
-        };
+    /// Pick the format for a file. Content wins over the extension, unless
+    /// the extension's format also accepts the content (several formats
+    /// share the TIFF header).
+    fn detect(&self, path: &Path, data: &[u8]) -> Option<&dyn FormatDecoder> {
+        match self.by_extension(path) {
+            Some(format) if format.sniff(data) => Some(format),
+            by_extension => self.by_content(data).or(by_extension),
+        }
+    }
 
-        Some(Arc::new(ImageData::new(
-            final_rgba, target_w, target_h, quality,
-        )))
+    /// Decode image (or the page it refers to) at specified quality tier
+    pub fn decode(
+        &self,
+        meta: &ImageMeta,
+        quality: QualityTier,
+    ) -> Result<Arc<ImageData>, DecodeError> {
+        match self.read(meta, quality)? {
+            Source::Decoded(data) => Ok(data),
+            Source::Bytes(data) => self.decode_bytes(meta, &data, quality),
+        }
     }
 
-    /// Check if file is JPEG by extension
-    fn is_jpeg(path: &Path) -> bool {
-        path.extension()
-            .and_then(|e| e.to_str())
-            .map(|e| {
-                let lower = e.to_lowercase();
-                lower == "jpg" || lower == "jpeg"
-            })
-            .unwrap_or(false)
-    }
-
-    /// Decode JPEG using zune-jpeg (fast)
-    fn decode_jpeg(data: &[u8]) -> Option<(Vec<u8>, u32, u32)> {
-        // Try zune-jpeg first
-        let mut decoder = zune_jpeg::JpegDecoder::new(data);
-        if let Ok(pixels) = decoder.decode() {
-            if let Some(info) = decoder.info() {
-                let rgba = Self::to_rgba(pixels, info.components);
-                return Some((rgba, info.width as u32, info.height as u32));
+    /// The I/O half of `decode`: read what decoding `meta` at `quality`
+    /// needs. Embedded thumbnails are small enough to decode right here.
+    pub fn read(&self, meta: &ImageMeta, quality: QualityTier) -> Result<Source, DecodeError> {
+        if quality == QualityTier::Thumbnail && self.embedded_thumbnails && meta.page.is_none() {
+            let thumbnail = catch_panics(|| Ok(self.embedded_thumbnail(meta)));
+            if let Ok(Some(thumbnail)) = thumbnail {
+                return Ok(Source::Decoded(Arc::new(thumbnail)));
             }
         }
 
-        // Fallback to image crate
-        Self::decode_generic(data)
+        read_source(&meta.source, u64::MAX)
+            .map(Source::Bytes)
+            .map_err(DecodeError::Io)
     }
 
-    /// Decode using image crate (generic fallback)
-    fn decode_generic(data: &[u8]) -> Option<(Vec<u8>, u32, u32)> {
-        let img = image::load_from_memory(data).ok()?;
-        let rgba = img.to_rgba8();
-        Some((rgba.as_raw().to_vec(), rgba.width(), rgba.height()))
-    }
+    /// `read` for the image on screen. While a progressive file is slow to
+    /// read, the scans that have arrived are decoded every
+    /// `PARTIAL_INTERVAL` and handed to `partial` as coarse passes, at no
+    /// more than preview tier. Files that read quickly show none.
+    pub fn read_progressively(
+        &self,
+        meta: &ImageMeta,
+        quality: QualityTier,
+        partial: &dyn Fn(Arc<ImageData>),
+    ) -> Result<Source, DecodeError> {
+        use std::io::Read;
 
-    /// Convert raw pixels to RGBA
-    fn to_rgba(pixels: Vec<u8>, components: u8) -> Vec<u8> {
-        match components {
-            4 => pixels, // Already RGBA
-            3 => pixels
-                .chunks_exact(3)
-                .flat_map(|rgb| [rgb[0], rgb[1], rgb[2], 255])
-                .collect(),
-            1 => pixels.iter().flat_map(|&g| [g, g, g, 255]).collect(),
-            _ => pixels,
+        let path = match &meta.source {
+            ImageSource::File(path) if quality > QualityTier::Thumbnail && meta.page.is_none() => {
+                path
+            }
+            _ => return self.read(meta, quality),
+        };
+        let mut file = fs::File::open(path).map_err(DecodeError::Io)?;
+        let mut data = Vec::new();
+        let mut next_pass = Instant::now() + PARTIAL_INTERVAL;
+        // Length of the last prefix shown, so each pass adds scans
+        let mut shown = 0;
+        loop {
+            let read = file
+                .by_ref()
+                .take(PARTIAL_CHUNK)
+                .read_to_end(&mut data)
+                .map_err(DecodeError::Io)?;
+            if read == 0 {
+                return Ok(Source::Bytes(data));
+            }
+            if Instant::now() < next_pass {
+                continue;
+            }
+            let prefix = self
+                .detect(meta.name(), &data)
+                .and_then(|format| format.progressive_prefix(&data))
+                .filter(|prefix| prefix.len() > shown);
+            if let Some(prefix) = prefix {
+                shown = prefix.len();
+                let quality = quality.min(QualityTier::Preview);
+                if let Ok(image) =
+                    catch_panics(|| self.decode_contents(meta, &prefix, quality, u64::MAX))
+                {
+                    partial(Arc::new(image.with_partial()));
+                }
+            }
+            next_pass = Instant::now() + PARTIAL_INTERVAL;
         }
     }
 
-    /// Resize using bilinear interpolation
-    fn resize_bilinear(data: &[u8], src_w: u32, src_h: u32, dst_w: u32, dst_h: u32) -> Vec<u8> {
-        if src_w == dst_w && src_h == dst_h {
-            return data.to_vec();
+    /// The CPU half of `decode`: decode file contents already in memory.
+    /// `meta` supplies the extension and page.
+    pub fn decode_bytes(
+        &self,
+        meta: &ImageMeta,
+        data: &[u8],
+        quality: QualityTier,
+    ) -> Result<Arc<ImageData>, DecodeError> {
+        self.decode_bytes_within(meta, data, quality, u64::MAX)
+    }
+
+    /// `decode_bytes`, reducing the image to at most `max_pixels` (beyond
+    /// the decoder's own cap) so it fits what memory is left. An image
+    /// reduced for this is marked `over_budget`; formats that can't reduce
+    /// while decoding give their preview tier instead.
+    pub fn decode_bytes_within(
+        &self,
+        meta: &ImageMeta,
+        data: &[u8],
+        quality: QualityTier,
+        max_pixels: u64,
+    ) -> Result<Arc<ImageData>, DecodeError> {
+        let started = Instant::now();
+        let result =
+            catch_panics(|| self.decode_contents(meta, data, quality, max_pixels)).map(Arc::new);
+        match &result {
+            Ok(image) => self
+                .metrics
+                .record_decode(image.memory_size(), started.elapsed()),
+            Err(_) => self.metrics.record_failure(),
         }
+        result
+    }
 
-        let src_w = src_w as usize;
-        let src_h = src_h as usize;
-        let dst_w = dst_w as usize;
-        let dst_h = dst_h as usize;
+    /// `decode_bytes` without the bookkeeping
+    fn decode_contents(
+        &self,
+        meta: &ImageMeta,
+        data: &[u8],
+        quality: QualityTier,
+        budget_pixels: u64,
+    ) -> Result<ImageData, DecodeError> {
+        let format = self
+            .detect(meta.name(), data)
+            .ok_or(DecodeError::UnsupportedFormat)?;
+        let page = meta.page.map_or(0, |p| p.index);
 
-        let mut result = vec![0u8; dst_w * dst_h * 4];
+        // Check the header before committing to a full-size decode
+        let max_pixels = self.max_pixels.min(budget_pixels);
+        let pixels = format
+            .dimensions(data)
+            .filter(|_| page == 0)
+            .map(|(width, height)| width as u64 * height as u64);
+        // Only the full tier is big enough to outgrow the budget
+        let over_budget = quality == QualityTier::Full
+            && budget_pixels < self.max_pixels
+            && pixels.is_some_and(|pixels| pixels > budget_pixels);
+        let mut image = match pixels {
+            Some(pixels) if pixels > max_pixels => {
+                match format.decode_scaled(data, max_pixels, quality, self.tiers) {
+                    Err(DecodeError::TooLarge) if over_budget => format.decode_capped(
+                        data,
+                        page,
+                        QualityTier::Preview,
+                        self.tiers,
+                        max_pixels,
+                    )?,
+                    decoded => decoded?,
+                }
+            }
+            _ => format.decode_capped(data, page, quality, self.tiers, max_pixels)?,
+        };
+        if over_budget {
+            image = image.with_over_budget();
+        }
 
-        let x_ratio = (src_w as f64 - 1.0) / dst_w.max(1) as f64;
-        let y_ratio = (src_h as f64 - 1.0) / dst_h.max(1) as f64;
+        if self.color_management {
+            let transform = color::embedded_profile(data).and_then(|icc| Transform::from_icc(&icc));
+            if let Some(transform) = &transform {
+                transform.apply(&mut image.pixels);
+                for frame in &mut image.extra_frames {
+                    transform.apply(frame);
+                }
+            }
+            meta.set_color_profile(transform.map(|t| t.name));
+        }
+        Ok(image)
+    }
 
-        for y in 0..dst_h {
-            let src_y = y as f64 * y_ratio;
-            let y0 = src_y.floor() as usize;
-            let y1 = (y0 + 1).min(src_h - 1);
-            let y_frac = src_y - y0 as f64;
+    /// Look for an embedded thumbnail in the start of a file
+    fn embedded_thumbnail(&self, meta: &ImageMeta) -> Option<ImageData> {
+        let head = read_source(&meta.source, HEAD_LEN as u64).ok()?;
+        self.detect(meta.name(), &head)?
+            .embedded_thumbnail(&head, self.tiers)
+    }
 
-            for x in 0..dst_w {
-                let src_x = x as f64 * x_ratio;
-                let x0 = src_x.floor() as usize;
-                let x1 = (x0 + 1).min(src_w - 1);
-                let x_frac = src_x - x0 as f64;
+    /// Read an image's full dimensions from its header, without decoding.
+    /// Only the first page of a multi-page file is measured.
+    pub fn read_dimensions(&self, meta: &ImageMeta) -> Option<(u32, u32)> {
+        if meta.page.is_some_and(|p| p.index > 0) {
+            return None;
+        }
+        let head = read_source(&meta.source, HEAD_LEN as u64).ok()?;
+        self.detect(meta.name(), &head)?.dimensions(&head)
+    }
 
-                let idx00 = (y0 * src_w + x0) * 4;
-                let idx01 = (y0 * src_w + x1) * 4;
-                let idx10 = (y1 * src_w + x0) * 4;
-                let idx11 = (y1 * src_w + x1) * 4;
-                let dst_idx = (y * dst_w + x) * 4;
+    /// Read what an image's header says - format, dimensions, color type
+    /// and page count - without decoding any pixels
+    pub fn probe(&self, meta: &ImageMeta) -> Result<Probe, DecodeError> {
+        use image::ImageDecoder;
 
-                for c in 0..4 {
-                    let v00 = data.get(idx00 + c).copied().unwrap_or(0) as f64;
-                    let v01 = data.get(idx01 + c).copied().unwrap_or(0) as f64;
-                    let v10 = data.get(idx10 + c).copied().unwrap_or(0) as f64;
-                    let v11 = data.get(idx11 + c).copied().unwrap_or(0) as f64;
+        let data = read_source(&meta.source, u64::MAX).map_err(DecodeError::Io)?;
+        let format = self
+            .detect(meta.name(), &data)
+            .ok_or(DecodeError::UnsupportedFormat)?;
+        let header = image::ImageReader::new(std::io::Cursor::new(&data))
+            .with_guessed_format()
+            .ok()
+            .and_then(|reader| reader.into_decoder().ok());
 
-                    let v0 = v00 * (1.0 - x_frac) + v01 * x_frac;
-                    let v1 = v10 * (1.0 - x_frac) + v11 * x_frac;
-                    let v = v0 * (1.0 - y_frac) + v1 * y_frac;
+        let dimensions = format
+            .dimensions(&data)
+            .or_else(|| header.as_ref().map(|h| h.dimensions()))
+            .ok_or_else(|| DecodeError::Corrupt("unreadable image header".to_string()))?;
+        let pages = match &meta.source {
+            ImageSource::File(path) => format.page_count(path),
+            ImageSource::ArchiveEntry { .. } => 1,
+        };
+        Ok(Probe {
+            format: format.name(),
+            dimensions,
+            color: header.map(|h| format!("{:?}", h.original_color_type())),
+            pages,
+        })
+    }
 
-                    result[dst_idx + c] = v.round() as u8;
+    /// Build store metadata for a path list, expanding multi-page files
+    /// into one entry per page so each page is browsed like its own image,
+    /// and archives into one entry per image inside
+    pub fn expand_pages(&self, paths: Vec<PathBuf>) -> Vec<ImageMeta> {
+        let mut metas = Vec::with_capacity(paths.len());
+        for path in paths {
+            if archive::is_archive(&path) {
+                match archive::list_images(&path, self) {
+                    Ok(names) => metas.extend(
+                        names
+                            .into_iter()
+                            .map(|name| ImageMeta::archive_entry(path.clone(), name)),
+                    ),
+                    Err(err) => eprintln!("Warning: {}", err),
                 }
+                continue;
+            }
+            let count = self.by_extension(&path).map_or(1, |f| f.page_count(&path));
+            if count > 1 {
+                metas.extend(
+                    (0..count).map(|page| ImageMeta::new(path.clone()).with_page(page, count)),
+                );
+            } else {
+                metas.push(ImageMeta::new(path));
             }
         }
+        metas
+    }
+}
 
-        result
+/// Read up to `limit` bytes of an image, wherever it is stored
+fn read_source(source: &ImageSource, limit: u64) -> std::io::Result<Vec<u8>> {
+    use std::io::Read;
+
+    match source {
+        ImageSource::File(path) if limit == u64::MAX => fs::read(path),
+        ImageSource::File(path) => {
+            let mut data = Vec::new();
+            fs::File::open(path)?.take(limit).read_to_end(&mut data)?;
+            Ok(data)
+        }
+        ImageSource::ArchiveEntry { archive, name } => archive::read_entry(archive, name, limit),
     }
 }
 
+/// Read up to `len` bytes from the start of a file
+fn read_head(path: &Path, len: usize) -> std::io::Result<Vec<u8>> {
+    use std::io::Read;
+
+    let mut head = Vec::with_capacity(len);
+    fs::File::open(path)?
+        .take(len as u64)
+        .read_to_end(&mut head)?;
+    Ok(head)
+}
+
 impl Default for Decoder {
     fn default() -> Self {
         Self::new()
     }
 }
 
-/// Scan a directory for supported images
-pub fn scan_directory(dir: &Path, decoder: &Decoder) -> Vec<std::path::PathBuf> {
+/// Scan a directory for supported images, descending up to `max_depth` levels
+/// (1 = only the directory itself). Symlinks are not followed, so link cycles
+/// cannot cause infinite recursion.
+pub fn scan_directory(dir: &Path, decoder: &Decoder, max_depth: usize) -> Vec<PathBuf> {
     let mut images: Vec<_> = walkdir::WalkDir::new(dir)
-        .max_depth(1)
+        .max_depth(max_depth)
+        .follow_links(false)
         .into_iter()
         .filter_map(|e| e.ok())
         .filter(|e| e.file_type().is_file())
@@ -183,6 +545,303 @@ pub fn scan_directory(dir: &Path, decoder: &Decoder) -> Vec<std::path::PathBuf>
     images
 }
 
+/// Directories beside `dir` in its parent, `dir` among them, in natural
+/// order. Hidden directories are left out.
+pub fn sibling_directories(dir: &Path) -> Vec<PathBuf> {
+    let Some(parent) = dir.parent() else {
+        return Vec::new();
+    };
+    let Ok(entries) = fs::read_dir(parent) else {
+        return Vec::new();
+    };
+    let mut dirs: Vec<PathBuf> = entries
+        .filter_map(|e| e.ok())
+        .filter(|e| !e.file_name().to_string_lossy().starts_with('.'))
+        .map(|e| e.path())
+        .filter(|p| p.is_dir())
+        .collect();
+
+    dirs.sort_by(|a, b| natural_cmp(a.as_os_str(), b.as_os_str()));
+    dirs
+}
+
+/// An image's header details, as read by `Decoder::probe`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Probe {
+    /// Format name, e.g. "PNG"
+    pub format: &'static str,
+    /// Full width and height (of the first page)
+    pub dimensions: (u32, u32),
+    /// Pixel layout as stored, e.g. "Rgb8", if the header says
+    pub color: Option<String>,
+    /// Number of pages (1 for single-image formats)
+    pub pages: usize,
+}
+
+/// File-level and EXIF details shown in the info overlay
+#[derive(Debug, Clone, Default)]
+pub struct ImageInfo {
+    /// File size in bytes
+    pub file_size: Option<u64>,
+    /// Camera make and model
+    pub camera: Option<String>,
+    /// Exposure time, e.g. "1/200 s"
+    pub exposure: Option<String>,
+    /// Aperture, e.g. "f/2.8"
+    pub aperture: Option<String>,
+    /// ISO sensitivity
+    pub iso: Option<String>,
+    /// Focal length, e.g. "50 mm"
+    pub focal_length: Option<String>,
+    /// Capture date and time
+    pub date: Option<String>,
+}
+
+impl ImageInfo {
+    /// Overlay lines; fields that are missing are left out
+    pub fn lines(&self, name: &str, dimensions: Option<(u32, u32)>) -> Vec<String> {
+        let mut lines = vec![name.to_string()];
+        if let Some((w, h)) = dimensions {
+            lines.push(format!("{} x {}", w, h));
+        }
+        if let Some(size) = self.file_size {
+            lines.push(format_file_size(size));
+        }
+
+        let labelled = [
+            ("Camera", &self.camera),
+            ("Date", &self.date),
+            ("Exposure", &self.exposure),
+            ("Aperture", &self.aperture),
+            ("ISO", &self.iso),
+            ("Focal length", &self.focal_length),
+        ];
+        for (label, value) in labelled {
+            if let Some(value) = value {
+                lines.push(format!("{}: {}", label, value));
+            }
+        }
+        lines
+    }
+}
+
+/// Human-readable file size
+pub fn format_file_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+/// Read file size and EXIF fields. Files without EXIF (or unreadable ones)
+/// just get whatever file-level information is available.
+pub fn read_image_info(source: &ImageSource) -> ImageInfo {
+    let (file_size, exif) = match source {
+        ImageSource::File(path) => (
+            fs::metadata(path).ok().map(|m| m.len()),
+            fs::File::open(path).ok().and_then(|file| {
+                exif::Reader::new()
+                    .read_from_container(&mut std::io::BufReader::new(file))
+                    .ok()
+            }),
+        ),
+        // Entries are compressed, so there is no reading just the header
+        ImageSource::ArchiveEntry { .. } => match read_source(source, u64::MAX) {
+            Ok(data) => (
+                Some(data.len() as u64),
+                exif::Reader::new()
+                    .read_from_container(&mut std::io::Cursor::new(data))
+                    .ok(),
+            ),
+            Err(_) => (None, None),
+        },
+    };
+    let mut info = ImageInfo {
+        file_size,
+        ..ImageInfo::default()
+    };
+    let Some(exif) = exif else {
+        return info;
+    };
+
+    let text = |tag: exif::Tag| -> Option<String> {
+        let field = exif.get_field(tag, exif::In::PRIMARY)?;
+        let value = match &field.value {
+            exif::Value::Ascii(parts) => parts
+                .first()
+                .map(|bytes| String::from_utf8_lossy(bytes).trim().to_string())?,
+            _ => field.display_value().with_unit(&exif).to_string(),
+        };
+        (!value.is_empty()).then_some(value)
+    };
+
+    info.camera = match (text(exif::Tag::Make), text(exif::Tag::Model)) {
+        // Models often repeat the make ("Canon" + "Canon EOS R5")
+        (Some(make), Some(model)) if model.starts_with(&make) => Some(model),
+        (Some(make), Some(model)) => Some(format!("{} {}", make, model)),
+        (make, model) => model.or(make),
+    };
+    info.exposure = text(exif::Tag::ExposureTime);
+    info.aperture = text(exif::Tag::FNumber);
+    info.iso = text(exif::Tag::PhotographicSensitivity);
+    info.focal_length = text(exif::Tag::FocalLength);
+    info.date = exif
+        .get_field(exif::Tag::DateTimeOriginal, exif::In::PRIMARY)
+        .map(|field| field.display_value().to_string());
+
+    info
+}
+
+/// Order in which a collection of images is browsed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum SortOrder {
+    /// Plain lexicographic path order
+    #[default]
+    Name,
+    /// Numeric-aware path order (img2 before img10)
+    Natural,
+    /// Modification time, oldest first
+    Mtime,
+    /// File size, smallest first
+    Size,
+    /// Keep the order the paths were found in
+    None,
+}
+
+/// Sort paths in place by the given order, optionally reversed.
+///
+/// Mtime and size need a stat per file; entries whose metadata can't be read
+/// are placed after the others in name order.
+pub fn sort_paths(paths: &mut [PathBuf], order: SortOrder, reverse: bool) {
+    match order {
+        SortOrder::Name => paths.sort(),
+        SortOrder::Natural => paths.sort_by(|a, b| natural_cmp(a.as_os_str(), b.as_os_str())),
+        SortOrder::Mtime => sort_by_metadata(paths, |m| m.modified().ok()),
+        SortOrder::Size => sort_by_metadata(paths, |m| Some(m.len())),
+        SortOrder::None => {}
+    }
+
+    if reverse {
+        paths.reverse();
+    }
+}
+
+/// Sort by a metadata-derived key, falling back to name order on stat failure
+fn sort_by_metadata<K: Ord>(paths: &mut [PathBuf], key: impl Fn(&fs::Metadata) -> Option<K>) {
+    let mut keyed: Vec<(Option<K>, PathBuf)> = paths
+        .iter()
+        .map(|p| (fs::metadata(p).ok().and_then(|m| key(&m)), p.clone()))
+        .collect();
+
+    keyed.sort_by(|(ka, a), (kb, b)| match (ka, kb) {
+        (Some(ka), Some(kb)) => ka.cmp(kb).then_with(|| a.cmp(b)),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => a.cmp(b),
+    });
+
+    for (slot, (_, path)) in paths.iter_mut().zip(keyed) {
+        *slot = path;
+    }
+}
+
+/// Compare two strings treating runs of digits as numbers.
+///
+/// Digit runs compare by numeric value (leading zeros ignored, with fewer
+/// zeros first on a tie); everything else compares character by character.
+pub fn natural_cmp(a: &std::ffi::OsStr, b: &std::ffi::OsStr) -> Ordering {
+    let a = a.to_string_lossy();
+    let b = b.to_string_lossy();
+    let mut a_chars = a.chars().peekable();
+    let mut b_chars = b.chars().peekable();
+
+    loop {
+        match (a_chars.peek().copied(), b_chars.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(ca), Some(cb)) if ca.is_ascii_digit() && cb.is_ascii_digit() => {
+                let run_a = take_digits(&mut a_chars);
+                let run_b = take_digits(&mut b_chars);
+                let ord = compare_digit_runs(&run_a, &run_b);
+                if ord != Ordering::Equal {
+                    return ord;
+                }
+            }
+            (Some(ca), Some(cb)) => {
+                if ca != cb {
+                    return ca.cmp(&cb);
+                }
+                a_chars.next();
+                b_chars.next();
+            }
+        }
+    }
+}
+
+/// Consume a run of ASCII digits
+fn take_digits(chars: &mut std::iter::Peekable<std::str::Chars>) -> String {
+    let mut run = String::new();
+    while let Some(c) = chars.peek().copied().filter(char::is_ascii_digit) {
+        run.push(c);
+        chars.next();
+    }
+    run
+}
+
+/// Compare digit runs numerically without overflowing on long runs
+fn compare_digit_runs(a: &str, b: &str) -> Ordering {
+    let a_trim = a.trim_start_matches('0');
+    let b_trim = b.trim_start_matches('0');
+    a_trim
+        .len()
+        .cmp(&b_trim.len())
+        .then_with(|| a_trim.cmp(b_trim))
+        .then_with(|| a.len().cmp(&b.len()))
+}
+
+/// Read a newline-separated list of image paths, preserving the given order.
+///
+/// Unsupported files are filtered out silently; empty lines and paths that
+/// don't exist are skipped and counted. Returns (paths, skipped count).
+pub fn read_path_list<R: BufRead>(reader: R, decoder: &Decoder) -> (Vec<PathBuf>, usize) {
+    let mut paths = Vec::new();
+    let mut skipped = 0;
+
+    for line in reader.lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => {
+                skipped += 1;
+                continue;
+            }
+        };
+
+        let trimmed = line.trim_end_matches('\r');
+        if trimmed.is_empty() {
+            skipped += 1;
+            continue;
+        }
+
+        let path = PathBuf::from(trimmed);
+        if !path.is_file() {
+            skipped += 1;
+        } else if decoder.is_supported(&path) {
+            paths.push(path);
+        }
+    }
+
+    (paths, skipped)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -199,16 +858,357 @@ mod tests {
     }
 
     #[test]
-    fn test_resize() {
-        // 2x2 image, all red
-        let src = vec![
-            255, 0, 0, 255, 255, 0, 0, 255, 255, 0, 0, 255, 255, 0, 0, 255,
-        ];
-        let dst = Decoder::resize_bilinear(&src, 2, 2, 4, 4);
+    fn test_scan_depth() {
+        let root = std::env::temp_dir().join(format!("fiv_scan_depth_{}", std::process::id()));
+        let nested = root.join("sub");
+        fs::create_dir_all(&nested).unwrap();
+        fs::write(root.join("b.png"), b"").unwrap();
+        fs::write(nested.join("a.png"), b"").unwrap();
+        fs::write(nested.join("notes.txt"), b"").unwrap();
+
+        let decoder = Decoder::new();
+        let flat = scan_directory(&root, &decoder, 1);
+        assert_eq!(flat, vec![root.join("b.png")]);
+
+        // Recursive results are sorted by full path
+        let deep = scan_directory(&root, &decoder, usize::MAX);
+        assert_eq!(deep, vec![root.join("b.png"), nested.join("a.png")]);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_sibling_directories() {
+        let root = std::env::temp_dir().join(format!("fiv_siblings_{}", std::process::id()));
+        for name in ["day10", "day2", ".hidden", "day2/nested"] {
+            fs::create_dir_all(root.join(name)).unwrap();
+        }
+        fs::write(root.join("day1.png"), b"").unwrap();
+
+        // Files, hidden and nested directories are left out
+        assert_eq!(
+            sibling_directories(&root.join("day2")),
+            vec![root.join("day2"), root.join("day10")]
+        );
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_read_path_list() {
+        let root = std::env::temp_dir().join(format!("fiv_path_list_{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        let a = root.join("a.png");
+        let b = root.join("b.jpg");
+        let txt = root.join("c.txt");
+        for p in [&a, &b, &txt] {
+            fs::write(p, b"").unwrap();
+        }
+
+        // Order is preserved, not re-sorted
+        let input = format!(
+            "{}\n\n{}\n{}\n{}\n",
+            b.display(),
+            root.join("missing.png").display(),
+            txt.display(),
+            a.display()
+        );
+        let (paths, skipped) = read_path_list(std::io::Cursor::new(input), &Decoder::new());
+        assert_eq!(paths, vec![b, a]);
+        assert_eq!(skipped, 2);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_natural_cmp() {
+        let cmp = |a: &str, b: &str| natural_cmp(a.as_ref(), b.as_ref());
+
+        assert_eq!(cmp("img2.jpg", "img10.jpg"), Ordering::Less);
+        assert_eq!(cmp("img10.jpg", "img2.jpg"), Ordering::Greater);
+        assert_eq!(cmp("img2.jpg", "img2.jpg"), Ordering::Equal);
+        // Leading zeros: same value, shorter run first
+        assert_eq!(cmp("img02.jpg", "img2.jpg"), Ordering::Greater);
+        assert_eq!(cmp("img002.jpg", "img10.jpg"), Ordering::Less);
+        // Mixed digit/letter runs
+        assert_eq!(cmp("a1b2", "a1b10"), Ordering::Less);
+        assert_eq!(cmp("a10b1", "a9b20"), Ordering::Greater);
+        assert_eq!(cmp("x1", "x1a"), Ordering::Less);
+        assert_eq!(cmp("2x", "x"), Ordering::Less);
+        // Runs longer than u64 don't overflow
+        assert_eq!(
+            cmp("99999999999999999999999", "100000000000000000000000"),
+            Ordering::Less
+        );
+    }
+
+    #[test]
+    fn test_sort_paths() {
+        let mut paths: Vec<PathBuf> = ["img10.png", "img2.png", "img1.png"]
+            .iter()
+            .map(PathBuf::from)
+            .collect();
+
+        sort_paths(&mut paths, SortOrder::Natural, false);
+        assert_eq!(paths[0], PathBuf::from("img1.png"));
+        assert_eq!(paths[2], PathBuf::from("img10.png"));
+
+        sort_paths(&mut paths, SortOrder::Name, true);
+        assert_eq!(paths[0], PathBuf::from("img2.png"));
+
+        // Missing files can't be stat'ed and fall back to name order
+        sort_paths(&mut paths, SortOrder::Size, false);
+        assert_eq!(paths[0], PathBuf::from("img1.png"));
+        assert_eq!(paths[1], PathBuf::from("img10.png"));
+    }
+
+    #[test]
+    fn test_info_without_exif() {
+        let path = std::env::temp_dir().join(format!("fiv_info_{}.png", std::process::id()));
+        fs::write(&path, vec![0u8; 2048]).unwrap();
+
+        let info = read_image_info(&ImageSource::File(path.clone()));
+        assert_eq!(info.file_size, Some(2048));
+        assert!(info.camera.is_none());
+
+        // Only file-level lines when EXIF is missing
+        let lines = info.lines("a.png", Some((640, 480)));
+        assert_eq!(lines, vec!["a.png", "640 x 480", "2.0 KB"]);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_multi_page_tiff() {
+        use tiff::encoder::{colortype, TiffEncoder};
+
+        let root = std::env::temp_dir().join(format!("fiv_tiff_pages_{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        let doc = root.join("scan.tiff");
+        let still = root.join("still.png");
+        fs::write(&still, b"").unwrap();
+
+        let mut file = fs::File::create(&doc).unwrap();
+        let mut encoder = TiffEncoder::new(&mut file).unwrap();
+        encoder
+            .write_image::<colortype::Gray8>(2, 2, &[10; 4])
+            .unwrap();
+        encoder
+            .write_image::<colortype::RGB8>(3, 1, &[200, 100, 50].repeat(3))
+            .unwrap();
+        drop(file);
+
+        let decoder = Decoder::new();
+        let metas = decoder.expand_pages(vec![doc.clone(), still.clone()]);
+        assert_eq!(metas.len(), 3);
+        assert_eq!(metas[0].page.map(|p| (p.index, p.count)), Some((0, 2)));
+        assert_eq!(metas[1].page.map(|p| (p.index, p.count)), Some((1, 2)));
+        assert_eq!(metas[2].page, None);
+
+        // Later pages are decoded from their own directory
+        let page = decoder.decode(&metas[1], QualityTier::Full).unwrap();
+        assert_eq!((page.width, page.height), (3, 1));
+        assert_eq!(&page.pixels[..4], &[200, 100, 50, 255]);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_detect_format() {
+        let decoder = Decoder::new();
+        let tiff = b"II*\0\x08\0\0\0\0\0";
+        let cr2 = b"II*\0\x10\0\0\0CR\x02\0";
+        let name =
+            |path: &str, data: &[u8]| decoder.detect(Path::new(path), data).map(|f| f.name());
+
+        // Content beats a wrong extension
+        assert_eq!(name("a.jpg", b"\x89PNG\r\n\x1a\n"), Some("PNG"));
+        // Shared TIFF headers defer to the extension
+        assert_eq!(name("a.tif", tiff), Some("TIFF"));
+        #[cfg(feature = "raw")]
+        assert_eq!(name("a.nef", tiff), Some("camera RAW"));
+        assert_eq!(name("a", tiff), Some("TIFF"));
+        #[cfg(feature = "raw")]
+        assert_eq!(name("a", cr2), Some("camera RAW"));
+        // Unknown content falls back to the extension
+        assert_eq!(name("a.png", b"garbage"), Some("PNG"));
+        assert_eq!(name("a", b"garbage"), None);
+    }
+
+    #[test]
+    fn test_decode_by_content() {
+        let root = std::env::temp_dir().join(format!("fiv_sniff_{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+
+        // A PNG with the wrong extension
+        let png = root.join("mislabelled.jpg");
+        image::RgbaImage::from_pixel(3, 2, image::Rgba([1, 2, 3, 255]))
+            .save_with_format(&png, image::ImageFormat::Png)
+            .unwrap();
+
+        // Text pretending to be an image
+        let fake = root.join("notes.png");
+        fs::write(&fake, b"definitely not a png").unwrap();
+
+        // A JPEG with no extension at all
+        let jpeg = root.join("photo");
+        image::RgbImage::from_pixel(4, 4, image::Rgb([200, 200, 200]))
+            .save_with_format(&jpeg, image::ImageFormat::Jpeg)
+            .unwrap();
+
+        let decoder = Decoder::new();
+        let data = decoder
+            .decode(&ImageMeta::new(png.clone()), QualityTier::Full)
+            .unwrap();
+        assert_eq!((data.width, data.height), (3, 2));
+        assert_eq!(&data.pixels[..4], &[1, 2, 3, 255]);
+
+        let data = decoder
+            .decode(&ImageMeta::new(jpeg.clone()), QualityTier::Full)
+            .unwrap();
+        assert_eq!((data.width, data.height), (4, 4));
+
+        let err = decoder.decode(&ImageMeta::new(fake.clone()), QualityTier::Full);
+        assert!(matches!(err, Err(DecodeError::Corrupt(_))));
+
+        // Probing reads the header only
+        let probe = decoder.probe(&ImageMeta::new(png.clone())).unwrap();
+        assert_eq!((probe.format, probe.dimensions), ("PNG", (3, 2)));
+        assert_eq!(probe.color.as_deref(), Some("Rgba8"));
+        assert_eq!(probe.pages, 1);
+        let err = decoder.probe(&ImageMeta::new(fake.clone()));
+        assert!(matches!(err, Err(DecodeError::Corrupt(_))));
+        let err = decoder.decode(&ImageMeta::new(root.join("missing.png")), QualityTier::Full);
+        assert!(matches!(err, Err(DecodeError::Io(_))));
+
+        // Extension-less files are only scanned when sniffing
+        assert!(!decoder.is_supported(&jpeg));
+        assert!(Decoder::new().with_sniffing(true).is_supported(&jpeg));
+        assert_eq!(
+            scan_directory(&root, &Decoder::new().with_sniffing(true), 1),
+            vec![png.clone(), fake.clone(), jpeg.clone()]
+        );
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_pixel_cap() {
+        let root = std::env::temp_dir().join(format!("fiv_pixel_cap_{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        let png = root.join("big.png");
+        let bmp = root.join("big.bmp");
+        let image = image::RgbImage::from_pixel(64, 32, image::Rgb([9, 9, 9]));
+        image.save(&png).unwrap();
+        image.save(&bmp).unwrap();
+
+        let decoder = Decoder::new().with_max_pixels(512);
+
+        // The header alone gives the real size
+        assert_eq!(
+            decoder.read_dimensions(&ImageMeta::new(png.clone())),
+            Some((64, 32))
+        );
+
+        // Capped formats still show a reduced version
+        let data = decoder
+            .decode(&ImageMeta::new(png), QualityTier::Full)
+            .unwrap();
+        assert_eq!((data.width, data.height), (32, 16));
+        assert_eq!((data.full_width, data.full_height), (64, 32));
+
+        // Others are refused rather than decoded at full size
+        let err = decoder.decode(&ImageMeta::new(bmp), QualityTier::Full);
+        assert!(matches!(err, Err(DecodeError::TooLarge)));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_budget_cap() {
+        let image = image::RgbImage::from_pixel(64, 32, image::Rgb([9, 9, 9]));
+        let mut png = Vec::new();
+        let mut bmp = Vec::new();
+        image
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+        image
+            .write_to(&mut std::io::Cursor::new(&mut bmp), image::ImageFormat::Bmp)
+            .unwrap();
+        let decoder = Decoder::new();
+        let png_meta = ImageMeta::new("pano.png".into());
+        let bmp_meta = ImageMeta::new("pano.bmp".into());
+
+        // Room enough: decoded as usual
+        let data = decoder
+            .decode_bytes_within(&png_meta, &png, QualityTier::Full, 64 * 32)
+            .unwrap();
+        assert_eq!((data.width, data.height), (64, 32));
+        assert!(!data.over_budget);
+
+        // Shrunk while decoding, and final at that size
+        let data = decoder
+            .decode_bytes_within(&png_meta, &png, QualityTier::Full, 512)
+            .unwrap();
+        assert_eq!((data.width, data.height), (32, 16));
+        assert!(data.over_budget);
+        assert_eq!(data.effective_quality(), QualityTier::Full);
+
+        // Lower tiers fit anyway
+        let data = decoder
+            .decode_bytes_within(&png_meta, &png, QualityTier::Preview, 512)
+            .unwrap();
+        assert!(!data.over_budget);
+
+        // Formats that can't shrink while decoding give their preview
+        let data = decoder
+            .decode_bytes_within(&bmp_meta, &bmp, QualityTier::Full, 512)
+            .unwrap();
+        assert_eq!(data.quality, QualityTier::Preview);
+        assert!(data.over_budget);
+    }
+
+    /// A format whose decoder panics on every file
+    struct Explosive;
+
+    impl FormatDecoder for Explosive {
+        fn name(&self) -> &'static str {
+            "Explosive"
+        }
+
+        fn sniff(&self, _header: &[u8]) -> bool {
+            false
+        }
+
+        fn extensions(&self) -> &[&str] {
+            &["boom"]
+        }
+
+        fn decode(
+            &self,
+            data: &[u8],
+            _quality: QualityTier,
+            _sizes: TierSizes,
+        ) -> Result<ImageData, DecodeError> {
+            panic!("tripped over {} bytes", data.len())
+        }
+    }
+
+    #[test]
+    fn test_decoder_panic_is_an_error() {
+        let mut decoder = Decoder::new();
+        decoder.register(Box::new(Explosive));
+        let meta = ImageMeta::new("bad.boom".into());
+
+        let err = decoder.decode_bytes(&meta, b"data", QualityTier::Full);
+        match err {
+            Err(DecodeError::Corrupt(message)) => assert!(message.contains("decoder panicked")),
+            other => panic!("expected a corrupt image error, got {:?}", other),
+        }
+        assert_eq!(decoder.metrics().sample(Instant::now()).failed, 1);
 
-        // Should be 4x4, still mostly red
-        assert_eq!(dst.len(), 4 * 4 * 4);
-        // First pixel should be red
-        assert_eq!(&dst[0..4], &[255, 0, 0, 255]);
+        // The decoder still works afterwards
+        let err = decoder.decode_bytes(&meta, b"data", QualityTier::Full);
+        assert!(matches!(err, Err(DecodeError::Corrupt(_))));
     }
 }
@@ -0,0 +1,203 @@
+//! Exporting resized, re-encoded copies of images for sharing.
+//!
+//! `export` scales an image down to fit `export.max_dimension` and encodes
+//! it as JPEG, PNG or WebP into `export.dir`, appending a number rather
+//! than overwriting an existing file. Resizing and encoding a large image
+//! takes a while, so the viewer runs it on a background thread.
+
+use crate::config::{ExportConfig, ExportFormat};
+use crate::ops::claim_free_name;
+use crate::slot::ImageData;
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::PngEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::imageops::{self, FilterType};
+use image::{ExtendedColorType, ImageEncoder, RgbaImage};
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+/// Size to export a `width` x `height` image at: scaled down to fit
+/// `max_dimension` on its longest side (0 for no limit), never up
+pub fn export_dimensions(width: u32, height: u32, max_dimension: u32) -> (u32, u32) {
+    let longest = width.max(height);
+    if max_dimension == 0 || longest <= max_dimension {
+        return (width, height);
+    }
+    let scale = max_dimension as f64 / longest as f64;
+    let scaled = |side: u32| ((side as f64 * scale).round() as u32).max(1);
+    (scaled(width), scaled(height))
+}
+
+/// Name for the export of `original`: its stem, the size limit and the
+/// format's extension, e.g. `IMG_1234.2048px.jpg`
+fn export_name(original: &Path, config: &ExportConfig) -> String {
+    let stem = original
+        .file_stem()
+        .unwrap_or(original.as_os_str())
+        .to_string_lossy();
+    match config.max_dimension {
+        0 => format!("{}.export.{}", stem, config.format.extension()),
+        max => format!("{}.{}px.{}", stem, max, config.format.extension()),
+    }
+}
+
+/// Directory to export `original` into: `export.dir`, where a leading `~`
+/// stands for the home directory, or else the image's own directory
+fn target_dir(original: &Path, config: &ExportConfig) -> PathBuf {
+    let Some(dir) = &config.dir else {
+        return original.parent().unwrap_or(Path::new(".")).to_path_buf();
+    };
+    match (dir.strip_prefix("~"), std::env::var_os("HOME")) {
+        (Ok(rest), Some(home)) => Path::new(&home).join(rest),
+        _ => dir.clone(),
+    }
+}
+
+/// Export animation frame `frame_index` of `data`, decoded from
+/// `original`, as configured. Returns the new path.
+pub fn export(
+    data: &ImageData,
+    frame_index: usize,
+    original: &Path,
+    config: &ExportConfig,
+) -> Result<PathBuf, String> {
+    let source = RgbaImage::from_raw(data.width, data.height, data.frame(frame_index).to_vec())
+        .ok_or("image data is incomplete")?;
+    let (width, height) = export_dimensions(data.width, data.height, config.max_dimension);
+    let image = if (width, height) == (data.width, data.height) {
+        source
+    } else {
+        imageops::resize(&source, width, height, FilterType::Lanczos3)
+    };
+
+    let dir = target_dir(original, config);
+    fs::create_dir_all(&dir).map_err(|e| format!("cannot create {}: {}", dir.display(), e))?;
+    let target = claim_free_name(&dir, Path::new(&export_name(original, config)))
+        .map_err(|e| format!("cannot write to {}: {}", dir.display(), e))?;
+    match encode(&target, &image, config) {
+        Ok(()) => Ok(target),
+        Err(err) => {
+            let _ = fs::remove_file(&target);
+            Err(err)
+        }
+    }
+}
+
+/// Write `image` to `path` in the configured format
+fn encode(path: &Path, image: &RgbaImage, config: &ExportConfig) -> Result<(), String> {
+    let file = File::create(path).map_err(|e| e.to_string())?;
+    let writer = BufWriter::new(file);
+    let (width, height) = image.dimensions();
+    let result =
+        match config.format {
+            ExportFormat::Jpeg => JpegEncoder::new_with_quality(writer, config.quality)
+                .write_image(&flatten(image), width, height, ExtendedColorType::Rgb8),
+            ExportFormat::Png => {
+                PngEncoder::new(writer).write_image(image, width, height, ExtendedColorType::Rgba8)
+            }
+            ExportFormat::Webp => WebPEncoder::new_lossless(writer).write_image(
+                image,
+                width,
+                height,
+                ExtendedColorType::Rgba8,
+            ),
+        };
+    result.map_err(|e| e.to_string())
+}
+
+/// RGB pixels of `image` composited onto white, for formats without alpha
+pub fn flatten(image: &RgbaImage) -> Vec<u8> {
+    image
+        .pixels()
+        .flat_map(|pixel| {
+            let [r, g, b, a] = pixel.0;
+            let over_white =
+                |c: u8| ((c as u32 * a as u32 + 255 * (255 - a as u32) + 127) / 255) as u8;
+            [over_white(r), over_white(g), over_white(b)]
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::QualityTier;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("fiv_export_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_export_dimensions() {
+        // Landscape and portrait scale their longest side to the limit
+        assert_eq!(export_dimensions(6000, 4000, 2048), (2048, 1365));
+        assert_eq!(export_dimensions(4000, 6000, 2048), (1365, 2048));
+        // Small images and no limit keep their size
+        assert_eq!(export_dimensions(800, 600, 2048), (800, 600));
+        assert_eq!(export_dimensions(6000, 4000, 0), (6000, 4000));
+        // Extreme aspect ratios keep at least a pixel
+        assert_eq!(export_dimensions(10000, 1, 100), (100, 1));
+    }
+
+    #[test]
+    fn test_export_name() {
+        let mut config = ExportConfig::default();
+        assert_eq!(
+            export_name(Path::new("/photos/IMG_1234.CR2"), &config),
+            "IMG_1234.2048px.jpg"
+        );
+        config.max_dimension = 0;
+        config.format = ExportFormat::Webp;
+        assert_eq!(export_name(Path::new("a.png"), &config), "a.export.webp");
+    }
+
+    #[test]
+    fn test_export_appends_suffix_on_collision() {
+        let root = temp_dir("collide");
+        let original = root.join("photo.png");
+        let config = ExportConfig {
+            dir: Some(root.join("out")),
+            max_dimension: 4,
+            format: ExportFormat::Png,
+            quality: 85,
+        };
+        let data = ImageData::new(vec![200; 8 * 2 * 4], 8, 2, QualityTier::Full);
+
+        let first = export(&data, 0, &original, &config).unwrap();
+        assert_eq!(first, root.join("out/photo.4px.png"));
+        let second = export(&data, 0, &original, &config).unwrap();
+        assert_eq!(second, root.join("out/photo.4px_1.png"));
+
+        let exported = image::open(&first).unwrap();
+        assert_eq!((exported.width(), exported.height()), (4, 1));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_jpeg_export() {
+        let root = temp_dir("jpeg");
+        let config = ExportConfig {
+            dir: None,
+            ..ExportConfig::default()
+        };
+        let data = ImageData::new(vec![0; 3 * 3 * 4], 3, 3, QualityTier::Full);
+
+        // Next to the original, whose name isn't taken by the export
+        let path = export(&data, 0, &root.join("clear.png"), &config).unwrap();
+        assert_eq!(path, root.join("clear.2048px.jpg"));
+        // Fully transparent pixels come out white
+        let exported = image::open(&path).unwrap().to_rgb8();
+        assert!(exported.pixels().all(|p| p.0.iter().all(|&c| c > 250)));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_flatten() {
+        let image = RgbaImage::from_raw(2, 1, vec![10, 20, 30, 255, 0, 0, 0, 128]).unwrap();
+        assert_eq!(flatten(&image), vec![10, 20, 30, 127, 127, 127]);
+    }
+}
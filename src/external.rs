@@ -0,0 +1,386 @@
+//! Handing images to other programs - setting the desktop wallpaper, and
+//! the `[hooks]` commands run as images are shown.
+//!
+//! Commands are shell templates run with `sh -c`: `{path}` stands for the
+//! image's absolute path and `{uri}` for its `file://` URI, both quoted for
+//! the shell. Unless `external.wallpaper_command` says otherwise, the
+//! command is picked from `XDG_CURRENT_DESKTOP`. Hooks also have `{index}`
+//! and `{total}`.
+
+use crate::config::HooksConfig;
+use std::io;
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Wallpaper commands for desktops named in `XDG_CURRENT_DESKTOP`
+/// (lowercase)
+const DESKTOP_WALLPAPER_COMMANDS: &[(&str, &str)] = &[
+    (
+        "gnome",
+        "gsettings set org.gnome.desktop.background picture-uri {uri} && \
+         gsettings set org.gnome.desktop.background picture-uri-dark {uri}",
+    ),
+    (
+        "unity",
+        "gsettings set org.gnome.desktop.background picture-uri {uri}",
+    ),
+    (
+        "budgie",
+        "gsettings set org.gnome.desktop.background picture-uri {uri}",
+    ),
+    (
+        "cinnamon",
+        "gsettings set org.cinnamon.desktop.background picture-uri {uri}",
+    ),
+    (
+        "mate",
+        "gsettings set org.mate.background picture-filename {path}",
+    ),
+    ("kde", "plasma-apply-wallpaperimage {path}"),
+    ("sway", "swaymsg output '*' bg {path} fill"),
+];
+
+/// Command for other X11 desktops and window managers
+const FALLBACK_WALLPAPER_COMMAND: &str = "feh --bg-fill {path}";
+
+/// Wallpaper command template: the configured one, else the one for the
+/// first desktop in `desktop` (an `XDG_CURRENT_DESKTOP` value) that has
+/// one, else `feh` under X11. None if nothing fits.
+pub fn wallpaper_command(
+    configured: Option<&str>,
+    desktop: Option<&str>,
+    x11: bool,
+) -> Option<String> {
+    if let Some(command) = configured {
+        return Some(command.to_string());
+    }
+    let known = desktop
+        .into_iter()
+        .flat_map(|d| d.split(':'))
+        .find_map(|name| {
+            let name = name.trim().to_lowercase();
+            DESKTOP_WALLPAPER_COMMANDS
+                .iter()
+                .find(|(desktop, _)| name == *desktop)
+                .map(|(_, command)| command.to_string())
+        });
+    known.or_else(|| x11.then(|| FALLBACK_WALLPAPER_COMMAND.to_string()))
+}
+
+/// The wallpaper command template for this session
+pub fn session_wallpaper_command(configured: Option<&str>) -> Option<String> {
+    let desktop = std::env::var("XDG_CURRENT_DESKTOP").ok();
+    let x11 = std::env::var_os("DISPLAY").is_some_and(|d| !d.is_empty());
+    wallpaper_command(configured, desktop.as_deref(), x11)
+}
+
+/// Set the image at `path` (absolute) as the wallpaper with a command
+/// template. Waits for the command, so run it off the main thread.
+pub fn set_wallpaper(path: &Path, template: &str) -> Result<(), String> {
+    run_shell(&fill_template(template, path))
+}
+
+/// Run a shell command and wait for it, failing with the last line of its
+/// error output
+fn run_shell(command: &str) -> Result<(), String> {
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| format!("cannot run sh: {}", e))?;
+    if output.status.success() {
+        return Ok(());
+    }
+    // The last line of the error output usually says what went wrong
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    match stderr.lines().rev().find(|line| !line.trim().is_empty()) {
+        Some(line) => Err(line.trim().to_string()),
+        None => Err(format!("command failed ({})", output.status)),
+    }
+}
+
+/// A `[hooks]` command
+pub struct Hook {
+    /// Its config key, for warnings
+    name: &'static str,
+    template: String,
+    /// Whether a failure has been reported, so later ones aren't
+    reported: Arc<AtomicBool>,
+}
+
+impl Hook {
+    /// The command configured under `name`, unless it's unset or blank
+    fn new(name: &'static str, template: Option<&str>) -> Option<Self> {
+        let template = template.filter(|t| !t.trim().is_empty())?;
+        Some(Self {
+            name,
+            template: template.to_string(),
+            reported: Arc::new(AtomicBool::new(false)),
+        })
+    }
+
+    /// Run the command for the image at `path` (absolute), number `index`
+    /// (from 1) of `total`, without waiting for it. A thread waits for it
+    /// to exit, to report a failure.
+    pub fn run(&self, path: &Path, index: usize, total: usize) {
+        let name = self.name;
+        let reported = Arc::clone(&self.reported);
+        // Once is enough; the next image would likely fail the same
+        let report = move |err: String| {
+            if !reported.swap(true, Ordering::Relaxed) {
+                eprintln!(
+                    "Warning: hooks.{} failed: {} (further failures not reported)",
+                    name, err
+                );
+            }
+        };
+        let mut child = match self.spawn(path, index, total) {
+            Ok(child) => child,
+            Err(err) => return report(format!("cannot run sh: {}", err)),
+        };
+        std::thread::spawn(move || match child.wait() {
+            Ok(status) if status.success() => {}
+            Ok(status) => report(format!("command failed ({})", status)),
+            Err(err) => report(err.to_string()),
+        });
+    }
+
+    /// Start the command without waiting for it, for a hook run on the way
+    /// out, when a thread wouldn't live long enough to see it finish
+    pub fn start(&self, path: &Path, index: usize, total: usize) {
+        if let Err(err) = self.spawn(path, index, total) {
+            eprintln!(
+                "Warning: hooks.{} failed: cannot run sh: {}",
+                self.name, err
+            );
+        }
+    }
+
+    /// Start the command, its output discarded and its errors going to
+    /// ours
+    fn spawn(&self, path: &Path, index: usize, total: usize) -> io::Result<Child> {
+        Command::new("sh")
+            .arg("-c")
+            .arg(fill_hook(&self.template, path, index, total))
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .spawn()
+    }
+}
+
+/// When `hooks.on_image_change` runs: once an image has been shown for the
+/// debounce, and not again until another image has had it run
+#[derive(Debug, Default)]
+pub struct ImageHookTimer {
+    /// Slot id of the image shown, and when the hook is due for it
+    due: Option<(usize, Instant)>,
+    /// Slot id of the image the hook last ran for
+    ran_for: Option<usize>,
+}
+
+impl ImageHookTimer {
+    /// Whether the hook should run now for the image with slot id `id`,
+    /// shown at least since the last call
+    pub fn due(&mut self, id: usize, debounce: Duration, now: Instant) -> bool {
+        if self.ran_for == Some(id) {
+            self.due = None;
+            return false;
+        }
+        match self.due {
+            Some((shown, due)) if shown == id => {
+                if now < due {
+                    return false;
+                }
+                self.due = None;
+                self.ran_for = Some(id);
+                true
+            }
+            _ => {
+                self.due = Some((id, now + debounce));
+                false
+            }
+        }
+    }
+
+    /// When the hook is next due, if the image shown stays
+    pub fn deadline(&self) -> Option<Instant> {
+        self.due.map(|(_, due)| due)
+    }
+}
+
+/// The configured hooks
+pub struct Hooks {
+    pub on_image_change: Option<Hook>,
+    pub on_start: Option<Hook>,
+    pub on_quit: Option<Hook>,
+}
+
+impl Hooks {
+    pub fn from_config(config: &HooksConfig) -> Self {
+        Self {
+            on_image_change: Hook::new("on_image_change", config.on_image_change.as_deref()),
+            on_start: Hook::new("on_start", config.on_start.as_deref()),
+            on_quit: Hook::new("on_quit", config.on_quit.as_deref()),
+        }
+    }
+}
+
+/// A hook's command template with the image's path, number and the total
+/// filled in
+fn fill_hook(template: &str, path: &Path, index: usize, total: usize) -> String {
+    // Numbers first, so a path that happens to contain `{total}` stays as is
+    let numbered = template
+        .replace("{index}", &index.to_string())
+        .replace("{total}", &total.to_string());
+    fill_template(&numbered, path)
+}
+
+/// A command template with `{path}` and `{uri}` filled in
+fn fill_template(template: &str, path: &Path) -> String {
+    let path_text = path.to_string_lossy();
+    template
+        .replace("{uri}", &shell_quote(&file_uri(&path_text)))
+        .replace("{path}", &shell_quote(&path_text))
+}
+
+/// `text` as a single shell word
+fn shell_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', r"'\''"))
+}
+
+/// `file://` URI of an absolute path, percent-encoding anything beyond
+/// unreserved characters and slashes
+fn file_uri(path: &str) -> String {
+    let mut uri = String::from("file://");
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                uri.push(byte as char)
+            }
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    uri
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wallpaper_command() {
+        // The configured command wins
+        assert_eq!(
+            wallpaper_command(Some("setbg {path}"), Some("GNOME"), true).unwrap(),
+            "setbg {path}"
+        );
+        // Desktops are matched case-insensitively, the first known one
+        assert!(wallpaper_command(None, Some("ubuntu:GNOME"), false)
+            .unwrap()
+            .contains("org.gnome.desktop"));
+        assert!(wallpaper_command(None, Some("KDE"), false)
+            .unwrap()
+            .starts_with("plasma-apply-wallpaperimage"));
+        // Unknown desktops fall back to feh under X11 only
+        assert_eq!(
+            wallpaper_command(None, Some("i3"), true).unwrap(),
+            FALLBACK_WALLPAPER_COMMAND
+        );
+        assert_eq!(wallpaper_command(None, Some("i3"), false), None);
+        assert_eq!(wallpaper_command(None, None, false), None);
+    }
+
+    #[test]
+    fn test_fill_template() {
+        let path = Path::new("/home/me/it's here.jpg");
+        assert_eq!(
+            fill_template("feh --bg-fill {path}", path),
+            r"feh --bg-fill '/home/me/it'\''s here.jpg'"
+        );
+        assert_eq!(
+            fill_template("set {uri}", path),
+            "set 'file:///home/me/it%27s%20here.jpg'"
+        );
+    }
+
+    #[test]
+    fn test_fill_hook() {
+        let path = Path::new("/photos/{total}.jpg");
+        assert_eq!(
+            fill_hook("log {path} {index}/{total}", path, 3, 40),
+            "log '/photos/{total}.jpg' 3/40"
+        );
+        assert!(Hook::new("on_start", Some("  ")).is_none());
+        assert!(Hook::new("on_start", None).is_none());
+    }
+
+    #[test]
+    fn test_image_hook_timer() {
+        let debounce = Duration::from_millis(300);
+        let start = Instant::now();
+        let at = |ms: u64| start + Duration::from_millis(ms);
+        let mut timer = ImageHookTimer::default();
+
+        // An image passed through quickly never has it run
+        assert!(!timer.due(1, debounce, at(0)));
+        assert_eq!(timer.deadline(), Some(at(300)));
+        assert!(!timer.due(1, debounce, at(200)));
+        assert!(!timer.due(2, debounce, at(250)));
+        assert!(!timer.due(2, debounce, at(500)));
+
+        // One that stays has it run once
+        assert!(timer.due(2, debounce, at(550)));
+        assert_eq!(timer.deadline(), None);
+        assert!(!timer.due(2, debounce, at(900)));
+        assert!(!timer.due(2, debounce, at(5000)));
+
+        // Glancing at another and back isn't a change
+        assert!(!timer.due(3, debounce, at(6000)));
+        assert!(!timer.due(2, debounce, at(6100)));
+        assert!(!timer.due(2, debounce, at(7000)));
+
+        // Staying on another and coming back is
+        assert!(!timer.due(3, debounce, at(8000)));
+        assert!(timer.due(3, debounce, at(8300)));
+        assert!(!timer.due(2, debounce, at(9000)));
+        assert!(timer.due(2, debounce, at(9300)));
+    }
+
+    #[test]
+    fn test_hook_runs_detached() {
+        let dir = std::env::temp_dir().join(format!("fiv_hook_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let done = dir.join("done");
+        let template = format!(
+            "sleep 0.2; echo {{index}} > '{0}.tmp' && mv '{0}.tmp' '{0}'",
+            done.display()
+        );
+        let hook = Hook::new("on_image_change", Some(&template)).unwrap();
+
+        // Returns before the command finishes
+        hook.run(Path::new("/photos/a.jpg"), 3, 9);
+        assert!(!done.exists());
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !done.exists() && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        assert_eq!(std::fs::read_to_string(&done).unwrap(), "3\n");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_set_wallpaper_reports_failure() {
+        let path = Path::new("/tmp/wallpaper.jpg");
+        assert!(set_wallpaper(path, "test {path} = /tmp/wallpaper.jpg").is_ok());
+        assert_eq!(
+            set_wallpaper(path, "echo 'no such output' >&2; exit 1"),
+            Err("no such output".to_string())
+        );
+        assert!(set_wallpaper(path, "exit 3").unwrap_err().contains('3'));
+    }
+}
@@ -0,0 +1,128 @@
+//! Name filters - narrowing the collection to matching file names.
+//!
+//! A pattern with `*` or `?` is a glob matched against the whole file name
+//! (`*.png`, `IMG_00??.*`); anything else matches the names containing it.
+//! Both ignore case. Only the file name counts, not its directory.
+
+use crate::slot::ImageMeta;
+
+/// A typed filter, ready to match file names
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NameFilter {
+    /// The pattern as typed
+    pattern: String,
+    /// Lowercased pattern characters, for matching
+    chars: Vec<char>,
+    /// Whether the pattern is a glob rather than a substring
+    glob: bool,
+}
+
+impl NameFilter {
+    /// Filter for a typed pattern; None if it's empty
+    pub fn new(pattern: &str) -> Option<Self> {
+        if pattern.is_empty() {
+            return None;
+        }
+        Some(Self {
+            pattern: pattern.to_string(),
+            chars: pattern.to_lowercase().chars().collect(),
+            glob: pattern.contains(['*', '?']),
+        })
+    }
+
+    /// The pattern as typed
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+
+    /// Whether a file name matches
+    pub fn matches(&self, name: &str) -> bool {
+        let name: Vec<char> = name.to_lowercase().chars().collect();
+        if self.glob {
+            glob_match(&self.chars, &name)
+        } else {
+            name.windows(self.chars.len())
+                .any(|part| part == self.chars)
+        }
+    }
+
+    /// Whether an image's file name (or archive entry name) matches
+    pub fn matches_meta(&self, meta: &ImageMeta) -> bool {
+        let name = meta.name();
+        self.matches(
+            &name
+                .file_name()
+                .unwrap_or(name.as_os_str())
+                .to_string_lossy(),
+        )
+    }
+}
+
+/// Whether `text` matches a glob where `*` stands for any run of
+/// characters and `?` for any one
+fn glob_match(pattern: &[char], text: &[char]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    // Where the last `*` was, and where in the text it would next resume
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                // Let the star take one more character and try again
+                Some((star_p, star_t)) => {
+                    p = star_p + 1;
+                    t = star_t + 1;
+                    star = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn matches(pattern: &str, name: &str) -> bool {
+        NameFilter::new(pattern).unwrap().matches(name)
+    }
+
+    #[test]
+    fn test_substring() {
+        assert!(matches("beach", "2023_Beach_04.jpg"));
+        assert!(matches("JPG", "a.jpg"));
+        assert!(!matches("beach", "mountain.jpg"));
+        assert!(NameFilter::new("").is_none());
+    }
+
+    #[test]
+    fn test_glob() {
+        assert!(matches("*.png", "Shot.PNG"));
+        assert!(!matches("*.png", "shot.png.bak"));
+        assert!(matches("img_00??.*", "IMG_0042.jpg"));
+        assert!(!matches("img_00??.*", "IMG_042.jpg"));
+        assert!(matches("*beach*", "2023_beach_04.jpg"));
+        assert!(matches("a*b*c", "aXbYbZc"));
+        assert!(!matches("a*b*c", "aXbYbZ"));
+        assert!(matches("*", ""));
+    }
+
+    #[test]
+    fn test_matches_file_name_only() {
+        let filter = NameFilter::new("trip").unwrap();
+        assert!(!filter.matches_meta(&ImageMeta::new(PathBuf::from("/trip/a.jpg"))));
+        assert!(filter.matches_meta(&ImageMeta::new(PathBuf::from("/photos/trip1.jpg"))));
+        let entry = ImageMeta::archive_entry(PathBuf::from("trip.zip"), "x/trip.png".to_string());
+        assert!(filter.matches_meta(&entry));
+    }
+}
@@ -0,0 +1,77 @@
+//! Formats decoded entirely by the `image` crate.
+
+use super::{fit_to_tier, FormatDecoder};
+use crate::config::{QualityTier, TierSizes};
+use crate::decode::DecodeError;
+use crate::slot::ImageData;
+
+/// A format with no special handling beyond `image::load_from_memory`
+pub struct Generic {
+    name: &'static str,
+    format: image::ImageFormat,
+    extensions: &'static [&'static str],
+    magic: fn(&[u8]) -> bool,
+}
+
+pub const BMP: Generic = Generic {
+    name: "BMP",
+    format: image::ImageFormat::Bmp,
+    extensions: &["bmp"],
+    magic: |h| h.starts_with(b"BM"),
+};
+
+pub const WEBP: Generic = Generic {
+    name: "WebP",
+    format: image::ImageFormat::WebP,
+    extensions: &["webp"],
+    magic: |h| h.len() >= 12 && h.starts_with(b"RIFF") && &h[8..12] == b"WEBP",
+};
+
+impl FormatDecoder for Generic {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn sniff(&self, header: &[u8]) -> bool {
+        (self.magic)(header)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        self.extensions
+    }
+
+    fn decode(
+        &self,
+        data: &[u8],
+        quality: QualityTier,
+        sizes: TierSizes,
+    ) -> Result<ImageData, DecodeError> {
+        let (rgba, width, height) = decode_rgba(data, self.format)?;
+        Ok(fit_to_tier(rgba, width, height, quality, sizes))
+    }
+
+    fn dimensions(&self, data: &[u8]) -> Option<(u32, u32)> {
+        probe_dimensions(data, self.format)
+    }
+}
+
+/// Read dimensions from the header without decoding pixel data
+pub fn probe_dimensions(data: &[u8], format: image::ImageFormat) -> Option<(u32, u32)> {
+    image::ImageReader::with_format(std::io::Cursor::new(data), format)
+        .into_dimensions()
+        .ok()
+}
+
+/// Decode with the `image` crate to full-size RGBA
+pub fn decode_rgba(
+    data: &[u8],
+    format: image::ImageFormat,
+) -> Result<(Vec<u8>, u32, u32), DecodeError> {
+    // Size is policed by `Decoder`'s pixel cap, not the crate's default limits
+    let mut reader = image::ImageReader::with_format(std::io::Cursor::new(data), format);
+    reader.no_limits();
+    let img = reader.decode()?;
+    let rgba = img.to_rgba8();
+    let (width, height) = rgba.dimensions();
+    Ok((rgba.into_raw(), width, height))
+}
@@ -0,0 +1,136 @@
+//! GIF, including animation frames and timing.
+
+use super::{fit_to_tier, generic, resize_bilinear, FormatDecoder};
+use crate::config::{QualityTier, TierSizes};
+use crate::decode::DecodeError;
+use crate::slot::ImageData;
+use image::{AnimationDecoder, ImageDecoder};
+use std::time::Duration;
+
+/// GIF frame delays below this are treated as 100ms, matching browsers
+const MIN_FRAME_DELAY_MS: u32 = 20;
+
+pub struct Gif;
+
+impl FormatDecoder for Gif {
+    fn name(&self) -> &'static str {
+        "GIF"
+    }
+
+    fn sniff(&self, header: &[u8]) -> bool {
+        header.starts_with(b"GIF87a") || header.starts_with(b"GIF89a")
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["gif"]
+    }
+
+    fn decode(
+        &self,
+        data: &[u8],
+        quality: QualityTier,
+        sizes: TierSizes,
+    ) -> Result<ImageData, DecodeError> {
+        self.decode_capped(data, 0, quality, sizes, u64::MAX)
+    }
+
+    fn decode_capped(
+        &self,
+        data: &[u8],
+        page: usize,
+        quality: QualityTier,
+        sizes: TierSizes,
+        max_pixels: u64,
+    ) -> Result<ImageData, DecodeError> {
+        if page > 0 {
+            return Err(DecodeError::UnsupportedFormat);
+        }
+        if let Some(animated) = decode_animation(data, quality, sizes, max_pixels) {
+            return Ok(animated);
+        }
+        let (rgba, width, height) = generic::decode_rgba(data, image::ImageFormat::Gif)?;
+        Ok(fit_to_tier(rgba, width, height, quality, sizes))
+    }
+
+    fn dimensions(&self, data: &[u8]) -> Option<(u32, u32)> {
+        generic::probe_dimensions(data, image::ImageFormat::Gif)
+    }
+}
+
+/// Decode every frame of an animated GIF, each downscaled to the tier as
+/// it is decoded. Returns None for single-frame GIFs, and for animations
+/// whose frames together would hold more than `max_pixels`, so they take
+/// the still-image path and show their first frame.
+fn decode_animation(
+    data: &[u8],
+    quality: QualityTier,
+    sizes: TierSizes,
+    max_pixels: u64,
+) -> Option<ImageData> {
+    let decoder = image::codecs::gif::GifDecoder::new(std::io::Cursor::new(data)).ok()?;
+    let (width, height) = decoder.dimensions();
+    let (target_w, target_h) = quality.target_dimensions(width, height, sizes);
+    let frame_pixels = target_w as u64 * target_h as u64;
+
+    let mut delays = Vec::new();
+    let mut pixels: Vec<Vec<u8>> = Vec::new();
+    for frame in decoder.into_frames() {
+        let frame = frame.ok()?;
+        if (pixels.len() as u64 + 1).saturating_mul(frame_pixels) > max_pixels {
+            return None;
+        }
+        let (numer, denom) = frame.delay().numer_denom_ms();
+        let ms = numer.checked_div(denom).unwrap_or(0);
+        // Browsers treat near-zero delays as 100ms; so do we
+        let ms = if ms < MIN_FRAME_DELAY_MS { 100 } else { ms };
+        delays.push(Duration::from_millis(ms as u64));
+
+        let rgba = frame.into_buffer().into_raw();
+        pixels.push(resize_bilinear(rgba, width, height, target_w, target_h));
+    }
+    if pixels.len() < 2 {
+        return None;
+    }
+
+    let first = pixels.remove(0);
+    Some(
+        ImageData::new(first, target_w, target_h, quality)
+            .with_full_size(width, height)
+            .with_animation(pixels, delays),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::codecs::gif::GifEncoder;
+    use image::{Frame, RgbaImage};
+
+    fn encode(frames: usize, width: u32, height: u32) -> Vec<u8> {
+        let mut gif = Vec::new();
+        let mut encoder = GifEncoder::new(&mut gif);
+        for i in 0..frames {
+            let image =
+                RgbaImage::from_pixel(width, height, image::Rgba([i as u8 * 60, 0, 0, 255]));
+            encoder.encode_frame(Frame::new(image)).unwrap();
+        }
+        drop(encoder);
+        gif
+    }
+
+    #[test]
+    fn test_animation_within_cap() {
+        let gif = encode(3, 16, 8);
+        let sizes = TierSizes::default();
+
+        let animated = Gif.decode(&gif, QualityTier::Full, sizes).unwrap();
+        assert_eq!(animated.extra_frames.len(), 2);
+
+        // Three frames don't fit in room for two: the first is shown still
+        let still = Gif
+            .decode_capped(&gif, 0, QualityTier::Full, sizes, 2 * 16 * 8)
+            .unwrap();
+        assert!(still.extra_frames.is_empty());
+        assert_eq!((still.width, still.height), (16, 8));
+    }
+}
@@ -0,0 +1,357 @@
+//! JPEG via zune-jpeg, the fast path for the most common format.
+
+use super::{
+    capped_dimensions, exif_orientation, fit_to_tier, generic, orient, read_exif, resize_bilinear,
+    to_rgba, FormatDecoder,
+};
+use crate::config::{QualityTier, TierSizes};
+use crate::decode::DecodeError;
+use crate::slot::ImageData;
+use crate::state::Orientation;
+
+pub struct Jpeg;
+
+impl FormatDecoder for Jpeg {
+    fn name(&self) -> &'static str {
+        "JPEG"
+    }
+
+    fn sniff(&self, header: &[u8]) -> bool {
+        header.starts_with(&[0xFF, 0xD8, 0xFF])
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["jpg", "jpeg"]
+    }
+
+    /// Lower tiers use DCT-scaled decoding when it saves at least half of
+    /// each dimension. The result is stored upright per EXIF orientation.
+    fn decode(
+        &self,
+        data: &[u8],
+        quality: QualityTier,
+        sizes: TierSizes,
+    ) -> Result<ImageData, DecodeError> {
+        let orientation = read_exif(data).map_or(Orientation::IDENTITY, |e| exif_orientation(&e));
+        self.decode_unrotated(data, quality, sizes)
+            .map(|image| orient(image, orientation))
+    }
+
+    fn dimensions(&self, data: &[u8]) -> Option<(u32, u32)> {
+        generic::probe_dimensions(data, image::ImageFormat::Jpeg)
+    }
+
+    fn decode_scaled(
+        &self,
+        data: &[u8],
+        max_pixels: u64,
+        quality: QualityTier,
+        sizes: TierSizes,
+    ) -> Result<ImageData, DecodeError> {
+        let orientation = read_exif(data).map_or(Orientation::IDENTITY, |e| exif_orientation(&e));
+        decode_downscaled(data, quality, sizes, |w, h| {
+            capped_dimensions(w, h, max_pixels)
+        })
+        .map(|image| orient(image, orientation))
+    }
+
+    /// The EXIF thumbnail, upright, at thumbnail tier (never upscaled)
+    fn embedded_thumbnail(&self, head: &[u8], sizes: TierSizes) -> Option<ImageData> {
+        let exif = read_exif(head)?;
+        let field = |tag| {
+            exif.get_field(tag, exif::In::THUMBNAIL)
+                .and_then(|f| f.value.get_uint(0))
+                .map(|v| v as usize)
+        };
+        let start = field(exif::Tag::JPEGInterchangeFormat)?;
+        let len = field(exif::Tag::JPEGInterchangeFormatLength)?;
+        let thumb = exif.buf().get(start..start.checked_add(len)?)?;
+        let (rgba, width, height) = decode_rgba(thumb).ok()?;
+
+        // Full size from the frame header, or EXIF if the head stops short
+        let pixel_dim = |tag| {
+            exif.get_field(tag, exif::In::PRIMARY)
+                .and_then(|f| f.value.get_uint(0))
+        };
+        let (full_w, full_h) = self
+            .dimensions(head)
+            .or_else(|| {
+                Some((
+                    pixel_dim(exif::Tag::PixelXDimension)?,
+                    pixel_dim(exif::Tag::PixelYDimension)?,
+                ))
+            })
+            .unwrap_or((width, height));
+
+        let image = fit_to_tier(rgba, width, height, QualityTier::Thumbnail, sizes)
+            .with_full_size(full_w, full_h);
+        Some(orient(image, exif_orientation(&exif)))
+    }
+
+    /// Everything up to the end of the last complete scan, then an EOI
+    fn progressive_prefix(&self, head: &[u8]) -> Option<Vec<u8>> {
+        let end = complete_scans(head)?;
+        let mut prefix = head[..end].to_vec();
+        prefix.extend([0xFF, 0xD9]);
+        Some(prefix)
+    }
+}
+
+impl Jpeg {
+    fn decode_unrotated(
+        &self,
+        data: &[u8],
+        quality: QualityTier,
+        sizes: TierSizes,
+    ) -> Result<ImageData, DecodeError> {
+        if let (Some(_), Some((width, height))) =
+            (quality.max_dimension(sizes), self.dimensions(data))
+        {
+            let (target_w, target_h) = quality.target_dimensions(width, height, sizes);
+            if target_w * 2 <= width && target_h * 2 <= height {
+                // Unusual pixel formats fall through to the full decode
+                if let Ok(image) =
+                    decode_downscaled(data, quality, sizes, |_, _| (target_w, target_h))
+                {
+                    return Ok(image);
+                }
+            }
+        }
+
+        let (rgba, width, height) = decode_rgba(data)?;
+        Ok(fit_to_tier(rgba, width, height, quality, sizes))
+    }
+}
+
+/// Where the last complete scan of a progressive JPEG ends, walking the
+/// marker segments. None for baseline files, before the first scan is
+/// complete, and once the end of image has been reached.
+fn complete_scans(data: &[u8]) -> Option<usize> {
+    let byte = |at: usize| data.get(at).copied();
+    let mut progressive = false;
+    let mut scans_end = None;
+    let mut pos = 2;
+    // Running out of data anywhere leaves the scans found so far
+    loop {
+        // Markers may be padded with fill bytes
+        while byte(pos) == Some(0xFF) && byte(pos + 1) == Some(0xFF) {
+            pos += 1;
+        }
+        let marker = match (byte(pos), byte(pos + 1)) {
+            (Some(0xFF), Some(marker)) => marker,
+            (Some(0xFF) | None, None) => return scans_end,
+            _ => return None,
+        };
+        match marker {
+            0xD9 => return None,
+            0x01 | 0xD0..=0xD7 => {
+                pos += 2;
+                continue;
+            }
+            _ => {}
+        }
+        let len = match (byte(pos + 2), byte(pos + 3)) {
+            (Some(high), Some(low)) => u16::from_be_bytes([high, low]) as usize,
+            _ => return scans_end,
+        };
+        pos += 2 + len;
+        match marker {
+            0xC2 => progressive = true,
+            0xDA if progressive => {
+                // Entropy-coded data runs to the next marker that isn't a
+                // stuffed zero or a restart
+                loop {
+                    match (byte(pos), byte(pos + 1)) {
+                        (None, _) | (Some(0xFF), None) => return scans_end,
+                        (Some(0xFF), Some(0x00 | 0xD0..=0xD7)) => pos += 2,
+                        (Some(0xFF), Some(_)) => break,
+                        _ => pos += 1,
+                    }
+                }
+                scans_end = Some(pos);
+            }
+            // A baseline frame, or a scan outside a progressive one
+            0xC0 | 0xC1 | 0xDA => return None,
+            _ => {}
+        }
+    }
+}
+
+/// Decode at reduced size using DCT scaling (down to 1/8), so the full-size
+/// image never exists, then resize the remainder of the way. `target` maps
+/// the full dimensions to the wanted ones.
+fn decode_downscaled(
+    data: &[u8],
+    quality: QualityTier,
+    sizes: TierSizes,
+    target: impl FnOnce(u32, u32) -> (u32, u32),
+) -> Result<ImageData, DecodeError> {
+    let corrupt = |e: jpeg_decoder::Error| DecodeError::Corrupt(e.to_string());
+
+    let mut decoder = jpeg_decoder::Decoder::new(data);
+    decoder.read_info().map_err(corrupt)?;
+    let info = decoder.info().ok_or(DecodeError::UnsupportedFormat)?;
+    let (width, height) = (info.width as u32, info.height as u32);
+    let (out_w, out_h) = target(width, height);
+
+    let (scaled_w, scaled_h) = decoder.scale(out_w as u16, out_h as u16).map_err(corrupt)?;
+    let pixels = decoder.decode().map_err(corrupt)?;
+    let components = match decoder.info().map(|i| i.pixel_format) {
+        Some(jpeg_decoder::PixelFormat::L8) => 1,
+        Some(jpeg_decoder::PixelFormat::RGB24) => 3,
+        _ => return Err(DecodeError::UnsupportedFormat),
+    };
+
+    let rgba = resize_bilinear(
+        to_rgba(pixels, components),
+        scaled_w as u32,
+        scaled_h as u32,
+        out_w,
+        out_h,
+    );
+    Ok(fit_to_tier(rgba, out_w, out_h, quality, sizes).with_full_size(width, height))
+}
+
+/// Decode a JPEG stream to full-size RGBA
+pub fn decode_rgba(data: &[u8]) -> Result<(Vec<u8>, u32, u32), DecodeError> {
+    // Try zune-jpeg first
+    let mut decoder = zune_jpeg::JpegDecoder::new(data);
+    if let Ok(pixels) = decoder.decode() {
+        if let Some(info) = decoder.info() {
+            let rgba = to_rgba(pixels, info.components);
+            return Ok((rgba, info.width as u32, info.height as u32));
+        }
+    }
+
+    // Fallback to image crate
+    generic::decode_rgba(data, image::ImageFormat::Jpeg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(width: u32, height: u32) -> Vec<u8> {
+        let mut jpeg = Vec::new();
+        image::RgbImage::from_pixel(width, height, image::Rgb([90, 120, 150]))
+            .write_to(
+                &mut std::io::Cursor::new(&mut jpeg),
+                image::ImageFormat::Jpeg,
+            )
+            .unwrap();
+        jpeg
+    }
+
+    #[test]
+    fn test_lower_tiers_match_target() {
+        let jpeg = encode(1200, 600);
+
+        let thumb = Jpeg
+            .decode(&jpeg, QualityTier::Thumbnail, TierSizes::default())
+            .unwrap();
+        assert_eq!((thumb.width, thumb.height), (256, 128));
+        assert_eq!((thumb.full_width, thumb.full_height), (1200, 600));
+        assert_eq!(thumb.quality, QualityTier::Thumbnail);
+        // Flat colour survives scaling (allowing for JPEG rounding)
+        assert!(thumb.pixels[..3]
+            .iter()
+            .zip([90, 120, 150])
+            .all(|(&a, b)| a.abs_diff(b) <= 4));
+
+        let full = Jpeg
+            .decode(&jpeg, QualityTier::Full, TierSizes::default())
+            .unwrap();
+        assert_eq!((full.width, full.height), (1200, 600));
+    }
+
+    /// A JPEG carrying an EXIF block with orientation 6 (rotate 90°
+    /// clockwise) and an embedded thumbnail
+    fn encode_with_thumbnail(width: u32, height: u32, thumb: &[u8]) -> Vec<u8> {
+        let mut tiff = b"II*\0".to_vec();
+        tiff.extend(8u32.to_le_bytes());
+        let entry = |tiff: &mut Vec<u8>, tag: u16, kind: u16, value: u32| {
+            tiff.extend(tag.to_le_bytes());
+            tiff.extend(kind.to_le_bytes());
+            tiff.extend(1u32.to_le_bytes());
+            tiff.extend(value.to_le_bytes());
+        };
+        // IFD0: orientation, then the offset of IFD1
+        tiff.extend(1u16.to_le_bytes());
+        entry(&mut tiff, 0x0112, 3, 6);
+        tiff.extend(26u32.to_le_bytes());
+        // IFD1: where the thumbnail is, which follows directly
+        tiff.extend(2u16.to_le_bytes());
+        entry(&mut tiff, 0x0201, 4, 56);
+        entry(&mut tiff, 0x0202, 4, thumb.len() as u32);
+        tiff.extend(0u32.to_le_bytes());
+        tiff.extend(thumb);
+
+        let main = encode(width, height);
+        let mut jpeg = main[..2].to_vec();
+        jpeg.extend([0xFF, 0xE1]);
+        jpeg.extend((2 + 6 + tiff.len() as u16).to_be_bytes());
+        jpeg.extend(b"Exif\0\0");
+        jpeg.extend(tiff);
+        jpeg.extend(&main[2..]);
+        jpeg
+    }
+
+    #[test]
+    fn test_embedded_thumbnail() {
+        let jpeg = encode_with_thumbnail(64, 32, &encode(16, 8));
+
+        let thumb = Jpeg
+            .embedded_thumbnail(&jpeg, TierSizes::default())
+            .unwrap();
+        assert_eq!((thumb.width, thumb.height), (8, 16));
+        assert_eq!((thumb.full_width, thumb.full_height), (32, 64));
+        assert_eq!(thumb.quality, QualityTier::Thumbnail);
+
+        // The full decode is rotated the same way
+        let full = Jpeg
+            .decode(&jpeg, QualityTier::Full, TierSizes::default())
+            .unwrap();
+        assert_eq!((full.width, full.height), (32, 64));
+
+        assert!(Jpeg
+            .embedded_thumbnail(&encode(64, 32), TierSizes::default())
+            .is_none());
+    }
+
+    #[test]
+    fn test_progressive_prefix() {
+        let segment = |marker: u8, body: &[u8]| {
+            let mut segment = vec![0xFF, marker];
+            segment.extend((body.len() as u16 + 2).to_be_bytes());
+            segment.extend(body);
+            segment
+        };
+        let mut file = vec![0xFF, 0xD8];
+        file.extend(segment(0xC2, &[8, 0, 1, 0, 1, 1, 1, 0x11, 0]));
+        file.extend(segment(0xDA, &[1, 1, 0, 0, 0, 0]));
+        // Entropy-coded data with a stuffed zero and a restart marker
+        file.extend([0x12, 0xFF, 0x00, 0x34, 0xFF, 0xD0, 0x56]);
+        let first_scan = file.len();
+        file.extend(segment(0xDA, &[1, 1, 0, 1, 63, 0]));
+        file.extend([0x78]);
+        file.extend([0xFF, 0xD9]);
+
+        // A scan is complete once the marker after it has arrived
+        assert_eq!(complete_scans(&file[..first_scan]), None);
+        assert_eq!(complete_scans(&file[..first_scan + 1]), None);
+        assert_eq!(complete_scans(&file[..first_scan + 2]), Some(first_scan));
+        assert_eq!(complete_scans(&file[..file.len() - 1]), Some(first_scan));
+        // The whole file needs no stand-in
+        assert_eq!(complete_scans(&file), None);
+
+        let prefix = Jpeg.progressive_prefix(&file[..first_scan + 4]).unwrap();
+        assert_eq!(prefix[..first_scan], file[..first_scan]);
+        assert_eq!(prefix[first_scan..], [0xFF, 0xD9]);
+
+        // Baseline files are decoded whole
+        let baseline = encode(16, 16);
+        assert!(Jpeg
+            .progressive_prefix(&baseline[..baseline.len() - 2])
+            .is_none());
+    }
+}
@@ -0,0 +1,380 @@
+//! Per-format decoders plugged into the `Decoder` registry.
+//!
+//! Each format lives in its own module and implements `FormatDecoder`.
+//! `builtin()` lists them in detection order; optional formats register
+//! behind their cargo feature.
+
+mod generic;
+mod gif;
+mod jpeg;
+mod png;
+#[cfg(feature = "raw")]
+mod raw;
+mod tiff;
+
+use crate::config::{QualityTier, TierSizes};
+use crate::decode::{cancelled, DecodeError};
+use crate::slot::ImageData;
+use crate::state::Orientation;
+use std::path::Path;
+
+/// A decodable image format
+pub trait FormatDecoder: Send + Sync {
+    /// Short human-readable name, e.g. "PNG"
+    fn name(&self) -> &'static str;
+
+    /// Whether a file's leading bytes belong to this format
+    fn sniff(&self, header: &[u8]) -> bool;
+
+    /// Extensions claimed by this format (lowercase, no dot)
+    fn extensions(&self) -> &[&str];
+
+    /// Decode to RGBA, downscaled for the quality tier at `sizes`
+    fn decode(
+        &self,
+        data: &[u8],
+        quality: QualityTier,
+        sizes: TierSizes,
+    ) -> Result<ImageData, DecodeError>;
+
+    /// Decode one page of a multi-page file. Single-page formats only have
+    /// page 0.
+    fn decode_page(
+        &self,
+        data: &[u8],
+        page: usize,
+        quality: QualityTier,
+        sizes: TierSizes,
+    ) -> Result<ImageData, DecodeError> {
+        match page {
+            0 => self.decode(data, quality, sizes),
+            _ => Err(DecodeError::UnsupportedFormat),
+        }
+    }
+
+    /// Decode one page holding at most `max_pixels` across all its
+    /// frames. Only animated formats differ from `decode_page`: an
+    /// animation too long or large for the cap shows its first frame.
+    fn decode_capped(
+        &self,
+        data: &[u8],
+        page: usize,
+        quality: QualityTier,
+        sizes: TierSizes,
+        _max_pixels: u64,
+    ) -> Result<ImageData, DecodeError> {
+        self.decode_page(data, page, quality, sizes)
+    }
+
+    /// Dimensions read from the header alone, if the format supports it
+    fn dimensions(&self, _data: &[u8]) -> Option<(u32, u32)> {
+        None
+    }
+
+    /// Decode an image too large to hold in full to at most `max_pixels`,
+    /// without materialising the full-size pixels. Formats that can't
+    /// refuse with `TooLarge`.
+    fn decode_scaled(
+        &self,
+        _data: &[u8],
+        _max_pixels: u64,
+        _quality: QualityTier,
+        _sizes: TierSizes,
+    ) -> Result<ImageData, DecodeError> {
+        Err(DecodeError::TooLarge)
+    }
+
+    /// A small preview stored inside the file (e.g. an EXIF thumbnail),
+    /// decoded at thumbnail tier. `head` may be only the start of the file.
+    fn embedded_thumbnail(&self, _head: &[u8], _sizes: TierSizes) -> Option<ImageData> {
+        None
+    }
+
+    /// For files stored coarse to fine (progressive JPEG): the scans
+    /// complete in `head`, the start of a file still being read, closed off
+    /// so they decode to a coarse version of the image. None before the
+    /// first scan is in, once the file is complete, or for other formats.
+    fn progressive_prefix(&self, _head: &[u8]) -> Option<Vec<u8>> {
+        None
+    }
+
+    /// Number of pages in a file on disk, checked at scan time
+    fn page_count(&self, _path: &Path) -> usize {
+        1
+    }
+}
+
+/// Built-in formats, in detection order
+pub fn builtin() -> Vec<Box<dyn FormatDecoder>> {
+    #[allow(unused_mut)]
+    let mut formats: Vec<Box<dyn FormatDecoder>> = vec![
+        Box::new(jpeg::Jpeg),
+        Box::new(png::Png),
+        Box::new(gif::Gif),
+        Box::new(generic::BMP),
+        Box::new(generic::WEBP),
+        Box::new(tiff::Tiff),
+    ];
+    #[cfg(feature = "raw")]
+    formats.push(Box::new(raw::Raw));
+    formats
+}
+
+/// Wrap full-size RGBA pixels, downscaling them for the quality tier
+fn fit_to_tier(
+    rgba: Vec<u8>,
+    width: u32,
+    height: u32,
+    quality: QualityTier,
+    sizes: TierSizes,
+) -> ImageData {
+    let (target_w, target_h) = quality.target_dimensions(width, height, sizes);
+    let pixels = resize_bilinear(rgba, width, height, target_w, target_h);
+    ImageData::new(pixels, target_w, target_h, quality).with_full_size(width, height)
+}
+
+/// Largest dimensions with the same aspect ratio that fit in `max_pixels`
+fn capped_dimensions(width: u32, height: u32, max_pixels: u64) -> (u32, u32) {
+    let pixels = width as u64 * height as u64;
+    if pixels <= max_pixels {
+        return (width, height);
+    }
+    let scale = (max_pixels as f64 / pixels as f64).sqrt();
+    let max_pixels = max_pixels.max(1);
+    match (
+        (width as f64 * scale) as u32,
+        (height as f64 * scale) as u32,
+    ) {
+        // Extreme aspect ratios: keep one pixel on the short side
+        (0, _) => (1, max_pixels.min(height as u64) as u32),
+        (_, 0) => (max_pixels.min(width as u64) as u32, 1),
+        dims => dims,
+    }
+}
+
+/// Parse the EXIF block of a file. `data` may be only the start of the
+/// file, as long as it covers the EXIF segment.
+fn read_exif(data: &[u8]) -> Option<exif::Exif> {
+    exif::Reader::new()
+        .read_from_container(&mut std::io::Cursor::new(data))
+        .ok()
+}
+
+/// Orientation recorded in EXIF, or upright if there is none
+fn exif_orientation(exif: &exif::Exif) -> Orientation {
+    exif.get_field(exif::Tag::Orientation, exif::In::PRIMARY)
+        .and_then(|field| field.value.get_uint(0))
+        .map_or(Orientation::IDENTITY, Orientation::from_exif)
+}
+
+/// Store decoded data upright, including its full-size dimensions
+fn orient(image: ImageData, orientation: Orientation) -> ImageData {
+    if orientation.is_identity() {
+        return image;
+    }
+    let (full_w, full_h) =
+        orientation.display_dims(image.full_width as usize, image.full_height as usize);
+    let (pixels, width, height) =
+        apply_orientation(image.pixels, image.width, image.height, orientation);
+    ImageData::new(pixels, width, height, image.quality)
+        .with_full_size(full_w as u32, full_h as u32)
+}
+
+/// Rearrange RGBA pixels so the image is stored as it should be displayed
+pub(crate) fn apply_orientation(
+    rgba: Vec<u8>,
+    width: u32,
+    height: u32,
+    orientation: Orientation,
+) -> (Vec<u8>, u32, u32) {
+    if orientation.is_identity() {
+        return (rgba, width, height);
+    }
+
+    let (w, h) = (width as usize, height as usize);
+    let (out_w, out_h) = orientation.display_dims(w, h);
+    let mut out = vec![0u8; rgba.len()];
+    for v in 0..out_h {
+        for u in 0..out_w {
+            let (x, y) = orientation.source_coords(u, v, w, h);
+            let src = (y * w + x) * 4;
+            let dst = (v * out_w + u) * 4;
+            out[dst..dst + 4].copy_from_slice(&rgba[src..src + 4]);
+        }
+    }
+    (out, out_w as u32, out_h as u32)
+}
+
+/// Convert raw pixels to RGBA
+fn to_rgba(pixels: Vec<u8>, components: u8) -> Vec<u8> {
+    match components {
+        4 => pixels, // Already RGBA
+        3 => pixels
+            .chunks_exact(3)
+            .flat_map(|rgb| [rgb[0], rgb[1], rgb[2], 255])
+            .collect(),
+        2 => pixels
+            .chunks_exact(2)
+            .flat_map(|ga| [ga[0], ga[0], ga[0], ga[1]])
+            .collect(),
+        1 => pixels.iter().flat_map(|&g| [g, g, g, 255]).collect(),
+        _ => pixels,
+    }
+}
+
+/// Derive lower-tier data from pixels already in memory, so a smaller
+/// copy costs no file I/O or decoding
+pub(crate) fn downscale(image: &ImageData, quality: QualityTier, sizes: TierSizes) -> ImageData {
+    let (width, height) = quality.target_dimensions(image.width, image.height, sizes);
+    let resize = |pixels: &[u8]| scale_bilinear(pixels, image.width, image.height, width, height);
+
+    let mut lower = ImageData::new(resize(&image.pixels), width, height, quality)
+        .with_full_size(image.full_width, image.full_height);
+    if image.is_animated() {
+        let frames = image.extra_frames.iter().map(|f| resize(f)).collect();
+        lower = lower.with_animation(frames, image.delays.clone());
+    }
+    if image.embedded_preview {
+        lower = lower.with_embedded_preview();
+    }
+    if image.partial {
+        lower = lower.with_partial();
+    }
+    lower
+}
+
+/// Resize using bilinear interpolation
+fn resize_bilinear(data: Vec<u8>, src_w: u32, src_h: u32, dst_w: u32, dst_h: u32) -> Vec<u8> {
+    if src_w == dst_w && src_h == dst_h {
+        return data;
+    }
+    scale_bilinear(&data, src_w, src_h, dst_w, dst_h)
+}
+
+/// Bilinear resize into a new buffer
+fn scale_bilinear(data: &[u8], src_w: u32, src_h: u32, dst_w: u32, dst_h: u32) -> Vec<u8> {
+    let src_w = src_w as usize;
+    let src_h = src_h as usize;
+    let dst_w = dst_w as usize;
+    let dst_h = dst_h as usize;
+
+    let mut result = vec![0u8; dst_w * dst_h * 4];
+
+    let x_ratio = (src_w as f64 - 1.0) / dst_w.max(1) as f64;
+    let y_ratio = (src_h as f64 - 1.0) / dst_h.max(1) as f64;
+
+    for y in 0..dst_h {
+        // An abandoned decode's pixels are thrown away, finished or not
+        if cancelled() {
+            break;
+        }
+        let src_y = y as f64 * y_ratio;
+        let y0 = src_y.floor() as usize;
+        let y1 = (y0 + 1).min(src_h - 1);
+        let y_frac = src_y - y0 as f64;
+
+        for x in 0..dst_w {
+            let src_x = x as f64 * x_ratio;
+            let x0 = src_x.floor() as usize;
+            let x1 = (x0 + 1).min(src_w - 1);
+            let x_frac = src_x - x0 as f64;
+
+            let idx00 = (y0 * src_w + x0) * 4;
+            let idx01 = (y0 * src_w + x1) * 4;
+            let idx10 = (y1 * src_w + x0) * 4;
+            let idx11 = (y1 * src_w + x1) * 4;
+            let dst_idx = (y * dst_w + x) * 4;
+
+            for c in 0..4 {
+                let v00 = data.get(idx00 + c).copied().unwrap_or(0) as f64;
+                let v01 = data.get(idx01 + c).copied().unwrap_or(0) as f64;
+                let v10 = data.get(idx10 + c).copied().unwrap_or(0) as f64;
+                let v11 = data.get(idx11 + c).copied().unwrap_or(0) as f64;
+
+                let v0 = v00 * (1.0 - x_frac) + v01 * x_frac;
+                let v1 = v10 * (1.0 - x_frac) + v11 * x_frac;
+                let v = v0 * (1.0 - y_frac) + v1 * y_frac;
+
+                result[dst_idx + c] = v.round() as u8;
+            }
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resize() {
+        // 2x2 image, all red
+        let src = vec![
+            255, 0, 0, 255, 255, 0, 0, 255, 255, 0, 0, 255, 255, 0, 0, 255,
+        ];
+        let dst = resize_bilinear(src, 2, 2, 4, 4);
+
+        // Should be 4x4, still mostly red
+        assert_eq!(dst.len(), 4 * 4 * 4);
+        // First pixel should be red
+        assert_eq!(&dst[0..4], &[255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn test_downscale_keeps_frames() {
+        let frame = vec![200u8; 2048 * 1024 * 4];
+        let delays = vec![std::time::Duration::from_millis(100); 2];
+        let full = ImageData::new(frame.clone(), 2048, 1024, QualityTier::Full)
+            .with_animation(vec![frame], delays);
+
+        let thumb = downscale(&full, QualityTier::Thumbnail, TierSizes::default());
+        assert_eq!((thumb.width, thumb.height), (256, 128));
+        assert_eq!((thumb.full_width, thumb.full_height), (2048, 1024));
+        assert_eq!(thumb.quality, QualityTier::Thumbnail);
+        assert_eq!(thumb.frame_count(), 2);
+        assert_eq!(thumb.frame(1).len(), 256 * 128 * 4);
+        assert_eq!(thumb.pixels[0], 200);
+    }
+
+    #[test]
+    fn test_apply_orientation() {
+        // 2x1: red, green
+        let rgba = vec![255, 0, 0, 255, 0, 255, 0, 255];
+        let (out, w, h) = apply_orientation(rgba.clone(), 2, 1, Orientation::from_exif(6));
+        assert_eq!((w, h), (1, 2));
+        assert_eq!(out, rgba);
+
+        let (out, w, h) = apply_orientation(rgba, 2, 1, Orientation::from_exif(2));
+        assert_eq!((w, h), (2, 1));
+        assert_eq!(out, vec![0, 255, 0, 255, 255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn test_capped_dimensions() {
+        assert_eq!(capped_dimensions(100, 50, 10_000), (100, 50));
+        assert_eq!(
+            capped_dimensions(30_000, 30_000, 100_000_000),
+            (10_000, 10_000)
+        );
+        assert_eq!(capped_dimensions(1_000_000, 1, 100), (100, 1));
+    }
+
+    #[test]
+    fn test_sniff_builtin() {
+        let detect = |header: &[u8]| {
+            builtin()
+                .into_iter()
+                .find(|f| f.sniff(header))
+                .map(|f| f.name())
+        };
+
+        assert_eq!(detect(b"\x89PNG\r\n\x1a\n...."), Some("PNG"));
+        assert_eq!(detect(&[0xFF, 0xD8, 0xFF, 0xE0]), Some("JPEG"));
+        assert_eq!(detect(b"GIF89a"), Some("GIF"));
+        assert_eq!(detect(b"RIFF\0\0\0\0WEBPVP8 "), Some("WebP"));
+        assert_eq!(detect(b"MM\0*"), Some("TIFF"));
+        assert_eq!(detect(b"hello"), None);
+        assert_eq!(detect(b""), None);
+    }
+}
@@ -0,0 +1,119 @@
+//! PNG, with a row-streaming path for images too large to hold in full.
+
+use super::{capped_dimensions, fit_to_tier, generic, FormatDecoder};
+use crate::config::{QualityTier, TierSizes};
+use crate::decode::{cancelled, DecodeError};
+use crate::slot::ImageData;
+
+pub struct Png;
+
+impl FormatDecoder for Png {
+    fn name(&self) -> &'static str {
+        "PNG"
+    }
+
+    fn sniff(&self, header: &[u8]) -> bool {
+        header.starts_with(b"\x89PNG\r\n\x1a\n")
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["png"]
+    }
+
+    fn decode(
+        &self,
+        data: &[u8],
+        quality: QualityTier,
+        sizes: TierSizes,
+    ) -> Result<ImageData, DecodeError> {
+        let (rgba, width, height) = generic::decode_rgba(data, image::ImageFormat::Png)?;
+        Ok(fit_to_tier(rgba, width, height, quality, sizes))
+    }
+
+    fn dimensions(&self, data: &[u8]) -> Option<(u32, u32)> {
+        generic::probe_dimensions(data, image::ImageFormat::Png)
+    }
+
+    /// Stream rows and keep only those that land in the capped image, so
+    /// memory stays proportional to the output. Interlaced files deliver
+    /// rows out of order and are refused.
+    fn decode_scaled(
+        &self,
+        data: &[u8],
+        max_pixels: u64,
+        quality: QualityTier,
+        sizes: TierSizes,
+    ) -> Result<ImageData, DecodeError> {
+        let corrupt = |e: png::DecodingError| DecodeError::Corrupt(e.to_string());
+
+        let mut decoder = png::Decoder::new(data);
+        decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+        let mut reader = decoder.read_info().map_err(corrupt)?;
+        let (width, height) = (reader.info().width, reader.info().height);
+        if reader.info().interlaced {
+            return Err(DecodeError::TooLarge);
+        }
+        let components = match reader.output_color_type().0 {
+            png::ColorType::Grayscale => 1,
+            png::ColorType::GrayscaleAlpha => 2,
+            png::ColorType::Rgb => 3,
+            png::ColorType::Rgba => 4,
+            png::ColorType::Indexed => return Err(DecodeError::UnsupportedFormat),
+        };
+
+        let (out_w, out_h) = capped_dimensions(width, height, max_pixels);
+        let mut out = Vec::with_capacity(out_w as usize * out_h as usize * 4);
+        let mut out_row = 0u32;
+        let mut y = 0u32;
+        while let Some(row) = reader.next_row().map_err(corrupt)? {
+            if cancelled() {
+                return Err(DecodeError::TimedOut);
+            }
+            // Nearest-neighbour: each output row samples one source row
+            while out_row < out_h && (out_row as u64 * height as u64 / out_h as u64) as u32 == y {
+                let src = row.data();
+                for x in 0..out_w {
+                    let sx = (x as u64 * width as u64 / out_w as u64) as usize * components;
+                    let px = &src[sx..sx + components];
+                    out.extend_from_slice(&match components {
+                        1 => [px[0], px[0], px[0], 255],
+                        2 => [px[0], px[0], px[0], px[1]],
+                        3 => [px[0], px[1], px[2], 255],
+                        _ => [px[0], px[1], px[2], px[3]],
+                    });
+                }
+                out_row += 1;
+            }
+            y += 1;
+        }
+        if out_row < out_h {
+            return Err(DecodeError::Corrupt("truncated image data".to_string()));
+        }
+
+        Ok(fit_to_tier(out, out_w, out_h, quality, sizes).with_full_size(width, height))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_scaled_caps_pixels() {
+        let mut png = Vec::new();
+        image::RgbImage::from_fn(64, 32, |x, _| image::Rgb([x as u8 * 4, 0, 0]))
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+
+        assert_eq!(Png.dimensions(&png), Some((64, 32)));
+
+        let data = Png
+            .decode_scaled(&png, 512, QualityTier::Full, TierSizes::default())
+            .unwrap();
+        assert!(data.width as u64 * data.height as u64 <= 512);
+        assert_eq!((data.width, data.height), (32, 16));
+        assert_eq!((data.full_width, data.full_height), (64, 32));
+        // Second output column samples source column 2
+        assert_eq!(&data.pixels[4..8], &[8, 0, 0, 255]);
+    }
+}
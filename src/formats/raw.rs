@@ -0,0 +1,350 @@
+//! Embedded preview extraction for camera RAW files.
+//!
+//! CR2, NEF, ARW and DNG are all TIFF containers that carry one or more
+//! JPEG previews next to the sensor data. Rather than developing the RAW
+//! data we walk the IFD tree, pick the largest baseline JPEG, and let the
+//! regular JPEG path decode it.
+
+use super::{fit_to_tier, jpeg, FormatDecoder};
+use crate::config::{QualityTier, TierSizes};
+use crate::decode::DecodeError;
+use crate::slot::ImageData;
+use std::collections::HashSet;
+use std::ops::Range;
+
+/// Previews covering at least this fraction of the sensor's long edge
+/// count as full quality
+const FULL_PREVIEW_FRACTION: f64 = 0.9;
+
+/// Upper bound on IFDs visited, guarding against malformed files
+const MAX_IFDS: usize = 64;
+
+const TAG_IMAGE_WIDTH: u16 = 0x0100;
+const TAG_IMAGE_LENGTH: u16 = 0x0101;
+const TAG_COMPRESSION: u16 = 0x0103;
+const TAG_STRIP_OFFSETS: u16 = 0x0111;
+const TAG_STRIP_BYTE_COUNTS: u16 = 0x0117;
+const TAG_SUB_IFDS: u16 = 0x014A;
+const TAG_JPEG_OFFSET: u16 = 0x0201;
+const TAG_JPEG_LENGTH: u16 = 0x0202;
+
+/// TIFF compression values that mean "JPEG"
+const COMPRESSION_OLD_JPEG: u32 = 6;
+const COMPRESSION_JPEG: u32 = 7;
+
+pub struct Raw;
+
+impl FormatDecoder for Raw {
+    fn name(&self) -> &'static str {
+        "camera RAW"
+    }
+
+    /// Any TIFF header: NEF, ARW and DNG don't mark themselves, so only
+    /// the extension (or CR2's marker) routes them here
+    fn sniff(&self, header: &[u8]) -> bool {
+        header.starts_with(b"II*\0") || header.starts_with(b"MM\0*")
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["cr2", "nef", "arw", "dng"]
+    }
+
+    /// Decode the embedded preview, which may stand in for less than full
+    /// quality
+    fn decode(
+        &self,
+        data: &[u8],
+        quality: QualityTier,
+        sizes: TierSizes,
+    ) -> Result<ImageData, DecodeError> {
+        let preview = find_preview(data)
+            .ok_or_else(|| DecodeError::Corrupt("no embedded preview found".to_string()))?;
+        let (rgba, width, height) = jpeg::decode_rgba(&data[preview.jpeg.clone()])?;
+        let native = preview.tier(width, height);
+        let mut image = fit_to_tier(rgba, width, height, quality, sizes).with_embedded_preview();
+        image.quality = quality.min(native);
+        Ok(image)
+    }
+}
+
+/// Location of the chosen preview within the file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawPreview {
+    /// Byte range of the JPEG stream
+    pub jpeg: Range<usize>,
+    /// Longest sensor edge recorded in the file, if any
+    pub sensor_long_edge: Option<u32>,
+}
+
+impl RawPreview {
+    /// Quality tier a decoded preview of the given size stands in for
+    pub fn tier(&self, width: u32, height: u32) -> QualityTier {
+        match self.sensor_long_edge {
+            Some(sensor) if (width.max(height) as f64) < sensor as f64 * FULL_PREVIEW_FRACTION => {
+                QualityTier::Preview
+            }
+            _ => QualityTier::Full,
+        }
+    }
+}
+
+/// Find the largest decodable JPEG preview in a TIFF-based RAW file
+pub fn find_preview(data: &[u8]) -> Option<RawPreview> {
+    let reader = TiffReader::new(data)?;
+
+    let mut candidates = Vec::new();
+    let mut sensor_long_edge = None;
+    let mut pending = vec![reader.u32_at(4)? as usize];
+    let mut visited = HashSet::new();
+
+    while let Some(offset) = pending.pop() {
+        if offset == 0 || visited.len() >= MAX_IFDS || !visited.insert(offset) {
+            continue;
+        }
+        let Some(ifd) = reader.ifd(offset) else {
+            continue;
+        };
+
+        if let (Some(w), Some(h)) = (ifd.width, ifd.height) {
+            sensor_long_edge = sensor_long_edge.max(Some(w.max(h)));
+        }
+        if let (Some(start), Some(len)) = (ifd.jpeg_offset, ifd.jpeg_length) {
+            candidates.push(start as usize..start as usize + len as usize);
+        }
+        let jpeg_strips = matches!(
+            ifd.compression,
+            Some(COMPRESSION_OLD_JPEG | COMPRESSION_JPEG)
+        );
+        if let (true, Some(start), Some(len)) = (jpeg_strips, ifd.strip_offset, ifd.strip_length) {
+            candidates.push(start as usize..start as usize + len as usize);
+        }
+
+        pending.extend(ifd.sub_ifds);
+        pending.extend(ifd.next);
+    }
+
+    candidates.retain(|range| range.end <= data.len() && is_baseline_jpeg(&data[range.clone()]));
+    let jpeg = candidates.into_iter().max_by_key(|range| range.len())?;
+    Some(RawPreview {
+        jpeg,
+        sensor_long_edge,
+    })
+}
+
+/// Whether a JPEG stream uses a DCT mode ordinary decoders handle. RAW
+/// sensor data is often stored as lossless JPEG, which must be skipped.
+fn is_baseline_jpeg(data: &[u8]) -> bool {
+    if !data.starts_with(&[0xFF, 0xD8]) {
+        return false;
+    }
+
+    let mut pos = 2;
+    while pos + 4 <= data.len() {
+        if data[pos] != 0xFF {
+            return false;
+        }
+        let marker = data[pos + 1];
+        match marker {
+            // Baseline, extended sequential, progressive
+            0xC0..=0xC2 => return true,
+            // Other start-of-frame markers (lossless, arithmetic, ...)
+            0xC3 | 0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF => return false,
+            _ => {}
+        }
+        let len = u16::from_be_bytes([data[pos + 2], data[pos + 3]]) as usize;
+        pos += 2 + len;
+    }
+    false
+}
+
+/// The tags of one IFD that matter for preview extraction
+#[derive(Debug, Default)]
+struct Ifd {
+    width: Option<u32>,
+    height: Option<u32>,
+    compression: Option<u32>,
+    strip_offset: Option<u32>,
+    strip_length: Option<u32>,
+    jpeg_offset: Option<u32>,
+    jpeg_length: Option<u32>,
+    sub_ifds: Vec<usize>,
+    next: Option<usize>,
+}
+
+/// Minimal endian-aware TIFF reader
+struct TiffReader<'a> {
+    data: &'a [u8],
+    little_endian: bool,
+}
+
+impl<'a> TiffReader<'a> {
+    fn new(data: &'a [u8]) -> Option<Self> {
+        let little_endian = match data.get(..4)? {
+            [b'I', b'I', 42, 0] => true,
+            [b'M', b'M', 0, 42] => false,
+            _ => return None,
+        };
+        Some(Self {
+            data,
+            little_endian,
+        })
+    }
+
+    fn u16_at(&self, pos: usize) -> Option<u16> {
+        let bytes: [u8; 2] = self.data.get(pos..pos + 2)?.try_into().ok()?;
+        Some(if self.little_endian {
+            u16::from_le_bytes(bytes)
+        } else {
+            u16::from_be_bytes(bytes)
+        })
+    }
+
+    fn u32_at(&self, pos: usize) -> Option<u32> {
+        let bytes: [u8; 4] = self.data.get(pos..pos + 4)?.try_into().ok()?;
+        Some(if self.little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        })
+    }
+
+    /// Read an IFD entry's values as integers (SHORT or LONG only)
+    fn values(&self, entry: usize) -> Vec<u32> {
+        let (Some(kind), Some(count)) = (self.u16_at(entry + 2), self.u32_at(entry + 4)) else {
+            return Vec::new();
+        };
+        let size = match kind {
+            3 => 2,      // SHORT
+            4 | 13 => 4, // LONG, IFD
+            _ => return Vec::new(),
+        };
+        let count = count as usize;
+        let total = size * count;
+        let start = if total <= 4 {
+            entry + 8
+        } else {
+            match self.u32_at(entry + 8) {
+                Some(offset) => offset as usize,
+                None => return Vec::new(),
+            }
+        };
+
+        (0..count.min(MAX_IFDS))
+            .filter_map(|i| match size {
+                2 => self.u16_at(start + i * 2).map(u32::from),
+                _ => self.u32_at(start + i * 4),
+            })
+            .collect()
+    }
+
+    fn ifd(&self, offset: usize) -> Option<Ifd> {
+        let count = self.u16_at(offset)? as usize;
+        let mut ifd = Ifd::default();
+
+        for i in 0..count {
+            let entry = offset + 2 + i * 12;
+            let tag = self.u16_at(entry)?;
+            let first = || self.values(entry).first().copied();
+            match tag {
+                TAG_IMAGE_WIDTH => ifd.width = first(),
+                TAG_IMAGE_LENGTH => ifd.height = first(),
+                TAG_COMPRESSION => ifd.compression = first(),
+                // Only single-strip images can be a standalone JPEG
+                TAG_STRIP_OFFSETS => ifd.strip_offset = self.single(entry),
+                TAG_STRIP_BYTE_COUNTS => ifd.strip_length = self.single(entry),
+                TAG_JPEG_OFFSET => ifd.jpeg_offset = first(),
+                TAG_JPEG_LENGTH => ifd.jpeg_length = first(),
+                TAG_SUB_IFDS => {
+                    ifd.sub_ifds = self.values(entry).into_iter().map(|v| v as usize).collect()
+                }
+                _ => {}
+            }
+        }
+
+        ifd.next = self
+            .u32_at(offset + 2 + count * 12)
+            .map(|next| next as usize)
+            .filter(|&next| next != 0);
+        Some(ifd)
+    }
+
+    /// The value of an entry that holds exactly one integer
+    fn single(&self, entry: usize) -> Option<u32> {
+        match self.values(entry).as_slice() {
+            [value] => Some(*value),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Minimal little-endian TIFF with one IFD pointing at `jpeg`
+    fn tiff_with_preview(jpeg: &[u8], sensor: (u32, u32)) -> Vec<u8> {
+        let entries: [(u16, u16, u32); 4] = [
+            (TAG_IMAGE_WIDTH, 4, sensor.0),
+            (TAG_IMAGE_LENGTH, 4, sensor.1),
+            (TAG_JPEG_OFFSET, 4, 0), // patched below
+            (TAG_JPEG_LENGTH, 4, jpeg.len() as u32),
+        ];
+        let ifd_len = 2 + entries.len() * 12 + 4;
+        let jpeg_start = 8 + ifd_len as u32;
+
+        let mut out = b"II*\0".to_vec();
+        out.extend(8u32.to_le_bytes());
+        out.extend((entries.len() as u16).to_le_bytes());
+        for (tag, kind, value) in entries {
+            let value = if tag == TAG_JPEG_OFFSET {
+                jpeg_start
+            } else {
+                value
+            };
+            out.extend(tag.to_le_bytes());
+            out.extend(kind.to_le_bytes());
+            out.extend(1u32.to_le_bytes());
+            out.extend(value.to_le_bytes());
+        }
+        out.extend(0u32.to_le_bytes());
+        out.extend(jpeg);
+        out
+    }
+
+    fn encode_jpeg(width: u32, height: u32) -> Vec<u8> {
+        let mut jpeg = Vec::new();
+        image::codecs::jpeg::JpegEncoder::new(&mut jpeg)
+            .encode(
+                &vec![128; (width * height * 3) as usize],
+                width,
+                height,
+                image::ExtendedColorType::Rgb8,
+            )
+            .unwrap();
+        jpeg
+    }
+
+    #[test]
+    fn test_find_preview() {
+        let jpeg = encode_jpeg(16, 8);
+        let file = tiff_with_preview(&jpeg, (100, 50));
+
+        let preview = find_preview(&file).unwrap();
+        assert_eq!(&file[preview.jpeg.clone()], &jpeg[..]);
+        assert_eq!(preview.sensor_long_edge, Some(100));
+
+        // Small previews stand in for the preview tier only
+        assert_eq!(preview.tier(16, 8), QualityTier::Preview);
+        assert_eq!(preview.tier(95, 47), QualityTier::Full);
+    }
+
+    #[test]
+    fn test_rejects_non_tiff_and_lossless() {
+        assert_eq!(find_preview(b"not a tiff at all"), None);
+
+        // SOF3 (lossless) streams are sensor data, not previews
+        let lossless = [0xFF, 0xD8, 0xFF, 0xC3, 0x00, 0x02];
+        assert!(!is_baseline_jpeg(&lossless));
+        assert!(find_preview(&tiff_with_preview(&lossless, (100, 50))).is_none());
+    }
+}
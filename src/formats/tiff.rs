@@ -0,0 +1,103 @@
+//! TIFF, with each page of a multi-page document browsable on its own.
+
+use super::{fit_to_tier, generic, to_rgba, FormatDecoder};
+use crate::config::{QualityTier, TierSizes};
+use crate::decode::DecodeError;
+use crate::slot::ImageData;
+use std::fs;
+use std::path::Path;
+
+pub struct Tiff;
+
+impl FormatDecoder for Tiff {
+    fn name(&self) -> &'static str {
+        "TIFF"
+    }
+
+    fn sniff(&self, header: &[u8]) -> bool {
+        // Canon CR2 shares the TIFF header but marks itself at byte 8
+        let tiff = header.starts_with(b"II*\0") || header.starts_with(b"MM\0*");
+        tiff && header.get(8..10) != Some(b"CR")
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["tif", "tiff"]
+    }
+
+    fn decode(
+        &self,
+        data: &[u8],
+        quality: QualityTier,
+        sizes: TierSizes,
+    ) -> Result<ImageData, DecodeError> {
+        let (rgba, width, height) = generic::decode_rgba(data, image::ImageFormat::Tiff)?;
+        Ok(fit_to_tier(rgba, width, height, quality, sizes))
+    }
+
+    /// Dimensions of the first page
+    fn dimensions(&self, data: &[u8]) -> Option<(u32, u32)> {
+        generic::probe_dimensions(data, image::ImageFormat::Tiff)
+    }
+
+    /// The first page goes through the `image` crate like any single-page
+    /// file; later pages are read from their own directory
+    fn decode_page(
+        &self,
+        data: &[u8],
+        page: usize,
+        quality: QualityTier,
+        sizes: TierSizes,
+    ) -> Result<ImageData, DecodeError> {
+        use tiff::decoder::DecodingResult;
+        use tiff::ColorType;
+
+        if page == 0 {
+            return self.decode(data, quality, sizes);
+        }
+
+        let corrupt = |e: tiff::TiffError| DecodeError::Corrupt(e.to_string());
+        let mut decoder =
+            tiff::decoder::Decoder::new(std::io::Cursor::new(data)).map_err(corrupt)?;
+        decoder.seek_to_image(page).map_err(corrupt)?;
+        let (width, height) = decoder.dimensions().map_err(corrupt)?;
+        let components = match decoder.colortype().map_err(corrupt)? {
+            ColorType::Gray(_) => 1,
+            ColorType::GrayA(_) => 2,
+            ColorType::RGB(_) => 3,
+            ColorType::RGBA(_) => 4,
+            _ => return Err(DecodeError::UnsupportedFormat),
+        };
+
+        // Wider samples are reduced to their high byte
+        let samples = match decoder.read_image().map_err(corrupt)? {
+            DecodingResult::U8(v) => v,
+            DecodingResult::U16(v) => v.into_iter().map(|s| (s >> 8) as u8).collect(),
+            _ => return Err(DecodeError::UnsupportedFormat),
+        };
+        Ok(fit_to_tier(
+            to_rgba(samples, components),
+            width,
+            height,
+            quality,
+            sizes,
+        ))
+    }
+
+    /// Number of images in the file (reads only the directory headers)
+    fn page_count(&self, path: &Path) -> usize {
+        let file = match fs::File::open(path) {
+            Ok(file) => std::io::BufReader::new(file),
+            Err(_) => return 1,
+        };
+        let mut decoder = match tiff::decoder::Decoder::new(file) {
+            Ok(decoder) => decoder,
+            Err(_) => return 1,
+        };
+
+        let mut count = 1;
+        while decoder.more_images() && decoder.next_image().is_ok() {
+            count += 1;
+        }
+        count
+    }
+}
@@ -0,0 +1,247 @@
+//! Touch gestures - turns raw touch points into swipes, pans and pinches.
+//!
+//! `TouchTracker` is a pure state machine: the app feeds it every touch
+//! event along with whether the image is zoomed in, and acts on the gestures
+//! it reports. One finger pans a zoomed image and swipes between images at
+//! fit-to-window; two fingers pinch to zoom.
+
+use std::time::{Duration, Instant};
+use winit::event::TouchPhase;
+
+/// Horizontal distance a swipe must cover, in window pixels
+const SWIPE_DISTANCE: f64 = 80.0;
+/// Slowest horizontal speed that still counts as a swipe, in pixels per second
+const SWIPE_SPEED: f64 = 300.0;
+
+/// What a touch sequence amounts to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Gesture {
+    /// Step to the next (1) or previous (-1) image
+    Swipe(i32),
+    /// Move a zoomed image by this many window pixels
+    Pan { dx: f64, dy: f64 },
+    /// Zoom by `factor` around `center` (window pixels)
+    Pinch { factor: f64, center: (f64, f64) },
+}
+
+/// A finger on the screen
+#[derive(Debug, Clone, Copy)]
+struct Finger {
+    id: u64,
+    position: (f64, f64),
+}
+
+/// Gesture recognizer state
+#[derive(Debug, Default)]
+pub struct TouchTracker {
+    /// Fingers currently down, in touch order
+    fingers: Vec<Finger>,
+    /// Where and when the first finger went down
+    start: Option<((f64, f64), Instant)>,
+    /// Whether a second finger joined since the first went down; such a
+    /// sequence never ends in a swipe
+    multi: bool,
+}
+
+impl TouchTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feed one touch event. `zoomed` decides whether one finger pans or
+    /// swipes.
+    pub fn touch(
+        &mut self,
+        id: u64,
+        phase: TouchPhase,
+        position: (f64, f64),
+        at: Instant,
+        zoomed: bool,
+    ) -> Option<Gesture> {
+        match phase {
+            TouchPhase::Started => {
+                if self.fingers.is_empty() {
+                    self.start = Some((position, at));
+                    self.multi = false;
+                } else {
+                    self.multi = true;
+                }
+                self.fingers.retain(|f| f.id != id);
+                self.fingers.push(Finger { id, position });
+                None
+            }
+            TouchPhase::Moved => self.moved(id, position, zoomed),
+            TouchPhase::Ended => {
+                let swipe = self.swipe(position, at, zoomed);
+                self.lift(id);
+                swipe
+            }
+            TouchPhase::Cancelled => {
+                self.lift(id);
+                None
+            }
+        }
+    }
+
+    fn moved(&mut self, id: u64, position: (f64, f64), zoomed: bool) -> Option<Gesture> {
+        let index = self.fingers.iter().position(|f| f.id == id)?;
+        let before = self.fingers[index].position;
+
+        // Pinch: the first two fingers, compared before and after the move
+        if self.fingers.len() >= 2 && index < 2 {
+            let other = self.fingers[1 - index].position;
+            let old_span = distance(before, other);
+            let new_span = distance(position, other);
+            self.fingers[index].position = position;
+            if old_span < 1.0 {
+                return None;
+            }
+            let center = ((position.0 + other.0) / 2.0, (position.1 + other.1) / 2.0);
+            return Some(Gesture::Pinch {
+                factor: new_span / old_span,
+                center,
+            });
+        }
+
+        self.fingers[index].position = position;
+        if zoomed && self.fingers.len() == 1 && !self.multi {
+            return Some(Gesture::Pan {
+                dx: position.0 - before.0,
+                dy: position.1 - before.1,
+            });
+        }
+        None
+    }
+
+    /// The swipe a lone finger made, if it is lifting now at `position`
+    fn swipe(&self, position: (f64, f64), at: Instant, zoomed: bool) -> Option<Gesture> {
+        if zoomed || self.multi || self.fingers.len() != 1 {
+            return None;
+        }
+        let (start, began) = self.start?;
+        let dx = position.0 - start.0;
+        let dy = position.1 - start.1;
+        let elapsed = at.duration_since(began).max(Duration::from_millis(1));
+        let speed = dx.abs() / elapsed.as_secs_f64();
+
+        // Mostly sideways, far enough and fast enough
+        if dx.abs() < SWIPE_DISTANCE || dx.abs() < 2.0 * dy.abs() || speed < SWIPE_SPEED {
+            return None;
+        }
+        // Dragging the image left brings up the next one
+        Some(Gesture::Swipe(if dx < 0.0 { 1 } else { -1 }))
+    }
+
+    fn lift(&mut self, id: u64) {
+        self.fingers.retain(|f| f.id != id);
+        if self.fingers.is_empty() {
+            self.start = None;
+        }
+    }
+}
+
+fn distance(a: (f64, f64), b: (f64, f64)) -> f64 {
+    (a.0 - b.0).hypot(a.1 - b.1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feed a one-finger stroke from `from` to `to` over `duration`,
+    /// returning every gesture reported
+    fn stroke(
+        tracker: &mut TouchTracker,
+        from: (f64, f64),
+        to: (f64, f64),
+        duration: Duration,
+        zoomed: bool,
+    ) -> Vec<Gesture> {
+        let start = Instant::now();
+        let mid = ((from.0 + to.0) / 2.0, (from.1 + to.1) / 2.0);
+        [
+            (TouchPhase::Started, from, start),
+            (TouchPhase::Moved, mid, start + duration / 2),
+            (TouchPhase::Ended, to, start + duration),
+        ]
+        .into_iter()
+        .filter_map(|(phase, pos, at)| tracker.touch(1, phase, pos, at, zoomed))
+        .collect()
+    }
+
+    #[test]
+    fn test_swipe() {
+        let mut tracker = TouchTracker::new();
+        let quick = Duration::from_millis(150);
+
+        let left = stroke(&mut tracker, (400.0, 300.0), (200.0, 310.0), quick, false);
+        assert_eq!(left, vec![Gesture::Swipe(1)]);
+        let right = stroke(&mut tracker, (200.0, 300.0), (400.0, 300.0), quick, false);
+        assert_eq!(right, vec![Gesture::Swipe(-1)]);
+
+        // Too short, too slow, or too vertical
+        assert!(stroke(&mut tracker, (400.0, 300.0), (350.0, 300.0), quick, false).is_empty());
+        let slow = Duration::from_secs(2);
+        assert!(stroke(&mut tracker, (400.0, 300.0), (200.0, 300.0), slow, false).is_empty());
+        assert!(stroke(&mut tracker, (400.0, 300.0), (300.0, 100.0), quick, false).is_empty());
+    }
+
+    #[test]
+    fn test_zoomed_drag_pans() {
+        let mut tracker = TouchTracker::new();
+        let quick = Duration::from_millis(150);
+
+        let gestures = stroke(&mut tracker, (400.0, 300.0), (200.0, 300.0), quick, true);
+        assert_eq!(
+            gestures,
+            vec![Gesture::Pan {
+                dx: -100.0,
+                dy: 0.0
+            }]
+        );
+    }
+
+    #[test]
+    fn test_pinch() {
+        let mut tracker = TouchTracker::new();
+        let now = Instant::now();
+
+        assert_eq!(
+            tracker.touch(1, TouchPhase::Started, (100.0, 100.0), now, false),
+            None
+        );
+        assert_eq!(
+            tracker.touch(2, TouchPhase::Started, (200.0, 100.0), now, false),
+            None
+        );
+        let pinch = tracker.touch(2, TouchPhase::Moved, (300.0, 100.0), now, false);
+        assert_eq!(
+            pinch,
+            Some(Gesture::Pinch {
+                factor: 2.0,
+                center: (200.0, 100.0)
+            })
+        );
+
+        // Lifting the fingers after a pinch is not a swipe
+        let later = now + Duration::from_millis(100);
+        assert_eq!(
+            tracker.touch(2, TouchPhase::Ended, (300.0, 100.0), later, false),
+            None
+        );
+        assert_eq!(
+            tracker.touch(1, TouchPhase::Ended, (400.0, 100.0), later, false),
+            None
+        );
+
+        // A fresh touch starts over
+        let swipe = stroke(
+            &mut tracker,
+            (400.0, 300.0),
+            (200.0, 300.0),
+            Duration::from_millis(150),
+            false,
+        );
+        assert_eq!(swipe, vec![Gesture::Swipe(1)]);
+    }
+}
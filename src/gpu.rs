@@ -0,0 +1,537 @@
+//! GPU render backend (feature `gpu`).
+//!
+//! Uploads the current image as a texture on the `pixels` wgpu device and
+//! draws it as a scaled quad, so moving between decoded images costs one
+//! upload and zooming or panning costs none. The `pixels` frame buffer is
+//! left transparent for the overlays and blended on top. Images larger
+//! than the device's texture limit, and the loading screen, go through the
+//! CPU path instead.
+
+use crate::backend::{CpuRenderer, Renderer, Style};
+use crate::config::ScalingFilter;
+use crate::render::{place, GridLayout, RenderResult};
+use crate::slot::ImageData;
+use crate::state::{Orientation, ViewState};
+use pixels::wgpu;
+use pixels::Pixels;
+use std::borrow::Cow;
+use std::sync::{Arc, Weak};
+
+/// Draws one textured quad. `rect` is left, top, width and height in clip
+/// space; texture coordinates are `uv_origin + x * uv_x + y * uv_y` for
+/// the quad corner (x, y), which carries the orientation.
+const SHADER: &str = r#"
+struct Quad {
+    rect: vec4<f32>,
+    uv_origin: vec2<f32>,
+    uv_x: vec2<f32>,
+    uv_y: vec2<f32>,
+    _padding: vec2<f32>,
+}
+
+@group(0) @binding(0) var<uniform> quad: Quad;
+@group(0) @binding(1) var image: texture_2d<f32>;
+@group(0) @binding(2) var image_sampler: sampler;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(0.0, 0.0),
+        vec2<f32>(1.0, 0.0),
+        vec2<f32>(0.0, 1.0),
+        vec2<f32>(0.0, 1.0),
+        vec2<f32>(1.0, 0.0),
+        vec2<f32>(1.0, 1.0),
+    );
+    let corner = corners[index];
+    var out: VertexOutput;
+    out.position = vec4<f32>(quad.rect.xy + corner * quad.rect.zw, 0.0, 1.0);
+    out.uv = quad.uv_origin + corner.x * quad.uv_x + corner.y * quad.uv_y;
+    return out;
+}
+
+// The image and backdrop, drawn opaque like the CPU path
+@fragment
+fn fs_opaque(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(textureSample(image, image_sampler, in.uv).rgb, 1.0);
+}
+
+// The overlay frame: transparent except for panels and text
+@fragment
+fn fs_overlay(in: VertexOutput) -> @location(0) vec4<f32> {
+    return textureSample(image, image_sampler, in.uv);
+}
+"#;
+
+/// Size of the `Quad` uniform in bytes
+const QUAD_SIZE: u64 = 48;
+
+/// A quad covering the whole window, texture upright
+const FULL_QUAD: [f32; 12] = [
+    -1.0, 1.0, 2.0, -2.0, // rect
+    0.0, 0.0, // uv_origin
+    1.0, 0.0, // uv_x
+    0.0, 1.0, // uv_y
+    0.0, 0.0, // padding
+];
+
+/// A texture holding uploaded pixels, and what they were uploaded from
+struct Upload {
+    /// The data (held weakly so the address isn't reused), plus the
+    /// animation frame or window size that picks out the pixels
+    source: (Weak<ImageData>, usize, usize),
+    texture: wgpu::Texture,
+}
+
+impl Upload {
+    fn is_for(&self, data: &Arc<ImageData>, a: usize, b: usize) -> bool {
+        let (made_for, x, y) = &self.source;
+        std::ptr::eq(made_for.as_ptr(), Arc::as_ptr(data)) && (*x, *y) == (a, b)
+    }
+}
+
+/// What the next present draws under the overlays
+struct Scene {
+    clear: wgpu::Color,
+    backdrop: Option<wgpu::BindGroup>,
+    image: wgpu::BindGroup,
+}
+
+/// Textured-quad renderer on the `pixels` device
+pub struct GpuRenderer {
+    opaque: wgpu::RenderPipeline,
+    overlay: wgpu::RenderPipeline,
+    layout: wgpu::BindGroupLayout,
+    linear: wgpu::Sampler,
+    nearest: wgpu::Sampler,
+    image_quad: wgpu::Buffer,
+    full_quad: wgpu::Buffer,
+    max_texture_size: u32,
+    image: Option<Upload>,
+    backdrop: Option<Upload>,
+    /// None when the frame buffer holds the whole picture (CPU fallback)
+    scene: Option<Scene>,
+}
+
+impl GpuRenderer {
+    pub fn new(pixels: &Pixels<'static>) -> Self {
+        let device = pixels.device();
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("fiv quad shader"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(SHADER)),
+        });
+
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("fiv quad bind group layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("fiv quad pipeline layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+
+        let format = pixels.render_texture_format();
+        let pipeline = |entry_point: &str, blend: wgpu::BlendState| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(entry_point),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point,
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format,
+                        blend: Some(blend),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            })
+        };
+        let opaque = pipeline("fs_opaque", wgpu::BlendState::REPLACE);
+        // Panels are drawn over a transparent frame, which leaves their
+        // color premultiplied
+        let overlay = pipeline("fs_overlay", wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING);
+
+        let sampler = |filter: wgpu::FilterMode| {
+            device.create_sampler(&wgpu::SamplerDescriptor {
+                label: Some("fiv sampler"),
+                address_mode_u: wgpu::AddressMode::ClampToEdge,
+                address_mode_v: wgpu::AddressMode::ClampToEdge,
+                mag_filter: filter,
+                min_filter: filter,
+                ..wgpu::SamplerDescriptor::default()
+            })
+        };
+        let quad_buffer = || {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("fiv quad"),
+                size: QUAD_SIZE,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            })
+        };
+        let full_quad = quad_buffer();
+        pixels
+            .queue()
+            .write_buffer(&full_quad, 0, &quad_bytes(FULL_QUAD));
+
+        Self {
+            opaque,
+            overlay,
+            layout,
+            linear: sampler(wgpu::FilterMode::Linear),
+            nearest: sampler(wgpu::FilterMode::Nearest),
+            image_quad: quad_buffer(),
+            full_quad,
+            max_texture_size: device.limits().max_texture_dimension_2d,
+            image: None,
+            backdrop: None,
+            scene: None,
+        }
+    }
+
+    fn bind_group(
+        &self,
+        device: &wgpu::Device,
+        quad: &wgpu::Buffer,
+        texture: &wgpu::Texture,
+        sampler: &wgpu::Sampler,
+    ) -> wgpu::BindGroup {
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("fiv quad bind group"),
+            layout: &self.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: quad.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+            ],
+        })
+    }
+}
+
+impl Renderer for GpuRenderer {
+    fn render(
+        &mut self,
+        pixels: &mut Pixels<'static>,
+        view: &ViewState,
+        image_data: Option<&Arc<ImageData>>,
+        style: &Style,
+    ) -> RenderResult {
+        let (win_w, win_h) = (view.window_width as usize, view.window_height as usize);
+        let fits = |d: &&Arc<ImageData>| {
+            d.width > 0 && d.height > 0 && d.width.max(d.height) <= self.max_texture_size
+        };
+        let drawable = win_w > 0 && win_h > 0 && view.look().is_identity();
+        let Some(data) = image_data.filter(fits).filter(|_| drawable) else {
+            self.scene = None;
+            return CpuRenderer.render(pixels, view, image_data, style);
+        };
+
+        let frame_index = view.frame_index % data.frame_count();
+        upload(
+            pixels,
+            &mut self.image,
+            data,
+            (frame_index, 0),
+            (data.width, data.height),
+            data.frame(frame_index),
+        );
+        let backdrop_pixels = style.backdrop.filter(|b| b.len() == win_w * win_h * 4);
+        match backdrop_pixels {
+            Some(backdrop) => upload(
+                pixels,
+                &mut self.backdrop,
+                data,
+                (win_w, win_h),
+                (view.window_width, view.window_height),
+                backdrop,
+            ),
+            None => self.backdrop = None,
+        }
+
+        let placement = place(data, win_w, win_h, &view.transform);
+        let (rot_w, rot_h) = view
+            .transform
+            .orientation
+            .display_dims(data.width as usize, data.height as usize);
+        let rect = [
+            (placement.origin.0 / win_w as f64 * 2.0 - 1.0) as f32,
+            (1.0 - placement.origin.1 / win_h as f64 * 2.0) as f32,
+            (rot_w as f64 * placement.scale / win_w as f64 * 2.0) as f32,
+            (-(rot_h as f64) * placement.scale / win_h as f64 * 2.0) as f32,
+        ];
+        let (origin, axis_x, axis_y) = uv_axes(view.transform.orientation);
+        let quad = [
+            rect[0], rect[1], rect[2], rect[3], origin[0], origin[1], axis_x[0], axis_x[1],
+            axis_y[0], axis_y[1], 0.0, 0.0,
+        ];
+        pixels
+            .queue()
+            .write_buffer(&self.image_quad, 0, &quad_bytes(quad));
+
+        let device = pixels.device();
+        let sampler = match style.filter {
+            ScalingFilter::Nearest => &self.nearest,
+            ScalingFilter::Bilinear | ScalingFilter::CatmullRom => &self.linear,
+        };
+        let image_texture = &self.image.as_ref().expect("just uploaded").texture;
+        let image = self.bind_group(device, &self.image_quad, image_texture, sampler);
+        let backdrop = self
+            .backdrop
+            .as_ref()
+            .map(|b| self.bind_group(device, &self.full_quad, &b.texture, &self.nearest));
+        self.scene = Some(Scene {
+            clear: clear_color(style.background, pixels.render_texture_format()),
+            backdrop,
+            image,
+        });
+
+        // Overlays go on a transparent frame
+        pixels.frame_mut().fill(0);
+
+        RenderResult {
+            quality: Some(data.effective_quality()),
+            pan: placement.pan,
+            scale: Some(placement.scale * data.width as f64 / data.full_width.max(1) as f64),
+        }
+    }
+
+    fn render_pair(
+        &mut self,
+        pixels: &mut Pixels<'static>,
+        view: &ViewState,
+        pinned: Option<&Arc<ImageData>>,
+        image_data: Option<&Arc<ImageData>>,
+        style: &Style,
+    ) -> (RenderResult, RenderResult) {
+        self.scene = None;
+        CpuRenderer.render_pair(pixels, view, pinned, image_data, style)
+    }
+
+    fn render_grid(
+        &mut self,
+        pixels: &mut Pixels<'static>,
+        view: &ViewState,
+        layout: &GridLayout,
+        cells: &[Option<Arc<ImageData>>],
+        cursor: usize,
+        style: &Style,
+    ) {
+        self.scene = None;
+        CpuRenderer.render_grid(pixels, view, layout, cells, cursor, style);
+    }
+
+    fn present(&mut self, pixels: &Pixels<'static>) -> Result<(), String> {
+        let Some(scene) = &self.scene else {
+            return pixels.render().map_err(|e| e.to_string());
+        };
+        pixels
+            .render_with(|encoder, target, context| {
+                let overlay = self.bind_group(
+                    &context.device,
+                    &self.full_quad,
+                    &context.texture,
+                    &self.nearest,
+                );
+                let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some("fiv image pass"),
+                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                        view: target,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(scene.clear),
+                            store: wgpu::StoreOp::Store,
+                        },
+                    })],
+                    depth_stencil_attachment: None,
+                    timestamp_writes: None,
+                    occlusion_query_set: None,
+                });
+                pass.set_pipeline(&self.opaque);
+                if let Some(backdrop) = &scene.backdrop {
+                    pass.set_bind_group(0, backdrop, &[]);
+                    pass.draw(0..6, 0..1);
+                }
+                pass.set_bind_group(0, &scene.image, &[]);
+                pass.draw(0..6, 0..1);
+                pass.set_pipeline(&self.overlay);
+                pass.set_bind_group(0, &overlay, &[]);
+                pass.draw(0..6, 0..1);
+                Ok(())
+            })
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// Upload `rgba` (`size` pixels) into `slot` unless it already holds the
+/// pixels for `data` and `key`, reusing the texture when the size matches
+fn upload(
+    pixels: &Pixels<'static>,
+    slot: &mut Option<Upload>,
+    data: &Arc<ImageData>,
+    key: (usize, usize),
+    size: (u32, u32),
+    rgba: &[u8],
+) {
+    if slot.as_ref().is_some_and(|u| u.is_for(data, key.0, key.1)) {
+        return;
+    }
+    let extent = wgpu::Extent3d {
+        width: size.0,
+        height: size.1,
+        depth_or_array_layers: 1,
+    };
+    let texture = match slot.take() {
+        Some(old) if old.texture.size() == extent => old.texture,
+        _ => pixels.device().create_texture(&wgpu::TextureDescriptor {
+            label: Some("fiv image"),
+            size: extent,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        }),
+    };
+    pixels.queue().write_texture(
+        wgpu::ImageCopyTexture {
+            texture: &texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+            aspect: wgpu::TextureAspect::All,
+        },
+        rgba,
+        wgpu::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: Some(4 * size.0),
+            rows_per_image: Some(size.1),
+        },
+        extent,
+    );
+    *slot = Some(Upload {
+        source: (Arc::downgrade(data), key.0, key.1),
+        texture,
+    });
+}
+
+/// Texture coordinates of the displayed image's top-left corner, and how
+/// they move along the display's x and y axes
+fn uv_axes(orientation: Orientation) -> ([f32; 2], [f32; 2], [f32; 2]) {
+    // On a 2x2 image the pixel indices of the corners are the corners'
+    // texture coordinates
+    let corner = |u, v| {
+        let (x, y) = orientation.source_coords(u, v, 2, 2);
+        [x as f32, y as f32]
+    };
+    let origin = corner(0, 0);
+    let (right, down) = (corner(1, 0), corner(0, 1));
+    (
+        origin,
+        [right[0] - origin[0], right[1] - origin[1]],
+        [down[0] - origin[0], down[1] - origin[1]],
+    )
+}
+
+/// The background color as a clear color, linearized for sRGB targets
+fn clear_color(color: [u8; 4], format: wgpu::TextureFormat) -> wgpu::Color {
+    let channel = |c: u8| {
+        let c = c as f64 / 255.0;
+        if !format.is_srgb() {
+            c
+        } else if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    wgpu::Color {
+        r: channel(color[0]),
+        g: channel(color[1]),
+        b: channel(color[2]),
+        a: 1.0,
+    }
+}
+
+fn quad_bytes(quad: [f32; 12]) -> Vec<u8> {
+    quad.iter().flat_map(|f| f.to_ne_bytes()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_uv_axes() {
+        assert_eq!(
+            uv_axes(Orientation::IDENTITY),
+            ([0.0, 0.0], [1.0, 0.0], [0.0, 1.0])
+        );
+        // Clockwise: the display's top-left is the source's bottom-left,
+        // and moving right on screen moves up the source
+        assert_eq!(
+            uv_axes(Orientation::IDENTITY.rotate_cw()),
+            ([0.0, 1.0], [0.0, -1.0], [1.0, 0.0])
+        );
+    }
+
+    #[test]
+    fn test_clear_color() {
+        let srgb = clear_color([255, 0, 128, 255], wgpu::TextureFormat::Bgra8UnormSrgb);
+        assert_eq!((srgb.r, srgb.g), (1.0, 0.0));
+        assert!((srgb.b - 0.2158).abs() < 0.001);
+        let unorm = clear_color([255, 0, 128, 255], wgpu::TextureFormat::Bgra8Unorm);
+        assert!((unorm.b - 128.0 / 255.0).abs() < 1e-9);
+    }
+}
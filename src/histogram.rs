@@ -0,0 +1,149 @@
+//! Histogram of an image's pixel values, for judging exposure.
+//!
+//! `compute` is a pure function over RGBA pixels; it spreads the counting
+//! over the rayon pool, but a 50MP image still takes a while, so the viewer
+//! runs it off the main thread and draws the result once it's ready.
+
+use crate::render::luminance;
+use crate::state::ChannelMode;
+use rayon::prelude::*;
+
+/// Pixels counted per rayon task
+const CHUNK_PIXELS: usize = 1 << 16;
+
+/// Counts of each 8-bit value per channel
+#[derive(Debug, Clone, PartialEq)]
+pub struct Histogram {
+    /// Red, green, blue and alpha
+    pub channels: [[u32; 256]; 4],
+    /// Rec. 709 luminance, as the luminance channel mode shows it
+    pub luminance: [u32; 256],
+}
+
+impl Histogram {
+    fn empty() -> Self {
+        Self {
+            channels: [[0; 256]; 4],
+            luminance: [0; 256],
+        }
+    }
+
+    fn merge(mut self, other: Self) -> Self {
+        for (mine, theirs) in self.channels.iter_mut().zip(&other.channels) {
+            for (a, b) in mine.iter_mut().zip(theirs) {
+                *a += b;
+            }
+        }
+        for (a, b) in self.luminance.iter_mut().zip(&other.luminance) {
+            *a += b;
+        }
+        self
+    }
+
+    /// The series to plot for a channel mode, each with its color: red,
+    /// green and blue for the image as it is, otherwise the one channel
+    /// being shown
+    pub fn series(&self, mode: ChannelMode) -> Vec<([u32; 256], [u8; 3])> {
+        const WHITE: [u8; 3] = [255, 255, 255];
+        let [red, green, blue, alpha] = self.channels;
+        match mode {
+            ChannelMode::Color => vec![
+                (red, [255, 0, 0]),
+                (green, [0, 255, 0]),
+                (blue, [0, 0, 255]),
+            ],
+            ChannelMode::Red => vec![(red, WHITE)],
+            ChannelMode::Green => vec![(green, WHITE)],
+            ChannelMode::Blue => vec![(blue, WHITE)],
+            ChannelMode::Alpha => vec![(alpha, WHITE)],
+            ChannelMode::Luminance => vec![(self.luminance, WHITE)],
+            ChannelMode::Invert => [
+                (red, [255, 0, 0]),
+                (green, [0, 255, 0]),
+                (blue, [0, 0, 255]),
+            ]
+            .into_iter()
+            .map(|(mut bins, color)| {
+                bins.reverse();
+                (bins, color)
+            })
+            .collect(),
+        }
+    }
+}
+
+/// Count the values of RGBA `pixels`
+pub fn compute(pixels: &[u8]) -> Histogram {
+    pixels
+        .par_chunks(CHUNK_PIXELS * 4)
+        .fold(Histogram::empty, |mut histogram, chunk| {
+            for pixel in chunk.chunks_exact(4) {
+                for (bins, &value) in histogram.channels.iter_mut().zip(pixel) {
+                    bins[value as usize] += 1;
+                }
+                histogram.luminance[luminance(pixel[0], pixel[1], pixel[2]) as usize] += 1;
+            }
+            histogram
+        })
+        .reduce(Histogram::empty, Histogram::merge)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A `width` x `height` image whose columns step through the gray
+    /// levels
+    fn gray_ramp(width: usize, height: usize) -> Vec<u8> {
+        (0..height)
+            .flat_map(|_| (0..width).flat_map(|x| [x as u8, x as u8, x as u8, 255]))
+            .collect()
+    }
+
+    #[test]
+    fn test_gradient() {
+        // Every level once per row
+        let histogram = compute(&gray_ramp(256, 3));
+        for bins in &histogram.channels[..3] {
+            assert!(bins.iter().all(|&count| count == 3));
+        }
+        assert!(histogram.luminance.iter().all(|&count| count == 3));
+        assert_eq!(histogram.channels[3][255], 256 * 3);
+        assert_eq!(histogram.channels[3].iter().sum::<u32>(), 256 * 3);
+    }
+
+    #[test]
+    fn test_chunks_add_up() {
+        // More pixels than one task counts, so the partial counts merge
+        let histogram = compute(&gray_ramp(256, 1024));
+        assert_eq!(histogram.channels[0][0], 1024);
+        assert_eq!(histogram.luminance[200], 1024);
+        assert_eq!(histogram.luminance.iter().sum::<u32>(), 256 * 1024);
+    }
+
+    #[test]
+    fn test_colors() {
+        let pixels = [255, 0, 0, 255, 0, 255, 0, 128];
+        let histogram = compute(&pixels);
+        assert_eq!(histogram.channels[0][255], 1);
+        assert_eq!(histogram.channels[0][0], 1);
+        assert_eq!(histogram.channels[3][128], 1);
+        assert_eq!(histogram.luminance[luminance(255, 0, 0) as usize], 1);
+        assert_eq!(histogram.luminance[luminance(0, 255, 0) as usize], 1);
+    }
+
+    #[test]
+    fn test_series() {
+        let histogram = compute(&[10, 20, 30, 40]);
+        assert_eq!(histogram.series(ChannelMode::Color).len(), 3);
+
+        let alpha = histogram.series(ChannelMode::Alpha);
+        assert_eq!(alpha.len(), 1);
+        assert_eq!(alpha[0].0[40], 1);
+
+        // Inverted colors mirror the counts
+        let inverted = histogram.series(ChannelMode::Invert);
+        assert_eq!(inverted[0].0[255 - 10], 1);
+        assert_eq!(inverted[2].0[255 - 30], 1);
+    }
+}
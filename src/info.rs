@@ -0,0 +1,165 @@
+//! Info mode - print image metadata and exit, without opening a window.
+//!
+//! `fiv --info FILE...` runs headers and EXIF through the same `Decoder`
+//! the viewer uses, so scripts (and tests) can check what fiv makes of a
+//! file. Archives list one entry per image inside.
+
+use fiv::archive;
+use fiv::decode::{format_file_size, read_image_info, Decoder};
+use fiv::slot::ImageMeta;
+use serde::Serialize;
+use std::path::PathBuf;
+
+/// What fiv found out about one image
+#[derive(Debug, Default, Serialize)]
+pub struct Entry {
+    pub path: String,
+    pub format: Option<&'static str>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub color: Option<String>,
+    pub pages: Option<usize>,
+    pub date: Option<String>,
+    pub file_size: Option<u64>,
+    /// Why the file couldn't be read, if it couldn't
+    pub error: Option<String>,
+}
+
+impl Entry {
+    /// Probe one image
+    fn of(meta: &ImageMeta, decoder: &Decoder) -> Self {
+        let path = meta.source.to_string();
+        let probe = match decoder.probe(meta) {
+            Ok(probe) => probe,
+            Err(err) => {
+                return Self {
+                    path,
+                    error: Some(err.to_string()),
+                    ..Self::default()
+                }
+            }
+        };
+        let info = read_image_info(&meta.source);
+        Self {
+            path,
+            format: Some(probe.format),
+            width: Some(probe.dimensions.0),
+            height: Some(probe.dimensions.1),
+            color: probe.color,
+            pages: Some(probe.pages),
+            date: info.date,
+            file_size: info.file_size,
+            error: None,
+        }
+    }
+
+    /// A failure that isn't about any one image, such as an unreadable
+    /// archive
+    fn failed(path: String, error: String) -> Self {
+        Self {
+            path,
+            error: Some(error),
+            ..Self::default()
+        }
+    }
+
+    /// Plain text: the path, then one indented line per known field
+    fn to_text(&self) -> String {
+        let mut text = self.path.clone();
+        let mut line = |label: &str, value: String| {
+            text.push_str(&format!("\n  {}: {}", label, value));
+        };
+        if let Some(error) = &self.error {
+            line("Error", error.clone());
+        }
+        if let Some(format) = self.format {
+            line("Format", format.to_string());
+        }
+        if let (Some(width), Some(height)) = (self.width, self.height) {
+            line("Dimensions", format!("{} x {}", width, height));
+        }
+        if let Some(color) = &self.color {
+            line("Color", color.clone());
+        }
+        if let Some(pages) = self.pages.filter(|&p| p > 1) {
+            line("Pages", pages.to_string());
+        }
+        if let Some(date) = &self.date {
+            line("Date", date.clone());
+        }
+        if let Some(size) = self.file_size {
+            line("File size", format_file_size(size));
+        }
+        text
+    }
+}
+
+/// Describe each path (every image inside an archive)
+pub fn describe(paths: &[PathBuf], decoder: &Decoder) -> Vec<Entry> {
+    let mut entries = Vec::with_capacity(paths.len());
+    for path in paths {
+        if !archive::is_archive(path) {
+            entries.push(Entry::of(&ImageMeta::new(path.clone()), decoder));
+            continue;
+        }
+        match archive::list_images(path, decoder) {
+            Ok(names) => entries.extend(
+                names
+                    .into_iter()
+                    .map(|name| Entry::of(&ImageMeta::archive_entry(path.clone(), name), decoder)),
+            ),
+            Err(err) => entries.push(Entry::failed(path.display().to_string(), err)),
+        }
+    }
+    entries
+}
+
+/// Print entries to stdout as text or a JSON array. Returns false if any
+/// image couldn't be read.
+pub fn print(entries: &[Entry], json: bool) -> bool {
+    if json {
+        let text = serde_json::to_string_pretty(entries).expect("entries are always serializable");
+        println!("{}", text);
+    } else {
+        for entry in entries {
+            println!("{}", entry.to_text());
+        }
+    }
+    entries.iter().all(|entry| entry.error.is_none())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_describe() {
+        let root = std::env::temp_dir().join(format!("fiv_info_{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        let png = root.join("a.png");
+        image::RgbImage::from_pixel(5, 3, image::Rgb([0, 0, 0]))
+            .save_with_format(&png, image::ImageFormat::Png)
+            .unwrap();
+        let missing = root.join("missing.png");
+
+        let entries = describe(&[png.clone(), missing], &Decoder::new());
+        assert_eq!(entries[0].format, Some("PNG"));
+        assert_eq!((entries[0].width, entries[0].height), (Some(5), Some(3)));
+        assert_eq!(entries[0].color.as_deref(), Some("Rgb8"));
+        assert!(entries[0].file_size.is_some());
+        assert!(entries[0].error.is_none());
+        assert!(entries[1].error.is_some());
+        assert_eq!(entries[1].format, None);
+
+        let text = entries[0].to_text();
+        assert!(text.starts_with(&png.display().to_string()));
+        assert!(text.contains("\n  Dimensions: 5 x 3"));
+
+        let json = serde_json::to_value(&entries).unwrap();
+        assert_eq!(json[0]["width"], 5);
+        assert!(json[1]["width"].is_null());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,686 @@
+//! Key bindings - data-driven mapping from physical keys to actions.
+//!
+//! The defaults live in `DEFAULT_BINDINGS`, `SHIFT_BINDINGS` and
+//! `CTRL_BINDINGS`; the `[keys]` config section adds or replaces entries by
+//! name, e.g. `KeyH = "NavigateLeft"` or `"Ctrl+KeyH" = "JumpHome"`. Key
+//! names are winit `KeyCode` variant names, optionally prefixed with
+//! `Shift+`, `Ctrl+`, `Alt+` and `Super+`. A key with modifiers held that
+//! has no binding of its own falls back to the plain key's binding.
+//! Configured sort directories are bound to the digit keys, as `SortInto1`
+//! to `SortInto9`. Ctrl with 1-5 rates the image, `SetRating1` to
+//! `SetRating5`, and Ctrl+0 clears its rating.
+
+use crate::xmp::MAX_RATING;
+use serde::de::value::{Error as ValueError, StrDeserializer};
+use serde::de::IntoDeserializer;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use winit::keyboard::{KeyCode, ModifiersState};
+
+/// Key actions for data-driven input handling
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum KeyAction {
+    NavigateRight,
+    NavigateLeft,
+    /// Step N images, back if negative (repeats while held)
+    Navigate(i32),
+    JumpHome,
+    JumpEnd,
+    /// Skip ahead by `input.jump_step` images (repeats while held)
+    JumpForward,
+    /// Skip back by `input.jump_step` images (repeats while held)
+    JumpBackward,
+    /// Jump N tenths of the way through the collection (0-9)
+    JumpToTenth(u8),
+    Reshuffle,
+    ToggleFullscreen,
+    ZoomIn,
+    ZoomOut,
+    ZoomReset,
+    ToggleActualSize,
+    RotateCw,
+    RotateCcw,
+    FlipHorizontal,
+    FlipVertical,
+    ToggleInfo,
+    /// Show or hide the status bar along the bottom edge
+    ToggleStatusBar,
+    /// Show or hide thumbnails of the neighbouring images along the bottom
+    /// edge
+    ToggleFilmstrip,
+    /// Switch between a solid and a blurred background around the image
+    ToggleBackground,
+    /// Cycle through the nearest, bilinear and Catmull-Rom scaling filters
+    CycleScalingFilter,
+    /// Show or hide the histogram, of the channel being shown if isolated
+    ToggleHistogram,
+    /// Show the coordinates and color of the pixel under the cursor;
+    /// clicking copies the color
+    ToggleInspector,
+    /// Show or hide the count of composed frames
+    ToggleDebug,
+    /// Cycle through showing the red, green, blue or alpha channel, the
+    /// luminance, the inverted colors and the image as it is
+    CycleChannelMode,
+    /// Rotate and flip the image file on disk as it is shown
+    SaveRotation,
+    /// Make the image the desktop wallpaper
+    SetWallpaper,
+    /// Resize the window to show the image 1:1, or as near as the monitor
+    /// allows
+    FitWindow,
+    /// Save a resized copy of the image as the `[export]` config says
+    Export,
+    /// Select a region with the mouse and save it as a PNG next to the file
+    Crop,
+    /// Brighten or darken the view by half a stop
+    ExposureUp,
+    ExposureDown,
+    /// Raise or lower the view gamma, lifting or deepening midtones
+    GammaUp,
+    GammaDown,
+    /// Steepen or flatten the tone curve about mid-gray
+    ContrastUp,
+    ContrastDown,
+    /// Clear exposure, gamma and contrast
+    ResetAdjustments,
+    /// Keep the adjustments when moving to another image
+    ToggleAdjustmentLock,
+    TogglePlayback,
+    Reload,
+    Delete,
+    /// Copy or move the current image into sort directory N (1-9)
+    SortInto(u8),
+    /// Rate the current image N stars (1-5) in its XMP sidecar, 0 clearing it
+    SetRating(u8),
+    /// Start or stop the slideshow
+    ToggleSlideshow,
+    /// Pause or resume the slideshow (Space, while one is running)
+    PauseSlideshow,
+    /// Picker mode: print the chosen path(s) and exit
+    Pick,
+    /// Add or remove the current image from the marked ones
+    ToggleMark,
+    /// Go to the next or previous marked image
+    NextMarked,
+    PreviousMarked,
+    /// Write the marked images' paths to the marks file, or stdout
+    WriteMarks,
+    /// Go to the next image that looks like the current one
+    NextSimilar,
+    /// Pin the current image, then show it beside another to compare
+    Compare,
+    /// Show the pinned image in place of the current one, or back; held,
+    /// keep swapping them
+    Blink,
+    /// Back to a single image (Escape, while comparing)
+    ExitCompare,
+    /// Switch between a grid of thumbnails of the whole collection and
+    /// the image under its cursor
+    ToggleGallery,
+    /// Type an image number and press Enter to go there
+    Goto,
+    /// Type part of a file name, or a glob, and press Enter to browse only
+    /// the matching images
+    Filter,
+    /// Browse the whole collection again (Escape, while filtered)
+    ClearFilter,
+    /// Jump to a random other image
+    RandomImage,
+    /// Browse the next or previous directory beside the current one
+    NextDirectory,
+    PreviousDirectory,
+    /// Show or hide the list of key bindings
+    ToggleHelp,
+    Quit,
+}
+
+impl KeyAction {
+    /// Whether a held key's repeats run the action again. Actions that
+    /// change files, or end picking, take a fresh press each time, so
+    /// holding the key can't work through image after image, or confirm
+    /// a delete by itself.
+    pub fn repeats(self) -> bool {
+        !matches!(
+            self,
+            KeyAction::Delete
+                | KeyAction::SortInto(_)
+                | KeyAction::SaveRotation
+                | KeyAction::Export
+                | KeyAction::SetWallpaper
+                | KeyAction::Pick
+        )
+    }
+}
+
+/// Actions of the keys held down, so repeats and the release go to the
+/// action the press started
+#[derive(Debug, Default)]
+pub struct HeldKeys {
+    actions: HashMap<KeyCode, KeyAction>,
+}
+
+impl HeldKeys {
+    /// A press of `key`, or a repeat if `repeat`, bound to `resolved` as
+    /// things stand. Returns the action to run: repeats keep the first
+    /// press's action, even if the modifiers changed since, and run it
+    /// again only if it `repeats`.
+    pub fn press(
+        &mut self,
+        key: KeyCode,
+        repeat: bool,
+        resolved: Option<KeyAction>,
+    ) -> Option<KeyAction> {
+        let action = match self.actions.get(&key) {
+            Some(&action) => action,
+            None => resolved?,
+        };
+        self.actions.insert(key, action);
+        (!repeat || action.repeats()).then_some(action)
+    }
+
+    /// A release of `key`, and the action its press started
+    pub fn release(&mut self, key: KeyCode) -> Option<KeyAction> {
+        self.actions.remove(&key)
+    }
+}
+
+/// Default key binding table - several keys may share an action
+const DEFAULT_BINDINGS: &[(KeyCode, KeyAction)] = &[
+    (KeyCode::ArrowRight, KeyAction::NavigateRight),
+    (KeyCode::KeyD, KeyAction::NavigateRight),
+    (KeyCode::Space, KeyAction::NavigateRight),
+    (KeyCode::ArrowLeft, KeyAction::NavigateLeft),
+    (KeyCode::KeyA, KeyAction::NavigateLeft),
+    (KeyCode::Home, KeyAction::JumpHome),
+    (KeyCode::End, KeyAction::JumpEnd),
+    (KeyCode::PageDown, KeyAction::JumpForward),
+    (KeyCode::PageUp, KeyAction::JumpBackward),
+    (KeyCode::KeyX, KeyAction::Reshuffle),
+    (KeyCode::KeyF, KeyAction::ToggleFullscreen),
+    (KeyCode::F11, KeyAction::ToggleFullscreen),
+    (KeyCode::Equal, KeyAction::ZoomIn),
+    (KeyCode::NumpadAdd, KeyAction::ZoomIn),
+    (KeyCode::Minus, KeyAction::ZoomOut),
+    (KeyCode::NumpadSubtract, KeyAction::ZoomOut),
+    (KeyCode::Digit0, KeyAction::ZoomReset),
+    (KeyCode::Numpad0, KeyAction::ZoomReset),
+    (KeyCode::Digit1, KeyAction::ToggleActualSize),
+    (KeyCode::Numpad1, KeyAction::ToggleActualSize),
+    (KeyCode::KeyR, KeyAction::RotateCw),
+    (KeyCode::KeyH, KeyAction::FlipHorizontal),
+    (KeyCode::KeyV, KeyAction::Compare),
+    (KeyCode::KeyK, KeyAction::Blink),
+    (KeyCode::KeyI, KeyAction::ToggleInfo),
+    (KeyCode::KeyB, KeyAction::ToggleStatusBar),
+    (KeyCode::KeyT, KeyAction::ToggleFilmstrip),
+    (KeyCode::KeyN, KeyAction::NextMarked),
+    (KeyCode::KeyP, KeyAction::TogglePlayback),
+    (KeyCode::F5, KeyAction::Reload),
+    (KeyCode::F12, KeyAction::ToggleDebug),
+    (KeyCode::KeyC, KeyAction::Crop),
+    (KeyCode::KeyE, KeyAction::ToggleInspector),
+    (KeyCode::KeyW, KeyAction::SetWallpaper),
+    (KeyCode::BracketRight, KeyAction::ExposureUp),
+    (KeyCode::BracketLeft, KeyAction::ExposureDown),
+    (KeyCode::Period, KeyAction::ContrastUp),
+    (KeyCode::Comma, KeyAction::ContrastDown),
+    (KeyCode::Backslash, KeyAction::ResetAdjustments),
+    (KeyCode::Delete, KeyAction::Delete),
+    (KeyCode::Enter, KeyAction::Pick),
+    (KeyCode::NumpadEnter, KeyAction::Pick),
+    (KeyCode::KeyM, KeyAction::ToggleMark),
+    (KeyCode::KeyS, KeyAction::ToggleSlideshow),
+    (KeyCode::KeyG, KeyAction::Goto),
+    (KeyCode::Tab, KeyAction::ToggleGallery),
+    (KeyCode::KeyZ, KeyAction::RandomImage),
+    (KeyCode::Semicolon, KeyAction::Goto),
+    (KeyCode::Slash, KeyAction::Filter),
+    (KeyCode::Escape, KeyAction::Quit),
+    (KeyCode::KeyQ, KeyAction::Quit),
+];
+
+/// Default bindings with Shift held, on top of `DEFAULT_BINDINGS`. Shift+digit
+/// jumps to that tenth of the collection.
+const SHIFT_BINDINGS: &[(KeyCode, KeyAction)] = &[
+    (KeyCode::ArrowRight, KeyAction::Navigate(10)),
+    (KeyCode::ArrowLeft, KeyAction::Navigate(-10)),
+    (KeyCode::KeyR, KeyAction::RotateCcw),
+    (KeyCode::KeyB, KeyAction::ToggleBackground),
+    (KeyCode::KeyH, KeyAction::ToggleHistogram),
+    (KeyCode::KeyC, KeyAction::CycleChannelMode),
+    (KeyCode::KeyE, KeyAction::Export),
+    (KeyCode::KeyN, KeyAction::PreviousMarked),
+    (KeyCode::KeyM, KeyAction::WriteMarks),
+    (KeyCode::KeyD, KeyAction::NextSimilar),
+    (KeyCode::KeyV, KeyAction::FlipVertical),
+    (KeyCode::KeyW, KeyAction::FitWindow),
+    (KeyCode::Slash, KeyAction::ToggleHelp),
+    (KeyCode::BracketRight, KeyAction::GammaUp),
+    (KeyCode::BracketLeft, KeyAction::GammaDown),
+    (KeyCode::Backslash, KeyAction::ToggleAdjustmentLock),
+];
+
+/// Default bindings with Ctrl held, on top of `DEFAULT_BINDINGS`
+const CTRL_BINDINGS: &[(KeyCode, KeyAction)] = &[
+    (KeyCode::KeyR, KeyAction::SaveRotation),
+    (KeyCode::KeyN, KeyAction::CycleScalingFilter),
+    (KeyCode::BracketRight, KeyAction::NextDirectory),
+    (KeyCode::BracketLeft, KeyAction::PreviousDirectory),
+];
+
+/// The number row, 0 to 9
+const DIGIT_KEYS: [KeyCode; 10] = [
+    KeyCode::Digit0,
+    KeyCode::Digit1,
+    KeyCode::Digit2,
+    KeyCode::Digit3,
+    KeyCode::Digit4,
+    KeyCode::Digit5,
+    KeyCode::Digit6,
+    KeyCode::Digit7,
+    KeyCode::Digit8,
+    KeyCode::Digit9,
+];
+
+/// The numpad digits, 0 to 9
+const NUMPAD_KEYS: [KeyCode; 10] = [
+    KeyCode::Numpad0,
+    KeyCode::Numpad1,
+    KeyCode::Numpad2,
+    KeyCode::Numpad3,
+    KeyCode::Numpad4,
+    KeyCode::Numpad5,
+    KeyCode::Numpad6,
+    KeyCode::Numpad7,
+    KeyCode::Numpad8,
+    KeyCode::Numpad9,
+];
+
+/// Runtime key binding table - maps physical keys, with the modifiers held,
+/// to actions
+#[derive(Debug, Clone)]
+pub struct KeyBindings {
+    map: HashMap<(KeyCode, ModifiersState), KeyAction>,
+}
+
+impl KeyBindings {
+    /// Build the default bindings, digit keys for the first `sort_dirs`
+    /// sort directories, then overrides from the `[keys]` config section.
+    /// Unknown key or action names are reported and skipped.
+    pub fn with_overrides(overrides: &BTreeMap<String, String>, sort_dirs: usize) -> Self {
+        let plain = ModifiersState::empty();
+        let shift = ModifiersState::SHIFT;
+        let ctrl = ModifiersState::CONTROL;
+        let mut map: HashMap<(KeyCode, ModifiersState), KeyAction> = DEFAULT_BINDINGS
+            .iter()
+            .map(|&(key, action)| ((key, plain), action))
+            .chain(
+                SHIFT_BINDINGS
+                    .iter()
+                    .map(|&(key, action)| ((key, shift), action)),
+            )
+            .chain(
+                CTRL_BINDINGS
+                    .iter()
+                    .map(|&(key, action)| ((key, ctrl), action)),
+            )
+            .collect();
+        for (n, key) in DIGIT_KEYS.iter().enumerate() {
+            map.insert((*key, shift), KeyAction::JumpToTenth(n as u8));
+        }
+        // Sort directories take keys 1-9
+        for (n, key) in DIGIT_KEYS[1..].iter().take(sort_dirs).enumerate() {
+            map.insert((*key, plain), KeyAction::SortInto(n as u8 + 1));
+        }
+        for n in 0..=MAX_RATING {
+            let action = KeyAction::SetRating(n);
+            map.insert((DIGIT_KEYS[n as usize], ctrl), action);
+            map.insert((NUMPAD_KEYS[n as usize], ctrl), action);
+        }
+
+        for (key_name, action_name) in overrides {
+            match (parse_binding(key_name), parse_action(action_name)) {
+                (Some(binding), Some(action)) => {
+                    map.insert(binding, action);
+                }
+                (None, _) => eprintln!("Warning: unknown key '{}' in [keys], ignored", key_name),
+                (_, None) => eprintln!(
+                    "Warning: unknown action '{}' for key '{}' in [keys], ignored",
+                    action_name, key_name
+                ),
+            }
+        }
+
+        Self { map }
+    }
+
+    /// The bindings grouped by action, for the help overlay: each action's
+    /// keys, comma-separated, and its name. Sorted by action name.
+    pub fn help(&self) -> Vec<(String, String)> {
+        let mut by_action: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for (&(key, modifiers), action) in &self.map {
+            by_action
+                .entry(format!("{:?}", action))
+                .or_default()
+                .push(binding_name(key, modifiers));
+        }
+        by_action
+            .into_iter()
+            .map(|(action, mut keys)| {
+                keys.sort();
+                (keys.join(", "), action)
+            })
+            .collect()
+    }
+
+    /// Action bound to a key with the given modifiers held, or else to
+    /// the plain key, if any
+    pub fn lookup(&self, key: KeyCode, modifiers: ModifiersState) -> Option<KeyAction> {
+        self.map
+            .get(&(key, modifiers))
+            .or_else(|| self.map.get(&(key, ModifiersState::empty())))
+            .copied()
+    }
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        Self::with_overrides(&BTreeMap::new(), 0)
+    }
+}
+
+/// The number a number row or numpad key stands for
+pub fn digit(key: KeyCode) -> Option<u8> {
+    DIGIT_KEYS
+        .iter()
+        .position(|&k| k == key)
+        .or_else(|| NUMPAD_KEYS.iter().position(|&k| k == key))
+        .map(|n| n as u8)
+}
+
+/// Parse a winit `KeyCode` variant name such as "KeyH" or "ArrowLeft"
+pub fn parse_key(name: &str) -> Option<KeyCode> {
+    let deserializer: StrDeserializer<ValueError> = name.into_deserializer();
+    KeyCode::deserialize(deserializer).ok()
+}
+
+/// Name of a key with modifiers, as `parse_binding` reads it
+pub fn binding_name(key: KeyCode, modifiers: ModifiersState) -> String {
+    let mut name = String::new();
+    for (flag, prefix) in [
+        (ModifiersState::CONTROL, "Ctrl+"),
+        (ModifiersState::ALT, "Alt+"),
+        (ModifiersState::SUPER, "Super+"),
+        (ModifiersState::SHIFT, "Shift+"),
+    ] {
+        if modifiers.contains(flag) {
+            name.push_str(prefix);
+        }
+    }
+    name.push_str(&format!("{:?}", key));
+    name
+}
+
+/// Parse a key name with optional modifier prefixes, such as
+/// "Shift+ArrowRight" or "Ctrl+Alt+KeyH"
+pub fn parse_binding(name: &str) -> Option<(KeyCode, ModifiersState)> {
+    let mut parts: Vec<&str> = name.split('+').collect();
+    let key = parse_key(parts.pop()?)?;
+    let mut modifiers = ModifiersState::empty();
+    for part in parts {
+        modifiers |= match part {
+            "Shift" => ModifiersState::SHIFT,
+            "Ctrl" | "Control" => ModifiersState::CONTROL,
+            "Alt" => ModifiersState::ALT,
+            "Super" => ModifiersState::SUPER,
+            _ => return None,
+        };
+    }
+    Some((key, modifiers))
+}
+
+/// Parse a `KeyAction` variant name such as "NavigateLeft", or one with a
+/// number: "Navigate10", "Navigate-10", "JumpToTenth3", "SortInto3" for
+/// a sort directory, or "SetRating3"
+pub fn parse_action(name: &str) -> Option<KeyAction> {
+    if let Some(n) = name.strip_prefix("SetRating") {
+        return match n.parse() {
+            Ok(n @ 0..=MAX_RATING) => Some(KeyAction::SetRating(n)),
+            _ => None,
+        };
+    }
+    if let Some(n) = name.strip_prefix("SortInto") {
+        return match n.parse() {
+            Ok(n @ 1..=9) => Some(KeyAction::SortInto(n)),
+            _ => None,
+        };
+    }
+    if let Some(n) = name.strip_prefix("JumpToTenth") {
+        return match n.parse() {
+            Ok(n @ 0..=9) => Some(KeyAction::JumpToTenth(n)),
+            _ => None,
+        };
+    }
+    // "NavigateLeft" and "NavigateRight" fall through to the plain names
+    if let Some(Ok(n)) = name.strip_prefix("Navigate").map(str::parse) {
+        return Some(KeyAction::Navigate(n));
+    }
+    let deserializer: StrDeserializer<ValueError> = name.into_deserializer();
+    KeyAction::deserialize(deserializer).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NONE: ModifiersState = ModifiersState::empty();
+
+    #[test]
+    fn test_defaults() {
+        let bindings = KeyBindings::default();
+
+        // Multiple keys share an action
+        assert_eq!(
+            bindings.lookup(KeyCode::ArrowRight, NONE),
+            Some(KeyAction::NavigateRight)
+        );
+        assert_eq!(
+            bindings.lookup(KeyCode::KeyD, NONE),
+            Some(KeyAction::NavigateRight)
+        );
+        assert_eq!(bindings.lookup(KeyCode::KeyY, NONE), None);
+    }
+
+    #[test]
+    fn test_destructive_actions_ignore_repeats() {
+        assert!(!KeyAction::Delete.repeats());
+        assert!(!KeyAction::SaveRotation.repeats());
+        assert!(!KeyAction::Pick.repeats());
+        assert!(KeyAction::NavigateRight.repeats());
+        assert!(KeyAction::ExposureUp.repeats());
+    }
+
+    #[test]
+    fn test_sort_ignores_repeats() {
+        let bindings = KeyBindings::with_overrides(&BTreeMap::new(), 3);
+        let resolved = bindings.lookup(KeyCode::Digit2, NONE);
+        let mut held = HeldKeys::default();
+
+        // Holding the digit sorts the image once, not again per repeat
+        assert_eq!(
+            held.press(KeyCode::Digit2, false, resolved),
+            Some(KeyAction::SortInto(2))
+        );
+        assert_eq!(held.press(KeyCode::Digit2, true, resolved), None);
+        assert_eq!(held.press(KeyCode::Digit2, true, resolved), None);
+        assert_eq!(held.release(KeyCode::Digit2), Some(KeyAction::SortInto(2)));
+        // A fresh press sorts again
+        assert_eq!(
+            held.press(KeyCode::Digit2, false, resolved),
+            Some(KeyAction::SortInto(2))
+        );
+
+        // Navigation repeats, keeping the action the press started
+        assert_eq!(
+            held.press(KeyCode::ArrowRight, false, Some(KeyAction::NavigateRight)),
+            Some(KeyAction::NavigateRight)
+        );
+        assert_eq!(
+            held.press(KeyCode::ArrowRight, true, Some(KeyAction::Quit)),
+            Some(KeyAction::NavigateRight)
+        );
+    }
+
+    #[test]
+    fn test_overrides() {
+        let overrides: BTreeMap<String, String> = [
+            ("KeyH", "NavigateLeft"),
+            ("KeyL", "NavigateRight"),
+            ("KeyA", "Quit"),
+            ("NoSuchKey", "Quit"),
+            ("KeyJ", "NoSuchAction"),
+        ]
+        .iter()
+        .map(|(k, a)| (k.to_string(), a.to_string()))
+        .collect();
+
+        let bindings = KeyBindings::with_overrides(&overrides, 0);
+
+        assert_eq!(
+            bindings.lookup(KeyCode::KeyH, NONE),
+            Some(KeyAction::NavigateLeft)
+        );
+        assert_eq!(
+            bindings.lookup(KeyCode::KeyL, NONE),
+            Some(KeyAction::NavigateRight)
+        );
+        // Overrides replace defaults, other defaults remain
+        assert_eq!(bindings.lookup(KeyCode::KeyA, NONE), Some(KeyAction::Quit));
+        assert_eq!(
+            bindings.lookup(KeyCode::ArrowLeft, NONE),
+            Some(KeyAction::NavigateLeft)
+        );
+        // Invalid entries are ignored
+        assert_eq!(bindings.lookup(KeyCode::KeyJ, NONE), None);
+    }
+
+    #[test]
+    fn test_parse_names() {
+        assert_eq!(parse_key("F11"), Some(KeyCode::F11));
+        assert_eq!(parse_key("h"), None);
+        assert_eq!(
+            parse_action("ToggleFullscreen"),
+            Some(KeyAction::ToggleFullscreen)
+        );
+        assert_eq!(parse_action("SortInto3"), Some(KeyAction::SortInto(3)));
+        assert_eq!(parse_action("SetRating0"), Some(KeyAction::SetRating(0)));
+        assert_eq!(parse_action("SetRating6"), None);
+        assert_eq!(parse_action("SortInto0"), None);
+        assert_eq!(parse_action("SortInto"), None);
+        assert_eq!(parse_action("JumpForward"), Some(KeyAction::JumpForward));
+        assert_eq!(parse_action("Navigate-10"), Some(KeyAction::Navigate(-10)));
+        assert_eq!(parse_action("NavigateLeft"), Some(KeyAction::NavigateLeft));
+        assert_eq!(parse_action("JumpToTenth10"), None);
+    }
+
+    #[test]
+    fn test_modifiers() {
+        let overrides: BTreeMap<String, String> =
+            [("Ctrl+Home", "Quit"), ("Shift+Alt+KeyL", "Navigate-3")]
+                .iter()
+                .map(|(k, a)| (k.to_string(), a.to_string()))
+                .collect();
+        let bindings = KeyBindings::with_overrides(&overrides, 0);
+        let shift = ModifiersState::SHIFT;
+
+        assert_eq!(
+            bindings.lookup(KeyCode::ArrowRight, shift),
+            Some(KeyAction::Navigate(10))
+        );
+        assert_eq!(
+            bindings.lookup(KeyCode::Digit3, shift),
+            Some(KeyAction::JumpToTenth(3))
+        );
+        assert_eq!(
+            bindings.lookup(KeyCode::Home, ModifiersState::CONTROL),
+            Some(KeyAction::Quit)
+        );
+        assert_eq!(
+            bindings.lookup(KeyCode::KeyL, shift | ModifiersState::ALT),
+            Some(KeyAction::Navigate(-3))
+        );
+        // Without a binding of their own, modified keys act as plain ones
+        assert_eq!(
+            bindings.lookup(KeyCode::Home, NONE),
+            Some(KeyAction::JumpHome)
+        );
+        assert_eq!(
+            bindings.lookup(KeyCode::KeyD, ModifiersState::CONTROL),
+            Some(KeyAction::NavigateRight)
+        );
+        assert_eq!(bindings.lookup(KeyCode::KeyL, shift), None);
+
+        assert_eq!(parse_binding("Hyper+KeyL"), None);
+        let ctrl_shift = ModifiersState::CONTROL | shift;
+        assert_eq!(binding_name(KeyCode::KeyL, ctrl_shift), "Ctrl+Shift+KeyL");
+        assert_eq!(
+            parse_binding(&binding_name(KeyCode::KeyL, ctrl_shift)),
+            Some((KeyCode::KeyL, ctrl_shift))
+        );
+        assert_eq!(parse_binding("Shift+"), None);
+    }
+
+    #[test]
+    fn test_help() {
+        let help = KeyBindings::default().help();
+        let quit = help.iter().find(|(_, action)| action == "Quit").unwrap();
+        assert_eq!(quit.0, "Escape, KeyQ");
+        let tenth = help.iter().find(|(_, action)| action == "JumpToTenth(3)");
+        assert_eq!(tenth.unwrap().0, "Shift+Digit3");
+    }
+
+    #[test]
+    fn test_digit() {
+        assert_eq!(digit(KeyCode::Digit0), Some(0));
+        assert_eq!(digit(KeyCode::Digit7), Some(7));
+        assert_eq!(digit(KeyCode::Numpad7), Some(7));
+        assert_eq!(digit(KeyCode::KeyG), None);
+    }
+
+    #[test]
+    fn test_sort_keys() {
+        let overrides: BTreeMap<String, String> = [("Digit2", "Quit")]
+            .iter()
+            .map(|(k, a)| (k.to_string(), a.to_string()))
+            .collect();
+        let bindings = KeyBindings::with_overrides(&overrides, 3);
+
+        // Sort directories take over the digit keys they need
+        assert_eq!(
+            bindings.lookup(KeyCode::Digit1, NONE),
+            Some(KeyAction::SortInto(1))
+        );
+        assert_eq!(
+            bindings.lookup(KeyCode::Digit3, NONE),
+            Some(KeyAction::SortInto(3))
+        );
+        assert_eq!(bindings.lookup(KeyCode::Digit4, NONE), None);
+        // Ctrl with a digit rates the image instead
+        let ctrl = ModifiersState::CONTROL;
+        assert_eq!(
+            bindings.lookup(KeyCode::Digit3, ctrl),
+            Some(KeyAction::SetRating(3))
+        );
+        assert_eq!(
+            bindings.lookup(KeyCode::Numpad0, ctrl),
+            Some(KeyAction::SetRating(0))
+        );
+        assert_eq!(bindings.lookup(KeyCode::Digit6, ctrl), None);
+        // Overrides still win
+        assert_eq!(
+            bindings.lookup(KeyCode::Digit2, NONE),
+            Some(KeyAction::Quit)
+        );
+    }
+}
@@ -0,0 +1,55 @@
+//! Fiv - A high-performance image viewer (Fast Image Viewer).
+//!
+//! Architecture overview:
+//! - Lock-free image slots for zero-contention access
+//! - Input state tracking for immediate press-and-hold response
+//! - Pure render functions (no side effects)
+//! - Background preloader that never blocks the main thread
+//!
+//! This library is everything but the window. The core of it:
+//! - [`config`]: user settings, as read from `config.toml`
+//! - [`decode`]: finding images and decoding them at a quality tier
+//! - [`slot`]: one image's metadata and decoded pixels
+//! - [`store`]: every image in the collection, under a memory budget
+//! - [`preload`]: the background thread filling the store around the
+//!   current image
+//! - [`state`]: what is being viewed, and the state shared with the
+//!   preloader
+//! - [`render`]: drawing images and overlays into an RGBA frame
+//!
+//! - [`app`]: the viewer's window and what input does to it
+//!
+//! The other modules support these, and are public for the tools built on
+//! them. The `fiv` binary parses the command line, runs the subcommands and
+//! starts the event loop.
+
+pub mod app;
+pub mod archive;
+pub mod backend;
+pub mod cache;
+mod color;
+pub mod config;
+pub mod decode;
+pub mod export;
+pub mod external;
+pub mod filter;
+pub mod formats;
+pub mod gesture;
+#[cfg(feature = "gpu")]
+pub mod gpu;
+pub mod histogram;
+pub mod keys;
+pub mod metrics;
+pub mod ops;
+pub mod phash;
+pub mod preload;
+#[cfg(unix)]
+pub mod remote;
+pub mod render;
+pub mod session;
+pub mod slot;
+pub mod state;
+pub mod store;
+pub mod title;
+pub mod watch;
+pub mod xmp;
@@ -1,366 +1,504 @@
-//! Fiv - A high-performance image viewer (Fast Image Viewer).
-//!
-//! Architecture overview:
-//! - Lock-free image slots for zero-contention access
-//! - Input state tracking for immediate press-and-hold response
-//! - Pure render functions (no side effects)
-//! - Background preloader that never blocks the main thread
-
-mod config;
-mod decode;
-mod preload;
-mod render;
-mod slot;
-mod state;
-mod store;
-
-use clap::Parser;
-use config::{Config, QualityTier};
-use decode::{scan_directory, Decoder};
-use pixels::{Pixels, SurfaceTexture};
-use preload::{create_store_fast, spawn_preloader};
-use render::render_image;
-use state::{InputState, SharedState, ViewState};
+//! Fiv - the command line: parses the arguments, runs the subcommands and
+//! starts the viewer from the `fiv` library.
+
+mod bench;
+mod info;
+mod thumbs;
+
+use clap::{Parser, Subcommand};
+#[cfg(unix)]
+use fiv::app::absolute_paths;
+use fiv::app::{
+    collect_images, expand_collection, start_preloading, write_paths, App, ScanArgs, UserEvent,
+};
+use fiv::cache::ThumbnailCache;
+use fiv::config::{Config, QualityTier, SortAction};
+use fiv::decode::Decoder;
+use fiv::preload::create_store_fast;
+#[cfg(unix)]
+use fiv::remote::{self, HeldRequests, Launch, RemoteCommand};
+use fiv::session::{self, Session, WindowGeometry};
+use fiv::store::MemoryBudget;
+use fiv::title::TitleFormat;
+use fiv::watch::watch_directories;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use store::{ImageStore, MemoryBudget};
-use winit::application::ApplicationHandler;
-use winit::dpi::LogicalSize;
-use winit::event::{ElementState, WindowEvent};
-use winit::event_loop::{ActiveEventLoop, ControlFlow, EventLoop};
-use winit::keyboard::{KeyCode, PhysicalKey};
-use winit::window::{Window, WindowId};
-
-#[derive(Parser, Debug)]
+use std::time::Duration;
+use winit::event_loop::EventLoop;
+
+#[derive(Parser, Debug, Clone)]
 #[command(name = "fiv")]
 #[command(about = "A high-performance image viewer", long_about = None)]
 struct Args {
-    #[arg(default_value = ".")]
-    directory: PathBuf,
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    browse: ScanArgs,
+
+    /// Seed for --shuffle, runtime reshuffles and random jumps (random if
+    /// omitted)
+    #[arg(long, value_name = "N")]
+    seed: Option<u64>,
+
+    /// Detect images without a known extension by reading their header
+    #[arg(long)]
+    sniff: bool,
+
+    /// Pick up images added to or removed from the browsed directories
+    #[arg(long)]
+    watch: bool,
+
+    /// Directories the keys 1-9 sort the current image into, in order
+    /// (replaces `files.sort_into` from the config)
+    #[arg(long, value_name = "DIRS", value_delimiter = ',')]
+    sort_into: Option<Vec<PathBuf>>,
+
+    /// Whether sorting copies or moves images [default: copy]
+    #[arg(long, value_enum, value_name = "ACTION")]
+    sort_action: Option<SortAction>,
+
+    /// Act as a file picker: Enter prints the current image's path (or
+    /// those marked with M) to stdout and exits; Escape exits with status 1
+    #[arg(long)]
+    pick: bool,
+
+    /// Write the paths of images marked with M here on exit (one per line)
+    /// instead of to stdout
+    #[arg(long, value_name = "PATH")]
+    marks_file: Option<PathBuf>,
+
+    /// Start a slideshow, advancing every SECONDS [default: from config, 5]
+    #[arg(long, value_name = "SECONDS", num_args = 0..=1, require_equals = true)]
+    slideshow: Option<Option<f64>>,
+
+    /// Start in fullscreen
+    #[arg(short, long)]
+    fullscreen: bool,
+
+    /// Resize the window to the image, and to each one moved to unless
+    /// `view.refit_window` is off
+    #[arg(long)]
+    fit_window: bool,
+
+    /// Window size and position as WxH or WxH+X+Y, in place of the
+    /// configured size and the remembered window
+    #[arg(long, value_name = "GEOMETRY")]
+    geometry: Option<WindowGeometry>,
+
+    /// Config file to use instead of $XDG_CONFIG_HOME/fiv/config.toml
+    #[arg(long, value_name = "PATH")]
+    config: Option<PathBuf>,
+
+    /// Print each file's format, dimensions, color type, date and size,
+    /// then exit without opening a window
+    #[arg(long)]
+    info: bool,
+
+    /// With --info, print a JSON array instead of text
+    #[arg(long, requires = "info")]
+    json: bool,
+
+    /// Print the effective configuration as TOML and exit
+    #[arg(long)]
+    dump_config: bool,
+
+    /// Send a command to the viewer started last, then exit: next [N],
+    /// prev [N], goto N, first, last, open PATH, quit or query-status
+    #[arg(long, value_name = "CMD", num_args = 1..=2)]
+    remote: Option<Vec<String>>,
+
+    /// Open the paths in the viewer already running, if there's one, and
+    /// exit (as `input.single_instance` in the config)
+    #[arg(long)]
+    single_instance: bool,
 }
 
-/// Key actions for data-driven input handling
-#[derive(Clone, Copy)]
-enum KeyAction {
-    NavigateRight,
-    NavigateLeft,
-    JumpHome,
-    JumpEnd,
-    Quit,
+/// Something to do other than browsing
+#[derive(Subcommand, Debug, Clone)]
+enum Command {
+    /// Decode every image in a directory and report how fast it went,
+    /// without opening a window
+    Bench(BenchArgs),
+    /// Store thumbnails for every image in a directory not already in the
+    /// thumbnail cache, so the gallery opens quickly
+    Thumbs(ThumbsArgs),
 }
 
-/// Key binding table - maps physical keys to actions
-const KEY_BINDINGS: &[(KeyCode, KeyAction)] = &[
-    (KeyCode::ArrowRight, KeyAction::NavigateRight),
-    (KeyCode::KeyD, KeyAction::NavigateRight),
-    (KeyCode::Space, KeyAction::NavigateRight),
-    (KeyCode::ArrowLeft, KeyAction::NavigateLeft),
-    (KeyCode::KeyA, KeyAction::NavigateLeft),
-    (KeyCode::Home, KeyAction::JumpHome),
-    (KeyCode::End, KeyAction::JumpEnd),
-    (KeyCode::Escape, KeyAction::Quit),
-    (KeyCode::KeyQ, KeyAction::Quit),
-];
-
-fn lookup_key_action(key: KeyCode) -> Option<KeyAction> {
-    KEY_BINDINGS
-        .iter()
-        .find(|(k, _)| *k == key)
-        .map(|(_, action)| *action)
-}
+#[derive(clap::Args, Debug, Clone)]
+struct BenchArgs {
+    /// Directory of images to decode (with fiv's -r, --sort and the like
+    /// given before `bench`)
+    dir: PathBuf,
 
-/// Initialized window state - created once window is ready
-struct WindowState {
-    window: Arc<Window>,
-    pixels: Pixels<'static>,
-    view_state: ViewState,
-    _preloader_handle: std::thread::JoinHandle<()>,
-}
+    /// Quality tier to decode at
+    #[arg(long, value_enum, default_value = "full")]
+    tier: QualityTier,
 
-impl WindowState {
-    fn create(
-        event_loop: &ActiveEventLoop,
-        config: &Config,
-        store: &Arc<ImageStore>,
-        shared_state: &Arc<SharedState>,
-        decoder: &Arc<Decoder>,
-    ) -> Self {
-        let window_attributes = Window::default_attributes()
-            .with_title("Fiv - Loading...")
-            .with_inner_size(LogicalSize::new(
-                config.render.default_width,
-                config.render.default_height,
-            ));
-
-        let window = Arc::new(
-            event_loop
-                .create_window(window_attributes)
-                .expect("Failed to create window"),
-        );
+    /// Decode threads [default: as many as the preloader uses]
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u16).range(1..))]
+    threads: Option<u16>,
+
+    /// Print the results as JSON instead of text
+    #[arg(long)]
+    json: bool,
+}
 
-        let size = window.inner_size();
-        let surface_texture = SurfaceTexture::new(size.width, size.height, Arc::clone(&window));
-        let pixels = Pixels::new(size.width, size.height, surface_texture)
-            .expect("Failed to create pixel buffer");
+#[derive(clap::Args, Debug, Clone)]
+struct ThumbsArgs {
+    /// Directory of images to make thumbnails for
+    dir: PathBuf,
 
-        let view_state = ViewState::new(store.len(), size.width, size.height);
+    /// Decode threads [default: as many as the preloader uses]
+    #[arg(short, long, value_name = "N", value_parser = clap::value_parser!(u16).range(1..))]
+    jobs: Option<u16>,
+}
 
-        // Load first image synchronously for immediate display
-        if let Some(slot) = store.get(0) {
-            if let Some(data) = decoder.decode(&slot.meta.path, QualityTier::Full) {
-                store.insert(0, data);
+/// `--remote`: send a command to a running viewer, printing the status it
+/// answers query-status with. Returns the exit status.
+#[cfg(unix)]
+fn run_remote(words: &[String]) -> i32 {
+    let result = RemoteCommand::parse(words).and_then(|command| {
+        // The viewer has a working directory of its own
+        let command = match command {
+            RemoteCommand::Open { paths } => RemoteCommand::Open {
+                paths: absolute_paths(&paths),
+            },
+            command => command,
+        };
+        let reply = remote::send(&command)?;
+        match reply.error {
+            Some(err) => Err(err),
+            None if command == RemoteCommand::QueryStatus => {
+                println!("{}", serde_json::to_string(&reply).unwrap_or_default());
+                Ok(())
             }
+            None => Ok(()),
         }
-
-        // Spawn preloader after first image
-        let preloader_handle = spawn_preloader(
-            Arc::clone(store),
-            Arc::clone(shared_state),
-            Arc::clone(decoder),
-            config.clone(),
-        );
-
-        Self {
-            window,
-            pixels,
-            view_state,
-            _preloader_handle: preloader_handle,
+    });
+    match result {
+        Ok(()) => 0,
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            1
         }
     }
+}
 
-    fn render(&mut self, store: &ImageStore, config: &Config) {
-        let frame = self.pixels.frame_mut();
-        let image_data = store.read(self.view_state.current_index);
-
-        let result = render_image(
-            image_data.as_ref(),
-            frame,
-            self.view_state.window_width,
-            self.view_state.window_height,
-            config.render.background_color,
-        );
-
-        match result.quality {
-            Some(quality) => self.view_state.render_complete(quality),
-            None => self.view_state.needs_render = true,
-        }
+/// `--remote` talks to the viewer over a Unix socket, which other
+/// platforms don't have
+#[cfg(not(unix))]
+fn run_remote(_words: &[String]) -> i32 {
+    eprintln!("Error: --remote is not supported on this platform");
+    1
+}
 
-        let _ = self.pixels.render();
+fn main() {
+    let args = Args::parse();
+    if let Some(words) = &args.remote {
+        std::process::exit(run_remote(words));
     }
 
-    fn update_title(&self, store: &ImageStore) {
-        let filename = store
-            .get(self.view_state.current_index)
-            .and_then(|slot| slot.meta.path.file_name())
-            .map(|s| s.to_string_lossy().to_string())
-            .unwrap_or_default();
-
-        self.window.set_title(&self.view_state.title(&filename));
+    let mut config = Config::load(args.config.as_deref()).unwrap_or_else(|err| {
+        eprintln!("Error: {}", err);
+        std::process::exit(1);
+    });
+    config.render.fullscreen |= args.fullscreen;
+    config.view.fit_window |= args.fit_window;
+    // A scripted geometry neither uses the remembered window nor replaces it
+    if args.geometry.is_some() {
+        config.view.remember_window = false;
     }
-
-    fn handle_resize(&mut self, width: u32, height: u32) {
-        self.view_state.resize(width, height);
-        let _ = self.pixels.resize_surface(width, height);
-        let _ = self.pixels.resize_buffer(width, height);
+    if let Some(Some(seconds)) = args.slideshow {
+        config.view.slideshow_interval = Duration::try_from_secs_f64(seconds)
+            .ok()
+            .filter(|interval| !interval.is_zero())
+            .unwrap_or_else(|| {
+                eprintln!("Error: --slideshow needs a positive number of seconds");
+                std::process::exit(1);
+            });
     }
-
-    fn check_quality_upgrade(&mut self, store: &ImageStore) {
-        if self.view_state.needs_render || !self.view_state.needs_quality_upgrade() {
-            return;
-        }
-
-        let dominated_by_preloader = store
-            .get(self.view_state.current_index)
-            .and_then(|slot| slot.current_quality())
-            .map(|q| Some(q) > self.view_state.last_render_quality)
-            .unwrap_or(false);
-
-        if dominated_by_preloader {
-            self.view_state.signal_quality_upgrade();
-        }
+    if let Some(dirs) = &args.sort_into {
+        config.files.sort_into = dirs.clone();
     }
-
-    fn control_flow(&self, input_state: &InputState) -> ControlFlow {
-        let active = input_state.is_navigating()
-            || self.view_state.needs_render
-            || self.view_state.needs_quality_upgrade();
-
-        if active {
-            ControlFlow::Poll
-        } else {
-            ControlFlow::Wait
-        }
+    if let Some(action) = args.sort_action {
+        config.files.sort_action = action;
     }
-}
-
-/// Application with two-phase initialization
-struct App {
-    config: Config,
-    decoder: Arc<Decoder>,
-    store: Arc<ImageStore>,
-    shared_state: Arc<SharedState>,
-    input_state: InputState,
-    window_state: Option<WindowState>,
-}
-
-impl App {
-    fn new(
-        config: Config,
-        decoder: Arc<Decoder>,
-        store: Arc<ImageStore>,
-        shared_state: Arc<SharedState>,
-    ) -> Self {
-        Self {
-            config,
-            decoder,
-            store,
-            shared_state,
-            input_state: InputState::new(),
-            window_state: None,
-        }
+    if config.files.sort_into.len() > 9 {
+        eprintln!("Error: at most 9 sort directories can be given");
+        std::process::exit(1);
     }
 
-    fn handle_key_action(
-        &mut self,
-        action: KeyAction,
-        pressed: bool,
-        event_loop: &ActiveEventLoop,
-    ) {
-        match action {
-            KeyAction::NavigateRight => self.input_state.set_right(pressed),
-            KeyAction::NavigateLeft => self.input_state.set_left(pressed),
-            KeyAction::JumpHome if pressed => self.input_state.home_pressed = true,
-            KeyAction::JumpEnd if pressed => self.input_state.end_pressed = true,
-            KeyAction::Quit if pressed => {
-                self.shared_state.shutdown();
-                event_loop.exit();
-            }
-            _ => {}
-        }
+    if args.dump_config {
+        print!("{}", config.to_toml());
+        return;
     }
-}
-
-impl ApplicationHandler for App {
-    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
-        if self.window_state.is_some() {
-            return;
-        }
-
-        let mut ws = WindowState::create(
-            event_loop,
-            &self.config,
-            &self.store,
-            &self.shared_state,
-            &self.decoder,
+    for placeholder in TitleFormat::new(&config.render.title_format).unknown() {
+        eprintln!(
+            "Warning: unknown placeholder '{}' in render.title_format, shown as is",
+            placeholder
         );
-
-        ws.render(&self.store, &self.config);
-        ws.update_title(&self.store);
-        self.window_state = Some(ws);
     }
-
-    fn window_event(
-        &mut self,
-        event_loop: &ActiveEventLoop,
-        _window_id: WindowId,
-        event: WindowEvent,
-    ) {
-        let ws = match self.window_state.as_mut() {
-            Some(ws) => ws,
-            None => return,
-        };
-
-        match event {
-            WindowEvent::CloseRequested => {
-                self.shared_state.shutdown();
-                event_loop.exit();
-            }
-
-            WindowEvent::KeyboardInput { event, .. } => {
-                if let PhysicalKey::Code(key) = event.physical_key {
-                    if let Some(action) = lookup_key_action(key) {
-                        self.handle_key_action(
-                            action,
-                            event.state == ElementState::Pressed,
-                            event_loop,
-                        );
-                    }
-                }
-            }
-
-            WindowEvent::Resized(size) if size.width > 0 && size.height > 0 => {
-                ws.handle_resize(size.width, size.height);
-            }
-
-            WindowEvent::RedrawRequested => {
-                ws.render(&self.store, &self.config);
-            }
-
-            _ => {}
+    let budget = Arc::new(MemoryBudget::from_config(&config));
+    let decoder = Arc::new(
+        Decoder::new()
+            .with_sniffing(args.sniff)
+            .with_max_pixels(config.memory.decode_pixel_cap(budget.total()))
+            .with_embedded_thumbnails(config.preload.use_embedded_thumbnails)
+            .with_color_management(config.render.color_management)
+            .with_tiers(config.preload.tier_sizes()),
+    );
+
+    if let Some(Command::Bench(bench)) = &args.command {
+        let paths = args.browse.scan(&bench.dir, &decoder);
+        if paths.is_empty() {
+            eprintln!(
+                "Error: no supported images found in '{}'",
+                bench.dir.display()
+            );
+            std::process::exit(1);
         }
+        let threads = bench
+            .threads
+            .map_or(config.preload.decode_threads(), usize::from);
+        let report = bench::run(&decoder.expand_pages(paths), &decoder, bench.tier, threads);
+        bench::print(&report, bench.json);
+        std::process::exit(if report.failures == 0 { 0 } else { 1 });
     }
 
-    fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
-        let ws = match self.window_state.as_mut() {
-            Some(ws) => ws,
-            None => return,
+    if let Some(Command::Thumbs(thumbs)) = &args.command {
+        let paths = args.browse.scan(&thumbs.dir, &decoder);
+        if paths.is_empty() {
+            eprintln!(
+                "Error: no supported images found in '{}'",
+                thumbs.dir.display()
+            );
+            std::process::exit(1);
+        }
+        let Some(dir) = ThumbnailCache::default_dir() else {
+            eprintln!("Error: no cache directory (neither XDG_CACHE_HOME nor HOME is set)");
+            std::process::exit(1);
         };
-
-        event_loop.set_control_flow(ws.control_flow(&self.input_state));
-
-        // Process navigation
-        if let Some(delta) = self.input_state.process(&self.config.input) {
-            ws.view_state.navigate(delta);
-            self.shared_state.set_current(ws.view_state.current_index);
-            ws.update_title(&self.store);
+        if !config.preload.thumbnail_cache {
+            eprintln!("Warning: preload.thumbnail_cache is off, so browsing won't use these");
         }
-
-        ws.check_quality_upgrade(&self.store);
-
-        if ws.view_state.needs_render {
-            ws.render(&self.store, &self.config);
-            ws.update_title(&self.store);
-            ws.window.request_redraw();
+        let cache = ThumbnailCache::open(
+            dir,
+            config.preload.thumbnail_cache_size,
+            config.preload.tier_sizes(),
+        )
+        .unwrap_or_else(|err| {
+            eprintln!("Error: {}", err);
+            std::process::exit(1);
+        });
+        // Finish the images being decoded rather than dying mid-write
+        let stop = Arc::new(AtomicBool::new(false));
+        let handler_stop = Arc::clone(&stop);
+        if let Err(err) = ctrlc::set_handler(move || handler_stop.store(true, Ordering::Relaxed)) {
+            eprintln!("Warning: cannot catch Ctrl-C: {}", err);
         }
+        let threads = thumbs
+            .jobs
+            .map_or(config.preload.decode_threads(), usize::from);
+        let metas = decoder.expand_pages(paths);
+        let summary = thumbs::run(&metas, &decoder, &cache, threads, &stop);
+        eprintln!("{}", summary.to_text());
+        let status = if summary.interrupted {
+            130
+        } else if summary.failures > 0 {
+            1
+        } else {
+            0
+        };
+        std::process::exit(status);
     }
-}
 
-fn main() {
-    let args = Args::parse();
+    if args.info {
+        let entries = info::describe(&args.browse.paths, &decoder);
+        let all_read = info::print(&entries, args.json);
+        std::process::exit(if all_read { 0 } else { 1 });
+    }
 
-    let dir = args.directory.canonicalize().unwrap_or_else(|_| {
+    // Pickers print to their own stdout, and a piped list is this
+    // process's stdin, so those always get a window of their own
+    let single_instance = (args.single_instance || config.input.single_instance)
+        && !args.pick
+        && !args.browse.paths.iter().any(|path| path.as_os_str() == "-");
+    // Served before the scan, however long it takes, so later launches
+    // are answered meanwhile
+    #[cfg(unix)]
+    let (instance, held) = if single_instance {
+        match remote::forward_or_claim(&absolute_paths(&args.browse.paths)) {
+            Launch::Forwarded => return,
+            Launch::First(claimed) => match claimed.serve_held() {
+                Ok((instance, held)) => (Some(instance), Some(held)),
+                Err(err) => {
+                    eprintln!("Warning: no single instance: {}", err);
+                    (None, None)
+                }
+            },
+            Launch::Alone(reason) => {
+                eprintln!("Warning: opening a new window: {}", reason);
+                (None, None)
+            }
+        }
+    } else {
+        (None, None)
+    };
+    // Later launches are handed over on a Unix socket, which other
+    // platforms don't have
+    #[cfg(not(unix))]
+    if single_instance {
         eprintln!(
-            "Error: Cannot access directory '{}'",
-            args.directory.display()
+            "Warning: opening a new window: single instance is not supported on this platform"
         );
-        std::process::exit(1);
-    });
-
-    if !dir.is_dir() {
-        eprintln!("Error: '{}' is not a directory", dir.display());
-        std::process::exit(1);
     }
 
-    let config = Config::default();
-    let decoder = Arc::new(Decoder::new());
-    let budget = Arc::new(MemoryBudget::from_config(&config));
-    let paths = scan_directory(&dir, &decoder);
-
-    if paths.is_empty() {
-        eprintln!(
-            "No supported images found in '{}'\nSupported formats: {:?}",
-            dir.display(),
-            decoder.extensions()
-        );
+    #[cfg(unix)]
+    let mut args = args;
+    let list = loop {
+        match collect_images(&args.browse, &decoder) {
+            Ok(list) if !list.paths.is_empty() => break list,
+            Ok(_) => eprintln!(
+                "No supported images found in '{}'\nSupported formats: {:?}",
+                args.browse.paths[0].display(),
+                decoder.extensions()
+            ),
+            Err(err) => eprintln!("Error: {}", err),
+        }
+        // A launch handed its paths over meanwhile, and was told they'd open
+        #[cfg(unix)]
+        if let Some(paths) = held.as_ref().and_then(HeldRequests::take_open) {
+            args.browse.paths = paths;
+            continue;
+        }
+        #[cfg(unix)]
+        drop(instance);
         std::process::exit(1);
+    };
+
+    let mut rng = match args.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+
+    let paths = list.paths;
+    let mut start_index = list.start_index;
+    let session = (config.view.remember_position || config.view.remember_window)
+        .then(Session::default_path)
+        .flatten()
+        .map(|path| Session::load(&path));
+    // Reopening a directory resumes where it was left off
+    let remembered = list
+        .session_dir
+        .as_ref()
+        .filter(|_| config.view.remember_position)
+        .zip(session.as_ref())
+        .and_then(|(dir, session)| session.get(dir).cloned());
+    if let Some(last) = remembered {
+        start_index = session::resume_index(&paths, &last);
+    }
+    let geometry = args.geometry.or_else(|| {
+        session
+            .as_ref()
+            .filter(|_| config.view.remember_window)
+            .and_then(Session::window)
+            .filter(|g| g.size.0 > 0 && g.size.1 > 0)
+    });
+    let (metas, start_index) = expand_collection(
+        &decoder,
+        paths,
+        start_index,
+        args.browse.shuffle.then_some(&mut rng),
+    );
+    let store = Arc::new(
+        create_store_fast(metas, Arc::clone(&budget))
+            .with_recency_weight(config.preload.eviction_recency_weight)
+            .with_wrap(config.input.wrap)
+            .with_tiers(config.preload.tier_sizes()),
+    );
+    let shared_state = start_preloading(&store, start_index);
+
+    let event_loop = EventLoop::<UserEvent>::with_user_event()
+        .build()
+        .expect("Failed to create event loop");
+
+    // Kept alive for the life of the event loop
+    let _watcher = if args.watch && list.watch_roots.is_empty() {
+        eprintln!("Warning: --watch only follows directories, not listed files");
+        None
+    } else if args.watch {
+        let proxy = event_loop.create_proxy();
+        watch_directories(
+            &list.watch_roots,
+            args.browse.scan_depth(),
+            Arc::clone(&decoder),
+            move |change| {
+                let _ = proxy.send_event(UserEvent::File(change));
+            },
+        )
+        .map_err(|err| eprintln!("Warning: {}", err))
+        .ok()
+    } else {
+        None
+    };
+
+    #[cfg(unix)]
+    let remote = if config.input.remote_control {
+        let proxy = event_loop.create_proxy();
+        remote::serve(move |request| {
+            let _ = proxy.send_event(UserEvent::Remote(request));
+        })
+        .map_err(|err| eprintln!("Warning: no remote control: {}", err))
+        .ok()
+    } else {
+        None
+    };
+
+    #[cfg(unix)]
+    if let Some(held) = held {
+        let proxy = event_loop.create_proxy();
+        held.release(move |request| {
+            let _ = proxy.send_event(UserEvent::Remote(request));
+        });
     }
 
-    let store = Arc::new(create_store_fast(paths, Arc::clone(&budget)));
-    let shared_state = Arc::new(SharedState::new());
-    shared_state.set_total(store.len());
-
-    let event_loop = EventLoop::new().expect("Failed to create event loop");
-    let mut app = App::new(config, decoder, store, shared_state);
+    let mut app = App::new(config, decoder, store, shared_state, list.title_root, rng)
+        .with_pick_mode(args.pick)
+        .with_slideshow(args.slideshow.is_some())
+        .with_marks_file(args.marks_file.clone())
+        .with_session_dir(list.session_dir)
+        .with_browse_dir(list.browse_dir)
+        .with_geometry(geometry)
+        .with_scan_args(args.browse.clone());
+    #[cfg(unix)]
+    {
+        app = app.with_proxy(event_loop.create_proxy());
+    }
 
     event_loop.run_app(&mut app).expect("Event loop error");
+    // Gone before any early exit below
+    #[cfg(unix)]
+    drop(remote);
+    #[cfg(unix)]
+    drop(instance);
+
+    // Only picks go to stdout, so scripts can capture them
+    if args.pick {
+        let written = match app.picked() {
+            Some(paths) => write_paths(paths, None),
+            None => std::process::exit(1),
+        };
+        if let Err(err) = written {
+            eprintln!("Error: can't write the picks: {}", err);
+            std::process::exit(1);
+        }
+    } else if !app.marks().is_empty() {
+        if let Err(err) = write_paths(app.marks(), args.marks_file.as_deref()) {
+            eprintln!("Warning: can't write marks: {}", err);
+        }
+    }
 }
@@ -0,0 +1,109 @@
+//! Counters behind the statistics overlay (F12).
+//!
+//! Recording is a few relaxed atomic additions, cheap enough to stay on
+//! whether or not the overlay is shown. The overlay samples the counters
+//! now and then and shows rates between samples.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Decode counts and timings, shared by everything that decodes
+#[derive(Debug, Default)]
+pub struct Metrics {
+    /// Images decoded
+    decoded: AtomicU64,
+    /// Bytes of decoded pixels
+    bytes: AtomicU64,
+    /// Time spent decoding
+    nanos: AtomicU64,
+    /// Decodes that failed
+    failed: AtomicU64,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A decode produced `bytes` of pixels in `took`
+    pub fn record_decode(&self, bytes: usize, took: Duration) {
+        self.decoded.fetch_add(1, Ordering::Relaxed);
+        self.bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+        self.nanos
+            .fetch_add(took.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    /// A decode failed
+    pub fn record_failure(&self) {
+        self.failed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// The counters as of `at`
+    pub fn sample(&self, at: Instant) -> Sample {
+        Sample {
+            at,
+            decoded: self.decoded.load(Ordering::Relaxed),
+            bytes: self.bytes.load(Ordering::Relaxed),
+            nanos: self.nanos.load(Ordering::Relaxed),
+            failed: self.failed.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// The counters at one moment; two of them give rates
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sample {
+    pub at: Instant,
+    pub decoded: u64,
+    pub bytes: u64,
+    pub nanos: u64,
+    pub failed: u64,
+}
+
+impl Sample {
+    /// Images and megabytes of pixels decoded per second since `earlier`
+    pub fn rates_since(&self, earlier: &Sample) -> (f64, f64) {
+        let seconds = self.at.saturating_duration_since(earlier.at).as_secs_f64();
+        if seconds <= 0.0 {
+            return (0.0, 0.0);
+        }
+        let images = self.decoded.saturating_sub(earlier.decoded) as f64;
+        let bytes = self.bytes.saturating_sub(earlier.bytes) as f64;
+        (images / seconds, bytes / 1_000_000.0 / seconds)
+    }
+
+    /// Mean time a decode took since `earlier`, if there were any
+    pub fn mean_decode_since(&self, earlier: &Sample) -> Option<Duration> {
+        let count = self.decoded.saturating_sub(earlier.decoded);
+        let nanos = self.nanos.saturating_sub(earlier.nanos);
+        (count > 0).then(|| Duration::from_nanos(nanos / count))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rates() {
+        let metrics = Metrics::new();
+        let start = Instant::now();
+        let before = metrics.sample(start);
+
+        metrics.record_decode(2_000_000, Duration::from_millis(30));
+        metrics.record_decode(1_000_000, Duration::from_millis(10));
+        metrics.record_failure();
+        let after = metrics.sample(start + Duration::from_secs(2));
+
+        assert_eq!((after.decoded, after.failed), (2, 1));
+        assert_eq!(after.rates_since(&before), (1.0, 1.5));
+        assert_eq!(
+            after.mean_decode_since(&before),
+            Some(Duration::from_millis(20))
+        );
+
+        // Nothing decoded, or no time passed: no rates to speak of
+        assert_eq!(after.mean_decode_since(&after), None);
+        assert_eq!(after.rates_since(&after), (0.0, 0.0));
+    }
+}
@@ -0,0 +1,603 @@
+//! File operations - deleting images, sorting them into directories,
+//! saving cropped copies and rotating files on disk.
+//!
+//! Errors are returned as messages ready to show the user; nothing here
+//! touches the image store.
+
+use crate::config::SortAction;
+use crate::formats::apply_orientation;
+use crate::render::PixelRect;
+use crate::slot::ImageData;
+use crate::state::Orientation;
+use image::{ExtendedColorType, ImageFormat};
+use std::fs::{self, File};
+use std::io::{self, Cursor, Write};
+use std::path::{Path, PathBuf};
+
+/// EXIF tag number of the orientation
+const ORIENTATION_TAG: u16 = 0x0112;
+
+/// Move a file to the trash, or delete it for good if `permanent`
+pub fn delete(path: &Path, permanent: bool) -> Result<(), String> {
+    if permanent {
+        fs::remove_file(path).map_err(|e| e.to_string())
+    } else {
+        trash::delete(path).map_err(|e| e.to_string())
+    }
+}
+
+/// Copy or move a file into `dir` (created if missing), keeping its name
+/// unless that is taken there, in which case a number is appended
+/// (`photo_1.jpg`, `photo_2.jpg`, ...). Returns the new path.
+pub fn sort_into(path: &Path, dir: &Path, action: SortAction) -> Result<PathBuf, String> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| format!("{} is not a file", path.display()))?;
+    fs::create_dir_all(dir).map_err(|e| format!("cannot create {}: {}", dir.display(), e))?;
+
+    // Claim the name first, so a concurrent writer can't take it meanwhile
+    let target = claim_free_name(dir, Path::new(file_name))
+        .map_err(|e| format!("cannot write to {}: {}", dir.display(), e))?;
+
+    let result = match action {
+        SortAction::Copy => fs::copy(path, &target).map(|_| ()),
+        SortAction::Move => move_to(path, &target),
+    };
+    match result {
+        Ok(()) => Ok(target),
+        Err(err) => {
+            let _ = fs::remove_file(&target);
+            Err(err.to_string())
+        }
+    }
+}
+
+/// Save the `rect` region (in pixels of the original image) of animation
+/// frame `frame_index` as a PNG next to `original`, named after it:
+/// `IMG_1234.crop.png`, or `IMG_1234.crop_1.png` and so on if that is
+/// taken. Returns the new path.
+pub fn save_crop(
+    data: &ImageData,
+    frame_index: usize,
+    rect: PixelRect,
+    original: &Path,
+) -> Result<PathBuf, String> {
+    // The data may be smaller than the original, if it was over the
+    // pixel cap
+    let rect = rect.rescale(
+        (data.full_width, data.full_height),
+        (data.width, data.height),
+    );
+    let stride = data.width as usize * 4;
+    let columns = rect.x as usize * 4..(rect.x + rect.width) as usize * 4;
+    let pixels: Vec<u8> = data
+        .frame(frame_index)
+        .chunks_exact(stride)
+        .skip(rect.y as usize)
+        .take(rect.height as usize)
+        .flat_map(|row| &row[columns.clone()])
+        .copied()
+        .collect();
+
+    let dir = original.parent().unwrap_or(Path::new("."));
+    let stem = original
+        .file_stem()
+        .unwrap_or(original.as_os_str())
+        .to_string_lossy();
+    let name = format!("{}.crop.png", stem);
+    let target = claim_free_name(dir, Path::new(&name))
+        .map_err(|e| format!("cannot write to {}: {}", dir.display(), e))?;
+
+    let result = image::save_buffer_with_format(
+        &target,
+        &pixels,
+        rect.width,
+        rect.height,
+        image::ColorType::Rgba8,
+        image::ImageFormat::Png,
+    );
+    match result {
+        Ok(()) => Ok(target),
+        Err(err) => {
+            let _ = fs::remove_file(&target);
+            Err(err.to_string())
+        }
+    }
+}
+
+/// Turn the image in `path` by `turn` on disk, replacing the file
+/// atomically. JPEGs only get their EXIF orientation rewritten; other
+/// formats are re-encoded from the pixels `decode` returns, which must be
+/// the full image as currently displayed before `turn`.
+pub fn save_orientation(
+    path: &Path,
+    turn: Orientation,
+    decode: impl FnOnce() -> Result<std::sync::Arc<ImageData>, String>,
+) -> Result<(), String> {
+    let bytes = fs::read(path).map_err(|e| e.to_string())?;
+    if bytes.starts_with(&[0xFF, 0xD8]) {
+        // Re-encoding would lose quality and metadata
+        return replace_file(path, &reorient_jpeg(&bytes, turn)?);
+    }
+
+    // Guessing from the contents alone would take a TIFF-based RAW file
+    // for a TIFF
+    let format = image::guess_format(&bytes)
+        .ok()
+        .filter(|&format| ImageFormat::from_path(path).ok() == Some(format))
+        .ok_or("can't rewrite this format")?;
+    let data = decode()?;
+    if data.is_animated() {
+        return Err("animations can't be rotated on disk".into());
+    }
+    if data.embedded_preview || (data.width, data.height) != (data.full_width, data.full_height) {
+        return Err("only a reduced copy of this image can be decoded".into());
+    }
+
+    let (pixels, width, height) =
+        apply_orientation(data.pixels.clone(), data.width, data.height, turn);
+    let mut encoded = Cursor::new(Vec::new());
+    image::write_buffer_with_format(
+        &mut encoded,
+        &pixels,
+        width,
+        height,
+        ExtendedColorType::Rgba8,
+        format,
+    )
+    .map_err(|e| e.to_string())?;
+    replace_file(path, encoded.get_ref())
+}
+
+/// A JPEG turned by `turn` through its EXIF orientation alone, leaving
+/// the compressed image and every other tag untouched. One without EXIF
+/// gets a segment holding just the orientation, and EXIF without an
+/// orientation gets one added. An error if `jpeg` isn't a JPEG or its
+/// EXIF can't be rewritten.
+fn reorient_jpeg(jpeg: &[u8], turn: Orientation) -> Result<Vec<u8>, String> {
+    const MALFORMED: &str = "this JPEG's metadata is malformed";
+    if !jpeg.starts_with(&[0xFF, 0xD8]) {
+        return Err("not a JPEG".into());
+    }
+    // A new segment goes after the JFIF header, which must come first
+    let mut insert_at = 2;
+    let mut pos = 2;
+    // Metadata (APPn and comment segments) precedes the image itself
+    while let [0xFF, marker @ (0xE0..=0xEF | 0xFE), high, low, ..] = jpeg[pos..] {
+        let end = pos + 2 + u16::from_be_bytes([high, low]) as usize;
+        let body = jpeg.get(pos + 4..end).ok_or(MALFORMED)?;
+        if marker == 0xE1 && body.starts_with(b"Exif\0\0") {
+            let tiff = &body[6..];
+            let Some((field, big_endian)) = orientation_field(tiff) else {
+                let tiff = with_orientation(tiff, turn).ok_or(MALFORMED)?;
+                let mut rewritten = jpeg[..pos].to_vec();
+                rewritten.extend(app1_segment(&tiff)?);
+                rewritten.extend_from_slice(&jpeg[end..]);
+                return Ok(rewritten);
+            };
+            let at = pos + 4 + 6 + field;
+            let value = read_u16(jpeg, at, big_endian).ok_or(MALFORMED)?;
+            let turned = Orientation::from_exif(value as u32).then(turn).to_exif();
+            let mut rewritten = jpeg.to_vec();
+            rewritten[at..at + 2].copy_from_slice(&if big_endian {
+                turned.to_be_bytes()
+            } else {
+                turned.to_le_bytes()
+            });
+            return Ok(rewritten);
+        }
+        if marker == 0xE0 {
+            insert_at = end;
+        }
+        pos = end;
+    }
+
+    let mut rewritten = jpeg[..insert_at].to_vec();
+    rewritten.extend(exif_segment(turn));
+    rewritten.extend_from_slice(&jpeg[insert_at..]);
+    Ok(rewritten)
+}
+
+/// A `u16` at `at` in `bytes`
+fn read_u16(bytes: &[u8], at: usize, big_endian: bool) -> Option<u16> {
+    let bytes: [u8; 2] = bytes.get(at..at.checked_add(2)?)?.try_into().ok()?;
+    Some(if big_endian {
+        u16::from_be_bytes(bytes)
+    } else {
+        u16::from_le_bytes(bytes)
+    })
+}
+
+/// A `u32` at `at` in `bytes`
+fn read_u32(bytes: &[u8], at: usize, big_endian: bool) -> Option<u32> {
+    let bytes: [u8; 4] = bytes.get(at..at.checked_add(4)?)?.try_into().ok()?;
+    Some(if big_endian {
+        u32::from_be_bytes(bytes)
+    } else {
+        u32::from_le_bytes(bytes)
+    })
+}
+
+/// Whether a TIFF block is big-endian, from its byte order mark
+fn tiff_big_endian(tiff: &[u8]) -> Option<bool> {
+    match tiff.get(..2)? {
+        b"MM" => Some(true),
+        b"II" => Some(false),
+        _ => None,
+    }
+}
+
+/// Where in a TIFF block the first IFD's orientation value sits, and
+/// whether the block is big-endian
+fn orientation_field(tiff: &[u8]) -> Option<(usize, bool)> {
+    let big_endian = tiff_big_endian(tiff)?;
+    let ifd = read_u32(tiff, 4, big_endian)? as usize;
+    let entries = read_u16(tiff, ifd, big_endian)? as usize;
+    (0..entries)
+        .map(|i| ifd + 2 + i * 12)
+        // A single SHORT, stored in the entry itself
+        .find(|&entry| {
+            read_u16(tiff, entry, big_endian) == Some(ORIENTATION_TAG)
+                && read_u16(tiff, entry + 2, big_endian) == Some(3)
+        })
+        .map(|entry| entry + 8)
+        .filter(|&field| field + 2 <= tiff.len())
+        .map(|field| (field, big_endian))
+}
+
+/// A TIFF block whose first IFD lacks an orientation, with one added.
+/// The IFD is copied to the end of the block with the new entry, so the
+/// offsets the other tags hold still point at their data. None if the
+/// block is malformed or already has an orientation of the wrong type.
+fn with_orientation(tiff: &[u8], orientation: Orientation) -> Option<Vec<u8>> {
+    let big_endian = tiff_big_endian(tiff)?;
+    let ifd = read_u32(tiff, 4, big_endian)? as usize;
+    let count = read_u16(tiff, ifd, big_endian)? as usize;
+    let entries = tiff.get(ifd + 2..ifd + 2 + count * 12)?;
+    let next_ifd = tiff.get(ifd + 2 + count * 12..ifd + 6 + count * 12)?;
+
+    let u16_bytes = |value: u16| {
+        if big_endian {
+            value.to_be_bytes()
+        } else {
+            value.to_le_bytes()
+        }
+    };
+    let u32_bytes = |value: u32| {
+        if big_endian {
+            value.to_be_bytes()
+        } else {
+            value.to_le_bytes()
+        }
+    };
+    let mut entry = Vec::with_capacity(12);
+    entry.extend_from_slice(&u16_bytes(ORIENTATION_TAG));
+    entry.extend_from_slice(&u16_bytes(3));
+    entry.extend_from_slice(&u32_bytes(1));
+    entry.extend_from_slice(&u16_bytes(orientation.to_exif()));
+    entry.extend_from_slice(&[0, 0]);
+
+    // Entries are sorted by tag
+    let tags: Vec<u16> = entries
+        .chunks_exact(12)
+        .map(|e| read_u16(e, 0, big_endian))
+        .collect::<Option<_>>()?;
+    if tags.contains(&ORIENTATION_TAG) {
+        return None;
+    }
+    let split = tags
+        .iter()
+        .take_while(|&&tag| tag < ORIENTATION_TAG)
+        .count()
+        * 12;
+
+    let mut rewritten = tiff.to_vec();
+    // IFDs start on a word boundary
+    if rewritten.len() % 2 == 1 {
+        rewritten.push(0);
+    }
+    let new_ifd = u32::try_from(rewritten.len()).ok()?;
+    rewritten.extend_from_slice(&u16_bytes(count as u16 + 1));
+    rewritten.extend_from_slice(&entries[..split]);
+    rewritten.extend(entry);
+    rewritten.extend_from_slice(&entries[split..]);
+    rewritten.extend_from_slice(next_ifd);
+    rewritten[4..8].copy_from_slice(&u32_bytes(new_ifd));
+    Some(rewritten)
+}
+
+/// An APP1 segment holding the EXIF TIFF block `tiff`
+fn app1_segment(tiff: &[u8]) -> Result<Vec<u8>, String> {
+    let length = u16::try_from(2 + 6 + tiff.len()).map_err(|_| "this JPEG's EXIF is too large")?;
+    let mut segment = vec![0xFF, 0xE1];
+    segment.extend_from_slice(&length.to_be_bytes());
+    segment.extend_from_slice(b"Exif\0\0");
+    segment.extend_from_slice(tiff);
+    Ok(segment)
+}
+
+/// An APP1 segment holding EXIF with just an orientation
+fn exif_segment(orientation: Orientation) -> Vec<u8> {
+    let mut tiff = b"MM\0\x2a".to_vec();
+    tiff.extend_from_slice(&8u32.to_be_bytes());
+    tiff.extend_from_slice(&1u16.to_be_bytes());
+    tiff.extend_from_slice(&ORIENTATION_TAG.to_be_bytes());
+    tiff.extend_from_slice(&3u16.to_be_bytes());
+    tiff.extend_from_slice(&1u32.to_be_bytes());
+    tiff.extend_from_slice(&orientation.to_exif().to_be_bytes());
+    tiff.extend_from_slice(&[0, 0]);
+    // No further IFDs
+    tiff.extend_from_slice(&0u32.to_be_bytes());
+    app1_segment(&tiff).expect("a single tag fits in a segment")
+}
+
+/// Replace the contents of `path`: write a temporary file next to it and
+/// rename that over it, so a crash leaves the old file or the new one,
+/// never half of each
+pub fn replace_file(path: &Path, contents: &[u8]) -> Result<(), String> {
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let name = path
+        .file_name()
+        .ok_or_else(|| format!("{} is not a file", path.display()))?;
+    let temp = dir.join(format!(".{}.fiv-tmp", name.to_string_lossy()));
+    let write = || -> io::Result<()> {
+        let mut file = File::create(&temp)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+        fs::set_permissions(&temp, fs::metadata(path)?.permissions())?;
+        fs::rename(&temp, path)
+    };
+    write().map_err(|e| {
+        let _ = fs::remove_file(&temp);
+        e.to_string()
+    })
+}
+
+/// Create an empty file at the first free name for `file_name` in `dir`
+pub fn claim_free_name(dir: &Path, file_name: &Path) -> io::Result<PathBuf> {
+    let stem = file_name
+        .file_stem()
+        .unwrap_or(file_name.as_os_str())
+        .to_string_lossy();
+    let extension = file_name.extension().map(|e| e.to_string_lossy());
+
+    for n in 0.. {
+        let name = match (n, &extension) {
+            (0, _) => file_name.to_string_lossy().into_owned(),
+            (n, Some(ext)) => format!("{}_{}.{}", stem, n, ext),
+            (n, None) => format!("{}_{}", stem, n),
+        };
+        let candidate = dir.join(name);
+        match File::options()
+            .write(true)
+            .create_new(true)
+            .open(&candidate)
+        {
+            Ok(_) => return Ok(candidate),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    unreachable!("ran out of suffixes")
+}
+
+/// Rename onto the claimed target, falling back to copy and delete when
+/// the target is on another filesystem
+fn move_to(from: &Path, to: &Path) -> io::Result<()> {
+    let Err(rename_err) = fs::rename(from, to) else {
+        return Ok(());
+    };
+    // Permissions and missing files fail the same way when copying, so a
+    // failed copy reports the rename error, which is the real reason
+    if fs::copy(from, to).is_err() {
+        return Err(rename_err);
+    }
+    fs::remove_file(from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("fiv_ops_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_copy_appends_suffix_on_collision() {
+        let root = temp_dir("copy");
+        let source = root.join("photo.jpg");
+        fs::write(&source, b"new").unwrap();
+        let target_dir = root.join("keep");
+        fs::create_dir_all(&target_dir).unwrap();
+        fs::write(target_dir.join("photo.jpg"), b"old").unwrap();
+
+        let first = sort_into(&source, &target_dir, SortAction::Copy).unwrap();
+        assert_eq!(first, target_dir.join("photo_1.jpg"));
+        let second = sort_into(&source, &target_dir, SortAction::Copy).unwrap();
+        assert_eq!(second, target_dir.join("photo_2.jpg"));
+
+        assert_eq!(fs::read(target_dir.join("photo.jpg")).unwrap(), b"old");
+        assert_eq!(fs::read(&first).unwrap(), b"new");
+        assert!(source.exists());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_move_creates_directory() {
+        let root = temp_dir("move");
+        let source = root.join("photo.jpg");
+        fs::write(&source, b"data").unwrap();
+
+        let moved = sort_into(&source, &root.join("a/b"), SortAction::Move).unwrap();
+        assert_eq!(moved, root.join("a/b/photo.jpg"));
+        assert_eq!(fs::read(&moved).unwrap(), b"data");
+        assert!(!source.exists());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_failure_leaves_no_placeholder() {
+        let root = temp_dir("fail");
+        let target_dir = root.join("keep");
+
+        let missing = root.join("missing.jpg");
+        assert!(sort_into(&missing, &target_dir, SortAction::Move).is_err());
+        assert!(sort_into(&missing, &target_dir, SortAction::Copy).is_err());
+        assert_eq!(fs::read_dir(&target_dir).unwrap().count(), 0);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_save_crop() {
+        use crate::config::QualityTier;
+
+        let root = temp_dir("crop");
+        let original = root.join("IMG_1234.jpg");
+        // 4x2, each pixel's red value its index
+        let pixels: Vec<u8> = (0..8u8).flat_map(|i| [i, 0, 0, 255]).collect();
+        let data = ImageData::new(pixels, 4, 2, QualityTier::Full);
+        let rect = PixelRect {
+            x: 1,
+            y: 0,
+            width: 2,
+            height: 2,
+        };
+
+        let first = save_crop(&data, 0, rect, &original).unwrap();
+        assert_eq!(first, root.join("IMG_1234.crop.png"));
+        let saved = image::open(&first).unwrap().to_rgba8();
+        assert_eq!(saved.dimensions(), (2, 2));
+        let red: Vec<u8> = saved.pixels().map(|p| p[0]).collect();
+        assert_eq!(red, vec![1, 2, 5, 6]);
+
+        // A second crop doesn't replace the first
+        let second = save_crop(&data, 0, rect, &original).unwrap();
+        assert_eq!(second, root.join("IMG_1234.crop_1.png"));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// EXIF orientation value of a JPEG, if it has one
+    fn jpeg_orientation(jpeg: &[u8]) -> Option<u16> {
+        let start = jpeg.windows(6).position(|w| w == b"Exif\0\0")? + 6;
+        let (field, big_endian) = orientation_field(&jpeg[start..])?;
+        read_u16(jpeg, start + field, big_endian)
+    }
+
+    #[test]
+    fn test_reorient_jpeg() {
+        // Start of image, a JFIF header, then the start of the image data
+        let mut jpeg = vec![0xFF, 0xD8, 0xFF, 0xE0, 0, 6, b'J', b'F', b'I', b'F'];
+        jpeg.extend_from_slice(&[0xFF, 0xDB, 0, 2]);
+        let quarter = Orientation::IDENTITY.rotate_cw();
+
+        // Without EXIF, a segment is added after the JFIF header
+        let once = reorient_jpeg(&jpeg, quarter).unwrap();
+        assert_eq!(jpeg_orientation(&once), Some(6));
+        assert_eq!(once[..10], jpeg[..10]);
+        assert_eq!(once[10..12], [0xFF, 0xE1]);
+        assert!(once.ends_with(&[0xFF, 0xDB, 0, 2]));
+
+        // With it, only the value changes
+        let twice = reorient_jpeg(&once, quarter).unwrap();
+        assert_eq!(twice.len(), once.len());
+        assert_eq!(jpeg_orientation(&twice), Some(3));
+        let mirrored = reorient_jpeg(&twice, Orientation::IDENTITY.flip_horizontal()).unwrap();
+        assert_eq!(jpeg_orientation(&mirrored), Some(4));
+
+        assert!(reorient_jpeg(b"\x89PNG", quarter).is_err());
+    }
+
+    #[test]
+    fn test_reorient_jpeg_without_orientation_tag() {
+        use exif::{In, Tag};
+
+        let mut encoded = Cursor::new(Vec::new());
+        let pixels: Vec<u8> = (0..48u8).collect();
+        image::write_buffer_with_format(
+            &mut encoded,
+            &pixels,
+            4,
+            4,
+            ExtendedColorType::Rgb8,
+            ImageFormat::Jpeg,
+        )
+        .unwrap();
+        let plain = encoded.into_inner();
+
+        // Little-endian EXIF with a Make (stored after the IFD) and an
+        // ImageWidth, but no orientation
+        let mut tiff = b"II\x2a\0".to_vec();
+        tiff.extend_from_slice(&8u32.to_le_bytes());
+        tiff.extend_from_slice(&2u16.to_le_bytes());
+        for (tag, kind, count, value) in [(0x0100u16, 3u16, 1u32, 4u32), (0x010F, 2, 5, 38)] {
+            tiff.extend_from_slice(&tag.to_le_bytes());
+            tiff.extend_from_slice(&kind.to_le_bytes());
+            tiff.extend_from_slice(&count.to_le_bytes());
+            tiff.extend_from_slice(&value.to_le_bytes());
+        }
+        tiff.extend_from_slice(&0u32.to_le_bytes());
+        tiff.extend_from_slice(b"Acme\0");
+        let mut jpeg = plain[..2].to_vec();
+        jpeg.extend(app1_segment(&tiff).unwrap());
+        jpeg.extend_from_slice(&plain[2..]);
+        assert_eq!(jpeg_orientation(&jpeg), None);
+
+        let root = temp_dir("exif");
+        let path = root.join("photo.jpg");
+        fs::write(&path, &jpeg).unwrap();
+        let decode = || Err("JPEGs aren't decoded".to_string());
+        save_orientation(&path, Orientation::IDENTITY.rotate_cw(), decode).unwrap();
+        let rewritten = fs::read(&path).unwrap();
+
+        // The image data after the segment is untouched
+        let segment_end = 4 + u16::from_be_bytes([rewritten[4], rewritten[5]]) as usize;
+        assert_eq!(rewritten[segment_end..], plain[2..]);
+        assert_eq!(jpeg_orientation(&rewritten), Some(6));
+
+        let exif = exif::Reader::new()
+            .read_from_container(&mut Cursor::new(&rewritten))
+            .unwrap();
+        let make = exif.get_field(Tag::Make, In::PRIMARY).unwrap();
+        assert_eq!(make.display_value().to_string(), "\"Acme\"");
+        let width = exif.get_field(Tag::ImageWidth, In::PRIMARY).unwrap();
+        assert_eq!(width.value.get_uint(0), Some(4));
+        let orientation = exif.get_field(Tag::Orientation, In::PRIMARY).unwrap();
+        assert_eq!(orientation.value.get_uint(0), Some(6));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_save_orientation_reencodes() {
+        use crate::config::QualityTier;
+        use std::sync::Arc;
+
+        let root = temp_dir("rotate");
+        let path = root.join("wide.png");
+        let pixels = vec![255, 0, 0, 255, 0, 0, 255, 255];
+        image::save_buffer(&path, &pixels, 2, 1, ExtendedColorType::Rgba8).unwrap();
+        let decode = || {
+            Ok(Arc::new(ImageData::new(
+                pixels.clone(),
+                2,
+                1,
+                QualityTier::Full,
+            )))
+        };
+
+        save_orientation(&path, Orientation::IDENTITY.rotate_cw(), decode).unwrap();
+        let saved = image::open(&path).unwrap().to_rgba8();
+        assert_eq!(saved.dimensions(), (1, 2));
+        // Red was on the left, so now it's on top
+        assert_eq!(saved.get_pixel(0, 0).0, [255, 0, 0, 255]);
+        assert_eq!(fs::read_dir(&root).unwrap().count(), 1);
+
+        // Failures are reported
+        let missing = root.join("missing.png");
+        assert!(save_orientation(&missing, Orientation::IDENTITY.rotate_cw(), decode).is_err());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,140 @@
+//! Perceptual hashes - finding near-duplicate images.
+//!
+//! `dhash` is a difference hash: the image is averaged down to a 9x8 grid
+//! of luminance values and each bit records whether a cell is darker than
+//! its right neighbour. Resizing, recompressing or brightening an image
+//! barely changes it, so near-duplicates are images whose hashes are a
+//! small Hamming distance apart.
+
+use crate::render::luminance;
+use crate::slot::ImageData;
+
+/// Grid the image is averaged down to: one more column than bits per row
+const GRID_WIDTH: usize = 9;
+const GRID_HEIGHT: usize = 8;
+
+/// Difference hash of the first frame
+pub fn dhash(data: &ImageData) -> u64 {
+    let (width, height) = (data.width as usize, data.height as usize);
+    if width == 0 || height == 0 {
+        return 0;
+    }
+    let pixels = data.frame(0);
+
+    let mut grid = [[0u32; GRID_WIDTH]; GRID_HEIGHT];
+    for (gy, row) in grid.iter_mut().enumerate() {
+        let (top, bottom) = cell_range(gy, GRID_HEIGHT, height);
+        for (gx, cell) in row.iter_mut().enumerate() {
+            let (left, right) = cell_range(gx, GRID_WIDTH, width);
+            let mut sum = 0u64;
+            for y in top..bottom {
+                let line = &pixels[(y * width + left) * 4..(y * width + right) * 4];
+                for pixel in line.chunks_exact(4) {
+                    sum += luminance(pixel[0], pixel[1], pixel[2]) as u64;
+                }
+            }
+            let count = ((bottom - top) * (right - left)) as u64;
+            *cell = (sum / count) as u32;
+        }
+    }
+
+    let mut hash = 0u64;
+    for row in &grid {
+        for pair in row.windows(2) {
+            hash = hash << 1 | (pair[0] < pair[1]) as u64;
+        }
+    }
+    hash
+}
+
+/// Number of bits two hashes differ in; 0 for identical images
+pub fn hamming(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+/// Pixel range covered by grid cell `index` of `cells` along a side of
+/// `size` pixels; never empty, so images smaller than the grid work too
+fn cell_range(index: usize, cells: usize, size: usize) -> (usize, usize) {
+    let start = (index * size / cells).min(size - 1);
+    let end = ((index + 1) * size / cells).max(start + 1);
+    (start, end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::QualityTier;
+
+    /// An image of 16x16 blocks with pseudo-random gray levels below 200
+    fn blocks(seed: u32, width: u32, height: u32) -> ImageData {
+        let mut pixels = Vec::with_capacity((width * height * 4) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                let block = (y / 16) * 64 + x / 16;
+                let level = (block ^ seed).wrapping_mul(2654435761) >> 24;
+                let level = (level % 200) as u8;
+                pixels.extend_from_slice(&[level, level, level, 255]);
+            }
+        }
+        ImageData::new(pixels, width, height, QualityTier::Thumbnail)
+    }
+
+    fn brightened(data: &ImageData, amount: u8) -> ImageData {
+        let pixels = data
+            .pixels
+            .chunks_exact(4)
+            .flat_map(|p| {
+                [
+                    p[0].saturating_add(amount),
+                    p[1].saturating_add(amount),
+                    p[2].saturating_add(amount),
+                    p[3],
+                ]
+            })
+            .collect();
+        ImageData::new(pixels, data.width, data.height, data.quality)
+    }
+
+    #[test]
+    fn test_identical_images() {
+        let image = blocks(1, 256, 192);
+        assert_eq!(dhash(&image), dhash(&blocks(1, 256, 192)));
+        assert_eq!(hamming(dhash(&image), dhash(&image)), 0);
+    }
+
+    #[test]
+    fn test_brightened_image_is_near() {
+        let image = blocks(2, 256, 192);
+        let distance = hamming(dhash(&image), dhash(&brightened(&image, 40)));
+        assert!(distance <= 4, "distance {}", distance);
+    }
+
+    #[test]
+    fn test_unrelated_images_are_far() {
+        let distance = hamming(dhash(&blocks(3, 256, 192)), dhash(&blocks(9, 256, 192)));
+        assert!(distance > 16, "distance {}", distance);
+    }
+
+    #[test]
+    fn test_tiny_images() {
+        let pixel = ImageData::new(vec![10, 20, 30, 255], 1, 1, QualityTier::Thumbnail);
+        assert_eq!(dhash(&pixel), 0);
+        let empty = ImageData::new(Vec::new(), 0, 0, QualityTier::Thumbnail);
+        assert_eq!(dhash(&empty), 0);
+        // Narrower than the grid: cells share columns
+        let strip = ImageData::new(
+            vec![0, 0, 0, 255, 255, 255, 255, 255],
+            2,
+            1,
+            QualityTier::Thumbnail,
+        );
+        assert_ne!(dhash(&strip), 0);
+    }
+
+    #[test]
+    fn test_hamming() {
+        assert_eq!(hamming(0, 0), 0);
+        assert_eq!(hamming(0b1010, 0b0110), 2);
+        assert_eq!(hamming(0, u64::MAX), 64);
+    }
+}
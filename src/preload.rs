@@ -10,106 +10,606 @@
 //! - Predict user's next images based on direction
 //! - Use all available cores for decoding
 
-use crate::config::{PreloadConfig, QualityTier};
-use crate::decode::Decoder;
-use crate::slot::ImageMeta;
+use crate::cache::ThumbnailCache;
+use crate::config::{MemoryConfig, PreloadConfig, QualityTier};
+use crate::decode::{catch_panics, with_cancel, DecodeError, Decoder, Source};
+use crate::phash::dhash;
+use crate::slot::{ImageData, ImageMeta};
 use crate::state::{Direction, SharedState};
-use crate::store::{circular_distance, ImageStore, MemoryBudget};
+use crate::store::{indices_around, ImageStore, MemoryBudget};
 use rayon::prelude::*;
-use std::sync::Arc;
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
 use std::thread;
+use std::time::{Duration, Instant};
 
-/// Spawn the preloader thread.
+/// Headers read per idle step of the metadata pass, between checks for work
+const METADATA_BATCH: usize = 32;
+
+/// How often running decodes are checked against the decode timeout
+const WATCHDOG_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Spawn the preloader thread with its own decode thread pool.
+///
+/// The pool size is recorded in `shared_state` before this returns.
 pub fn spawn_preloader(
     store: Arc<ImageStore>,
     shared_state: Arc<SharedState>,
     decoder: Arc<Decoder>,
     config: crate::config::Config,
 ) -> thread::JoinHandle<()> {
+    let pool = decode_pool(config.preload.decode_threads());
+    shared_state.set_decode_threads(pool.current_num_threads());
+
     thread::spawn(move || {
-        preloader_loop(store, shared_state, decoder, config.preload);
+        let cache = open_thumbnail_cache(&config.preload).map(Arc::new);
+        preloader_loop(
+            store,
+            shared_state,
+            decoder,
+            cache,
+            pool,
+            config.preload,
+            config.memory,
+            config.duplicates.enabled,
+        );
     })
 }
 
-/// Main preloader loop - runs continuously until shutdown
+/// Thread pool that decodes on `threads` threads
+pub fn decode_pool(threads: usize) -> rayon::ThreadPool {
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .thread_name(|i| format!("fiv-decode-{}", i))
+        .build()
+        .expect("Failed to create decode thread pool")
+}
+
+/// Open the on-disk thumbnail cache, if enabled. Pruning a large cache
+/// takes a moment, so this runs on the preloader thread.
+fn open_thumbnail_cache(config: &PreloadConfig) -> Option<ThumbnailCache> {
+    if !config.thumbnail_cache {
+        return None;
+    }
+    let dir = ThumbnailCache::default_dir()?;
+    ThumbnailCache::open(dir, config.thumbnail_cache_size, config.tier_sizes())
+        .map_err(|e| eprintln!("Warning: thumbnail cache disabled: {}", e))
+        .ok()
+}
+
+/// Main preloader loop - runs continuously until shutdown. With
+/// `hash_all`, idle time also hashes every image for duplicate finding.
+#[allow(clippy::too_many_arguments)]
 fn preloader_loop(
     store: Arc<ImageStore>,
     state: Arc<SharedState>,
     decoder: Arc<Decoder>,
+    cache: Option<Arc<ThumbnailCache>>,
+    pool: rayon::ThreadPool,
     config: PreloadConfig,
+    memory: MemoryConfig,
+    hash_all: bool,
 ) {
-    // Configure rayon thread pool if max_parallel_tasks is set
-    if config.max_parallel_tasks > 0 {
-        rayon::ThreadPoolBuilder::new()
-            .num_threads(config.max_parallel_tasks)
-            .build_global()
-            .ok(); // Ignore if already initialized
-    }
+    // Slot ids whose header has been read for dimensions, and whose
+    // thumbnail has been hashed (both grow as images are added)
+    let mut probed = vec![false; store.slot_count()];
+    let mut hashed = vec![false; store.slot_count()];
+    let mut last_budget_check = Instant::now();
 
     loop {
         if state.is_shutdown() {
             return;
         }
 
+        // Follow the system's free memory, giving images back if it shrank
+        if !memory.recheck_interval.is_zero()
+            && last_budget_check.elapsed() >= memory.recheck_interval
+        {
+            last_budget_check = Instant::now();
+            let budget = memory.current_budget(store.total_memory_used());
+            store.resize_budget(budget, state.current());
+        }
+
         let total = store.len();
         if total == 0 {
-            thread::sleep(config.idle_poll_interval);
+            state.wait_for_change(config.idle_poll_interval);
             continue;
         }
 
-        // Get current state
-        let current = state.current();
-        let direction = state.direction();
-
-        // Build load tasks based on direction
-        let tasks = build_prioritized_tasks(&store, current, total, direction, &config);
+        // Small chunks, so a jump elsewhere redirects work quickly
+        let chunk_size = 2 * pool.current_num_threads();
+        // Decodes run detached on the pool, so they own what they use
+        let decode = {
+            let (store, decoder, cache) = (Arc::clone(&store), Arc::clone(&decoder), cache.clone());
+            move |meta: &ImageMeta, data: &[u8], quality| -> Result<Arc<ImageData>, DecodeError> {
+                // Sized to fit before decoding, rather than refused after
+                let decoded =
+                    decoder.decode_bytes_within(meta, data, quality, store.fitting_pixels())?;
+                if decoded.over_budget {
+                    eprintln!(
+                        "Warning: {}: too large for the memory budget, shown at {}x{}",
+                        meta.source, decoded.width, decoded.height
+                    );
+                }
+                if quality == QualityTier::Thumbnail {
+                    meta.set_phash(dhash(&decoded));
+                }
+                if let Some(cache) = &cache {
+                    cache.store(meta, &decoded);
+                }
+                Ok(decoded)
+            }
+        };
+        let worked = preload_pass(
+            &store,
+            &state,
+            &config,
+            chunk_size,
+            &pool,
+            |meta, quality, partial: ShowPartial| {
+                // A cached thumbnail needs no decode at all
+                if quality == QualityTier::Thumbnail {
+                    if let Some(hit) = cache.as_ref().and_then(|c| c.load(meta)) {
+                        return Ok(Source::Decoded(Arc::new(hit)));
+                    }
+                }
+                match partial {
+                    Some(show) if config.progressive_display => {
+                        decoder.read_progressively(meta, quality, show)
+                    }
+                    _ => decoder.read(meta, quality),
+                }
+            },
+            decode,
+        );
 
-        if tasks.is_empty() {
-            // Nothing to load - evict far images and wait
-            evict_far_images(&store, current, &config);
-            thread::sleep(config.idle_poll_interval);
+        if !worked {
+            // Nothing to load - evict far images, then use the idle time to
+            // read image headers and hash thumbnails; wait for navigation
+            // once all are done
+            evict_far_images(&store, state.current(), &config);
+            probed.resize(store.slot_count(), false);
+            hashed.resize(store.slot_count(), false);
+            let busy = prefetch_dimensions(&store, &state, &decoder, &mut probed, METADATA_BATCH)
+                || (hash_all
+                    && pool.install(|| {
+                        hash_thumbnails(
+                            &store,
+                            &state,
+                            &decoder,
+                            cache.as_deref(),
+                            &mut hashed,
+                            chunk_size,
+                        )
+                    }));
+            if !busy {
+                state.wait_for_change(config.idle_poll_interval);
+            }
             continue;
         }
 
-        // Decode ALL tasks in parallel - don't limit batch size
-        // Rayon will efficiently distribute across cores
-        let results: Vec<_> = tasks
-            .par_iter()
+        // Evict images that are too far from current position
+        evict_far_images(&store, state.current(), &config);
+
+        // Inserts, reloads and removals hold the store's change lock, which
+        // the check takes too; making room, demoting and evicting only
+        // happen on this thread. So no change can be caught midway.
+        debug_assert!(
+            store.budget_reconciled(),
+            "memory budget drifted from slot contents"
+        );
+    }
+}
+
+/// Plan tasks around the current position and load them `chunk_size` at
+/// a time. Between chunks, navigation makes the rest of the plan stale:
+/// finished decodes are kept, pending ones are dropped so the next pass
+/// replans from the new position. Returns false if there was nothing to
+/// load.
+fn preload_pass<R, D>(
+    store: &Arc<ImageStore>,
+    state: &SharedState,
+    config: &PreloadConfig,
+    chunk_size: usize,
+    pool: &rayon::ThreadPool,
+    read: R,
+    decode: D,
+) -> bool
+where
+    R: Fn(&ImageMeta, QualityTier, ShowPartial) -> Result<Source, DecodeError> + Sync,
+    D: Fn(&ImageMeta, &[u8], QualityTier) -> Result<Arc<ImageData>, DecodeError>
+        + Send
+        + Sync
+        + 'static,
+{
+    let decode = Arc::new(decode);
+    let total = store.len();
+    if total == 0 {
+        return false;
+    }
+    let generation = state.generation();
+
+    // Also refreshes the current image's reservation, in case its header
+    // has been read since navigation pinned it
+    store.pin(state.current());
+
+    // Build load tasks for the gallery's cells, or based on direction
+    let tasks = match state.grid() {
+        Some(cells) => build_grid_tasks(store, cells, state.current()),
+        None => {
+            // Nearby images are decoded to fit the window, the one on
+            // screen at full resolution once zoomed in
+            let near = if config.display_fit {
+                state.display_tier(config.tier_sizes())
+            } else {
+                QualityTier::Full
+            };
+            let shown = if state.zoomed_in() {
+                QualityTier::Full
+            } else {
+                near
+            };
+            let tiers = NearTiers { near, shown };
+            let mut tasks = build_prioritized_tasks(
+                store,
+                state.current(),
+                total,
+                state.direction(),
+                config,
+                tiers,
+            );
+            let filmstrip =
+                build_filmstrip_tasks(store, state.current(), state.filmstrip(), &tasks);
+            tasks.extend(filmstrip);
+            tasks
+        }
+    };
+    state.set_queued(tasks.len());
+    if tasks.is_empty() {
+        return false;
+    }
+
+    for (n, batch) in tasks.chunks(chunk_size.max(1)).enumerate() {
+        // Make room ahead of decoding nearby images whose size is known
+        let reserve: usize = batch
+            .iter()
+            .filter(|task| task.distance <= config.full_quality_count)
             .filter_map(|task| {
-                // Don't check generation during decode - we want to finish work
-                // even if user navigated (the images are still useful)
-                let slot = store.slot(task.index);
-                let path = &slot.meta.path;
-                decoder
-                    .decode(path, task.quality)
-                    .map(|data| (task.index, data))
+                store
+                    .slot_by_id(task.slot_id)
+                    .meta
+                    .memory_for_tier(task.quality, config.tier_sizes())
             })
-            .collect();
+            .sum();
+        if reserve > 0 {
+            make_room(store, reserve, state.current(), config);
+        }
+
+        // Insert each result as it lands, so the current image's preview
+        // shows while its full decode still runs. Even if the user
+        // navigated, these are still useful; they'll be evicted later if
+        // too far away.
+        read_and_decode(
+            store,
+            batch,
+            config.read_threads,
+            config.decode_timeout,
+            pool,
+            &read,
+            &decode,
+            |task, data| {
+                let id = task.slot_id;
+                // The file was reloaded, deleted or filtered out while this
+                // decode ran (the insert checks for a reload again)
+                if !store.is_shown(id) || store.slot_by_id(id).reloads() != task.reloads {
+                    return;
+                }
+                let current_now = state.current();
+                let dist = store.distance(store.position_of(id), current_now, total);
+                // Make room for nearby images
+                if dist <= config.full_quality_count {
+                    make_room(store, data.memory_size(), current_now, config);
+                }
+                store.insert_unless_reloaded(id, data, task.reloads);
+            },
+        );
+        state.set_queued(tasks.len().saturating_sub((n + 1) * chunk_size.max(1)));
 
-        // Insert all results - even if user navigated, these are still useful
-        // They'll be evicted later if too far away
-        let current_now = state.current();
-        for (idx, data) in results {
-            let dist = circular_distance(idx, current_now, total);
-            // Make room for nearby images
-            if dist <= config.full_quality_count {
-                store.make_room(data.memory_size(), current_now);
+        if state.generation() != generation || state.is_shutdown() {
+            break;
+        }
+    }
+    true
+}
+
+/// Where a read hands coarse passes over the image on screen, for the
+/// reads that have one
+type ShowPartial<'a> = Option<&'a dyn Fn(Arc<ImageData>)>;
+
+/// A decode handed to the pool, watched for running over the timeout
+#[derive(Default)]
+struct DecodeWatch {
+    /// When a worker picked the decode up
+    started: OnceLock<Instant>,
+    /// Set once the decode is given up on
+    cancel: Arc<AtomicBool>,
+}
+
+/// Two-stage pipeline: a few threads read files in priority order (so slow
+/// disks see few, mostly sequential reads) and hand the bytes to `pool` to
+/// decode as they arrive. Failures, panics included, are recorded on their
+/// slots. The current image's read may put coarse passes in its slot on
+/// the way.
+///
+/// Each decode is handed to `done` on this thread as soon as it finishes,
+/// while later reads and decodes are still under way.
+///
+/// A decode running longer than `timeout` (unless zero) fails its slot and
+/// is left behind rather than waited for. Its worker stays busy until the
+/// decoder notices the cancellation, or finishes; the result is dropped.
+#[allow(clippy::too_many_arguments)]
+fn read_and_decode<R, D>(
+    store: &Arc<ImageStore>,
+    tasks: &[LoadTask],
+    read_threads: usize,
+    timeout: Duration,
+    pool: &rayon::ThreadPool,
+    read: &R,
+    decode: &Arc<D>,
+    mut done: impl FnMut(LoadTask, Arc<ImageData>),
+) where
+    R: Fn(&ImageMeta, QualityTier, ShowPartial) -> Result<Source, DecodeError> + Sync,
+    D: Fn(&ImageMeta, &[u8], QualityTier) -> Result<Arc<ImageData>, DecodeError>
+        + Send
+        + Sync
+        + 'static,
+{
+    let (task_tx, task_rx) = crossbeam_channel::unbounded();
+    for task in tasks {
+        let _ = task_tx.send(*task);
+    }
+    drop(task_tx);
+
+    // Bounded, so readers don't run far ahead of decoding
+    let (read_tx, read_rx) = crossbeam_channel::bounded(tasks.len().max(1));
+    let (done_tx, done_rx) = crossbeam_channel::unbounded();
+    let mut pending: Vec<Option<(LoadTask, Arc<DecodeWatch>)>> = Vec::new();
+    let mut left = 0;
+
+    thread::scope(|scope| {
+        for _ in 0..read_threads.max(1) {
+            let (task_rx, read_tx) = (task_rx.clone(), read_tx.clone());
+            scope.spawn(move || {
+                for task in task_rx {
+                    let slot = store.slot_by_id(task.slot_id);
+                    let show = |data: Arc<ImageData>| {
+                        store.insert_unless_reloaded(task.slot_id, data, task.reloads);
+                    };
+                    let partial = (task.distance == 0).then_some(&show as &dyn Fn(_));
+                    if read_tx
+                        .send((
+                            task,
+                            catch_panics(|| read(&slot.meta, task.quality, partial)),
+                        ))
+                        .is_err()
+                    {
+                        return;
+                    }
+                }
+            });
+        }
+        drop(read_tx);
+
+        // Hand reads to the pool and collect finished decodes together,
+        // until every read is in and every decode is out
+        let mut reads = read_rx;
+        let mut reading = true;
+        while reading || left > 0 {
+            let mut finished = Vec::new();
+            crossbeam_channel::select! {
+                recv(reads) -> read => match read {
+                    Ok((task, source)) => {
+                        left += 1;
+                        let watch = Arc::new(DecodeWatch::default());
+                        let index = pending.len();
+                        pending.push(Some((task, Arc::clone(&watch))));
+                        let (store, decode, done_tx) =
+                            (Arc::clone(store), Arc::clone(decode), done_tx.clone());
+                        pool.spawn(move || {
+                            let _ = watch.started.set(Instant::now());
+                            let slot = store.slot_by_id(task.slot_id);
+                            let decoded = source.and_then(|source| match source {
+                                Source::Decoded(data) => Ok(data),
+                                Source::Bytes(data) => with_cancel(&watch.cancel, || {
+                                    catch_panics(|| decode(&slot.meta, &data, task.quality))
+                                }),
+                            });
+                            // Nobody listens for a decode that was given up on
+                            let _ = done_tx.send((index, decoded));
+                        });
+                    }
+                    Err(_) => {
+                        reading = false;
+                        reads = crossbeam_channel::never();
+                    }
+                },
+                recv(done_rx) -> decoded => {
+                    if let Ok((index, decoded)) = decoded {
+                        if let Some((task, _)) = pending[index].take() {
+                            finished.push((task, decoded));
+                        }
+                    }
+                },
+                default(WATCHDOG_INTERVAL) => {}
+            }
+            if !timeout.is_zero() {
+                for entry in &mut pending {
+                    let overran = entry
+                        .as_ref()
+                        .and_then(|(_, watch)| watch.started.get())
+                        .is_some_and(|started| started.elapsed() >= timeout);
+                    if !overran {
+                        continue;
+                    }
+                    if let Some((task, watch)) = entry.take() {
+                        watch.cancel.store(true, Ordering::Relaxed);
+                        finished.push((task, Err(DecodeError::TimedOut)));
+                    }
+                }
+            }
+
+            for (task, decoded) in finished {
+                left -= 1;
+                match decoded {
+                    Ok(data) => done(task, data),
+                    Err(err) => {
+                        let warning = format!(
+                            "Warning: {}: {}",
+                            store.slot_by_id(task.slot_id).meta.source,
+                            err
+                        );
+                        // Reloaded or deleted meanwhile: the error is stale
+                        if store.fail_unless_reloaded(task.slot_id, err, task.reloads) {
+                            eprintln!("{}", warning);
+                        }
+                    }
+                }
             }
-            store.insert(idx, data);
         }
+    });
+}
 
-        // Evict images that are too far from current position
-        evict_far_images(&store, state.current(), &config);
+/// Low-priority metadata pass: read header dimensions for up to `limit`
+/// images not yet looked at, nearest to the current image first, so memory
+/// can be planned before decoding. Returns false once every image has been
+/// looked at.
+fn prefetch_dimensions(
+    store: &ImageStore,
+    state: &SharedState,
+    decoder: &Decoder,
+    probed: &mut [bool],
+    limit: usize,
+) -> bool {
+    let generation = state.generation();
+    let mut done = 0;
+    for position in nearest_first(store, state.current()) {
+        let id = store.slot_id(position);
+        // Images added since the caller sized `probed` wait for next time
+        if probed.get(id) != Some(&false) {
+            continue;
+        }
+        probed[id] = true;
+
+        let slot = store.slot_by_id(id);
+        let meta = &slot.meta;
+        if meta.dimensions().is_none() {
+            if let Some((width, height)) = decoder.read_dimensions(meta) {
+                meta.set_dimensions(width, height);
+            }
+        }
+
+        done += 1;
+        // Navigation means there may be decoding to do first
+        if done == limit || state.generation() != generation {
+            break;
+        }
     }
+    done > 0
+}
+
+/// Low-priority hashing pass: compute the perceptual hash of up to `limit`
+/// images not yet looked at, nearest to the current image first, in
+/// parallel. The resident or cached thumbnail is used where there is one;
+/// otherwise a thumbnail is decoded for the cache but not kept in memory.
+/// Returns false once every image has been looked at.
+fn hash_thumbnails(
+    store: &ImageStore,
+    state: &SharedState,
+    decoder: &Decoder,
+    cache: Option<&ThumbnailCache>,
+    hashed: &mut [bool],
+    limit: usize,
+) -> bool {
+    // Images added since the caller sized `hashed` wait for next time
+    let batch: Vec<usize> = nearest_first(store, state.current())
+        .map(|position| store.slot_id(position))
+        .filter(|&id| hashed.get(id) == Some(&false))
+        .take(limit)
+        .collect();
+    for &id in &batch {
+        hashed[id] = true;
+    }
+
+    batch.par_iter().for_each(|&id| {
+        let slot = store.slot_by_id(id);
+        let meta = &slot.meta;
+        if meta.phash().is_some() {
+            return;
+        }
+        if let Some(thumbnail) = slot.thumbnail() {
+            meta.set_phash(dhash(&thumbnail));
+            return;
+        }
+        if let Some(cache) = cache {
+            if let Some(hit) = cache.load(meta) {
+                // Entries from before hashing get their hash added
+                if meta.phash().is_none() {
+                    meta.set_phash(dhash(&hit));
+                    cache.store(meta, &hit);
+                }
+                return;
+            }
+        }
+        // Failures are reported when the image itself is shown
+        if let Ok(thumbnail) = decoder.decode(meta, QualityTier::Thumbnail) {
+            meta.set_phash(dhash(&thumbnail));
+            if let Some(cache) = cache {
+                cache.store(meta, &thumbnail);
+            }
+        }
+    });
+    !batch.is_empty()
+}
+
+/// Every position, the current one first and then outward
+fn nearest_first(store: &ImageStore, current: usize) -> impl Iterator<Item = usize> {
+    let total = store.len();
+    std::iter::once(current).chain(
+        indices_around(current, total, total, total, store.wraps())
+            .into_iter()
+            .map(|(i, _)| i),
+    )
 }
 
 /// A task describing what to load
 #[derive(Debug, Clone, Copy)]
 struct LoadTask {
-    index: usize,
+    /// Slot id resolved at planning time (stable across reshuffles)
+    slot_id: usize,
     quality: QualityTier,
     distance: usize,
     in_direction: bool, // Is this in the predicted direction of travel?
+    /// The slot's reload count at planning time
+    reloads: u64,
+}
+
+/// What stands in for the full tier when planning
+#[derive(Debug, Clone, Copy)]
+struct NearTiers {
+    /// For images within `full_quality_count` of the current one
+    near: QualityTier,
+    /// For the images on screen
+    shown: QualityTier,
+}
+
+impl NearTiers {
+    /// Full resolution throughout
+    #[cfg(test)]
+    const FULL: Self = Self {
+        near: QualityTier::Full,
+        shown: QualityTier::Full,
+    };
 }
 
 /// Build prioritized list of images to load based on direction
@@ -119,48 +619,72 @@ fn build_prioritized_tasks(
     total: usize,
     direction: Direction,
     config: &PreloadConfig,
+    tiers: NearTiers,
 ) -> Vec<LoadTask> {
     let mut tasks = Vec::new();
     let (ahead_range, behind_range) = config.range_for_direction(direction);
 
-    // Current image: ALWAYS load at full quality first
-    if !store.slot(current).has_quality(QualityTier::Full) {
-        tasks.push(LoadTask {
-            index: current,
-            quality: QualityTier::Full,
-            distance: 0,
-            in_direction: true,
-        });
+    // Current image: ALWAYS first. A quick preview goes ahead of the full
+    // decode so something shows while a large image decodes; the full
+    // result replaces it when it lands.
+    let mut current_tasks = Vec::new();
+    let slot = store.slot(current);
+    if slot.error().is_none() {
+        for quality in [QualityTier::Preview, tiers.shown] {
+            if !slot.has_quality(quality) {
+                current_tasks.push(LoadTask {
+                    slot_id: store.slot_id(current),
+                    quality,
+                    distance: 0,
+                    in_direction: true,
+                    reloads: slot.reloads(),
+                });
+            }
+        }
     }
 
-    // Build tasks for ahead direction
-    for offset in 1..=ahead_range {
-        let idx = (current + offset) % total;
-        let desired_quality = config.quality_for_distance(offset);
-        let slot = store.slot(idx);
-
-        if !slot.has_quality(desired_quality) {
-            tasks.push(LoadTask {
-                index: idx,
-                quality: desired_quality,
-                distance: offset,
-                in_direction: direction != Direction::Backward,
+    // An image compared with the current one is on screen too
+    if let Some(id) = store.held().filter(|&id| store.is_shown(id)) {
+        let held = store.slot_by_id(id);
+        if id != store.slot_id(current) && !held.has_quality(tiers.shown) && held.error().is_none()
+        {
+            current_tasks.push(LoadTask {
+                slot_id: id,
+                quality: tiers.shown,
+                distance: 0,
+                in_direction: true,
+                reloads: held.reloads(),
             });
         }
     }
 
-    // Build tasks for behind direction
-    for offset in 1..=behind_range {
-        let idx = (current + total - offset) % total;
-        let desired_quality = config.quality_for_distance(offset);
+    // Neighbours in both directions, each index once even when the
+    // ranges exceed the collection
+    for (idx, offset) in indices_around(current, total, ahead_range, behind_range, store.wraps()) {
+        let distance = offset.unsigned_abs();
+        let desired_quality = match config.quality_for_distance(distance) {
+            QualityTier::Full => tiers.near,
+            quality => quality,
+        };
         let slot = store.slot(idx);
 
-        if !slot.has_quality(desired_quality) {
+        // Evicted for memory: leave it until the user comes closer, or it
+        // would be decoded and evicted again in a loop
+        if slot.demoted_at().is_some_and(|at| distance >= at) {
+            continue;
+        }
+
+        if !slot.has_quality(desired_quality) && slot.error().is_none() {
             tasks.push(LoadTask {
-                index: idx,
+                slot_id: store.slot_id(idx),
                 quality: desired_quality,
-                distance: offset,
-                in_direction: direction != Direction::Forward,
+                distance,
+                in_direction: if offset > 0 {
+                    direction != Direction::Backward
+                } else {
+                    direction != Direction::Forward
+                },
+                reloads: slot.reloads(),
             });
         }
     }
@@ -185,7 +709,89 @@ fn build_prioritized_tasks(
         a.distance.cmp(&b.distance)
     });
 
-    tasks
+    current_tasks.append(&mut tasks);
+    current_tasks
+}
+
+/// Plan thumbnails for the filmstrip's images within `reach` of the current
+/// one that have nothing to show and aren't already planned
+fn build_filmstrip_tasks(
+    store: &ImageStore,
+    current: usize,
+    reach: usize,
+    planned: &[LoadTask],
+) -> Vec<LoadTask> {
+    indices_around(current, store.len(), reach, reach, store.wraps())
+        .into_iter()
+        .filter_map(|(index, offset)| {
+            let slot = store.slot(index);
+            let id = store.slot_id(index);
+            (slot.is_empty()
+                && slot.error().is_none()
+                && !planned.iter().any(|task| task.slot_id == id))
+            .then(|| LoadTask {
+                slot_id: id,
+                quality: QualityTier::Thumbnail,
+                distance: offset.unsigned_abs(),
+                in_direction: true,
+                reloads: slot.reloads(),
+            })
+        })
+        .collect()
+}
+
+/// Plan thumbnails for the gallery: the cells on screen top down, then a
+/// screenful past them ahead and behind, so scrolling finds them ready
+fn build_grid_tasks(store: &ImageStore, cells: Range<usize>, current: usize) -> Vec<LoadTask> {
+    let total = store.len();
+    let page = cells.len();
+    let ahead = cells.end.min(total)..(cells.end + page).min(total);
+    let behind = (cells.start.saturating_sub(page)..cells.start.min(total)).rev();
+
+    cells
+        .clone()
+        .filter(|&index| index < total)
+        .chain(ahead)
+        .chain(behind)
+        .filter_map(|index| {
+            let slot = store.slot(index);
+            (!slot.has_quality(QualityTier::Thumbnail) && slot.error().is_none()).then(|| {
+                LoadTask {
+                    slot_id: store.slot_id(index),
+                    quality: QualityTier::Thumbnail,
+                    distance: store.distance(index, current, total),
+                    in_direction: true,
+                    reloads: slot.reloads(),
+                }
+            })
+        })
+        .collect()
+}
+
+/// Free memory for a new image near the current one. Neighbours holding
+/// more than their distance calls for are downgraded in memory first,
+/// furthest first, so they keep something to show; only then is anything
+/// dropped.
+fn make_room(store: &ImageStore, needed: usize, current: usize, config: &PreloadConfig) {
+    let total = store.len();
+    let mut surplus: Vec<(usize, usize)> = (0..total)
+        .filter_map(|index| {
+            let dist = store.distance(index, current, total);
+            let held = store.slot(index).current_quality()?;
+            (dist <= config.total_range() && held > config.quality_for_distance(dist))
+                .then_some((index, dist))
+        })
+        .collect();
+    surplus.sort_by_key(|&(_, dist)| std::cmp::Reverse(dist));
+
+    for (index, dist) in surplus {
+        if store.available_memory() >= needed {
+            return;
+        }
+        store.downgrade(index, config.quality_for_distance(dist));
+    }
+
+    store.make_room(needed, current);
 }
 
 /// Evict images that are too far from current position
@@ -194,10 +800,8 @@ fn evict_far_images(store: &ImageStore, current: usize, config: &PreloadConfig)
     store.evict_far(current, keep_range);
 }
 
-/// Create image store with paths only (fast startup, no I/O)
-pub fn create_store_fast(paths: Vec<std::path::PathBuf>, budget: Arc<MemoryBudget>) -> ImageStore {
-    let metas: Vec<ImageMeta> = paths.into_iter().map(ImageMeta::new).collect();
-
+/// Create image store with metadata only (fast startup, no decoding)
+pub fn create_store_fast(metas: Vec<ImageMeta>, budget: Arc<MemoryBudget>) -> ImageStore {
     ImageStore::with_metadata(metas, budget)
 }
 
@@ -216,6 +820,643 @@ mod tests {
         assert!(config.quality_for_distance(20) == QualityTier::Thumbnail);
     }
 
+    #[test]
+    fn test_make_room_downgrades_before_evicting() {
+        let config = PreloadConfig::default();
+        let far = config.full_quality_count + 1;
+        let size = 2048 * 1024 * 4;
+        let thumbnail = 256 * 128 * 4;
+        let metas = (0..50)
+            .map(|i| ImageMeta::new(format!("{}.png", i).into()))
+            .collect();
+        let store =
+            ImageStore::with_metadata(metas, Arc::new(MemoryBudget::new(2 * (size + thumbnail))));
+        for index in [0, far] {
+            let data = ImageData::new(vec![0; size], 2048, 1024, QualityTier::Full);
+            assert!(store.insert(index, Arc::new(data)));
+        }
+
+        make_room(&store, size / 2, 0, &config);
+
+        // The neighbour now wants only a preview, derived from its pixels
+        assert_eq!(store.slot(0).current_quality(), Some(QualityTier::Full));
+        assert_eq!(
+            store.slot(far).current_quality(),
+            Some(QualityTier::Preview)
+        );
+        assert!(store.available_memory() >= size / 2);
+    }
+
+    #[test]
+    fn test_partial_passes_of_current_image() {
+        use std::path::PathBuf;
+        use std::sync::Mutex;
+
+        let config = PreloadConfig::default();
+        let metas = (0..4)
+            .map(|i| ImageMeta::new(format!("{}.jpg", i).into()))
+            .collect();
+        let store = Arc::new(ImageStore::with_metadata(
+            metas,
+            Arc::new(MemoryBudget::new(usize::MAX)),
+        ));
+        let tasks =
+            build_prioritized_tasks(&store, 0, 4, Direction::Forward, &config, NearTiers::FULL);
+
+        // Each read offers a coarse pass; the decode checks what got shown
+        let read = |_: &ImageMeta, _: QualityTier, partial: ShowPartial| {
+            if let Some(show) = partial {
+                let pass = ImageData::new(vec![0; 4], 1, 1, QualityTier::Preview).with_partial();
+                show(Arc::new(pass));
+            }
+            Ok(Source::Bytes(Vec::new()))
+        };
+        let shown = Arc::new(Mutex::new(Vec::new()));
+        let decode = Arc::new({
+            let (store, shown) = (Arc::clone(&store), Arc::clone(&shown));
+            move |meta: &ImageMeta, _: &[u8], quality: QualityTier| {
+                let stem = meta.path().file_stem().unwrap().to_str().unwrap();
+                let partial = store.slot(stem.parse().unwrap()).read();
+                if partial.is_some_and(|data| data.partial) {
+                    shown.lock().unwrap().push(meta.path().to_path_buf());
+                }
+                Ok(Arc::new(ImageData::new(vec![0; 4], 1, 1, quality)))
+            }
+        });
+
+        let pool = decode_pool(2);
+        let mut results = Vec::new();
+        read_and_decode(
+            &store,
+            &tasks,
+            1,
+            Duration::ZERO,
+            &pool,
+            &read,
+            &decode,
+            |task, data| results.push((task, data)),
+        );
+        let mut shown = shown.lock().unwrap().clone();
+        shown.dedup();
+        assert_eq!(shown, vec![PathBuf::from("0.jpg")]);
+        // The complete decode replaces the pass
+        for (task, data) in results {
+            store.insert_by_id(task.slot_id, data);
+        }
+        assert!(!store.slot(0).read().unwrap().partial);
+    }
+
+    #[test]
+    fn test_preview_handed_over_before_full_decode_ends() {
+        let config = PreloadConfig::default();
+        let metas = (0..4)
+            .map(|i| ImageMeta::new(format!("{}.png", i).into()))
+            .collect();
+        let store = Arc::new(ImageStore::with_metadata(
+            metas,
+            Arc::new(MemoryBudget::new(usize::MAX)),
+        ));
+        let tasks =
+            build_prioritized_tasks(&store, 0, 4, Direction::Forward, &config, NearTiers::FULL);
+        let read = |_: &ImageMeta, _: QualityTier, _: ShowPartial| Ok(Source::Bytes(Vec::new()));
+        // The current image's full decode lasts until its preview is out
+        let preview_out = Arc::new(AtomicBool::new(false));
+        let waited = Arc::new(AtomicBool::new(false));
+        let decode = Arc::new({
+            let (preview_out, waited) = (Arc::clone(&preview_out), Arc::clone(&waited));
+            move |meta: &ImageMeta, _: &[u8], quality: QualityTier| {
+                if meta.path().ends_with("0.png") && quality == QualityTier::Full {
+                    let deadline = Instant::now() + Duration::from_secs(1);
+                    while !preview_out.load(Ordering::Relaxed) && Instant::now() < deadline {
+                        thread::sleep(Duration::from_millis(5));
+                    }
+                    waited.store(preview_out.load(Ordering::Relaxed), Ordering::Relaxed);
+                }
+                Ok(Arc::new(ImageData::new(vec![0; 4], 1, 1, quality)))
+            }
+        });
+
+        let pool = decode_pool(2);
+        read_and_decode(
+            &store,
+            &tasks,
+            1,
+            Duration::ZERO,
+            &pool,
+            &read,
+            &decode,
+            |task, _| {
+                if task.slot_id == 0 && task.quality == QualityTier::Preview {
+                    preview_out.store(true, Ordering::Relaxed);
+                }
+            },
+        );
+        assert!(waited.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn test_decoder_panic_fails_its_slot_only() {
+        let config = PreloadConfig::default();
+        let metas = (0..4)
+            .map(|i| ImageMeta::new(format!("{}.png", i).into()))
+            .collect();
+        let store = Arc::new(ImageStore::with_metadata(
+            metas,
+            Arc::new(MemoryBudget::new(1 << 20)),
+        ));
+        let tasks =
+            build_prioritized_tasks(&store, 0, 4, Direction::Forward, &config, NearTiers::FULL);
+        let read = |_: &ImageMeta, _: QualityTier, _: ShowPartial| Ok(Source::Bytes(Vec::new()));
+        let decode = Arc::new(|meta: &ImageMeta, _: &[u8], quality: QualityTier| {
+            if meta.path().ends_with("2.png") {
+                panic!("malformed file");
+            }
+            Ok(Arc::new(ImageData::new(vec![0; 4], 1, 1, quality)))
+        });
+
+        let pool = decode_pool(2);
+        let mut results = Vec::new();
+        read_and_decode(
+            &store,
+            &tasks,
+            1,
+            Duration::ZERO,
+            &pool,
+            &read,
+            &decode,
+            |task, data| results.push((task, data)),
+        );
+        let mut loaded: Vec<usize> = results.iter().map(|(task, _)| task.slot_id).collect();
+        loaded.sort();
+        loaded.dedup();
+        assert_eq!(loaded, vec![0, 1, 3]);
+        let error = store.slot(2).error().unwrap().to_string();
+        assert!(error.contains("decoder panicked: malformed file"));
+
+        // The pool survives to decode the next batch
+        let mut again = 0;
+        read_and_decode(
+            &store,
+            &tasks[..1],
+            1,
+            Duration::ZERO,
+            &pool,
+            &read,
+            &decode,
+            |_, _| again += 1,
+        );
+        assert_eq!(again, 1);
+    }
+
+    #[test]
+    fn test_slow_decode_times_out() {
+        let config = PreloadConfig::default();
+        let metas = (0..4)
+            .map(|i| ImageMeta::new(format!("{}.png", i).into()))
+            .collect();
+        let store = Arc::new(ImageStore::with_metadata(
+            metas,
+            Arc::new(MemoryBudget::new(1 << 20)),
+        ));
+        let tasks =
+            build_prioritized_tasks(&store, 0, 4, Direction::Forward, &config, NearTiers::FULL);
+        let read = |_: &ImageMeta, _: QualityTier, _: ShowPartial| Ok(Source::Bytes(Vec::new()));
+        // 1.png ignores cancellation; 2.png polls for it like our own
+        // resize loops do
+        let gave_up = Arc::new(AtomicBool::new(false));
+        let decode = Arc::new({
+            let gave_up = Arc::clone(&gave_up);
+            move |meta: &ImageMeta, _: &[u8], quality: QualityTier| {
+                if meta.path().ends_with("1.png") {
+                    thread::sleep(Duration::from_secs(2));
+                }
+                if meta.path().ends_with("2.png") {
+                    while !crate::decode::cancelled() {
+                        thread::sleep(Duration::from_millis(5));
+                    }
+                    gave_up.store(true, Ordering::Relaxed);
+                    return Err(DecodeError::TimedOut);
+                }
+                Ok(Arc::new(ImageData::new(vec![0; 4], 1, 1, quality)))
+            }
+        });
+
+        let started = Instant::now();
+        let pool = decode_pool(4);
+        let timeout = Duration::from_millis(50);
+        let mut results = Vec::new();
+        read_and_decode(
+            &store,
+            &tasks,
+            1,
+            timeout,
+            &pool,
+            &read,
+            &decode,
+            |task, data| results.push((task, data)),
+        );
+        // The batch didn't wait for the sleeping decode
+        assert!(started.elapsed() < Duration::from_secs(1));
+        let mut loaded: Vec<usize> = results.iter().map(|(task, _)| task.slot_id).collect();
+        loaded.sort();
+        loaded.dedup();
+        assert_eq!(loaded, vec![0, 3]);
+        for index in [1, 2] {
+            let error = store.slot(index).error().unwrap();
+            assert!(matches!(*error, DecodeError::TimedOut));
+        }
+
+        let deadline = Instant::now() + Duration::from_secs(1);
+        while !gave_up.load(Ordering::Relaxed) {
+            assert!(Instant::now() < deadline, "cancellation never seen");
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn test_small_collection_tasks_are_unique() {
+        let config = PreloadConfig::default();
+        let metas = (0..4)
+            .map(|i| ImageMeta::new(format!("{}.png", i).into()))
+            .collect();
+        let store = ImageStore::with_metadata(metas, Arc::new(MemoryBudget::new(1000)));
+
+        let tasks =
+            build_prioritized_tasks(&store, 1, 4, Direction::Forward, &config, NearTiers::FULL);
+        let mut ids: Vec<usize> = tasks
+            .iter()
+            .filter(|t| t.distance > 0)
+            .map(|t| t.slot_id)
+            .collect();
+        ids.sort();
+        assert_eq!(ids, vec![0, 2, 3]);
+    }
+
+    #[test]
+    fn test_jump_redirects_pending_work() {
+        use std::path::PathBuf;
+        use std::sync::Mutex;
+
+        let config = PreloadConfig::default();
+        let metas = (0..100)
+            .map(|i| ImageMeta::new(format!("{}.png", i).into()))
+            .collect();
+        let store = Arc::new(ImageStore::with_metadata(
+            metas,
+            Arc::new(MemoryBudget::new(usize::MAX)),
+        ));
+        let state = SharedState::new();
+        state.set_total(100);
+        state.set_start(0);
+
+        // Records read order; the user jumps to 50 during the third read
+        let decoded = Mutex::new(Vec::new());
+        let read = |meta: &ImageMeta, _: QualityTier, _: ShowPartial| {
+            let mut decoded = decoded.lock().unwrap();
+            decoded.push(meta.path().to_path_buf());
+            if decoded.len() == 3 {
+                state.set_current(50);
+            }
+            Ok::<_, DecodeError>(Source::Bytes(Vec::new()))
+        };
+        let decode = |_: &ImageMeta, _: &[u8], quality| {
+            Ok::<_, DecodeError>(Arc::new(ImageData::new(vec![0; 4], 1, 1, quality)))
+        };
+        let chunk_size = 4;
+        let pool = decode_pool(2);
+
+        assert!(preload_pass(
+            &store, &state, &config, chunk_size, &pool, read, decode
+        ));
+        // The old plan stops at the end of the chunk it was in
+        let before_jump = decoded.lock().unwrap().len();
+        assert!(before_jump <= chunk_size);
+
+        // The next pass starts at the new position (files are read in
+        // priority order)
+        assert!(preload_pass(
+            &store, &state, &config, chunk_size, &pool, read, decode
+        ));
+        assert_eq!(
+            decoded.lock().unwrap()[before_jump],
+            PathBuf::from("50.png")
+        );
+    }
+
+    #[test]
+    fn test_image_removed_mid_decode() {
+        let config = PreloadConfig::default();
+        let metas = (0..10)
+            .map(|i| ImageMeta::new(format!("{}.png", i).into()))
+            .collect();
+        let store = Arc::new(ImageStore::with_metadata(
+            metas,
+            Arc::new(MemoryBudget::new(usize::MAX)),
+        ));
+        let state = SharedState::new();
+        state.set_total(10);
+        state.set_start(0);
+        let removed = store.slot_id(1);
+
+        // The file is deleted while it's being read, and reading fails
+        let read = |meta: &ImageMeta, _: QualityTier, _: ShowPartial| {
+            if meta.path() == std::path::Path::new("1.png") {
+                store.remove_path(meta.path());
+                return Err(DecodeError::UnsupportedFormat);
+            }
+            Ok(Source::Bytes(Vec::new()))
+        };
+        let decode = |_: &ImageMeta, _: &[u8], quality| {
+            Ok::<_, DecodeError>(Arc::new(ImageData::new(vec![0; 4], 1, 1, quality)))
+        };
+
+        assert!(preload_pass(
+            &store,
+            &state,
+            &config,
+            4,
+            &decode_pool(2),
+            read,
+            decode
+        ));
+        assert!(store.is_removed(removed));
+        assert!(store.slot_by_id(removed).error().is_none());
+        assert!(store.slot_by_id(removed).is_empty());
+        assert!(store.budget_reconciled());
+    }
+
+    #[test]
+    fn test_current_image_previews_first() {
+        let config = PreloadConfig::default();
+        let metas = (0..50)
+            .map(|i| ImageMeta::new(format!("{}.png", i).into()))
+            .collect();
+        let store = ImageStore::with_metadata(metas, Arc::new(MemoryBudget::new(usize::MAX)));
+
+        let tasks =
+            build_prioritized_tasks(&store, 7, 50, Direction::Forward, &config, NearTiers::FULL);
+        let first: Vec<_> = tasks[..2].iter().map(|t| (t.slot_id, t.quality)).collect();
+        assert_eq!(
+            first,
+            vec![(7, QualityTier::Preview), (7, QualityTier::Full)]
+        );
+        assert!(tasks[2..].iter().all(|t| t.slot_id != 7));
+
+        // Once a preview is showing, only the full decode is left
+        let preview = ImageData::new(vec![0; 4], 1, 1, QualityTier::Preview);
+        store.insert(7, Arc::new(preview));
+        let tasks =
+            build_prioritized_tasks(&store, 7, 50, Direction::Forward, &config, NearTiers::FULL);
+        assert_eq!((tasks[0].slot_id, tasks[0].quality), (7, QualityTier::Full));
+        assert_ne!(tasks[1].slot_id, 7);
+    }
+
+    #[test]
+    fn test_display_fit_tasks() {
+        let config = PreloadConfig::default();
+        let metas = (0..50)
+            .map(|i| ImageMeta::new(format!("{}.png", i).into()))
+            .collect();
+        let store = ImageStore::with_metadata(metas, Arc::new(MemoryBudget::new(usize::MAX)));
+        let fit = QualityTier::display_fit(1600, 900, config.tier_sizes());
+        let fitted = NearTiers {
+            near: fit,
+            shown: fit,
+        };
+
+        // Nothing nearby is decoded at full resolution
+        let tasks = build_prioritized_tasks(&store, 7, 50, Direction::Forward, &config, fitted);
+        assert_eq!((tasks[1].slot_id, tasks[1].quality), (7, fit));
+        assert!(tasks.iter().all(|t| t.quality != QualityTier::Full));
+
+        // Zoomed in, the current image wants the real thing
+        let zoomed = NearTiers {
+            shown: QualityTier::Full,
+            ..fitted
+        };
+        let tasks = build_prioritized_tasks(&store, 7, 50, Direction::Forward, &config, zoomed);
+        assert_eq!((tasks[1].slot_id, tasks[1].quality), (7, QualityTier::Full));
+        assert!(tasks[2..].iter().all(|t| t.quality != QualityTier::Full));
+
+        // A fit for a smaller window is enough; a larger one needs more
+        let data = ImageData::new(vec![0; 4], 1600, 1200, fit).with_full_size(8000, 6000);
+        store.slot(8).upgrade(Arc::new(data));
+        let tasks = build_prioritized_tasks(&store, 7, 50, Direction::Forward, &config, fitted);
+        assert!(tasks.iter().all(|t| t.slot_id != 8));
+        let larger = NearTiers {
+            near: QualityTier::display_fit(2560, 1440, config.tier_sizes()),
+            ..fitted
+        };
+        let tasks = build_prioritized_tasks(&store, 7, 50, Direction::Forward, &config, larger);
+        assert!(tasks.iter().any(|t| t.slot_id == 8));
+    }
+
+    #[test]
+    fn test_held_image_follows_current() {
+        let config = PreloadConfig::default();
+        let metas = (0..50)
+            .map(|i| ImageMeta::new(format!("{}.png", i).into()))
+            .collect();
+        let store = ImageStore::with_metadata(metas, Arc::new(MemoryBudget::new(usize::MAX)));
+        store.hold(Some(30));
+
+        // Straight after the current image, however far away
+        let tasks =
+            build_prioritized_tasks(&store, 7, 50, Direction::Forward, &config, NearTiers::FULL);
+        assert_eq!(
+            (tasks[2].slot_id, tasks[2].quality),
+            (30, QualityTier::Full)
+        );
+
+        let full = ImageData::new(vec![0; 4], 1, 1, QualityTier::Full);
+        store.insert(30, Arc::new(full));
+        let tasks =
+            build_prioritized_tasks(&store, 7, 50, Direction::Forward, &config, NearTiers::FULL);
+        assert!(tasks.iter().all(|t| t.slot_id != 30));
+    }
+
+    #[test]
+    fn test_grid_tasks() {
+        let metas = (0..50)
+            .map(|i| ImageMeta::new(format!("{}.png", i).into()))
+            .collect();
+        let store = ImageStore::with_metadata(metas, Arc::new(MemoryBudget::new(usize::MAX)));
+        let thumbnail = ImageData::new(vec![0; 4], 1, 1, QualityTier::Thumbnail);
+        store.insert(11, Arc::new(thumbnail));
+
+        // Cells on screen first, then the next page and the one before
+        let tasks = build_grid_tasks(&store, 10..14, 12);
+        let ids: Vec<usize> = tasks.iter().map(|t| t.slot_id).collect();
+        assert_eq!(ids, vec![10, 12, 13, 14, 15, 16, 17, 9, 8, 7, 6]);
+        assert!(tasks.iter().all(|t| t.quality == QualityTier::Thumbnail));
+
+        // Cut off at the ends of the collection
+        let tasks = build_grid_tasks(&store, 48..52, 48);
+        let ids: Vec<usize> = tasks.iter().map(|t| t.slot_id).collect();
+        assert_eq!(ids, vec![48, 49, 47, 46, 45, 44]);
+    }
+
+    #[test]
+    fn test_filmstrip_tasks() {
+        let metas = (0..50)
+            .map(|i| ImageMeta::new(format!("{}.png", i).into()))
+            .collect();
+        let store = ImageStore::with_metadata(metas, Arc::new(MemoryBudget::new(usize::MAX)));
+        let thumbnail = ImageData::new(vec![0; 4], 1, 1, QualityTier::Thumbnail);
+        store.insert(11, Arc::new(thumbnail));
+        let planned = [LoadTask {
+            slot_id: 12,
+            quality: QualityTier::Full,
+            distance: 2,
+            in_direction: true,
+            reloads: 0,
+        }];
+
+        // Thumbnails for the neighbours with nothing to show, once each
+        let tasks = build_filmstrip_tasks(&store, 10, 2, &planned);
+        let mut ids: Vec<usize> = tasks.iter().map(|t| t.slot_id).collect();
+        ids.sort();
+        assert_eq!(ids, vec![8, 9]);
+        assert!(tasks.iter().all(|t| t.quality == QualityTier::Thumbnail));
+
+        // Hidden: nothing
+        assert!(build_filmstrip_tasks(&store, 10, 0, &planned).is_empty());
+    }
+
+    #[test]
+    fn test_prefetch_dimensions() {
+        let root = std::env::temp_dir().join(format!("fiv_prefetch_{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let metas = (0..5)
+            .map(|i| {
+                let path = root.join(format!("{}.png", i));
+                image::RgbImage::new(10 + i, 20).save(&path).unwrap();
+                ImageMeta::new(path)
+            })
+            .collect();
+        let store = ImageStore::with_metadata(metas, Arc::new(MemoryBudget::new(1000)));
+        let state = SharedState::new();
+        state.set_total(5);
+        state.set_start(2);
+        let mut probed = vec![false; 5];
+
+        // Nearest first, a batch at a time
+        assert!(prefetch_dimensions(
+            &store,
+            &state,
+            &Decoder::new(),
+            &mut probed,
+            3
+        ));
+        assert_eq!(store.slot(2).meta.dimensions(), Some((12, 20)));
+        assert_eq!(store.slot(0).meta.dimensions(), None);
+
+        assert!(prefetch_dimensions(
+            &store,
+            &state,
+            &Decoder::new(),
+            &mut probed,
+            3
+        ));
+        assert_eq!(store.slot(0).meta.dimensions(), Some((10, 20)));
+        assert!(!prefetch_dimensions(
+            &store,
+            &state,
+            &Decoder::new(),
+            &mut probed,
+            3
+        ));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_hash_thumbnails() {
+        let root = std::env::temp_dir().join(format!("fiv_hash_{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let metas = (0..3)
+            .map(|i| {
+                let path = root.join(format!("{}.png", i));
+                image::RgbImage::from_fn(40, 30, |x, _| image::Rgb([(x * 6 * (i + 1)) as u8; 3]))
+                    .save(&path)
+                    .unwrap();
+                ImageMeta::new(path)
+            })
+            .collect();
+        let store = ImageStore::with_metadata(metas, Arc::new(MemoryBudget::new(1 << 20)));
+        let state = SharedState::new();
+        state.set_total(3);
+        state.set_start(1);
+        let decoder = Decoder::new();
+        let mut hashed = vec![false; 3];
+
+        // A resident thumbnail is hashed as it is
+        let resident = ImageData::new(vec![255; 4], 1, 1, QualityTier::Thumbnail);
+        store.slot(2).set_thumbnail(Arc::new(resident));
+
+        assert!(hash_thumbnails(
+            &store,
+            &state,
+            &decoder,
+            None,
+            &mut hashed,
+            2
+        ));
+        assert!(store.slot(1).meta.phash().is_some());
+        assert_eq!(store.slot(0).meta.phash(), None);
+        assert!(hash_thumbnails(
+            &store,
+            &state,
+            &decoder,
+            None,
+            &mut hashed,
+            2
+        ));
+        assert!(!hash_thumbnails(
+            &store,
+            &state,
+            &decoder,
+            None,
+            &mut hashed,
+            2
+        ));
+        assert_eq!(store.slot(2).meta.phash(), Some(0));
+        // Decoded thumbnails are not kept
+        assert!(store.slot(0).meta.phash().is_some());
+        assert!(store.slot(0).is_empty());
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_demoted_neighbour_waits_until_closer() {
+        let config = PreloadConfig::default();
+        let metas = (0..50)
+            .map(|i| ImageMeta::new(format!("{}.png", i).into()))
+            .collect();
+        let store = ImageStore::with_metadata(metas, Arc::new(MemoryBudget::new(usize::MAX)));
+        let full = ImageData::new(vec![0; 1024 * 1024 * 4], 1024, 1024, QualityTier::Full);
+        store.insert(10, Arc::new(full));
+
+        // Evicted while the user is 8 away: quality drops to thumbnail...
+        store.evict_far(2, 5);
+        assert_eq!(
+            store.slot(10).current_quality(),
+            Some(QualityTier::Thumbnail)
+        );
+        // ...and isn't scheduled back up from the same distance
+        let tasks =
+            build_prioritized_tasks(&store, 2, 50, Direction::Unknown, &config, NearTiers::FULL);
+        assert!(tasks.iter().all(|t| t.slot_id != 10));
+
+        // Coming back closer promotes it again
+        let tasks =
+            build_prioritized_tasks(&store, 7, 50, Direction::Forward, &config, NearTiers::FULL);
+        assert!(tasks
+            .iter()
+            .any(|t| t.slot_id == 10 && t.quality == QualityTier::Full));
+    }
+
     #[test]
     fn test_direction_ranges() {
         let config = PreloadConfig::default();
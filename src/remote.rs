@@ -0,0 +1,766 @@
+//! Remote control - drive a running viewer from scripts.
+//!
+//! Each viewer listens on a Unix socket, `$XDG_RUNTIME_DIR/fiv-<pid>.sock`,
+//! for newline-delimited JSON commands such as `{"command":"next","count":3}`.
+//! Commands are forwarded to the event loop, which carries them out as the
+//! matching keys would, and each is answered with a line of JSON.
+//! `fiv --remote CMD` sends one to the viewer started last.
+//!
+//! With `--single-instance`, one viewer also listens on
+//! `fiv-instance.sock`, and later launches hand their paths to it there
+//! instead of opening a window of their own. A lock on the file
+//! `fiv-instance.lock`, which stays in place, lets only one launch at a
+//! time check for that viewer and take its place, so two racing launches
+//! can't both become it. The lock goes with the process holding it, so a
+//! launch that dies can't leave it taken.
+//! The viewer serves that socket before scanning its own paths, holding
+//! commands until its event loop is up, and accepts handed-over paths
+//! before scanning them, so a slow scan can't outlast the launch's wait
+//! and leave it opening a window as well.
+
+use crossbeam_channel::{bounded, Sender};
+use serde::{Deserialize, Serialize};
+use std::ffi::OsStr;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+/// How long the viewer has to answer a command
+const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The socket of the viewer other launches hand their paths to
+const INSTANCE_SOCKET: &str = "fiv-instance.sock";
+
+/// Held by the launch checking for that viewer
+const INSTANCE_LOCK: &str = "fiv-instance.lock";
+
+/// How long a launch waits for another to finish checking
+const LOCK_WAIT: Duration = Duration::from_secs(2);
+
+/// A command for a running viewer
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "kebab-case")]
+pub enum RemoteCommand {
+    /// Step forward this many images
+    Next {
+        #[serde(default = "one")]
+        count: u32,
+    },
+    /// Step back this many images
+    Prev {
+        #[serde(default = "one")]
+        count: u32,
+    },
+    /// Go to an image by number, counting from 1 as the title does
+    Goto {
+        index: usize,
+    },
+    /// Browse these files and directories instead, and bring the window
+    /// forward
+    Open {
+        paths: Vec<PathBuf>,
+    },
+    First,
+    Last,
+    Quit,
+    /// Answer with the shown image's path and number
+    QueryStatus,
+}
+
+fn one() -> u32 {
+    1
+}
+
+impl RemoteCommand {
+    /// Parse a command from words such as `next 3` or `goto 42`
+    pub fn parse(words: &[String]) -> Result<Self, String> {
+        let (name, arg) = match words {
+            [name] => (name.as_str(), None),
+            [name, arg] => (name.as_str(), Some(arg.as_str())),
+            _ => return Err("expected a command such as `next` or `goto 42`".to_string()),
+        };
+        let number = || match arg {
+            None => Ok(1),
+            Some(arg) => arg
+                .parse::<u32>()
+                .ok()
+                .filter(|&n| n > 0)
+                .ok_or_else(|| format!("'{}' is not a positive number", arg)),
+        };
+        let command = match name {
+            "next" => Self::Next { count: number()? },
+            "prev" => Self::Prev { count: number()? },
+            "goto" if arg.is_none() => return Err("goto needs an image number".to_string()),
+            "goto" => Self::Goto {
+                index: number()? as usize,
+            },
+            "open" => match arg {
+                Some(arg) => Self::Open {
+                    paths: vec![PathBuf::from(arg)],
+                },
+                None => return Err("open needs a path".to_string()),
+            },
+            "first" => Self::First,
+            "last" => Self::Last,
+            "quit" => Self::Quit,
+            "query-status" => Self::QueryStatus,
+            _ => return Err(format!("unknown command '{}'", name)),
+        };
+        let takes_arg = matches!(
+            command,
+            Self::Next { .. } | Self::Prev { .. } | Self::Goto { .. } | Self::Open { .. }
+        );
+        if arg.is_some() && !takes_arg {
+            return Err(format!("{} takes no argument", name));
+        }
+        Ok(command)
+    }
+}
+
+/// A viewer's answer to a command
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Reply {
+    pub ok: bool,
+    /// Why the command failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// The shown image's path (query-status)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// The shown image's number, counting from 1 (query-status)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub index: Option<usize>,
+    /// Number of images browsed (query-status)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total: Option<usize>,
+}
+
+impl Reply {
+    /// The command was carried out
+    pub fn ok() -> Self {
+        Self {
+            ok: true,
+            ..Self::default()
+        }
+    }
+
+    /// The command failed, for this reason
+    pub fn error(message: impl Into<String>) -> Self {
+        Self {
+            ok: false,
+            error: Some(message.into()),
+            ..Self::default()
+        }
+    }
+}
+
+/// A command received, and where its reply goes
+#[derive(Debug)]
+pub struct RemoteRequest {
+    pub command: RemoteCommand,
+    reply: Sender<Reply>,
+    /// Set by the reply or by the connection giving up waiting for it,
+    /// whichever comes first
+    settled: Arc<AtomicBool>,
+}
+
+impl RemoteRequest {
+    /// Tell the client the command went ahead before it's carried out.
+    /// The request returned is answered into the void; None if the client
+    /// had already given up.
+    fn accept(self) -> Option<Self> {
+        let command = self.command.clone();
+        self.reply(Reply::ok()).then(|| Self {
+            command,
+            reply: bounded(1).0,
+            settled: Arc::new(AtomicBool::new(false)),
+        })
+    }
+
+    /// Answer the command. Returns false, dropping the reply, if the
+    /// client was already told the viewer didn't answer.
+    pub fn reply(self, reply: Reply) -> bool {
+        if self.settled.swap(true, Ordering::AcqRel) {
+            return false;
+        }
+        let _ = self.reply.send(reply);
+        true
+    }
+}
+
+/// Where viewers' sockets live: `$XDG_RUNTIME_DIR`, which only its user
+/// can reach
+pub fn socket_dir() -> Option<PathBuf> {
+    std::env::var_os("XDG_RUNTIME_DIR")
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+}
+
+/// The listening socket, removed when dropped
+pub struct RemoteServer {
+    path: PathBuf,
+    /// The socket file's inode, so one that has since replaced it stays
+    inode: Option<u64>,
+}
+
+impl Drop for RemoteServer {
+    fn drop(&mut self) {
+        let ours = fs::metadata(&self.path).ok().map(|m| m.ino()) == self.inode;
+        if ours {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// A socket bound but not yet answered, for the single-instance viewer
+/// until its event loop is up
+pub struct Claimed {
+    path: PathBuf,
+    listener: UnixListener,
+}
+
+impl Claimed {
+    /// Start answering commands, calling `send` with each one
+    pub fn serve<F>(self, send: F) -> Result<RemoteServer, String>
+    where
+        F: Fn(RemoteRequest) + Send + 'static,
+    {
+        let Claimed { path, listener } = self;
+        let inode = fs::metadata(&path).ok().map(|m| m.ino());
+        std::thread::Builder::new()
+            .name("remote".to_string())
+            .spawn(move || {
+                // One client at a time; each is cut off if it goes quiet
+                for stream in listener.incoming().flatten() {
+                    let _ = answer(stream, &send);
+                }
+            })
+            .map_err(|e| format!("cannot start the remote control thread: {}", e))?;
+        Ok(RemoteServer { path, inode })
+    }
+}
+
+/// Commands a claimed socket received before there was anywhere to send
+/// them
+pub struct HeldRequests {
+    relay: Arc<Mutex<Relay>>,
+}
+
+/// Where a held socket's commands go
+enum Relay {
+    Holding(Vec<RemoteRequest>),
+    Sending(Box<dyn Fn(RemoteRequest) + Send>),
+}
+
+impl Claimed {
+    /// Start answering commands before the event loop is up. Handed-over
+    /// paths are accepted at once and all commands are held, for
+    /// `HeldRequests::release` to pass on.
+    pub fn serve_held(self) -> Result<(RemoteServer, HeldRequests), String> {
+        let relay = Arc::new(Mutex::new(Relay::Holding(Vec::new())));
+        let held = HeldRequests {
+            relay: Arc::clone(&relay),
+        };
+        let server = self.serve(move |request| {
+            let mut relay = relay.lock().unwrap_or_else(|e| e.into_inner());
+            match &mut *relay {
+                Relay::Sending(send) => send(request),
+                Relay::Holding(requests) => {
+                    let request = match request.command {
+                        RemoteCommand::Open { .. } => request.accept(),
+                        _ => Some(request),
+                    };
+                    requests.extend(request);
+                }
+            }
+        })?;
+        Ok((server, held))
+    }
+}
+
+impl HeldRequests {
+    /// Paths of the last `open` held, taking every held `open`
+    pub fn take_open(&self) -> Option<Vec<PathBuf>> {
+        let mut relay = self.relay.lock().unwrap_or_else(|e| e.into_inner());
+        let Relay::Holding(requests) = &mut *relay else {
+            return None;
+        };
+        let mut paths = None;
+        requests.retain(|request| match &request.command {
+            RemoteCommand::Open { paths: open } => {
+                paths = Some(open.clone());
+                false
+            }
+            _ => true,
+        });
+        paths
+    }
+
+    /// Call `send` with the commands held so far, in the order they came,
+    /// and with each one from now on
+    pub fn release<F>(self, send: F)
+    where
+        F: Fn(RemoteRequest) + Send + 'static,
+    {
+        let mut relay = self.relay.lock().unwrap_or_else(|e| e.into_inner());
+        if let Relay::Holding(requests) = &mut *relay {
+            for request in requests.drain(..) {
+                send(request);
+            }
+        }
+        *relay = Relay::Sending(Box::new(send));
+    }
+}
+
+/// Listen for commands on this process's socket, calling `send` with each
+/// one from a thread of its own
+pub fn serve<F>(send: F) -> Result<RemoteServer, String>
+where
+    F: Fn(RemoteRequest) + Send + 'static,
+{
+    let dir = socket_dir().ok_or("XDG_RUNTIME_DIR is not set")?;
+    let path = dir.join(format!("fiv-{}.sock", std::process::id()));
+    // Left behind by an earlier process with the same id
+    let _ = fs::remove_file(&path);
+    bind(path)?.serve(send)
+}
+
+/// Bind a socket at `path`
+fn bind(path: PathBuf) -> Result<Claimed, String> {
+    let listener = UnixListener::bind(&path)
+        .map_err(|e| format!("cannot listen on {}: {}", path.display(), e))?;
+    Ok(Claimed { path, listener })
+}
+
+/// How a single-instance launch went
+pub enum Launch {
+    /// The running viewer took the paths, so this launch is done
+    Forwarded,
+    /// No viewer was running: this one takes the paths of later launches
+    /// once it serves the claimed socket
+    First(Claimed),
+    /// Start as usual, e.g. as the running viewer didn't answer in time
+    Alone(String),
+}
+
+/// Hand `paths` (absolute, as the viewer has its own working directory)
+/// to the single-instance viewer, or become it if there's none
+pub fn forward_or_claim(paths: &[PathBuf]) -> Launch {
+    match socket_dir() {
+        Some(dir) => forward_or_claim_in(&dir, paths),
+        None => Launch::Alone("XDG_RUNTIME_DIR is not set".to_string()),
+    }
+}
+
+fn forward_or_claim_in(dir: &Path, paths: &[PathBuf]) -> Launch {
+    let lock = match InstanceLock::acquire(&dir.join(INSTANCE_LOCK)) {
+        Ok(lock) => lock,
+        Err(err) => return Launch::Alone(err),
+    };
+    let path = dir.join(INSTANCE_SOCKET);
+    let stream = match UnixStream::connect(&path) {
+        Ok(stream) => stream,
+        // None there, or one left by a viewer that's gone: take its place
+        Err(_) => {
+            let _ = fs::remove_file(&path);
+            return match bind(path) {
+                Ok(claimed) => Launch::First(claimed),
+                Err(err) => Launch::Alone(err),
+            };
+        }
+    };
+    // The viewer is there; others may check while it opens the paths
+    drop(lock);
+    let open = RemoteCommand::Open {
+        paths: paths.to_vec(),
+    };
+    match exchange(stream, &open) {
+        Ok(reply) if reply.ok => Launch::Forwarded,
+        Ok(reply) => Launch::Alone(reply.error.unwrap_or_default()),
+        Err(err) => Launch::Alone(err),
+    }
+}
+
+/// An exclusive lock on the lock file, released when dropped. The file
+/// itself is never removed: a launch removing it could take it from under
+/// another that had just locked it.
+struct InstanceLock {
+    _file: fs::File,
+}
+
+impl InstanceLock {
+    /// Take the lock on the file at `path`, creating it if needed and
+    /// waiting for another launch to let go of it
+    fn acquire(path: &Path) -> Result<Self, String> {
+        let file = fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+            .map_err(|e| format!("cannot open {}: {}", path.display(), e))?;
+        let deadline = Instant::now() + LOCK_WAIT;
+        loop {
+            match file.try_lock() {
+                Ok(()) => return Ok(Self { _file: file }),
+                Err(fs::TryLockError::WouldBlock) if Instant::now() < deadline => {
+                    std::thread::sleep(Duration::from_millis(10));
+                }
+                Err(fs::TryLockError::WouldBlock) => {
+                    return Err("another launch is still checking for a viewer".to_string());
+                }
+                Err(fs::TryLockError::Error(e)) => {
+                    return Err(format!("cannot lock {}: {}", path.display(), e));
+                }
+            }
+        }
+    }
+}
+
+/// Answer the commands on a connection, a line each, until it closes
+fn answer<F: Fn(RemoteRequest)>(stream: UnixStream, send: &F) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(REPLY_TIMEOUT))?;
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let reply = match serde_json::from_str(&line) {
+            Ok(command) => {
+                let (reply, replied) = bounded(1);
+                let settled = Arc::new(AtomicBool::new(false));
+                send(RemoteRequest {
+                    command,
+                    reply,
+                    settled: Arc::clone(&settled),
+                });
+                match replied.recv_timeout(REPLY_TIMEOUT) {
+                    Ok(reply) => reply,
+                    // Answered just as the wait ran out: the answer stands
+                    Err(_) if settled.swap(true, Ordering::AcqRel) => replied
+                        .recv()
+                        .unwrap_or_else(|_| Reply::error("the viewer didn't answer")),
+                    Err(_) => Reply::error("the viewer didn't answer"),
+                }
+            }
+            Err(err) => Reply::error(format!("bad command: {}", err)),
+        };
+        writeln!(writer, "{}", to_json(&reply))?;
+    }
+    Ok(())
+}
+
+/// Send `command` to the viewer started last and wait for its reply
+pub fn send(command: &RemoteCommand) -> Result<Reply, String> {
+    let dir = socket_dir().ok_or("XDG_RUNTIME_DIR is not set, so no viewer can be found")?;
+    // Sockets of viewers that didn't exit cleanly refuse connections
+    sockets(&dir)
+        .iter()
+        .find_map(|path| UnixStream::connect(path).ok())
+        .ok_or_else(|| "no running viewer found".to_string())
+        .and_then(|stream| exchange(stream, command))
+}
+
+/// Send one command over a connection and read the reply
+fn exchange(stream: UnixStream, command: &RemoteCommand) -> Result<Reply, String> {
+    let failed = |e: std::io::Error| format!("lost the viewer: {}", e);
+    // A little longer than the viewer waits on its event loop
+    stream
+        .set_read_timeout(Some(REPLY_TIMEOUT + Duration::from_secs(1)))
+        .map_err(failed)?;
+    writeln!(&stream, "{}", to_json(command)).map_err(failed)?;
+    let mut line = String::new();
+    BufReader::new(&stream)
+        .read_line(&mut line)
+        .map_err(failed)?;
+    serde_json::from_str(&line).map_err(|e| format!("unexpected reply from the viewer: {}", e))
+}
+
+/// One line of JSON
+fn to_json<T: Serialize>(value: &T) -> String {
+    // Plain enums and structs of strings and numbers always serialize
+    serde_json::to_string(value).unwrap_or_default()
+}
+
+/// Viewer sockets in `dir`, newest first
+fn sockets(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut found: Vec<(SystemTime, PathBuf)> = entries
+        .filter_map(|e| e.ok())
+        .filter(|e| is_socket_name(&e.file_name()))
+        .filter_map(|e| Some((e.metadata().ok()?.modified().ok()?, e.path())))
+        .collect();
+    found.sort_by_key(|(modified, _)| std::cmp::Reverse(*modified));
+    found.into_iter().map(|(_, path)| path).collect()
+}
+
+/// Whether a file name is a viewer socket's, `fiv-<pid>.sock`
+fn is_socket_name(name: &OsStr) -> bool {
+    name.to_str()
+        .and_then(|name| name.strip_prefix("fiv-"))
+        .and_then(|name| name.strip_suffix(".sock"))
+        .is_some_and(|pid| !pid.is_empty() && pid.bytes().all(|b| b.is_ascii_digit()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(text: &str) -> Vec<String> {
+        text.split_whitespace().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        let parse = |text: &str| RemoteCommand::parse(&words(text));
+
+        assert_eq!(parse("next"), Ok(RemoteCommand::Next { count: 1 }));
+        assert_eq!(parse("prev 5"), Ok(RemoteCommand::Prev { count: 5 }));
+        assert_eq!(parse("goto 42"), Ok(RemoteCommand::Goto { index: 42 }));
+        assert_eq!(parse("query-status"), Ok(RemoteCommand::QueryStatus));
+        assert_eq!(
+            parse("open a.jpg"),
+            Ok(RemoteCommand::Open {
+                paths: vec![PathBuf::from("a.jpg")]
+            })
+        );
+        assert!(parse("open").is_err());
+        assert!(parse("goto").is_err());
+        assert!(parse("next 0").is_err());
+        assert!(parse("next -1").is_err());
+        assert!(parse("quit now").is_err());
+        assert!(parse("dance").is_err());
+        assert!(parse("").is_err());
+    }
+
+    #[test]
+    fn test_json() {
+        let command: RemoteCommand = serde_json::from_str(r#"{"command":"next"}"#).unwrap();
+        assert_eq!(command, RemoteCommand::Next { count: 1 });
+        assert_eq!(
+            to_json(&RemoteCommand::Goto { index: 3 }),
+            r#"{"command":"goto","index":3}"#
+        );
+        assert_eq!(to_json(&Reply::ok()), r#"{"ok":true}"#);
+    }
+
+    #[test]
+    fn test_socket_names() {
+        assert!(is_socket_name(OsStr::new("fiv-1234.sock")));
+        assert!(!is_socket_name(OsStr::new("fiv-.sock")));
+        assert!(!is_socket_name(OsStr::new("fiv-12a.sock")));
+        assert!(!is_socket_name(OsStr::new("other-1234.sock")));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let path = std::env::temp_dir().join(format!("fiv-test-{}.sock", std::process::id()));
+        let server = bind(path.clone())
+            .unwrap()
+            .serve(|request: RemoteRequest| {
+                let reply = match request.command {
+                    RemoteCommand::QueryStatus => Reply {
+                        index: Some(7),
+                        total: Some(9),
+                        ..Reply::ok()
+                    },
+                    _ => Reply::error("not now"),
+                };
+                assert!(request.reply(reply));
+            })
+            .unwrap();
+
+        let status = exchange(
+            UnixStream::connect(&path).unwrap(),
+            &RemoteCommand::QueryStatus,
+        )
+        .unwrap();
+        assert_eq!((status.index, status.total), (Some(7), Some(9)));
+        let refused = exchange(UnixStream::connect(&path).unwrap(), &RemoteCommand::Quit);
+        assert_eq!(refused.unwrap().error.as_deref(), Some("not now"));
+
+        // The socket goes with the server
+        drop(server);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_late_reply() {
+        let (reply, replied) = bounded(1);
+        let settled = Arc::new(AtomicBool::new(false));
+        let request = |settled: &Arc<AtomicBool>| RemoteRequest {
+            command: RemoteCommand::Quit,
+            reply: reply.clone(),
+            settled: Arc::clone(settled),
+        };
+        assert!(request(&settled).reply(Reply::ok()));
+        assert_eq!(replied.try_recv(), Ok(Reply::ok()));
+
+        // Once the connection has given up, the viewer hears the client
+        // wasn't told
+        let settled = Arc::new(AtomicBool::new(true));
+        assert!(!request(&settled).reply(Reply::ok()));
+        assert!(replied.try_recv().is_err());
+    }
+
+    /// Serve `claimed` as a viewer that opens anything but "missing"
+    fn viewer(claimed: Claimed) -> RemoteServer {
+        claimed
+            .serve(|request: RemoteRequest| {
+                let reply = match &request.command {
+                    RemoteCommand::Open { paths } if paths[0].ends_with("missing") => {
+                        Reply::error("no images found")
+                    }
+                    _ => Reply::ok(),
+                };
+                assert!(request.reply(reply));
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn test_single_instance() {
+        let dir = std::env::temp_dir().join(format!("fiv_instance_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let paths = [PathBuf::from("/photos")];
+
+        let Launch::First(claimed) = forward_or_claim_in(&dir, &paths) else {
+            panic!("the first launch should become the viewer");
+        };
+        // Launches while the first starts up wait for it
+        let server = viewer(claimed);
+        assert!(matches!(
+            forward_or_claim_in(&dir, &paths),
+            Launch::Forwarded
+        ));
+        assert!(matches!(
+            forward_or_claim_in(&dir, &[PathBuf::from("/missing")]),
+            Launch::Alone(_)
+        ));
+        // Each launch let go of the lock
+        drop(InstanceLock::acquire(&dir.join(INSTANCE_LOCK)).unwrap());
+
+        // Once it's gone the next launch takes its place
+        drop(server);
+        assert!(matches!(
+            forward_or_claim_in(&dir, &paths),
+            Launch::First(_)
+        ));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_held_requests() {
+        let dir = std::env::temp_dir().join(format!("fiv_held_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let Launch::First(claimed) = forward_or_claim_in(&dir, &[]) else {
+            panic!("the first launch should become the viewer");
+        };
+        let (_server, held) = claimed.serve_held().unwrap();
+
+        // Handed-over paths are accepted while the viewer is still starting
+        let open = |path: &str| {
+            matches!(
+                forward_or_claim_in(&dir, &[PathBuf::from(path)]),
+                Launch::Forwarded
+            )
+        };
+        assert!(open("/a"));
+        assert!(open("/b"));
+        assert_eq!(held.take_open(), Some(vec![PathBuf::from("/b")]));
+        assert_eq!(held.take_open(), None);
+        assert!(open("/c"));
+
+        // Held commands are passed on in order once released
+        let (sent, received) = crossbeam_channel::unbounded();
+        let status = std::thread::spawn({
+            let path = dir.join(INSTANCE_SOCKET);
+            move || {
+                exchange(
+                    UnixStream::connect(path).unwrap(),
+                    &RemoteCommand::QueryStatus,
+                )
+            }
+        });
+        // Wait for the query to be held too
+        while !matches!(&*held.relay.lock().unwrap(), Relay::Holding(requests) if requests.len() == 2)
+        {
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        held.release(move |request: RemoteRequest| {
+            let _ = sent.send(request.command.clone());
+            request.reply(Reply::ok());
+        });
+        assert_eq!(status.join().unwrap(), Ok(Reply::ok()));
+        assert_eq!(
+            received.try_iter().collect::<Vec<_>>(),
+            [
+                RemoteCommand::Open {
+                    paths: vec![PathBuf::from("/c")]
+                },
+                RemoteCommand::QueryStatus
+            ]
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_racing_launches() {
+        let dir = std::env::temp_dir().join(format!("fiv_race_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let launch = {
+            let dir = dir.clone();
+            move || match forward_or_claim_in(&dir, &[PathBuf::from("/photos")]) {
+                Launch::First(claimed) => Some(viewer(claimed)),
+                Launch::Forwarded => None,
+                Launch::Alone(reason) => panic!("launch opened a window: {}", reason),
+            }
+        };
+
+        for _ in 0..20 {
+            let racing: Vec<_> = (0..2).map(|_| std::thread::spawn(launch.clone())).collect();
+            let servers: Vec<RemoteServer> = racing
+                .into_iter()
+                .filter_map(|thread| thread.join().unwrap())
+                .collect();
+            // Exactly one became the viewer, and the other handed over
+            assert_eq!(servers.len(), 1);
+            drop(servers);
+            assert!(!dir.join(INSTANCE_SOCKET).exists());
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_stale_instance() {
+        let dir = std::env::temp_dir().join(format!("fiv_stale_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        // A viewer that died without cleaning up leaves a socket that
+        // refuses connections
+        drop(UnixListener::bind(dir.join(INSTANCE_SOCKET)).unwrap());
+        let Launch::First(claimed) = forward_or_claim_in(&dir, &[]) else {
+            panic!("a stale socket should be replaced");
+        };
+        let server = viewer(claimed);
+
+        // Whoever replaced a socket keeps it when the old owner lets go
+        let old = RemoteServer {
+            path: dir.join(INSTANCE_SOCKET),
+            inode: None,
+        };
+        drop(old);
+        assert!(dir.join(INSTANCE_SOCKET).exists());
+
+        drop(server);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -4,14 +4,20 @@
 //! No side effects, no locks, no mutations to shared state.
 //! This is the "view" in model-view separation.
 
-use crate::config::QualityTier;
+use crate::config::{QualityTier, ScalingFilter};
 use crate::slot::ImageData;
+use crate::state::{ChannelMode, Orientation, ViewTransform};
+use rayon::prelude::*;
 use std::sync::Arc;
 
 /// Result of a render operation
 pub struct RenderResult {
     /// Quality tier of rendered image (None if no image available)
     pub quality: Option<QualityTier>,
+    /// Pan actually applied after clamping to the image bounds
+    pub pan: (f64, f64),
+    /// Displayed size relative to the original image (1.0 = actual pixels)
+    pub scale: Option<f64>,
 }
 
 /// Render an image to a pixel buffer.
@@ -25,26 +31,47 @@ pub struct RenderResult {
 /// * `window_width` - Window width in pixels
 /// * `window_height` - Window height in pixels
 /// * `background` - Background color (RGBA)
+/// * `backdrop` - Full-frame pixels to show around the image instead of the
+///   background color, such as `blurred_backdrop`
+/// * `transform` - Zoom and pan relative to fit-to-window
+/// * `filter` - Resampling used to scale the image
+/// * `frame_index` - Animation frame to show (ignored for still images)
+/// * `look` - Channel view and tone adjustment of the image pixels
 ///
 /// # Returns
 /// RenderResult indicating success and quality
+#[allow(clippy::too_many_arguments)]
 pub fn render_image(
     image_data: Option<&Arc<ImageData>>,
     frame: &mut [u8],
     window_width: u32,
     window_height: u32,
     background: [u8; 4],
+    backdrop: Option<&[u8]>,
+    transform: &ViewTransform,
+    filter: ScalingFilter,
+    frame_index: usize,
+    look: &Look,
 ) -> RenderResult {
-    // Clear to background
-    clear_frame(frame, background);
-
+    let pan = (transform.pan_x, transform.pan_y);
     let img = match image_data {
         Some(data) => data,
         None => {
-            return RenderResult { quality: None };
+            render_loading(frame, window_width as usize, background);
+            return RenderResult {
+                quality: None,
+                pan,
+                scale: None,
+            };
         }
     };
 
+    // Clear to background
+    match backdrop {
+        Some(backdrop) if backdrop.len() == frame.len() => frame.copy_from_slice(backdrop),
+        _ => clear_frame(frame, background),
+    }
+
     let win_w = window_width as usize;
     let win_h = window_height as usize;
     let img_w = img.width as usize;
@@ -52,10 +79,25 @@ pub fn render_image(
 
     if win_w == 0 || win_h == 0 || img_w == 0 || img_h == 0 {
         return RenderResult {
-            quality: Some(img.quality),
+            quality: Some(img.effective_quality()),
+            pan,
+            scale: None,
         };
     }
 
+    if !transform.is_fit() || !transform.orientation.is_identity() {
+        return render_transformed(
+            img,
+            frame_index,
+            frame,
+            win_w,
+            win_h,
+            transform,
+            filter,
+            look,
+        );
+    }
+
     // Calculate scaling to fit window while maintaining aspect ratio (letterbox)
     let scale_x = win_w as f64 / img_w as f64;
     let scale_y = win_h as f64 / img_h as f64;
@@ -68,21 +110,715 @@ pub fn render_image(
     let offset_x = (win_w - display_w) / 2;
     let offset_y = (win_h - display_h) / 2;
 
-    // Blit with nearest-neighbor scaling
-    blit_scaled(
-        &img.pixels,
-        img_w,
-        img_h,
+    let opaque = look.channel != ChannelMode::Alpha;
+    if filter == ScalingFilter::Nearest {
+        blit_scaled(
+            img.frame(frame_index),
+            img_w,
+            img_h,
+            frame,
+            win_w,
+            offset_x,
+            offset_y,
+            display_w,
+            display_h,
+            opaque,
+        );
+    } else {
+        blit_filtered(
+            img.frame(frame_index),
+            img_w,
+            img_h,
+            frame,
+            win_w,
+            win_h,
+            (offset_x as f64, offset_y as f64),
+            scale,
+            Orientation::IDENTITY,
+            filter,
+            opaque,
+        );
+    }
+    finish_rect(
+        frame,
+        win_w,
+        offset_x..offset_x + display_w,
+        offset_y..offset_y + display_h,
+        look,
+    );
+
+    RenderResult {
+        quality: Some(img.effective_quality()),
+        pan: (0.0, 0.0),
+        scale: Some(scale * img_w as f64 / img.full_width.max(1) as f64),
+    }
+}
+
+/// Render two images side by side for comparing: `left` fit into the left
+/// half of the frame and `right` into the right half, with `transform`'s
+/// orientation. Zoom and pan don't apply. Returns the results for the left
+/// and right image.
+#[allow(clippy::too_many_arguments)]
+pub fn render_pair(
+    left: Option<&Arc<ImageData>>,
+    right: Option<&Arc<ImageData>>,
+    frame: &mut [u8],
+    window_width: u32,
+    window_height: u32,
+    background: [u8; 4],
+    transform: &ViewTransform,
+    filter: ScalingFilter,
+    frame_index: usize,
+    look: &Look,
+) -> (RenderResult, RenderResult) {
+    let (win_w, win_h) = (window_width as usize, window_height as usize);
+    let left_w = win_w / 2;
+    let halves = [
+        (left, 0, left_w, ViewTransform::FIT),
+        (right, left_w, win_w - left_w, transform.fit()),
+    ];
+    // Each half is drawn on its own, then copied into place row by row
+    let [left, right] = halves.map(|(data, x, width, transform)| {
+        let mut half = vec![0; width * win_h * 4];
+        let result = render_image(
+            data,
+            &mut half,
+            width as u32,
+            window_height,
+            background,
+            None,
+            &transform,
+            filter,
+            frame_index,
+            look,
+        );
+        if width > 0 {
+            for (y, row) in half.chunks_exact(width * 4).enumerate() {
+                let start = (y * win_w + x) * 4;
+                frame[start..start + row.len()].copy_from_slice(row);
+            }
+        }
+        result
+    });
+    (left, right)
+}
+
+/// Cells of the gallery grid in the window: whole cells only, the grid
+/// centered
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GridLayout {
+    /// Side of a cell in pixels
+    pub cell: usize,
+    pub columns: usize,
+    pub rows: usize,
+    /// Top-left corner of the first cell
+    pub origin: (usize, usize),
+}
+
+impl GridLayout {
+    /// Number of cells in the window
+    pub fn cells(&self) -> usize {
+        self.columns * self.rows
+    }
+
+    /// Number of the cell at window position `at`, if it's on one
+    pub fn cell_at(&self, at: (f64, f64)) -> Option<usize> {
+        let x = at.0 - self.origin.0 as f64;
+        let y = at.1 - self.origin.1 as f64;
+        if x < 0.0 || y < 0.0 {
+            return None;
+        }
+        let (column, row) = (x as usize / self.cell, y as usize / self.cell);
+        (column < self.columns && row < self.rows).then_some(row * self.columns + column)
+    }
+
+    /// Top-left corner of the `n`th cell, counting left to right and then
+    /// top down
+    pub fn cell_origin(&self, n: usize) -> (usize, usize) {
+        (
+            self.origin.0 + n % self.columns * self.cell,
+            self.origin.1 + n / self.columns * self.cell,
+        )
+    }
+}
+
+/// Lay out gallery cells of side `cell` in the window. There's always at
+/// least one cell, however small the window.
+pub fn grid_layout(win_w: usize, win_h: usize, cell: usize) -> GridLayout {
+    let cell = cell.max(1);
+    let columns = (win_w / cell).max(1);
+    let rows = (win_h / cell).max(1);
+    GridLayout {
+        cell,
+        columns,
+        rows,
+        origin: (
+            win_w.saturating_sub(columns * cell) / 2,
+            win_h.saturating_sub(rows * cell) / 2,
+        ),
+    }
+}
+
+/// Gap between a gallery thumbnail and the edge of its cell
+const GRID_GAP: usize = 6;
+
+/// Width of the outline around the gallery cursor
+const GRID_CURSOR_WIDTH: usize = 3;
+
+/// Thumbnails in the filmstrip, the current image in the middle
+pub const FILMSTRIP_CELLS: usize = 9;
+
+/// Largest side of a filmstrip cell; narrow windows get smaller ones
+const FILMSTRIP_CELL: usize = 96;
+
+/// Lay out the filmstrip along the bottom of the window, `bottom` pixels
+/// above its edge
+pub fn filmstrip_layout(win_w: usize, win_h: usize, bottom: usize) -> GridLayout {
+    let cell = FILMSTRIP_CELL.min(win_w / FILMSTRIP_CELLS).max(1);
+    GridLayout {
+        cell,
+        columns: FILMSTRIP_CELLS,
+        rows: 1,
+        origin: (
+            win_w.saturating_sub(FILMSTRIP_CELLS * cell) / 2,
+            win_h.saturating_sub(bottom + cell),
+        ),
+    }
+}
+
+/// Draw the filmstrip over the frame: `cells` holds the images from cell
+/// `first` on, or None for one still loading, and the middle cell is
+/// outlined
+pub fn draw_filmstrip(
+    frame: &mut [u8],
+    frame_w: usize,
+    frame_h: usize,
+    layout: &GridLayout,
+    first: usize,
+    cells: &[Option<Arc<ImageData>>],
+    filter: ScalingFilter,
+) {
+    // A dim band across the window behind the thumbnails
+    fill_rect_blend(
+        frame,
+        frame_w,
+        frame_h,
+        0,
+        layout.origin.1,
+        frame_w,
+        layout.cell,
+        [0, 0, 0, 160],
+    );
+    draw_cells(
+        frame,
+        frame_w,
+        frame_h,
+        layout,
+        first,
+        cells,
+        [0, 0, 0, 255],
+        filter,
+    );
+    outline_cell(frame, frame_w, frame_h, layout, FILMSTRIP_CELLS / 2);
+}
+
+/// Render the gallery: `cells` holds the images from the top-left cell on,
+/// each fit and centered in its cell, or None for one still loading. The
+/// cell `cursor` is outlined.
+#[allow(clippy::too_many_arguments)]
+pub fn render_grid(
+    cells: &[Option<Arc<ImageData>>],
+    frame: &mut [u8],
+    window_width: u32,
+    window_height: u32,
+    background: [u8; 4],
+    layout: &GridLayout,
+    cursor: usize,
+    filter: ScalingFilter,
+) {
+    let (win_w, win_h) = (window_width as usize, window_height as usize);
+    clear_frame(frame, background);
+    let light = background.map(|c| c.saturating_add(16));
+    draw_cells(frame, win_w, win_h, layout, 0, cells, light, filter);
+    outline_cell(frame, win_w, win_h, layout, cursor);
+}
+
+/// Draw `cells` into the cells of `layout` from cell `first` on, each fit
+/// and centered, with a `placeholder` colored square for those loading
+#[allow(clippy::too_many_arguments)]
+fn draw_cells(
+    frame: &mut [u8],
+    win_w: usize,
+    win_h: usize,
+    layout: &GridLayout,
+    first: usize,
+    cells: &[Option<Arc<ImageData>>],
+    placeholder: [u8; 4],
+    filter: ScalingFilter,
+) {
+    let inner = layout.cell.saturating_sub(2 * GRID_GAP).max(1);
+    let cells = cells.iter().take(layout.cells().saturating_sub(first));
+    for (n, data) in (first..).zip(cells) {
+        let (x, y) = layout.cell_origin(n);
+        let (x, y) = (x + GRID_GAP, y + GRID_GAP);
+        match data.as_ref().filter(|d| d.width > 0 && d.height > 0) {
+            Some(img) => {
+                let (img_w, img_h) = (img.width as usize, img.height as usize);
+                let scale = (inner as f64 / img_w as f64).min(inner as f64 / img_h as f64);
+                let origin = (
+                    x as f64 + (inner as f64 - img_w as f64 * scale) / 2.0,
+                    y as f64 + (inner as f64 - img_h as f64 * scale) / 2.0,
+                );
+                blit_filtered(
+                    img.frame(0),
+                    img_w,
+                    img_h,
+                    frame,
+                    win_w,
+                    win_h,
+                    origin,
+                    scale,
+                    Orientation::IDENTITY,
+                    filter,
+                    true,
+                );
+            }
+            None => fill_rect_blend(frame, win_w, win_h, x, y, inner, inner, placeholder),
+        }
+    }
+}
+
+/// Outline cell `n` of `layout`, if it's in the window
+fn outline_cell(frame: &mut [u8], win_w: usize, win_h: usize, layout: &GridLayout, n: usize) {
+    const LINE: [u8; 4] = [255, 196, 0, 255];
+    const T: usize = GRID_CURSOR_WIDTH;
+    if n >= layout.cells() {
+        return;
+    }
+    let (x, y) = layout.cell_origin(n);
+    let side = layout.cell;
+    fill_rect_blend(frame, win_w, win_h, x, y, side, T, LINE);
+    fill_rect_blend(
+        frame,
+        win_w,
+        win_h,
+        x,
+        (y + side).saturating_sub(T),
+        side,
+        T,
+        LINE,
+    );
+    fill_rect_blend(frame, win_w, win_h, x, y, T, side, LINE);
+    fill_rect_blend(
+        frame,
+        win_w,
+        win_h,
+        (x + side).saturating_sub(T),
+        y,
+        T,
+        side,
+        LINE,
+    );
+}
+
+/// Where an image lands in the window
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Placement {
+    /// Top-left corner of the displayed image in window pixels (may be
+    /// negative)
+    pub origin: (f64, f64),
+    /// Window pixels per data pixel
+    pub scale: f64,
+    /// Pan after clamping to the image bounds
+    pub pan: (f64, f64),
+}
+
+/// Place an image with zoom, pan and orientation: scale relative to fit
+/// (computed on the rotated dimensions), offset from center
+pub fn place(img: &ImageData, win_w: usize, win_h: usize, transform: &ViewTransform) -> Placement {
+    let img_w = img.width as usize;
+    let img_h = img.height as usize;
+    let (rot_w, rot_h) = transform.orientation.display_dims(img_w, img_h);
+
+    // Scale in window pixels per data pixel. Actual size maps the original
+    // dimensions 1:1, upscaling a lower tier until the full decode arrives.
+    let scale = if transform.actual_size {
+        img.full_width.max(1) as f64 / img_w.max(1) as f64
+    } else {
+        let fit = (win_w as f64 / rot_w.max(1) as f64).min(win_h as f64 / rot_h.max(1) as f64);
+        fit * transform.zoom
+    };
+    let display_w = rot_w as f64 * scale;
+    let display_h = rot_h as f64 * scale;
+
+    let pan_x = clamp_pan(transform.pan_x, display_w, win_w as f64);
+    let pan_y = clamp_pan(transform.pan_y, display_h, win_h as f64);
+    Placement {
+        origin: (
+            (win_w as f64 - display_w) / 2.0 + pan_x,
+            (win_h as f64 - display_h) / 2.0 + pan_y,
+        ),
+        scale,
+        pan: (pan_x, pan_y),
+    }
+}
+
+/// The data pixel of `img` under window position `pos`, inverting `place`
+/// (zoom, pan and orientation included), or None off the image
+pub fn window_to_image(
+    img: &ImageData,
+    win_w: usize,
+    win_h: usize,
+    transform: &ViewTransform,
+    pos: (f64, f64),
+) -> Option<(usize, usize)> {
+    let (img_w, img_h) = (img.width as usize, img.height as usize);
+    let (rot_w, rot_h) = transform.orientation.display_dims(img_w, img_h);
+    let Placement { origin, scale, .. } = place(img, win_w, win_h, transform);
+    let u = (pos.0 - origin.0) / scale;
+    let v = (pos.1 - origin.1) / scale;
+    if !(0.0..rot_w as f64).contains(&u) || !(0.0..rot_h as f64).contains(&v) {
+        return None;
+    }
+    Some(
+        transform
+            .orientation
+            .source_coords(u as usize, v as usize, img_w, img_h),
+    )
+}
+
+/// A rectangle of image pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PixelRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl PixelRect {
+    /// The same region in an image of `to` pixels, given it is
+    /// expressed in one of `from` pixels
+    pub fn rescale(&self, from: (u32, u32), to: (u32, u32)) -> PixelRect {
+        let along = |v: u32, from: u32, to: u32| (v as u64 * to as u64 / from.max(1) as u64) as u32;
+        let (x, y) = (along(self.x, from.0, to.0), along(self.y, from.1, to.1));
+        let right = along(self.x + self.width, from.0, to.0)
+            .max(x + 1)
+            .min(to.0);
+        let bottom = along(self.y + self.height, from.1, to.1)
+            .max(y + 1)
+            .min(to.1);
+        PixelRect {
+            x,
+            y,
+            width: right.saturating_sub(x),
+            height: bottom.saturating_sub(y),
+        }
+    }
+}
+
+/// The part of `img` between two window positions, inverting `place` and
+/// clamped to the image, in pixels of the original image. None if the
+/// rectangle misses the image.
+pub fn window_rect_to_image(
+    img: &ImageData,
+    win_w: usize,
+    win_h: usize,
+    transform: &ViewTransform,
+    a: (f64, f64),
+    b: (f64, f64),
+) -> Option<PixelRect> {
+    let (img_w, img_h) = (img.width as usize, img.height as usize);
+    let (rot_w, rot_h) = transform.orientation.display_dims(img_w, img_h);
+    let Placement { origin, scale, .. } = place(img, win_w, win_h, transform);
+
+    // Displayed pixel edges, clamped to the image
+    let edge = |pos: f64, origin: f64, limit: usize| {
+        ((pos - origin) / scale).round().clamp(0.0, limit as f64) as usize
+    };
+    let (u0, u1) = (
+        edge(a.0.min(b.0), origin.0, rot_w),
+        edge(a.0.max(b.0), origin.0, rot_w),
+    );
+    let (v0, v1) = (
+        edge(a.1.min(b.1), origin.1, rot_h),
+        edge(a.1.max(b.1), origin.1, rot_h),
+    );
+    if u0 >= u1 || v0 >= v1 {
+        return None;
+    }
+
+    // Opposite corner pixels span the same rectangle in the source
+    let orientation = transform.orientation;
+    let first = orientation.source_coords(u0, v0, img_w, img_h);
+    let last = orientation.source_coords(u1 - 1, v1 - 1, img_w, img_h);
+    let (x0, x1) = (first.0.min(last.0), first.0.max(last.0) + 1);
+    let (y0, y1) = (first.1.min(last.1), first.1.max(last.1) + 1);
+    let rect = PixelRect {
+        x: x0 as u32,
+        y: y0 as u32,
+        width: (x1 - x0) as u32,
+        height: (y1 - y0) as u32,
+    };
+    Some(rect.rescale((img.width, img.height), (img.full_width, img.full_height)))
+}
+
+/// A pixel read from the image under the cursor
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PixelSample {
+    /// Position in the original image
+    pub x: u32,
+    pub y: u32,
+    pub rgba: [u8; 4],
+    /// Whether it was read from a downscaled copy, so the original pixel
+    /// may differ
+    pub approximate: bool,
+}
+
+impl PixelSample {
+    /// "#RRGGBB"
+    pub fn hex(&self) -> String {
+        let [r, g, b, _] = self.rgba;
+        format!("#{:02X}{:02X}{:02X}", r, g, b)
+    }
+
+    /// Inspector text: position, then the value
+    pub fn lines(&self) -> Vec<String> {
+        let [r, g, b, a] = self.rgba;
+        let mut position = format!("{}, {}", self.x, self.y);
+        if self.approximate {
+            position.push_str(" (preview)");
+        }
+        vec![position, format!("{} {} {} {} {}", self.hex(), r, g, b, a)]
+    }
+}
+
+/// Read the pixel of animation frame `frame_index` under window position
+/// `pos`, with its coordinates scaled up to the original image
+pub fn sample_pixel(
+    img: &ImageData,
+    frame_index: usize,
+    win_w: usize,
+    win_h: usize,
+    transform: &ViewTransform,
+    pos: (f64, f64),
+) -> Option<PixelSample> {
+    let (x, y) = window_to_image(img, win_w, win_h, transform, pos)?;
+    let idx = (y * img.width as usize + x) * 4;
+    let rgba = img.frame(frame_index).get(idx..idx + 4)?.try_into().ok()?;
+    let to_full =
+        |v: usize, data: u32, full: u32| (v as u64 * full as u64 / data.max(1) as u64) as u32;
+    Some(PixelSample {
+        x: to_full(x, img.width, img.full_width),
+        y: to_full(y, img.height, img.full_height),
+        rgba,
+        approximate: (img.width, img.height) != (img.full_width, img.full_height),
+    })
+}
+
+/// Top-left corner for a box of `size` beside the cursor, flipped to the
+/// other side where it would leave the window
+pub fn tooltip_origin(
+    cursor: (f64, f64),
+    (w, h): (usize, usize),
+    (win_w, win_h): (usize, usize),
+) -> (usize, usize) {
+    const GAP: f64 = 16.0;
+    let place = |at: f64, size: usize, limit: usize| {
+        let after = at + GAP;
+        if after + size as f64 <= limit as f64 {
+            after as usize
+        } else {
+            (at - GAP - size as f64).max(0.0) as usize
+        }
+    };
+    (place(cursor.0, w, win_w), place(cursor.1, h, win_h))
+}
+
+/// Render with zoom, pan and orientation
+#[allow(clippy::too_many_arguments)]
+fn render_transformed(
+    img: &ImageData,
+    frame_index: usize,
+    frame: &mut [u8],
+    win_w: usize,
+    win_h: usize,
+    transform: &ViewTransform,
+    filter: ScalingFilter,
+    look: &Look,
+) -> RenderResult {
+    let img_w = img.width as usize;
+    let img_h = img.height as usize;
+    let Placement { origin, scale, pan } = place(img, win_w, win_h, transform);
+
+    let src = img.frame(frame_index);
+    let orientation = transform.orientation;
+    let opaque = look.channel != ChannelMode::Alpha;
+    if filter == ScalingFilter::Nearest {
+        blit_transformed(
+            src,
+            img_w,
+            img_h,
+            frame,
+            win_w,
+            win_h,
+            origin,
+            scale,
+            orientation,
+            opaque,
+        );
+    } else {
+        blit_filtered(
+            src,
+            img_w,
+            img_h,
+            frame,
+            win_w,
+            win_h,
+            origin,
+            scale,
+            orientation,
+            filter,
+            opaque,
+        );
+    }
+
+    // The same pixels the blit covered
+    let (rot_w, rot_h) = orientation.display_dims(img_w, img_h);
+    let covered = |start: f64, len: usize, limit: usize| {
+        let end = ((start + len as f64 * scale).ceil().max(0.0) as usize).min(limit);
+        start.max(0.0).floor() as usize..end
+    };
+    finish_rect(
         frame,
         win_w,
-        offset_x,
-        offset_y,
-        display_w,
-        display_h,
+        covered(origin.0, rot_w, win_w),
+        covered(origin.1, rot_h, win_h),
+        look,
     );
 
     RenderResult {
-        quality: Some(img.quality),
+        quality: Some(img.effective_quality()),
+        pan,
+        scale: Some(scale * img_w as f64 / img.full_width.max(1) as f64),
+    }
+}
+
+/// Lookup tables mapping each 8-bit value to its adjusted value, one per
+/// color channel
+pub type Lut = [[u8; 256]; 3];
+
+/// Tables for an exposure change in stops, a gamma (1.0 = unchanged) and a
+/// contrast change (0.0 = unchanged, 1.0 = twice the slope about mid-gray),
+/// applied in that order to the display values
+pub fn adjustment_lut(exposure: f32, gamma: f32, contrast: f32) -> Lut {
+    let gain = exposure.exp2();
+    let mut table = [0u8; 256];
+    for (value, out) in table.iter_mut().enumerate() {
+        let x = (value as f32 / 255.0 * gain).min(1.0);
+        let x = x.powf(1.0 / gamma);
+        let x = (x - 0.5) * (1.0 + contrast) + 0.5;
+        *out = (x * 255.0).round().clamp(0.0, 255.0) as u8;
+    }
+    [table; 3]
+}
+
+/// Map the color channels of RGBA pixels through `lut`, leaving alpha
+pub fn apply_lut(pixels: &mut [u8], lut: &Lut) {
+    for pixel in pixels.chunks_exact_mut(4) {
+        pixel[0] = lut[0][pixel[0] as usize];
+        pixel[1] = lut[1][pixel[1] as usize];
+        pixel[2] = lut[2][pixel[2] as usize];
+    }
+}
+
+/// Show one channel of RGBA pixels, or their luminance, as opaque
+/// grayscale, or invert their colors
+pub fn apply_channel_mode(pixels: &mut [u8], mode: ChannelMode) {
+    for pixel in pixels.chunks_exact_mut(4) {
+        let value = match mode {
+            ChannelMode::Color => continue,
+            ChannelMode::Red => pixel[0],
+            ChannelMode::Green => pixel[1],
+            ChannelMode::Blue => pixel[2],
+            ChannelMode::Alpha => pixel[3],
+            ChannelMode::Luminance => luminance(pixel[0], pixel[1], pixel[2]),
+            ChannelMode::Invert => {
+                for channel in &mut pixel[..3] {
+                    *channel = 255 - *channel;
+                }
+                continue;
+            }
+        };
+        pixel.copy_from_slice(&[value, value, value, 255]);
+    }
+}
+
+/// Rec. 709 luminance of a color, with the weights in 8-bit fixed point
+#[inline]
+pub fn luminance(r: u8, g: u8, b: u8) -> u8 {
+    let sum = 54 * r as u32 + 183 * g as u32 + 19 * b as u32;
+    ((sum + 128) >> 8) as u8
+}
+
+/// Channel view and tone adjustment for the image pixels
+#[derive(Debug, Clone, PartialEq)]
+pub struct Look {
+    pub channel: ChannelMode,
+    pub lut: Option<Lut>,
+}
+
+impl Look {
+    /// The pixels as they are
+    pub const NONE: Self = Self {
+        channel: ChannelMode::Color,
+        lut: None,
+    };
+
+    pub fn is_identity(&self) -> bool {
+        self.channel == ChannelMode::Color && self.lut.is_none()
+    }
+}
+
+/// Apply `look` to a rectangle of the frame the image was blitted to, a
+/// row per task
+fn finish_rect(
+    frame: &mut [u8],
+    frame_w: usize,
+    columns: std::ops::Range<usize>,
+    rows: std::ops::Range<usize>,
+    look: &Look,
+) {
+    let stride = frame_w * 4;
+    if look.is_identity()
+        || columns.is_empty()
+        || rows.is_empty()
+        || frame.len() < rows.end * stride
+    {
+        return;
+    }
+    frame[rows.start * stride..rows.end * stride]
+        .par_chunks_exact_mut(stride)
+        .for_each(|row| {
+            let pixels = &mut row[columns.start * 4..columns.end * 4];
+            apply_channel_mode(pixels, look.channel);
+            if let Some(lut) = &look.lut {
+                apply_lut(pixels, lut);
+            }
+        });
+}
+
+/// Clamp a pan offset along one axis.
+///
+/// An image smaller than the window stays centered; a larger one can move
+/// until its edge meets the window edge, so it never leaves the screen.
+#[inline]
+pub fn clamp_pan(pan: f64, display: f64, window: f64) -> f64 {
+    let slack = (display - window) / 2.0;
+    if slack <= 0.0 {
+        0.0
+    } else {
+        pan.clamp(-slack, slack)
     }
 }
 
@@ -103,6 +839,131 @@ pub fn clear_frame(frame: &mut [u8], color: [u8; 4]) {
     }
 }
 
+/// Longest side of the tiny copy the blurred backdrop is made from
+const BACKDROP_SIZE: usize = 32;
+/// Brightness of the backdrop, so the sharp image stands out against it
+const BACKDROP_DIM: f32 = 0.6;
+
+/// A heavily blurred, dimmed copy of an image covering a `win_w` x `win_h`
+/// frame, for filling the letterbox bars around it. The image is shrunk to
+/// a few dozen pixels, blurred there, and scaled back up, so this is cheap
+/// enough to rebuild on every image change.
+pub fn blurred_backdrop(
+    src: &[u8],
+    src_w: usize,
+    src_h: usize,
+    win_w: usize,
+    win_h: usize,
+) -> Vec<u8> {
+    let mut out = vec![0u8; win_w * win_h * 4];
+    if src_w == 0 || src_h == 0 || win_w == 0 || win_h == 0 {
+        return out;
+    }
+
+    let shrink = (BACKDROP_SIZE as f64 / src_w.max(src_h) as f64).min(1.0);
+    let tiny_w = ((src_w as f64 * shrink).round() as usize).clamp(1, BACKDROP_SIZE);
+    let tiny_h = ((src_h as f64 * shrink).round() as usize).clamp(1, BACKDROP_SIZE);
+    let mut tiny = shrink_image(src, src_w, src_h, tiny_w, tiny_h);
+    for _ in 0..2 {
+        box_blur(&mut tiny, tiny_w, tiny_h, 2);
+    }
+
+    // Scale up to cover the frame, keeping the aspect ratio, with bilinear
+    // sampling so the blur stays smooth
+    let cover = (win_w as f64 / tiny_w as f64).max(win_h as f64 / tiny_h as f64);
+    let sample = |pos: usize, win: usize, tiny: usize| {
+        let offset = (win as f64 - tiny as f64 * cover) / 2.0;
+        let t = ((pos as f64 + 0.5 - offset) / cover - 0.5).clamp(0.0, (tiny - 1) as f64);
+        let i = t as usize;
+        (i, (i + 1).min(tiny - 1), (t - i as f64) as f32)
+    };
+    let columns: Vec<_> = (0..win_w).map(|x| sample(x, win_w, tiny_w)).collect();
+
+    for y in 0..win_h {
+        let (y0, y1, fy) = sample(y, win_h, tiny_h);
+        for (x, &(x0, x1, fx)) in columns.iter().enumerate() {
+            let idx = (y * win_w + x) * 4;
+            for c in 0..3 {
+                let top = tiny[y0 * tiny_w + x0][c] * (1.0 - fx) + tiny[y0 * tiny_w + x1][c] * fx;
+                let bottom =
+                    tiny[y1 * tiny_w + x0][c] * (1.0 - fx) + tiny[y1 * tiny_w + x1][c] * fx;
+                out[idx + c] = ((top * (1.0 - fy) + bottom * fy) * BACKDROP_DIM) as u8;
+            }
+            out[idx + 3] = 255;
+        }
+    }
+    out
+}
+
+/// Average RGBA pixels down to `w` x `h` RGB, sampling at most 8x8
+/// pixels per block
+fn shrink_image(src: &[u8], src_w: usize, src_h: usize, w: usize, h: usize) -> Vec<[f32; 3]> {
+    let mut out = vec![[0.0; 3]; w * h];
+    for ty in 0..h {
+        let (y_start, y_end) = (
+            ty * src_h / h,
+            ((ty + 1) * src_h / h).max(ty * src_h / h + 1),
+        );
+        for tx in 0..w {
+            let (x_start, x_end) = (
+                tx * src_w / w,
+                ((tx + 1) * src_w / w).max(tx * src_w / w + 1),
+            );
+            let step_y = ((y_end - y_start) / 8).max(1);
+            let step_x = ((x_end - x_start) / 8).max(1);
+            let mut sum = [0.0f32; 3];
+            let mut count = 0.0;
+            for y in (y_start..y_end.min(src_h)).step_by(step_y) {
+                for x in (x_start..x_end.min(src_w)).step_by(step_x) {
+                    let idx = (y * src_w + x) * 4;
+                    if idx + 2 < src.len() {
+                        for c in 0..3 {
+                            sum[c] += src[idx + c] as f32;
+                        }
+                        count += 1.0;
+                    }
+                }
+            }
+            if count > 0.0 {
+                out[ty * w + tx] = sum.map(|v| v / count);
+            }
+        }
+    }
+    out
+}
+
+/// Blur in place with a box of `radius` pixels, rows then columns, edges
+/// clamped
+fn box_blur(pixels: &mut [[f32; 3]], w: usize, h: usize, radius: usize) {
+    let blur_line = |get: &dyn Fn(usize) -> [f32; 3], len: usize| -> Vec<[f32; 3]> {
+        (0..len)
+            .map(|i| {
+                let lo = i.saturating_sub(radius);
+                let hi = (i + radius).min(len - 1);
+                let mut sum = [0.0f32; 3];
+                for j in lo..=hi {
+                    let p = get(j);
+                    for c in 0..3 {
+                        sum[c] += p[c];
+                    }
+                }
+                sum.map(|v| v / (hi - lo + 1) as f32)
+            })
+            .collect()
+    };
+
+    for y in 0..h {
+        let row = blur_line(&|x| pixels[y * w + x], w);
+        pixels[y * w..(y + 1) * w].copy_from_slice(&row);
+    }
+    for x in 0..w {
+        let column = blur_line(&|y| pixels[y * w + x], h);
+        for (y, p) in column.into_iter().enumerate() {
+            pixels[y * w + x] = p;
+        }
+    }
+}
+
 /// Blit source image to destination with nearest-neighbor scaling.
 #[inline]
 #[allow(clippy::too_many_arguments)]
@@ -116,6 +977,7 @@ fn blit_scaled(
     dst_y: usize,
     dst_w: usize,
     dst_h: usize,
+    opaque: bool,
 ) {
     if dst_w == 0 || dst_h == 0 {
         return;
@@ -140,102 +1002,1422 @@ fn blit_scaled(
                 dst[dst_idx] = src[src_idx];
                 dst[dst_idx + 1] = src[src_idx + 1];
                 dst[dst_idx + 2] = src[src_idx + 2];
-                dst[dst_idx + 3] = 255; // Force opaque
+                // Force opaque unless the alpha is to be shown
+                dst[dst_idx + 3] = if opaque { 255 } else { src[src_idx + 3] };
             }
         }
     }
 }
 
-/// Blit with bilinear interpolation (higher quality, slower)
-#[allow(dead_code, clippy::too_many_arguments)]
-pub fn blit_bilinear(
+/// Blit source image scaled by `scale` and reoriented, with its top-left
+/// corner at `origin` (window pixels, may be negative). Parts outside the
+/// window are cropped.
+///
+/// Every orientation maps each source axis to exactly one display axis, so
+/// the source offset splits into a per-column and a per-row term that are
+/// precomputed once, keeping the inner loop a plain copy.
+#[allow(clippy::too_many_arguments)]
+fn blit_transformed(
     src: &[u8],
     src_w: usize,
     src_h: usize,
     dst: &mut [u8],
-    dst_stride: usize,
-    dst_x: usize,
-    dst_y: usize,
     dst_w: usize,
     dst_h: usize,
+    origin: (f64, f64),
+    scale: f64,
+    orientation: Orientation,
+    opaque: bool,
 ) {
-    if dst_w == 0 || dst_h == 0 || src_w < 2 || src_h < 2 {
-        blit_scaled(
-            src, src_w, src_h, dst, dst_stride, dst_x, dst_y, dst_w, dst_h,
-        );
+    if src_w == 0 || src_h == 0 || scale <= 0.0 {
         return;
     }
 
-    let x_ratio = (src_w as f64 - 1.0) / dst_w.max(1) as f64;
-    let y_ratio = (src_h as f64 - 1.0) / dst_h.max(1) as f64;
+    let (rot_w, rot_h) = orientation.display_dims(src_w, src_h);
+    let (left, top) = origin;
+    let x_start = left.max(0.0).floor() as usize;
+    let x_end = ((left + rot_w as f64 * scale).ceil().max(0.0) as usize).min(dst_w);
+    let y_start = top.max(0.0).floor() as usize;
+    let y_end = ((top + rot_h as f64 * scale).ceil().max(0.0) as usize).min(dst_h);
+    if x_start >= x_end || y_start >= y_end {
+        return;
+    }
 
-    for dy in 0..dst_h {
-        let src_y = dy as f64 * y_ratio;
-        let y0 = src_y.floor() as usize;
-        let y1 = (y0 + 1).min(src_h - 1);
-        let y_frac = src_y - y0 as f64;
+    let src_index = |u: usize, v: usize| {
+        let (x, y) = orientation.source_coords(u, v, src_w, src_h);
+        ((y * src_w + x) * 4) as isize
+    };
+    let base = src_index(0, 0);
 
-        let dst_row_offset = ((dst_y + dy) * dst_stride + dst_x) * 4;
+    // Display coordinate sampled at each pixel center
+    let col_offsets: Vec<isize> = (x_start..x_end)
+        .map(|x| {
+            let u = (((x as f64 + 0.5 - left) / scale).max(0.0) as usize).min(rot_w - 1);
+            src_index(u, 0) - base
+        })
+        .collect();
 
-        for dx in 0..dst_w {
-            let src_x = dx as f64 * x_ratio;
-            let x0 = src_x.floor() as usize;
-            let x1 = (x0 + 1).min(src_w - 1);
-            let x_frac = src_x - x0 as f64;
+    for y in y_start..y_end {
+        let v = (((y as f64 + 0.5 - top) / scale).max(0.0) as usize).min(rot_h - 1);
+        let row_offset = src_index(0, v);
+        let dst_row_offset = (y * dst_w + x_start) * 4;
 
-            let idx00 = (y0 * src_w + x0) * 4;
-            let idx01 = (y0 * src_w + x1) * 4;
-            let idx10 = (y1 * src_w + x0) * 4;
-            let idx11 = (y1 * src_w + x1) * 4;
+        for (i, &col_offset) in col_offsets.iter().enumerate() {
+            let src_idx = (row_offset + col_offset) as usize;
+            let dst_idx = dst_row_offset + i * 4;
 
-            let dst_idx = dst_row_offset + dx * 4;
+            if dst_idx + 3 < dst.len() && src_idx + 3 < src.len() {
+                dst[dst_idx] = src[src_idx];
+                dst[dst_idx + 1] = src[src_idx + 1];
+                dst[dst_idx + 2] = src[src_idx + 2];
+                // Force opaque unless the alpha is to be shown
+                dst[dst_idx + 3] = if opaque { 255 } else { src[src_idx + 3] };
+            }
+        }
+    }
+}
 
-            if dst_idx + 3 < dst.len() {
-                for c in 0..3 {
-                    let v00 = src[idx00 + c] as f64;
-                    let v01 = src[idx01 + c] as f64;
-                    let v10 = src[idx10 + c] as f64;
-                    let v11 = src[idx11 + c] as f64;
+/// Text scale for overlays (font is 8x8, drawn at 2x)
+pub const TEXT_SCALE: usize = 2;
+/// Height of one overlay text line including spacing
+pub const LINE_HEIGHT: usize = 10 * TEXT_SCALE;
+/// Padding between overlay panel edge and text
+const PANEL_PADDING: usize = 8;
 
-                    let v0 = v00 * (1.0 - x_frac) + v01 * x_frac;
-                    let v1 = v10 * (1.0 - x_frac) + v11 * x_frac;
-                    let v = v0 * (1.0 - y_frac) + v1 * y_frac;
+/// Draw text with the built-in 8x8 bitmap font, clipped to the frame.
+/// Characters without a glyph are drawn as blanks.
+#[allow(clippy::too_many_arguments)]
+pub fn draw_text(
+    frame: &mut [u8],
+    frame_w: usize,
+    frame_h: usize,
+    x: usize,
+    y: usize,
+    text: &str,
+    color: [u8; 4],
+    scale: usize,
+) {
+    use font8x8::UnicodeFonts;
+
+    for (i, ch) in text.chars().enumerate() {
+        let glyph = match font8x8::BASIC_FONTS.get(ch) {
+            Some(glyph) => glyph,
+            None => continue,
+        };
+        let gx = x + i * 8 * scale;
 
-                    dst[dst_idx + c] = v.round() as u8;
+        for (row, bits) in glyph.iter().enumerate() {
+            for col in 0..8 {
+                // Bit 0 is the leftmost pixel
+                if bits & (1 << col) == 0 {
+                    continue;
+                }
+                for sy in 0..scale {
+                    for sx in 0..scale {
+                        let px = gx + col * scale + sx;
+                        let py = y + row * scale + sy;
+                        if px < frame_w && py < frame_h {
+                            let idx = (py * frame_w + px) * 4;
+                            frame[idx..idx + 4].copy_from_slice(&color);
+                        }
+                    }
                 }
-                dst[dst_idx + 3] = 255;
             }
         }
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Blend a translucent rectangle over the frame, clipped to its bounds
+#[allow(clippy::too_many_arguments)]
+pub fn fill_rect_blend(
+    frame: &mut [u8],
+    frame_w: usize,
+    frame_h: usize,
+    x: usize,
+    y: usize,
+    w: usize,
+    h: usize,
+    color: [u8; 4],
+) {
+    let alpha = color[3] as u32;
+    let x_end = (x + w).min(frame_w);
+    let y_end = (y + h).min(frame_h);
 
-    fn make_test_image(w: u32, h: u32) -> Arc<ImageData> {
-        let pixels = vec![128u8; (w * h * 4) as usize];
-        Arc::new(ImageData::new(pixels, w, h, QualityTier::Full))
+    for py in y.min(y_end)..y_end {
+        for px in x.min(x_end)..x_end {
+            let idx = (py * frame_w + px) * 4;
+            for c in 0..3 {
+                let dst = frame[idx + c] as u32;
+                frame[idx + c] = ((color[c] as u32 * alpha + dst * (255 - alpha)) / 255) as u8;
+            }
+            // Opaque stays opaque; a transparent overlay frame picks up the
+            // panel's own alpha
+            frame[idx + 3] = (alpha + frame[idx + 3] as u32 * (255 - alpha) / 255) as u8;
+        }
     }
+}
 
-    #[test]
-    fn test_render_empty() {
-        let mut frame = vec![0u8; 100 * 100 * 4];
-        let result = render_image(None, &mut frame, 100, 100, [0, 0, 0, 255]);
+/// Share of the monitor a window fitted to an image takes at most
+const FIT_WINDOW_SHARE: f64 = 0.9;
+/// Smallest window fitted to an image, either way
+const FIT_WINDOW_MIN: u32 = 128;
 
-        assert!(result.quality.is_none());
-    }
+/// Window size showing an image of `image` pixels 1:1, shrunk keeping its
+/// aspect to within 90% of the `monitor`, if known
+pub fn fitted_window_size(image: (usize, usize), monitor: Option<(u32, u32)>) -> (u32, u32) {
+    let (w, h) = (image.0.max(1) as f64, image.1.max(1) as f64);
+    let scale = monitor.map_or(1.0, |(mw, mh)| {
+        let (max_w, max_h) = (mw as f64 * FIT_WINDOW_SHARE, mh as f64 * FIT_WINDOW_SHARE);
+        (max_w / w).min(max_h / h).min(1.0)
+    });
+    (
+        ((w * scale).round() as u32).max(FIT_WINDOW_MIN),
+        ((h * scale).round() as u32).max(FIT_WINDOW_MIN),
+    )
+}
+
+/// Size in pixels of a text panel holding `lines`
+pub fn panel_size(lines: &[String]) -> (usize, usize) {
+    let longest = lines.iter().map(|l| l.chars().count()).max().unwrap_or(0);
+    (
+        longest * 8 * TEXT_SCALE + PANEL_PADDING * 2,
+        lines.len() * LINE_HEIGHT + PANEL_PADDING * 2,
+    )
+}
+
+/// Draw lines of text on a dark translucent panel with its top-left at (x, y)
+pub fn draw_panel(
+    frame: &mut [u8],
+    frame_w: usize,
+    frame_h: usize,
+    x: usize,
+    y: usize,
+    lines: &[String],
+) {
+    if lines.is_empty() {
+        return;
+    }
+
+    let (w, h) = panel_size(lines);
+    fill_rect_blend(frame, frame_w, frame_h, x, y, w, h, [0, 0, 0, 170]);
+
+    for (i, line) in lines.iter().enumerate() {
+        draw_text(
+            frame,
+            frame_w,
+            frame_h,
+            x + PANEL_PADDING,
+            y + PANEL_PADDING + i * LINE_HEIGHT,
+            line,
+            [255, 255, 255, 255],
+            TEXT_SCALE,
+        );
+    }
+}
+
+/// Dim the frame outside the window rectangle `(x, y, w, h)` of a crop
+/// selection, outline it, and label it with `label` (its size)
+pub fn draw_crop_overlay(
+    frame: &mut [u8],
+    frame_w: usize,
+    frame_h: usize,
+    (x, y, w, h): (usize, usize, usize, usize),
+    label: &str,
+) {
+    const DIM: [u8; 4] = [0, 0, 0, 140];
+    const LINE: [u8; 4] = [255, 255, 255, 255];
+    let right = (x + w).min(frame_w);
+    let bottom = (y + h).min(frame_h);
+    fill_rect_blend(frame, frame_w, frame_h, 0, 0, frame_w, y, DIM);
+    fill_rect_blend(frame, frame_w, frame_h, 0, bottom, frame_w, frame_h, DIM);
+    fill_rect_blend(
+        frame,
+        frame_w,
+        frame_h,
+        0,
+        y,
+        x,
+        bottom - y.min(bottom),
+        DIM,
+    );
+    fill_rect_blend(
+        frame,
+        frame_w,
+        frame_h,
+        right,
+        y,
+        frame_w,
+        bottom - y.min(bottom),
+        DIM,
+    );
+
+    if w > 0 && h > 0 {
+        fill_rect_blend(frame, frame_w, frame_h, x, y, w, 1, LINE);
+        fill_rect_blend(frame, frame_w, frame_h, x, y + h - 1, w, 1, LINE);
+        fill_rect_blend(frame, frame_w, frame_h, x, y, 1, h, LINE);
+        fill_rect_blend(frame, frame_w, frame_h, x + w - 1, y, 1, h, LINE);
+    }
+
+    // Below the selection, or inside it at the bottom if there's no room
+    let lines = [label.to_string()];
+    let (_, label_h) = panel_size(&lines);
+    let label_y = if bottom + label_h <= frame_h {
+        bottom
+    } else {
+        bottom.saturating_sub(label_h)
+    };
+    draw_panel(frame, frame_w, frame_h, x, label_y, &lines);
+}
+
+/// Side of the badge on a marked image
+pub const MARK_BADGE_SIZE: usize = LINE_HEIGHT + PANEL_PADDING;
+
+/// Draw the badge of a marked image, a star on gold, with its top-left at
+/// (x, y)
+pub fn draw_mark_badge(frame: &mut [u8], frame_w: usize, frame_h: usize, x: usize, y: usize) {
+    fill_rect_blend(
+        frame,
+        frame_w,
+        frame_h,
+        x,
+        y,
+        MARK_BADGE_SIZE,
+        MARK_BADGE_SIZE,
+        [255, 196, 0, 230],
+    );
+    let inset = (MARK_BADGE_SIZE - 8 * TEXT_SCALE) / 2;
+    draw_text(
+        frame,
+        frame_w,
+        frame_h,
+        x + inset,
+        y + inset,
+        "*",
+        [0, 0, 0, 255],
+        TEXT_SCALE,
+    );
+}
+
+/// Width of the border shown while files are dragged over the window
+const DROP_BORDER_WIDTH: usize = 6;
+
+/// Draw a border around the whole frame, showing files can be dropped
+pub fn draw_drop_border(frame: &mut [u8], frame_w: usize, frame_h: usize) {
+    const LINE: [u8; 4] = [255, 196, 0, 200];
+    const T: usize = DROP_BORDER_WIDTH;
+    let (w, h) = (frame_w, frame_h);
+    fill_rect_blend(frame, w, h, 0, 0, w, T, LINE);
+    fill_rect_blend(frame, w, h, 0, h.saturating_sub(T), w, T, LINE);
+    fill_rect_blend(frame, w, h, 0, T, T, h.saturating_sub(2 * T), LINE);
+    fill_rect_blend(
+        frame,
+        w,
+        h,
+        w.saturating_sub(T),
+        T,
+        T,
+        h.saturating_sub(2 * T),
+        LINE,
+    );
+}
+
+/// Height of the status bar
+pub const STATUS_BAR_HEIGHT: usize = LINE_HEIGHT + PANEL_PADDING;
+
+/// Height of the histogram plot; each of its 256 columns is a value
+const HISTOGRAM_HEIGHT: usize = 100;
+
+/// Size of the histogram panel, plot and padding
+pub const HISTOGRAM_SIZE: (usize, usize) = (
+    256 + PANEL_PADDING * 2,
+    HISTOGRAM_HEIGHT + PANEL_PADDING * 2,
+);
+
+/// Draw a histogram on a dark translucent panel with its top-left at
+/// (x, y). Each series is plotted in its color, overlapping bars adding
+/// up. The plot is scaled to the tallest bar away from the ends, so
+/// clipped shadows or highlights run off the top instead of flattening
+/// the rest.
+pub fn draw_histogram(
+    frame: &mut [u8],
+    frame_w: usize,
+    frame_h: usize,
+    x: usize,
+    y: usize,
+    series: &[([u32; 256], [u8; 3])],
+) {
+    let (w, h) = HISTOGRAM_SIZE;
+    fill_rect_blend(frame, frame_w, frame_h, x, y, w, h, [0, 0, 0, 170]);
+
+    let peak = |bins: std::ops::RangeInclusive<usize>| {
+        series
+            .iter()
+            .flat_map(|(counts, _)| counts[bins.clone()].iter())
+            .copied()
+            .max()
+            .unwrap_or(0)
+    };
+    let peak = match peak(1..=254) {
+        0 => peak(0..=255),
+        inner => inner,
+    };
+    if peak == 0 {
+        return;
+    }
+
+    let bottom = y + PANEL_PADDING + HISTOGRAM_HEIGHT;
+    for bin in 0..256 {
+        let mut heights: Vec<usize> = series
+            .iter()
+            .map(|(counts, _)| {
+                let height = counts[bin] as u64 * HISTOGRAM_HEIGHT as u64 / peak as u64;
+                (height as usize).min(HISTOGRAM_HEIGHT)
+            })
+            .collect();
+        let tops = heights.clone();
+        heights.sort_unstable();
+        heights.dedup();
+
+        // Stack segments from the bottom, each in the colors of the bars
+        // that reach past it
+        let mut low = 0;
+        for high in heights {
+            if high == low {
+                continue;
+            }
+            let mut color = [0u8, 0, 0, 200];
+            for ((_, series_color), &top) in series.iter().zip(&tops) {
+                if top >= high {
+                    for (c, s) in color.iter_mut().zip(series_color) {
+                        *c = c.saturating_add(*s);
+                    }
+                }
+            }
+            fill_rect_blend(
+                frame,
+                frame_w,
+                frame_h,
+                x + PANEL_PADDING + bin,
+                bottom - high,
+                1,
+                high - low,
+                color,
+            );
+            low = high;
+        }
+    }
+}
+
+/// Draw a bar along the bottom of the frame with `left` (the file name) and
+/// `right` (the details) at either end
+pub fn draw_status_bar(frame: &mut [u8], frame_w: usize, frame_h: usize, left: &str, right: &str) {
+    let bar_h = STATUS_BAR_HEIGHT;
+    let top = frame_h.saturating_sub(bar_h);
+    fill_rect_blend(
+        frame,
+        frame_w,
+        frame_h,
+        0,
+        top,
+        frame_w,
+        bar_h,
+        [0, 0, 0, 170],
+    );
+
+    let max_chars = frame_w.saturating_sub(PANEL_PADDING * 2) / (8 * TEXT_SCALE);
+    draw_text(
+        frame,
+        frame_w,
+        frame_h,
+        PANEL_PADDING,
+        top + (bar_h - 8 * TEXT_SCALE) / 2,
+        &status_line(left, right, max_chars),
+        [255, 255, 255, 255],
+        TEXT_SCALE,
+    );
+}
+
+/// Fit `left` and right-aligned `right` into `max_chars`. The left side is
+/// shortened from the front first (file names end in the telling part),
+/// then the right side is cut off.
+fn status_line(left: &str, right: &str, max_chars: usize) -> String {
+    let right_len = right.chars().count();
+    if right_len >= max_chars {
+        return right.chars().take(max_chars).collect();
+    }
+
+    // Keep at least one space between the two
+    let room = max_chars - right_len - 1;
+    let left_len = left.chars().count();
+    let left: String = if left_len <= room {
+        left.to_string()
+    } else if room > 3 {
+        let tail: String = left.chars().skip(left_len - (room - 3)).collect();
+        format!("...{}", tail)
+    } else {
+        String::new()
+    };
+    let pad = max_chars - left.chars().count() - right_len;
+    format!("{}{}{}", left, " ".repeat(pad), right)
+}
+
+/// Lay out (left, right) rows as two text columns, at most `max_chars`
+/// wide, wrapping either cell onto further lines when it doesn't fit
+pub fn two_columns(rows: &[(String, String)], max_chars: usize) -> Vec<String> {
+    const GAP: usize = 2;
+    let longest = rows
+        .iter()
+        .map(|(l, _)| l.chars().count())
+        .max()
+        .unwrap_or(0);
+    let left_w = longest.min(max_chars / 2).max(1);
+    let right_w = max_chars.saturating_sub(left_w + GAP).max(1);
+
+    let mut lines = Vec::new();
+    for (left, right) in rows {
+        let left = wrap(left, left_w);
+        let right = wrap(right, right_w);
+        for i in 0..left.len().max(right.len()) {
+            let l = left.get(i).map_or("", String::as_str);
+            let r = right.get(i).map_or("", String::as_str);
+            let line = format!("{:<width$}{}{}", l, " ".repeat(GAP), r, width = left_w);
+            lines.push(line.trim_end().to_string());
+        }
+    }
+    lines
+}
+
+/// Break text into lines of at most `width` characters, at spaces where
+/// possible
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let width = width.max(1);
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        let len = line.chars().count();
+        if len > 0 && len + 1 + word.chars().count() > width {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+        // Words longer than a line are broken up
+        while line.chars().count() > width {
+            let rest: String = line.chars().skip(width).collect();
+            line = line.chars().take(width).collect();
+            lines.push(std::mem::replace(&mut line, rest));
+        }
+    }
+    if !line.is_empty() || lines.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+/// Draw the key binding help panel, (keys, action) rows in two columns,
+/// centered over the frame
+pub fn draw_help(frame: &mut [u8], frame_w: usize, frame_h: usize, rows: &[(String, String)]) {
+    let max_chars = frame_w.saturating_sub(PANEL_PADDING * 2) / (8 * TEXT_SCALE);
+    let lines = two_columns(rows, max_chars);
+    let (panel_w, panel_h) = panel_size(&lines);
+    draw_panel(
+        frame,
+        frame_w,
+        frame_h,
+        frame_w.saturating_sub(panel_w) / 2,
+        frame_h.saturating_sub(panel_h) / 2,
+        &lines,
+    );
+}
+
+/// Size of the broken-image glyph
+const GLYPH_W: usize = 48;
+const GLYPH_H: usize = 40;
+/// Gap between the glyph and the message panel below it
+const GLYPH_GAP: usize = 12;
+/// Checkerboard square size for the loading treatment
+const CHECKER_SIZE: usize = 16;
+
+/// Fill the frame with a broken-image glyph above a centered message
+/// panel, shown in place of images that failed to decode
+pub fn render_placeholder(
+    frame: &mut [u8],
+    frame_w: usize,
+    frame_h: usize,
+    background: [u8; 4],
+    lines: &[String],
+) {
+    clear_frame(frame, background);
+
+    let (panel_w, panel_h) = panel_size(lines);
+    let top = frame_h.saturating_sub(GLYPH_H + GLYPH_GAP + panel_h) / 2;
+    draw_broken_glyph(
+        frame,
+        frame_w,
+        frame_h,
+        frame_w.saturating_sub(GLYPH_W) / 2,
+        top,
+    );
+    draw_panel(
+        frame,
+        frame_w,
+        frame_h,
+        frame_w.saturating_sub(panel_w) / 2,
+        top + GLYPH_H + GLYPH_GAP,
+        lines,
+    );
+}
+
+/// Fill the frame with a dim checkerboard while an image is still loading,
+/// so it can't be mistaken for a black image or a failed one
+pub fn render_loading(frame: &mut [u8], frame_w: usize, background: [u8; 4]) {
+    let light = background.map(|c| c.saturating_add(16));
+    for (i, pixel) in frame.chunks_exact_mut(4).enumerate() {
+        let (x, y) = (i % frame_w.max(1), i / frame_w.max(1));
+        let color = if (x / CHECKER_SIZE + y / CHECKER_SIZE).is_multiple_of(2) {
+            background
+        } else {
+            light
+        };
+        pixel.copy_from_slice(&[color[0], color[1], color[2], 255]);
+    }
+}
+
+/// Picture frame outline with a zig-zag crack through it
+fn draw_broken_glyph(frame: &mut [u8], frame_w: usize, frame_h: usize, x: usize, y: usize) {
+    const COLOR: [u8; 4] = [200, 200, 200, 255];
+    const T: usize = 2;
+
+    let mut rect = |rx: usize, ry: usize, w: usize, h: usize| {
+        fill_rect_blend(frame, frame_w, frame_h, x + rx, y + ry, w, h, COLOR)
+    };
+    rect(0, 0, GLYPH_W, T);
+    rect(0, GLYPH_H - T, GLYPH_W, T);
+    rect(0, 0, T, GLYPH_H);
+    rect(GLYPH_W - T, 0, T, GLYPH_H);
+
+    let crack = [(30, 0), (22, 14), (30, 25), (20, GLYPH_H - 1)];
+    for pair in crack.windows(2) {
+        let ((x0, y0), (x1, y1)) = (pair[0], pair[1]);
+        let steps = (x1 as isize - x0 as isize)
+            .abs()
+            .max((y1 as isize - y0 as isize).abs())
+            .max(1);
+        for i in 0..=steps {
+            let px = x0 as isize + (x1 as isize - x0 as isize) * i / steps;
+            let py = y0 as isize + (y1 as isize - y0 as isize) * i / steps;
+            rect(px as usize, py as usize, T, T);
+        }
+    }
+}
+
+/// Most source pixels averaged along each axis when shrinking; beyond this
+/// the covered pixels are sampled evenly instead
+const MAX_AREA_TAPS: usize = 8;
+
+/// Source pixels contributing to one output column (or row), as offsets
+/// into the source with weights summing to 1
+type Taps = Vec<(isize, f32)>;
+
+/// Taps for each output pixel in `start..end` along one axis, where the
+/// image spans `len` display pixels scaled by `scale` from `origin`.
+/// `index` turns a display coordinate into a source offset.
+///
+/// Shrinking averages the source pixels each output pixel covers;
+/// enlarging interpolates around its center.
+fn axis_taps(
+    filter: ScalingFilter,
+    range: std::ops::Range<usize>,
+    origin: f64,
+    scale: f64,
+    len: usize,
+    index: impl Fn(usize) -> isize,
+) -> Vec<Taps> {
+    let last = len as isize - 1;
+    let at = |i: isize| index(i.clamp(0, last) as usize);
+
+    range
+        .map(|x| {
+            let center = (x as f64 + 0.5 - origin) / scale - 0.5;
+            if scale < 1.0 && filter != ScalingFilter::Nearest {
+                let a = ((x as f64 - origin) / scale).clamp(0.0, len as f64);
+                let b = ((x as f64 + 1.0 - origin) / scale).clamp(0.0, len as f64);
+                let lo = a.floor() as usize;
+                let hi = (b.ceil() as usize).min(len);
+                if lo < hi {
+                    return area_taps(lo, hi, a, b, &at);
+                }
+            }
+            // Past the outer pixel centers the edge pixel is held, so
+            // Catmull-Rom doesn't overshoot there
+            let center = center.clamp(0.0, last.max(0) as f64);
+            let i = center.floor() as isize;
+            let f = (center - i as f64) as f32;
+            match filter {
+                ScalingFilter::Nearest => vec![(at((center + 0.5).floor() as isize), 1.0)],
+                ScalingFilter::Bilinear => vec![(at(i), 1.0 - f), (at(i + 1), f)],
+                ScalingFilter::CatmullRom => catmull_rom_weights(f)
+                    .into_iter()
+                    .enumerate()
+                    .map(|(k, w)| (at(i - 1 + k as isize), w))
+                    .collect(),
+            }
+        })
+        .collect()
+}
+
+/// Box filter over the display pixels `lo..hi`, which the output pixel
+/// covers from `a` to `b`
+fn area_taps(lo: usize, hi: usize, a: f64, b: f64, at: &impl Fn(isize) -> isize) -> Taps {
+    let step = (hi - lo).div_ceil(MAX_AREA_TAPS);
+    let mut taps: Taps = (lo..hi)
+        .step_by(step)
+        .map(|i| {
+            let weight = if step == 1 {
+                b.min(i as f64 + 1.0) - a.max(i as f64)
+            } else {
+                1.0
+            };
+            (at(i as isize), weight as f32)
+        })
+        .collect();
+    let total: f32 = taps.iter().map(|&(_, w)| w).sum();
+    if total > 0.0 {
+        for tap in &mut taps {
+            tap.1 /= total;
+        }
+    }
+    taps
+}
+
+/// Catmull-Rom weights for the four pixels around a point `f` (0..1) past
+/// the second of them
+fn catmull_rom_weights(f: f32) -> [f32; 4] {
+    let f2 = f * f;
+    let f3 = f2 * f;
+    [
+        -0.5 * f3 + f2 - 0.5 * f,
+        1.5 * f3 - 2.5 * f2 + 1.0,
+        -1.5 * f3 + 2.0 * f2 + 0.5 * f,
+        0.5 * f3 - 0.5 * f2,
+    ]
+}
+
+/// Blit like `blit_transformed`, but resampling with `filter` instead of
+/// picking the nearest pixel.
+///
+/// The weights split per axis the same way the offsets do, so each output
+/// pixel is a weighted sum over (row taps x column taps), both precomputed.
+#[allow(clippy::too_many_arguments)]
+fn blit_filtered(
+    src: &[u8],
+    src_w: usize,
+    src_h: usize,
+    dst: &mut [u8],
+    dst_w: usize,
+    dst_h: usize,
+    origin: (f64, f64),
+    scale: f64,
+    orientation: Orientation,
+    filter: ScalingFilter,
+    opaque: bool,
+) {
+    if src_w == 0
+        || src_h == 0
+        || scale <= 0.0
+        || src.len() < src_w * src_h * 4
+        || dst.len() < dst_w * dst_h * 4
+    {
+        return;
+    }
+
+    let (rot_w, rot_h) = orientation.display_dims(src_w, src_h);
+    let (left, top) = origin;
+    let x_start = left.max(0.0).floor() as usize;
+    let x_end = ((left + rot_w as f64 * scale).ceil().max(0.0) as usize).min(dst_w);
+    let y_start = top.max(0.0).floor() as usize;
+    let y_end = ((top + rot_h as f64 * scale).ceil().max(0.0) as usize).min(dst_h);
+    if x_start >= x_end || y_start >= y_end {
+        return;
+    }
+
+    let src_index = |u: usize, v: usize| {
+        let (x, y) = orientation.source_coords(u, v, src_w, src_h);
+        ((y * src_w + x) * 4) as isize
+    };
+    let base = src_index(0, 0);
+    let columns = axis_taps(filter, x_start..x_end, left, scale, rot_w, |u| {
+        src_index(u, 0) - base
+    });
+    let rows = axis_taps(filter, y_start..y_end, top, scale, rot_h, |v| {
+        src_index(0, v)
+    });
+
+    // Rows are independent, and a 4K frame is worth spreading over cores
+    let stride = dst_w * 4;
+    dst[y_start * stride..y_end * stride]
+        .par_chunks_exact_mut(stride)
+        .zip(rows.par_iter())
+        .for_each(|(dst_row, row_taps)| {
+            let pixels = dst_row[x_start * 4..x_end * 4].chunks_exact_mut(4);
+            for (pixel, column_taps) in pixels.zip(&columns) {
+                let mut sum = [0.0f32; 3];
+                let mut alpha = 0.0f32;
+                for &(row, row_weight) in row_taps {
+                    for &(column, column_weight) in column_taps {
+                        let idx = (row + column) as usize;
+                        let weight = row_weight * column_weight;
+                        sum[0] += src[idx] as f32 * weight;
+                        sum[1] += src[idx + 1] as f32 * weight;
+                        sum[2] += src[idx + 2] as f32 * weight;
+                        if !opaque {
+                            alpha += src[idx + 3] as f32 * weight;
+                        }
+                    }
+                }
+                for (channel, value) in pixel.iter_mut().zip(sum) {
+                    *channel = value.round().clamp(0.0, 255.0) as u8;
+                }
+                // Force opaque unless the alpha is to be shown
+                pixel[3] = if opaque {
+                    255
+                } else {
+                    alpha.round().clamp(0.0, 255.0) as u8
+                };
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_test_image(w: u32, h: u32) -> Arc<ImageData> {
+        let pixels = vec![128u8; (w * h * 4) as usize];
+        Arc::new(ImageData::new(pixels, w, h, QualityTier::Full))
+    }
+
+    #[test]
+    fn test_grid_layout() {
+        let layout = grid_layout(1000, 700, 192);
+        assert_eq!((layout.columns, layout.rows), (5, 3));
+        assert_eq!(layout.cells(), 15);
+        // Centered, whole cells only
+        assert_eq!(layout.origin, (20, 62));
+        assert_eq!(layout.cell_origin(0), (20, 62));
+        assert_eq!(layout.cell_origin(6), (212, 254));
+
+        // Reflows with the window, never below one cell
+        assert_eq!(grid_layout(400, 700, 192).columns, 2);
+        let tiny = grid_layout(50, 50, 192);
+        assert_eq!((tiny.columns, tiny.rows, tiny.origin), (1, 1, (0, 0)));
+
+        // Hit testing
+        assert_eq!(layout.cell_at((20.0, 62.0)), Some(0));
+        assert_eq!(layout.cell_at((405.0, 300.0)), Some(7));
+        assert_eq!(layout.cell_at((10.0, 100.0)), None);
+        assert_eq!(layout.cell_at((990.0, 100.0)), None);
+        assert_eq!(layout.cell_at((100.0, 650.0)), None);
+    }
+
+    #[test]
+    fn test_filmstrip() {
+        // Above a 20px status bar, centered
+        let layout = filmstrip_layout(1000, 600, 20);
+        assert_eq!((layout.cell, layout.columns, layout.rows), (96, 9, 1));
+        assert_eq!(layout.origin, (68, 484));
+        // Narrow windows get smaller cells
+        assert_eq!(filmstrip_layout(450, 600, 0).cell, 50);
+
+        let layout = filmstrip_layout(900, 100, 0);
+        assert_eq!(layout.origin, (18, 4));
+        let image = Arc::new(ImageData::new(
+            vec![255; 4 * 4 * 4],
+            4,
+            4,
+            QualityTier::Thumbnail,
+        ));
+        let mut frame: Vec<u8> = [200, 200, 200, 255].repeat(900 * 100);
+        // Near the start: the first cells stay empty
+        draw_filmstrip(
+            &mut frame,
+            900,
+            100,
+            &layout,
+            3,
+            &[None, Some(image.clone()), Some(image)],
+            ScalingFilter::Nearest,
+        );
+        let pixel = |x: usize, y: usize| &frame[(y * 900 + x) * 4..(y * 900 + x) * 4 + 4];
+        // The band dims what's behind it; a loading cell is black
+        assert_eq!(pixel(30, 50), &[74, 74, 74, 255]);
+        assert_eq!(pixel(350, 50), &[0, 0, 0, 255]);
+        // The current image is in the middle, outlined
+        assert_eq!(pixel(450, 50), &[255, 255, 255, 255]);
+        assert_eq!(pixel(403, 50), &[255, 196, 0, 255]);
+        // Above the band, untouched
+        assert_eq!(pixel(450, 2), &[200, 200, 200, 255]);
+    }
+
+    #[test]
+    fn test_render_grid() {
+        let layout = grid_layout(200, 100, 100);
+        let wide = Arc::new(ImageData::new(
+            vec![255; 40 * 20 * 4],
+            40,
+            20,
+            QualityTier::Thumbnail,
+        ));
+        let mut frame = vec![0u8; 200 * 100 * 4];
+        render_grid(
+            &[Some(wide), None],
+            &mut frame,
+            200,
+            100,
+            [0, 0, 0, 255],
+            &layout,
+            1,
+            ScalingFilter::Nearest,
+        );
+        let pixel = |x: usize, y: usize| &frame[(y * 200 + x) * 4..(y * 200 + x) * 4 + 4];
+        // Letterboxed in the first cell
+        assert_eq!(pixel(50, 50), &[255, 255, 255, 255]);
+        assert_eq!(pixel(50, 15), &[0, 0, 0, 255]);
+        // A placeholder in the second, outlined as the cursor
+        assert_eq!(pixel(150, 50), &[16, 16, 16, 255]);
+        assert_eq!(pixel(150, 1), &[255, 196, 0, 255]);
+        assert_eq!(pixel(198, 50), &[255, 196, 0, 255]);
+        assert_eq!(pixel(50, 1), &[0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn test_render_pair() {
+        let tall = make_test_image(10, 40);
+        let wide = Arc::new(ImageData::new(
+            vec![255; 40 * 10 * 4],
+            40,
+            10,
+            QualityTier::Preview,
+        ));
+        let mut frame = vec![0u8; 100 * 50 * 4];
+        let (left, right) = render_pair(
+            Some(&tall),
+            Some(&wide),
+            &mut frame,
+            100,
+            50,
+            [0, 0, 0, 255],
+            &ViewTransform::FIT,
+            ScalingFilter::Nearest,
+            0,
+            &Look::NONE,
+        );
+        assert_eq!(left.quality, Some(QualityTier::Full));
+        assert_eq!(right.quality, Some(QualityTier::Preview));
+
+        // Each letterboxed in its own half
+        let pixel = |x: usize, y: usize| &frame[(y * 100 + x) * 4..(y * 100 + x) * 4 + 4];
+        assert_eq!(pixel(25, 25), &[128, 128, 128, 255]);
+        assert_eq!(pixel(5, 25), &[0, 0, 0, 255]);
+        assert_eq!(pixel(75, 25), &[255, 255, 255, 255]);
+        assert_eq!(pixel(75, 5), &[0, 0, 0, 255]);
+
+        // A side still loading shows as such
+        let (left, _) = render_pair(
+            None,
+            Some(&wide),
+            &mut frame,
+            100,
+            50,
+            [0, 0, 0, 255],
+            &ViewTransform::FIT,
+            ScalingFilter::Nearest,
+            0,
+            &Look::NONE,
+        );
+        assert!(left.quality.is_none());
+    }
+
+    #[test]
+    fn test_render_empty() {
+        let mut frame = vec![0u8; 100 * 100 * 4];
+        let result = render_image(
+            None,
+            &mut frame,
+            100,
+            100,
+            [0, 0, 0, 255],
+            None,
+            &ViewTransform::FIT,
+            ScalingFilter::Nearest,
+            0,
+            &Look::NONE,
+        );
+
+        assert!(result.quality.is_none());
+        // Loading shows a checkerboard, not a flat frame
+        assert_eq!(&frame[0..4], &[0, 0, 0, 255]);
+        let second_square = CHECKER_SIZE * 4;
+        assert_eq!(&frame[second_square..second_square + 4], &[16, 16, 16, 255]);
+    }
+
+    #[test]
+    fn test_placeholder_small_windows() {
+        let lines = vec!["broken.png".to_string(), "corrupt image".to_string()];
+        for (w, h) in [(0, 0), (1, 1), (7, 3), (40, 30), (200, 100)] {
+            let mut frame = vec![0u8; w * h * 4];
+            render_placeholder(&mut frame, w, h, [10, 10, 10, 255], &lines);
+            render_loading(&mut frame, w, [10, 10, 10, 255]);
+            assert_eq!(frame.len(), w * h * 4);
+        }
+
+        // The glyph is drawn at the center of a roomy frame
+        let (w, h) = (400, 300);
+        let mut frame = vec![0u8; w * h * 4];
+        render_placeholder(&mut frame, w, h, [0, 0, 0, 255], &lines);
+        let (_, panel_h) = panel_size(&lines);
+        let top = (h - (GLYPH_H + GLYPH_GAP + panel_h)) / 2;
+        let idx = (top * w + (w - GLYPH_W) / 2) * 4;
+        assert_eq!(&frame[idx..idx + 4], &[200, 200, 200, 255]);
+    }
 
     #[test]
     fn test_render_image() {
         let img = make_test_image(50, 50);
         let mut frame = vec![0u8; 100 * 100 * 4];
 
-        let result = render_image(Some(&img), &mut frame, 100, 100, [0, 0, 0, 255]);
+        let result = render_image(
+            Some(&img),
+            &mut frame,
+            100,
+            100,
+            [0, 0, 0, 255],
+            None,
+            &ViewTransform::FIT,
+            ScalingFilter::Nearest,
+            0,
+            &Look::NONE,
+        );
 
         assert_eq!(result.quality, Some(QualityTier::Full));
     }
 
+    #[test]
+    fn test_render_zoomed_fills_window() {
+        // 10x10 image fit into 100x100, zoomed 2x: covers the whole window
+        let img = make_test_image(10, 10);
+        let mut frame = vec![0u8; 100 * 100 * 4];
+        let transform = ViewTransform {
+            zoom: 2.0,
+            pan_x: 1000.0,
+            ..ViewTransform::FIT
+        };
+
+        let result = render_image(
+            Some(&img),
+            &mut frame,
+            100,
+            100,
+            [0, 0, 0, 255],
+            None,
+            &transform,
+            ScalingFilter::Nearest,
+            0,
+            &Look::NONE,
+        );
+
+        // Pan clamped so the image edge meets the window edge
+        assert_eq!(result.pan, (50.0, 0.0));
+        assert_eq!(&frame[0..4], &[128, 128, 128, 255]);
+    }
+
+    #[test]
+    fn test_actual_size_upscales_lower_tier() {
+        // 20x20 preview of a 40x40 original, shown at 100% in a 100x100 window
+        let pixels = vec![200u8; 20 * 20 * 4];
+        let img =
+            Arc::new(ImageData::new(pixels, 20, 20, QualityTier::Preview).with_full_size(40, 40));
+        let mut frame = vec![0u8; 100 * 100 * 4];
+        let transform = ViewTransform {
+            actual_size: true,
+            ..ViewTransform::FIT
+        };
+
+        let result = render_image(
+            Some(&img),
+            &mut frame,
+            100,
+            100,
+            [0, 0, 0, 255],
+            None,
+            &transform,
+            ScalingFilter::Nearest,
+            0,
+            &Look::NONE,
+        );
+
+        // Reports the lower tier so the full decode triggers a re-render
+        assert_eq!(result.quality, Some(QualityTier::Preview));
+        assert_eq!(result.scale, Some(1.0));
+        // Covers 40x40 centered: (30..70)
+        let at = |x: usize, y: usize| frame[(y * 100 + x) * 4];
+        assert_eq!(at(30, 30), 200);
+        assert_eq!(at(69, 69), 200);
+        assert_eq!(at(29, 50), 0);
+        assert_eq!(at(70, 50), 0);
+    }
+
+    /// Apply an orientation forward, independently of `source_coords`:
+    /// mirror, then rotate clockwise one quarter turn at a time
+    fn orient_forward(o: Orientation, x: usize, y: usize, w: usize, h: usize) -> (usize, usize) {
+        let (mut x, mut y, mut w, mut h) = (x, y, w, h);
+        if o.mirrored {
+            x = w - 1 - x;
+        }
+        for _ in 0..o.quarter_turns {
+            (x, y) = (h - 1 - y, x);
+            (w, h) = (h, w);
+        }
+        (x, y)
+    }
+
+    #[test]
+    fn test_orientations_2x3() {
+        // 2x3 pattern with a distinct value per pixel in the red channel
+        let (w, h) = (2usize, 3usize);
+        let value = |x: usize, y: usize| ((y * w + x) * 40) as u8;
+        let mut pixels = Vec::new();
+        for y in 0..h {
+            for x in 0..w {
+                pixels.extend_from_slice(&[value(x, y), 0, 0, 255]);
+            }
+        }
+        let img = Arc::new(ImageData::new(
+            pixels,
+            w as u32,
+            h as u32,
+            QualityTier::Full,
+        ));
+
+        let mut orientations = Vec::new();
+        for quarter_turns in 0..4 {
+            for mirrored in [false, true] {
+                orientations.push(Orientation {
+                    quarter_turns,
+                    mirrored,
+                });
+            }
+        }
+
+        // At scale 1 every filter copies pixels exactly
+        let filters = [
+            ScalingFilter::Nearest,
+            ScalingFilter::Bilinear,
+            ScalingFilter::CatmullRom,
+        ];
+        for (o, filter) in orientations
+            .into_iter()
+            .flat_map(|o| filters.map(|filter| (o, filter)))
+        {
+            // Window exactly the rotated size, so scale is 1
+            let (dw, dh) = o.display_dims(w, h);
+            let mut frame = vec![0u8; dw * dh * 4];
+            let transform = ViewTransform {
+                orientation: o,
+                ..ViewTransform::FIT
+            };
+            render_image(
+                Some(&img),
+                &mut frame,
+                dw as u32,
+                dh as u32,
+                [0, 0, 0, 255],
+                None,
+                &transform,
+                filter,
+                0,
+                &Look::NONE,
+            );
+
+            for y in 0..h {
+                for x in 0..w {
+                    let (dx, dy) = orient_forward(o, x, y, w, h);
+                    assert_eq!(
+                        frame[(dy * dw + dx) * 4],
+                        value(x, y),
+                        "{:?} {:?} at ({}, {})",
+                        o,
+                        filter,
+                        x,
+                        y
+                    );
+                }
+            }
+        }
+
+        // Spot check: clockwise turn puts the left column, bottom-up, on top
+        let o = Orientation::IDENTITY.rotate_cw();
+        let mut frame = vec![0u8; 3 * 2 * 4];
+        let transform = ViewTransform {
+            orientation: o,
+            ..ViewTransform::FIT
+        };
+        render_image(
+            Some(&img),
+            &mut frame,
+            3,
+            2,
+            [0, 0, 0, 255],
+            None,
+            &transform,
+            ScalingFilter::Nearest,
+            0,
+            &Look::NONE,
+        );
+        let top_row: Vec<u8> = (0..3).map(|x| frame[x * 4]).collect();
+        assert_eq!(top_row, vec![value(0, 2), value(0, 1), value(0, 0)]);
+    }
+
+    /// Render `pixels` (red channel only) at fit-to-window with `filter`,
+    /// returning the red channel of the frame
+    fn render_red(
+        pixels: &[u8],
+        (w, h): (u32, u32),
+        (win_w, win_h): (u32, u32),
+        filter: ScalingFilter,
+    ) -> Vec<u8> {
+        let rgba = pixels.iter().flat_map(|&r| [r, 0, 0, 255]).collect();
+        let img = Arc::new(ImageData::new(rgba, w, h, QualityTier::Full));
+        let mut frame = vec![0u8; (win_w * win_h * 4) as usize];
+        render_image(
+            Some(&img),
+            &mut frame,
+            win_w,
+            win_h,
+            [0, 0, 0, 255],
+            None,
+            &ViewTransform::FIT,
+            filter,
+            0,
+            &Look::NONE,
+        );
+        frame.chunks_exact(4).map(|p| p[0]).collect()
+    }
+
+    #[test]
+    fn test_scaling_filters() {
+        // Shrinking a checkerboard by half: nearest picks one square,
+        // the smooth filters average each 2x2 block
+        let checker: Vec<u8> = (0..16)
+            .map(|i| if (i % 4 + i / 4) % 2 == 0 { 0 } else { 255 })
+            .collect();
+        let nearest = render_red(&checker, (4, 4), (2, 2), ScalingFilter::Nearest);
+        assert!(nearest.iter().all(|&v| v == 0 || v == 255));
+        for filter in [ScalingFilter::Bilinear, ScalingFilter::CatmullRom] {
+            let shrunk = render_red(&checker, (4, 4), (2, 2), filter);
+            assert_eq!(shrunk, vec![128; 4], "{:?}", filter);
+        }
+
+        // Enlarging two pixels to four
+        let pair = [0, 200];
+        let nearest = render_red(&pair, (2, 1), (4, 2), ScalingFilter::Nearest);
+        assert_eq!(&nearest[..4], &[0, 0, 200, 200]);
+        let bilinear = render_red(&pair, (2, 1), (4, 2), ScalingFilter::Bilinear);
+        assert_eq!(&bilinear[..4], &[0, 50, 150, 200]);
+        assert_eq!(&bilinear[4..], &bilinear[..4]);
+        let catmull_rom = render_red(&pair, (2, 1), (4, 2), ScalingFilter::CatmullRom);
+        assert_eq!((catmull_rom[0], catmull_rom[3]), (0, 200));
+        assert!(0 < catmull_rom[1] && catmull_rom[1] < catmull_rom[2] && catmull_rom[2] < 200);
+    }
+
+    /// Time each filter filling a 4K window, shrinking a 24 MP photo and
+    /// enlarging a 1080p one. Run with
+    /// `cargo test --release -- --ignored --nocapture bench_scaling_filters`.
+    #[test]
+    #[ignore]
+    fn bench_scaling_filters() {
+        let (win_w, win_h) = (3840u32, 2160u32);
+        let mut frame = vec![0u8; (win_w * win_h * 4) as usize];
+        for (w, h) in [(6000u32, 4000u32), (1920, 1080)] {
+            let img = make_test_image(w, h);
+            for filter in [
+                ScalingFilter::Nearest,
+                ScalingFilter::Bilinear,
+                ScalingFilter::CatmullRom,
+            ] {
+                let runs = 5;
+                let start = std::time::Instant::now();
+                for _ in 0..runs {
+                    render_image(
+                        Some(&img),
+                        &mut frame,
+                        win_w,
+                        win_h,
+                        [0, 0, 0, 255],
+                        None,
+                        &ViewTransform::FIT,
+                        filter,
+                        0,
+                        &Look::NONE,
+                    );
+                }
+                println!(
+                    "{}x{} -> {}x{} {:?}: {:.1} ms",
+                    w,
+                    h,
+                    win_w,
+                    win_h,
+                    filter,
+                    start.elapsed().as_secs_f64() * 1000.0 / runs as f64
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_clamp_pan() {
+        // Smaller than the window: always centered
+        assert_eq!(clamp_pan(30.0, 80.0, 100.0), 0.0);
+        // Larger: limited to half the overflow in either direction
+        assert_eq!(clamp_pan(30.0, 200.0, 100.0), 30.0);
+        assert_eq!(clamp_pan(-80.0, 200.0, 100.0), -50.0);
+    }
+
+    #[test]
+    fn test_draw_text_clips() {
+        let mut frame = vec![0u8; 20 * 10 * 4];
+        // Runs past the right and bottom edges without panicking
+        draw_text(&mut frame, 20, 10, 4, 4, "AB", [255, 255, 255, 255], 2);
+        assert!(frame.chunks_exact(4).any(|p| p[0] == 255));
+
+        // Unknown glyphs draw nothing
+        let mut blank = vec![0u8; 20 * 10 * 4];
+        draw_text(
+            &mut blank,
+            20,
+            10,
+            0,
+            0,
+            "\u{2603}",
+            [255, 255, 255, 255],
+            1,
+        );
+        assert!(blank.iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn test_draw_panel_darkens() {
+        let mut frame = vec![200u8; 200 * 100 * 4];
+        let lines = vec!["hi".to_string()];
+        draw_panel(&mut frame, 200, 100, 0, 0, &lines);
+
+        let (w, h) = panel_size(&lines);
+        // Corner of the panel is blended toward black, outside is untouched
+        assert!(frame[0] < 200);
+        assert_eq!(frame[(h * 200 + w) * 4], 200);
+
+        // On a transparent frame (GPU overlays) the panel keeps its alpha
+        let mut frame = vec![0u8; 200 * 100 * 4];
+        draw_panel(&mut frame, 200, 100, 0, 0, &lines);
+        assert_eq!(frame[3], 170);
+        assert_eq!(frame[(h * 200 + w) * 4 + 3], 0);
+    }
+
+    #[test]
+    fn test_place() {
+        let img = make_test_image(200, 100);
+
+        // Fit: half size, centered vertically
+        let fit = place(&img, 100, 100, &ViewTransform::FIT);
+        assert_eq!(fit.scale, 0.5);
+        assert_eq!(fit.origin, (0.0, 25.0));
+
+        // Zoomed in, with the pan clamped to the image edge
+        let transform = ViewTransform {
+            zoom: 2.0,
+            pan_x: 500.0,
+            ..ViewTransform::FIT
+        };
+        let zoomed = place(&img, 100, 100, &transform);
+        assert_eq!(zoomed.pan, (50.0, 0.0));
+        assert_eq!(zoomed.origin, (0.0, 0.0));
+    }
+
+    #[test]
+    fn test_blurred_backdrop() {
+        // Left half red, right half blue
+        let (w, h) = (64, 32);
+        let mut src = vec![0u8; w * h * 4];
+        for (i, px) in src.chunks_exact_mut(4).enumerate() {
+            let color = if i % w < w / 2 {
+                [255, 0, 0, 255]
+            } else {
+                [0, 0, 255, 255]
+            };
+            px.copy_from_slice(&color);
+        }
+
+        let backdrop = blurred_backdrop(&src, w, h, 100, 50);
+        assert_eq!(backdrop.len(), 100 * 50 * 4);
+        let at = |x: usize, y: usize| &backdrop[(y * 100 + x) * 4..(y * 100 + x) * 4 + 4];
+        // Dimmed, opaque, and blended across the middle
+        assert!(at(0, 25)[0] > 100 && at(0, 25)[0] < 255);
+        assert!(at(99, 25)[2] > 100 && at(99, 25)[0] < 50);
+        assert!(at(50, 25)[0] > 30 && at(50, 25)[2] > 30);
+        assert!(backdrop.chunks_exact(4).all(|px| px[3] == 255));
+
+        // Shown around a letterboxed image instead of the background color
+        let img = make_test_image(50, 10);
+        let mut frame = vec![0u8; 100 * 100 * 4];
+        let backdrop = vec![7u8; frame.len()];
+        render_image(
+            Some(&img),
+            &mut frame,
+            100,
+            100,
+            [0, 0, 0, 255],
+            Some(&backdrop),
+            &ViewTransform::FIT,
+            ScalingFilter::Nearest,
+            0,
+            &Look::NONE,
+        );
+        assert_eq!(frame[0], 7);
+    }
+
+    #[test]
+    fn test_status_line() {
+        assert_eq!(status_line("a.jpg", "[1/2]", 14), "a.jpg    [1/2]");
+        // The name gives way first, keeping its end
+        assert_eq!(status_line("holiday.jpg", "[1/2]", 14), "...y.jpg [1/2]");
+        assert_eq!(status_line("holiday.jpg", "[1/2]", 8), "   [1/2]");
+        assert_eq!(status_line("holiday.jpg", "[1/2] 50%", 4), "[1/2");
+    }
+
+    #[test]
+    fn test_two_columns() {
+        let rows = vec![
+            ("KeyQ".to_string(), "Quit".to_string()),
+            ("ArrowRight, KeyD".to_string(), "NavigateRight".to_string()),
+        ];
+        assert_eq!(
+            two_columns(&rows, 80),
+            vec!["KeyQ              Quit", "ArrowRight, KeyD  NavigateRight"]
+        );
+
+        // Narrow: the key column wraps at spaces, long words are split up
+        assert_eq!(
+            two_columns(&rows, 24),
+            vec![
+                "KeyQ          Quit",
+                "ArrowRight,   NavigateRi",
+                "KeyD          ght",
+            ]
+        );
+        assert_eq!(wrap("", 5), vec![""]);
+    }
+
     #[test]
     fn test_clear_frame() {
         let mut frame = vec![0u8; 16];
@@ -244,4 +2426,315 @@ mod tests {
         assert_eq!(&frame[0..4], &[255, 0, 0, 255]);
         assert_eq!(&frame[4..8], &[255, 0, 0, 255]);
     }
+
+    #[test]
+    fn test_identity_lut() {
+        let lut = adjustment_lut(0.0, 1.0, 0.0);
+        let original: Vec<u8> = (0..=255).flat_map(|v| [v, 255 - v, v / 2, 7]).collect();
+        let mut pixels = original.clone();
+        apply_lut(&mut pixels, &lut);
+        assert_eq!(pixels, original);
+    }
+
+    #[test]
+    fn test_exposure_lut() {
+        // +1 stop doubles midtones and clips the upper half to white
+        let lut = adjustment_lut(1.0, 1.0, 0.0);
+        assert_eq!(lut[0][0], 0);
+        assert_eq!(lut[0][64], 128);
+        assert_eq!(lut[1][100], 200);
+        assert_eq!(lut[2][128], 255);
+        assert_eq!(lut[0][200], 255);
+
+        let mut pixels = vec![64, 100, 200, 40];
+        apply_lut(&mut pixels, &lut);
+        assert_eq!(pixels, vec![128, 200, 255, 40]);
+
+        // Contrast pivots about mid-gray
+        let lut = adjustment_lut(0.0, 1.0, 0.5);
+        assert_eq!(lut[0][128], 128);
+        assert_eq!(lut[0][64], 32);
+        assert_eq!(lut[0][240], 255);
+    }
+
+    #[test]
+    fn test_lut_spares_background() {
+        // A 2x2 image fit into 4x2 lands in the middle two columns
+        let img = Arc::new(ImageData::new(vec![64; 16], 2, 2, QualityTier::Full));
+        let look = Look {
+            channel: ChannelMode::Color,
+            lut: Some(adjustment_lut(1.0, 1.0, 0.0)),
+        };
+        let mut frame = vec![0u8; 4 * 2 * 4];
+        render_image(
+            Some(&img),
+            &mut frame,
+            4,
+            2,
+            [10, 10, 10, 255],
+            None,
+            &ViewTransform::FIT,
+            ScalingFilter::Nearest,
+            0,
+            &look,
+        );
+        let red: Vec<u8> = frame.chunks_exact(4).map(|p| p[0]).collect();
+        assert_eq!(red, vec![10, 128, 128, 10, 10, 128, 128, 10]);
+    }
+
+    #[test]
+    fn test_channel_modes() {
+        let pixel = [200u8, 100, 50, 30];
+        let shown = |mode| {
+            let mut pixels = pixel.to_vec();
+            apply_channel_mode(&mut pixels, mode);
+            pixels
+        };
+        assert_eq!(shown(ChannelMode::Color), pixel);
+        assert_eq!(shown(ChannelMode::Red), [200, 200, 200, 255]);
+        assert_eq!(shown(ChannelMode::Green), [100, 100, 100, 255]);
+        assert_eq!(shown(ChannelMode::Blue), [50, 50, 50, 255]);
+        assert_eq!(shown(ChannelMode::Alpha), [30, 30, 30, 255]);
+        assert_eq!(shown(ChannelMode::Luminance), [117, 117, 117, 255]);
+        assert_eq!(shown(ChannelMode::Invert), [55, 155, 205, 30]);
+    }
+
+    #[test]
+    fn test_alpha_view() {
+        // The blit normally drops alpha; the alpha view still shows it, as
+        // opaque gray
+        let pixels: Vec<u8> = [0u8, 255].iter().flat_map(|&a| [255, 0, 0, a]).collect();
+        let img = Arc::new(ImageData::new(pixels, 2, 1, QualityTier::Full));
+        let look = Look {
+            channel: ChannelMode::Alpha,
+            lut: None,
+        };
+        for filter in [ScalingFilter::Nearest, ScalingFilter::Bilinear] {
+            let mut frame = vec![0u8; 2 * 4];
+            render_image(
+                Some(&img),
+                &mut frame,
+                2,
+                1,
+                [0, 0, 0, 255],
+                None,
+                &ViewTransform::FIT,
+                filter,
+                0,
+                &look,
+            );
+            assert_eq!(
+                frame,
+                vec![0, 0, 0, 255, 255, 255, 255, 255],
+                "{:?}",
+                filter
+            );
+        }
+    }
+
+    #[test]
+    fn test_window_to_image() {
+        // 4x2 image fit into 8x8: scale 2, rows 2..6 of the window
+        let img = ImageData::new(vec![0; 4 * 2 * 4], 4, 2, QualityTier::Full);
+        let fit = ViewTransform::FIT;
+        assert_eq!(window_to_image(&img, 8, 8, &fit, (0.5, 2.5)), Some((0, 0)));
+        assert_eq!(window_to_image(&img, 8, 8, &fit, (7.5, 5.5)), Some((3, 1)));
+        assert_eq!(window_to_image(&img, 8, 8, &fit, (3.0, 1.5)), None);
+        assert_eq!(window_to_image(&img, 8, 8, &fit, (3.0, 6.0)), None);
+
+        // Rotated clockwise the image is 2x4, scale 2, columns 2..6; the
+        // top-left of the window shows the bottom-left of the image
+        let rotated = ViewTransform {
+            orientation: Orientation::IDENTITY.rotate_cw(),
+            ..fit
+        };
+        assert_eq!(
+            window_to_image(&img, 8, 8, &rotated, (2.5, 0.5)),
+            Some((0, 1))
+        );
+        assert_eq!(
+            window_to_image(&img, 8, 8, &rotated, (5.5, 7.5)),
+            Some((3, 0))
+        );
+
+        // Zoomed 2x and panned 4 pixels left: scale 4, the image spans
+        // -8..8 across and fills the window down
+        let zoomed = ViewTransform {
+            zoom: 2.0,
+            pan_x: -4.0,
+            ..fit
+        };
+        assert_eq!(
+            window_to_image(&img, 8, 8, &zoomed, (0.5, 4.0)),
+            Some((2, 1))
+        );
+    }
+
+    #[test]
+    fn test_sample_pixel() {
+        let pixels: Vec<u8> = (0..4u8).flat_map(|i| [i * 60, 255 - i, 7, 255]).collect();
+        let img = ImageData::new(pixels.clone(), 2, 2, QualityTier::Full);
+        let sample = sample_pixel(&img, 0, 2, 2, &ViewTransform::FIT, (1.5, 0.5)).unwrap();
+        assert_eq!(sample.rgba, [60, 254, 7, 255]);
+        assert_eq!(sample.hex(), "#3CFE07");
+        assert_eq!((sample.x, sample.y, sample.approximate), (1, 0, false));
+
+        // A preview of a 4x4 original reports original coordinates
+        let mut preview = ImageData::new(pixels, 2, 2, QualityTier::Preview);
+        (preview.full_width, preview.full_height) = (4, 4);
+        let sample = sample_pixel(&preview, 0, 2, 2, &ViewTransform::FIT, (1.5, 1.5)).unwrap();
+        assert_eq!((sample.x, sample.y, sample.approximate), (2, 2, true));
+        assert_eq!(sample.lines()[0], "2, 2 (preview)");
+    }
+
+    #[test]
+    fn test_fitted_window_size() {
+        let monitor = Some((1920, 1080));
+        // Small enough: 1:1, but not tiny
+        assert_eq!(fitted_window_size((800, 600), monitor), (800, 600));
+        assert_eq!(fitted_window_size((32, 400), monitor), (128, 400));
+        // Too big: 90% of the monitor's tighter side, keeping the aspect
+        assert_eq!(fitted_window_size((6000, 4000), monitor), (1458, 972));
+        assert_eq!(fitted_window_size((1000, 3000), monitor), (324, 972));
+        // Monitor unknown: 1:1
+        assert_eq!(fitted_window_size((6000, 4000), None), (6000, 4000));
+    }
+
+    #[test]
+    fn test_tooltip_origin() {
+        assert_eq!(tooltip_origin((10.0, 10.0), (50, 20), (200, 100)), (26, 26));
+        // Flipped to the left and above near the bottom-right corner
+        assert_eq!(
+            tooltip_origin((190.0, 95.0), (50, 20), (200, 100)),
+            (124, 59)
+        );
+        // Too big either way: pinned to the edge
+        assert_eq!(tooltip_origin((5.0, 5.0), (300, 20), (200, 100)).0, 0);
+    }
+
+    #[test]
+    fn test_draw_mark_badge() {
+        let size = MARK_BADGE_SIZE + 4;
+        let mut frame = vec![0u8; size * size * 4];
+        draw_mark_badge(&mut frame, size, size, 4, 4);
+        let at = |x: usize, y: usize| frame[(y * size + x) * 4..][..3].to_vec();
+        // Gold inside the corner, untouched outside it
+        assert!(at(5, 5)[0] > 200 && at(5, 5)[2] < 50);
+        assert_eq!(at(1, 1), vec![0, 0, 0]);
+        // The star is dark on the gold
+        let dark = (4..size)
+            .flat_map(|y| (4..size).map(move |x| (x, y)))
+            .filter(|&(x, y)| at(x, y)[0] < 50)
+            .count();
+        assert!(dark > 0);
+    }
+
+    #[test]
+    fn test_draw_drop_border() {
+        let mut frame = vec![0u8; 40 * 30 * 4];
+        draw_drop_border(&mut frame, 40, 30);
+        let at = |x: usize, y: usize| frame[(y * 40 + x) * 4..][..3].to_vec();
+        // Gold along every edge, untouched in the middle
+        for (x, y) in [(0, 0), (39, 15), (20, 29), (2, 15)] {
+            assert!(at(x, y)[0] > 150, "({x}, {y})");
+        }
+        assert_eq!(at(20, 15), vec![0, 0, 0]);
+
+        // Frames smaller than the border don't panic
+        let mut tiny = vec![0u8; 4 * 4 * 4];
+        draw_drop_border(&mut tiny, 4, 4);
+    }
+
+    #[test]
+    fn test_draw_histogram() {
+        let (w, h) = HISTOGRAM_SIZE;
+        let mut frame = vec![255u8; w * h * 4];
+        let mut red = [0u32; 256];
+        let mut blue = [0u32; 256];
+        red[10] = 4;
+        blue[10] = 2;
+        blue[255] = 1000; // Clipped: off the scale
+        draw_histogram(
+            &mut frame,
+            w,
+            h,
+            0,
+            0,
+            &[(red, [255, 0, 0]), (blue, [0, 0, 255])],
+        );
+
+        let at = |x: usize, y: usize| {
+            let idx = ((y + PANEL_PADDING) * w + x + PANEL_PADDING) * 4;
+            frame[idx..idx + 3].to_vec()
+        };
+        let bottom = HISTOGRAM_HEIGHT - 1;
+        // Both bars overlap at the bottom, only red reaches the top
+        assert!(at(10, bottom)[0] > 150 && at(10, bottom)[2] > 150);
+        assert!(at(10, 0)[0] > 150 && at(10, 0)[2] < 100);
+        // Empty bins just darken
+        assert!(at(11, bottom).iter().all(|&c| c < 100));
+        // The clipped bin fills the plot
+        assert!(at(255, 0)[2] > 150);
+    }
+
+    #[test]
+    fn test_window_rect_to_image() {
+        // 4x2 image fit into 8x8: scale 2, rows 2..6 of the window
+        let img = ImageData::new(vec![0; 4 * 2 * 4], 4, 2, QualityTier::Full);
+        let fit = ViewTransform::FIT;
+        let rect = |x, y, width, height| PixelRect {
+            x,
+            y,
+            width,
+            height,
+        };
+        assert_eq!(
+            window_rect_to_image(&img, 8, 8, &fit, (2.0, 2.0), (6.0, 4.0)),
+            Some(rect(1, 0, 2, 1))
+        );
+        // Dragged the other way, and past the edges: clamped to the image
+        assert_eq!(
+            window_rect_to_image(&img, 8, 8, &fit, (9.0, 9.0), (-3.0, 3.9)),
+            Some(rect(0, 1, 4, 1))
+        );
+        // Entirely in the letterbox
+        assert_eq!(
+            window_rect_to_image(&img, 8, 8, &fit, (0.0, 0.0), (8.0, 1.0)),
+            None
+        );
+
+        // Rotated clockwise the window's top-left quarter of the image is
+        // the source's bottom-left
+        let rotated = ViewTransform {
+            orientation: Orientation::IDENTITY.rotate_cw(),
+            ..fit
+        };
+        assert_eq!(
+            window_rect_to_image(&img, 8, 8, &rotated, (2.0, 0.0), (4.0, 4.0)),
+            Some(rect(0, 1, 2, 1))
+        );
+
+        // A half-size preview reports the original's pixels
+        let mut preview = ImageData::new(vec![0; 4 * 2 * 4], 4, 2, QualityTier::Preview);
+        (preview.full_width, preview.full_height) = (8, 4);
+        assert_eq!(
+            window_rect_to_image(&preview, 8, 8, &fit, (2.0, 2.0), (6.0, 4.0)),
+            Some(rect(2, 0, 4, 2))
+        );
+    }
+
+    #[test]
+    fn test_rescale_rect() {
+        let rect = PixelRect {
+            x: 10,
+            y: 0,
+            width: 5,
+            height: 99,
+        };
+        let half = rect.rescale((100, 100), (50, 50));
+        assert_eq!((half.x, half.y, half.width, half.height), (5, 0, 2, 49));
+        // Never empty, never past the edge
+        let tiny = rect.rescale((100, 100), (2, 2));
+        assert_eq!((tiny.x, tiny.y, tiny.width, tiny.height), (0, 0, 1, 1));
+    }
 }
@@ -0,0 +1,393 @@
+//! Session memory - where browsing each directory was left off, and where
+//! the window was.
+//!
+//! `$XDG_STATE_HOME/fiv/session.json` maps canonical directory paths to the
+//! image last shown there, so reopening a directory resumes at that image,
+//! and holds the last window's size, position and state. Only the
+//! `MAX_ENTRIES` most recently closed directories are kept. The file is
+//! read again just before writing, so several viewers closing one after
+//! another all keep their entries.
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Directories remembered at most; the least recently closed go first
+const MAX_ENTRIES: usize = 300;
+
+/// Pixels of a restored window that must be on a monitor, each way, for
+/// it to be left where it was
+const MIN_VISIBLE: i64 = 64;
+
+/// Where a directory was left off
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LastViewed {
+    /// Canonical path of the directory
+    pub dir: PathBuf,
+    /// The image shown, relative to the directory
+    pub file: PathBuf,
+    /// Its position in the directory, for when the file has gone since
+    pub index: usize,
+}
+
+/// Where the window was and how big, to open the next one the same way
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct WindowGeometry {
+    /// Size in logical pixels, when neither maximized nor fullscreen
+    pub size: (u32, u32),
+    /// Top-left corner of the window frame in physical pixels, where the
+    /// platform tells it
+    pub position: Option<(i32, i32)>,
+    #[serde(default)]
+    pub maximized: bool,
+    #[serde(default)]
+    pub fullscreen: bool,
+}
+
+/// A monitor's area in physical pixels
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Screen {
+    pub position: (i32, i32),
+    pub size: (u32, u32),
+    pub scale: f64,
+}
+
+impl WindowGeometry {
+    /// The geometry moved and shrunk as needed to show on `screens`, the
+    /// primary one first: a window all but off every monitor, say after one
+    /// was unplugged, goes back on the primary one
+    pub fn clamped(mut self, screens: &[Screen]) -> Self {
+        let Some(&primary) = screens.first() else {
+            return self;
+        };
+        let screen = screens
+            .iter()
+            .copied()
+            .find(|screen| self.shows_on(screen))
+            .unwrap_or(primary);
+
+        let (max_w, max_h) = (
+            (screen.size.0 as f64 / screen.scale) as u32,
+            (screen.size.1 as f64 / screen.scale) as u32,
+        );
+        self.size = (self.size.0.min(max_w).max(1), self.size.1.min(max_h).max(1));
+        if let Some((x, y)) = self.position.filter(|_| !self.shows_on(&screen)) {
+            let (w, h) = physical(self.size, screen.scale);
+            let clamp = |at: i32, len: i64, start: i32, screen_len: u32| {
+                let end = (start as i64 + screen_len as i64 - len).max(start as i64);
+                (at as i64).clamp(start as i64, end) as i32
+            };
+            self.position = Some((
+                clamp(x, w, screen.position.0, screen.size.0),
+                clamp(y, h, screen.position.1, screen.size.1),
+            ));
+        }
+        self
+    }
+
+    /// Whether enough of the window would be on `screen` to reach it
+    fn shows_on(&self, screen: &Screen) -> bool {
+        let Some((x, y)) = self.position else {
+            return false;
+        };
+        let (w, h) = physical(self.size, screen.scale);
+        let overlap = |start: i32, len: i64, screen_start: i32, screen_len: u32| {
+            (start as i64 + len).min(screen_start as i64 + screen_len as i64)
+                - (start as i64).max(screen_start as i64)
+        };
+        overlap(x, w, screen.position.0, screen.size.0) >= MIN_VISIBLE
+            && overlap(y, h, screen.position.1, screen.size.1) >= MIN_VISIBLE
+    }
+}
+
+/// A logical size in physical pixels at `scale`
+fn physical(size: (u32, u32), scale: f64) -> (i64, i64) {
+    (
+        (size.0 as f64 * scale).round() as i64,
+        (size.1 as f64 * scale).round() as i64,
+    )
+}
+
+/// `WxH` or `WxH+X+Y`, as for X11 programs; offsets are the coordinates
+/// of the top-left corner, negative ones written `+-X`
+impl FromStr for WindowGeometry {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid geometry '{}': expected WxH or WxH+X+Y", text);
+        let (size, offsets) = match text.find('+') {
+            Some(at) => (&text[..at], Some(&text[at + 1..])),
+            None => (text, None),
+        };
+        let (w, h) = size.split_once('x').ok_or_else(invalid)?;
+        let size = (
+            w.parse::<u32>().map_err(|_| invalid())?,
+            h.parse::<u32>().map_err(|_| invalid())?,
+        );
+        if size.0 == 0 || size.1 == 0 {
+            return Err(invalid());
+        }
+        let position = match offsets {
+            Some(offsets) => {
+                let (x, y) = offsets.split_once('+').ok_or_else(invalid)?;
+                Some((
+                    x.parse().map_err(|_| invalid())?,
+                    y.parse().map_err(|_| invalid())?,
+                ))
+            }
+            None => None,
+        };
+        Ok(Self {
+            size,
+            position,
+            maximized: false,
+            fullscreen: false,
+        })
+    }
+}
+
+/// Remembered directories, least recently closed first, and the window
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Session {
+    entries: Vec<LastViewed>,
+    #[serde(default)]
+    window: Option<WindowGeometry>,
+}
+
+impl Session {
+    /// Default location: `$XDG_STATE_HOME/fiv/session.json`, falling back
+    /// to `~/.local/state/fiv/session.json`
+    pub fn default_path() -> Option<PathBuf> {
+        let base = std::env::var_os("XDG_STATE_HOME")
+            .filter(|v| !v.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| {
+                std::env::var_os("HOME").map(|home| Path::new(&home).join(".local").join("state"))
+            })?;
+        Some(base.join("fiv").join("session.json"))
+    }
+
+    /// Read the session file. A missing file is an empty session; an
+    /// unreadable one is reported and treated the same.
+    pub fn load(path: &Path) -> Self {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Self::default(),
+            Err(e) => {
+                eprintln!("Warning: cannot read {}: {}", path.display(), e);
+                return Self::default();
+            }
+        };
+        serde_json::from_str(&text).unwrap_or_else(|e| {
+            eprintln!("Warning: ignoring {}: {}", path.display(), e);
+            Self::default()
+        })
+    }
+
+    /// Write the session file, replacing it in one step
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let dir = path.parent().unwrap_or(Path::new("."));
+        fs::create_dir_all(dir).map_err(|e| format!("cannot create {}: {}", dir.display(), e))?;
+        let text = serde_json::to_string(self).map_err(|e| e.to_string())?;
+        let temp = path.with_extension(format!("{}.tmp", std::process::id()));
+        fs::write(&temp, text)
+            .and_then(|()| fs::rename(&temp, path))
+            .map_err(|e| {
+                let _ = fs::remove_file(&temp);
+                format!("cannot write {}: {}", path.display(), e)
+            })
+    }
+
+    /// Where `dir` was left off, if it's remembered
+    pub fn get(&self, dir: &Path) -> Option<&LastViewed> {
+        self.entries.iter().find(|entry| entry.dir == dir)
+    }
+
+    /// Remember where a directory was left off, as the most recent entry
+    pub fn record(&mut self, last: LastViewed) {
+        self.entries.retain(|entry| entry.dir != last.dir);
+        self.entries.push(last);
+        let excess = self.entries.len().saturating_sub(MAX_ENTRIES);
+        self.entries.drain(..excess);
+    }
+
+    /// Where the last window closed was
+    pub fn window(&self) -> Option<WindowGeometry> {
+        self.window
+    }
+
+    /// Remember where the window was, for the next one
+    pub fn set_window(&mut self, window: WindowGeometry) {
+        self.window = Some(window);
+    }
+}
+
+/// Position to resume at in `paths`, the images of `last.dir`: the
+/// remembered file, or if it's gone, the position it had
+pub fn resume_index(paths: &[PathBuf], last: &LastViewed) -> usize {
+    let file = last.dir.join(&last.file);
+    paths
+        .iter()
+        .position(|path| *path == file)
+        .unwrap_or_else(|| last.index.min(paths.len().saturating_sub(1)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn last(dir: &str, file: &str, index: usize) -> LastViewed {
+        LastViewed {
+            dir: PathBuf::from(dir),
+            file: PathBuf::from(file),
+            index,
+        }
+    }
+
+    fn geometry(size: (u32, u32), position: Option<(i32, i32)>) -> WindowGeometry {
+        WindowGeometry {
+            size,
+            position,
+            maximized: false,
+            fullscreen: false,
+        }
+    }
+
+    #[test]
+    fn test_record_prunes_least_recent() {
+        let mut session = Session::default();
+        for n in 0..MAX_ENTRIES + 5 {
+            session.record(last(&format!("/photos/{}", n), "a.jpg", 0));
+        }
+        assert_eq!(session.entries.len(), MAX_ENTRIES);
+        assert!(session.get(Path::new("/photos/4")).is_none());
+        assert!(session.get(Path::new("/photos/5")).is_some());
+
+        // Recording again refreshes an entry rather than duplicating it
+        session.record(last("/photos/5", "b.jpg", 1));
+        assert_eq!(session.entries.len(), MAX_ENTRIES);
+        assert_eq!(session.get(Path::new("/photos/5")).unwrap().index, 1);
+        session.record(last("/photos/new", "a.jpg", 0));
+        assert!(session.get(Path::new("/photos/5")).is_some());
+        assert!(session.get(Path::new("/photos/6")).is_none());
+    }
+
+    #[test]
+    fn test_resume_index() {
+        let paths: Vec<PathBuf> = ["a.jpg", "b.jpg", "d.jpg"]
+            .iter()
+            .map(|name| Path::new("/photos").join(name))
+            .collect();
+        assert_eq!(resume_index(&paths, &last("/photos", "d.jpg", 7)), 2);
+        // A deleted file resumes at its old position, within the list
+        assert_eq!(resume_index(&paths, &last("/photos", "c.jpg", 2)), 2);
+        assert_eq!(resume_index(&paths, &last("/photos", "z.jpg", 9)), 2);
+        assert_eq!(resume_index(&[], &last("/photos", "z.jpg", 9)), 0);
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let root = std::env::temp_dir().join(format!("fiv_session_{}", std::process::id()));
+        let path = root.join("state/session.json");
+        assert!(Session::load(&path).entries.is_empty());
+
+        let mut session = Session::default();
+        session.record(last("/photos", "sub/a.jpg", 3));
+        session.save(&path).unwrap();
+        let loaded = Session::load(&path);
+        assert_eq!(
+            loaded.get(Path::new("/photos")),
+            Some(&last("/photos", "sub/a.jpg", 3))
+        );
+        assert_eq!(loaded.window(), None);
+
+        let mut session = loaded;
+        let window = WindowGeometry {
+            maximized: true,
+            ..geometry((1600, 900), Some((-20, 40)))
+        };
+        session.set_window(window);
+        session.save(&path).unwrap();
+        assert_eq!(Session::load(&path).window(), Some(window));
+
+        // Files from before windows were remembered still load
+        fs::write(
+            &path,
+            r#"{"entries":[{"dir":"/p","file":"a.jpg","index":1,"window":[800,600]}]}"#,
+        )
+        .unwrap();
+        let old = Session::load(&path);
+        assert_eq!(old.get(Path::new("/p")).unwrap().index, 1);
+        assert_eq!(old.window(), None);
+
+        // A corrupt file is ignored
+        fs::write(&path, "{").unwrap();
+        assert!(Session::load(&path).entries.is_empty());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_parse_geometry() {
+        assert_eq!("800x600".parse(), Ok(geometry((800, 600), None)));
+        assert_eq!(
+            "1024x768+10+-20".parse(),
+            Ok(geometry((1024, 768), Some((10, -20))))
+        );
+        for bad in [
+            "",
+            "800",
+            "800x",
+            "0x600",
+            "800x600+10",
+            "800x600+a+b",
+            "wxh",
+        ] {
+            assert!(bad.parse::<WindowGeometry>().is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_clamp_geometry() {
+        let screens = [
+            Screen {
+                position: (0, 0),
+                size: (1920, 1080),
+                scale: 1.0,
+            },
+            Screen {
+                position: (1920, 0),
+                size: (2560, 1440),
+                scale: 2.0,
+            },
+        ];
+        // On a monitor, or mostly off one: left alone
+        let on = geometry((800, 600), Some((2000, 100)));
+        assert_eq!(on.clamped(&screens), on);
+        let partly = geometry((800, 600), Some((-700, 100)));
+        assert_eq!(partly.clamped(&screens), partly);
+        // No monitors known: left alone
+        let gone = geometry((800, 600), Some((5000, 3000)));
+        assert_eq!(gone.clamped(&[]), gone);
+
+        // Off every monitor: back onto the primary one
+        assert_eq!(
+            gone.clamped(&screens),
+            geometry((800, 600), Some((1120, 480)))
+        );
+        assert_eq!(
+            geometry((800, 600), Some((-900, -700))).clamped(&screens),
+            geometry((800, 600), Some((0, 0)))
+        );
+        // Bigger than the monitor: shrunk to it, in logical pixels
+        assert_eq!(
+            geometry((3000, 2000), Some((2000, 0))).clamped(&screens),
+            geometry((1280, 720), Some((2000, 0)))
+        );
+        assert_eq!(
+            geometry((3000, 2000), None).clamped(&screens),
+            geometry((1920, 1080), None)
+        );
+    }
+}
@@ -7,11 +7,15 @@
 //!
 //! Key invariant: reads never block, writes are atomic swaps.
 
-use crate::config::QualityTier;
-use std::path::PathBuf;
-use std::ptr;
-use std::sync::atomic::{AtomicPtr, AtomicU64, Ordering};
-use std::sync::Arc;
+use crate::config::{QualityTier, TierSizes};
+use crate::decode::{read_image_info, DecodeError, ImageInfo};
+use crate::xmp::read_rating;
+use arc_swap::ArcSwapOption;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
 
 /// Decoded image data ready for display.
 /// This is the "raw data" that the viewer renders from.
@@ -25,6 +29,22 @@ pub struct ImageData {
     pub height: u32,
     /// Quality tier this was decoded at
     pub quality: QualityTier,
+    /// Width of the original image (before tier downscaling)
+    pub full_width: u32,
+    /// Height of the original image (before tier downscaling)
+    pub full_height: u32,
+    /// Frames after the first, for animations (same size as `pixels`)
+    pub extra_frames: Vec<Vec<u8>>,
+    /// Display time of each frame; empty for still images
+    pub delays: Vec<Duration>,
+    /// Decoded from a RAW file's embedded JPEG rather than the sensor data
+    pub embedded_preview: bool,
+    /// Decoded smaller than its tier calls for, as that wouldn't fit the
+    /// memory budget
+    pub over_budget: bool,
+    /// Decoded from the scans of a progressive file read so far: coarse,
+    /// and replaced by the complete decode
+    pub partial: bool,
 }
 
 impl ImageData {
@@ -34,29 +54,306 @@ impl ImageData {
             width,
             height,
             quality,
+            full_width: width,
+            full_height: height,
+            extra_frames: Vec::new(),
+            delays: Vec::new(),
+            embedded_preview: false,
+            over_budget: false,
+            partial: false,
         }
     }
 
-    /// Memory size in bytes
+    /// Mark this data as a RAW file's embedded preview
+    pub fn with_embedded_preview(mut self) -> Self {
+        self.embedded_preview = true;
+        self
+    }
+
+    /// Mark this data as reduced to fit the memory budget
+    pub fn with_over_budget(mut self) -> Self {
+        self.over_budget = true;
+        self
+    }
+
+    /// Mark this data as a coarse pass over a file still being read
+    pub fn with_partial(mut self) -> Self {
+        self.partial = true;
+        self
+    }
+
+    /// Quality for upgrade decisions: an embedded preview at preview tier
+    /// or above, an image reduced to fit the budget, or one already whole
+    /// at display fit, is the best this file can give, so nothing waits on
+    /// more. A coarse pass counts for no more than a thumbnail.
+    #[inline]
+    pub fn effective_quality(&self) -> QualityTier {
+        let whole = (self.width, self.height) == (self.full_width, self.full_height);
+        if self.partial {
+            QualityTier::Thumbnail
+        } else if (self.embedded_preview && self.quality >= QualityTier::Preview)
+            || self.over_budget
+            || (matches!(self.quality, QualityTier::DisplayFit(_)) && whole)
+        {
+            QualityTier::Full
+        } else {
+            self.quality
+        }
+    }
+
+    /// Attach the remaining frames of an animation. `delays` has one entry
+    /// per frame, including the first.
+    pub fn with_animation(mut self, extra_frames: Vec<Vec<u8>>, delays: Vec<Duration>) -> Self {
+        debug_assert_eq!(extra_frames.len() + 1, delays.len());
+        self.extra_frames = extra_frames;
+        self.delays = delays;
+        self
+    }
+
+    /// Whether this image has more than one frame
+    #[inline]
+    pub fn is_animated(&self) -> bool {
+        !self.extra_frames.is_empty()
+    }
+
+    /// Number of frames (1 for still images)
+    #[inline]
+    pub fn frame_count(&self) -> usize {
+        1 + self.extra_frames.len()
+    }
+
+    /// Pixels of a frame; the index wraps around the frame count
+    #[inline]
+    pub fn frame(&self, index: usize) -> &[u8] {
+        match index % self.frame_count() {
+            0 => &self.pixels,
+            i => &self.extra_frames[i - 1],
+        }
+    }
+
+    /// Record the original dimensions this data was downscaled from
+    pub fn with_full_size(mut self, full_width: u32, full_height: u32) -> Self {
+        self.full_width = full_width;
+        self.full_height = full_height;
+        self
+    }
+
+    /// Memory size in bytes, counting every animation frame
     #[inline]
     pub fn memory_size(&self) -> usize {
-        self.pixels.len()
+        self.pixels.len() + self.extra_frames.iter().map(Vec::len).sum::<usize>()
     }
 }
 
-/// Immutable metadata about an image.
-#[derive(Debug, Clone)]
+/// Where an image's bytes are stored
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImageSource {
+    /// A file of its own
+    File(PathBuf),
+    /// A member of a zip archive
+    ArchiveEntry { archive: PathBuf, name: String },
+}
+
+impl std::fmt::Display for ImageSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImageSource::File(path) => write!(f, "{}", path.display()),
+            ImageSource::ArchiveEntry { archive, name } => {
+                write!(f, "{}:{}", archive.display(), name)
+            }
+        }
+    }
+}
+
+impl ImageSource {
+    /// The source as it displays, keeping paths that aren't UTF-8 intact
+    pub fn to_os_string(&self) -> OsString {
+        match self {
+            ImageSource::File(path) => path.clone().into_os_string(),
+            ImageSource::ArchiveEntry { archive, name } => {
+                let mut source = archive.as_os_str().to_owned();
+                source.push(":");
+                source.push(name);
+                source
+            }
+        }
+    }
+}
+
+/// Metadata about an image. The source is fixed; details are filled in as
+/// they are discovered.
+#[derive(Debug)]
 pub struct ImageMeta {
-    /// Path to the image file
-    pub path: PathBuf,
+    /// Where the image is read from
+    pub source: ImageSource,
+    /// Page within a multi-page file (None for single-image files)
+    pub page: Option<Page>,
+    /// File and EXIF details, read on first use
+    info: OnceLock<ImageInfo>,
+    /// Full dimensions packed as `width << 32 | height`, 0 until known.
+    /// Set from the header by the preloader, then from decoded data.
+    dimensions: AtomicU64,
+    /// Name of the embedded color profile the last decode converted from
+    color_profile: ArcSwapOption<String>,
+    /// Star rating from the XMP sidecar, `RATING_UNREAD` until first asked
+    rating: AtomicU8,
+    /// Perceptual hash of the thumbnail, valid once `has_phash` is set
+    phash: AtomicU64,
+    has_phash: AtomicBool,
+}
+
+/// `ImageMeta::rating` before the sidecar has been read
+const RATING_UNREAD: u8 = u8::MAX;
+
+impl Clone for ImageMeta {
+    fn clone(&self) -> Self {
+        Self {
+            source: self.source.clone(),
+            page: self.page,
+            info: self.info.clone(),
+            dimensions: AtomicU64::new(self.dimensions.load(Ordering::Relaxed)),
+            color_profile: ArcSwapOption::new(self.color_profile.load_full()),
+            rating: AtomicU8::new(self.rating.load(Ordering::Relaxed)),
+            phash: AtomicU64::new(self.phash.load(Ordering::Relaxed)),
+            has_phash: AtomicBool::new(self.has_phash.load(Ordering::Acquire)),
+        }
+    }
 }
 
 impl ImageMeta {
     pub fn new(path: PathBuf) -> Self {
-        Self { path }
+        Self::from_source(ImageSource::File(path))
+    }
+
+    /// Metadata for an image stored inside a zip archive
+    pub fn archive_entry(archive: PathBuf, name: String) -> Self {
+        Self::from_source(ImageSource::ArchiveEntry { archive, name })
+    }
+
+    fn from_source(source: ImageSource) -> Self {
+        Self {
+            source,
+            page: None,
+            info: OnceLock::new(),
+            dimensions: AtomicU64::new(0),
+            color_profile: ArcSwapOption::empty(),
+            rating: AtomicU8::new(RATING_UNREAD),
+            phash: AtomicU64::new(0),
+            has_phash: AtomicBool::new(false),
+        }
+    }
+
+    /// The file on disk holding the image (the archive, for an entry)
+    pub fn path(&self) -> &Path {
+        match &self.source {
+            ImageSource::File(path) => path,
+            ImageSource::ArchiveEntry { archive, .. } => archive,
+        }
+    }
+
+    /// The image's own name, whose extension names its format
+    pub fn name(&self) -> &Path {
+        match &self.source {
+            ImageSource::File(path) => path,
+            ImageSource::ArchiveEntry { name, .. } => Path::new(name),
+        }
+    }
+
+    /// Full image dimensions, if known yet
+    pub fn dimensions(&self) -> Option<(u32, u32)> {
+        let packed = self.dimensions.load(Ordering::Relaxed);
+        (packed != 0).then_some(((packed >> 32) as u32, packed as u32))
+    }
+
+    /// Record the full image dimensions
+    pub fn set_dimensions(&self, width: u32, height: u32) {
+        let packed = (width as u64) << 32 | height as u64;
+        self.dimensions.store(packed, Ordering::Relaxed);
+    }
+
+    /// Name of the color profile the image was converted from, if it had
+    /// one that needed converting
+    pub fn color_profile(&self) -> Option<Arc<String>> {
+        self.color_profile.load_full()
+    }
+
+    /// Record the color profile found while decoding
+    pub fn set_color_profile(&self, name: Option<String>) {
+        self.color_profile.store(name.map(Arc::new));
+    }
+
+    /// Star rating, 0 for none (reads the XMP sidecar on first call).
+    /// Images inside archives have no sidecar.
+    pub fn rating(&self) -> u8 {
+        let rating = self.rating.load(Ordering::Relaxed);
+        if rating != RATING_UNREAD {
+            return rating;
+        }
+        let rating = match &self.source {
+            ImageSource::File(path) => read_rating(path),
+            ImageSource::ArchiveEntry { .. } => 0,
+        };
+        self.rating.store(rating, Ordering::Relaxed);
+        rating
+    }
+
+    /// Record the rating just saved to the sidecar
+    pub fn set_rating(&self, rating: u8) {
+        self.rating.store(rating, Ordering::Relaxed);
+    }
+
+    /// Perceptual hash of the image, if computed yet
+    pub fn phash(&self) -> Option<u64> {
+        self.has_phash
+            .load(Ordering::Acquire)
+            .then(|| self.phash.load(Ordering::Relaxed))
+    }
+
+    /// Record the perceptual hash
+    pub fn set_phash(&self, hash: u64) {
+        self.phash.store(hash, Ordering::Relaxed);
+        self.has_phash.store(true, Ordering::Release);
+    }
+
+    /// Forget the dimensions and hash, e.g. when the file has changed
+    pub fn forget_contents(&self) {
+        self.dimensions.store(0, Ordering::Relaxed);
+        self.has_phash.store(false, Ordering::Release);
+    }
+
+    /// Bytes the image would take decoded at a tier, if its size is known
+    pub fn memory_for_tier(&self, quality: QualityTier, sizes: TierSizes) -> Option<usize> {
+        let (width, height) = self.dimensions()?;
+        let (width, height) = quality.target_dimensions(width, height, sizes);
+        Some(width as usize * height as usize * 4)
+    }
+
+    /// Bytes the image would take decoded at full size, if its size is known
+    pub fn full_memory_estimate(&self, sizes: TierSizes) -> Option<usize> {
+        self.memory_for_tier(QualityTier::Full, sizes)
+    }
+
+    /// Metadata for one page of a multi-page file
+    pub fn with_page(mut self, index: usize, count: usize) -> Self {
+        self.page = Some(Page { index, count });
+        self
+    }
+
+    /// File size and EXIF details (reads the file header on first call)
+    pub fn info(&self) -> &ImageInfo {
+        self.info.get_or_init(|| read_image_info(&self.source))
     }
 }
 
+/// Position of a store entry within a multi-page file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Page {
+    /// Zero-based page index
+    pub index: usize,
+    /// Total pages in the file
+    pub count: usize,
+}
+
 /// A lock-free slot holding image data.
 ///
 /// The slot can be in one of three states:
@@ -64,12 +361,20 @@ impl ImageMeta {
 /// - Loading: data is being decoded (optional intermediate state)
 /// - Ready: data is available for rendering
 ///
+/// Ready slots hold up to two buffers: a small thumbnail that stays
+/// resident, and the best higher tier decoded so far. Eviction only drops
+/// the higher tier, so a slot that was ever loaded always has something
+/// to show.
+///
 /// The main thread reads via `read()` which never blocks.
 /// Background threads write via `upgrade()` which atomically swaps in new data.
 pub struct ImageSlot {
-    /// Pointer to current image data (null if empty)
-    /// Uses raw pointer for lock-free atomic operations
-    data_ptr: AtomicPtr<ImageData>,
+    /// Best data above thumbnail tier (None if none). Swapped atomically;
+    /// readers never block and a reader's Arc keeps replaced data alive.
+    data: ArcSwapOption<ImageData>,
+
+    /// The resident thumbnail (None if none)
+    thumbnail: ArcSwapOption<ImageData>,
 
     /// Metadata about this image (immutable after creation)
     pub meta: ImageMeta,
@@ -77,48 +382,128 @@ pub struct ImageSlot {
     /// Generation counter - incremented on each update
     /// Used by preloader to detect stale work
     generation: AtomicU64,
+
+    /// Set if decoding failed; failed slots are not retried until reloaded
+    error: Mutex<Option<Arc<DecodeError>>>,
+
+    /// Bumped by `reset`, so decodes of the old file can be told apart
+    reloads: AtomicU64,
+
+    /// Distance at which memory pressure last demoted this slot to its
+    /// thumbnail (`usize::MAX` if it wasn't)
+    demoted_at: AtomicUsize,
+
+    /// When `read()` last returned data, as milliseconds since startup
+    /// plus one (0 = never)
+    last_read: AtomicU64,
+}
+
+/// Milliseconds since the first call, for cheap atomic timestamps
+pub fn now_millis() -> u64 {
+    static START: OnceLock<Instant> = OnceLock::new();
+    START.get_or_init(Instant::now).elapsed().as_millis() as u64
 }
 
 impl ImageSlot {
     /// Create a new empty slot with metadata
     pub fn new(meta: ImageMeta) -> Self {
         Self {
-            data_ptr: AtomicPtr::new(ptr::null_mut()),
+            data: ArcSwapOption::empty(),
+            thumbnail: ArcSwapOption::empty(),
             meta,
             generation: AtomicU64::new(0),
+            error: Mutex::new(None),
+            reloads: AtomicU64::new(0),
+            demoted_at: AtomicUsize::new(usize::MAX),
+            last_read: AtomicU64::new(0),
         }
     }
 
-    /// Read current image data (lock-free).
+    /// Record that eviction demoted this slot at the given distance
+    pub fn mark_demoted(&self, distance: usize) {
+        self.demoted_at.store(distance, Ordering::Relaxed);
+    }
+
+    /// Distance at which this slot was demoted, until it is upgraded again
+    #[inline]
+    pub fn demoted_at(&self) -> Option<usize> {
+        let distance = self.demoted_at.load(Ordering::Relaxed);
+        (distance != usize::MAX).then_some(distance)
+    }
+
+    /// Record that this image cannot be decoded (the first error is kept)
+    pub fn fail(&self, error: DecodeError) {
+        self.error
+            .lock()
+            .unwrap()
+            .get_or_insert_with(|| Arc::new(error));
+    }
+
+    /// Why decoding failed, if it did
+    #[inline]
+    pub fn error(&self) -> Option<Arc<DecodeError>> {
+        self.error.lock().unwrap().clone()
+    }
+
+    /// How many times the slot has been reset for a reload
+    #[inline]
+    pub fn reloads(&self) -> u64 {
+        self.reloads.load(Ordering::Acquire)
+    }
+
+    /// Forget everything decoded from the file, errors, dimensions and
+    /// hash included, so it is loaded afresh. Returns the memory released.
+    pub fn reset(&self) -> usize {
+        self.reloads.fetch_add(1, Ordering::AcqRel);
+        *self.error.lock().unwrap() = None;
+        self.demoted_at.store(usize::MAX, Ordering::Relaxed);
+        self.meta.forget_contents();
+        self.clear_all()
+    }
+
+    /// Read the best image data available (lock-free).
     ///
     /// Returns None if no data is loaded yet.
     /// The returned Arc keeps the data alive even if the slot is upgraded.
     #[inline]
     pub fn read(&self) -> Option<Arc<ImageData>> {
-        let ptr = self.data_ptr.load(Ordering::Acquire);
-        if ptr.is_null() {
-            return None;
-        }
+        let data = self.peek()?;
+        self.last_read.store(now_millis() + 1, Ordering::Relaxed);
+        Some(data)
+    }
+
+    /// Like `read`, but not counted as a view (for background work)
+    #[inline]
+    pub fn peek(&self) -> Option<Arc<ImageData>> {
+        self.data.load_full().or_else(|| self.thumbnail.load_full())
+    }
 
-        // SAFETY: If ptr is non-null, it points to a valid Arc allocation.
-        // We increment the refcount by cloning, so the data stays alive.
-        // The original Arc in the slot also keeps it alive.
-        unsafe {
-            // Reconstruct Arc without taking ownership (just increment refcount)
-            Arc::increment_strong_count(ptr);
-            Some(Arc::from_raw(ptr))
+    /// When data was last read for display, as `now_millis()` time
+    #[inline]
+    pub fn last_read(&self) -> Option<u64> {
+        match self.last_read.load(Ordering::Relaxed) {
+            0 => None,
+            stamp => Some(stamp - 1),
         }
     }
 
+    /// Read the resident thumbnail, if one is loaded
+    #[inline]
+    pub fn thumbnail(&self) -> Option<Arc<ImageData>> {
+        self.thumbnail.load_full()
+    }
+
     /// Check current quality tier without cloning the data
     #[inline]
     pub fn current_quality(&self) -> Option<QualityTier> {
-        let ptr = self.data_ptr.load(Ordering::Acquire);
-        if ptr.is_null() {
-            return None;
+        match &*self.data.load() {
+            Some(data) => Some(data.effective_quality()),
+            None => self
+                .thumbnail
+                .load()
+                .as_ref()
+                .map(|t| t.effective_quality()),
         }
-        // SAFETY: ptr is valid if non-null
-        unsafe { Some((*ptr).quality) }
     }
 
     /// Check if this slot has data at or above the given quality
@@ -132,93 +517,121 @@ impl ImageSlot {
     /// Check if slot is empty
     #[inline]
     pub fn is_empty(&self) -> bool {
-        self.data_ptr.load(Ordering::Acquire).is_null()
+        self.data.load().is_none() && self.thumbnail.load().is_none()
+    }
+
+    /// Check if slot holds data above the resident thumbnail
+    #[inline]
+    pub fn has_detail(&self) -> bool {
+        self.data.load().is_some()
     }
 
     /// Upgrade the slot with new image data (lock-free).
     ///
     /// This atomically swaps in the new data. If there was previous data,
-    /// it will be dropped when all references to it are gone.
+    /// it will be dropped when all references to it are gone. Thumbnail
+    /// tier data becomes the resident thumbnail. A coarse pass only takes
+    /// the place of nothing, a thumbnail or an earlier pass.
     ///
     /// Returns true if the upgrade was performed (new quality > old quality).
     pub fn upgrade(&self, new_data: Arc<ImageData>) -> bool {
-        // Check if this is actually an upgrade
-        if let Some(current_quality) = self.current_quality() {
-            if new_data.quality <= current_quality {
-                // Not an upgrade, skip
-                return false;
-            }
-        }
+        self.try_upgrade(new_data).is_some()
+    }
 
-        // Convert Arc to raw pointer (transfers ownership to the pointer)
-        let new_ptr = Arc::into_raw(new_data) as *mut ImageData;
+    /// Like `upgrade`, but on success returns the memory of the data it
+    /// replaced, so the caller can account for exactly what changed.
+    pub fn try_upgrade(&self, new_data: Arc<ImageData>) -> Option<usize> {
+        let target = if new_data.quality == QualityTier::Thumbnail {
+            &self.thumbnail
+        } else {
+            &self.data
+        };
+
+        // Compare-and-swap so a concurrent write can't slip in between the
+        // quality check and the swap
+        let replaced = loop {
+            let current = target.load();
+
+            // Check if this is actually an upgrade
+            if let Some(current_quality) = self.current_quality() {
+                let improves = if new_data.partial {
+                    match current.as_ref() {
+                        Some(data) => data.partial,
+                        None => true,
+                    }
+                } else {
+                    new_data.effective_quality() > current_quality
+                };
+                if !improves {
+                    // Not an upgrade, skip
+                    return None;
+                }
+            }
 
-        // Atomically swap in the new pointer
-        let old_ptr = self.data_ptr.swap(new_ptr, Ordering::AcqRel);
+            let previous = target.compare_and_swap(&current, Some(Arc::clone(&new_data)));
+            if previous.as_ref().map(Arc::as_ptr) == current.as_ref().map(Arc::as_ptr) {
+                break previous.as_ref().map_or(0, |d| d.memory_size());
+            }
+        };
 
         // Increment generation to signal change
         self.generation.fetch_add(1, Ordering::Release);
+        self.demoted_at.store(usize::MAX, Ordering::Relaxed);
+        Some(replaced)
+    }
 
-        // Drop old data if it existed
-        if !old_ptr.is_null() {
-            // SAFETY: old_ptr was a valid Arc that we owned
-            unsafe {
-                drop(Arc::from_raw(old_ptr));
-            }
-        }
-
-        true
+    /// Counter bumped whenever the slot's data changes
+    #[inline]
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Acquire)
     }
 
-    /// Force-set new data regardless of quality (used for eviction/replacement)
-    pub fn set(&self, new_data: Option<Arc<ImageData>>) {
-        let new_ptr = new_data
-            .map(|d| Arc::into_raw(d) as *mut ImageData)
-            .unwrap_or(ptr::null_mut());
+    /// Force-set the data above the thumbnail regardless of quality
+    /// (used for eviction/replacement). Returns the memory of the data
+    /// it replaced.
+    pub fn set(&self, new_data: Option<Arc<ImageData>>) -> usize {
+        let old = self.data.swap(new_data);
+        self.generation.fetch_add(1, Ordering::Release);
+        old.map_or(0, |d| d.memory_size())
+    }
 
-        let old_ptr = self.data_ptr.swap(new_ptr, Ordering::AcqRel);
+    /// Replace the resident thumbnail, returning the memory it replaced
+    pub fn set_thumbnail(&self, thumbnail: Arc<ImageData>) -> usize {
+        let old = self.thumbnail.swap(Some(thumbnail));
         self.generation.fetch_add(1, Ordering::Release);
+        old.map_or(0, |t| t.memory_size())
+    }
 
-        if !old_ptr.is_null() {
-            unsafe {
-                drop(Arc::from_raw(old_ptr));
-            }
-        }
+    /// Release the data above the thumbnail, keeping the thumbnail.
+    /// Returns the memory released.
+    pub fn clear(&self) -> usize {
+        self.set(None)
     }
 
-    /// Clear the slot (release data)
-    pub fn clear(&self) {
-        self.set(None);
+    /// Release everything, thumbnail included. Returns the memory released.
+    pub fn clear_all(&self) -> usize {
+        let old = self.thumbnail.swap(None);
+        self.clear() + old.map_or(0, |t| t.memory_size())
     }
 
-    /// Estimate memory currently used by this slot
+    /// Estimate memory currently used by this slot, both buffers included
     pub fn memory_used(&self) -> usize {
-        let ptr = self.data_ptr.load(Ordering::Acquire);
-        if ptr.is_null() {
-            0
-        } else {
-            // SAFETY: ptr is valid if non-null
-            unsafe { (*ptr).memory_size() }
-        }
+        self.detail_memory() + self.thumbnail_memory()
     }
-}
 
-impl Drop for ImageSlot {
-    fn drop(&mut self) {
-        // Clean up any remaining data
-        let ptr = self.data_ptr.load(Ordering::Acquire);
-        if !ptr.is_null() {
-            unsafe {
-                drop(Arc::from_raw(ptr));
-            }
-        }
+    /// Memory used by the data above the thumbnail
+    pub fn detail_memory(&self) -> usize {
+        self.data.load().as_ref().map_or(0, |d| d.memory_size())
     }
-}
 
-// SAFETY: ImageSlot uses atomic operations for all mutable state.
-// The Arc<ImageData> is safely shared between threads.
-unsafe impl Send for ImageSlot {}
-unsafe impl Sync for ImageSlot {}
+    /// Memory used by the resident thumbnail
+    pub fn thumbnail_memory(&self) -> usize {
+        self.thumbnail
+            .load()
+            .as_ref()
+            .map_or(0, |t| t.memory_size())
+    }
+}
 
 #[cfg(test)]
 mod tests {
@@ -228,6 +641,178 @@ mod tests {
         Arc::new(ImageData::new(vec![0u8; 100], 10, 10, quality))
     }
 
+    #[test]
+    fn test_failure_is_recorded_once() {
+        let slot = ImageSlot::new(ImageMeta::new(PathBuf::from("test.jpg")));
+        assert!(slot.error().is_none());
+
+        slot.fail(DecodeError::TooLarge);
+        slot.fail(DecodeError::UnsupportedFormat);
+        assert!(matches!(
+            slot.error().as_deref(),
+            Some(DecodeError::TooLarge)
+        ));
+    }
+
+    #[test]
+    fn test_reset_forgets_file() {
+        let slot = ImageSlot::new(ImageMeta::new(PathBuf::from("test.jpg")));
+        slot.upgrade(make_test_data(QualityTier::Thumbnail));
+        slot.upgrade(make_test_data(QualityTier::Full));
+        slot.meta.set_dimensions(10, 10);
+        slot.meta.set_phash(7);
+        slot.fail(DecodeError::TooLarge);
+
+        assert_eq!(slot.reset(), 200);
+        assert!(slot.read().is_none());
+        assert!(slot.error().is_none());
+        assert_eq!(slot.meta.dimensions(), None);
+        assert_eq!(slot.meta.phash(), None);
+        assert_eq!(slot.reloads(), 1);
+    }
+
+    #[test]
+    fn test_embedded_preview_is_final() {
+        let meta = ImageMeta::new(PathBuf::from("test.nef"));
+        let slot = ImageSlot::new(meta);
+
+        // A small embedded preview is as good as this file gets
+        let data = ImageData::new(vec![0; 4], 1, 1, QualityTier::Preview).with_embedded_preview();
+        slot.upgrade(Arc::new(data));
+        assert!(slot.has_quality(QualityTier::Full));
+
+        // But a thumbnail of it can still be improved
+        let slot = ImageSlot::new(ImageMeta::new(PathBuf::from("test.nef")));
+        let data = ImageData::new(vec![0; 4], 1, 1, QualityTier::Thumbnail).with_embedded_preview();
+        slot.upgrade(Arc::new(data));
+        assert!(!slot.has_quality(QualityTier::Preview));
+    }
+
+    #[test]
+    fn test_over_budget_is_final() {
+        let slot = ImageSlot::new(ImageMeta::new(PathBuf::from("pano.png")));
+        let data = ImageData::new(vec![0; 4], 1, 1, QualityTier::Preview).with_over_budget();
+        slot.upgrade(Arc::new(data));
+        assert!(slot.has_quality(QualityTier::Full));
+    }
+
+    #[test]
+    fn test_partial_passes() {
+        let slot = ImageSlot::new(ImageMeta::new(PathBuf::from("big.jpg")));
+        slot.upgrade(make_test_data(QualityTier::Thumbnail));
+        let pass =
+            || Arc::new(ImageData::new(vec![0; 4], 1, 1, QualityTier::Preview).with_partial());
+
+        // Passes replace the thumbnail and each other, but count as no more
+        let generation = slot.generation();
+        assert!(slot.upgrade(pass()));
+        assert!(slot.upgrade(pass()));
+        assert_eq!(slot.generation(), generation + 2);
+        assert_eq!(slot.current_quality(), Some(QualityTier::Thumbnail));
+        assert!(slot.read().unwrap().partial);
+
+        // The complete decode takes over, and no pass replaces it
+        assert!(slot.upgrade(make_test_data(QualityTier::Preview)));
+        assert!(!slot.upgrade(pass()));
+        assert!(!slot.read().unwrap().partial);
+    }
+
+    #[test]
+    fn test_display_fit_upgrades() {
+        let fitted = |side: u32| {
+            let data = ImageData::new(vec![0; 4], side, side / 2, QualityTier::DisplayFit(side));
+            Arc::new(data.with_full_size(4000, 2000))
+        };
+        let slot = ImageSlot::new(ImageMeta::new(PathBuf::from("big.jpg")));
+        assert!(slot.upgrade(fitted(1600)));
+
+        // A larger window wants more, a smaller one has enough
+        assert!(!slot.has_quality(QualityTier::DisplayFit(2400)));
+        assert!(slot.has_quality(QualityTier::DisplayFit(1200)));
+        assert!(!slot.upgrade(fitted(1200)));
+        assert!(slot.upgrade(fitted(2400)));
+        assert!(!slot.has_quality(QualityTier::Full));
+
+        // Fitted whole, there is nothing more to decode
+        let small = ImageData::new(vec![0; 4], 800, 600, QualityTier::DisplayFit(1600));
+        assert_eq!(small.effective_quality(), QualityTier::Full);
+    }
+
+    #[test]
+    fn test_animation_frames() {
+        let data = ImageData::new(vec![1u8; 4], 1, 1, QualityTier::Full).with_animation(
+            vec![vec![2u8; 4], vec![3u8; 4]],
+            vec![Duration::from_millis(100); 3],
+        );
+
+        assert!(data.is_animated());
+        assert_eq!(data.frame_count(), 3);
+        assert_eq!(data.frame(1), &[2u8; 4]);
+        assert_eq!(data.frame(3), &[1u8; 4]); // Wraps
+        assert_eq!(data.memory_size(), 12); // All frames counted
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_source_keeps_raw_bytes() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let path = PathBuf::from(std::ffi::OsStr::from_bytes(b"/photos/caf\xe9.jpg"));
+        let file = ImageSource::File(path.clone());
+        assert_eq!(file.to_os_string(), path.as_os_str());
+        let entry = ImageSource::ArchiveEntry {
+            archive: path,
+            name: "01.png".to_string(),
+        };
+        assert_eq!(
+            entry.to_os_string().as_bytes(),
+            b"/photos/caf\xe9.jpg:01.png"
+        );
+    }
+
+    #[test]
+    fn test_meta_dimensions() {
+        let meta = ImageMeta::new(PathBuf::from("test.jpg"));
+        assert_eq!(meta.dimensions(), None);
+        let sizes = TierSizes::default();
+        assert_eq!(meta.full_memory_estimate(sizes), None);
+
+        meta.set_dimensions(4000, 3000);
+        assert_eq!(meta.dimensions(), Some((4000, 3000)));
+        assert_eq!(meta.full_memory_estimate(sizes), Some(4000 * 3000 * 4));
+        assert_eq!(
+            meta.memory_for_tier(QualityTier::Thumbnail, sizes),
+            Some(256 * 192 * 4)
+        );
+        assert_eq!(meta.clone().dimensions(), Some((4000, 3000)));
+    }
+
+    #[test]
+    fn test_meta_rating() {
+        // No sidecar, or none possible, is unrated
+        let meta = ImageMeta::new(PathBuf::from("/nonexistent/test.jpg"));
+        assert_eq!(meta.rating(), 0);
+        let entry = ImageMeta::archive_entry(PathBuf::from("a.zip"), "b.jpg".to_string());
+        assert_eq!(entry.rating(), 0);
+
+        meta.set_rating(4);
+        assert_eq!(meta.rating(), 4);
+        assert_eq!(meta.clone().rating(), 4);
+    }
+
+    #[test]
+    fn test_meta_phash() {
+        let meta = ImageMeta::new(PathBuf::from("test.jpg"));
+        assert_eq!(meta.phash(), None);
+        // Zero is a hash like any other
+        meta.set_phash(0);
+        assert_eq!(meta.phash(), Some(0));
+        meta.set_phash(u64::MAX);
+        assert_eq!(meta.clone().phash(), Some(u64::MAX));
+        meta.forget_contents();
+        assert_eq!(meta.phash(), None);
+    }
+
     #[test]
     fn test_empty_slot() {
         let meta = ImageMeta::new(PathBuf::from("test.jpg"));
@@ -259,6 +844,75 @@ mod tests {
         assert_eq!(slot.current_quality(), Some(QualityTier::Full)); // Still full
     }
 
+    #[test]
+    fn test_thumbnail_survives_clear() {
+        let slot = ImageSlot::new(ImageMeta::new(PathBuf::from("test.jpg")));
+
+        slot.upgrade(make_test_data(QualityTier::Thumbnail));
+        slot.upgrade(make_test_data(QualityTier::Full));
+        assert_eq!(slot.current_quality(), Some(QualityTier::Full));
+        assert_eq!(slot.memory_used(), 200);
+        assert_eq!(slot.detail_memory(), 100);
+
+        // Eviction drops back to the thumbnail instead of to nothing
+        slot.clear();
+        assert!(!slot.has_detail());
+        assert_eq!(slot.read().unwrap().quality, QualityTier::Thumbnail);
+        assert_eq!(slot.memory_used(), 100);
+
+        slot.clear_all();
+        assert!(slot.is_empty());
+    }
+
+    #[test]
+    fn test_concurrent_read_and_clear() {
+        use std::sync::atomic::AtomicBool;
+
+        let slot = Arc::new(ImageSlot::new(ImageMeta::new(PathBuf::from("test.jpg"))));
+        let done = Arc::new(AtomicBool::new(false));
+
+        let reader = {
+            let (slot, done) = (slot.clone(), done.clone());
+            std::thread::spawn(move || {
+                while !done.load(Ordering::Relaxed) {
+                    // Every read must see whole, live data
+                    if let Some(data) = slot.read() {
+                        assert_eq!(data.pixels.len(), 100);
+                        assert!(data.pixels.iter().all(|&p| p == 7));
+                    }
+                }
+            })
+        };
+
+        for i in 0..20_000 {
+            let quality = if i % 2 == 0 {
+                QualityTier::Preview
+            } else {
+                QualityTier::Full
+            };
+            slot.upgrade(Arc::new(ImageData::new(vec![7u8; 100], 10, 10, quality)));
+            slot.clear();
+        }
+        done.store(true, Ordering::Relaxed);
+        reader.join().unwrap();
+        assert!(slot.is_empty());
+    }
+
+    #[test]
+    fn test_read_records_access() {
+        let slot = ImageSlot::new(ImageMeta::new(PathBuf::from("test.jpg")));
+        assert!(slot.read().is_none());
+        assert_eq!(slot.last_read(), None); // Nothing was shown
+
+        slot.upgrade(make_test_data(QualityTier::Full));
+        slot.peek();
+        assert_eq!(slot.last_read(), None);
+
+        let before = now_millis();
+        slot.read();
+        assert!(slot.last_read().unwrap() >= before);
+    }
+
     #[test]
     fn test_read_returns_clone() {
         let meta = ImageMeta::new(PathBuf::from("test.jpg"));
@@ -4,9 +4,27 @@
 //! is separating input state (what keys are held) from view state (what to render).
 //! This allows frame-based navigation during key hold.
 
-use crate::config::InputConfig;
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::time::Instant;
+use crate::config::{BackgroundMode, InputConfig, ScalingFilter, TierSizes, ViewConfig};
+use crate::render::{
+    filmstrip_layout, grid_layout, GridLayout, FILMSTRIP_CELLS, STATUS_BAR_HEIGHT,
+};
+use crate::title::{Field, TitleFormat};
+use std::collections::HashSet;
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// Held navigation keys, each of which steps by its own delta
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NavKey {
+    Right,
+    Left,
+    JumpForward,
+    JumpBackward,
+    /// A key bound to a step of its own size
+    Step(i32),
+}
 
 /// Input state tracking with click vs hold distinction.
 ///
@@ -15,35 +33,47 @@ use std::time::Instant;
 /// - Long press (>= hold_threshold): Repeat navigation while held
 #[derive(Debug)]
 pub struct InputState {
-    /// Right/forward navigation key held
-    right_held: bool,
-    /// Left/backward navigation key held
-    left_held: bool,
+    /// Navigation keys currently held
+    held: Vec<NavKey>,
     /// Home key pressed (single shot)
     pub home_pressed: bool,
     /// End key pressed (single shot)
     pub end_pressed: bool,
+    /// Pending one-off jump by this delta (single shot)
+    pending_jump: Option<i32>,
+    /// Steps from the mouse wheel and buttons, not yet navigated
+    pending_steps: i32,
+    /// Wheel movement short of a whole step, in notches
+    scrolled: f64,
+    /// Whether the current touchpad swipe has already stepped
+    swiped: bool,
     /// When the current key was pressed
     press_start: Option<Instant>,
-    /// Direction of current press (1 = right, -1 = left)
-    press_direction: i32,
+    /// Key of the current press
+    press_key: Option<NavKey>,
+    /// Step of the current press (1 = right, -1 = left, larger for jumps)
+    press_step: i32,
     /// Whether we're in repeat mode (held past threshold)
     in_repeat_mode: bool,
     /// When last repeat navigation occurred
     last_repeat: Instant,
-    /// Pending click to emit on release (direction)
+    /// Pending click to emit on release (step)
     pending_click: Option<i32>,
 }
 
 impl InputState {
     pub fn new() -> Self {
         Self {
-            right_held: false,
-            left_held: false,
+            held: Vec::new(),
             home_pressed: false,
             end_pressed: false,
+            pending_jump: None,
+            pending_steps: 0,
+            scrolled: 0.0,
+            swiped: false,
             press_start: None,
-            press_direction: 0,
+            press_key: None,
+            press_step: 0,
             in_repeat_mode: false,
             last_repeat: Instant::now(),
             pending_click: None,
@@ -52,53 +82,107 @@ impl InputState {
 
     /// Called when right key state changes
     pub fn set_right(&mut self, pressed: bool) {
-        if pressed && !self.right_held {
-            // Key just pressed
-            self.start_press(1);
-        } else if !pressed && self.right_held {
-            // Key just released
-            self.end_press(1);
-        }
-        self.right_held = pressed;
+        self.set_key(NavKey::Right, 1, pressed);
     }
 
     /// Called when left key state changes
     pub fn set_left(&mut self, pressed: bool) {
-        if pressed && !self.left_held {
+        self.set_key(NavKey::Left, -1, pressed);
+    }
+
+    /// Called when a navigation key's state changes. `step` is the signed
+    /// delta the key moves by; it only matters on press.
+    pub fn set_key(&mut self, key: NavKey, step: i32, pressed: bool) {
+        let was_held = self.held.contains(&key);
+        if pressed && !was_held {
             // Key just pressed
-            self.start_press(-1);
-        } else if !pressed && self.left_held {
+            self.held.push(key);
+            self.start_press(key, step);
+        } else if !pressed && was_held {
             // Key just released
-            self.end_press(-1);
+            self.held.retain(|&k| k != key);
+            self.end_press(key);
         }
-        self.left_held = pressed;
+    }
+
+    /// Queue a single navigation by `delta`, such as a jump to a share of
+    /// the collection. Jumps queued before the next `process` add up, and
+    /// never reach the values it uses for the first and last image.
+    pub fn jump(&mut self, delta: i32) {
+        let total = self.pending_jump.unwrap_or(0).saturating_add(delta);
+        self.pending_jump = Some(total.clamp(i32::MIN + 1, i32::MAX - 1));
+    }
+
+    /// Queue a single step, as from a mouse button
+    pub fn step(&mut self, delta: i32) {
+        self.pending_steps += delta;
+    }
+
+    /// Mouse wheel movement in notches (positive = up, toward the previous
+    /// image). Every whole notch is a step; fractions carry over.
+    pub fn scroll(&mut self, notches: f64) {
+        self.accumulate(notches);
+        let whole = self.scrolled.trunc();
+        self.scrolled -= whole;
+        self.pending_steps -= whole as i32;
+    }
+
+    /// A touchpad scroll gesture begins
+    pub fn start_swipe(&mut self) {
+        self.scrolled = 0.0;
+        self.swiped = false;
+    }
+
+    /// Touchpad scroll movement in notches. A swipe steps once, when it
+    /// has moved a notch's worth; the rest of it is ignored.
+    pub fn swipe(&mut self, notches: f64) {
+        if self.swiped {
+            return;
+        }
+        self.accumulate(notches);
+        if self.scrolled.abs() >= 1.0 {
+            self.pending_steps -= self.scrolled.signum() as i32;
+            self.scrolled = 0.0;
+            self.swiped = true;
+        }
+    }
+
+    /// Add wheel movement, dropping any leftover from the other direction
+    fn accumulate(&mut self, notches: f64) {
+        if self.scrolled * notches < 0.0 {
+            self.scrolled = 0.0;
+        }
+        self.scrolled += notches;
     }
 
     /// Start tracking a key press
-    fn start_press(&mut self, direction: i32) {
+    fn start_press(&mut self, key: NavKey, step: i32) {
         self.press_start = Some(Instant::now());
-        self.press_direction = direction;
+        self.press_key = Some(key);
+        self.press_step = step;
         self.in_repeat_mode = false;
         self.pending_click = None;
     }
 
     /// Handle key release
-    fn end_press(&mut self, direction: i32) {
+    fn end_press(&mut self, key: NavKey) {
         // Only handle if this was the active press
-        if self.press_direction == direction {
+        if self.press_key == Some(key) {
             if !self.in_repeat_mode {
                 // Was a quick click - queue single navigation
-                self.pending_click = Some(direction);
+                self.pending_click = Some(self.press_step);
             }
             // Reset press tracking
             self.press_start = None;
-            self.press_direction = 0;
+            self.press_key = None;
+            self.press_step = 0;
             self.in_repeat_mode = false;
         }
     }
 
-    /// Process input and return navigation direction.
-    /// Returns: Some(1) for forward, Some(-1) for backward, None for no navigation.
+    /// Process input and return the navigation delta.
+    /// Returns: Some(1) for forward, Some(-1) for backward, larger steps for
+    /// jumps, None for no navigation.
     pub fn process(&mut self, config: &InputConfig) -> Option<i32> {
         let now = Instant::now();
 
@@ -111,10 +195,16 @@ impl InputState {
             self.end_pressed = false;
             return Some(i32::MAX); // Special: go to end
         }
+        if let Some(delta) = self.pending_jump.take() {
+            return Some(delta);
+        }
+        if self.pending_steps != 0 {
+            return Some(std::mem::take(&mut self.pending_steps));
+        }
 
         // Handle pending click from release
-        if let Some(dir) = self.pending_click.take() {
-            return Some(dir);
+        if let Some(step) = self.pending_click.take() {
+            return Some(step);
         }
 
         // Check if a key is being held
@@ -128,7 +218,7 @@ impl InputState {
                 // Enter repeat mode - first navigation
                 self.in_repeat_mode = true;
                 self.last_repeat = now;
-                return Some(self.press_direction);
+                return Some(self.press_step);
             }
             // Still in click detection phase - no navigation yet
             return None;
@@ -138,18 +228,24 @@ impl InputState {
         let since_last = now.duration_since(self.last_repeat);
         if since_last >= config.repeat_interval {
             self.last_repeat = now;
-            return Some(self.press_direction);
+            return Some(self.press_step);
         }
 
         None
     }
 
+    /// Whether the steps come from a key held past the hold threshold
+    pub fn is_repeating(&self) -> bool {
+        self.in_repeat_mode
+    }
+
     /// Check if any navigation is active (for control flow)
     pub fn is_navigating(&self) -> bool {
-        self.right_held
-            || self.left_held
+        !self.held.is_empty()
             || self.home_pressed
             || self.end_pressed
+            || self.pending_jump.is_some()
+            || self.pending_steps != 0
             || self.pending_click.is_some()
     }
 }
@@ -160,6 +256,397 @@ impl Default for InputState {
     }
 }
 
+/// Rotation and mirroring of the displayed image - one of the eight
+/// symmetries of a rectangle.
+///
+/// The source is mirrored horizontally first (if `mirrored`), then rotated
+/// clockwise by `quarter_turns` * 90 degrees.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Orientation {
+    /// Clockwise quarter turns (0-3)
+    pub quarter_turns: u8,
+    /// Horizontal mirror applied before rotation
+    pub mirrored: bool,
+}
+
+impl Orientation {
+    pub const IDENTITY: Self = Self {
+        quarter_turns: 0,
+        mirrored: false,
+    };
+
+    pub fn is_identity(&self) -> bool {
+        *self == Self::IDENTITY
+    }
+
+    /// Orientation that displays an image upright, from its EXIF
+    /// orientation tag (1-8; anything else is treated as upright)
+    pub fn from_exif(value: u32) -> Self {
+        let (quarter_turns, mirrored) = match value {
+            2 => (0, true),
+            3 => (2, false),
+            4 => (2, true),
+            5 => (3, true),
+            6 => (1, false),
+            7 => (1, true),
+            8 => (3, false),
+            _ => (0, false),
+        };
+        Self {
+            quarter_turns,
+            mirrored,
+        }
+    }
+
+    /// The EXIF orientation tag value (1-8) for this orientation
+    pub fn to_exif(self) -> u16 {
+        match (self.quarter_turns % 4, self.mirrored) {
+            (0, false) => 1,
+            (0, true) => 2,
+            (2, false) => 3,
+            (2, true) => 4,
+            (3, true) => 5,
+            (1, false) => 6,
+            (1, true) => 7,
+            _ => 8,
+        }
+    }
+
+    /// This orientation followed by `next`.
+    /// R(q2) H(m2) R(q1) H(m1): a mirror in `next` negates the first turn.
+    pub fn then(self, next: Self) -> Self {
+        let turns = if next.mirrored {
+            4 + next.quarter_turns - self.quarter_turns
+        } else {
+            next.quarter_turns + self.quarter_turns
+        };
+        Self {
+            quarter_turns: turns % 4,
+            mirrored: self.mirrored != next.mirrored,
+        }
+    }
+
+    /// Rotate the displayed image 90 degrees clockwise
+    pub fn rotate_cw(self) -> Self {
+        Self {
+            quarter_turns: (self.quarter_turns + 1) % 4,
+            ..self
+        }
+    }
+
+    /// Rotate the displayed image 90 degrees counter-clockwise
+    pub fn rotate_ccw(self) -> Self {
+        Self {
+            quarter_turns: (self.quarter_turns + 3) % 4,
+            ..self
+        }
+    }
+
+    /// Mirror the displayed image left-right.
+    /// H * R(q) * M = R(-q) * H * M, so the turn count is negated.
+    pub fn flip_horizontal(self) -> Self {
+        Self {
+            quarter_turns: (4 - self.quarter_turns) % 4,
+            mirrored: !self.mirrored,
+        }
+    }
+
+    /// Mirror the displayed image top-bottom (a left-right flip plus 180 degrees)
+    pub fn flip_vertical(self) -> Self {
+        let flipped = self.flip_horizontal();
+        Self {
+            quarter_turns: (flipped.quarter_turns + 2) % 4,
+            ..flipped
+        }
+    }
+
+    /// Whether width and height trade places on screen
+    pub fn swaps_axes(&self) -> bool {
+        self.quarter_turns % 2 == 1
+    }
+
+    /// Displayed dimensions of a `width` x `height` source
+    pub fn display_dims(&self, width: usize, height: usize) -> (usize, usize) {
+        if self.swaps_axes() {
+            (height, width)
+        } else {
+            (width, height)
+        }
+    }
+
+    /// Map a displayed pixel (u, v) back to source coordinates
+    pub fn source_coords(&self, u: usize, v: usize, width: usize, height: usize) -> (usize, usize) {
+        let (x, y) = match self.quarter_turns % 4 {
+            0 => (u, v),
+            1 => (v, height - 1 - u),
+            2 => (width - 1 - u, height - 1 - v),
+            _ => (width - 1 - v, u),
+        };
+        if self.mirrored {
+            (width - 1 - x, y)
+        } else {
+            (x, y)
+        }
+    }
+}
+
+/// Zoom and pan applied on top of fit-to-window display.
+///
+/// `zoom` is relative to the fit scale (1.0 = fit), unless `actual_size` is
+/// set, in which case the image is shown at 100% of its original size, one
+/// image pixel per physical device pixel, and `zoom` is ignored. Pan
+/// offsets are in window (physical) pixels, measured from the
+/// centered position. `orientation` is independent of zoom and survives
+/// zoom resets.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ViewTransform {
+    pub zoom: f64,
+    pub pan_x: f64,
+    pub pan_y: f64,
+    pub actual_size: bool,
+    pub orientation: Orientation,
+}
+
+impl ViewTransform {
+    /// Plain fit-to-window, centered, unrotated
+    pub const FIT: Self = Self {
+        zoom: 1.0,
+        pan_x: 0.0,
+        pan_y: 0.0,
+        actual_size: false,
+        orientation: Orientation::IDENTITY,
+    };
+
+    /// Whether zoom and pan are plain fit-to-window (orientation aside)
+    pub fn is_fit(&self) -> bool {
+        self.zoom == 1.0 && self.pan_x == 0.0 && self.pan_y == 0.0 && !self.actual_size
+    }
+
+    /// Fit-to-window keeping this transform's orientation
+    pub fn fit(&self) -> Self {
+        Self {
+            orientation: self.orientation,
+            ..Self::FIT
+        }
+    }
+}
+
+impl Default for ViewTransform {
+    fn default() -> Self {
+        Self::FIT
+    }
+}
+
+/// What typed keys do
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum InputMode {
+    /// Keys run their bound actions
+    #[default]
+    Normal,
+    /// Digits build a 1-based image number to go to
+    Goto(String),
+    /// Typed text builds a file name filter
+    Filter(String),
+    /// Dragging selects a region of the image to save
+    Crop(Option<CropSelection>),
+}
+
+/// Comparing with a pinned image, holding its slot id
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compare {
+    /// Pinned, waiting for the image to compare it with
+    Pinned(usize),
+    /// Shown on the left, beside the current image
+    Split(usize),
+}
+
+/// The gallery grid, while shown in place of the current image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Gallery {
+    /// Side of a cell in pixels
+    pub cell_size: usize,
+    /// Row of the collection shown in the window's top row
+    pub top_row: usize,
+}
+
+/// A rectangle dragged out in crop mode, in window pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CropSelection {
+    /// Where the drag started
+    pub start: (i32, i32),
+    /// Where the pointer is now
+    pub end: (i32, i32),
+}
+
+impl CropSelection {
+    /// The corners as (left, top) and (right, bottom)
+    pub fn corners(&self) -> ((i32, i32), (i32, i32)) {
+        (
+            (self.start.0.min(self.end.0), self.start.1.min(self.end.1)),
+            (self.start.0.max(self.end.0), self.start.1.max(self.end.1)),
+        )
+    }
+}
+
+/// A running slideshow
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Slideshow {
+    /// Time each image is shown
+    pub interval: Duration,
+    /// When the next image is due; None while paused
+    pub next_advance: Option<Instant>,
+}
+
+/// Hides the mouse pointer once the mouse has been left alone for a while
+#[derive(Debug, Clone, PartialEq)]
+pub struct CursorIdle {
+    /// How long the mouse stays still before the pointer hides; None never
+    /// hides it
+    delay: Option<Duration>,
+    /// Where the pointer last moved to
+    position: Option<(f64, f64)>,
+    /// When the pointer hides, unless the mouse is used before then
+    hide_at: Option<Instant>,
+    hidden: bool,
+}
+
+impl CursorIdle {
+    pub fn new(delay: Option<Duration>, now: Instant) -> Self {
+        Self {
+            delay,
+            position: None,
+            hide_at: delay.map(|delay| now + delay),
+            hidden: false,
+        }
+    }
+
+    /// The pointer moved to `position`. Moves to where it already was
+    /// don't count: some compositors send them when the focus changes.
+    /// Returns true if the pointer is to be shown again.
+    pub fn moved(&mut self, position: (f64, f64), now: Instant) -> bool {
+        if self.position.replace(position) == Some(position) {
+            return false;
+        }
+        self.used(now)
+    }
+
+    /// A button was pressed or the wheel turned. Returns true if the
+    /// pointer is to be shown again.
+    pub fn used(&mut self, now: Instant) -> bool {
+        self.hide_at = self.delay.map(|delay| now + delay);
+        std::mem::take(&mut self.hidden)
+    }
+
+    /// When the pointer is due to hide
+    pub fn deadline(&self) -> Option<Instant> {
+        self.hide_at
+    }
+
+    /// Returns true, once, when the pointer is to be hidden
+    pub fn hide_due(&mut self, now: Instant) -> bool {
+        if self.hide_at.is_some_and(|at| now >= at) {
+            self.hide_at = None;
+            self.hidden = true;
+            return true;
+        }
+        false
+    }
+}
+
+/// Tone adjustments to the displayed image
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Adjustments {
+    /// Exposure change in stops
+    pub exposure: f32,
+    /// Gamma, above 1.0 lifting the midtones
+    pub gamma: f32,
+    /// Contrast change, 0.0 leaving the tone curve as it is
+    pub contrast: f32,
+}
+
+impl Adjustments {
+    pub const NONE: Self = Self {
+        exposure: 0.0,
+        gamma: 1.0,
+        contrast: 0.0,
+    };
+
+    pub fn is_identity(&self) -> bool {
+        *self == Self::NONE
+    }
+
+    /// Lookup tables for the blit, or None when nothing is adjusted
+    pub fn lut(&self) -> Option<crate::render::Lut> {
+        (!self.is_identity())
+            .then(|| crate::render::adjustment_lut(self.exposure, self.gamma, self.contrast))
+    }
+
+    /// Short description of what is adjusted, such as "+1.0 EV, γ 1.21"
+    pub fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        if self.exposure != 0.0 {
+            parts.push(format!("{:+.1} EV", self.exposure));
+        }
+        if self.gamma != 1.0 {
+            parts.push(format!("\u{3b3} {:.2}", self.gamma));
+        }
+        if self.contrast != 0.0 {
+            parts.push(format!("contrast {:+.0}%", self.contrast * 100.0));
+        }
+        parts.join(", ")
+    }
+}
+
+impl Default for Adjustments {
+    fn default() -> Self {
+        Self::NONE
+    }
+}
+
+/// Which of the image's channels are shown, and how
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ChannelMode {
+    /// The image as it is
+    #[default]
+    Color,
+    /// One channel as grayscale
+    Red,
+    Green,
+    Blue,
+    Alpha,
+    /// Luminance as grayscale
+    Luminance,
+    /// Colors inverted, for reading dark-on-white diagrams in the dark
+    Invert,
+}
+
+impl ChannelMode {
+    /// The mode after this one, back to color after the last
+    pub fn next(self) -> Self {
+        match self {
+            ChannelMode::Color => ChannelMode::Red,
+            ChannelMode::Red => ChannelMode::Green,
+            ChannelMode::Green => ChannelMode::Blue,
+            ChannelMode::Blue => ChannelMode::Alpha,
+            ChannelMode::Alpha => ChannelMode::Luminance,
+            ChannelMode::Luminance => ChannelMode::Invert,
+            ChannelMode::Invert => ChannelMode::Color,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ChannelMode::Color => "color",
+            ChannelMode::Red => "red",
+            ChannelMode::Green => "green",
+            ChannelMode::Blue => "blue",
+            ChannelMode::Alpha => "alpha",
+            ChannelMode::Luminance => "luminance",
+            ChannelMode::Invert => "inverted",
+        }
+    }
+}
+
 /// View state - what the viewer is currently showing.
 ///
 /// This is the "model" in a model-view separation. It contains everything
@@ -170,13 +657,90 @@ pub struct ViewState {
     pub current_index: usize,
     /// Total number of images
     pub total_images: usize,
-    /// Window dimensions
+    /// Window dimensions in physical pixels, like everything drawn
     pub window_width: u32,
     pub window_height: u32,
     /// Whether a render is needed
     pub needs_render: bool,
     /// Last rendered quality (for upgrade detection)
     pub last_render_quality: Option<crate::config::QualityTier>,
+    /// Zoom and pan
+    pub transform: ViewTransform,
+    /// Last rendered scale relative to the original image (1.0 = 100%)
+    pub display_scale: Option<f64>,
+    /// Whether the file/EXIF info overlay is visible
+    pub show_info: bool,
+    /// Whether the key binding help overlay is visible
+    pub show_help: bool,
+    /// Whether the status bar is visible
+    pub show_status: bool,
+    /// Whether thumbnails of the neighbouring images run along the bottom
+    pub show_filmstrip: bool,
+    /// What the window title shows
+    pub title_format: TitleFormat,
+    /// Whether the debug overlay (render counter) is visible
+    pub show_debug: bool,
+    /// Whether the pixel under the cursor is shown beside it
+    pub show_inspector: bool,
+    /// Whether the histogram overlay is visible
+    pub show_histogram: bool,
+    /// What fills the window around the image
+    pub background_mode: BackgroundMode,
+    /// Resampling used to scale the image to the window
+    pub scaling_filter: ScalingFilter,
+    /// Exposure, gamma and contrast of the displayed image
+    pub adjustments: Adjustments,
+    /// Whether the adjustments carry over to the next image
+    pub adjustments_locked: bool,
+    /// Channel isolation, grayscale or inversion of the displayed image
+    pub channel_mode: ChannelMode,
+    /// Whether the last render showed a RAW file's embedded preview
+    pub embedded_preview: bool,
+    /// Whether the last render showed an image shrunk to fit the memory
+    /// budget
+    pub over_budget: bool,
+    /// Slot generation of the coarse pass over a progressive file last
+    /// rendered, so finer passes are noticed though their tier is the same
+    pub partial: Option<u64>,
+    /// Whether the last render showed the decode-failure placeholder
+    pub decode_failed: bool,
+    /// When navigation landed on an image not yet drawn
+    pub navigated_at: Option<Instant>,
+    /// Time from the last navigation to the new image's first frame
+    pub first_pixel: Option<Duration>,
+    /// Frame shown for animated images
+    pub frame_index: usize,
+    /// Whether animation playback is paused
+    pub animation_paused: bool,
+    /// When the next animation frame is due
+    pub next_frame_at: Option<Instant>,
+    /// Short message shown in the title, and when it goes away
+    pub notice: Option<(String, Instant)>,
+    /// Slot ids of the marked images (slot ids survive the store changing)
+    pub marked: HashSet<usize>,
+    /// Automatic advancing, while in slideshow mode
+    pub slideshow: Option<Slideshow>,
+    /// Whether navigation wraps around at either end
+    pub wrap: bool,
+    /// Tier sizes, bounding the display-fit tier the shown image wants
+    pub tiers: TierSizes,
+    /// Normal, or typing an image number
+    pub mode: InputMode,
+    /// Size of the whole collection while a filter hides some of it
+    pub unfiltered_total: Option<usize>,
+    /// Image pinned for comparing, and whether it's shown yet
+    pub compare: Option<Compare>,
+    /// Last rendered quality of the pinned image in split view
+    pub pinned_render_quality: Option<crate::config::QualityTier>,
+    /// Whether the pinned image is shown in place of the current one
+    pub blink: bool,
+    /// When the images swap next while the blink key is held
+    pub next_blink_at: Option<Instant>,
+    /// Thumbnails of the whole collection, with the current image as the
+    /// cursor
+    pub gallery: Option<Gallery>,
+    /// Whether files are being dragged over the window
+    pub drop_hover: bool,
 }
 
 impl ViewState {
@@ -188,13 +752,52 @@ impl ViewState {
             window_height,
             needs_render: true,
             last_render_quality: None,
+            transform: ViewTransform::FIT,
+            display_scale: None,
+            show_info: false,
+            show_help: false,
+            show_debug: false,
+            show_inspector: false,
+            show_histogram: false,
+            show_status: false,
+            show_filmstrip: false,
+            title_format: TitleFormat::default(),
+            background_mode: BackgroundMode::Solid,
+            scaling_filter: ScalingFilter::default(),
+            adjustments: Adjustments::NONE,
+            adjustments_locked: false,
+            channel_mode: ChannelMode::Color,
+            embedded_preview: false,
+            over_budget: false,
+            partial: None,
+            decode_failed: false,
+            navigated_at: None,
+            first_pixel: None,
+            frame_index: 0,
+            animation_paused: false,
+            next_frame_at: None,
+            notice: None,
+            marked: HashSet::new(),
+            slideshow: None,
+            wrap: true,
+            tiers: TierSizes::default(),
+            mode: InputMode::Normal,
+            unfiltered_total: None,
+            compare: None,
+            pinned_render_quality: None,
+            blink: false,
+            next_blink_at: None,
+            gallery: None,
+            drop_hover: false,
         }
     }
 
-    /// Navigate by delta (positive = forward, negative = backward)
-    pub fn navigate(&mut self, delta: i32) {
+    /// Navigate by delta (positive = forward, negative = backward).
+    /// Returns false if there was nothing further that way (only without
+    /// wrap-around).
+    pub fn navigate(&mut self, delta: i32) -> bool {
         if self.total_images == 0 {
-            return;
+            return false;
         }
 
         // Handle special values
@@ -202,24 +805,376 @@ impl ViewState {
             self.current_index = 0;
         } else if delta == i32::MAX {
             self.current_index = self.total_images - 1;
+        } else if self.wrap {
+            // Normal navigation with wrap-around, for deltas of any size
+            let total = self.total_images as i64;
+            self.current_index =
+                (self.current_index as i64 + delta as i64).rem_euclid(total) as usize;
         } else {
-            // Normal navigation with wrap-around
-            let new_index = if delta >= 0 {
-                (self.current_index + delta as usize) % self.total_images
-            } else {
-                let back = (-delta) as usize;
-                if back > self.current_index {
-                    self.total_images - (back - self.current_index) % self.total_images
-                } else {
-                    self.current_index - back
-                }
-            };
-            // Handle edge case where modulo gives total_images
-            self.current_index = new_index % self.total_images;
+            let last = self.total_images as i64 - 1;
+            let target = (self.current_index as i64 + delta as i64).clamp(0, last) as usize;
+            if delta != 0 && target == self.current_index {
+                return false;
+            }
+            self.current_index = target;
         }
 
+        self.frame_index = 0;
+        self.next_frame_at = None;
         self.needs_render = true;
         self.last_render_quality = None;
+        self.navigated_at = Some(Instant::now());
+        self.end_blink();
+        self.scroll_gallery();
+        true
+    }
+
+    /// Show another collection of `total` images from `current`, as if
+    /// starting out with it: nothing marked, filtered or compared, and the
+    /// image fit to the window. Locked adjustments stay.
+    pub fn replace_collection(&mut self, total: usize, current: usize) {
+        self.exit_compare();
+        self.marked.clear();
+        self.unfiltered_total = None;
+        self.mode = InputMode::Normal;
+        self.total_images = total;
+        self.current_index = current.min(total.saturating_sub(1));
+        self.frame_index = 0;
+        self.next_frame_at = None;
+        self.last_render_quality = None;
+        self.reset_zoom();
+        self.reset_orientation();
+        if !self.adjustments_locked {
+            self.reset_adjustments();
+        }
+        self.reset_channel_mode();
+        if let Some(gallery) = &mut self.gallery {
+            gallery.top_row = 0;
+        }
+        self.scroll_gallery();
+        self.needs_render = true;
+    }
+
+    /// Files started or stopped being dragged over the window
+    pub fn hover_drop(&mut self, hovering: bool) {
+        if self.drop_hover != hovering {
+            self.drop_hover = hovering;
+            self.needs_render = true;
+        }
+    }
+
+    /// Step for PageUp/PageDown: `configured`, or 5% of the collection if
+    /// that is larger
+    pub fn jump_step(&self, configured: usize) -> i32 {
+        let step = configured.max(self.total_images / 20);
+        i32::try_from(step).unwrap_or(i32::MAX)
+    }
+
+    /// Delta to the image `tenth` tenths of the way through the collection
+    /// (3 is the image at 30%)
+    pub fn delta_to_tenth(&self, tenth: u8) -> i32 {
+        self.delta_to(self.total_images * tenth.min(9) as usize / 10)
+    }
+
+    /// Delta to a random image other than the current one, if there is one
+    pub fn random_delta<R: rand::Rng>(&self, rng: &mut R) -> Option<i32> {
+        if self.total_images < 2 {
+            return None;
+        }
+        // Draw from the others by skipping over the current index
+        let mut target = rng.gen_range(0..self.total_images - 1);
+        if target >= self.current_index {
+            target += 1;
+        }
+        Some(self.delta_to(target))
+    }
+
+    /// Delta from the current image to `index`
+    fn delta_to(&self, index: usize) -> i32 {
+        (index as i64 - self.current_index as i64) as i32
+    }
+
+    /// Advance the animation clock for an image with the given frame
+    /// delays. Returns true when a new frame should be shown.
+    pub fn tick_animation(&mut self, delays: &[Duration], now: Instant) -> bool {
+        if delays.len() < 2 || self.animation_paused {
+            self.next_frame_at = None;
+            return false;
+        }
+
+        let due = match self.next_frame_at {
+            Some(due) => due,
+            None => {
+                // First tick for this image: schedule the current frame
+                self.frame_index %= delays.len();
+                self.next_frame_at = Some(now + delays[self.frame_index]);
+                return false;
+            }
+        };
+        if now < due {
+            return false;
+        }
+
+        self.frame_index = (self.frame_index + 1) % delays.len();
+        // Schedule from the deadline to avoid drift, but don't try to
+        // catch up after a long stall
+        let next = due + delays[self.frame_index];
+        self.next_frame_at = Some(if next < now {
+            now + delays[self.frame_index]
+        } else {
+            next
+        });
+        self.needs_render = true;
+        true
+    }
+
+    /// Pause or resume animation playback
+    pub fn toggle_animation_pause(&mut self) {
+        self.animation_paused = !self.animation_paused;
+        self.next_frame_at = None;
+    }
+
+    /// Zoom by a factor, keeping the point at `anchor` (window pixels from
+    /// the window center) fixed on screen
+    pub fn zoom_by(&mut self, factor: f64, anchor: (f64, f64), config: &ViewConfig) {
+        let old = self.transform.zoom;
+        let new = (old * factor).clamp(config.min_zoom, config.max_zoom);
+        if new == old {
+            return;
+        }
+
+        let ratio = new / old;
+        let t = &mut self.transform;
+        t.pan_x = (t.pan_x - anchor.0) * ratio + anchor.0;
+        t.pan_y = (t.pan_y - anchor.1) * ratio + anchor.1;
+        t.zoom = new;
+        self.needs_render = true;
+    }
+
+    /// Whether the image is magnified beyond fit-to-window
+    pub fn is_zoomed(&self) -> bool {
+        self.transform.actual_size || self.transform.zoom > 1.0
+    }
+
+    /// Toggle between fit-to-window and 100% actual pixels
+    pub fn toggle_actual_size(&mut self) {
+        self.transform = ViewTransform {
+            actual_size: !self.transform.actual_size,
+            ..self.transform.fit()
+        };
+        self.needs_render = true;
+    }
+
+    /// Convert actual-size mode into the equivalent relative zoom, so zoom
+    /// steps continue from 100%. `fit` is the fit-to-window scale of the
+    /// original image.
+    pub fn leave_actual_size(&mut self, fit: f64) {
+        if self.transform.actual_size && fit > 0.0 {
+            self.transform.actual_size = false;
+            self.transform.zoom = 1.0 / fit;
+        }
+    }
+
+    /// Move the image by a window-pixel delta. Bounds are enforced at render
+    /// time, which writes the clamped pan back.
+    pub fn pan_by(&mut self, dx: f64, dy: f64) {
+        if dx == 0.0 && dy == 0.0 {
+            return;
+        }
+        self.transform.pan_x += dx;
+        self.transform.pan_y += dy;
+        self.needs_render = true;
+    }
+
+    /// Return to fit-to-window
+    pub fn reset_zoom(&mut self) {
+        if !self.transform.is_fit() {
+            self.transform = self.transform.fit();
+            self.needs_render = true;
+        }
+    }
+
+    /// Change the orientation of the displayed image
+    pub fn reorient(&mut self, change: fn(Orientation) -> Orientation) {
+        self.transform.orientation = change(self.transform.orientation);
+        self.needs_render = true;
+    }
+
+    /// Return to the unrotated, unmirrored orientation
+    pub fn reset_orientation(&mut self) {
+        if !self.transform.orientation.is_identity() {
+            self.reorient(|_| Orientation::IDENTITY);
+        }
+    }
+
+    /// Show or hide the info overlay
+    pub fn toggle_info(&mut self) {
+        self.show_info = !self.show_info;
+        self.needs_render = true;
+    }
+
+    /// Show or hide the status bar
+    pub fn toggle_status_bar(&mut self) {
+        self.show_status = !self.show_status;
+        self.needs_render = true;
+    }
+
+    /// Show or hide the filmstrip
+    pub fn toggle_filmstrip(&mut self) {
+        self.show_filmstrip = !self.show_filmstrip;
+        self.needs_render = true;
+    }
+
+    /// Where the filmstrip goes, while it's shown: along the bottom, above
+    /// the status bar
+    pub fn filmstrip_layout(&self) -> Option<GridLayout> {
+        if !self.show_filmstrip || self.gallery.is_some() || self.total_images == 0 {
+            return None;
+        }
+        let bottom = if self.show_status {
+            STATUS_BAR_HEIGHT
+        } else {
+            0
+        };
+        Some(filmstrip_layout(
+            self.window_width as usize,
+            self.window_height as usize,
+            bottom,
+        ))
+    }
+
+    /// The filmstrip's first filled cell, and the positions of the images
+    /// in it from there on: the current image in the middle, with as many
+    /// neighbours either side as there are, each shown once
+    pub fn filmstrip_positions(&self) -> (usize, Vec<usize>) {
+        let half = FILMSTRIP_CELLS / 2;
+        let total = self.total_images;
+        if total == 0 {
+            return (half, Vec::new());
+        }
+        let (behind, ahead) = if self.wrap {
+            let behind = half.min((total - 1) / 2);
+            (behind, half.min(total - 1 - behind))
+        } else {
+            (
+                half.min(self.current_index),
+                half.min(total - 1 - self.current_index),
+            )
+        };
+        let positions = (0..=behind + ahead)
+            .map(|i| (self.current_index + total + i - behind) % total)
+            .collect();
+        (half - behind, positions)
+    }
+
+    /// Delta to the image in the filmstrip cell at window position `at`,
+    /// if there's one there
+    pub fn filmstrip_delta_at(&self, at: (f64, f64)) -> Option<i32> {
+        let cell = self.filmstrip_layout()?.cell_at(at)?;
+        let (first, positions) = self.filmstrip_positions();
+        cell.checked_sub(first).filter(|&n| n < positions.len())?;
+        Some(cell as i32 - (FILMSTRIP_CELLS / 2) as i32)
+    }
+
+    /// Switch between the solid and the blurred background
+    pub fn toggle_background(&mut self) {
+        self.background_mode = match self.background_mode {
+            BackgroundMode::Solid => BackgroundMode::Blur,
+            BackgroundMode::Blur => BackgroundMode::Solid,
+        };
+        self.needs_render = true;
+    }
+
+    /// Switch to the next scaling filter, returning it
+    pub fn cycle_scaling_filter(&mut self) -> ScalingFilter {
+        self.scaling_filter = self.scaling_filter.next();
+        self.needs_render = true;
+        self.scaling_filter
+    }
+
+    /// Change the exposure by `stops`, within eight stops either way
+    pub fn adjust_exposure(&mut self, stops: f32) {
+        self.adjustments.exposure = (self.adjustments.exposure + stops).clamp(-8.0, 8.0);
+        self.needs_render = true;
+    }
+
+    /// Multiply the gamma by `factor`, between 0.1 and 10
+    pub fn adjust_gamma(&mut self, factor: f32) {
+        self.adjustments.gamma = (self.adjustments.gamma * factor).clamp(0.1, 10.0);
+        // Land exactly on 1.0 again after stepping there and back
+        if (self.adjustments.gamma - 1.0).abs() < 1e-3 {
+            self.adjustments.gamma = 1.0;
+        }
+        self.needs_render = true;
+    }
+
+    /// Change the contrast by `amount`; -1.0 flattens the image to gray
+    pub fn adjust_contrast(&mut self, amount: f32) {
+        let contrast = (self.adjustments.contrast + amount).clamp(-1.0, 4.0);
+        self.adjustments.contrast = if contrast.abs() < 1e-3 { 0.0 } else { contrast };
+        self.needs_render = true;
+    }
+
+    /// Clear the adjustments
+    pub fn reset_adjustments(&mut self) {
+        if !self.adjustments.is_identity() {
+            self.adjustments = Adjustments::NONE;
+            self.needs_render = true;
+        }
+    }
+
+    /// Lock or unlock the adjustments across navigation, returning whether
+    /// they are now locked
+    pub fn toggle_adjustment_lock(&mut self) -> bool {
+        self.adjustments_locked = !self.adjustments_locked;
+        self.adjustments_locked
+    }
+
+    /// Switch to the next channel mode, returning it
+    pub fn cycle_channel_mode(&mut self) -> ChannelMode {
+        self.channel_mode = self.channel_mode.next();
+        self.needs_render = true;
+        self.channel_mode
+    }
+
+    /// Go back to showing the image in color
+    pub fn reset_channel_mode(&mut self) {
+        if self.channel_mode != ChannelMode::Color {
+            self.channel_mode = ChannelMode::Color;
+            self.needs_render = true;
+        }
+    }
+
+    /// How the image's pixels are to be shown
+    pub fn look(&self) -> crate::render::Look {
+        crate::render::Look {
+            channel: self.channel_mode,
+            lut: self.adjustments.lut(),
+        }
+    }
+
+    /// Show or hide the histogram overlay
+    pub fn toggle_histogram(&mut self) {
+        self.show_histogram = !self.show_histogram;
+        self.needs_render = true;
+    }
+
+    /// Turn the pixel inspector on or off
+    pub fn toggle_inspector(&mut self) {
+        self.show_inspector = !self.show_inspector;
+        self.needs_render = true;
+    }
+
+    /// Show or hide the debug overlay
+    pub fn toggle_debug(&mut self) {
+        self.show_debug = !self.show_debug;
+        self.needs_render = true;
+    }
+
+    /// Show or hide the key binding help overlay
+    pub fn toggle_help(&mut self) {
+        self.show_help = !self.show_help;
+        self.needs_render = true;
     }
 
     /// Update window size
@@ -227,49 +1182,599 @@ impl ViewState {
         if width > 0 && height > 0 {
             self.window_width = width;
             self.window_height = height;
+            self.scroll_gallery();
             self.needs_render = true;
         }
     }
 
-    /// Mark that a quality upgrade is available
-    pub fn signal_quality_upgrade(&mut self) {
-        self.needs_render = true;
+    /// Switch between the gallery, with cells of side `cell_size`, and the
+    /// current image. The gallery opens scrolled to the current image; the
+    /// image comes back at fit-to-window.
+    pub fn toggle_gallery(&mut self, cell_size: usize) {
+        self.gallery = match self.gallery {
+            Some(_) => None,
+            None => {
+                self.reset_zoom();
+                Some(Gallery {
+                    cell_size,
+                    top_row: 0,
+                })
+            }
+        };
+        self.last_render_quality = None;
+        self.scroll_gallery();
+        self.needs_render = true;
+    }
+
+    /// How the gallery's cells fit the window, while it's shown
+    pub fn gallery_layout(&self) -> Option<GridLayout> {
+        let gallery = self.gallery?;
+        Some(grid_layout(
+            self.window_width as usize,
+            self.window_height as usize,
+            gallery.cell_size,
+        ))
+    }
+
+    /// Positions of the images in the gallery's cells, while it's shown
+    pub fn gallery_cells(&self) -> Option<Range<usize>> {
+        let layout = self.gallery_layout()?;
+        let first = self.gallery?.top_row * layout.columns;
+        Some(first.min(self.total_images)..(first + layout.cells()).min(self.total_images))
+    }
+
+    /// Delta moving the gallery cursor `rows` rows down, or up if
+    /// negative, stopping at the first and last image
+    pub fn gallery_rows_delta(&self, rows: i32) -> i32 {
+        let Some(layout) = self.gallery_layout() else {
+            return 0;
+        };
+        let last = self.total_images.saturating_sub(1) as i64;
+        let target =
+            (self.current_index as i64 + rows as i64 * layout.columns as i64).clamp(0, last);
+        self.delta_to(target as usize)
+    }
+
+    /// Rows of the gallery on screen, for paging
+    pub fn gallery_page(&self) -> i32 {
+        self.gallery_layout()
+            .map_or(1, |layout| i32::try_from(layout.rows).unwrap_or(i32::MAX))
+    }
+
+    /// Delta to the image in the gallery cell at window position `at`, if
+    /// there's one there
+    pub fn gallery_delta_at(&self, at: (f64, f64)) -> Option<i32> {
+        let cells = self.gallery_cells()?;
+        let index = cells.start + self.gallery_layout()?.cell_at(at)?;
+        cells.contains(&index).then(|| self.delta_to(index))
+    }
+
+    /// Scroll the gallery by whole rows to keep the cursor on screen,
+    /// without leaving empty rows below the last image
+    pub fn scroll_gallery(&mut self) {
+        let Some(layout) = self.gallery_layout() else {
+            return;
+        };
+        let Some(gallery) = &mut self.gallery else {
+            return;
+        };
+        let row = self.current_index / layout.columns;
+        let last_top = self
+            .total_images
+            .div_ceil(layout.columns)
+            .saturating_sub(layout.rows);
+        let top = gallery
+            .top_row
+            .clamp((row + 1).saturating_sub(layout.rows), row);
+        gallery.top_row = top.min(last_top);
+    }
+
+    /// Mark that a quality upgrade is available
+    pub fn signal_quality_upgrade(&mut self) {
+        self.needs_render = true;
+    }
+
+    /// Start over on the current image after its file was reloaded,
+    /// keeping zoom and orientation
+    pub fn reload(&mut self) {
+        self.frame_index = 0;
+        self.next_frame_at = None;
+        self.needs_render = true;
+        self.last_render_quality = None;
+    }
+
+    /// Show a message in the title for `duration`
+    pub fn show_notice(&mut self, text: impl Into<String>, duration: Duration) {
+        self.notice = Some((text.into(), Instant::now() + duration));
+    }
+
+    /// Drop the notice once its time is up. Returns true if it went away.
+    pub fn expire_notice(&mut self, now: Instant) -> bool {
+        if self.notice.as_ref().is_some_and(|(_, until)| now >= *until) {
+            self.notice = None;
+            return true;
+        }
+        false
+    }
+
+    /// Start a slideshow showing each image for `interval`, or stop the
+    /// running one
+    pub fn toggle_slideshow(&mut self, interval: Duration, now: Instant) {
+        self.slideshow = match self.slideshow {
+            Some(_) => None,
+            None => Some(Slideshow {
+                interval,
+                next_advance: Some(now + interval),
+            }),
+        };
+    }
+
+    /// Pause or resume the slideshow; resuming shows the current image for
+    /// a full interval
+    pub fn toggle_slideshow_pause(&mut self, now: Instant) {
+        if let Some(show) = &mut self.slideshow {
+            show.next_advance = match show.next_advance {
+                Some(_) => None,
+                None => Some(now + show.interval),
+            };
+        }
+    }
+
+    /// Give the current image a full interval again (after manual
+    /// navigation)
+    pub fn restart_slideshow_timer(&mut self, now: Instant) {
+        if let Some(show) = &mut self.slideshow {
+            if show.next_advance.is_some() {
+                show.next_advance = Some(now + show.interval);
+            }
+        }
+    }
+
+    /// Whether the slideshow should move on now. Schedules the next
+    /// advance when it does.
+    pub fn slideshow_due(&mut self, now: Instant) -> bool {
+        let Some(show) = &mut self.slideshow else {
+            return false;
+        };
+        match show.next_advance {
+            Some(due) if now >= due => {
+                show.next_advance = Some(now + show.interval);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Start typing an image number to go to
+    pub fn start_goto(&mut self) {
+        self.mode = InputMode::Goto(String::new());
+    }
+
+    /// Append a digit to the typed image number
+    pub fn push_goto_digit(&mut self, digit: u8) {
+        if let InputMode::Goto(number) = &mut self.mode {
+            // Enough for any collection, short of overflowing
+            if number.len() < 9 {
+                number.push(char::from(b'0' + digit));
+            }
+        }
+    }
+
+    /// Remove the last digit of the typed image number
+    pub fn pop_goto_digit(&mut self) {
+        if let InputMode::Goto(number) = &mut self.mode {
+            number.pop();
+        }
+    }
+
+    /// Leave goto mode. Returns the delta to the typed image, clamped to
+    /// the collection, or None if no number was typed.
+    pub fn finish_goto(&mut self) -> Option<i32> {
+        let InputMode::Goto(number) = std::mem::take(&mut self.mode) else {
+            return None;
+        };
+        Some(self.delta_to_number(number.parse().ok()?))
+    }
+
+    /// Delta to image `number`, counting from 1, or to the nearest end if
+    /// there's no such image
+    pub fn delta_to_number(&self, number: usize) -> i32 {
+        self.delta_to(number.clamp(1, self.total_images.max(1)) - 1)
+    }
+
+    /// Start typing a file name filter, beginning with the current one
+    pub fn start_filter(&mut self, current: &str) {
+        self.mode = InputMode::Filter(current.to_string());
+    }
+
+    /// Append typed text to the filter
+    pub fn push_filter_text(&mut self, text: &str) {
+        if let InputMode::Filter(pattern) = &mut self.mode {
+            pattern.extend(text.chars().filter(|c| !c.is_control()));
+        }
+    }
+
+    /// Remove the last character of the filter
+    pub fn pop_filter_char(&mut self) {
+        if let InputMode::Filter(pattern) = &mut self.mode {
+            pattern.pop();
+        }
+    }
+
+    /// Leave filter mode. Returns the typed filter (empty for none), or
+    /// None if not typing one.
+    pub fn finish_filter(&mut self) -> Option<String> {
+        match std::mem::take(&mut self.mode) {
+            InputMode::Filter(pattern) => Some(pattern),
+            _ => None,
+        }
+    }
+
+    /// Enter crop mode, with nothing selected yet
+    pub fn start_crop(&mut self) {
+        self.mode = InputMode::Crop(None);
+        self.needs_render = true;
+    }
+
+    /// Start a new crop selection at a window position
+    pub fn begin_crop_selection(&mut self, at: (i32, i32)) {
+        if let InputMode::Crop(selection) = &mut self.mode {
+            *selection = Some(CropSelection { start: at, end: at });
+            self.needs_render = true;
+        }
+    }
+
+    /// Move the dragged corner of the crop selection
+    pub fn drag_crop_selection(&mut self, to: (i32, i32)) {
+        if let InputMode::Crop(Some(selection)) = &mut self.mode {
+            selection.end = to;
+            self.needs_render = true;
+        }
+    }
+
+    /// The current crop selection, if in crop mode and one was dragged
+    pub fn crop_selection(&self) -> Option<CropSelection> {
+        match self.mode {
+            InputMode::Crop(selection) => selection,
+            _ => None,
+        }
+    }
+
+    /// Whether the mouse is selecting a region to crop
+    pub fn is_cropping(&self) -> bool {
+        matches!(self.mode, InputMode::Crop(_))
+    }
+
+    /// Leave goto, filter or crop mode
+    pub fn cancel_mode(&mut self) {
+        if matches!(self.mode, InputMode::Crop(_)) {
+            self.needs_render = true;
+        }
+        self.mode = InputMode::Normal;
+    }
+
+    /// Delta to the nearest of `positions` after the current one (before
+    /// it, if not `forward`), e.g. the marked images' positions. Goes round
+    /// the ends if `wrap`; None if there's none that way.
+    pub fn delta_to_nearest(
+        &self,
+        positions: impl IntoIterator<Item = usize>,
+        forward: bool,
+        wrap: bool,
+    ) -> Option<i32> {
+        let (current, total) = (self.current_index, self.total_images);
+        // Steps away in the direction of travel; ones behind are most of a
+        // lap away
+        let steps = |p: usize| {
+            if forward {
+                (p + total - current) % total
+            } else {
+                (current + total - p) % total
+            }
+        };
+        positions
+            .into_iter()
+            .filter(|&p| p != current && p < total)
+            .filter(|&p| wrap || (p > current) == forward)
+            .min_by_key(|&p| steps(p))
+            .map(|p| self.delta_to(p))
+    }
+
+    /// Mark or unmark an image by slot id. Returns whether it is now marked.
+    pub fn toggle_mark(&mut self, id: usize) -> bool {
+        if self.marked.remove(&id) {
+            return false;
+        }
+        self.marked.insert(id);
+        true
+    }
+
+    /// Compare key on the image with slot id `id`: pin it, or show the
+    /// pinned image beside it, or leave compare mode. Pressing it on the
+    /// pinned image itself unpins it.
+    pub fn toggle_compare(&mut self, id: usize) {
+        self.compare = match self.compare {
+            None => Some(Compare::Pinned(id)),
+            Some(Compare::Pinned(pinned)) if pinned != id => Some(Compare::Split(pinned)),
+            Some(_) => None,
+        };
+        self.pinned_render_quality = None;
+        self.end_blink();
+        self.needs_render = true;
+    }
+
+    /// Back to viewing just the current image
+    pub fn exit_compare(&mut self) {
+        self.compare = None;
+        self.pinned_render_quality = None;
+        self.end_blink();
+        self.needs_render = true;
+    }
+
+    /// Slot id of the pinned image, whether or not it's shown yet
+    pub fn pinned(&self) -> Option<usize> {
+        match self.compare {
+            Some(Compare::Pinned(id) | Compare::Split(id)) => Some(id),
+            None => None,
+        }
+    }
+
+    /// Slot id of the image shown beside the current one, in split view
+    pub fn compared(&self) -> Option<usize> {
+        match self.compare {
+            Some(Compare::Split(id)) => Some(id),
+            _ => None,
+        }
+    }
+
+    /// Blink key pressed: swap between the pinned image and the current
+    /// one in place, and keep swapping every `interval` while the key is
+    /// held. False if no image is pinned, or it's already side by side.
+    pub fn start_blink(&mut self, interval: Duration, now: Instant) -> bool {
+        if !matches!(self.compare, Some(Compare::Pinned(_))) {
+            return false;
+        }
+        // Key repeats leave the held key's timer alone
+        if self.next_blink_at.is_none() {
+            self.blink = !self.blink;
+            self.next_blink_at = Some(now + interval);
+            self.needs_render = true;
+        }
+        true
+    }
+
+    /// Blink key released: stop swapping, showing whichever image is up
+    pub fn stop_blink(&mut self) {
+        self.next_blink_at = None;
+    }
+
+    /// Whether the held blink key swapped the images now. Schedules the
+    /// next swap when it did.
+    pub fn blink_due(&mut self, interval: Duration, now: Instant) -> bool {
+        match self.next_blink_at {
+            Some(due) if now >= due => {
+                self.blink = !self.blink;
+                self.next_blink_at = Some(now + interval);
+                self.needs_render = true;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Slot id of the pinned image while it's blinked in place of the
+    /// current one
+    pub fn blinked(&self) -> Option<usize> {
+        match self.compare {
+            Some(Compare::Pinned(id)) if self.blink => Some(id),
+            _ => None,
+        }
+    }
+
+    /// Show the current image again and stop swapping
+    fn end_blink(&mut self) {
+        self.blink = false;
+        self.next_blink_at = None;
+    }
+
+    /// Mark render complete with given quality
+    pub fn render_complete(&mut self, quality: crate::config::QualityTier) {
+        self.needs_render = false;
+        self.last_render_quality = Some(quality);
+        self.decode_failed = false;
+        if let Some(at) = self.navigated_at.take() {
+            self.first_pixel = Some(at.elapsed());
+        }
+    }
+
+    /// Mark render complete with the failure placeholder shown
+    pub fn render_failed(&mut self) {
+        self.needs_render = false;
+        self.last_render_quality = None;
+        self.display_scale = None;
+        self.decode_failed = true;
+        self.navigated_at = None;
+    }
+
+    /// Check if we need to re-render for quality upgrade, of the current
+    /// image or the one beside it
+    pub fn needs_quality_upgrade(&self) -> bool {
+        if self.gallery.is_some() {
+            return false;
+        }
+        let wanted = self.wanted_quality();
+        let below =
+            |quality: Option<crate::config::QualityTier>| quality.is_some_and(|q| q < wanted);
+        below(self.last_render_quality)
+            || (self.compared().is_some() && below(self.pinned_render_quality))
+    }
+
+    /// The tier the shown image should reach: fitted to the window, or
+    /// full resolution once zoomed in past fit
+    pub fn wanted_quality(&self) -> crate::config::QualityTier {
+        if self.zoomed_in() {
+            crate::config::QualityTier::Full
+        } else {
+            crate::config::QualityTier::display_fit(
+                self.window_width,
+                self.window_height,
+                self.tiers,
+            )
+        }
+    }
+
+    /// Whether the image is magnified past fitting the window
+    pub fn zoomed_in(&self) -> bool {
+        self.transform.zoom > 1.0 || self.transform.actual_size
+    }
+
+    /// Window title from `title_format`, with `context` for what the view
+    /// doesn't know about the image
+    pub fn title(&self, context: &TitleContext) -> String {
+        if self.total_images == 0 {
+            return "Fiv - No images found".to_string();
+        }
+        self.title_format.render(|field| match field {
+            Field::Filename => Some(context.filename.to_string()),
+            Field::Path => Some(context.path.to_string()),
+            Field::Index => Some((self.current_index + 1).to_string()),
+            Field::Total => Some(self.total_images.to_string()),
+            Field::Width => context.dimensions.map(|(w, _)| w.to_string()),
+            Field::Height => context.dimensions.map(|(_, h)| h.to_string()),
+            Field::Size => context.file_size.map(crate::decode::format_file_size),
+            Field::Quality => indicator(self.quality_indicator()),
+            Field::Zoom => self
+                .display_scale
+                .map(|scale| format!("{:.0}", scale * 100.0)),
+            Field::Filter => self
+                .unfiltered_total
+                .map(|all| format!("[filtered: {}/{}]", self.total_images, all)),
+            Field::Gallery => self.gallery.is_some().then(|| "[gallery]".to_string()),
+            Field::Compare => match self.compare {
+                Some(Compare::Pinned(_)) if self.blink => indicator("[showing pinned]"),
+                Some(Compare::Pinned(_)) => indicator("[pinned]"),
+                Some(Compare::Split(_)) => indicator("[compare]"),
+                None => None,
+            },
+            Field::Channel => indicator(&self.channel_indicator()),
+            Field::Adjustments => indicator(&self.adjustment_indicator()),
+            Field::Slideshow => match self.slideshow {
+                Some(Slideshow {
+                    next_advance: Some(_),
+                    ..
+                }) => indicator("\u{25b6}"),
+                Some(_) => indicator("\u{23f8}"),
+                None => None,
+            },
+            // The goto, filter and crop prompts take the place of any notice
+            Field::Notice => match (&self.mode, &self.notice) {
+                (InputMode::Goto(number), _) => Some(format!("- goto: {}_", number)),
+                (InputMode::Filter(pattern), _) => Some(format!("- filter: {}_", pattern)),
+                (InputMode::Crop(_), Some((text, _))) => Some(format!("- crop: {}", text)),
+                (InputMode::Crop(_), None) => {
+                    indicator("- crop: drag to select, Enter saves, Escape cancels")
+                }
+                (InputMode::Normal, Some((text, _))) => Some(format!("- {}", text)),
+                (InputMode::Normal, None) => None,
+            },
+        })
+    }
+
+    /// Status bar text, after the file name: position, star rating, number
+    /// of similar images, dimensions, zoom, channel mode, adjustments, file
+    /// size and loading quality
+    pub fn status_text(
+        &self,
+        rating: u8,
+        similar: usize,
+        dimensions: Option<(u32, u32)>,
+        file_size: Option<u64>,
+    ) -> String {
+        let mut text = format!("[{}/{}]", self.current_index + 1, self.total_images);
+        if rating > 0 {
+            text.push(' ');
+            text.push_str(&crate::xmp::stars(rating));
+        }
+        if similar > 0 {
+            text.push_str(&format!(" [{} similar]", similar));
+        }
+        if let Some((w, h)) = dimensions {
+            text.push_str(&format!(" {}x{}", w, h));
+        }
+        text.push_str(&self.zoom_indicator());
+        text.push_str(&self.channel_indicator());
+        text.push_str(&self.adjustment_indicator());
+        if let Some(size) = file_size {
+            text.push(' ');
+            text.push_str(&crate::decode::format_file_size(size));
+        }
+        text.push_str(self.quality_indicator());
+        text
+    }
+
+    /// " [preview]" and the like while the shown image isn't final
+    fn quality_indicator(&self) -> &'static str {
+        match self.last_render_quality {
+            Some(crate::config::QualityTier::Thumbnail) => " [loading...]",
+            Some(crate::config::QualityTier::Preview) => " [preview]",
+            // Fitted to a smaller window, or shown zoomed in
+            Some(quality @ crate::config::QualityTier::DisplayFit(_))
+                if quality < self.wanted_quality() =>
+            {
+                " [preview]"
+            }
+            _ if self.decode_failed => " [decode failed]",
+            _ if self.over_budget => " [image exceeds memory budget]",
+            _ if self.embedded_preview => " [embedded preview]",
+            _ => "",
+        }
+    }
+
+    /// " 50%", once the image has been rendered
+    fn zoom_indicator(&self) -> String {
+        self.display_scale
+            .map(|scale| format!(" {:.0}%", scale * 100.0))
+            .unwrap_or_default()
     }
 
-    /// Mark render complete with given quality
-    pub fn render_complete(&mut self, quality: crate::config::QualityTier) {
-        self.needs_render = false;
-        self.last_render_quality = Some(quality);
+    /// " [alpha]" unless the image is shown in color
+    fn channel_indicator(&self) -> String {
+        match self.channel_mode {
+            ChannelMode::Color => String::new(),
+            mode => format!(" [{}]", mode.name()),
+        }
     }
 
-    /// Check if we need to re-render for quality upgrade
-    pub fn needs_quality_upgrade(&self) -> bool {
-        match self.last_render_quality {
-            Some(q) => q != crate::config::QualityTier::Full,
-            None => false,
+    /// " [+1.0 EV, locked]" while the image is adjusted
+    fn adjustment_indicator(&self) -> String {
+        if self.adjustments.is_identity() {
+            return String::new();
         }
+        let lock = if self.adjustments_locked {
+            ", locked"
+        } else {
+            ""
+        };
+        format!(" [{}{}]", self.adjustments.describe(), lock)
     }
+}
 
-    /// Get formatted title string
-    pub fn title(&self, filename: &str) -> String {
-        let quality_indicator = match self.last_render_quality {
-            Some(crate::config::QualityTier::Thumbnail) => " [loading...]",
-            Some(crate::config::QualityTier::Preview) => " [preview]",
-            _ => "",
-        };
+/// An indicator for the title without its leading space, None if it's
+/// empty
+fn indicator(text: &str) -> Option<String> {
+    Some(text.trim_start().to_string()).filter(|text| !text.is_empty())
+}
 
-        if self.total_images == 0 {
-            "Fiv - No images found".to_string()
-        } else {
-            format!(
-                "Fiv - {} [{}/{}]{}",
-                filename,
-                self.current_index + 1,
-                self.total_images,
-                quality_indicator
-            )
-        }
-    }
+/// What the window title shows about the current image beyond the view
+/// itself, from the store
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TitleContext<'a> {
+    /// The name shown for the image
+    pub filename: &'a str,
+    pub path: &'a str,
+    /// Full image size, once known
+    pub dimensions: Option<(u32, u32)>,
+    pub file_size: Option<u64>,
 }
 
 /// Navigation direction for predictive loading
@@ -295,6 +1800,27 @@ pub struct SharedState {
     shutdown: AtomicUsize,
     /// Total number of images (for wrap-around detection)
     total: AtomicUsize,
+    /// Threads in the preloader's decode pool (for the info overlay)
+    decode_threads: AtomicUsize,
+    /// Loads left in the preloader's current pass (for the statistics
+    /// overlay)
+    queued: AtomicUsize,
+    /// Positions of the gallery's cells, start and end (equal while no
+    /// gallery is shown)
+    grid_start: AtomicUsize,
+    grid_end: AtomicUsize,
+    /// Images the filmstrip shows either side of the current one, 0 while
+    /// it's hidden
+    filmstrip: AtomicUsize,
+    /// Longest side of the window, sizing the display-fit tier (0 until
+    /// the window is up)
+    window_side: AtomicUsize,
+    /// Whether the current image is zoomed in past fitting the window
+    zoomed_in: AtomicBool,
+    /// Set when something changed since the preloader last waited
+    changed: Mutex<bool>,
+    /// Wakes the preloader when `changed` is set
+    wake: Condvar,
 }
 
 impl SharedState {
@@ -306,14 +1832,69 @@ impl SharedState {
             direction: AtomicUsize::new(0),
             shutdown: AtomicUsize::new(0),
             total: AtomicUsize::new(0),
+            decode_threads: AtomicUsize::new(0),
+            queued: AtomicUsize::new(0),
+            grid_start: AtomicUsize::new(0),
+            grid_end: AtomicUsize::new(0),
+            filmstrip: AtomicUsize::new(0),
+            window_side: AtomicUsize::new(0),
+            zoomed_in: AtomicBool::new(false),
+            changed: Mutex::new(false),
+            wake: Condvar::new(),
         }
     }
 
+    /// Wake the preloader if it is waiting
+    fn notify(&self) {
+        *self.changed.lock().unwrap() = true;
+        self.wake.notify_all();
+    }
+
+    /// Block until the main thread signals a change or `timeout` passes
+    /// (preloader). A change made before the call returns immediately.
+    pub fn wait_for_change(&self, timeout: Duration) {
+        let changed = self.changed.lock().unwrap();
+        let (mut changed, _) = self
+            .wake
+            .wait_timeout_while(changed, timeout, |changed| !*changed)
+            .unwrap();
+        *changed = false;
+    }
+
     /// Set total number of images
     pub fn set_total(&self, total: usize) {
         self.total.store(total, Ordering::SeqCst);
     }
 
+    /// Record the size of the decode thread pool (preloader)
+    pub fn set_decode_threads(&self, threads: usize) {
+        self.decode_threads.store(threads, Ordering::SeqCst);
+    }
+
+    /// Size of the decode thread pool, 0 before the preloader starts
+    pub fn decode_threads(&self) -> usize {
+        self.decode_threads.load(Ordering::SeqCst)
+    }
+
+    /// Record how many loads the current pass has left (preloader)
+    pub fn set_queued(&self, loads: usize) {
+        self.queued.store(loads, Ordering::Relaxed);
+    }
+
+    /// Loads left in the preloader's current pass
+    pub fn queued(&self) -> usize {
+        self.queued.load(Ordering::Relaxed)
+    }
+
+    /// Set the starting index without implying a navigation direction
+    pub fn set_start(&self, index: usize) {
+        self.previous_index.store(index, Ordering::SeqCst);
+        self.current_index.store(index, Ordering::SeqCst);
+        self.direction.store(0, Ordering::SeqCst);
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        self.notify();
+    }
+
     /// Update current index and track direction (main thread)
     pub fn set_current(&self, index: usize) {
         let prev = self.current_index.load(Ordering::SeqCst);
@@ -336,6 +1917,84 @@ impl SharedState {
         self.current_index.store(index, Ordering::SeqCst);
         self.direction.store(dir, Ordering::SeqCst);
         self.generation.fetch_add(1, Ordering::SeqCst);
+        self.notify();
+    }
+
+    /// Counter bumped on every navigation, to spot stale plans (preloader)
+    pub fn generation(&self) -> usize {
+        self.generation.load(Ordering::SeqCst)
+    }
+
+    /// Have the preloader plan afresh without navigating, e.g. when another
+    /// image went on screen (main thread)
+    pub fn replan(&self) {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        self.notify();
+    }
+
+    /// Have the preloader load the thumbnails of the gallery's cells
+    /// first, or with None load around the current image again (main
+    /// thread). Replans if the cells changed.
+    pub fn set_grid(&self, cells: Option<Range<usize>>) {
+        let cells = cells.unwrap_or(0..0);
+        let start = self.grid_start.swap(cells.start, Ordering::SeqCst);
+        let end = self.grid_end.swap(cells.end, Ordering::SeqCst);
+        if (start..end) != cells {
+            self.replan();
+        }
+    }
+
+    /// Positions of the gallery's cells, while it's shown (preloader)
+    pub fn grid(&self) -> Option<Range<usize>> {
+        let start = self.grid_start.load(Ordering::SeqCst);
+        let end = self.grid_end.load(Ordering::SeqCst);
+        (start < end).then_some(start..end)
+    }
+
+    /// Have the preloader load thumbnails for the filmstrip's `reach`
+    /// images either side of the current one, 0 for none (main thread).
+    /// Replans if it changed.
+    pub fn set_filmstrip(&self, reach: usize) {
+        if self.filmstrip.swap(reach, Ordering::SeqCst) != reach {
+            self.replan();
+        }
+    }
+
+    /// Images the filmstrip shows either side of the current one (preloader)
+    pub fn filmstrip(&self) -> usize {
+        self.filmstrip.load(Ordering::SeqCst)
+    }
+
+    /// Record the window size, which the display-fit tier follows (main
+    /// thread). Replans if it grew; what's loaded suffices for a smaller
+    /// window.
+    pub fn set_window_size(&self, width: u32, height: u32) {
+        let side = width.max(height) as usize;
+        if self.window_side.swap(side, Ordering::SeqCst) < side {
+            self.replan();
+        }
+    }
+
+    /// Record whether the current image is zoomed in past fit, so needs
+    /// its full resolution (main thread). Replans if it changed.
+    pub fn set_zoomed_in(&self, zoomed_in: bool) {
+        if self.zoomed_in.swap(zoomed_in, Ordering::SeqCst) != zoomed_in {
+            self.replan();
+        }
+    }
+
+    /// The tier nearby images are decoded at in place of full: fitted to
+    /// the window, or full before there is one (preloader)
+    pub fn display_tier(&self, sizes: TierSizes) -> crate::config::QualityTier {
+        match self.window_side.load(Ordering::SeqCst) {
+            0 => crate::config::QualityTier::Full,
+            side => crate::config::QualityTier::display_fit(side as u32, 0, sizes),
+        }
+    }
+
+    /// Whether the current image is zoomed in past fit (preloader)
+    pub fn zoomed_in(&self) -> bool {
+        self.zoomed_in.load(Ordering::SeqCst)
     }
 
     /// Get current index (preloader)
@@ -355,6 +2014,7 @@ impl SharedState {
     /// Signal shutdown (main thread)
     pub fn shutdown(&self) {
         self.shutdown.store(1, Ordering::SeqCst);
+        self.notify();
     }
 
     /// Check if shutdown was requested (preloader)
@@ -372,8 +2032,15 @@ impl Default for SharedState {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::config::InputConfig;
-    use std::time::Duration;
+    use crate::config::{InputConfig, ViewConfig};
+
+    /// Title context with just a file name
+    fn named(filename: &str) -> TitleContext<'_> {
+        TitleContext {
+            filename,
+            ..TitleContext::default()
+        }
+    }
 
     #[test]
     fn test_navigation() {
@@ -396,6 +2063,845 @@ mod tests {
         // Wrap backward
         state.navigate(-1);
         assert_eq!(state.current_index, 9);
+
+        // Jumps to either end
+        state.navigate(i32::MIN);
+        assert_eq!(state.current_index, 0);
+    }
+
+    #[test]
+    fn test_first_pixel_timed() {
+        let mut state = ViewState::new(10, 800, 600);
+        state.navigate(1);
+        assert!(state.first_pixel.is_none());
+
+        // The first frame of the new image is timed, later upgrades aren't
+        state.render_complete(crate::config::QualityTier::Preview);
+        let first = state.first_pixel.unwrap();
+        state.render_complete(crate::config::QualityTier::Full);
+        assert_eq!(state.first_pixel, Some(first));
+
+        // A failure shows no pixels of the image
+        state.navigate(1);
+        state.render_failed();
+        state.render_complete(crate::config::QualityTier::Full);
+        assert_eq!(state.first_pixel, Some(first));
+    }
+
+    #[test]
+    fn test_shared_state_display_tier() {
+        let state = SharedState::new();
+        assert_eq!(
+            state.display_tier(TierSizes::default()),
+            crate::config::QualityTier::Full
+        );
+        state.set_window_size(1600, 900);
+        assert_eq!(
+            state.display_tier(TierSizes::default()),
+            crate::config::QualityTier::DisplayFit(1600)
+        );
+
+        // Growing replans, shrinking doesn't
+        let generation = state.generation();
+        state.set_window_size(1200, 800);
+        assert_eq!(state.generation(), generation);
+        state.set_window_size(2560, 1440);
+        assert_eq!(state.generation(), generation + 1);
+        state.set_zoomed_in(true);
+        assert!(state.zoomed_in());
+        assert_eq!(state.generation(), generation + 2);
+    }
+
+    #[test]
+    fn test_shared_state_wakes_waiter() {
+        use std::sync::Arc;
+
+        let shared = Arc::new(SharedState::new());
+
+        // A change made before waiting isn't lost
+        shared.set_current(3);
+        let start = Instant::now();
+        shared.wait_for_change(Duration::from_secs(10));
+        assert!(start.elapsed() < Duration::from_secs(5));
+
+        // Nothing pending: waits out the timeout
+        let start = Instant::now();
+        shared.wait_for_change(Duration::from_millis(20));
+        assert!(start.elapsed() >= Duration::from_millis(20));
+
+        // Shutdown wakes a waiting thread promptly
+        let waiter = {
+            let shared = shared.clone();
+            std::thread::spawn(move || {
+                let start = Instant::now();
+                shared.wait_for_change(Duration::from_secs(10));
+                start.elapsed()
+            })
+        };
+        std::thread::sleep(Duration::from_millis(10));
+        shared.shutdown();
+        assert!(waiter.join().unwrap() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_navigation_matches_stepping() {
+        for total in 1..=7 {
+            let span = 3 * total as i32;
+            for start in 0..total {
+                for delta in -span..=span {
+                    // Reference: one image at a time
+                    let mut expected = start;
+                    for _ in 0..delta.unsigned_abs() {
+                        expected = if delta > 0 {
+                            (expected + 1) % total
+                        } else {
+                            (expected + total - 1) % total
+                        };
+                    }
+
+                    let mut state = ViewState::new(total, 800, 600);
+                    state.current_index = start;
+                    state.navigate(delta);
+                    assert_eq!(
+                        state.current_index, expected,
+                        "total {} start {} delta {}",
+                        total, start, delta
+                    );
+                }
+            }
+        }
+
+        // Five images at index 1, seven back
+        let mut state = ViewState::new(5, 800, 600);
+        state.current_index = 1;
+        state.navigate(-7);
+        assert_eq!(state.current_index, 4);
+    }
+
+    #[test]
+    fn test_navigation_without_wrap() {
+        let mut state = ViewState::new(5, 800, 600);
+        state.wrap = false;
+
+        assert!(!state.navigate(-1));
+        assert_eq!(state.current_index, 0);
+        // Large steps stop at the end rather than failing
+        assert!(state.navigate(7));
+        assert_eq!(state.current_index, 4);
+        assert!(!state.navigate(1));
+        assert_eq!(state.current_index, 4);
+        assert!(state.navigate(-2));
+        assert_eq!(state.current_index, 2);
+        // A redraw in place still counts
+        assert!(state.navigate(0));
+    }
+
+    #[test]
+    fn test_jump_deltas() {
+        let mut state = ViewState::new(3000, 800, 600);
+        assert_eq!(state.jump_step(10), 150);
+        assert_eq!(state.jump_step(500), 500);
+        assert_eq!(ViewState::new(40, 800, 600).jump_step(10), 10);
+
+        state.current_index = 1000;
+        assert_eq!(state.delta_to_tenth(3), -100);
+        assert_eq!(state.delta_to_tenth(0), -1000);
+        assert!(state.navigate(state.delta_to_tenth(9)));
+        assert_eq!(state.current_index, 2700);
+    }
+
+    #[test]
+    fn test_random_delta() {
+        use rand::SeedableRng;
+
+        let mut rng = rand::rngs::StdRng::seed_from_u64(7);
+        let mut state = ViewState::new(4, 800, 600);
+        state.current_index = 2;
+
+        let mut seen = HashSet::new();
+        for _ in 0..200 {
+            let delta = state.random_delta(&mut rng).unwrap();
+            assert_ne!(delta, 0);
+            seen.insert(state.current_index as i32 + delta);
+        }
+        assert_eq!(seen, HashSet::from([0, 1, 3]));
+
+        assert_eq!(ViewState::new(1, 800, 600).random_delta(&mut rng), None);
+    }
+
+    #[test]
+    fn test_jumps_add_up() {
+        let config = InputConfig::default();
+        let mut input = InputState::new();
+        input.jump(3);
+        input.jump(2);
+        assert_eq!(input.process(&config), Some(5));
+        assert_eq!(input.process(&config), None);
+
+        // However far, a jump isn't taken for first or last
+        input.jump(i32::MAX);
+        input.jump(1);
+        assert_eq!(input.process(&config), Some(i32::MAX - 1));
+        input.jump(-i32::MAX);
+        input.jump(-1);
+        assert_eq!(input.process(&config), Some(i32::MIN + 1));
+    }
+
+    #[test]
+    fn test_wheel_steps() {
+        let config = InputConfig::default();
+        let mut input = InputState::new();
+
+        // Wheel notches step, fractions add up
+        input.scroll(-1.0);
+        assert_eq!(input.process(&config), Some(1));
+        input.scroll(0.6);
+        assert_eq!(input.process(&config), None);
+        input.scroll(0.6);
+        assert_eq!(input.process(&config), Some(-1));
+        // A change of direction drops the leftover
+        input.scroll(-0.5);
+        input.scroll(-0.4);
+        assert_eq!(input.process(&config), None);
+
+        // A light swipe moves exactly one image, however far it goes
+        input.start_swipe();
+        for _ in 0..10 {
+            input.swipe(-0.4);
+        }
+        assert_eq!(input.process(&config), Some(1));
+        assert_eq!(input.process(&config), None);
+
+        // Buttons queue steps like keys do
+        input.step(1);
+        input.step(1);
+        assert!(input.is_navigating());
+        assert_eq!(input.process(&config), Some(2));
+    }
+
+    #[test]
+    fn test_hold_repeat_jump() {
+        let config = InputConfig {
+            hold_threshold: Duration::from_millis(10),
+            repeat_interval: Duration::from_millis(5),
+            ..InputConfig::default()
+        };
+        let mut input = InputState::new();
+
+        // A quick press jumps once, by the step given on press
+        input.set_key(NavKey::JumpForward, 150, true);
+        input.set_key(NavKey::JumpForward, 0, false);
+        assert_eq!(input.process(&config), Some(150));
+        assert_eq!(input.process(&config), None);
+
+        // Holding repeats the step
+        input.set_key(NavKey::JumpBackward, -150, true);
+        std::thread::sleep(Duration::from_millis(15));
+        assert_eq!(input.process(&config), Some(-150));
+        std::thread::sleep(Duration::from_millis(10));
+        assert_eq!(input.process(&config), Some(-150));
+
+        // Releasing a key that isn't the active press changes nothing
+        input.set_right(true);
+        input.set_key(NavKey::JumpBackward, 0, false);
+        assert!(input.is_navigating());
+        input.set_right(false);
+        assert_eq!(input.process(&config), Some(1));
+    }
+
+    #[test]
+    fn test_zoom_clamp_and_reset() {
+        let config = ViewConfig::default();
+        let mut state = ViewState::new(10, 800, 600);
+
+        state.zoom_by(2.0, (0.0, 0.0), &config);
+        assert_eq!(state.transform.zoom, 2.0);
+
+        // Clamped to the configured range
+        state.zoom_by(1e6, (0.0, 0.0), &config);
+        assert_eq!(state.transform.zoom, config.max_zoom);
+        state.zoom_by(1e-9, (0.0, 0.0), &config);
+        assert_eq!(state.transform.zoom, config.min_zoom);
+
+        state.reset_zoom();
+        assert!(state.transform.is_fit());
+    }
+
+    #[test]
+    fn test_zoom_keeps_anchor_fixed() {
+        let config = ViewConfig::default();
+        let mut state = ViewState::new(10, 800, 600);
+
+        // The point 100px right of center stays put when doubling zoom
+        state.zoom_by(2.0, (100.0, 0.0), &config);
+        assert_eq!(state.transform.pan_x, -100.0);
+        assert_eq!(state.transform.pan_y, 0.0);
+    }
+
+    #[test]
+    fn test_actual_size_toggle() {
+        let config = ViewConfig::default();
+        let mut state = ViewState::new(10, 800, 600);
+
+        state.toggle_actual_size();
+        assert!(state.transform.actual_size);
+        assert!(state.is_zoomed());
+
+        // Zooming from 100% of an image fitted at 25% continues from 4x fit
+        state.leave_actual_size(0.25);
+        state.zoom_by(2.0, (0.0, 0.0), &config);
+        assert!(!state.transform.actual_size);
+        assert_eq!(state.transform.zoom, 8.0);
+
+        state.toggle_actual_size();
+        state.toggle_actual_size();
+        assert!(state.transform.is_fit());
+    }
+
+    #[test]
+    fn test_title_shows_zoom() {
+        let mut state = ViewState::new(10, 800, 600);
+        state.display_scale = Some(0.5);
+        assert_eq!(state.title(&named("a.jpg")), "Fiv - a.jpg [1/10] 50%");
+
+        state.render_failed();
+        assert_eq!(
+            state.title(&named("a.jpg")),
+            "Fiv - a.jpg [1/10] [decode failed]"
+        );
+
+        state.render_complete(crate::config::QualityTier::Full);
+        state.display_scale = Some(0.5);
+        state.embedded_preview = true;
+        assert_eq!(
+            state.title(&named("a.nef")),
+            "Fiv - a.nef [1/10] 50% [embedded preview]"
+        );
+
+        state.embedded_preview = false;
+        state.over_budget = true;
+        assert_eq!(
+            state.title(&named("pano.png")),
+            "Fiv - pano.png [1/10] 50% [image exceeds memory budget]"
+        );
+    }
+
+    #[test]
+    fn test_title_format() {
+        let mut state = ViewState::new(10, 800, 600);
+        state.navigate(2);
+        state.title_format = TitleFormat::new(
+            "{filename}|{path}|{index}/{total}|{width}x{height}|{size}|{quality}|{zoom}%",
+        );
+        let context = TitleContext {
+            filename: "* a.jpg",
+            path: "/photos/a.jpg",
+            dimensions: Some((4000, 3000)),
+            file_size: Some(2048),
+        };
+        state.render_complete(crate::config::QualityTier::Preview);
+        state.display_scale = Some(0.25);
+        assert_eq!(
+            state.title(&context),
+            "* a.jpg|/photos/a.jpg|3/10|4000x3000|2.0 KB|[preview]|25%"
+        );
+
+        // Before decoding, the size and zoom aren't known: their words go
+        state.display_scale = None;
+        state.title_format = TitleFormat::new("{filename} {width}x{height} {size} {zoom}% {index}");
+        assert_eq!(state.title(&named("a.jpg")), "a.jpg 3");
+        state.title_format = TitleFormat::new("{filename} - {quality}");
+        state.render_complete(crate::config::QualityTier::Full);
+        assert_eq!(state.title(&named("a.jpg")), "a.jpg -");
+
+        // Unknown placeholders show as typed
+        state.title_format = TitleFormat::new("{filename} {dimensions}");
+        assert_eq!(state.title(&named("a.jpg")), "a.jpg {dimensions}");
+
+        // Nothing to show overrides the template
+        let empty = ViewState::new(0, 800, 600);
+        assert_eq!(empty.title(&named("")), "Fiv - No images found");
+    }
+
+    #[test]
+    fn test_slideshow_timer() {
+        let mut state = ViewState::new(10, 800, 600);
+        let start = Instant::now();
+        let interval = Duration::from_secs(5);
+        assert!(!state.slideshow_due(start + interval));
+
+        state.toggle_slideshow(interval, start);
+        assert_eq!(state.title(&named("a.jpg")), "Fiv - a.jpg [1/10] \u{25b6}");
+        assert!(!state.slideshow_due(start + Duration::from_secs(4)));
+        assert!(state.slideshow_due(start + interval));
+        // The next advance is a full interval later
+        assert!(!state.slideshow_due(start + Duration::from_secs(9)));
+
+        // Manual navigation restarts the timer
+        state.restart_slideshow_timer(start + Duration::from_secs(9));
+        assert!(!state.slideshow_due(start + Duration::from_secs(13)));
+        assert!(state.slideshow_due(start + Duration::from_secs(14)));
+
+        // Paused: never due, until resumed
+        state.toggle_slideshow_pause(start);
+        assert!(!state.slideshow_due(start + Duration::from_secs(60)));
+        assert_eq!(state.title(&named("a.jpg")), "Fiv - a.jpg [1/10] \u{23f8}");
+        state.toggle_slideshow_pause(start + Duration::from_secs(60));
+        assert!(state.slideshow_due(start + Duration::from_secs(65)));
+
+        state.toggle_slideshow(interval, start);
+        assert_eq!(state.slideshow, None);
+    }
+
+    #[test]
+    fn test_toggle_mark() {
+        let mut state = ViewState::new(10, 800, 600);
+        assert!(state.toggle_mark(3));
+        assert!(state.toggle_mark(5));
+        assert!(!state.toggle_mark(3));
+        assert_eq!(state.marked, HashSet::from([5]));
+    }
+
+    #[test]
+    fn test_delta_to_nearest() {
+        let mut state = ViewState::new(10, 800, 600);
+        state.current_index = 5;
+        let marked = [1, 5, 7];
+
+        assert_eq!(state.delta_to_nearest(marked, true, false), Some(2));
+        assert_eq!(state.delta_to_nearest(marked, false, false), Some(-4));
+        state.current_index = 8;
+        // Past the last mark, only wrapping finds one
+        assert_eq!(state.delta_to_nearest(marked, true, false), None);
+        assert_eq!(state.delta_to_nearest(marked, true, true), Some(-7));
+        state.current_index = 0;
+        assert_eq!(state.delta_to_nearest(marked, false, true), Some(7));
+        // The current image alone doesn't count
+        state.current_index = 5;
+        assert_eq!(state.delta_to_nearest([5], true, true), None);
+        assert_eq!(state.delta_to_nearest([], false, true), None);
+    }
+
+    #[test]
+    fn test_notice_expires() {
+        let mut state = ViewState::new(10, 800, 600);
+        state.show_notice("Moved to trash", Duration::from_secs(2));
+        assert_eq!(
+            state.title(&named("a.jpg")),
+            "Fiv - a.jpg [1/10] - Moved to trash"
+        );
+
+        assert!(!state.expire_notice(Instant::now()));
+        assert!(state.expire_notice(Instant::now() + Duration::from_secs(3)));
+        assert_eq!(state.title(&named("a.jpg")), "Fiv - a.jpg [1/10]");
+    }
+
+    #[test]
+    fn test_status_text() {
+        let mut state = ViewState::new(120, 800, 600);
+        state.current_index = 2;
+        assert_eq!(state.status_text(0, 0, None, None), "[3/120]");
+
+        state.display_scale = Some(0.5);
+        state.last_render_quality = Some(crate::config::QualityTier::Preview);
+        assert_eq!(
+            state.status_text(3, 2, Some((4000, 3000)), Some(2048)),
+            "[3/120] *** [2 similar] 4000x3000 50% 2.0 KB [preview]"
+        );
+    }
+
+    #[test]
+    fn test_goto() {
+        let mut state = ViewState::new(2000, 800, 600);
+        state.current_index = 10;
+
+        state.start_goto();
+        for digit in [1, 4, 2, 9] {
+            state.push_goto_digit(digit);
+        }
+        state.pop_goto_digit();
+        assert_eq!(
+            state.title(&named("a.jpg")),
+            "Fiv - a.jpg [11/2000] - goto: 142_"
+        );
+        assert_eq!(state.finish_goto(), Some(131));
+        assert_eq!(state.mode, InputMode::Normal);
+
+        // Out of range clamps to the last image; 0 to the first
+        state.start_goto();
+        state.push_goto_digit(9);
+        state.push_goto_digit(9);
+        state.push_goto_digit(9);
+        state.push_goto_digit(9);
+        assert_eq!(state.finish_goto(), Some(1989));
+        state.start_goto();
+        state.push_goto_digit(0);
+        assert_eq!(state.finish_goto(), Some(-10));
+
+        // Nothing typed, nowhere to go
+        state.start_goto();
+        assert_eq!(state.finish_goto(), None);
+        assert_eq!(state.finish_goto(), None);
+    }
+
+    #[test]
+    fn test_display_fit_until_zoomed_in() {
+        use crate::config::QualityTier;
+
+        let mut state = ViewState::new(10, 1600, 900);
+        state.render_complete(QualityTier::display_fit(1600, 900, TierSizes::default()));
+        assert!(!state.needs_quality_upgrade());
+        assert_eq!(state.title(&named("a.jpg")), "Fiv - a.jpg [1/10]");
+
+        // A larger window wants a larger fit
+        state.resize(2560, 1440);
+        assert!(state.needs_quality_upgrade());
+        state.resize(1600, 900);
+
+        // Zoomed in, only the full resolution will do
+        state.transform.zoom = 2.0;
+        assert!(state.zoomed_in());
+        assert!(state.needs_quality_upgrade());
+        assert_eq!(state.title(&named("a.jpg")), "Fiv - a.jpg [1/10] [preview]");
+        state.render_complete(QualityTier::Full);
+        assert!(!state.needs_quality_upgrade());
+    }
+
+    #[test]
+    fn test_filter_prompt() {
+        let mut state = ViewState::new(34, 800, 600);
+        state.start_filter("bea");
+        state.push_filter_text("ch\r");
+        state.push_filter_text("X");
+        state.pop_filter_char();
+        assert_eq!(
+            state.title(&named("a.jpg")),
+            "Fiv - a.jpg [1/34] - filter: beach_"
+        );
+        assert_eq!(state.finish_filter(), Some("beach".to_string()));
+        assert_eq!(state.mode, InputMode::Normal);
+        assert_eq!(state.finish_filter(), None);
+
+        state.unfiltered_total = Some(2100);
+        assert_eq!(
+            state.title(&named("a.jpg")),
+            "Fiv - a.jpg [1/34] [filtered: 34/2100]"
+        );
+    }
+
+    #[test]
+    fn test_compare() {
+        use crate::config::QualityTier;
+
+        let mut state = ViewState::new(10, 800, 600);
+        state.toggle_compare(3);
+        assert_eq!(state.compare, Some(Compare::Pinned(3)));
+        assert_eq!(state.compared(), None);
+        assert_eq!(state.title(&named("a.jpg")), "Fiv - a.jpg [1/10] [pinned]");
+        // On the pinned image again, it lets go
+        state.toggle_compare(3);
+        assert_eq!(state.compare, None);
+
+        state.toggle_compare(3);
+        state.toggle_compare(7);
+        assert_eq!(state.compared(), Some(3));
+        assert_eq!(state.title(&named("a.jpg")), "Fiv - a.jpg [1/10] [compare]");
+
+        // Either side below full quality wants an upgrade
+        state.render_complete(QualityTier::Full);
+        state.pinned_render_quality = Some(QualityTier::Preview);
+        assert!(state.needs_quality_upgrade());
+        state.pinned_render_quality = Some(QualityTier::Full);
+        assert!(!state.needs_quality_upgrade());
+
+        state.exit_compare();
+        assert_eq!(state.compare, None);
+        assert!(state.needs_render);
+    }
+
+    #[test]
+    fn test_blink() {
+        let interval = Duration::from_millis(500);
+        let start = Instant::now();
+        let mut state = ViewState::new(10, 800, 600);
+        // Nothing pinned: nothing to blink with
+        assert!(!state.start_blink(interval, start));
+
+        state.toggle_compare(3);
+        state.navigate(1);
+        assert!(state.start_blink(interval, start));
+        assert_eq!(state.blinked(), Some(3));
+        assert_eq!(
+            state.title(&named("a.jpg")),
+            "Fiv - a.jpg [2/10] [showing pinned]"
+        );
+        // Key repeats don't swap back
+        assert!(state.start_blink(interval, start + Duration::from_millis(30)));
+        assert_eq!(state.blinked(), Some(3));
+        // Held, it swaps every interval
+        assert!(!state.blink_due(interval, start + Duration::from_millis(400)));
+        assert!(state.blink_due(interval, start + interval));
+        assert_eq!(state.blinked(), None);
+        assert!(state.blink_due(interval, start + interval * 2));
+        assert_eq!(state.blinked(), Some(3));
+
+        // Released, it stays on whichever is shown; a press swaps again
+        state.stop_blink();
+        assert!(!state.blink_due(interval, start + interval * 3));
+        assert_eq!(state.blinked(), Some(3));
+        assert!(state.start_blink(interval, start + interval * 4));
+        assert_eq!(state.blinked(), None);
+        state.stop_blink();
+
+        // Navigating shows the current image again
+        state.start_blink(interval, start);
+        state.navigate(1);
+        assert_eq!(state.blinked(), None);
+        assert_eq!(state.next_blink_at, None);
+
+        // Side by side there's nothing to blink
+        state.toggle_compare(2);
+        assert_eq!(state.compared(), Some(3));
+        assert!(!state.start_blink(interval, start));
+        assert_eq!(state.pinned(), Some(3));
+    }
+
+    #[test]
+    fn test_gallery() {
+        // 4 columns and 3 rows of 100px cells
+        let mut state = ViewState::new(30, 400, 300);
+        state.navigate(13);
+        state.toggle_gallery(100);
+        assert_eq!(
+            state.title(&named("a.jpg")),
+            "Fiv - a.jpg [14/30] [gallery]"
+        );
+        // Opens with the current image on screen
+        assert_eq!(state.gallery_cells(), Some(4..16));
+
+        // Rows down and up stop at either end
+        assert_eq!(state.gallery_rows_delta(1), 4);
+        assert_eq!(state.gallery_rows_delta(-5), -13);
+        assert_eq!(state.gallery_rows_delta(10), 16);
+        assert_eq!(state.gallery_page(), 3);
+
+        // Scrolls a row at a time to follow the cursor, and no further
+        // than the last row
+        state.navigate(4);
+        assert_eq!(state.gallery_cells(), Some(8..20));
+        state.navigate(12);
+        assert_eq!(state.gallery_cells(), Some(20..30));
+        state.navigate(-29);
+        assert_eq!(state.gallery_cells(), Some(0..12));
+
+        // Clicks land on cells that hold an image
+        assert_eq!(state.gallery_delta_at((250.0, 150.0)), Some(6));
+        assert_eq!(state.gallery_delta_at((450.0, 150.0)), None);
+
+        // A narrower window reflows the grid around the cursor
+        state.navigate(21);
+        state.resize(200, 300);
+        assert_eq!(state.gallery_cells(), Some(16..22));
+
+        state.toggle_gallery(100);
+        assert_eq!(state.gallery_cells(), None);
+        assert_eq!(state.title(&named("a.jpg")), "Fiv - a.jpg [22/30]");
+    }
+
+    #[test]
+    fn test_cursor_idle() {
+        let delay = Duration::from_secs(2);
+        let start = Instant::now();
+        let mut idle = CursorIdle::new(Some(delay), start);
+        assert_eq!(idle.deadline(), Some(start + delay));
+        assert!(!idle.hide_due(start + delay / 2));
+        assert!(idle.hide_due(start + delay));
+        // Once hidden there's nothing left to wait for
+        assert_eq!(idle.deadline(), None);
+        assert!(!idle.hide_due(start + delay * 2));
+
+        // Moving shows it and restarts the wait
+        let later = start + delay * 3;
+        assert!(idle.moved((10.0, 10.0), later));
+        assert_eq!(idle.deadline(), Some(later + delay));
+        assert!(idle.hide_due(later + delay));
+
+        // A move to where the pointer already was is ignored
+        assert!(!idle.moved((10.0, 10.0), later + delay * 2));
+        assert_eq!(idle.deadline(), None);
+        assert!(idle.used(later + delay * 2));
+        assert!(!idle.used(later + delay * 2));
+
+        // Without a delay it never hides
+        let mut never = CursorIdle::new(None, start);
+        assert_eq!(never.deadline(), None);
+        assert!(!never.moved((1.0, 1.0), start));
+        assert!(!never.hide_due(start + delay * 100));
+    }
+
+    #[test]
+    fn test_replace_collection() {
+        let mut state = ViewState::new(100, 800, 600);
+        state.navigate(40);
+        state.marked.insert(3);
+        state.unfiltered_total = Some(200);
+        state.toggle_compare(7);
+        state.zoom_by(2.0, (400.0, 300.0), &ViewConfig::default());
+        state.adjust_exposure(1.0);
+        state.toggle_adjustment_lock();
+        state.mode = InputMode::Goto("5".to_string());
+
+        state.replace_collection(10, 4);
+        assert_eq!((state.total_images, state.current_index), (10, 4));
+        assert!(state.marked.is_empty() && state.compare.is_none());
+        assert_eq!(state.unfiltered_total, None);
+        assert_eq!(state.mode, InputMode::Normal);
+        assert!(state.transform.is_fit());
+        // Locked adjustments carry over, like they do between images
+        assert_eq!(state.adjustments.exposure, 1.0);
+
+        state.replace_collection(3, 10);
+        assert_eq!(state.current_index, 2);
+    }
+
+    #[test]
+    fn test_filmstrip() {
+        let mut state = ViewState::new(30, 900, 600);
+        assert_eq!(state.filmstrip_layout(), None);
+        state.toggle_filmstrip();
+        // Nine 96px cells along the bottom, above the status bar if shown
+        let layout = state.filmstrip_layout().unwrap();
+        assert_eq!((layout.cell, layout.origin), (96, (18, 504)));
+        state.toggle_status_bar();
+        let layout = state.filmstrip_layout().unwrap();
+        assert_eq!(layout.origin.1, 504 - STATUS_BAR_HEIGHT);
+
+        // Wrapping around from the first image
+        assert_eq!(
+            state.filmstrip_positions(),
+            (0, vec![26, 27, 28, 29, 0, 1, 2, 3, 4])
+        );
+        // Not wrapping: nothing before the first
+        state.wrap = false;
+        assert_eq!(state.filmstrip_positions(), (4, vec![0, 1, 2, 3, 4]));
+        // Few images: each once
+        let mut few = ViewState::new(4, 900, 600);
+        few.navigate(2);
+        assert_eq!(few.filmstrip_positions(), (3, vec![1, 2, 3, 0]));
+
+        // Clicks on a thumbnail go to it, not on an empty cell
+        let y = layout.origin.1 as f64 + 40.0;
+        assert_eq!(state.filmstrip_delta_at((18.0 + 6.5 * 96.0, y)), Some(2));
+        assert_eq!(state.filmstrip_delta_at((18.0 + 4.5 * 96.0, y)), Some(0));
+        assert_eq!(state.filmstrip_delta_at((18.0 + 1.5 * 96.0, y)), None);
+        assert_eq!(state.filmstrip_delta_at((18.0 + 6.5 * 96.0, 100.0)), None);
+    }
+
+    #[test]
+    fn test_shared_grid() {
+        let state = SharedState::new();
+        assert_eq!(state.grid(), None);
+        let generation = state.generation();
+        state.set_grid(Some(8..20));
+        assert_eq!(state.grid(), Some(8..20));
+        assert_eq!(state.generation(), generation + 1);
+        // Unchanged cells don't replan
+        state.set_grid(Some(8..20));
+        assert_eq!(state.generation(), generation + 1);
+        state.set_grid(None);
+        assert_eq!(state.grid(), None);
+
+        let generation = state.generation();
+        state.set_filmstrip(4);
+        assert_eq!(state.filmstrip(), 4);
+        assert_eq!(state.generation(), generation + 1);
+        state.set_filmstrip(4);
+        assert_eq!(state.generation(), generation + 1);
+    }
+
+    #[test]
+    fn test_crop_selection() {
+        let mut state = ViewState::new(20, 800, 600);
+
+        // Dragging outside crop mode selects nothing
+        state.begin_crop_selection((10, 10));
+        assert_eq!(state.crop_selection(), None);
+
+        state.start_crop();
+        assert!(state.is_cropping());
+        assert_eq!(state.crop_selection(), None);
+        state.begin_crop_selection((300, 200));
+        state.drag_crop_selection((100, 250));
+        let selection = state.crop_selection().unwrap();
+        assert_eq!(selection.corners(), ((100, 200), (300, 250)));
+
+        state.cancel_mode();
+        assert!(!state.is_cropping());
+        assert_eq!(state.crop_selection(), None);
+    }
+
+    #[test]
+    fn test_orientation_group() {
+        let all = |o: Orientation| {
+            [
+                o.rotate_cw(),
+                o.rotate_ccw(),
+                o.flip_horizontal(),
+                o.flip_vertical(),
+            ]
+        };
+
+        // Four turns and double flips are identity
+        let id = Orientation::IDENTITY;
+        assert_eq!(id.rotate_cw().rotate_cw().rotate_cw().rotate_cw(), id);
+        assert_eq!(id.rotate_cw().rotate_ccw(), id);
+        assert_eq!(id.flip_horizontal().flip_horizontal(), id);
+        assert_eq!(id.flip_vertical().flip_vertical(), id);
+        // H then V is a half turn
+        assert_eq!(
+            id.flip_horizontal().flip_vertical(),
+            id.rotate_cw().rotate_cw()
+        );
+
+        // All eight transforms are reachable
+        let mut seen = vec![id];
+        let mut i = 0;
+        while i < seen.len() {
+            for next in all(seen[i]) {
+                if !seen.contains(&next) {
+                    seen.push(next);
+                }
+            }
+            i += 1;
+        }
+        assert_eq!(seen.len(), 8);
+
+        // Composing matches applying the steps one by one
+        for &o in &seen {
+            for &next in &seen {
+                assert_eq!(o.then(next).rotate_cw(), o.then(next.rotate_cw()));
+                assert_eq!(o.then(next).flip_vertical(), o.then(next.flip_vertical()));
+            }
+            assert_eq!(o.then(id.rotate_cw()), o.rotate_cw());
+            assert_eq!(o.then(id.flip_horizontal()), o.flip_horizontal());
+            assert_eq!(o.then(id.flip_vertical()), o.flip_vertical());
+            assert_eq!(id.then(o), o);
+            assert_eq!(Orientation::from_exif(o.to_exif() as u32), o);
+        }
+    }
+
+    #[test]
+    fn test_reset_zoom_keeps_orientation() {
+        let config = ViewConfig::default();
+        let mut state = ViewState::new(10, 800, 600);
+
+        state.reorient(Orientation::rotate_cw);
+        state.zoom_by(2.0, (0.0, 0.0), &config);
+        state.reset_zoom();
+        assert_eq!(state.transform.orientation.quarter_turns, 1);
+
+        state.reset_orientation();
+        assert!(state.transform.orientation.is_identity());
     }
 
     #[test]
@@ -403,6 +2909,7 @@ mod tests {
         let config = InputConfig {
             hold_threshold: Duration::from_millis(150),
             repeat_interval: Duration::from_millis(60),
+            ..InputConfig::default()
         };
 
         let mut input = InputState::new();
@@ -416,6 +2923,7 @@ mod tests {
         input.set_right(false);
         let result = input.process(&config);
         assert_eq!(result, Some(1)); // Click navigation
+        assert!(!input.is_repeating());
 
         // Should not navigate again
         let result = input.process(&config);
@@ -427,6 +2935,7 @@ mod tests {
         let config = InputConfig {
             hold_threshold: Duration::from_millis(10), // Short for testing
             repeat_interval: Duration::from_millis(5),
+            ..InputConfig::default()
         };
 
         let mut input = InputState::new();
@@ -440,6 +2949,7 @@ mod tests {
         // Should enter repeat mode
         let result = input.process(&config);
         assert_eq!(result, Some(1));
+        assert!(input.is_repeating());
 
         // Wait for repeat interval
         std::thread::sleep(Duration::from_millis(10));
@@ -456,6 +2966,10 @@ mod tests {
         state.set_current(5);
         assert_eq!(state.current(), 5);
 
+        state.set_start(3);
+        assert_eq!(state.current(), 3);
+        assert_eq!(state.direction(), Direction::Unknown);
+
         assert!(!state.is_shutdown());
         state.shutdown();
         assert!(state.is_shutdown());
@@ -490,4 +3004,98 @@ mod tests {
         state.set_current(9);
         assert_eq!(state.direction(), Direction::Backward);
     }
+
+    #[test]
+    fn test_animation_ticks() {
+        let mut state = ViewState::new(10, 800, 600);
+        let delays = [Duration::from_millis(100), Duration::from_millis(50)];
+        let start = Instant::now();
+
+        // First tick schedules the current frame
+        assert!(!state.tick_animation(&delays, start));
+        assert_eq!(state.next_frame_at, Some(start + delays[0]));
+
+        // Not yet due
+        assert!(!state.tick_animation(&delays, start + Duration::from_millis(50)));
+        assert_eq!(state.frame_index, 0);
+
+        // Due: advance and schedule from the deadline
+        assert!(state.tick_animation(&delays, start + Duration::from_millis(100)));
+        assert_eq!(state.frame_index, 1);
+        assert_eq!(
+            state.next_frame_at,
+            Some(start + Duration::from_millis(150))
+        );
+
+        // Wraps around
+        assert!(state.tick_animation(&delays, start + Duration::from_millis(150)));
+        assert_eq!(state.frame_index, 0);
+
+        // Paused images don't advance
+        state.toggle_animation_pause();
+        assert!(!state.tick_animation(&delays, start + Duration::from_secs(10)));
+        assert_eq!(state.frame_index, 0);
+
+        // Navigation restarts from the first frame
+        state.toggle_animation_pause();
+        state.frame_index = 1;
+        state.navigate(1);
+        assert_eq!(state.frame_index, 0);
+        assert_eq!(state.next_frame_at, None);
+    }
+
+    #[test]
+    fn test_still_images_do_not_tick() {
+        let mut state = ViewState::new(10, 800, 600);
+        assert!(!state.tick_animation(&[], Instant::now()));
+        assert_eq!(state.next_frame_at, None);
+    }
+
+    #[test]
+    fn test_adjustments() {
+        let mut state = ViewState::new(3, 100, 100);
+        assert!(state.adjustments.lut().is_none());
+
+        state.adjust_exposure(0.5);
+        state.adjust_exposure(0.5);
+        state.adjust_gamma(1.1);
+        state.adjust_contrast(0.2);
+        assert_eq!(
+            state.adjustments.describe(),
+            "+1.0 EV, \u{3b3} 1.10, contrast +20%"
+        );
+        assert!(state.adjustments.lut().is_some());
+
+        // Stepping back lands on the neutral values exactly
+        state.adjust_gamma(1.0 / 1.1);
+        state.adjust_contrast(-0.1);
+        state.adjust_contrast(-0.1);
+        assert_eq!(state.adjustments.describe(), "+1.0 EV");
+        state.toggle_adjustment_lock();
+        assert_eq!(
+            state.title(&named("a.png")),
+            "Fiv - a.png [1/3] [+1.0 EV, locked]"
+        );
+
+        state.adjust_exposure(-20.0);
+        assert_eq!(state.adjustments.exposure, -8.0);
+        state.reset_adjustments();
+        assert!(state.adjustments.is_identity());
+    }
+
+    #[test]
+    fn test_channel_modes() {
+        let mut state = ViewState::new(3, 100, 100);
+        let mut seen = vec![state.channel_mode];
+        while state.cycle_channel_mode() != ChannelMode::Color {
+            seen.push(state.channel_mode);
+        }
+        assert_eq!(seen.len(), 7);
+
+        state.cycle_channel_mode();
+        assert_eq!(state.title(&named("a.png")), "Fiv - a.png [1/3] [red]");
+        assert!(!state.look().is_identity());
+        state.reset_channel_mode();
+        assert_eq!(state.title(&named("a.png")), "Fiv - a.png [1/3]");
+    }
 }
@@ -4,24 +4,33 @@
 //! and manages memory allocation. It provides a consistent view of all images
 //! that can be accessed without locking.
 
-use crate::config::Config;
-use crate::slot::{ImageData, ImageMeta, ImageSlot};
+use crate::config::{Config, QualityTier, TierSizes};
+use crate::decode::DecodeError;
+use crate::filter::NameFilter;
+use crate::formats;
+use crate::phash::hamming;
+use crate::slot::{now_millis, ImageData, ImageMeta, ImageSlot};
+use arc_swap::ArcSwap;
+use std::path::Path;
 use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 /// Memory budget tracker using atomic operations.
 pub struct MemoryBudget {
-    /// Total budget in bytes
-    total: usize,
+    /// Total budget in bytes; may change at runtime (see `set_total`)
+    total: AtomicUsize,
     /// Currently used bytes (atomic for lock-free tracking)
     used: AtomicUsize,
+    /// Bytes held back for the pinned image's full decode
+    reserved: AtomicUsize,
 }
 
 impl MemoryBudget {
     pub fn new(total: usize) -> Self {
         Self {
-            total,
+            total: AtomicUsize::new(total),
             used: AtomicUsize::new(0),
+            reserved: AtomicUsize::new(0),
         }
     }
 
@@ -29,21 +38,65 @@ impl MemoryBudget {
         Self::new(config.memory.calculate_budget())
     }
 
+    #[inline]
+    pub fn total(&self) -> usize {
+        self.total.load(Ordering::Relaxed)
+    }
+
+    /// Change the budget. Shrinking it below what is in use only stops new
+    /// allocations; call `ImageStore::make_room` to get back under it.
+    pub fn set_total(&self, total: usize) {
+        self.total.store(total, Ordering::SeqCst);
+    }
+
     #[inline]
     pub fn used(&self) -> usize {
         self.used.load(Ordering::Relaxed)
     }
 
+    /// Bytes free for anything but the pinned image
     #[inline]
     pub fn available(&self) -> usize {
-        self.total.saturating_sub(self.used())
+        self.total()
+            .saturating_sub(self.used())
+            .saturating_sub(self.reserved.load(Ordering::Relaxed))
     }
 
-    /// Try to allocate memory. Returns true if successful.
+    /// Whether `bytes` more would fit beside what is used and reserved.
+    /// Unlike `available() >= bytes`, false while over budget even for 0.
+    #[inline]
+    pub fn fits(&self, bytes: usize) -> bool {
+        self.used()
+            .checked_add(self.reserved.load(Ordering::Relaxed))
+            .and_then(|held| held.checked_add(bytes))
+            .is_some_and(|wanted| wanted <= self.total())
+    }
+
+    /// Hold back bytes that only `try_allocate_reserved` may use
+    pub fn set_reserved(&self, bytes: usize) {
+        self.reserved.store(bytes, Ordering::Relaxed);
+    }
+
+    /// Try to allocate memory, leaving the reservation alone.
+    /// Returns true if successful.
     pub fn try_allocate(&self, bytes: usize) -> bool {
+        let reserved = self.reserved.load(Ordering::Relaxed);
+        self.try_allocate_within(bytes.saturating_add(reserved), bytes)
+    }
+
+    /// Try to allocate memory for the pinned image, which may use the
+    /// reservation. Returns true if successful.
+    pub fn try_allocate_reserved(&self, bytes: usize) -> bool {
+        self.try_allocate_within(bytes, bytes)
+    }
+
+    /// Allocate `bytes` if `needed` more would still fit in the total.
+    /// The total is re-read on every attempt, so an allocation racing a
+    /// `set_total` sees either the old or the new limit, never a torn one.
+    fn try_allocate_within(&self, needed: usize, bytes: usize) -> bool {
         let mut current = self.used.load(Ordering::Relaxed);
         loop {
-            if current + bytes > self.total {
+            if current.saturating_add(needed) > self.total() {
                 return false;
             }
             match self.used.compare_exchange_weak(
@@ -58,6 +111,12 @@ impl MemoryBudget {
         }
     }
 
+    /// Record memory that is already in use, even past the total (e.g. a
+    /// smaller copy that replaces a released buffer)
+    pub fn allocate(&self, bytes: usize) {
+        self.used.fetch_add(bytes, Ordering::SeqCst);
+    }
+
     /// Release previously allocated memory
     pub fn release(&self, bytes: usize) {
         self.used.fetch_sub(bytes, Ordering::SeqCst);
@@ -65,150 +124,823 @@ impl MemoryBudget {
 }
 
 /// The image store - holds all slots and manages memory.
+///
+/// Slots never move once created. Browsing order goes through an
+/// indirection table (position -> slot id) so the collection can be
+/// reordered while the preloader is running. Indices passed to `get`,
+/// `slot`, `read` and `insert` are positions; `*_by_id` methods address a
+/// slot directly and are stable across reorders.
+///
+/// Images can be added and removed at runtime (see `add` and
+/// `remove_path`). Readers always see a consistent snapshot of the slot
+/// list and order; a removed image keeps its emptied slot, so ids held by
+/// background work never point at a different image.
+///
+/// A name filter (see `set_filter`) narrows the browsing order to the
+/// matching images. The others keep their slots and place in the
+/// unfiltered order but have no position until the filter changes.
 pub struct ImageStore {
-    /// All image slots (indexed by position in directory)
-    slots: Vec<ImageSlot>,
+    /// Slots and browsing order, replaced whole when images come or go
+    layout: ArcSwap<Layout>,
+    /// Serializes changes to the layout and order
+    changes: Mutex<()>,
     /// Memory budget
     budget: Arc<MemoryBudget>,
+    /// Slot id of the displayed image, which is never evicted or
+    /// downgraded (`usize::MAX` for none)
+    pinned: AtomicUsize,
+    /// Slot id of an image shown beside the pinned one (compare mode),
+    /// likewise kept whole (`usize::MAX` for none)
+    held: AtomicUsize,
+    /// How much recency counts against distance when evicting (0 to 1)
+    recency_weight: f64,
+    /// Whether navigation wraps around, making the ends neighbours
+    wrap: bool,
+    /// Longest side of each reduced quality tier
+    tiers: TierSizes,
+}
+
+/// Position of a slot whose image has been removed
+const REMOVED: usize = usize::MAX;
+
+/// Position of a slot whose image the filter hides
+const HIDDEN: usize = usize::MAX - 1;
+
+/// A snapshot of the store's slots and browsing order
+struct Layout {
+    /// Every slot created so far, indexed by slot id
+    slots: Vec<Arc<ImageSlot>>,
+    /// Slot ids of every image not removed, in browsing order, including
+    /// those the filter hides
+    all: Vec<usize>,
+    /// Filter choosing the images shown, if any
+    filter: Option<NameFilter>,
+    /// Slot id shown at each position
+    order: Vec<AtomicUsize>,
+    /// Position of each slot id (inverse of `order`; `REMOVED` if gone,
+    /// `HIDDEN` if filtered out)
+    positions: Vec<AtomicUsize>,
+}
+
+impl Layout {
+    /// Layout showing the images of `all` (slot ids, in order) out of
+    /// `slots` that match `filter`
+    fn new(slots: Vec<Arc<ImageSlot>>, all: Vec<usize>, filter: Option<NameFilter>) -> Self {
+        let positions: Vec<AtomicUsize> = slots.iter().map(|_| AtomicUsize::new(REMOVED)).collect();
+        let mut order = Vec::with_capacity(all.len());
+        for &id in &all {
+            let shown = filter
+                .as_ref()
+                .is_none_or(|filter| filter.matches_meta(&slots[id].meta));
+            let position = if shown { order.len() } else { HIDDEN };
+            positions[id].store(position, Ordering::Relaxed);
+            if shown {
+                order.push(AtomicUsize::new(id));
+            }
+        }
+        Self {
+            slots,
+            all,
+            filter,
+            order,
+            positions,
+        }
+    }
+
+    #[inline]
+    fn is_removed(&self, id: usize) -> bool {
+        self.positions[id].load(Ordering::Acquire) == REMOVED
+    }
+
+    /// Position of a slot id, if its image is shown
+    #[inline]
+    fn shown_position(&self, id: usize) -> Option<usize> {
+        let position = self.positions[id].load(Ordering::Acquire);
+        (position < HIDDEN).then_some(position)
+    }
 }
 
 impl ImageStore {
     /// Create store with pre-populated metadata
     pub fn with_metadata(metas: Vec<ImageMeta>, budget: Arc<MemoryBudget>) -> Self {
-        let slots = metas.into_iter().map(ImageSlot::new).collect();
-        Self { slots, budget }
+        let slots: Vec<Arc<ImageSlot>> = metas
+            .into_iter()
+            .map(|meta| Arc::new(ImageSlot::new(meta)))
+            .collect();
+        let all = (0..slots.len()).collect();
+        Self {
+            layout: ArcSwap::from_pointee(Layout::new(slots, all, None)),
+            changes: Mutex::new(()),
+            budget,
+            pinned: AtomicUsize::new(usize::MAX),
+            held: AtomicUsize::new(usize::MAX),
+            recency_weight: 0.0,
+            wrap: true,
+            tiers: TierSizes::default(),
+        }
+    }
+
+    /// Let recently viewed images outlast nearer but unviewed ones when
+    /// making room (0 = distance only, 1 = recency only)
+    pub fn with_recency_weight(mut self, weight: f64) -> Self {
+        self.recency_weight = weight;
+        self
+    }
+
+    /// Whether the last and first images are neighbours (navigation wraps
+    /// around); when not, distances are measured along the list
+    pub fn with_wrap(mut self, wrap: bool) -> Self {
+        self.wrap = wrap;
+        self
+    }
+
+    /// Set the tier sizes that thumbnails and previews are cut down to
+    pub fn with_tiers(mut self, tiers: TierSizes) -> Self {
+        self.tiers = tiers;
+        self
+    }
+
+    /// Empty store for other images, sharing this one's memory budget and
+    /// settings, to replace it with
+    pub fn sibling(&self, metas: Vec<ImageMeta>) -> Self {
+        Self::with_metadata(metas, self.budget.clone())
+            .with_recency_weight(self.recency_weight)
+            .with_wrap(self.wrap)
+    }
+
+    /// Whether navigation wraps around
+    #[inline]
+    pub fn wraps(&self) -> bool {
+        self.wrap
+    }
+
+    /// Navigation distance between two positions among `total`
+    #[inline]
+    pub fn distance(&self, a: usize, b: usize, total: usize) -> usize {
+        position_distance(a, b, total, self.wrap)
+    }
+
+    /// Distance of a slot from the position `current`, for eviction.
+    /// Images the filter hides count as farther than any shown.
+    fn slot_distance(&self, layout: &Layout, id: usize, current: usize) -> usize {
+        let total = layout.order.len();
+        match layout.shown_position(id) {
+            Some(position) => self.distance(position, current, total),
+            None => total,
+        }
+    }
+
+    /// Protect the image at a position (the one on screen) from eviction
+    /// and downgrades, and reserve budget for its full decode
+    pub fn pin(&self, index: usize) {
+        // One snapshot, so a concurrent removal can't empty it under us
+        let layout = self.layout.load();
+        if layout.order.is_empty() {
+            return;
+        }
+        let id = layout.order[index % layout.order.len()].load(Ordering::Acquire);
+        self.pinned.store(id, Ordering::Release);
+        self.update_reservation();
+    }
+
+    #[inline]
+    fn is_pinned(&self, id: usize) -> bool {
+        self.pinned.load(Ordering::Acquire) == id
+    }
+
+    /// Protect a second image by slot id, shown beside the pinned one, from
+    /// eviction and downgrades; None releases it
+    pub fn hold(&self, id: Option<usize>) {
+        self.held.store(id.unwrap_or(usize::MAX), Ordering::Release);
+    }
+
+    /// Slot id of the held image, if any
+    pub fn held(&self) -> Option<usize> {
+        let id = self.held.load(Ordering::Acquire);
+        (id != usize::MAX).then_some(id)
+    }
+
+    /// Whether an image is on screen, pinned or held
+    #[inline]
+    fn is_kept(&self, id: usize) -> bool {
+        self.is_pinned(id) || self.held.load(Ordering::Acquire) == id
+    }
+
+    /// Reserve what the pinned image still needs to reach full size, as
+    /// far as its dimensions are known
+    fn update_reservation(&self) {
+        let layout = self.layout.load();
+        let reserve = layout
+            .slots
+            .get(self.pinned.load(Ordering::Acquire))
+            .and_then(|slot| {
+                let full = slot.meta.full_memory_estimate(self.tiers)?;
+                // One too large for the budget is decoded at the size that fits
+                let full = if full > self.budget.total() {
+                    self.fitting_pixels() as usize * 4
+                } else {
+                    full
+                };
+                Some(full.saturating_sub(slot.memory_used()))
+            })
+            .unwrap_or(0);
+        self.budget.set_reserved(reserve);
     }
 
-    /// Number of images
+    /// Number of images shown
     #[inline]
     pub fn len(&self) -> usize {
-        self.slots.len()
+        self.layout.load().order.len()
+    }
+
+    /// Number of images, including those the filter hides
+    pub fn unfiltered_len(&self) -> usize {
+        self.layout.load().all.len()
     }
 
-    /// Get a slot by index (wraps around)
     #[inline]
-    pub fn get(&self, index: usize) -> Option<&ImageSlot> {
-        if self.slots.is_empty() {
-            None
-        } else {
-            Some(&self.slots[index % self.slots.len()])
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of slot ids handed out, including those of removed images
+    #[inline]
+    pub fn slot_count(&self) -> usize {
+        self.layout.load().slots.len()
+    }
+
+    /// Slot id displayed at a position (wraps around; the store must not
+    /// be empty)
+    #[inline]
+    pub fn slot_id(&self, position: usize) -> usize {
+        let layout = self.layout.load();
+        layout.order[position % layout.order.len()].load(Ordering::Acquire)
+    }
+
+    /// Current position of a slot id (`usize::MAX` once its image has been
+    /// removed, `usize::MAX - 1` while the filter hides it)
+    #[inline]
+    pub fn position_of(&self, id: usize) -> usize {
+        self.layout.load().positions[id].load(Ordering::Acquire)
+    }
+
+    /// Whether a slot's image has been taken out of the store
+    #[inline]
+    pub fn is_removed(&self, id: usize) -> bool {
+        self.position_of(id) == REMOVED
+    }
+
+    /// Whether a slot's image is in the browsing order: neither removed
+    /// nor hidden by the filter
+    #[inline]
+    pub fn is_shown(&self, id: usize) -> bool {
+        self.layout.load().shown_position(id).is_some()
+    }
+
+    /// Whether any image is read from `path`, shown or not
+    pub fn contains_path(&self, path: &Path) -> bool {
+        let layout = self.layout.load();
+        layout
+            .all
+            .iter()
+            .any(|&id| layout.slots[id].meta.path() == path)
+    }
+
+    /// Positions of the shown images, other than slot `id`'s, whose
+    /// perceptual hash is at most `max_distance` bits from its hash, in
+    /// browsing order. Images not hashed yet are left out.
+    pub fn similar_positions(&self, id: usize, max_distance: u32) -> Vec<usize> {
+        let layout = self.layout.load();
+        let Some(hash) = layout.slots[id].meta.phash() else {
+            return Vec::new();
+        };
+        let near = |other: usize| {
+            other != id
+                && layout.slots[other]
+                    .meta
+                    .phash()
+                    .is_some_and(|h| hamming(h, hash) <= max_distance)
+        };
+        (0..layout.order.len())
+            .filter(|&position| near(layout.order[position].load(Ordering::Acquire)))
+            .collect()
+    }
+
+    /// The filter narrowing the browsing order, if any
+    pub fn filter(&self) -> Option<NameFilter> {
+        self.layout.load().filter.clone()
+    }
+
+    /// Show only the images `filter` matches, or all of them for None.
+    /// A filter matching nothing is not applied; returns false for one.
+    pub fn set_filter(&self, filter: Option<NameFilter>) -> bool {
+        let _changes = self.changes.lock().unwrap();
+        let layout = self.layout.load();
+        let filtered = Layout::new(layout.slots.clone(), layout.all.clone(), filter);
+        if filtered.order.is_empty() && !filtered.all.is_empty() {
+            return false;
         }
+        self.layout.store(Arc::new(filtered));
+        true
+    }
+
+    /// Position of a slot id's image or, if the filter hides it, of the
+    /// shown image nearest it in the unfiltered order (the later one on a
+    /// tie). None if it has been removed or nothing is shown.
+    pub fn nearest_position(&self, id: usize) -> Option<usize> {
+        let layout = self.layout.load();
+        let rank = layout.all.iter().position(|&other| other == id)?;
+        let shown = |other: &usize| layout.shown_position(*other).is_some();
+        let after = layout.all[rank..].iter().position(shown);
+        let before = layout.all[..rank].iter().rposition(shown).map(|n| rank - n);
+        let nearest = match (after, before) {
+            (Some(after), Some(before)) if before < after => rank - before,
+            (Some(after), _) => rank + after,
+            (None, Some(before)) => rank - before,
+            (None, None) => return None,
+        };
+        layout.shown_position(layout.all[nearest])
     }
 
-    /// Get slot unchecked (caller ensures valid index)
+    /// Get a slot by position (wraps around)
     #[inline]
-    pub fn slot(&self, index: usize) -> &ImageSlot {
-        &self.slots[index]
+    pub fn get(&self, index: usize) -> Option<Arc<ImageSlot>> {
+        let layout = self.layout.load();
+        if layout.order.is_empty() {
+            return None;
+        }
+        let id = layout.order[index % layout.order.len()].load(Ordering::Acquire);
+        Some(Arc::clone(&layout.slots[id]))
+    }
+
+    /// Get slot unchecked (caller ensures the store isn't empty)
+    #[inline]
+    pub fn slot(&self, index: usize) -> Arc<ImageSlot> {
+        self.slot_by_id(self.slot_id(index))
+    }
+
+    /// Get slot by id, independent of browsing order
+    #[inline]
+    pub fn slot_by_id(&self, id: usize) -> Arc<ImageSlot> {
+        Arc::clone(&self.layout.load().slots[id])
+    }
+
+    /// Append images to the end of the browsing order. Returns the
+    /// position of the first one (if the filter shows any of them).
+    pub fn add(&self, metas: Vec<ImageMeta>) -> usize {
+        let _changes = self.changes.lock().unwrap();
+        let layout = self.layout.load();
+        let mut slots = layout.slots.clone();
+        let mut all = layout.all.clone();
+        let first = layout.order.len();
+        for meta in metas {
+            all.push(slots.len());
+            slots.push(Arc::new(ImageSlot::new(meta)));
+        }
+        self.layout
+            .store(Arc::new(Layout::new(slots, all, layout.filter.clone())));
+        first
+    }
+
+    /// Drop everything held for the images read from `path` (all pages of
+    /// a multi-page file), so they are decoded again from the file.
+    /// Returns the positions they have, in ascending order.
+    pub fn reload_path(&self, path: &Path) -> Vec<usize> {
+        let layout = self.layout.load();
+        let ids: Vec<usize> = layout
+            .all
+            .iter()
+            .copied()
+            .filter(|&id| layout.slots[id].meta.path() == path)
+            .collect();
+        for &id in &ids {
+            self.reload_by_id(id);
+        }
+        let mut positions: Vec<usize> = ids
+            .into_iter()
+            .filter_map(|id| layout.shown_position(id))
+            .collect();
+        positions.sort_unstable();
+        positions
+    }
+
+    /// Remove every image read from `path` (all pages of a multi-page
+    /// file), releasing their memory. Returns the positions they had, in
+    /// ascending order; images the filter hid had none.
+    pub fn remove_path(&self, path: &Path) -> Vec<usize> {
+        let _changes = self.changes.lock().unwrap();
+        let layout = self.layout.load();
+        let (removed, kept): (Vec<usize>, Vec<usize>) = layout
+            .all
+            .iter()
+            .partition(|&&id| layout.slots[id].meta.path() == path);
+        if removed.is_empty() {
+            return Vec::new();
+        }
+
+        self.layout.store(Arc::new(Layout::new(
+            layout.slots.clone(),
+            kept,
+            layout.filter.clone(),
+        )));
+
+        // Published first, so an insert racing this one sees the removal
+        // and gives its memory back itself
+        for &id in &removed {
+            self.budget.release(layout.slots[id].clear_all());
+            for kept in [&self.pinned, &self.held] {
+                let _ = kept.compare_exchange(id, usize::MAX, Ordering::AcqRel, Ordering::Acquire);
+            }
+        }
+        self.update_reservation();
+        removed
+            .into_iter()
+            .filter_map(|id| layout.shown_position(id))
+            .collect()
     }
 
-    /// Read image data at index (lock-free)
+    /// Read image data at position (lock-free)
     #[inline]
     pub fn read(&self, index: usize) -> Option<Arc<ImageData>> {
         self.get(index)?.read()
     }
 
-    /// Insert/upgrade image data at index.
+    /// Insert/upgrade image data at position.
     /// Manages memory budget automatically.
     pub fn insert(&self, index: usize, data: Arc<ImageData>) -> bool {
-        let slot = match self.get(index) {
-            Some(s) => s,
+        if self.is_empty() {
+            return false;
+        }
+        self.insert_by_id(self.slot_id(index), data)
+    }
+
+    /// Insert/upgrade image data into a slot by id.
+    ///
+    /// Background work should resolve the id when it starts and insert by id,
+    /// so a reorder in between can't put the data in the wrong slot.
+    /// Higher tiers also leave a resident thumbnail behind if the slot has
+    /// none yet (coarse passes don't); both count against the budget.
+    pub fn insert_by_id(&self, id: usize, data: Arc<ImageData>) -> bool {
+        self.insert_checked(id, data, None)
+    }
+
+    /// `insert_by_id` for a decode begun at the slot's reload count
+    /// `reloads`. Dropped if the image was reloaded since, as the data
+    /// shows the old file.
+    pub fn insert_unless_reloaded(&self, id: usize, data: Arc<ImageData>, reloads: u64) -> bool {
+        self.insert_checked(id, data, Some(reloads))
+    }
+
+    fn insert_checked(&self, id: usize, data: Arc<ImageData>, reloads: Option<u64>) -> bool {
+        let slot = match self.layout.load().slots.get(id) {
+            Some(s) => Arc::clone(s),
             None => return false,
         };
+        if self.is_removed(id) {
+            return false;
+        }
 
-        let new_size = data.memory_size();
-        let old_size = slot.memory_used();
+        let thumbnail =
+            (data.quality > QualityTier::Thumbnail && !data.partial && slot.thumbnail().is_none())
+                .then(|| {
+                    Arc::new(formats::downscale(
+                        &data,
+                        QualityTier::Thumbnail,
+                        self.tiers,
+                    ))
+                });
 
-        // Calculate net memory change
-        let net_increase = new_size.saturating_sub(old_size);
+        // Reloads hold `changes` too, so none can clear the slot between
+        // this check and the swap, and `budget_reconciled` never sees the
+        // budget charged for data not yet in its slot
+        let _changes = self.changes.lock().unwrap();
+        if reloads.is_some_and(|reloads| slot.reloads() != reloads) {
+            return false;
+        }
 
-        // Try to allocate the additional memory needed
-        if net_increase > 0 && !self.budget.try_allocate(net_increase) {
+        // Reserve the whole new size up front (the old data is still alive
+        // until the swap), then release exactly what the swap replaced. This
+        // stays correct if the slot changes between the two.
+        let new_size = data.memory_size() + thumbnail.as_ref().map_or(0, |t| t.memory_size());
+        let pinned = self.is_pinned(id);
+        let allocated = if pinned {
+            self.budget.try_allocate_reserved(new_size)
+        } else {
+            self.budget.try_allocate(new_size)
+        };
+        if !allocated {
             return false; // Not enough memory
         }
 
-        // Perform the upgrade
-        if slot.upgrade(data) {
-            // Release old memory if we had some
-            if old_size > 0 && new_size > old_size {
-                // We already accounted for net increase, nothing more needed
-            } else if old_size > new_size {
-                // Somehow got smaller (shouldn't happen with upgrade)
-                self.budget.release(old_size - new_size);
+        let (full_width, full_height) = (data.full_width, data.full_height);
+        let mut replaced = match slot.try_upgrade(data) {
+            Some(replaced) => replaced,
+            None => {
+                // Upgrade rejected (not higher quality) - release allocated memory
+                self.budget.release(new_size);
+                return false;
             }
-            true
+        };
+        if let Some(thumbnail) = thumbnail {
+            replaced += slot.set_thumbnail(thumbnail);
+        }
+        // Decoded dimensions are authoritative over the header's
+        slot.meta.set_dimensions(full_width, full_height);
+        self.budget.release(replaced);
+
+        // Removed meanwhile: give the memory straight back
+        if self.position_of(id) == REMOVED {
+            self.budget.release(slot.clear_all());
+            return false;
+        }
+        if pinned {
+            self.update_reservation();
+        }
+        true
+    }
+
+    /// Drop everything held for the image at a position, so it is decoded
+    /// again from the file. Returns amount of memory freed.
+    pub fn reload(&self, index: usize) -> usize {
+        if self.is_empty() {
+            return 0;
+        }
+        self.reload_by_id(self.slot_id(index))
+    }
+
+    /// `reload` for a slot id. Holds `changes`, so the budget is never
+    /// seen out of step with the cleared slot.
+    pub fn reload_by_id(&self, id: usize) -> usize {
+        let _changes = self.changes.lock().unwrap();
+        let released = self.slot_by_id(id).reset();
+        self.budget.release(released);
+        if self.is_pinned(id) {
+            self.update_reservation();
+        }
+        released
+    }
+
+    /// Record a failed decode begun at the slot's reload count `reloads`,
+    /// unless the image was reloaded or removed since. Returns whether the
+    /// failure was recorded.
+    pub fn fail_unless_reloaded(&self, id: usize, error: DecodeError, reloads: u64) -> bool {
+        let _changes = self.changes.lock().unwrap();
+        let slot = self.slot_by_id(id);
+        if slot.reloads() != reloads || self.is_removed(id) {
+            return false;
+        }
+        slot.fail(error);
+        true
+    }
+
+    /// Replace the data at a position with a lower tier resized from the
+    /// pixels it already holds, instead of dropping it outright.
+    /// Returns amount of memory freed.
+    pub fn downgrade(&self, index: usize, quality: QualityTier) -> usize {
+        if self.is_empty() {
+            return 0;
+        }
+        self.downgrade_by_id(self.slot_id(index), quality)
+    }
+
+    /// `downgrade` for a slot id
+    fn downgrade_by_id(&self, id: usize, quality: QualityTier) -> usize {
+        if self.is_kept(id) {
+            return 0;
+        }
+        let slot = self.slot_by_id(id);
+        let data = match slot.peek() {
+            Some(d) if d.quality > quality => d,
+            _ => return 0,
+        };
+
+        // A resident thumbnail already is the lower copy
+        if quality == QualityTier::Thumbnail && slot.thumbnail().is_some() {
+            let released = slot.clear();
+            self.budget.release(released);
+            return released;
+        }
+
+        let lower = Arc::new(formats::downscale(&data, quality, self.tiers));
+        let kept = lower.memory_size();
+        let released = if quality == QualityTier::Thumbnail {
+            slot.set_thumbnail(lower) + slot.clear()
         } else {
-            // Upgrade rejected (not higher quality) - release allocated memory
-            if net_increase > 0 {
-                self.budget.release(net_increase);
+            slot.set(Some(lower))
+        };
+
+        self.budget.allocate(kept);
+        self.budget.release(released);
+        released.saturating_sub(kept)
+    }
+
+    /// Drop a slot to its thumbnail under memory pressure, resizing one from
+    /// the pixels it holds if needed. The slot remembers its distance so the
+    /// preloader won't promote it again until the user comes closer.
+    fn demote(&self, id: usize, distance: usize) -> usize {
+        let slot = self.slot_by_id(id);
+        if !slot.has_detail() {
+            return 0;
+        }
+        let freed = self.downgrade_by_id(id, QualityTier::Thumbnail);
+        if !slot.has_detail() {
+            slot.mark_demoted(distance);
+        }
+        freed
+    }
+
+    /// Memory actually held by all slots, for reconciling with the budget
+    pub fn total_memory_used(&self) -> usize {
+        let layout = self.layout.load();
+        layout.slots.iter().map(|slot| slot.memory_used()).sum()
+    }
+
+    /// Whether the budget agrees with what the slots hold. Only meaningful
+    /// while no other thread is making room or evicting; inserts, reloads
+    /// and removals are waited out.
+    pub fn budget_reconciled(&self) -> bool {
+        let _changes = self.changes.lock().unwrap();
+        self.budget.used() == self.total_memory_used()
+    }
+
+    /// Bytes still free in the memory budget
+    #[inline]
+    pub fn available_memory(&self) -> usize {
+        self.budget.available()
+    }
+
+    /// The memory budget, shared with any store replacing this one
+    pub fn budget(&self) -> &MemoryBudget {
+        &self.budget
+    }
+
+    /// Most pixels a full decode may have and still be stored: the whole
+    /// budget, less room for the preview and thumbnail its slot holds
+    /// meanwhile
+    pub fn fitting_pixels(&self) -> u64 {
+        let lower: usize = [QualityTier::Thumbnail, QualityTier::Preview]
+            .into_iter()
+            .filter_map(|tier| tier.max_dimension(self.tiers))
+            .map(|side| side as usize * side as usize * 4)
+            .sum();
+        (self.budget.total().saturating_sub(lower) / 4).max(1) as u64
+    }
+
+    /// How many images have a thumbnail, a preview and their full data
+    /// (or data fitted to the window) loaded, in that order. Images with
+    /// more than a thumbnail mostly keep the thumbnail too, and count for
+    /// both.
+    pub fn resident_tiers(&self) -> [usize; 3] {
+        let mut counts = [0; 3];
+        for slot in &self.layout.load().slots {
+            if slot.thumbnail().is_some() {
+                counts[0] += 1;
+            }
+            if slot.has_detail() {
+                match slot.current_quality() {
+                    Some(quality) if quality > QualityTier::Preview => counts[2] += 1,
+                    Some(_) => counts[1] += 1,
+                    None => {}
+                }
             }
-            false
         }
+        counts
     }
 
-    /// Evict images far from current position.
+    /// Change the memory budget, evicting (furthest from `current` first)
+    /// if what is held no longer fits. Returns amount of memory freed.
+    pub fn resize_budget(&self, total: usize, current: usize) -> usize {
+        self.budget.set_total(total);
+        self.make_room(0, current)
+    }
+
+    /// Evict images far from current position down to their thumbnails.
     /// Returns amount of memory freed.
     pub fn evict_far(&self, current: usize, keep_range: usize) -> usize {
-        let total = self.len();
+        let layout = self.layout.load();
+        let total = layout.order.len();
         if total == 0 {
             return 0;
         }
 
         let mut freed = 0;
 
-        for (idx, slot) in self.slots.iter().enumerate() {
-            let dist = circular_distance(idx, current, total);
-            if dist > keep_range && !slot.is_empty() {
-                let mem = slot.memory_used();
-                slot.clear();
-                self.budget.release(mem);
-                freed += mem;
+        for (id, slot) in layout.slots.iter().enumerate() {
+            if layout.is_removed(id) {
+                continue;
+            }
+            let dist = self.slot_distance(&layout, id, current);
+            if dist > keep_range && slot.has_detail() {
+                freed += self.demote(id, dist);
             }
         }
 
         freed
     }
 
-    /// Evict lowest priority images until we have enough space.
+    /// Evict lowest priority images until we have enough space. Images are
+    /// demoted to thumbnails first; thumbnails only go once nothing else is
+    /// left to drop.
     /// Returns amount of memory freed.
     pub fn make_room(&self, needed: usize, current: usize) -> usize {
-        if self.budget.available() >= needed {
+        if self.budget.fits(needed) {
             return 0;
         }
 
-        let total = self.len();
+        let layout = self.layout.load();
+        let total = layout.order.len();
         if total == 0 {
             return 0;
         }
 
-        // Collect (index, distance, memory) for non-empty slots
-        let mut candidates: Vec<(usize, usize, usize)> = self
+        // Collect (slot id, distance, idle time) for non-empty slots
+        let now = now_millis();
+        let mut candidates: Vec<(usize, usize, Option<u64>)> = layout
             .slots
             .iter()
             .enumerate()
-            .filter(|(_, slot)| !slot.is_empty())
-            .map(|(idx, slot)| {
-                let dist = circular_distance(idx, current, total);
-                let mem = slot.memory_used();
-                (idx, dist, mem)
+            .filter(|(id, slot)| !slot.is_empty() && !self.is_kept(*id) && !layout.is_removed(*id))
+            .map(|(id, slot)| {
+                let dist = self.slot_distance(&layout, id, current);
+                let idle = slot.last_read().map(|t| now.saturating_sub(t));
+                (id, dist, idle)
             })
             .collect();
 
-        // Sort by distance descending (furthest first)
-        candidates.sort_by(|a, b| b.1.cmp(&a.1));
+        // Highest eviction score first
+        let max_idle = candidates.iter().filter_map(|c| c.2).max().unwrap_or(0);
+        let score = |&(_, dist, idle): &(usize, usize, Option<u64>)| {
+            eviction_score(dist, total, idle, max_idle, self.recency_weight)
+        };
+        candidates.sort_by(|a, b| score(b).total_cmp(&score(a)));
 
         let mut freed = 0;
 
-        for (idx, _, mem) in candidates {
-            if self.budget.available() >= needed {
-                break;
+        for thumbnails in [false, true] {
+            for &(id, dist, _) in &candidates {
+                if self.budget.fits(needed) {
+                    return freed;
+                }
+                if thumbnails {
+                    let released = layout.slots[id].clear_all();
+                    self.budget.release(released);
+                    freed += released;
+                } else {
+                    freed += self.demote(id, dist);
+                }
             }
-            self.slots[idx].clear();
-            self.budget.release(mem);
-            freed += mem;
         }
 
         freed
     }
+
+    /// Randomly reorder every position except `keep`, which keeps its image.
+    /// Images the filter hides are shuffled in among the others.
+    ///
+    /// Safe to call while the preloader runs: slots don't move, only the
+    /// browsing order is replaced, which at worst mis-prioritizes a preload
+    /// planned from the old one.
+    pub fn reshuffle<R: rand::Rng>(&self, keep: usize, rng: &mut R) {
+        use rand::seq::SliceRandom;
+
+        let _changes = self.changes.lock().unwrap();
+        let layout = self.layout.load();
+        let total = layout.order.len();
+        if total < 2 {
+            return;
+        }
+
+        let kept_id = layout.order[keep % total].load(Ordering::Acquire);
+        let mut all: Vec<usize> = layout
+            .all
+            .iter()
+            .copied()
+            .filter(|&id| id != kept_id)
+            .collect();
+        all.shuffle(rng);
+        // Back in before whichever shown image now has its position
+        let mut shown = all
+            .iter()
+            .enumerate()
+            .filter(|&(_, &id)| layout.shown_position(id).is_some());
+        let at = shown.nth(keep % total).map_or(all.len(), |(at, _)| at);
+        all.insert(at, kept_id);
+
+        self.layout.store(Arc::new(Layout::new(
+            layout.slots.clone(),
+            all,
+            layout.filter.clone(),
+        )));
+    }
+}
+
+impl Drop for ImageStore {
+    /// Give back what the images held, for a store replacing this one
+    fn drop(&mut self) {
+        for slot in &self.layout.load().slots {
+            self.budget.release(slot.clear_all());
+        }
+    }
 }
 
 /// Calculate shortest distance in circular list
@@ -217,11 +949,92 @@ pub fn circular_distance(a: usize, b: usize, total: usize) -> usize {
     if total == 0 {
         return 0;
     }
+    // Positions read before the collection shrank may be out of range
+    let (a, b) = (a % total, b % total);
     let forward = if a >= b { a - b } else { total - b + a };
     let backward = if b >= a { b - a } else { total - a + b };
     forward.min(backward)
 }
 
+/// Distance between positions: around the circle when navigation wraps,
+/// along the list when it doesn't
+#[inline]
+pub fn position_distance(a: usize, b: usize, total: usize, wrap: bool) -> usize {
+    if wrap {
+        circular_distance(a, b, total)
+    } else if total == 0 {
+        0
+    } else {
+        (a % total).abs_diff(b % total)
+    }
+}
+
+/// Eviction priority; higher is evicted first. Blends how far an image is,
+/// as a fraction of the farthest possible, with how long since it was last
+/// shown, as a fraction of `max_idle` (never shown counts as longest).
+pub fn eviction_score(
+    distance: usize,
+    total: usize,
+    idle_ms: Option<u64>,
+    max_idle_ms: u64,
+    recency_weight: f64,
+) -> f64 {
+    let distance = distance as f64 / (total / 2).max(1) as f64;
+    let idle = match idle_ms {
+        None => 1.0,
+        Some(_) if max_idle_ms == 0 => 0.0,
+        Some(ms) => ms as f64 / max_idle_ms as f64,
+    };
+    (1.0 - recency_weight) * distance + recency_weight * idle
+}
+
+/// Neighbours of `center` up to `ahead` positions forward and `behind`
+/// backward, as (index, signed offset). Each index appears once, at its
+/// nearest offset (forward wins ties, matching `circular_distance`), and
+/// `center` itself is never included, however the ranges compare to `total`.
+/// Without `wrap`, neighbours stop at either end of the list.
+pub fn indices_around(
+    center: usize,
+    total: usize,
+    ahead: usize,
+    behind: usize,
+    wrap: bool,
+) -> Vec<(usize, isize)> {
+    let mut seen = vec![false; total];
+    let mut out = Vec::new();
+    if total < 2 {
+        return out;
+    }
+    let center = center % total;
+    seen[center] = true;
+    let (ahead, behind) = if wrap {
+        (ahead, behind)
+    } else {
+        (ahead.min(total - 1 - center), behind.min(center))
+    };
+
+    for offset in 1..=ahead.max(behind) {
+        if out.len() == total - 1 {
+            break;
+        }
+        let forward =
+            (offset <= ahead).then(|| ((center + offset % total) % total, offset as isize));
+        let backward = (offset <= behind).then(|| {
+            (
+                (center + total - offset % total) % total,
+                -(offset as isize),
+            )
+        });
+        for (index, signed) in forward.into_iter().chain(backward) {
+            if !seen[index] {
+                seen[index] = true;
+                out.push((index, signed));
+            }
+        }
+    }
+    out
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -236,6 +1049,538 @@ mod tests {
         assert_eq!(circular_distance(3, 7, 10), 4);
     }
 
+    #[test]
+    fn test_indices_around_small_totals() {
+        assert!(indices_around(0, 0, 30, 30, true).is_empty());
+        assert!(indices_around(0, 1, 30, 30, true).is_empty());
+
+        // Ranges far beyond the collection visit each other index once,
+        // at its circular distance
+        for total in 2..=12 {
+            for center in 0..total {
+                let around = indices_around(center, total, 30, 30, true);
+                let mut indices: Vec<usize> = around.iter().map(|&(i, _)| i).collect();
+                indices.sort();
+                let expected: Vec<usize> = (0..total).filter(|&i| i != center).collect();
+                assert_eq!(indices, expected);
+                for (index, offset) in around {
+                    assert_eq!(
+                        offset.unsigned_abs(),
+                        circular_distance(index, center, total)
+                    );
+                }
+            }
+        }
+
+        // A short backward range still reaches everything going forward
+        let around = indices_around(0, 10, 30, 2, true);
+        assert_eq!(around.len(), 9);
+        assert!(around.contains(&(9, -1)));
+        assert!(around.contains(&(7, 7)));
+    }
+
+    #[test]
+    fn test_no_wrap_stops_at_the_ends() {
+        assert_eq!(position_distance(0, 9, 10, true), 1);
+        assert_eq!(position_distance(0, 9, 10, false), 9);
+
+        let around = indices_around(1, 10, 3, 3, false);
+        assert_eq!(around, vec![(2, 1), (0, -1), (3, 2), (4, 3)]);
+        let around = indices_around(9, 10, 3, 3, false);
+        assert_eq!(around, vec![(8, -1), (7, -2), (6, -3)]);
+    }
+
+    #[test]
+    fn test_reshuffle_keeps_current() {
+        use rand::SeedableRng;
+
+        let metas = (0..20)
+            .map(|i| ImageMeta::new(format!("{}.png", i).into()))
+            .collect();
+        let store = ImageStore::with_metadata(metas, Arc::new(MemoryBudget::new(1000)));
+        let kept = store.slot(7).meta.path().to_path_buf();
+
+        let mut rng = rand::rngs::StdRng::seed_from_u64(42);
+        store.reshuffle(7, &mut rng);
+
+        assert_eq!(store.slot(7).meta.path(), kept);
+        // Order and inverse stay consistent, every slot appears once
+        let mut seen: Vec<usize> = (0..20).map(|p| store.slot_id(p)).collect();
+        for p in 0..20 {
+            assert_eq!(store.position_of(store.slot_id(p)), p);
+        }
+        seen.sort();
+        assert_eq!(seen, (0..20).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_dropped_store_releases_memory() {
+        let budget = Arc::new(MemoryBudget::new(usize::MAX));
+        let store = ImageStore::with_metadata(vec![ImageMeta::new("a.png".into())], budget.clone())
+            .with_wrap(false);
+        let data = ImageData::new(vec![0; 64 * 64 * 4], 64, 64, QualityTier::Full);
+        assert!(store.insert(0, Arc::new(data)));
+        assert!(budget.used() > 0);
+
+        let sibling = store.sibling(vec![ImageMeta::new("b.png".into())]);
+        assert!(!sibling.wraps());
+        drop(store);
+        assert_eq!(budget.used(), 0);
+    }
+
+    #[test]
+    fn test_downgrade_releases_memory() {
+        let budget = Arc::new(MemoryBudget::new(usize::MAX));
+        let store = ImageStore::with_metadata(vec![ImageMeta::new("a.png".into())], budget.clone());
+        let full = ImageData::new(vec![0; 2048 * 1024 * 4], 2048, 1024, QualityTier::Full);
+        assert!(store.insert(0, Arc::new(full)));
+
+        // Inserting full data leaves a thumbnail behind too
+        assert_eq!(budget.used(), (2048 * 1024 + 256 * 128) * 4);
+        assert_eq!(store.resident_tiers(), [1, 0, 1]);
+
+        let freed = store.downgrade(0, QualityTier::Thumbnail);
+        assert_eq!(freed, 2048 * 1024 * 4);
+        assert_eq!(budget.used(), 256 * 128 * 4);
+        let thumb = store.read(0).unwrap();
+        assert_eq!(thumb.quality, QualityTier::Thumbnail);
+        assert_eq!((thumb.full_width, thumb.full_height), (2048, 1024));
+
+        assert_eq!(store.resident_tiers(), [1, 0, 0]);
+        // Never goes up, nor sideways
+        assert_eq!(store.downgrade(0, QualityTier::Preview), 0);
+        assert_eq!(store.downgrade(0, QualityTier::Thumbnail), 0);
+    }
+
+    #[test]
+    fn test_eviction_keeps_thumbnails() {
+        let budget = Arc::new(MemoryBudget::new(usize::MAX));
+        let metas = (0..10)
+            .map(|i| ImageMeta::new(format!("{}.png", i).into()))
+            .collect();
+        let store = ImageStore::with_metadata(metas, budget.clone());
+        let full = ImageData::new(vec![0; 512 * 512 * 4], 512, 512, QualityTier::Full);
+        assert!(store.insert(5, Arc::new(full)));
+
+        assert_eq!(store.evict_far(0, 2), 512 * 512 * 4);
+        assert_eq!(store.read(5).unwrap().quality, QualityTier::Thumbnail);
+        assert_eq!(budget.used(), 256 * 256 * 4);
+
+        // Only a real shortage takes the thumbnail too
+        let freed = store.make_room(usize::MAX, 0);
+        assert_eq!(freed, 256 * 256 * 4);
+        assert!(store.slot(5).is_empty());
+        assert_eq!(budget.used(), 0);
+    }
+
+    fn data(size: u32, quality: QualityTier) -> Arc<ImageData> {
+        let pixels = vec![0; (size * size * 4) as usize];
+        Arc::new(ImageData::new(pixels, size, size, quality))
+    }
+
+    #[test]
+    fn test_budget_matches_slots_over_cycles() {
+        let metas = (0..8)
+            .map(|i| ImageMeta::new(format!("{}.png", i).into()))
+            .collect();
+        let store = ImageStore::with_metadata(metas, Arc::new(MemoryBudget::new(usize::MAX)));
+
+        for cycle in 0..24 {
+            let index = cycle % 8;
+            store.insert(index, data(64, QualityTier::Thumbnail));
+            store.insert(index, data(300, QualityTier::Preview));
+            // Rejected: not an upgrade
+            assert!(!store.insert(index, data(32, QualityTier::Thumbnail)));
+            store.insert(index, data(600, QualityTier::Full));
+            assert!(store.budget_reconciled());
+
+            store.downgrade((index + 3) % 8, QualityTier::Preview);
+            store.evict_far(index, 2);
+            assert!(store.budget_reconciled());
+
+            store.make_room(usize::MAX, index);
+            assert!(store.budget_reconciled());
+            assert_eq!(store.total_memory_used(), 0);
+        }
+    }
+
+    #[test]
+    fn test_budget_survives_racing_inserts() {
+        // Several writers change the same slots between one insert's
+        // reservation and its swap
+        let metas = (0..2)
+            .map(|i| ImageMeta::new(format!("{}.png", i).into()))
+            .collect();
+        let store = Arc::new(ImageStore::with_metadata(
+            metas,
+            Arc::new(MemoryBudget::new(usize::MAX)),
+        ));
+
+        let writers: Vec<_> = (0..4)
+            .map(|t| {
+                let store = store.clone();
+                std::thread::spawn(move || {
+                    for i in 0..200 {
+                        let quality = match (t + i) % 3 {
+                            0 => QualityTier::Thumbnail,
+                            1 => QualityTier::Preview,
+                            _ => QualityTier::Full,
+                        };
+                        store.insert(i % 2, data(16 + t as u32, quality));
+                        if i % 7 == t {
+                            store.evict_far(1 - i % 2, 0);
+                        }
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        assert!(store.budget_reconciled());
+    }
+
+    #[test]
+    fn test_pinned_image_keeps_full_quality() {
+        let (full, thumb) = (512 * 512 * 4, 256 * 256 * 4);
+        let budget = Arc::new(MemoryBudget::new(full + 2 * thumb));
+        let metas = (0..10)
+            .map(|i| ImageMeta::new(format!("{}.png", i).into()))
+            .collect();
+        let store = ImageStore::with_metadata(metas, budget.clone());
+        store.slot(0).meta.set_dimensions(512, 512);
+        store.pin(0);
+
+        // The reservation keeps a neighbour from taking the current
+        // image's room before it decodes
+        assert!(!store.insert(1, data(512, QualityTier::Full)));
+        assert!(store.insert(0, data(512, QualityTier::Full)));
+
+        // Large neighbours under pressure never push it out
+        for index in 1..10 {
+            store.make_room(full + thumb, 0);
+            store.insert(index, data(512, QualityTier::Full));
+            assert_eq!(store.slot(0).current_quality(), Some(QualityTier::Full));
+        }
+        store.evict_far(5, 1);
+        assert_eq!(store.downgrade(0, QualityTier::Thumbnail), 0);
+        assert_eq!(store.slot(0).current_quality(), Some(QualityTier::Full));
+        assert!(store.budget_reconciled());
+    }
+
+    #[test]
+    fn test_held_image_is_kept() {
+        let budget = Arc::new(MemoryBudget::new(usize::MAX));
+        let metas = (0..10)
+            .map(|i| ImageMeta::new(format!("{}.png", i).into()))
+            .collect();
+        let store = ImageStore::with_metadata(metas, budget);
+        store.pin(0);
+        store.hold(Some(store.slot_id(5)));
+        assert_eq!(store.held(), Some(5));
+        for index in [0, 5, 6] {
+            store.insert(index, data(64, QualityTier::Full));
+        }
+
+        // Far from the current image, but on screen beside it
+        store.evict_far(0, 1);
+        assert_eq!(store.slot(5).current_quality(), Some(QualityTier::Full));
+        assert_eq!(
+            store.slot(6).current_quality(),
+            Some(QualityTier::Thumbnail)
+        );
+
+        // Released, it goes like any other
+        store.hold(None);
+        store.evict_far(0, 1);
+        assert_eq!(
+            store.slot(5).current_quality(),
+            Some(QualityTier::Thumbnail)
+        );
+
+        // Removing the image releases it too
+        store.hold(Some(store.slot_id(6)));
+        store.remove_path(Path::new("6.png"));
+        assert_eq!(store.held(), None);
+        assert!(store.budget_reconciled());
+    }
+
+    #[test]
+    fn test_eviction_resizes_missing_thumbnail() {
+        let budget = Arc::new(MemoryBudget::new(usize::MAX));
+        let metas = (0..10)
+            .map(|i| ImageMeta::new(format!("{}.png", i).into()))
+            .collect();
+        let store = ImageStore::with_metadata(metas, budget.clone());
+
+        // Full data with no thumbnail beside it
+        let full = data(1024, QualityTier::Full);
+        budget.allocate(full.memory_size());
+        store.slot(5).set(Some(full));
+
+        let freed = store.evict_far(0, 2);
+        assert_eq!(freed, (1024 * 1024 - 256 * 256) * 4);
+        assert_eq!(store.read(5).unwrap().quality, QualityTier::Thumbnail);
+        assert_eq!(store.slot(5).demoted_at(), Some(5));
+        assert!(store.budget_reconciled());
+
+        // A later upgrade lifts the mark
+        store.insert(5, data(1024, QualityTier::Preview));
+        assert_eq!(store.slot(5).demoted_at(), None);
+    }
+
+    #[test]
+    fn test_eviction_score() {
+        // Distance only
+        assert!(
+            eviction_score(40, 100, Some(0), 1000, 0.0) > eviction_score(10, 100, None, 1000, 0.0)
+        );
+
+        // Comparing two regions: the one just looked at outlasts a nearer
+        // neighbour that was only preloaded
+        let viewed = eviction_score(50, 100, Some(0), 1000, 0.7);
+        let preloaded = eviction_score(30, 100, None, 1000, 0.7);
+        assert!(preloaded > viewed);
+
+        // Among viewed images, older views go first
+        assert!(
+            eviction_score(20, 100, Some(900), 1000, 0.5)
+                > eviction_score(20, 100, Some(100), 1000, 0.5)
+        );
+    }
+
+    #[test]
+    fn test_make_room_keeps_recently_viewed() {
+        let metas = (0..100)
+            .map(|i| ImageMeta::new(format!("{}.png", i).into()))
+            .collect();
+        let size = 512 * 512 * 4 + 256 * 256 * 4;
+        let budget = Arc::new(MemoryBudget::new(2 * size));
+        let store = ImageStore::with_metadata(metas, budget).with_recency_weight(0.7);
+        store.insert(30, data(512, QualityTier::Full));
+        store.insert(50, data(512, QualityTier::Full));
+        store.read(50); // Looked at, then jumped back to 0
+
+        store.make_room(512 * 512 * 4, 0);
+        assert_eq!(store.slot(50).current_quality(), Some(QualityTier::Full));
+        assert_eq!(
+            store.slot(30).current_quality(),
+            Some(QualityTier::Thumbnail)
+        );
+    }
+
+    #[test]
+    fn test_reload_releases_memory() {
+        let metas = (0..3)
+            .map(|i| ImageMeta::new(format!("{}.png", i).into()))
+            .collect();
+        let budget = Arc::new(MemoryBudget::new(1 << 30));
+        let store = ImageStore::with_metadata(metas, Arc::clone(&budget));
+        store.pin(1);
+        store.insert(1, data(64, QualityTier::Full));
+        let used = budget.used();
+        assert!(used > 0);
+
+        assert_eq!(store.reload(1), used);
+        assert!(store.slot(1).is_empty());
+        assert_eq!(budget.used(), 0);
+        assert!(store.budget_reconciled());
+
+        // Work begun on the old file no longer lands
+        let id = store.slot_id(1);
+        assert!(!store.insert_unless_reloaded(id, data(64, QualityTier::Full), 0));
+        assert!(!store.fail_unless_reloaded(id, DecodeError::UnsupportedFormat, 0));
+        assert!(store.slot(1).is_empty() && store.slot(1).error().is_none());
+        assert!(store.insert_unless_reloaded(id, data(64, QualityTier::Full), 1));
+        assert!(store.budget_reconciled());
+    }
+
+    #[test]
+    fn test_reload_path_clears_failure() {
+        let metas = (0..3)
+            .map(|i| ImageMeta::new(format!("{}.png", i).into()))
+            .collect();
+        let budget = Arc::new(MemoryBudget::new(1 << 30));
+        let store = ImageStore::with_metadata(metas, Arc::clone(&budget));
+        // A decode of the partly copied file failed
+        store.slot(2).fail(DecodeError::UnsupportedFormat);
+        store.insert(1, data(64, QualityTier::Full));
+
+        assert_eq!(store.reload_path(Path::new("2.png")), vec![2]);
+        assert!(store.slot(2).error().is_none());
+        assert!(!store.slot(1).is_empty());
+        assert_eq!(store.reload_path(Path::new("1.png")), vec![1]);
+        assert!(store.slot(1).is_empty());
+        assert_eq!(store.reload_path(Path::new("9.png")), Vec::<usize>::new());
+        assert!(store.budget_reconciled());
+    }
+
+    #[test]
+    fn test_add_and_remove() {
+        let metas = (0..4)
+            .map(|i| ImageMeta::new(format!("{}.png", i).into()))
+            .collect();
+        let budget = Arc::new(MemoryBudget::new(1 << 30));
+        let store = ImageStore::with_metadata(metas, Arc::clone(&budget));
+        store.insert(1, data(64, QualityTier::Full));
+        let id = store.slot_id(1);
+
+        assert_eq!(store.add(vec![ImageMeta::new("4.png".into())]), 4);
+        assert_eq!(store.len(), 5);
+        assert!(store.contains_path(Path::new("4.png")));
+        assert_eq!(store.slot(4).meta.path(), Path::new("4.png"));
+
+        assert_eq!(store.remove_path(Path::new("1.png")), vec![1]);
+        assert_eq!(store.len(), 4);
+        assert_eq!(store.slot(1).meta.path(), Path::new("2.png"));
+        assert_eq!(store.position_of(id), usize::MAX);
+        assert!(store.is_removed(id));
+        assert_eq!(budget.used(), 0);
+
+        // A decode that finishes after the removal is dropped
+        assert!(!store.insert_by_id(id, data(64, QualityTier::Full)));
+        assert_eq!(budget.used(), 0);
+
+        // Reordering only touches the images still listed
+        use rand::SeedableRng;
+        store.reshuffle(0, &mut rand::rngs::StdRng::seed_from_u64(42));
+        let mut paths: Vec<_> = (0..store.len())
+            .map(|i| store.slot(i).meta.path().to_path_buf())
+            .collect();
+        paths.sort();
+        assert_eq!(
+            paths,
+            ["0.png", "2.png", "3.png", "4.png"].map(std::path::PathBuf::from)
+        );
+        assert!(store.budget_reconciled());
+    }
+
+    #[test]
+    fn test_filter() {
+        use rand::SeedableRng;
+
+        let metas = ["0.png", "1.jpg", "2.png", "3.jpg", "4.png", "5.jpg"]
+            .iter()
+            .map(|name| ImageMeta::new(name.into()))
+            .collect();
+        let store = ImageStore::with_metadata(metas, Arc::new(MemoryBudget::new(1 << 30)));
+        let (png0, png2) = (store.slot_id(0), store.slot_id(2));
+
+        assert!(store.set_filter(NameFilter::new("*.jpg")));
+        assert_eq!((store.len(), store.unfiltered_len()), (3, 6));
+        assert_eq!(store.slot(1).meta.path(), Path::new("3.jpg"));
+        assert!(!store.is_shown(png0) && !store.is_removed(png0));
+        // Hidden images move to the nearest match, the later on a tie
+        assert_eq!(store.nearest_position(png0), Some(0));
+        assert_eq!(store.nearest_position(png2), Some(1));
+        assert_eq!(store.nearest_position(store.slot_id(2)), Some(2));
+
+        // A filter matching nothing leaves the current one
+        assert!(!store.set_filter(NameFilter::new("*.gif")));
+        assert_eq!(store.len(), 3);
+
+        // New images join if they match; removing hidden ones moves nothing
+        store.add(vec![ImageMeta::new("6.jpg".into())]);
+        store.add(vec![ImageMeta::new("7.png".into())]);
+        assert_eq!((store.len(), store.unfiltered_len()), (4, 8));
+        assert!(store.contains_path(Path::new("7.png")));
+        assert_eq!(store.remove_path(Path::new("0.png")), Vec::<usize>::new());
+        assert_eq!(store.remove_path(Path::new("3.jpg")), vec![1]);
+        assert_eq!(store.nearest_position(png0), None);
+
+        // Shuffling keeps the filter and the kept image
+        let kept = store.slot_id(1);
+        store.reshuffle(1, &mut rand::rngs::StdRng::seed_from_u64(7));
+        assert_eq!(store.slot_id(1), kept);
+        assert_eq!(store.len(), 3);
+        assert!((0..3).all(|p| store.slot(p).meta.path().extension().unwrap() == "jpg"));
+
+        assert!(store.set_filter(None));
+        assert_eq!(store.len(), 6);
+        assert!(store.is_shown(png2));
+    }
+
+    #[test]
+    fn test_similar_positions() {
+        let metas = ["a.png", "b.png", "c.png", "d.png", "e.jpg"]
+            .iter()
+            .map(|name| ImageMeta::new(name.into()))
+            .collect();
+        let store = ImageStore::with_metadata(metas, Arc::new(MemoryBudget::new(1 << 30)));
+        // d.png isn't hashed yet
+        for (position, hash) in [(0, 0b0000), (1, u64::MAX), (2, 0b0011), (4, 0b0111)] {
+            store.slot(position).meta.set_phash(hash);
+        }
+
+        assert_eq!(store.similar_positions(0, 3), vec![2, 4]);
+        assert_eq!(store.similar_positions(0, 2), vec![2]);
+        assert_eq!(store.similar_positions(3, 64), Vec::<usize>::new());
+
+        // Only shown images count
+        assert!(store.set_filter(NameFilter::new("*.png")));
+        assert_eq!(store.similar_positions(0, 3), vec![2]);
+    }
+
+    #[test]
+    fn test_budget_set_total() {
+        let budget = MemoryBudget::new(1000);
+        assert!(budget.try_allocate(800));
+
+        budget.set_total(500);
+        assert_eq!(budget.available(), 0);
+        assert!(!budget.fits(0));
+        assert!(!budget.try_allocate(1));
+
+        budget.release(400);
+        assert!(budget.fits(100));
+        assert!(budget.try_allocate(100));
+        assert!(!budget.try_allocate(1));
+    }
+
+    #[test]
+    fn test_resize_budget_evicts() {
+        let metas = (0..10)
+            .map(|i| ImageMeta::new(format!("{}.png", i).into()))
+            .collect();
+        let size = 512 * 512 * 4 + 256 * 256 * 4;
+        let budget = Arc::new(MemoryBudget::new(4 * size));
+        let store = ImageStore::with_metadata(metas, Arc::clone(&budget));
+        for index in [0, 1, 5] {
+            store.insert(index, data(512, QualityTier::Full));
+        }
+
+        // Memory got tight: the furthest image gives way
+        let freed = store.resize_budget(2 * size + 256 * 256 * 4, 0);
+        assert_eq!(freed, 512 * 512 * 4);
+        assert_eq!(
+            store.slot(5).current_quality(),
+            Some(QualityTier::Thumbnail)
+        );
+        assert_eq!(store.slot(1).current_quality(), Some(QualityTier::Full));
+        assert!(budget.fits(0));
+        assert!(store.budget_reconciled());
+    }
+
+    #[test]
+    fn test_image_larger_than_budget() {
+        let metas = (0..3)
+            .map(|i| ImageMeta::new(format!("{}.png", i).into()))
+            .collect();
+        let total = 64 << 20;
+        let store = ImageStore::with_metadata(metas, Arc::new(MemoryBudget::new(total)));
+        let lower = 256 * 256 * 4 + 1024 * 1024 * 4;
+        assert_eq!(store.fitting_pixels(), ((total - lower) / 4) as u64);
+
+        // A 200 megapixel panorama reserves only what it will be shrunk to,
+        // leaving the rest for its neighbours
+        store.slot(0).meta.set_dimensions(20_000, 10_000);
+        store.pin(0);
+        assert_eq!(store.budget().available(), lower);
+        assert!(store.insert(1, data(512, QualityTier::Full)));
+        assert!(store.budget_reconciled());
+    }
+
     #[test]
     fn test_budget() {
         let budget = MemoryBudget::new(1000);
@@ -0,0 +1,159 @@
+//! Thumbs mode - fill the thumbnail cache ahead of browsing.
+//!
+//! `fiv thumbs DIR` decodes a thumbnail for every image in the directory
+//! that has no cache entry, or one made before the file last changed or
+//! at another thumbnail size, so the gallery opens on a warm cache.
+//! Ctrl-C stops handing out images; those being decoded are finished and
+//! stored, and entries are written aside and renamed, so the cache is
+//! never left with half an entry.
+
+use fiv::cache::ThumbnailCache;
+use fiv::config::QualityTier;
+use fiv::decode::Decoder;
+use fiv::phash::dhash;
+use fiv::preload::decode_pool;
+use fiv::slot::ImageMeta;
+use rayon::prelude::*;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// How a run went
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Summary {
+    /// Images looked at
+    pub images: usize,
+    /// Thumbnails decoded and stored
+    pub generated: usize,
+    /// Images whose entry was already up to date
+    pub cached: usize,
+    pub failures: usize,
+    /// Whether the run was stopped before the end
+    pub interrupted: bool,
+    pub took: Duration,
+}
+
+/// What became of one image
+enum Outcome {
+    Generated,
+    Cached,
+    Failed,
+}
+
+/// Store a thumbnail for each image not already cached, on `threads`
+/// threads, until done or `stop` is set
+pub fn run(
+    metas: &[ImageMeta],
+    decoder: &Decoder,
+    cache: &ThumbnailCache,
+    threads: usize,
+    stop: &AtomicBool,
+) -> Summary {
+    let pool = decode_pool(threads);
+    let started = Instant::now();
+    let done = AtomicUsize::new(0);
+    let outcomes: Vec<Outcome> = pool.install(|| {
+        metas
+            .par_iter()
+            .filter(|_| !stop.load(Ordering::Relaxed))
+            .map(|meta| {
+                let outcome = if cache.contains(meta) {
+                    Outcome::Cached
+                } else {
+                    match decoder.decode(meta, QualityTier::Thumbnail) {
+                        Ok(thumbnail) => {
+                            meta.set_phash(dhash(&thumbnail));
+                            cache.store(meta, &thumbnail);
+                            Outcome::Generated
+                        }
+                        Err(err) => {
+                            eprintln!("Warning: {}: {}", meta.source, err);
+                            Outcome::Failed
+                        }
+                    }
+                };
+                let done = done.fetch_add(1, Ordering::Relaxed) + 1;
+                eprint!("\r{}/{} images", done, metas.len());
+                outcome
+            })
+            .collect()
+    });
+    if !metas.is_empty() {
+        eprintln!();
+    }
+
+    let count = |wanted: fn(&Outcome) -> bool| outcomes.iter().filter(|&o| wanted(o)).count();
+    Summary {
+        images: outcomes.len(),
+        generated: count(|o| matches!(o, Outcome::Generated)),
+        cached: count(|o| matches!(o, Outcome::Cached)),
+        failures: count(|o| matches!(o, Outcome::Failed)),
+        interrupted: outcomes.len() < metas.len(),
+        took: started.elapsed(),
+    }
+}
+
+impl Summary {
+    pub fn to_text(self) -> String {
+        let failed = match self.failures {
+            0 => String::new(),
+            n => format!(", {} failed", n),
+        };
+        let stopped = if self.interrupted {
+            " (interrupted)"
+        } else {
+            ""
+        };
+        format!(
+            "Generated {} thumbnails, {} already cached{}, in {:.2} s{}",
+            self.generated,
+            self.cached,
+            failed,
+            self.took.as_secs_f64(),
+            stopped,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fiv::config::TierSizes;
+
+    #[test]
+    fn test_run() {
+        let root = std::env::temp_dir().join(format!("fiv_thumbs_{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let png = root.join("a.png");
+        image::RgbImage::from_pixel(5, 3, image::Rgb([0, 0, 0]))
+            .save_with_format(&png, image::ImageFormat::Png)
+            .unwrap();
+        let cache =
+            ThumbnailCache::open(root.join("cache"), 1 << 20, TierSizes::default()).unwrap();
+        let metas = vec![
+            ImageMeta::new(png.clone()),
+            ImageMeta::new(root.join("missing.png")),
+        ];
+        let decoder = Decoder::new();
+
+        let first = run(&metas, &decoder, &cache, 2, &AtomicBool::new(false));
+        assert_eq!(
+            (first.images, first.generated, first.cached, first.failures),
+            (2, 1, 0, 1)
+        );
+        assert!(!first.interrupted);
+        assert!(cache.contains(&ImageMeta::new(png.clone())));
+
+        // A second run finds the entry fresh
+        let second = run(&metas[..1], &decoder, &cache, 1, &AtomicBool::new(false));
+        assert_eq!((second.generated, second.cached), (0, 1));
+        assert!(second.to_text().contains("0 thumbnails, 1 already cached"));
+
+        // Stopped before starting, nothing is done
+        let stopped = run(&metas, &decoder, &cache, 1, &AtomicBool::new(true));
+        assert_eq!(stopped.images, 0);
+        assert!(stopped.interrupted);
+        assert!(stopped.to_text().ends_with("(interrupted)"));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,257 @@
+//! Window title templates.
+//!
+//! A template is text with `{placeholder}`s, like
+//! `"{filename} - {index}/{total} {zoom}%"`. It is split into words at
+//! whitespace, and a word whose placeholder has nothing to show drops out
+//! along with the space before it: `{width}x{height}` disappears until the
+//! dimensions are known, and `[{gallery}]`-style indicators only show while
+//! they apply. Braces around anything but a known name stay as typed.
+
+/// Template matching the title from before titles were configurable
+pub const DEFAULT_TITLE_FORMAT: &str = "Fiv - {filename} [{index}/{total}] {filter} {gallery} \
+    {compare} {zoom}% {channel} {adjustments} {quality} {slideshow} {notice}";
+
+/// Something the title can show
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    /// The name shown for the image, with "* " in front while it's marked
+    Filename,
+    /// The full path
+    Path,
+    /// 1-based position in the collection
+    Index,
+    Total,
+    Width,
+    Height,
+    /// File size, like "2.4 MB"
+    Size,
+    /// "[preview]" and the like while the shown image isn't final
+    Quality,
+    /// Zoom in percent, once the image has been rendered
+    Zoom,
+    /// "[filtered: 3/10]" while a name filter is on
+    Filter,
+    /// "[gallery]" while browsing thumbnails
+    Gallery,
+    /// "[pinned]", "[compare]" or "[showing pinned]"
+    Compare,
+    /// "[red]" and the like while one channel is isolated
+    Channel,
+    /// "[+1.0 EV, locked]" while the image is adjusted
+    Adjustments,
+    /// A play or pause sign during a slideshow
+    Slideshow,
+    /// "- " and the latest notice or prompt
+    Notice,
+}
+
+impl Field {
+    const ALL: [Field; 16] = [
+        Field::Filename,
+        Field::Path,
+        Field::Index,
+        Field::Total,
+        Field::Width,
+        Field::Height,
+        Field::Size,
+        Field::Quality,
+        Field::Zoom,
+        Field::Filter,
+        Field::Gallery,
+        Field::Compare,
+        Field::Channel,
+        Field::Adjustments,
+        Field::Slideshow,
+        Field::Notice,
+    ];
+
+    /// Placeholder name, without the braces
+    pub fn name(self) -> &'static str {
+        match self {
+            Field::Filename => "filename",
+            Field::Path => "path",
+            Field::Index => "index",
+            Field::Total => "total",
+            Field::Width => "width",
+            Field::Height => "height",
+            Field::Size => "size",
+            Field::Quality => "quality",
+            Field::Zoom => "zoom",
+            Field::Filter => "filter",
+            Field::Gallery => "gallery",
+            Field::Compare => "compare",
+            Field::Channel => "channel",
+            Field::Adjustments => "adjustments",
+            Field::Slideshow => "slideshow",
+            Field::Notice => "notice",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|field| field.name() == name)
+    }
+}
+
+/// Piece of a word: literal text or a placeholder
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Text(String),
+    Field(Field),
+}
+
+/// Run of non-space text, and the whitespace before it
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Word {
+    space: String,
+    parts: Vec<Part>,
+}
+
+/// A parsed title template
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TitleFormat {
+    words: Vec<Word>,
+    /// Placeholders that aren't fields, kept as literal text
+    unknown: Vec<String>,
+}
+
+impl TitleFormat {
+    pub fn new(template: &str) -> Self {
+        let mut words: Vec<Word> = Vec::new();
+        let mut unknown = Vec::new();
+        let mut rest = template;
+        while !rest.is_empty() {
+            let word_start = rest
+                .find(|c: char| !c.is_whitespace())
+                .unwrap_or(rest.len());
+            let (space, after) = rest.split_at(word_start);
+            let word_end = after.find(char::is_whitespace).unwrap_or(after.len());
+            let (text, after) = after.split_at(word_end);
+            rest = after;
+            if text.is_empty() {
+                break;
+            }
+            words.push(Word {
+                space: space.to_string(),
+                parts: parse_word(text, &mut unknown),
+            });
+        }
+        Self { words, unknown }
+    }
+
+    /// Placeholders in the template that aren't fields, with their braces
+    pub fn unknown(&self) -> &[String] {
+        &self.unknown
+    }
+
+    /// Whether the template shows `field`, to skip working out the rest
+    pub fn uses(&self, field: Field) -> bool {
+        self.words
+            .iter()
+            .flat_map(|word| &word.parts)
+            .any(|part| *part == Part::Field(field))
+    }
+
+    /// Fill in the template, leaving out words with a field that `value`
+    /// has nothing for
+    pub fn render(&self, value: impl Fn(Field) -> Option<String>) -> String {
+        let mut title = String::new();
+        'words: for word in &self.words {
+            let mut text = String::new();
+            for part in &word.parts {
+                match part {
+                    Part::Text(literal) => text.push_str(literal),
+                    Part::Field(field) => match value(*field) {
+                        Some(value) => text.push_str(&value),
+                        None => continue 'words,
+                    },
+                }
+            }
+            if !title.is_empty() {
+                title.push_str(&word.space);
+            }
+            title.push_str(&text);
+        }
+        title
+    }
+}
+
+impl Default for TitleFormat {
+    fn default() -> Self {
+        Self::new(DEFAULT_TITLE_FORMAT)
+    }
+}
+
+/// Split a word into text and placeholders, noting unknown ones
+fn parse_word(mut text: &str, unknown: &mut Vec<String>) -> Vec<Part> {
+    let mut parts = Vec::new();
+    let mut literal = String::new();
+    while let Some(open) = text.find('{') {
+        let Some(close) = text[open..].find('}').map(|n| open + n) else {
+            break;
+        };
+        literal.push_str(&text[..open]);
+        let placeholder = &text[open..=close];
+        match Field::from_name(&placeholder[1..placeholder.len() - 1]) {
+            Some(field) => {
+                if !literal.is_empty() {
+                    parts.push(Part::Text(std::mem::take(&mut literal)));
+                }
+                parts.push(Part::Field(field));
+            }
+            None => {
+                literal.push_str(placeholder);
+                unknown.push(placeholder.to_string());
+            }
+        }
+        text = &text[close + 1..];
+    }
+    literal.push_str(text);
+    if !literal.is_empty() {
+        parts.push(Part::Text(literal));
+    }
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filled(field: Field) -> Option<String> {
+        match field {
+            Field::Filename => Some("a.jpg".to_string()),
+            Field::Index => Some("3".to_string()),
+            Field::Total => Some("10".to_string()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_render() {
+        let format = TitleFormat::new("{filename} \u{2014} {index}/{total}");
+        assert_eq!(format.render(filled), "a.jpg \u{2014} 3/10");
+        assert!(format.unknown().is_empty());
+        assert!(format.uses(Field::Index) && !format.uses(Field::Size));
+
+        // Words with nothing to show drop out with the space before them
+        let format = TitleFormat::new("{filename} {width}x{height} [{index}/{total}] {zoom}%");
+        assert_eq!(format.render(filled), "a.jpg [3/10]");
+        let format = TitleFormat::new("  {zoom}%   {filename}  ");
+        assert_eq!(format.render(filled), "a.jpg");
+        assert_eq!(TitleFormat::new("").render(filled), "");
+    }
+
+    #[test]
+    fn test_unknown_placeholders() {
+        let format = TitleFormat::new("{filename} {nope} {index}{x}/{total} {");
+        assert_eq!(format.render(filled), "a.jpg {nope} 3{x}/10 {");
+        assert_eq!(format.unknown(), ["{nope}", "{x}"]);
+    }
+
+    #[test]
+    fn test_field_names() {
+        for field in Field::ALL {
+            assert_eq!(Field::from_name(field.name()), Some(field));
+        }
+        assert!(TitleFormat::default().unknown().is_empty());
+    }
+}
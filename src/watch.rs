@@ -0,0 +1,202 @@
+//! Directory watching - keeps the image list in step with the disk.
+//!
+//! A filesystem watcher reports images created, removed, renamed and
+//! rewritten in the browsed directories. Changes are forwarded to the event
+//! loop, which applies them to the store on the main thread so the
+//! displayed index can be adjusted in the same step.
+
+use crate::decode::Decoder;
+use notify::event::{AccessKind, AccessMode, ModifyKind, RenameMode};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// A change to the set of images on disk
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileChange {
+    /// A supported image appeared (created, or renamed into place)
+    Created(PathBuf),
+    /// A file went away (deleted, or renamed elsewhere)
+    Removed(PathBuf),
+    /// A file's contents were written, such as while it is copied in, so
+    /// what was decoded from it may be stale or have failed on a part
+    Modified(PathBuf),
+}
+
+/// Watch `roots` for image changes, down to `depth` directory levels
+/// (1 = only the root itself), calling `send` for each one. The watcher
+/// stops when the returned handle is dropped.
+pub fn watch_directories<F>(
+    roots: &[PathBuf],
+    depth: usize,
+    decoder: Arc<Decoder>,
+    send: F,
+) -> Result<RecommendedWatcher, String>
+where
+    F: Fn(FileChange) + Send + 'static,
+{
+    let owned_roots = roots.to_vec();
+    let mut watcher = notify::recommended_watcher(move |result: notify::Result<Event>| {
+        let Ok(event) = result else {
+            return;
+        };
+        for change in changes_for(&event) {
+            if let FileChange::Created(path) | FileChange::Modified(path) = &change {
+                let in_range = owned_roots
+                    .iter()
+                    .any(|root| within_depth(path, root, depth));
+                if !in_range || !path.is_file() || !decoder.is_supported(path) {
+                    continue;
+                }
+            }
+            send(change);
+        }
+    })
+    .map_err(|e| format!("cannot start watcher: {}", e))?;
+
+    let mode = if depth > 1 {
+        RecursiveMode::Recursive
+    } else {
+        RecursiveMode::NonRecursive
+    };
+    for root in roots {
+        watcher
+            .watch(root, mode)
+            .map_err(|e| format!("cannot watch {}: {}", root.display(), e))?;
+    }
+    Ok(watcher)
+}
+
+/// The image list changes a filesystem event stands for, before checking
+/// that created and written files are supported images
+fn changes_for(event: &Event) -> Vec<FileChange> {
+    let paths = &event.paths;
+    match event.kind {
+        EventKind::Create(_) | EventKind::Modify(ModifyKind::Name(RenameMode::To)) => {
+            paths.iter().cloned().map(FileChange::Created).collect()
+        }
+        EventKind::Remove(_) | EventKind::Modify(ModifyKind::Name(RenameMode::From)) => {
+            paths.iter().cloned().map(FileChange::Removed).collect()
+        }
+        EventKind::Modify(ModifyKind::Name(RenameMode::Both)) if paths.len() == 2 => vec![
+            FileChange::Removed(paths[0].clone()),
+            FileChange::Created(paths[1].clone()),
+        ],
+        // The backend couldn't tell which side of a rename this is
+        EventKind::Modify(ModifyKind::Name(_)) => paths
+            .iter()
+            .map(|path| {
+                if path.exists() {
+                    FileChange::Created(path.clone())
+                } else {
+                    FileChange::Removed(path.clone())
+                }
+            })
+            .collect(),
+        // Written, or closed after writing where the backend says so
+        EventKind::Modify(ModifyKind::Data(_))
+        | EventKind::Access(AccessKind::Close(AccessMode::Write)) => {
+            paths.iter().cloned().map(FileChange::Modified).collect()
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// Whether `path` is inside `root` and at most `depth` levels down
+fn within_depth(path: &Path, root: &Path, depth: usize) -> bool {
+    path.strip_prefix(root)
+        .is_ok_and(|relative| relative.components().count() <= depth)
+}
+
+/// Where the displayed position ends up after the images at `removed`
+/// (ascending positions before the removal) are taken out, leaving
+/// `remaining` images. If the displayed image itself went, the one that
+/// followed it takes its place.
+pub fn remap_index(current: usize, removed: &[usize], remaining: usize) -> usize {
+    if remaining == 0 {
+        return 0;
+    }
+    let before = removed
+        .iter()
+        .filter(|&&position| position < current)
+        .count();
+    (current - before).min(remaining - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use notify::event::{CreateKind, DataChange, RemoveKind};
+
+    fn event(kind: EventKind, paths: &[&str]) -> Event {
+        paths
+            .iter()
+            .fold(Event::new(kind), |event, path| event.add_path(path.into()))
+    }
+
+    #[test]
+    fn test_changes_for() {
+        assert_eq!(
+            changes_for(&event(EventKind::Create(CreateKind::File), &["/a/1.jpg"])),
+            vec![FileChange::Created("/a/1.jpg".into())]
+        );
+        assert_eq!(
+            changes_for(&event(EventKind::Remove(RemoveKind::File), &["/a/1.jpg"])),
+            vec![FileChange::Removed("/a/1.jpg".into())]
+        );
+        assert_eq!(
+            changes_for(&event(
+                EventKind::Modify(ModifyKind::Name(RenameMode::Both)),
+                &["/a/old.jpg", "/a/new.jpg"]
+            )),
+            vec![
+                FileChange::Removed("/a/old.jpg".into()),
+                FileChange::Created("/a/new.jpg".into()),
+            ]
+        );
+        // Writes, as while a file is copied in, mark it for decoding again
+        assert_eq!(
+            changes_for(&event(
+                EventKind::Modify(ModifyKind::Data(DataChange::Content)),
+                &["/a/1.jpg"]
+            )),
+            vec![FileChange::Modified("/a/1.jpg".into())]
+        );
+        assert_eq!(
+            changes_for(&event(
+                EventKind::Access(AccessKind::Close(AccessMode::Write)),
+                &["/a/1.jpg"]
+            )),
+            vec![FileChange::Modified("/a/1.jpg".into())]
+        );
+        // Metadata changes and reads don't
+        assert!(changes_for(&event(EventKind::Modify(ModifyKind::Any), &["/a/1.jpg"])).is_empty());
+        assert!(changes_for(&event(
+            EventKind::Access(AccessKind::Close(AccessMode::Read)),
+            &["/a/1.jpg"]
+        ))
+        .is_empty());
+    }
+
+    #[test]
+    fn test_within_depth() {
+        let root = Path::new("/photos");
+        assert!(within_depth(Path::new("/photos/a.jpg"), root, 1));
+        assert!(!within_depth(Path::new("/photos/2024/a.jpg"), root, 1));
+        assert!(within_depth(Path::new("/photos/2024/a.jpg"), root, 2));
+        assert!(!within_depth(Path::new("/elsewhere/a.jpg"), root, 5));
+    }
+
+    #[test]
+    fn test_remap_index() {
+        // Removed before the current image: shift back
+        assert_eq!(remap_index(5, &[1, 2], 8), 3);
+        // Removed after: unchanged
+        assert_eq!(remap_index(5, &[7], 9), 5);
+        // The current image itself: the next one takes its place
+        assert_eq!(remap_index(5, &[5], 9), 5);
+        // ...unless it was the last
+        assert_eq!(remap_index(9, &[9], 9), 8);
+        assert_eq!(remap_index(0, &[0], 0), 0);
+    }
+}
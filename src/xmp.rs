@@ -0,0 +1,341 @@
+//! Star ratings in XMP sidecar files.
+//!
+//! A rating of 1 to 5 stars is kept as the standard `xmp:Rating` property
+//! in `NAME.EXT.xmp` next to the image, the sidecar darktable and digiKam
+//! read, so a RAW and the JPEG beside it are rated apart. A Lightroom-style
+//! `NAME.xmp` is read when the image has no sidecar of its own, but never
+//! written, as other images may share it. Existing sidecars are edited in
+//! place: only the rating is added, changed or removed, and everything
+//! else in the file is left as it was. Ratings below 0 ("rejected") read
+//! as unrated.
+
+use crate::ops::replace_file;
+use std::fs;
+use std::io::ErrorKind;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+/// Highest rating
+pub const MAX_RATING: u8 = 5;
+
+/// Qualified name of the rating property
+const RATING: &str = "xmp:Rating";
+
+/// Namespace the `xmp` prefix stands for
+const XMP_NAMESPACE: &str = "http://ns.adobe.com/xap/1.0/";
+
+/// A rating as a row of stars, e.g. `***`
+pub fn stars(rating: u8) -> String {
+    "*".repeat(rating as usize)
+}
+
+/// Sidecar holding the rating of `image`, named after its whole file name
+pub fn sidecar_path(image: &Path) -> PathBuf {
+    let mut name = image.as_os_str().to_owned();
+    name.push(".xmp");
+    PathBuf::from(name)
+}
+
+/// Sidecar named after the stem of `image` alone, which images differing
+/// only in extension share
+fn shared_sidecar_path(image: &Path) -> PathBuf {
+    image.with_extension("xmp")
+}
+
+/// Rating of `image` from its sidecar, or the shared one if it has none;
+/// 0 if neither exists. An unreadable sidecar is reported and reads as
+/// unrated.
+pub fn read_rating(image: &Path) -> u8 {
+    match read_sidecar(&sidecar_path(image)) {
+        Some(rating) => rating,
+        None => read_sidecar(&shared_sidecar_path(image)).unwrap_or(0),
+    }
+}
+
+/// Rating in the sidecar at `path`, None if there is no such file
+fn read_sidecar(path: &Path) -> Option<u8> {
+    match fs::read_to_string(path) {
+        Ok(xml) => Some(rating_in(&xml)),
+        Err(e) if e.kind() == ErrorKind::NotFound => None,
+        Err(e) => {
+            eprintln!("Warning: cannot read {}: {}", path.display(), e);
+            Some(0)
+        }
+    }
+}
+
+/// Save the rating of `image` to its own sidecar, creating it if needed;
+/// 0 removes the rating
+pub fn write_rating(image: &Path, rating: u8) -> Result<(), String> {
+    let path = sidecar_path(image);
+    match fs::read_to_string(&path) {
+        Ok(xml) => {
+            let updated = with_rating(&xml, rating)?;
+            if updated == xml {
+                return Ok(());
+            }
+            replace_file(&path, updated.as_bytes())
+                .map_err(|e| format!("cannot write {}: {}", path.display(), e))
+        }
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            // A rating of 0 is still written when the shared sidecar has
+            // one, to hide it
+            if rating == 0 && read_rating(image) == 0 {
+                return Ok(());
+            }
+            fs::write(&path, new_sidecar(rating))
+                .map_err(|e| format!("cannot write {}: {}", path.display(), e))
+        }
+        Err(e) => Err(format!("cannot read {}: {}", path.display(), e)),
+    }
+}
+
+/// Rating in an XMP document, 0 if it has none
+fn rating_in(xml: &str) -> u8 {
+    let Some(property) = find_rating(xml) else {
+        return 0;
+    };
+    // Written as a real number by some tools
+    match xml[property.value].trim().parse::<f64>() {
+        Ok(rating) if rating > 0.0 => rating.round().min(MAX_RATING as f64) as u8,
+        _ => 0,
+    }
+}
+
+/// An XMP document with its rating set to `rating`, or removed for 0
+fn with_rating(xml: &str, rating: u8) -> Result<String, String> {
+    let mut xml = xml.to_string();
+    if let Some(property) = find_rating(&xml) {
+        if rating == 0 {
+            xml.replace_range(property.whole, "");
+        } else {
+            xml.replace_range(property.value, &rating.to_string());
+        }
+        return Ok(xml);
+    }
+    if rating == 0 {
+        return Ok(xml);
+    }
+
+    const DESCRIPTION: &str = "<rdf:Description";
+    if let Some(start) = xml.find(DESCRIPTION) {
+        let tag_end = xml[start..].find('>').map_or(xml.len(), |end| start + end);
+        // Declared again here unless this tag has it; doing so is harmless
+        // when an enclosing element already does
+        let namespace = if xml[start..tag_end].contains("xmlns:xmp=") {
+            String::new()
+        } else {
+            format!(" xmlns:xmp=\"{}\"", XMP_NAMESPACE)
+        };
+        let at = start + DESCRIPTION.len();
+        xml.insert_str(at, &format!("{} {}=\"{}\"", namespace, RATING, rating));
+    } else if let Some(at) = xml.find("</rdf:RDF>") {
+        xml.insert_str(at, &description(rating));
+    } else {
+        return Err("sidecar has no RDF description".to_string());
+    }
+    Ok(xml)
+}
+
+/// An `rdf:Description` element holding just a rating
+fn description(rating: u8) -> String {
+    format!(
+        "<rdf:Description rdf:about=\"\" xmlns:xmp=\"{}\" {}=\"{}\"/>\n",
+        XMP_NAMESPACE, RATING, rating
+    )
+}
+
+/// A new sidecar holding just a rating
+fn new_sidecar(rating: u8) -> String {
+    format!(
+        "<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n\
+         <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n\
+         {}</rdf:RDF>\n\
+         </x:xmpmeta>\n",
+        description(rating)
+    )
+}
+
+/// Where the rating property is in a document: the byte range of its
+/// value and of the whole property
+#[derive(Debug, PartialEq)]
+struct Property {
+    value: Range<usize>,
+    whole: Range<usize>,
+}
+
+/// Find the rating, written either as an attribute, `xmp:Rating="3"`, or
+/// as an element, `<xmp:Rating>3</xmp:Rating>`
+fn find_rating(xml: &str) -> Option<Property> {
+    let mut from = 0;
+    while let Some(offset) = xml[from..].find(RATING) {
+        let start = from + offset;
+        let end = start + RATING.len();
+        from = end;
+
+        if xml[..start].ends_with('<') {
+            if !xml[end..].starts_with('>') {
+                continue;
+            }
+            let close = format!("</{}>", RATING);
+            let value_end = end + 1 + xml[end + 1..].find(&close)?;
+            return Some(Property {
+                value: end + 1..value_end,
+                whole: start - 1..value_end + close.len(),
+            });
+        }
+
+        let Some(assigned) = xml[end..].trim_start().strip_prefix('=') else {
+            continue;
+        };
+        let quoted = assigned.trim_start();
+        let quote = quoted.chars().next().filter(|&c| c == '"' || c == '\'')?;
+        let value_start = xml.len() - quoted.len() + 1;
+        let value_end = value_start + xml[value_start..].find(quote)?;
+        // The whitespace before the attribute goes with it
+        return Some(Property {
+            value: value_start..value_end,
+            whole: xml[..start].trim_end().len()..value_end + 1,
+        });
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A sidecar another tool wrote, with other properties to keep
+    const DARKTABLE: &str = r#"<x:xmpmeta xmlns:x="adobe:ns:meta/">
+ <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+  <rdf:Description rdf:about=""
+    xmlns:xmp="http://ns.adobe.com/xap/1.0/"
+    xmlns:darktable="http://darktable.sf.net/"
+    xmp:Rating="2"
+    darktable:xmp_version="5">
+   <darktable:history/>
+  </rdf:Description>
+ </rdf:RDF>
+</x:xmpmeta>
+"#;
+
+    #[test]
+    fn test_rating_forms() {
+        assert_eq!(rating_in(DARKTABLE), 2);
+        assert_eq!(rating_in("<d xmp:Rating = '4'/>"), 4);
+        assert_eq!(rating_in("<d><xmp:Rating>3</xmp:Rating></d>"), 3);
+        assert_eq!(rating_in("<d><xmp:Rating> 5.0 </xmp:Rating></d>"), 5);
+        // Rejected, out of range and missing
+        assert_eq!(rating_in("<d xmp:Rating=\"-1\"/>"), 0);
+        assert_eq!(rating_in("<d xmp:Rating=\"9\"/>"), 5);
+        assert_eq!(rating_in("<d xmp:Label=\"Red\"/>"), 0);
+    }
+
+    #[test]
+    fn test_update_keeps_other_fields() {
+        let updated = with_rating(DARKTABLE, 5).unwrap();
+        assert_eq!(rating_in(&updated), 5);
+        assert_eq!(
+            updated,
+            DARKTABLE.replace("xmp:Rating=\"2\"", "xmp:Rating=\"5\"")
+        );
+
+        let cleared = with_rating(DARKTABLE, 0).unwrap();
+        assert_eq!(rating_in(&cleared), 0);
+        assert_eq!(cleared, DARKTABLE.replace("\n    xmp:Rating=\"2\"", ""));
+
+        let element = "<d>\n <xmp:Rating>3</xmp:Rating>\n <xmp:Label>Red</xmp:Label>\n</d>";
+        assert_eq!(
+            with_rating(element, 1).unwrap(),
+            element.replace(">3<", ">1<")
+        );
+        assert_eq!(
+            with_rating(element, 0).unwrap(),
+            "<d>\n \n <xmp:Label>Red</xmp:Label>\n</d>"
+        );
+    }
+
+    #[test]
+    fn test_adds_rating() {
+        // To the first description, declaring the namespace
+        let unrated = DARKTABLE.replace("\n    xmp:Rating=\"2\"", "");
+        assert_eq!(rating_in(&with_rating(&unrated, 4).unwrap()), 4);
+        let bare = r#"<rdf:RDF><rdf:Description rdf:about="" dc:format="image/jpeg"/></rdf:RDF>"#;
+        let rated = with_rating(bare, 3).unwrap();
+        assert!(rated.contains(&format!("xmlns:xmp=\"{}\"", XMP_NAMESPACE)));
+        assert!(rated.contains("dc:format=\"image/jpeg\""));
+        assert_eq!(rating_in(&rated), 3);
+
+        // As a new description when there is none
+        let empty = "<rdf:RDF>\n</rdf:RDF>";
+        assert_eq!(rating_in(&with_rating(empty, 1).unwrap()), 1);
+        assert!(with_rating("not xmp", 1).is_err());
+        // Clearing a rating that isn't there changes nothing
+        assert_eq!(with_rating(bare, 0).unwrap(), bare);
+    }
+
+    #[test]
+    fn test_sidecar_round_trip() {
+        let dir = std::env::temp_dir().join(format!("fiv_xmp_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let image = dir.join("photo.jpg");
+        assert_eq!(sidecar_path(&image), dir.join("photo.jpg.xmp"));
+        assert_eq!(read_rating(&image), 0);
+
+        // Clearing creates no sidecar; rating does
+        write_rating(&image, 0).unwrap();
+        assert!(!sidecar_path(&image).exists());
+        write_rating(&image, 4).unwrap();
+        assert_eq!(read_rating(&image), 4);
+        write_rating(&image, 2).unwrap();
+        assert_eq!(read_rating(&image), 2);
+        write_rating(&image, 0).unwrap();
+        assert_eq!(read_rating(&image), 0);
+
+        // Other tools' sidecars keep their other fields
+        fs::write(sidecar_path(&image), DARKTABLE).unwrap();
+        write_rating(&image, 1).unwrap();
+        let xml = fs::read_to_string(sidecar_path(&image)).unwrap();
+        assert_eq!(rating_in(&xml), 1);
+        assert!(xml.contains("darktable:xmp_version=\"5\""));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_shared_stem() {
+        let dir = std::env::temp_dir().join(format!("fiv_xmp_stem_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let raw = dir.join("photo.CR2");
+        let jpeg = dir.join("photo.jpg");
+
+        // Rating one leaves the other alone
+        write_rating(&raw, 5).unwrap();
+        assert_eq!(read_rating(&raw), 5);
+        assert_eq!(read_rating(&jpeg), 0);
+        assert!(!dir.join("photo.xmp").exists());
+
+        // A shared sidecar is read by both until one has its own
+        fs::write(dir.join("photo.xmp"), DARKTABLE).unwrap();
+        assert_eq!(read_rating(&jpeg), 2);
+        write_rating(&jpeg, 3).unwrap();
+        assert_eq!(read_rating(&jpeg), 3);
+        assert_eq!(read_rating(&raw), 5);
+        assert_eq!(
+            fs::read_to_string(dir.join("photo.xmp")).unwrap(),
+            DARKTABLE
+        );
+
+        // Clearing hides the shared rating rather than editing it
+        let other = dir.join("photo.png");
+        assert_eq!(read_rating(&other), 2);
+        write_rating(&other, 0).unwrap();
+        assert_eq!(read_rating(&other), 0);
+        assert_eq!(
+            fs::read_to_string(dir.join("photo.xmp")).unwrap(),
+            DARKTABLE
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
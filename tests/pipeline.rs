@@ -0,0 +1,148 @@
+//! Scan, store, preload and render a directory of generated images, the
+//! way the viewer does but without a window.
+
+use fiv::config::{Config, QualityTier, ScalingFilter};
+use fiv::decode::{scan_directory, Decoder};
+use fiv::preload::{create_store_fast, spawn_preloader};
+use fiv::render::{render_image, Look};
+use fiv::state::{SharedState, ViewTransform};
+use fiv::store::{ImageStore, MemoryBudget};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+const WIDTH: u32 = 64;
+const HEIGHT: u32 = 48;
+
+/// A directory of solid-colored PNGs, one per color, named in order
+fn image_dir(name: &str, colors: &[[u8; 3]]) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("fiv_{}_{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    for (i, &color) in colors.iter().enumerate() {
+        image::RgbImage::from_pixel(WIDTH, HEIGHT, image::Rgb(color))
+            .save_with_format(dir.join(format!("{}.png", i)), image::ImageFormat::Png)
+            .unwrap();
+    }
+    std::fs::write(dir.join("notes.txt"), b"not an image").unwrap();
+    dir
+}
+
+/// Wait for the preloader to load image `index` at full quality
+fn wait_for_full(store: &ImageStore, index: usize) {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while !store.slot(index).has_quality(QualityTier::Full) {
+        assert!(Instant::now() < deadline, "image {} never loaded", index);
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+/// The center pixel of `index` rendered to a window its size
+fn center_pixel(store: &ImageStore, index: usize) -> [u8; 4] {
+    let data = store.slot(index).read();
+    let mut frame = vec![0; (WIDTH * HEIGHT * 4) as usize];
+    let result = render_image(
+        data.as_ref(),
+        &mut frame,
+        WIDTH,
+        HEIGHT,
+        [0, 0, 0, 255],
+        None,
+        &ViewTransform::default(),
+        ScalingFilter::default(),
+        0,
+        &Look::NONE,
+    );
+    assert_eq!(result.quality, Some(QualityTier::Full));
+    let center = ((HEIGHT / 2 * WIDTH + WIDTH / 2) * 4) as usize;
+    frame[center..center + 4].try_into().unwrap()
+}
+
+/// A store over a directory with its preloader running, as the viewer
+/// sets them up
+struct Viewer {
+    store: Arc<ImageStore>,
+    shared_state: Arc<SharedState>,
+    preloader: JoinHandle<()>,
+}
+
+impl Viewer {
+    fn open(dir: &Path) -> Self {
+        let decoder = Arc::new(Decoder::new());
+        let paths = scan_directory(dir, &decoder, 1);
+        let store = Arc::new(create_store_fast(
+            decoder.expand_pages(paths),
+            Arc::new(MemoryBudget::new(64 << 20)),
+        ));
+
+        let mut config = Config::default();
+        // Leave the user's thumbnail cache alone
+        config.preload.thumbnail_cache = false;
+        let shared_state = Arc::new(SharedState::new());
+        shared_state.set_total(store.len());
+        store.pin(0);
+        shared_state.set_start(0);
+        let preloader = spawn_preloader(
+            Arc::clone(&store),
+            Arc::clone(&shared_state),
+            decoder,
+            config,
+        );
+        Self {
+            store,
+            shared_state,
+            preloader,
+        }
+    }
+
+    fn close(self) {
+        self.shared_state.shutdown();
+        self.preloader.join().unwrap();
+    }
+}
+
+fn remove(dir: &Path) {
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_scan_preload_render() {
+    let colors = [[200, 0, 0], [0, 200, 0], [0, 0, 200], [90, 90, 90]];
+    let dir = image_dir("pipeline", &colors);
+    let viewer = Viewer::open(&dir);
+    assert_eq!(viewer.store.len(), colors.len());
+    let (store, shared_state) = (&viewer.store, &viewer.shared_state);
+
+    wait_for_full(store, 0);
+    let [r, g, b, _] = center_pixel(store, 0);
+    assert_eq!([r, g, b], colors[0]);
+
+    // Jumping ahead loads the new current image
+    store.pin(3);
+    shared_state.set_current(3);
+    wait_for_full(store, 3);
+    let [r, g, b, _] = center_pixel(store, 3);
+    assert_eq!([r, g, b], colors[3]);
+
+    viewer.close();
+    remove(&dir);
+}
+
+#[test]
+fn test_unreadable_image_fails_its_slot_only() {
+    let dir = image_dir("pipeline_broken", &[[10, 20, 30], [40, 50, 60]]);
+    std::fs::write(dir.join("1.png"), b"\x89PNG but not really").unwrap();
+    let viewer = Viewer::open(&dir);
+    let store = &viewer.store;
+
+    wait_for_full(store, 0);
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while store.slot(1).error().is_none() {
+        assert!(Instant::now() < deadline, "broken image never failed");
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    assert!(store.slot(1).read().is_none());
+
+    viewer.close();
+    remove(&dir);
+}